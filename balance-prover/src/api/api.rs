@@ -1,100 +1,157 @@
 use actix_web::{
-    post,
+    get, post,
     web::{scope, Data, Json},
-    Error, Scope,
+    Error, HttpResponse, Scope,
 };
-use intmax2_interfaces::api::balance_prover::types::{
-    ProveReceiveDepositRequest, ProveReceiveTransferRequest, ProveResponse, ProveSendRequest,
-    ProveSingleWithdrawalRequest, ProveSpentRequest, ProveUpdateRequest,
+use intmax2_interfaces::api::{
+    balance_prover::types::{
+        ProveReceiveDepositRequest, ProveReceiveTransferRequest, ProveResponse, ProveSendRequest,
+        ProveSingleWithdrawalRequest, ProveSpentRequest, ProveUpdateRequest,
+    },
+    version::VersionResponse,
 };
 
-use crate::api::balance_prover::BalanceProver;
+use crate::api::state::ProverState;
+
+#[get("/version")]
+pub async fn version() -> Json<VersionResponse> {
+    Json(VersionResponse::new(
+        "balance-prover",
+        env!("CARGO_PKG_VERSION"),
+    ))
+}
+
+/// Progress of the circuit build. 503 until the circuits are ready.
+#[get("/ready")]
+pub async fn ready(state: Data<ProverState>) -> HttpResponse {
+    let status = state.status();
+    if status.ready {
+        HttpResponse::Ok().json(status)
+    } else {
+        HttpResponse::ServiceUnavailable().json(status)
+    }
+}
 
 #[post("/prove-spent")]
 pub async fn prove_spent(
-    state: Data<BalanceProver>,
+    state: Data<ProverState>,
     request: Json<ProveSpentRequest>,
 ) -> Result<Json<ProveResponse>, Error> {
-    let proof = state
+    let prover = state.get().await?;
+    let proof = prover
         .prove_spent(&request.spent_witness)
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(Json(ProveResponse { proof }))
+    // the client has no verifier data of the spent circuit to decompress with
+    Ok(Json(ProveResponse::full(proof)))
 }
 
 #[post("/prove-send")]
 pub async fn prove_send(
-    state: Data<BalanceProver>,
+    state: Data<ProverState>,
     request: Json<ProveSendRequest>,
 ) -> Result<Json<ProveResponse>, Error> {
-    let proof = state
+    let prover = state.get().await?;
+    let prev_proof = prover
+        .decode_prev_proof(&request.prev_proof)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+    let proof = prover
         .prove_send(
             request.pubkey,
             &request.tx_witnes,
             &request.update_witness,
             &request.spent_proof,
-            &request.prev_proof,
+            &prev_proof,
         )
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(Json(ProveResponse { proof }))
+    let response = prover
+        .balance_proof_response(proof, request.proof_encoding)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    Ok(Json(response))
 }
 
 #[post("/prove-update")]
 pub async fn prove_update(
-    state: Data<BalanceProver>,
+    state: Data<ProverState>,
     request: Json<ProveUpdateRequest>,
 ) -> Result<Json<ProveResponse>, Error> {
-    let proof = state
-        .prove_update(request.pubkey, &request.update_witness, &request.prev_proof)
+    let prover = state.get().await?;
+    let prev_proof = prover
+        .decode_prev_proof(&request.prev_proof)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+    let proof = prover
+        .prove_update(request.pubkey, &request.update_witness, &prev_proof)
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(Json(ProveResponse { proof }))
+    let response = prover
+        .balance_proof_response(proof, request.proof_encoding)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    Ok(Json(response))
 }
 
 #[post("/prove-receive-transfer")]
 pub async fn prove_receive_transfer(
-    state: Data<BalanceProver>,
+    state: Data<ProverState>,
     request: Json<ProveReceiveTransferRequest>,
 ) -> Result<Json<ProveResponse>, Error> {
+    let prover = state.get().await?;
     let request = request.into_inner();
-    let proof = state
+    let prev_proof = prover
+        .decode_prev_proof(&request.prev_proof)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+    let proof = prover
         .prove_receive_transfer(
             request.pubkey,
             &request.receive_transfer_witness,
-            &request.prev_proof,
+            &prev_proof,
         )
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(Json(ProveResponse { proof }))
+    let response = prover
+        .balance_proof_response(proof, request.proof_encoding)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    Ok(Json(response))
 }
 
 #[post("/prove-receive-deposit")]
 pub async fn prove_receive_deposit(
-    state: Data<BalanceProver>,
+    state: Data<ProverState>,
     request: Json<ProveReceiveDepositRequest>,
 ) -> Result<Json<ProveResponse>, Error> {
+    let prover = state.get().await?;
     let request = request.into_inner();
-    let proof = state
+    let prev_proof = prover
+        .decode_prev_proof(&request.prev_proof)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+    let proof = prover
         .prove_receive_deposit(
             request.pubkey,
             &request.receive_deposit_witness,
-            &request.prev_proof,
+            &prev_proof,
         )
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(Json(ProveResponse { proof }))
+    let response = prover
+        .balance_proof_response(proof, request.proof_encoding)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    Ok(Json(response))
 }
 
 #[post("/prove-single-withdrawal")]
 pub async fn prove_single_withdrawal(
-    state: Data<BalanceProver>,
+    state: Data<ProverState>,
     request: Json<ProveSingleWithdrawalRequest>,
 ) -> Result<Json<ProveResponse>, Error> {
+    let prover = state.get().await?;
     let request = request.into_inner();
-    let proof = state
+    let proof = prover
         .prove_single_withdrawal(&request.withdrawal_witness)
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(Json(ProveResponse { proof }))
+    let response = ProveResponse::new(proof, request.proof_encoding, &prover.single_withdrawal_vd)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    Ok(Json(response))
 }
 
 pub fn balance_prover_scope() -> Scope {
     scope("/balance-prover")
+        .service(version)
+        .service(ready)
         .service(prove_spent)
         .service(prove_send)
         .service(prove_update)
@@ -1,4 +1,7 @@
+use std::path::Path;
+
 use intmax2_client_sdk::utils::circuit_verifiers::CircuitVerifiers;
+use intmax2_interfaces::api::balance_prover::types::{PrevProof, ProofEncoding, ProveResponse};
 
 use intmax2_zkp::{
     circuits::withdrawal::single_withdrawal_circuit::SingleWithdrawalCircuit,
@@ -20,7 +23,7 @@ use plonky2::{
 
 use intmax2_zkp::circuits::balance::balance_processor::BalanceProcessor;
 
-use super::error::BalanceProverError;
+use super::{error::BalanceProverError, state::BuildProgress};
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
@@ -31,13 +34,38 @@ pub struct BalanceProver {
     pub balance_vd: VerifierCircuitData<F, C, D>,
     pub balance_processor: BalanceProcessor<F, C, D>,
     pub single_withdrawal_circuit: SingleWithdrawalCircuit<F, C, D>,
+    pub single_withdrawal_vd: VerifierCircuitData<F, C, D>,
 }
 
+/// Stages reported by `BalanceProver::build`
+pub const BUILD_STEPS: u32 = 3;
+
 impl BalanceProver {
     pub fn new() -> anyhow::Result<Self> {
-        let verifiers = CircuitVerifiers::load();
+        Self::build(&BuildProgress::new(BUILD_STEPS))
+    }
+
+    /// Construct the circuits, reporting each stage to `progress`
+    pub fn build(progress: &BuildProgress) -> anyhow::Result<Self> {
+        Self::build_with_artifacts(progress, None)
+    }
+
+    /// Same as `build`, with the verifier data written by `gen-circuit-artifacts`
+    /// to `artifacts_dir` instead of the embedded one. The prover circuits are still
+    /// constructed, as intmax2-zkp cannot deserialize them, and the build fails if
+    /// they are not the circuits of the artifacts.
+    pub fn build_with_artifacts(
+        progress: &BuildProgress,
+        artifacts_dir: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        progress.begin("verifier data");
+        let verifiers = match artifacts_dir {
+            Some(dir) => CircuitVerifiers::load_from(dir)?,
+            None => CircuitVerifiers::load(),
+        };
 
         let validity_vd = verifiers.get_validity_vd();
+        progress.begin("balance circuits");
         let balance_processor = BalanceProcessor::new(&validity_vd);
         let balance_common_data = balance_processor.balance_circuit.data.common.clone();
         let balance_vd = balance_processor
@@ -45,16 +73,52 @@ impl BalanceProver {
             .data
             .verifier_data()
             .clone();
+        progress.begin("single withdrawal circuit");
         let single_withdrawal_circuit = SingleWithdrawalCircuit::new(&balance_common_data);
+        let single_withdrawal_vd = single_withdrawal_circuit.data.verifier_data();
+        if let Some(dir) = artifacts_dir {
+            anyhow::ensure!(
+                balance_vd.verifier_only.circuit_digest
+                    == verifiers.get_balance_vd().verifier_only.circuit_digest
+                    && single_withdrawal_vd.verifier_only.circuit_digest
+                        == verifiers
+                            .get_single_withdrawal_vd()
+                            .verifier_only
+                            .circuit_digest,
+                "the circuit artifacts of {:?} are not those of this version, regenerate them \
+                 with gen-circuit-artifacts",
+                dir
+            );
+        }
 
         Ok(Self {
             validity_vd,
             balance_vd,
             balance_processor,
             single_withdrawal_circuit,
+            single_withdrawal_vd,
         })
     }
 
+    pub fn decode_prev_proof(
+        &self,
+        prev_proof: &PrevProof,
+    ) -> Result<Option<ProofWithPublicInputs<F, C, D>>, BalanceProverError> {
+        prev_proof
+            .decode(&self.balance_vd)
+            .map_err(|e| BalanceProverError::ProofEncodingError(e.to_string()))
+    }
+
+    /// Response with a balance proof in the requested encoding
+    pub fn balance_proof_response(
+        &self,
+        proof: ProofWithPublicInputs<F, C, D>,
+        encoding: ProofEncoding,
+    ) -> Result<ProveResponse, BalanceProverError> {
+        ProveResponse::new(proof, encoding, &self.balance_vd)
+            .map_err(|e| BalanceProverError::ProofEncodingError(e.to_string()))
+    }
+
     pub fn prove_spent(
         &self,
         spent_witness: &SpentWitness,
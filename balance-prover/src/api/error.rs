@@ -12,4 +12,6 @@ pub enum BalanceProverError {
     ProveUpdateError(String),
     #[error("ProveSingleWithdrawalError: {0}")]
     ProveSingleWithdrawalError(String),
+    #[error("ProofEncodingError: {0}")]
+    ProofEncodingError(String),
 }
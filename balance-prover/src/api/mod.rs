@@ -1,3 +1,4 @@
 pub mod api;
 pub mod balance_prover;
 pub mod error;
+pub mod state;
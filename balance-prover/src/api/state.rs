@@ -0,0 +1,266 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::{error::InternalError, http::header::RETRY_AFTER, Error, HttpResponse};
+use serde::Serialize;
+use tokio::sync::watch;
+
+use super::balance_prover::BalanceProver;
+
+pub type ProverState = WarmPool<BalanceProver>;
+
+/// Build progress, as reported by `/balance-prover/ready`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildStatus {
+    pub ready: bool,
+    /// Stage being built, `None` once ready
+    pub stage: Option<String>,
+    pub completed_steps: u32,
+    pub total_steps: u32,
+    pub elapsed_secs: u64,
+    pub error: Option<String>,
+}
+
+/// Handed to the build to report its stages
+#[derive(Debug, Clone)]
+pub struct BuildProgress {
+    inner: Arc<Mutex<(Option<String>, u32)>>,
+    total_steps: u32,
+}
+
+impl BuildProgress {
+    pub fn new(total_steps: u32) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new((None, 0))),
+            total_steps,
+        }
+    }
+
+    /// Start `stage`, completing the previous one
+    pub fn begin(&self, stage: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.0.is_some() {
+            inner.1 += 1;
+        }
+        inner.0 = Some(stage.to_string());
+        log::info!("Building {} ({}/{})", stage, inner.1 + 1, self.total_steps);
+    }
+}
+
+enum Slot<T> {
+    Building,
+    Ready(Arc<T>),
+    Failed(String),
+}
+
+/// A value built once in the background at boot, e.g. the prover circuits, and
+/// shared by every request. Requests arriving before it is ready wait for it, or
+/// get a 503 with `Retry-After` if `wait_for_ready` is false.
+pub struct WarmPool<T> {
+    slot: watch::Receiver<Slot<T>>,
+    progress: BuildProgress,
+    started_at: Instant,
+    wait_for_ready: bool,
+    retry_after: Duration,
+}
+
+impl<T: Send + Sync + 'static> WarmPool<T> {
+    /// Start `build` on its own thread
+    pub fn start<B>(total_steps: u32, build: B, wait_for_ready: bool, retry_after: Duration) -> Self
+    where
+        B: FnOnce(&BuildProgress) -> anyhow::Result<T> + Send + 'static,
+    {
+        let (sender, slot) = watch::channel(Slot::Building);
+        let progress = BuildProgress::new(total_steps);
+        let reporter = progress.clone();
+        std::thread::spawn(move || {
+            let started_at = Instant::now();
+            let result = match build(&reporter) {
+                Ok(value) => {
+                    log::info!("Circuits ready after {:?}", started_at.elapsed());
+                    Slot::Ready(Arc::new(value))
+                }
+                Err(e) => {
+                    log::error!("Failed to build circuits: {}", e);
+                    Slot::Failed(e.to_string())
+                }
+            };
+            let _ = sender.send(result);
+        });
+        Self {
+            slot,
+            progress,
+            started_at: Instant::now(),
+            wait_for_ready,
+            retry_after,
+        }
+    }
+
+    pub fn status(&self) -> BuildStatus {
+        let (stage, completed_steps) = self.progress.inner.lock().unwrap().clone();
+        let total_steps = self.progress.total_steps;
+        let elapsed_secs = self.started_at.elapsed().as_secs();
+        match &*self.slot.borrow() {
+            Slot::Building => BuildStatus {
+                ready: false,
+                stage,
+                completed_steps,
+                total_steps,
+                elapsed_secs,
+                error: None,
+            },
+            Slot::Ready(_) => BuildStatus {
+                ready: true,
+                stage: None,
+                completed_steps: total_steps,
+                total_steps,
+                elapsed_secs,
+                error: None,
+            },
+            Slot::Failed(e) => BuildStatus {
+                ready: false,
+                stage,
+                completed_steps,
+                total_steps,
+                elapsed_secs,
+                error: Some(e.clone()),
+            },
+        }
+    }
+
+    /// The built value, waiting for it if `wait_for_ready`
+    pub async fn get(&self) -> Result<Arc<T>, Error> {
+        let mut slot = self.slot.clone();
+        loop {
+            let built = match &*slot.borrow_and_update() {
+                Slot::Ready(value) => Some(Ok(value.clone())),
+                Slot::Failed(e) => Some(Err(e.clone())),
+                Slot::Building => None,
+            };
+            match built {
+                Some(Ok(value)) => return Ok(value),
+                Some(Err(e)) => {
+                    return Err(actix_web::error::ErrorInternalServerError(format!(
+                        "Failed to build circuits: {}",
+                        e
+                    )))
+                }
+                None if !self.wait_for_ready => return Err(self.not_ready()),
+                None => {}
+            }
+            if slot.changed().await.is_err() {
+                return Err(actix_web::error::ErrorInternalServerError(
+                    "Circuit build stopped",
+                ));
+            }
+        }
+    }
+
+    fn not_ready(&self) -> Error {
+        let status = self.status();
+        let message = format!(
+            "Circuits are building ({}/{} steps done)",
+            status.completed_steps, status.total_steps
+        );
+        let response = HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, self.retry_after.as_secs().max(1)))
+            .json(serde_json::json!({
+                "error": "not_ready",
+                "message": message,
+            }));
+        InternalError::from_response(message, response).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use actix_web::{body::MessageBody as _, ResponseError as _};
+
+    use super::*;
+
+    // a build that goes through its two stages when told to
+    fn start_pool(wait_for_ready: bool) -> (WarmPool<u32>, mpsc::Sender<()>) {
+        let (next, steps) = mpsc::channel();
+        let pool = WarmPool::start(
+            2,
+            move |progress| {
+                progress.begin("first");
+                steps.recv()?;
+                progress.begin("second");
+                steps.recv()?;
+                Ok(42)
+            },
+            wait_for_ready,
+            Duration::from_secs(5),
+        );
+        (pool, next)
+    }
+
+    async fn wait_for_status(pool: &WarmPool<u32>, completed_steps: u32, ready: bool) {
+        for _ in 0..100 {
+            let status = pool.status();
+            if status.completed_steps == completed_steps && status.ready == ready {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("status is {:?}", pool.status());
+    }
+
+    #[tokio::test]
+    async fn test_ready_transitions() {
+        let (pool, next) = start_pool(true);
+        wait_for_status(&pool, 0, false).await;
+        assert_eq!(pool.status().stage.as_deref(), Some("first"));
+
+        // a request issued before the circuits are ready waits for them
+        let request = pool.get();
+        tokio::pin!(request);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), &mut request)
+                .await
+                .is_err()
+        );
+        next.send(()).unwrap();
+        wait_for_status(&pool, 1, false).await;
+        assert_eq!(pool.status().stage.as_deref(), Some("second"));
+        next.send(()).unwrap();
+        assert_eq!(*request.await.unwrap(), 42);
+
+        let status = pool.status();
+        assert!(status.ready);
+        assert_eq!(status.completed_steps, 2);
+        assert_eq!(status.stage, None);
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_is_503_with_retry_after() {
+        let (pool, next) = start_pool(false);
+        let error = pool.get().await.unwrap_err();
+        let response = error.as_response_error().error_response();
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "5");
+        let body = response.into_body().try_into_bytes().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "not_ready");
+
+        next.send(()).unwrap();
+        next.send(()).unwrap();
+        wait_for_status(&pool, 2, true).await;
+        assert_eq!(*pool.get().await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn test_failed_build() {
+        let (pool, next) = start_pool(true);
+        drop(next);
+        let error = pool.get().await.unwrap_err();
+        assert_eq!(error.as_response_error().status_code(), 500);
+        assert!(pool.status().error.is_some());
+    }
+}
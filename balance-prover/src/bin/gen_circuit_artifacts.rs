@@ -0,0 +1,18 @@
+//! Writes the verifier data of the circuits built by this version to a directory,
+//! for `PRECOMPILED_CIRCUITS_DIR`.
+//!
+//! Usage: gen-circuit-artifacts <dir>
+
+use std::path::PathBuf;
+
+use intmax2_client_sdk::utils::circuit_verifiers::CircuitVerifiers;
+
+fn main() -> anyhow::Result<()> {
+    let dir = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow::anyhow!("Usage: gen-circuit-artifacts <dir>"))?;
+    CircuitVerifiers::construct().save_to(&dir)?;
+    println!("Wrote the circuit verifier data to {:?}", dir);
+    Ok(())
+}
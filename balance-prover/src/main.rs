@@ -1,12 +1,17 @@
-use std::io;
+use std::{path::PathBuf, time::Duration};
 
 use actix_cors::Cors;
 use actix_web::{middleware::Logger, web::Data, App, HttpServer};
 use balance_prover::{
-    api::{api::balance_prover_scope, balance_prover::BalanceProver},
+    api::{
+        api::balance_prover_scope,
+        balance_prover::{BalanceProver, BUILD_STEPS},
+        state::WarmPool,
+    },
     health_check::health_check,
 };
 use intmax2_client_sdk::utils::init_logger::init_logger;
+use intmax2_interfaces::api::CORS_EXPOSED_HEADERS;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -15,10 +20,27 @@ async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
 
     let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let state = BalanceProver::new().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    // the circuits are built in the background, so that the server answers
+    // `/balance-prover/ready` and health checks meanwhile
+    let wait_for_circuits = std::env::var("WAIT_FOR_CIRCUITS")
+        .map(|value| value != "false")
+        .unwrap_or(true);
+    let retry_after = std::env::var("CIRCUITS_RETRY_AFTER")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(10);
+    let artifacts_dir = std::env::var("PRECOMPILED_CIRCUITS_DIR")
+        .ok()
+        .map(PathBuf::from);
+    let state = WarmPool::start(
+        BUILD_STEPS,
+        move |progress| BalanceProver::build_with_artifacts(progress, artifacts_dir.as_deref()),
+        wait_for_circuits,
+        Duration::from_secs(retry_after),
+    );
     let state = Data::new(state);
     HttpServer::new(move || {
-        let cors = Cors::permissive();
+        let cors = Cors::permissive().expose_headers(CORS_EXPOSED_HEADERS);
         App::new()
             .wrap(cors)
             .wrap(Logger::new("Request: %r | Status: %s | Duration: %Ts"))
@@ -1,16 +1,33 @@
 use actix_web::{
-    get, post,
-    web::{Data, Json},
+    delete, get, post,
+    web::{Data, Json, Path},
     Error,
 };
-use intmax2_interfaces::api::block_builder::types::{
-    GetBlockBuilderStatusQuery, GetBlockBuilderStatusResponse, PostSignatureRequest,
-    QueryProposalRequest, QueryProposalResponse, TxRequestRequest,
+use intmax2_interfaces::api::{
+    block_builder::types::{
+        AdvanceTimeRequest, CancelTxRequestRequest, GetBlockBuilderHealthResponse,
+        GetBlockBuilderStatusQuery, GetBlockBuilderStatusResponse, GetFeeInfoResponse,
+        GetProposalAttestationResponse, GetTimeResponse, PostSignatureRequest,
+        QueryProposalRequest, QueryProposalResponse, QueryProposalsRequest, QueryProposalsResponse,
+        TxRequestRequest,
+    },
+    version::VersionResponse,
+};
+use intmax2_zkp::{
+    common::block_builder::UserSignature,
+    ethereum_types::{bytes32::Bytes32, u32limb_trait::U32LimbTrait as _},
 };
-use intmax2_zkp::common::block_builder::UserSignature;
 use serde_qs::actix::QsQuery;
 
-use crate::api::state::State;
+use crate::api::{error::BlockBuilderError, state::State};
+
+#[get("/version")]
+pub async fn version() -> Json<VersionResponse> {
+    Json(VersionResponse::new(
+        "block-builder",
+        env!("CARGO_PKG_VERSION"),
+    ))
+}
 
 // todo: remove in production
 #[post("/post-empty-block")]
@@ -35,6 +52,20 @@ pub async fn get_status(
     Ok(Json(GetBlockBuilderStatusResponse { status }))
 }
 
+#[get("/health")]
+pub async fn get_health(state: Data<State>) -> Result<Json<GetBlockBuilderHealthResponse>, Error> {
+    // probe without holding the lock, which the block posting holds for long
+    let block_builder = state.block_builder.read().await.clone();
+    let dependencies = block_builder.check_dependencies().await;
+    Ok(Json(GetBlockBuilderHealthResponse { dependencies }))
+}
+
+// fees are not charged yet, see `tx_request`
+#[get("/fee-info")]
+pub async fn get_fee_info() -> Result<Json<GetFeeInfoResponse>, Error> {
+    Ok(Json(GetFeeInfoResponse { fees: Vec::new() }))
+}
+
 #[post("/tx-request")]
 pub async fn tx_request(
     state: Data<State>,
@@ -45,12 +76,47 @@ pub async fn tx_request(
         .block_builder
         .write()
         .await
-        .send_tx_request(request.is_registration_block, request.pubkey, request.tx)
+        .send_tx_request(
+            request.is_registration_block,
+            request.pubkey,
+            request.tx,
+            request.fee_voucher,
+        )
         .await
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
     Ok(Json(()))
 }
 
+// drop a tx request of the signer before the block is proposed. 401 if the
+// signature does not hold, 404 if it is not queued, 409 if it is already in the
+// proposed block.
+#[delete("/tx-request/{tx_hash}")]
+pub async fn cancel_tx_request(
+    state: Data<State>,
+    tx_hash: Path<String>,
+    request: Json<CancelTxRequestRequest>,
+) -> Result<Json<()>, Error> {
+    let tx_hash = Bytes32::from_hex(&tx_hash)
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("invalid tx hash: {}", e)))?;
+    let request = request.into_inner();
+    let user_signature = UserSignature {
+        pubkey: request.pubkey,
+        signature: request.signature,
+    };
+    state
+        .block_builder
+        .write()
+        .await
+        .cancel_tx_request(tx_hash, user_signature)
+        .map_err(|e| match e {
+            BlockBuilderError::InvalidSignature(_) => actix_web::error::ErrorUnauthorized(e),
+            BlockBuilderError::TxRequestNotFound => actix_web::error::ErrorNotFound(e),
+            BlockBuilderError::TxAlreadyProposed(_) => actix_web::error::ErrorConflict(e),
+            e => actix_web::error::ErrorInternalServerError(e),
+        })?;
+    Ok(Json(()))
+}
+
 #[post("/query-proposal")]
 pub async fn query_proposal(
     state: Data<State>,
@@ -66,6 +132,41 @@ pub async fn query_proposal(
     Ok(Json(QueryProposalResponse { block_proposal }))
 }
 
+#[post("/query-proposals")]
+pub async fn query_proposals(
+    state: Data<State>,
+    request: Json<QueryProposalsRequest>,
+) -> Result<Json<QueryProposalsResponse>, Error> {
+    let requests = request
+        .into_inner()
+        .requests
+        .into_iter()
+        .map(|request| (request.is_registration_block, request.pubkey, request.tx))
+        .collect::<Vec<_>>();
+    let block_proposals = state
+        .block_builder
+        .read()
+        .await
+        .query_proposals(&requests)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    Ok(Json(QueryProposalsResponse { block_proposals }))
+}
+
+#[post("/proposal-attestation")]
+pub async fn get_proposal_attestation(
+    state: Data<State>,
+    request: Json<QueryProposalRequest>,
+) -> Result<Json<GetProposalAttestationResponse>, Error> {
+    let request = request.into_inner();
+    let attestation = state
+        .block_builder
+        .read()
+        .await
+        .attest_proposal(request.is_registration_block, request.pubkey, request.tx)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    Ok(Json(GetProposalAttestationResponse { attestation }))
+}
+
 #[post("/post-signature")]
 pub async fn post_signature(
     state: Data<State>,
@@ -85,11 +186,48 @@ pub async fn post_signature(
     Ok(Json(()))
 }
 
+// only available with VIRTUAL_TIME=true, for the tests of timeouts
+#[post("/time/advance")]
+pub async fn advance_time(
+    state: Data<State>,
+    request: Json<AdvanceTimeRequest>,
+) -> Result<Json<GetTimeResponse>, Error> {
+    let now = state
+        .clock
+        .advance(request.seconds)
+        .map_err(|e| actix_web::error::ErrorForbidden(e))?;
+    log::info!("virtual time advanced by {}s to {}", request.seconds, now);
+    Ok(Json(GetTimeResponse {
+        now,
+        is_virtual: true,
+    }))
+}
+
+#[get("/time")]
+pub async fn get_time(state: Data<State>) -> Result<Json<GetTimeResponse>, Error> {
+    Ok(Json(GetTimeResponse {
+        now: state.clock.now(),
+        is_virtual: state.clock.is_virtual(),
+    }))
+}
+
+pub fn admin_scope() -> actix_web::Scope {
+    actix_web::web::scope("/admin")
+        .service(advance_time)
+        .service(get_time)
+}
+
 pub fn block_builder_scope() -> actix_web::Scope {
     actix_web::web::scope("/block-builder")
+        .service(version)
         .service(post_empty_block)
         .service(get_status)
+        .service(get_health)
+        .service(get_fee_info)
         .service(tx_request)
+        .service(cancel_tx_request)
         .service(query_proposal)
+        .service(query_proposals)
+        .service(get_proposal_attestation)
         .service(post_signature)
 }
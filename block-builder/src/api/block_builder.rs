@@ -2,10 +2,14 @@ use ark_bn254::{Bn254, Fr, G1Affine, G2Affine};
 use ark_ec::{pairing::Pairing as _, AffineRepr as _};
 use ethers::types::{Address, H256};
 use intmax2_client_sdk::external_api::{
-    contract::rollup_contract::RollupContract, validity_prover::ValidityProverClient,
+    block_builder::{sign_proposal_attestation, verify_cancel_tx_request},
+    contract::rollup_contract::RollupContract,
+    sponsor::verify_fee_voucher,
+    validity_prover::ValidityProverClient,
 };
 use intmax2_interfaces::api::{
-    block_builder::interface::BlockBuilderStatus,
+    block_builder::interface::{BlockBuilderDependencies, BlockBuilderStatus, ProposalAttestation},
+    sponsor::interface::FeeVoucher,
     validity_prover::interface::ValidityProverClientInterface,
 };
 use intmax2_zkp::{
@@ -20,14 +24,15 @@ use intmax2_zkp::{
     },
     constants::NUM_SENDERS_IN_BLOCK,
     ethereum_types::{
-        account_id_packed::AccountIdPacked, bytes16::Bytes16, bytes32::Bytes32, u256::U256,
-        u32limb_trait::U32LimbTrait,
+        account_id_packed::AccountIdPacked, address::Address as IntmaxAddress, bytes16::Bytes16,
+        bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait,
     },
+    utils::leafable::Leafable as _,
 };
 use num::BigUint;
 use plonky2_bn254::fields::recover::RecoverFromX as _;
 
-use super::{error::BlockBuilderError, internal_state::BuilderState};
+use super::{clock::Clock, error::BlockBuilderError, internal_state::BuilderState};
 
 #[derive(Debug, Clone)]
 pub struct BlockBuilder {
@@ -35,9 +40,12 @@ pub struct BlockBuilder {
     rollup_contract: RollupContract,
     block_builder_private_key: H256,
     eth_allowance_for_block: ethers::types::U256,
+    sponsor_address: Option<IntmaxAddress>,
 
     registration_state: BuilderState,
     non_registration_state: BuilderState,
+    last_block_posted_at: Option<u64>,
+    clock: Clock,
 }
 
 // todo: remove status clone
@@ -50,6 +58,7 @@ impl BlockBuilder {
         block_builder_private_key: H256,
         eth_allowance_for_block: ethers::types::U256,
         validity_prover_base_url: &str,
+        sponsor_address: Option<Address>,
     ) -> Self {
         let validity_prover_client = ValidityProverClient::new(validity_prover_base_url);
         let rollup_contract = RollupContract::new(
@@ -63,11 +72,21 @@ impl BlockBuilder {
             rollup_contract,
             block_builder_private_key,
             eth_allowance_for_block,
+            sponsor_address: sponsor_address
+                .map(|address| IntmaxAddress::from_bytes_be(address.as_bytes())),
             registration_state: BuilderState::new(),
             non_registration_state: BuilderState::new(),
+            last_block_posted_at: None,
+            clock: Clock::Real,
         }
     }
 
+    /// Use `clock` for the timestamps instead of the real time
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn get_status(&self, is_registration_block: bool) -> BlockBuilderStatus {
         if is_registration_block {
             self.registration_state.get_status()
@@ -76,13 +95,61 @@ impl BlockBuilder {
         }
     }
 
+    /// Probe the validity prover and the chain the blocks are posted to
+    pub async fn check_dependencies(&self) -> BlockBuilderDependencies {
+        let validity_prover_ok = match self.validity_prover_client.get_block_number().await {
+            Ok(_) => true,
+            Err(e) => {
+                log::warn!("validity prover is unhealthy: {}", e);
+                false
+            }
+        };
+        let l1_ok = match self.rollup_contract.get_latest_block_number().await {
+            Ok(_) => true,
+            Err(e) => {
+                log::warn!("rollup contract is unreachable: {}", e);
+                false
+            }
+        };
+        BlockBuilderDependencies {
+            validity_prover_ok,
+            l1_ok,
+            last_block_posted_at: self.last_block_posted_at,
+        }
+    }
+
+    // Fees are not charged yet, but a voucher must be valid if a sponsor is
+    // configured. Without one, the voucher is ignored and the sender pays the fee.
+    fn check_fee_voucher(
+        &self,
+        pubkey: U256,
+        tx: Tx,
+        fee_voucher: Option<&FeeVoucher>,
+    ) -> Result<(), BlockBuilderError> {
+        let Some(voucher) = fee_voucher else {
+            return Ok(());
+        };
+        let Some(sponsor) = self.sponsor_address else {
+            log::info!(
+                "no sponsor is configured, ignoring the fee voucher of {}",
+                pubkey
+            );
+            return Ok(());
+        };
+        verify_fee_voucher(voucher, sponsor, pubkey, tx.hash().into())
+            .map_err(|e| BlockBuilderError::InvalidFeeVoucher(e.to_string()))
+    }
+
     // Send a tx request by the user.
     pub async fn send_tx_request(
         &mut self,
         is_registration_block: bool,
         pubkey: U256,
         tx: Tx,
+        fee_voucher: Option<FeeVoucher>,
     ) -> Result<(), BlockBuilderError> {
+        self.check_fee_voucher(pubkey, tx, fee_voucher.as_ref())?;
+
         let mut status = if is_registration_block {
             self.registration_state.clone()
         } else {
@@ -132,6 +199,31 @@ impl BlockBuilder {
         Ok(())
     }
 
+    // Drop the tx request whose tx has `tx_hash`, before the block is proposed.
+    // `signature` is the sender's signature over the cancellation, and only the
+    // request of its pubkey is dropped.
+    pub fn cancel_tx_request(
+        &mut self,
+        tx_hash: Bytes32,
+        signature: UserSignature,
+    ) -> Result<(), BlockBuilderError> {
+        verify_cancel_tx_request(tx_hash, &signature)
+            .map_err(|e| BlockBuilderError::InvalidSignature(e.to_string()))?;
+        let pubkey = signature.pubkey;
+        for state in [
+            &mut self.registration_state,
+            &mut self.non_registration_state,
+        ] {
+            if state.is_proposed(pubkey, tx_hash) {
+                return Err(BlockBuilderError::TxAlreadyProposed(tx_hash));
+            }
+            if state.remove_tx_request(pubkey, tx_hash) {
+                return Ok(());
+            }
+        }
+        Err(BlockBuilderError::TxRequestNotFound)
+    }
+
     // Construct a block with the given tx requests by the block builder.
     pub fn construct_block(
         &mut self,
@@ -184,6 +276,36 @@ impl BlockBuilder {
         return Ok(status.query_proposal(pubkey, tx));
     }
 
+    // Query the proposals of several txs. Each request is checked as in `query_proposal`.
+    pub fn query_proposals(
+        &self,
+        requests: &[(bool, U256, Tx)],
+    ) -> Result<Vec<Option<BlockProposal>>, BlockBuilderError> {
+        requests
+            .iter()
+            .map(|(is_registration_block, pubkey, tx)| {
+                self.query_proposal(*is_registration_block, *pubkey, *tx)
+            })
+            .collect()
+    }
+
+    // Attest the proposal for `tx` of `pubkey` with the key the blocks are posted
+    // with, for the sender to keep as evidence of the proposal.
+    pub fn attest_proposal(
+        &self,
+        is_registration_block: bool,
+        pubkey: U256,
+        tx: Tx,
+    ) -> Result<Option<ProposalAttestation>, BlockBuilderError> {
+        let Some(proposal) = self.query_proposal(is_registration_block, pubkey, tx)? else {
+            return Ok(None);
+        };
+        let attestation =
+            sign_proposal_attestation(self.block_builder_private_key, pubkey, tx, &proposal)
+                .map_err(|e| BlockBuilderError::InvalidSignature(e.to_string()))?;
+        Ok(Some(attestation))
+    }
+
     // Post the signature by the user.
     pub fn post_signature(
         &mut self,
@@ -354,6 +476,7 @@ impl BlockBuilder {
                 )
                 .await?;
         };
+        self.last_block_posted_at = Some(self.clock.now());
         status.finalize_block();
         if is_registration_block {
             self.registration_state = status;
@@ -469,3 +592,180 @@ fn construct_signature(
         message_point: message_point.into(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, H256};
+    use intmax2_client_sdk::external_api::{
+        block_builder::sign_cancel_tx_request, sponsor::sign_fee_voucher,
+    };
+    use intmax2_zkp::{
+        common::{signature::key_set::KeySet, tx::Tx},
+        ethereum_types::{bytes32::Bytes32, u256::U256},
+        utils::leafable::Leafable as _,
+    };
+
+    use super::{BlockBuilder, BlockBuilderError};
+
+    fn pubkey(i: u32) -> U256 {
+        U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, i])
+    }
+
+    fn key(i: u32) -> KeySet {
+        KeySet::new(pubkey(i))
+    }
+
+    fn block_builder() -> BlockBuilder {
+        BlockBuilder::new(
+            "http://localhost:1",
+            31337,
+            Address::zero(),
+            0,
+            H256::zero(),
+            0.into(),
+            "http://localhost:1",
+            None,
+        )
+    }
+
+    #[test]
+    fn test_query_proposals() {
+        let mut block_builder = block_builder();
+        let txs = (0..3)
+            .map(|i| Tx {
+                nonce: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        // two txs are proposed, and the third is still waiting for the block
+        block_builder.registration_state.start_accepting_txs();
+        block_builder
+            .registration_state
+            .append_tx_request(pubkey(1), txs[0]);
+        block_builder
+            .registration_state
+            .append_tx_request(pubkey(2), txs[1]);
+        block_builder.construct_block(true).unwrap();
+        block_builder.non_registration_state.start_accepting_txs();
+        block_builder
+            .non_registration_state
+            .append_tx_request(pubkey(3), txs[2]);
+
+        let proposals = block_builder
+            .query_proposals(&[
+                (true, pubkey(1), txs[0]),
+                (true, pubkey(2), txs[1]),
+                (false, pubkey(3), txs[2]),
+            ])
+            .unwrap();
+        assert_eq!(proposals.len(), 3);
+        assert!(proposals[0].is_some());
+        assert!(proposals[1].is_some());
+        assert!(proposals[2].is_none());
+        assert_eq!(
+            proposals[0].as_ref().unwrap().tx_tree_root,
+            proposals[1].as_ref().unwrap().tx_tree_root
+        );
+
+        // the tx of another sender is not returned
+        assert!(block_builder
+            .query_proposals(&[(true, pubkey(1), txs[0]), (false, pubkey(1), txs[2])])
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_fee_voucher() {
+        let sponsor_key: H256 =
+            "0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d"
+                .parse()
+                .unwrap();
+        let tx = Tx::default();
+        let voucher = sign_fee_voucher(sponsor_key, pubkey(1), tx.hash().into()).unwrap();
+
+        // without a sponsor, the voucher is ignored and the sender pays
+        let block_builder = block_builder();
+        block_builder
+            .check_fee_voucher(pubkey(1), tx, Some(&voucher))
+            .unwrap();
+
+        let mut block_builder = block_builder();
+        block_builder.sponsor_address = Some(voucher.sponsor);
+        block_builder
+            .check_fee_voucher(pubkey(1), tx, Some(&voucher))
+            .unwrap();
+        block_builder
+            .check_fee_voucher(pubkey(1), tx, None)
+            .unwrap();
+        // a voucher of the sponsor for another sender is rejected
+        assert!(matches!(
+            block_builder.check_fee_voucher(pubkey(2), tx, Some(&voucher)),
+            Err(BlockBuilderError::InvalidFeeVoucher(_))
+        ));
+    }
+
+    #[test]
+    fn test_cancel_tx_request() {
+        let mut block_builder = block_builder();
+        let keys = (1..=3).map(key).collect::<Vec<_>>();
+        let txs = (0..3)
+            .map(|i| Tx {
+                nonce: i,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let hash = |tx: &Tx| Bytes32::from(tx.hash());
+        let cancel = |key: KeySet, tx: &Tx| sign_cancel_tx_request(key, hash(tx));
+
+        block_builder.registration_state.start_accepting_txs();
+        block_builder
+            .registration_state
+            .append_tx_request(keys[0].pubkey, txs[0]);
+        block_builder.construct_block(true).unwrap();
+        block_builder.non_registration_state.start_accepting_txs();
+        block_builder
+            .non_registration_state
+            .append_tx_request(keys[1].pubkey, txs[1]);
+        block_builder
+            .non_registration_state
+            .append_tx_request(keys[2].pubkey, txs[2]);
+
+        // another sender can not drop the request, even with a valid signature
+        assert!(matches!(
+            block_builder.cancel_tx_request(hash(&txs[1]), cancel(keys[2], &txs[1])),
+            Err(BlockBuilderError::TxRequestNotFound)
+        ));
+        // nor with the signature of the sender over another tx
+        let mut forged = cancel(keys[1], &txs[2]);
+        assert!(matches!(
+            block_builder.cancel_tx_request(hash(&txs[1]), forged.clone()),
+            Err(BlockBuilderError::InvalidSignature(_))
+        ));
+        // nor with another signature under the sender's pubkey
+        forged.signature = cancel(keys[2], &txs[1]).signature;
+        assert!(matches!(
+            block_builder.cancel_tx_request(hash(&txs[1]), forged),
+            Err(BlockBuilderError::InvalidSignature(_))
+        ));
+        assert_eq!(block_builder.non_registration_state.count_tx_requests(), 2);
+
+        // a queued request is dropped once by its sender, and the others stay
+        block_builder
+            .cancel_tx_request(hash(&txs[1]), cancel(keys[1], &txs[1]))
+            .unwrap();
+        assert!(matches!(
+            block_builder.cancel_tx_request(hash(&txs[1]), cancel(keys[1], &txs[1])),
+            Err(BlockBuilderError::TxRequestNotFound)
+        ));
+        assert_eq!(block_builder.non_registration_state.count_tx_requests(), 1);
+        assert!(block_builder
+            .non_registration_state
+            .is_request_contained(keys[2].pubkey, txs[2]));
+
+        // a proposed tx can no longer be dropped
+        assert!(matches!(
+            block_builder.cancel_tx_request(hash(&txs[0]), cancel(keys[0], &txs[0])),
+            Err(BlockBuilderError::TxAlreadyProposed(_))
+        ));
+    }
+}
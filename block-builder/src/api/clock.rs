@@ -0,0 +1,129 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use super::error::BlockBuilderError;
+
+/// Source of the current time for the block builder. The virtual clock runs with
+/// the real one but can be moved forward through the admin API, so that tests of
+/// timeouts do not have to wait for them.
+#[derive(Debug, Clone)]
+pub enum Clock {
+    Real,
+    Virtual(Arc<VirtualClock>),
+}
+
+#[derive(Debug, Default)]
+pub struct VirtualClock {
+    // seconds added to the real time
+    offset: AtomicU64,
+    // wakes up the sleeps when the clock is advanced
+    advanced: Notify,
+}
+
+impl Clock {
+    pub fn new(is_virtual: bool) -> Self {
+        if is_virtual {
+            Clock::Virtual(Arc::new(VirtualClock::default()))
+        } else {
+            Clock::Real
+        }
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        matches!(self, Clock::Virtual(_))
+    }
+
+    /// Current unix timestamp
+    pub fn now(&self) -> u64 {
+        let real = chrono::Utc::now().timestamp() as u64;
+        match self {
+            Clock::Real => real,
+            Clock::Virtual(clock) => real + clock.offset.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Move the virtual clock forward by `seconds` and return the new time
+    pub fn advance(&self, seconds: u64) -> Result<u64, BlockBuilderError> {
+        match self {
+            Clock::Real => Err(BlockBuilderError::VirtualTimeDisabled),
+            Clock::Virtual(clock) => {
+                clock.offset.fetch_add(seconds, Ordering::SeqCst);
+                clock.advanced.notify_waiters();
+                Ok(self.now())
+            }
+        }
+    }
+
+    /// Sleep until `seconds` have passed on this clock. A sleep on the virtual clock
+    /// ends early when the clock is advanced past its end.
+    pub async fn sleep(&self, seconds: u64) {
+        let clock = match self {
+            Clock::Real => {
+                tokio::time::sleep(Duration::from_secs(seconds)).await;
+                return;
+            }
+            Clock::Virtual(clock) => clock,
+        };
+        let until = self.now() + seconds;
+        loop {
+            // registered before reading the time, so that no advance is missed
+            let advanced = clock.advanced.notified();
+            let now = self.now();
+            if now >= until {
+                return;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(until - now)) => {}
+                _ = advanced => {}
+            }
+        }
+    }
+
+    /// The current time formatted for the `Date` header, so that clients measuring
+    /// the server clock follow the virtual time
+    pub fn http_date(&self) -> String {
+        chrono::DateTime::from_timestamp(self.now() as i64, 0)
+            .unwrap_or_default()
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_advance_ends_sleep() {
+        let clock = Clock::new(true);
+        let start = clock.now();
+        let sleeping = tokio::spawn({
+            let clock = clock.clone();
+            async move { clock.sleep(3600).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!sleeping.is_finished());
+
+        assert!(clock.advance(3600).unwrap() >= start + 3600);
+        tokio::time::timeout(Duration::from_secs(5), sleeping)
+            .await
+            .expect("sleep did not end after the clock was advanced")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_real_clock_cannot_be_advanced() {
+        let clock = Clock::new(false);
+        assert!(matches!(
+            clock.advance(1),
+            Err(BlockBuilderError::VirtualTimeDisabled)
+        ));
+    }
+}
@@ -1,6 +1,6 @@
 use intmax2_client_sdk::external_api::contract::interface::BlockchainError;
 use intmax2_interfaces::api::error::ServerError;
-use intmax2_zkp::ethereum_types::u256::U256;
+use intmax2_zkp::ethereum_types::{bytes32::Bytes32, u256::U256};
 
 #[derive(Debug, thiserror::Error)]
 pub enum BlockBuilderError {
@@ -37,9 +37,18 @@ pub enum BlockBuilderError {
     #[error("Tx request is not found")]
     TxRequestNotFound,
 
+    #[error("Tx {0} is already in the proposed block, it can no longer be cancelled")]
+    TxAlreadyProposed(Bytes32),
+
     #[error("Invalid signature: {0}")]
     InvalidSignature(String),
 
+    #[error("Invalid fee voucher: {0}")]
+    InvalidFeeVoucher(String),
+
     #[error("Block builder should be pausing")]
     ShouldBePausing,
+
+    #[error("Virtual time is disabled, set VIRTUAL_TIME=true to enable it")]
+    VirtualTimeDisabled,
 }
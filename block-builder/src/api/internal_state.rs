@@ -100,6 +100,33 @@ impl BuilderState {
         }
     }
 
+    /// Remove the tx request of `pubkey` whose tx has `tx_hash`. Returns whether it
+    /// was queued.
+    pub fn remove_tx_request(&mut self, pubkey: U256, tx_hash: Bytes32) -> bool {
+        match self {
+            BuilderState::AcceptingTxs(state) => {
+                let len = state.tx_requests.len();
+                state
+                    .tx_requests
+                    .retain(|(p, tx)| p != &pubkey || Bytes32::from(tx.hash()) != tx_hash);
+                state.tx_requests.len() < len
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the tx of `pubkey` with `tx_hash` is in the proposed block
+    pub fn is_proposed(&self, pubkey: U256, tx_hash: Bytes32) -> bool {
+        match self {
+            BuilderState::ProposingBlock(state) => state
+                .memo
+                .tx_requests
+                .iter()
+                .any(|(p, tx)| p == &pubkey && Bytes32::from(tx.hash()) == tx_hash),
+            _ => false,
+        }
+    }
+
     pub fn get_proposal_memo(&self) -> Option<ProposalMemo> {
         match self {
             BuilderState::ProposingBlock(state) => Some(state.memo.clone()),
@@ -1,5 +1,6 @@
 pub mod api;
 pub mod block_builder;
+pub mod clock;
 pub mod error;
 pub mod internal_state;
 pub mod state;
@@ -1,24 +1,26 @@
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
 use tokio::sync::RwLock;
 
 use crate::Env;
 
-use super::{block_builder::BlockBuilder, error::BlockBuilderError};
+use super::{block_builder::BlockBuilder, clock::Clock, error::BlockBuilderError};
 
 #[derive(Debug, Clone)]
 pub struct State {
     pub is_shutting_down: Arc<RwLock<bool>>,
     pub force_post: Arc<RwLock<bool>>,
     pub block_builder: Arc<RwLock<BlockBuilder>>,
+    pub clock: Clock,
 }
 
 impl State {
-    pub fn new(block_builder: BlockBuilder) -> Self {
+    pub fn new(block_builder: BlockBuilder, clock: Clock) -> Self {
         State {
             is_shutting_down: Arc::new(RwLock::new(false)),
             force_post: Arc::new(RwLock::new(false)),
-            block_builder: Arc::new(RwLock::new(block_builder)),
+            block_builder: Arc::new(RwLock::new(block_builder.with_clock(clock.clone()))),
+            clock,
         }
     }
 
@@ -57,7 +59,7 @@ impl State {
             .await
             .start_accepting_txs(is_registration_block)?;
 
-        tokio::time::sleep(Duration::from_secs(env.accepting_tx_interval)).await;
+        self.clock.sleep(env.accepting_tx_interval).await;
 
         let num_tx_requests = self
             .block_builder
@@ -80,7 +82,7 @@ impl State {
             .await
             .construct_block(is_registration_block)?;
 
-        tokio::time::sleep(Duration::from_secs(env.proposing_block_interval)).await;
+        self.clock.sleep(env.proposing_block_interval).await;
 
         self.block_builder
             .write()
@@ -17,6 +17,13 @@ pub struct Env {
     pub block_builder_private_key: H256,
     pub eth_allowance_for_block: String,
 
+    // Ethereum address of the sponsor whose fee vouchers are accepted (optional)
+    pub sponsor_address: Option<Address>,
+
     pub accepting_tx_interval: u64,
     pub proposing_block_interval: u64,
+
+    // run on a clock that can be advanced through `/admin/time/advance`, for tests
+    #[serde(default)]
+    pub virtual_time: bool,
 }
@@ -1,13 +1,25 @@
 use std::io;
 
 use actix_cors::Cors;
-use actix_web::{middleware::Logger, web::Data, App, HttpServer};
+use actix_web::{
+    dev::Service as _,
+    http::header::{HeaderValue, DATE},
+    middleware::Logger,
+    web::Data,
+    App, HttpServer,
+};
 use block_builder::{
-    api::{api::block_builder_scope, block_builder::BlockBuilder, state::State},
+    api::{
+        api::{admin_scope, block_builder_scope},
+        block_builder::BlockBuilder,
+        clock::Clock,
+        state::State,
+    },
     health_check::health_check,
     Env,
 };
 use intmax2_client_sdk::utils::init_logger::init_logger;
+use intmax2_interfaces::api::CORS_EXPOSED_HEADERS;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -26,8 +38,13 @@ async fn main() -> std::io::Result<()> {
         env.block_builder_private_key,
         eth_allowance_for_block.into(),
         &env.validity_prover_base_url,
+        env.sponsor_address,
     );
-    let state = State::new(block_builder);
+    let clock = Clock::new(env.virtual_time);
+    if clock.is_virtual() {
+        log::warn!("Virtual time is enabled, do not use this in production");
+    }
+    let state = State::new(block_builder, clock.clone());
 
     // Start the block builder job
     let state_for_registration_cycle = state.clone();
@@ -37,13 +54,31 @@ async fn main() -> std::io::Result<()> {
 
     let state = Data::new(state);
     HttpServer::new(move || {
-        let cors = Cors::permissive();
+        let cors = Cors::permissive().expose_headers(CORS_EXPOSED_HEADERS);
         App::new()
             .wrap(cors)
             .wrap(Logger::new("Request: %r | Status: %s | Duration: %Ts"))
             .app_data(state.clone())
+            .wrap_fn({
+                let clock = clock.clone();
+                move |req, srv| {
+                    let fut = srv.call(req);
+                    let clock = clock.clone();
+                    async move {
+                        let mut res = fut.await?;
+                        // clients measure the server clock from this header
+                        if clock.is_virtual() {
+                            if let Ok(date) = HeaderValue::from_str(&clock.http_date()) {
+                                res.headers_mut().insert(DATE, date);
+                            }
+                        }
+                        Ok(res)
+                    }
+                }
+            })
             .service(health_check)
             .service(block_builder_scope())
+            .service(admin_scope())
     })
     .bind(format!("0.0.0.0:{}", env.port))?
     .run()
@@ -0,0 +1,220 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use intmax2_client_sdk::client::template::{contact_name, parse_recipient};
+use intmax2_zkp::{
+    common::generic_address::GenericAddress,
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::CliError,
+    i18n::{tr, tr_with},
+    utils::local_dir,
+};
+
+/// Named intmax pubkeys, kept in `~/.intmax2/address_book.json`. The book is shared
+/// by all accounts of the user and is not encrypted, as it only holds public keys.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AddressBook {
+    /// Pubkey in hex by name
+    pub entries: BTreeMap<String, String>,
+}
+
+fn validate_name(name: &str) -> Result<(), CliError> {
+    if name.is_empty() {
+        return Err(CliError::AddressBookError("name is empty".to_string()));
+    }
+    // names are told apart from pubkeys and addresses by the prefix
+    if name.starts_with("0x") {
+        return Err(CliError::AddressBookError(format!(
+            "name {} starts with 0x",
+            name
+        )));
+    }
+    // `@name` refers to `name`
+    if name.starts_with('@') {
+        return Err(CliError::AddressBookError(format!(
+            "name {} starts with @",
+            name
+        )));
+    }
+    if name.chars().any(char::is_whitespace) {
+        return Err(CliError::AddressBookError(format!(
+            "name {:?} has a space",
+            name
+        )));
+    }
+    Ok(())
+}
+
+impl AddressBook {
+    /// Save `pubkey` as `name`, replacing the pubkey saved under it if any. Returns
+    /// the replaced pubkey.
+    pub fn add(&mut self, name: &str, pubkey: &str) -> Result<Option<String>, CliError> {
+        validate_name(name)?;
+        let recipient = parse_recipient(pubkey.trim()).map_err(CliError::AddressBookError)?;
+        let pubkey = recipient.to_pubkey().map_err(|_| {
+            CliError::AddressBookError(format!("{} is not an intmax pubkey", pubkey))
+        })?;
+        Ok(self.entries.insert(name.to_string(), pubkey.to_hex()))
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<String, CliError> {
+        self.entries
+            .remove(name)
+            .ok_or_else(|| CliError::AddressBookError(format!("no entry named {}", name)))
+    }
+
+    /// A hex pubkey or address as is, or the pubkey saved as a name, which may be
+    /// written `@name`
+    pub fn resolve(&self, recipient: &str) -> Result<GenericAddress, CliError> {
+        if recipient.starts_with("0x") {
+            return parse_recipient(recipient).map_err(CliError::AddressBookError);
+        }
+        let recipient = contact_name(recipient).unwrap_or(recipient);
+        let pubkey = self.entries.get(recipient).ok_or_else(|| {
+            CliError::AddressBookError(format!(
+                "{} is neither a hex pubkey or address nor a name of the address book",
+                recipient
+            ))
+        })?;
+        let pubkey = U256::from_hex(pubkey).map_err(|_| {
+            CliError::AddressBookError(format!("entry {} has an invalid pubkey", recipient))
+        })?;
+        Ok(GenericAddress::from_pubkey(pubkey))
+    }
+
+    /// Pubkey saved as `name`
+    pub fn pubkey(&self, name: &str) -> Option<U256> {
+        U256::from_hex(self.entries.get(name)?).ok()
+    }
+
+    /// Name of `pubkey` in the book, the first by name if it is saved more than once
+    pub fn label(&self, pubkey: U256) -> Option<String> {
+        let pubkey = pubkey.to_hex();
+        self.entries
+            .iter()
+            .find(|(_, saved)| **saved == pubkey)
+            .map(|(name, _)| name.clone())
+    }
+}
+
+fn address_book_path() -> Result<PathBuf, CliError> {
+    Ok(local_dir()?.join("address_book.json"))
+}
+
+pub fn load_address_book() -> Result<AddressBook, CliError> {
+    load_address_book_in(&local_dir()?)
+}
+
+/// The address book in `dir`, or an empty one if there is none
+pub fn load_address_book_in(dir: &Path) -> Result<AddressBook, CliError> {
+    let path = dir.join("address_book.json");
+    let json = match fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(AddressBook::default()),
+        Err(e) => {
+            return Err(CliError::AddressBookError(format!(
+                "Failed to read {:?}: {}",
+                path, e
+            )))
+        }
+    };
+    serde_json::from_str(&json)
+        .map_err(|e| CliError::AddressBookError(format!("Failed to parse {:?}: {}", path, e)))
+}
+
+fn save_address_book(book: &AddressBook) -> Result<(), CliError> {
+    let path = address_book_path()?;
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, serde_json::to_string_pretty(book).unwrap()))
+        .map_err(|e| CliError::AddressBookError(format!("Failed to write {:?}: {}", path, e)))
+}
+
+/// The recipient of `--to`: a hex pubkey or address, or a name of the address book,
+/// with or without a leading `@`
+pub fn resolve_recipient(recipient: &str) -> Result<GenericAddress, CliError> {
+    if recipient.starts_with("0x") {
+        return AddressBook::default().resolve(recipient);
+    }
+    load_address_book()?.resolve(recipient)
+}
+
+pub fn address_book_add(name: String, pubkey: String) -> Result<(), CliError> {
+    let mut book = load_address_book()?;
+    let replaced = book.add(&name, &pubkey)?;
+    save_address_book(&book)?;
+    match replaced {
+        Some(old) => println!(
+            "{}",
+            tr_with("msg.contact_replaced", &[("name", &name), ("old", &old)])
+        ),
+        None => println!("{}", tr_with("msg.contact_saved", &[("name", &name)])),
+    }
+    Ok(())
+}
+
+pub fn address_book_list() -> Result<(), CliError> {
+    let book = load_address_book()?;
+    if book.entries.is_empty() {
+        println!("{}", tr("msg.address_book_empty"));
+    }
+    for (name, pubkey) in book.entries.iter() {
+        println!("{}\t{}", name, pubkey);
+    }
+    Ok(())
+}
+
+pub fn address_book_remove(name: String) -> Result<(), CliError> {
+    let mut book = load_address_book()?;
+    book.remove(&name)?;
+    save_address_book(&book)?;
+    println!("{}", tr_with("msg.contact_removed", &[("name", &name)]));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: &str = "0x0000000000000000000000000000000000000000000000000000000000000007";
+
+    #[test]
+    fn test_address_book() {
+        let mut book = AddressBook::default();
+        assert_eq!(book.add("alice", PUBKEY).unwrap(), None);
+        assert_eq!(book.add("alice", PUBKEY).unwrap(), Some(PUBKEY.to_string()));
+
+        // only intmax pubkeys, under names that are not hex
+        let address = format!("0x{}", "11".repeat(20));
+        assert!(book.add("bob", &address).is_err());
+        assert!(book.add("bob", "0x1234").is_err());
+        assert!(book.add("0xbob", PUBKEY).is_err());
+        assert!(book.add("@bob", PUBKEY).is_err());
+        assert!(book.add("bob smith", PUBKEY).is_err());
+
+        let alice = book.resolve("alice").unwrap();
+        assert_eq!(alice.to_pubkey().unwrap().to_hex(), PUBKEY);
+        assert_eq!(book.resolve("@alice").unwrap(), alice);
+        assert_eq!(book.resolve(PUBKEY).unwrap(), alice);
+        assert!(!book.resolve(&address).unwrap().is_pubkey);
+        let CliError::AddressBookError(message) = book.resolve("carol").unwrap_err() else {
+            panic!("expected an address book error");
+        };
+        assert!(message.contains("carol"));
+
+        assert_eq!(
+            book.label(alice.to_pubkey().unwrap()),
+            Some("alice".to_string())
+        );
+        book.remove("alice").unwrap();
+        assert!(book.remove("alice").is_err());
+        assert_eq!(book.label(alice.to_pubkey().unwrap()), None);
+    }
+}
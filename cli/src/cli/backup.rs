@@ -0,0 +1,51 @@
+use intmax2_client_sdk::client::backup_health::BackupCoverage;
+use intmax2_zkp::{common::signature::key_set::KeySet, ethereum_types::bytes32::Bytes32};
+
+use super::{
+    client::get_client,
+    error::CliError,
+    i18n::{tr, tr_with},
+};
+
+fn print_coverage(name: &str, coverage: &BackupCoverage) {
+    println!(
+        "{}",
+        tr_with(
+            "msg.coverage",
+            &[
+                ("name", &name),
+                ("backed_up", &coverage.backed_up),
+                ("expected", &coverage.expected),
+            ],
+        )
+    );
+    for id in coverage.missing.iter() {
+        println!("{}", tr_with("msg.coverage_missing", &[("id", &id)]));
+    }
+}
+
+/// Print whether everything needed to recover the account is in the store vault.
+/// Returns false if anything is missing.
+pub async fn backup_status(key: KeySet, tx_tree_roots: &[Bytes32]) -> Result<bool, CliError> {
+    let client = get_client()?;
+    let health = client.backup_health(key, tx_tree_roots).await?;
+    if health.user_data_present {
+        println!("{}", tr("msg.user_data_present"));
+    } else {
+        println!("{}", tr("msg.user_data_missing"));
+    }
+    print_coverage(&tr("msg.deposits"), &health.deposits);
+    print_coverage(&tr("msg.transfers"), &health.transfers);
+    print_coverage(&tr("msg.txs"), &health.txs);
+    match health.last_backup_at {
+        Some(timestamp) => println!(
+            "{}",
+            tr_with("msg.last_backup", &[("timestamp", &timestamp)])
+        ),
+        None => println!("{}", tr("msg.no_backups")),
+    }
+    if health.is_healthy() {
+        println!("{}", tr("msg.fully_backed_up"));
+    }
+    Ok(health.is_healthy())
+}
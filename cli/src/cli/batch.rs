@@ -0,0 +1,330 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ethers::types::U256;
+use intmax2_client_sdk::client::template::parse_recipient;
+use intmax2_interfaces::api::output_stream::types::ProgressStage;
+use intmax2_zkp::{
+    common::{generic_address::GenericAddress, signature::key_set::KeySet, transfer::Transfer},
+    constants::NUM_TRANSFERS_IN_TX,
+};
+use num_bigint::BigUint;
+use serde_json::json;
+
+use crate::cli::{
+    client::get_client,
+    send::single_transfer,
+    utils::{display_id, load_env},
+};
+
+use super::{
+    error::CliError,
+    i18n::{tr, tr_with},
+    output::{print_notice, print_warning},
+    send::tx,
+    stream::{emit_progress, set_stream_result},
+};
+
+/// A row `recipient,token_index,amount` of a payout file
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchRow {
+    /// 1-based line in the file, for error messages
+    pub line: usize,
+    pub recipient: GenericAddress,
+    pub token_index: u32,
+    pub amount: U256,
+    /// The line as written, copied to the remainder file
+    pub raw: String,
+}
+
+fn parse_row(line: usize, raw: &str) -> Result<BatchRow, String> {
+    let fields = raw.split(',').map(str::trim).collect::<Vec<_>>();
+    let [recipient, token_index, amount] = fields[..] else {
+        return Err(format!(
+            "expected recipient,token_index,amount, got {} fields",
+            fields.len()
+        ));
+    };
+    let recipient = parse_recipient(recipient)?;
+    if !recipient.is_pubkey {
+        return Err(format!(
+            "{} is an address; only transfers to intmax pubkeys can be batched",
+            fields[0]
+        ));
+    }
+    let token_index = token_index
+        .parse::<u32>()
+        .map_err(|_| format!("invalid token index {}", token_index))?;
+    let amount = U256::from_dec_str(amount).map_err(|_| format!("invalid amount {}", amount))?;
+    if amount.is_zero() {
+        return Err("zero amount".to_string());
+    }
+    Ok(BatchRow {
+        line,
+        recipient,
+        token_index,
+        amount,
+        raw: raw.trim().to_string(),
+    })
+}
+
+/// Parse every row of a payout CSV, reporting all invalid rows at once. Blank lines
+/// and a `recipient,...` header are skipped.
+pub fn parse_batch(content: &str) -> Result<Vec<BatchRow>, CliError> {
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (i, raw) in content.lines().enumerate() {
+        let line = i + 1;
+        if raw.trim().is_empty() || (rows.is_empty() && raw.trim().starts_with("recipient")) {
+            continue;
+        }
+        match parse_row(line, raw) {
+            Ok(row) => rows.push(row),
+            Err(e) => errors.push(format!("line {}: {}", line, e)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(CliError::BatchError(errors.join("\n")));
+    }
+    if rows.is_empty() {
+        return Err(CliError::BatchError("no transfers in the file".to_string()));
+    }
+    Ok(rows)
+}
+
+/// Check that the account holds every token of `rows`, and enough of it to pay
+/// them all. `balances` are the amounts per token index.
+pub fn check_balances(
+    rows: &[BatchRow],
+    balances: &BTreeMap<u32, BigUint>,
+) -> Result<(), CliError> {
+    let mut totals = BTreeMap::<u32, BigUint>::new();
+    for row in rows {
+        let mut bytes = [0u8; 32];
+        row.amount.to_big_endian(&mut bytes);
+        *totals.entry(row.token_index).or_default() += BigUint::from_bytes_be(&bytes);
+    }
+    let mut errors = Vec::new();
+    for (token_index, total) in totals.iter() {
+        match balances.get(token_index) {
+            None => errors.push(format!("unknown token {}: no balance", token_index)),
+            Some(balance) if balance < total => errors.push(format!(
+                "token {}: the rows pay {}, but the balance is {}",
+                token_index, total, balance
+            )),
+            Some(_) => {}
+        }
+    }
+    if !errors.is_empty() {
+        return Err(CliError::BatchError(errors.join("\n")));
+    }
+    Ok(())
+}
+
+/// Rows grouped into txs of up to `NUM_TRANSFERS_IN_TX` transfers, in file order
+pub fn chunk_rows(rows: &[BatchRow]) -> Vec<Vec<BatchRow>> {
+    rows.chunks(NUM_TRANSFERS_IN_TX)
+        .map(|chunk| chunk.to_vec())
+        .collect()
+}
+
+fn remainder_path(file: &Path) -> PathBuf {
+    let mut name = file
+        .file_stem()
+        .map(|stem| stem.to_os_string())
+        .unwrap_or_default();
+    name.push(".remainder.csv");
+    file.with_file_name(name)
+}
+
+fn write_remainder(file: &Path, chunks: &[Vec<BatchRow>]) -> Result<PathBuf, CliError> {
+    let path = remainder_path(file);
+    let mut csv = "recipient,token_index,amount\n".to_string();
+    for row in chunks.iter().flatten() {
+        csv += &row.raw;
+        csv += "\n";
+    }
+    fs::write(&path, csv)
+        .map_err(|e| CliError::BatchError(format!("Failed to write {:?}: {}", path, e)))?;
+    Ok(path)
+}
+
+/// Send the transfers of the payout CSV `file`, one tx per chunk. If a chunk fails,
+/// the rows not sent are written to `<file>.remainder.csv` to resume with. With
+/// `dry_run`, only the validation and the chunks are printed.
+pub async fn send_batch(
+    key: KeySet,
+    file: &Path,
+    dry_run: bool,
+    sponsor_url: Option<String>,
+) -> Result<(), CliError> {
+    let content = fs::read_to_string(file)
+        .map_err(|e| CliError::BatchError(format!("Failed to read {:?}: {}", file, e)))?;
+    let rows = parse_batch(&content)?;
+
+    let env = load_env()?;
+    let client = get_client()?;
+    // checked without a sync, so that a dry run writes nothing to the store vault
+    let user_data = client.get_user_data(key).await?;
+    let balances = user_data
+        .balances()
+        .iter()
+        .map(|(token_index, leaf)| (*token_index as u32, BigUint::from(leaf.amount)))
+        .collect();
+    check_balances(&rows, &balances)?;
+
+    let chunks = chunk_rows(&rows);
+    print_notice(tr_with(
+        "msg.batch_plan",
+        &[("transfers", &rows.len()), ("txs", &chunks.len())],
+    ));
+    for (i, chunk) in chunks.iter().enumerate() {
+        print_notice(tr_with(
+            "msg.batch_plan_tx",
+            &[
+                ("tx", &(i + 1)),
+                ("first", &chunk.first().unwrap().line),
+                ("last", &chunk.last().unwrap().line),
+            ],
+        ));
+    }
+    if dry_run {
+        print_notice(tr("msg.dry_run"));
+        set_stream_result(&json!({ "dryRun": true, "transfers": rows.len(), "txs": chunks.len() }));
+        return Ok(());
+    }
+
+    let mut tx_tree_roots = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        print_notice(tr_with(
+            "msg.batch_sending",
+            &[("tx", &(i + 1)), ("txs", &chunks.len())],
+        ));
+        let transfers = chunk
+            .iter()
+            .map(|row| single_transfer(row.recipient, row.amount, row.token_index))
+            .collect::<Vec<Transfer>>();
+        let result = tx(
+            key,
+            transfers,
+            None,
+            None,
+            false,
+            sponsor_url.clone(),
+            None,
+            None,
+            None,
+        )
+        .await;
+        let result = match result {
+            Ok(Some(tx_tree_root)) => client
+                .wait_for_inclusion(
+                    tx_tree_root,
+                    env.tx_timeout,
+                    env.block_builder_query_interval,
+                )
+                .await
+                .map(|_| tx_tree_root)
+                .map_err(CliError::from),
+            Ok(None) => Err(CliError::BatchError(
+                "the account has pending actions".to_string(),
+            )),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(tx_tree_root) => {
+                print_notice(tr_with(
+                    "msg.batch_included",
+                    &[("root", &display_id(tx_tree_root))],
+                ));
+                tx_tree_roots.push(tx_tree_root);
+                emit_progress(ProgressStage::Batch, i as u32 + 1, chunks.len() as u32);
+            }
+            Err(e) => {
+                // a tx finalized but not seen in a block yet may still be included
+                let path = write_remainder(file, &chunks[i..])?;
+                print_warning(format!(
+                    "Tx {} failed. The rows from line {} are written to {:?}; check the \
+                     history before resuming with it, as tx {} may still be included",
+                    i + 1,
+                    chunk.first().unwrap().line,
+                    path,
+                    i + 1
+                ));
+                return Err(e);
+            }
+        }
+    }
+    print_notice(tr_with("msg.batch_sent", &[("count", &rows.len())]));
+    set_stream_result(&json!({ "transfers": rows.len(), "txTreeRoots": tx_tree_roots }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: &str = "0x0000000000000000000000000000000000000000000000000000000000000007";
+
+    #[test]
+    fn test_parse_batch() {
+        let content = format!(
+            "recipient,token_index,amount\n{},0,100\n\n{}, 1 , 5\n",
+            PUBKEY, PUBKEY
+        );
+        let rows = parse_batch(&content).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[1].line, 4);
+        assert_eq!(rows[1].token_index, 1);
+        assert_eq!(rows[1].amount, U256::from(5u64));
+
+        // every bad row is reported
+        let content = format!(
+            "{},0,0\n0x1234,0,1\n{},x,1\n0x{},0,1\n{},0\n",
+            PUBKEY,
+            PUBKEY,
+            "11".repeat(20),
+            PUBKEY
+        );
+        let CliError::BatchError(errors) = parse_batch(&content).unwrap_err() else {
+            panic!("expected a batch error");
+        };
+        let errors = errors.lines().collect::<Vec<_>>();
+        assert_eq!(errors.len(), 5);
+        assert!(errors[0].contains("zero amount"));
+        assert!(errors[3].contains("address"));
+    }
+
+    #[test]
+    fn test_check_balances_and_chunks() {
+        let content = (0..NUM_TRANSFERS_IN_TX + 1)
+            .map(|_| format!("{},0,10", PUBKEY))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let rows = parse_batch(&content).unwrap();
+        let chunks = chunk_rows(&rows);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), NUM_TRANSFERS_IN_TX);
+        assert_eq!(chunks[1][0].line, NUM_TRANSFERS_IN_TX + 1);
+
+        let total = BigUint::from(10 * (NUM_TRANSFERS_IN_TX + 1));
+        let enough = BTreeMap::from([(0, total.clone())]);
+        assert!(check_balances(&rows, &enough).is_ok());
+        let short = BTreeMap::from([(0, total - 1u32)]);
+        assert!(check_balances(&rows, &short).is_err());
+        let other_token = BTreeMap::from([(1, BigUint::from(1000u32))]);
+        assert!(check_balances(&rows, &other_token).is_err());
+    }
+
+    #[test]
+    fn test_remainder_path() {
+        assert_eq!(
+            remainder_path(Path::new("/tmp/payouts.csv")),
+            PathBuf::from("/tmp/payouts.remainder.csv")
+        );
+    }
+}
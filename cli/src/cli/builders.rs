@@ -0,0 +1,722 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use colored::Colorize as _;
+use fs2::FileExt as _;
+use intmax2_client_sdk::{
+    client::tx_status::{get_tx_status, TxStatus},
+    external_api::utils::clock::global_clock,
+};
+use intmax2_interfaces::api::{
+    indexer::interface::BlockBuilderInfo, validity_prover::interface::ValidityProverClientInterface,
+};
+use intmax2_zkp::ethereum_types::bytes32::Bytes32;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    client::get_client,
+    error::CliError,
+    i18n::tr,
+    profile::{Config, Profile},
+    utils::{load_env, local_dir},
+};
+
+fn normalize(entry: &str) -> String {
+    entry.trim().trim_end_matches('/').to_lowercase()
+}
+
+/// `trusted_builders` and `blocked_builders` of the active profile, each entry a
+/// URL or an advertised address
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuilderPolicy {
+    trusted: Vec<String>,
+    blocked: Vec<String>,
+}
+
+impl BuilderPolicy {
+    pub fn new(trusted: &[String], blocked: &[String]) -> Self {
+        let entries = |list: &[String]| {
+            list.iter()
+                .map(|entry| normalize(entry))
+                .filter(|entry| !entry.is_empty())
+                .collect()
+        };
+        Self {
+            trusted: entries(trusted),
+            blocked: entries(blocked),
+        }
+    }
+
+    pub fn from_profile(profile: &Profile) -> Self {
+        Self::new(&profile.trusted_builders, &profile.blocked_builders)
+    }
+
+    /// The policy of the active profile, which is empty without one
+    pub fn load() -> Result<Self, CliError> {
+        let config = Config::load()?;
+        Ok(config
+            .active_profile()
+            .map(|(_, profile)| Self::from_profile(profile))
+            .unwrap_or_default())
+    }
+
+    fn matches(list: &[String], url: &str, address: Option<&str>) -> bool {
+        let url = normalize(url);
+        let address = address.map(normalize);
+        list.iter()
+            .any(|entry| *entry == url || Some(entry) == address.as_ref())
+    }
+
+    pub fn is_trusted(&self, url: &str, address: Option<&str>) -> bool {
+        Self::matches(&self.trusted, url, address)
+    }
+
+    pub fn is_blocked(&self, url: &str, address: Option<&str>) -> bool {
+        Self::matches(&self.blocked, url, address)
+    }
+}
+
+/// Outcome of a tx sent through a block builder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuilderOutcome {
+    /// The proposal came this long after the tx request was accepted
+    Proposal(Duration),
+    /// The tx request was accepted, but no proposal came
+    Timeout,
+    /// The tx request was refused on every try
+    Failure,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuilderStats {
+    pub proposals: u64,
+    pub timeouts: u64,
+    pub failures: u64,
+    pub total_latency_ms: u64,
+    /// Txs finalized through the builder that are in a block
+    #[serde(default)]
+    pub included: u64,
+    /// Txs finalized through the builder that were in no block after the tx timeout
+    #[serde(default)]
+    pub expired: u64,
+}
+
+impl BuilderStats {
+    /// Tx requests accepted by the builder
+    pub fn requests(&self) -> u64 {
+        self.proposals + self.timeouts
+    }
+
+    /// Share of the finalized txs whose inclusion is known that are in a block
+    pub fn inclusion_rate(&self) -> Option<f64> {
+        let settled = self.included + self.expired;
+        (settled > 0).then(|| self.included as f64 / settled as f64)
+    }
+
+    pub fn average_latency(&self) -> Option<Duration> {
+        (self.proposals > 0).then(|| Duration::from_millis(self.total_latency_ms / self.proposals))
+    }
+
+    // share of successes with one success and one miss assumed, so that a builder
+    // without history ranks between a reliable and an unreliable one. A proposal
+    // whose tx expired is a miss.
+    fn score(&self) -> f64 {
+        let successes = self.proposals.saturating_sub(self.expired);
+        (successes + 1) as f64 / (self.requests() + self.failures + 2) as f64
+    }
+}
+
+/// Tx finalized through a block builder, not yet in a block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingInclusion {
+    pub url: String,
+    pub tx_tree_root: Bytes32,
+    pub finalized_at: u64,
+}
+
+/// Outcomes of the txs sent through each block builder, kept in
+/// `~/.intmax2/builders.json` by the normalized URL of the builder
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuilderStatsBook {
+    pub builders: BTreeMap<String, BuilderStats>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending: Vec<PendingInclusion>,
+}
+
+impl BuilderStatsBook {
+    pub fn get(&self, url: &str) -> Option<&BuilderStats> {
+        self.builders.get(&normalize(url))
+    }
+
+    pub fn record(&mut self, url: &str, outcome: BuilderOutcome) {
+        let stats = self.builders.entry(normalize(url)).or_default();
+        match outcome {
+            BuilderOutcome::Proposal(latency) => {
+                stats.proposals += 1;
+                stats.total_latency_ms += latency.as_millis() as u64;
+            }
+            BuilderOutcome::Timeout => stats.timeouts += 1,
+            BuilderOutcome::Failure => stats.failures += 1,
+        }
+    }
+
+    /// Wait for the tx of `tx_tree_root`, finalized through the builder at `url`,
+    /// to be in a block
+    pub fn track_inclusion(&mut self, url: &str, tx_tree_root: Bytes32, finalized_at: u64) {
+        if self
+            .pending
+            .iter()
+            .any(|pending| pending.tx_tree_root == tx_tree_root)
+        {
+            return;
+        }
+        self.pending.push(PendingInclusion {
+            url: normalize(url),
+            tx_tree_root,
+            finalized_at,
+        });
+    }
+
+    /// Count the tx of `tx_tree_root` as included or expired once its status can no
+    /// longer change
+    pub fn settle_inclusion(&mut self, tx_tree_root: Bytes32, status: TxStatus) {
+        if !status.is_terminal() {
+            return;
+        }
+        let Some(index) = self
+            .pending
+            .iter()
+            .position(|pending| pending.tx_tree_root == tx_tree_root)
+        else {
+            return;
+        };
+        let pending = self.pending.remove(index);
+        let stats = self.builders.entry(pending.url).or_default();
+        match status {
+            TxStatus::Included { .. } => stats.included += 1,
+            TxStatus::Expired => stats.expired += 1,
+            TxStatus::Pending => unreachable!("status is terminal"),
+        }
+    }
+}
+
+/// The builder to send txs to among those of the indexer: never a blocked one, a
+/// trusted one if any, and among them the one with the best record, then the
+/// fastest. Ties keep the order of the indexer.
+pub fn select_builder<'a>(
+    candidates: &'a [BlockBuilderInfo],
+    policy: &BuilderPolicy,
+    book: &BuilderStatsBook,
+) -> Result<&'a BlockBuilderInfo, CliError> {
+    let default_stats = BuilderStats::default();
+    let mut allowed = candidates
+        .iter()
+        .filter(|info| !policy.is_blocked(&info.url, info.address.as_deref()))
+        .map(|info| {
+            let stats = book.get(&info.url).unwrap_or(&default_stats);
+            let trusted = policy.is_trusted(&info.url, info.address.as_deref());
+            (info, trusted, stats)
+        })
+        .collect::<Vec<_>>();
+    if allowed.is_empty() {
+        return Err(CliError::BuilderError(format!(
+            "all {} block builders of the indexer are blocked",
+            candidates.len()
+        )));
+    }
+    allowed.sort_by(|(_, trusted_a, a), (_, trusted_b, b)| {
+        trusted_b
+            .cmp(trusted_a)
+            .then(b.score().total_cmp(&a.score()))
+            .then(
+                a.average_latency()
+                    .unwrap_or(Duration::MAX)
+                    .cmp(&b.average_latency().unwrap_or(Duration::MAX)),
+            )
+    });
+    Ok(allowed[0].0)
+}
+
+fn builders_path_in(dir: &Path) -> PathBuf {
+    dir.join("builders.json")
+}
+
+pub fn load_builder_stats() -> Result<BuilderStatsBook, CliError> {
+    load_builder_stats_in(&local_dir()?)
+}
+
+pub fn load_builder_stats_in(dir: &Path) -> Result<BuilderStatsBook, CliError> {
+    let path = builders_path_in(dir);
+    let json = match fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(BuilderStatsBook::default())
+        }
+        Err(e) => {
+            return Err(CliError::BuilderError(format!(
+                "Failed to read {:?}: {}",
+                path, e
+            )))
+        }
+    };
+    serde_json::from_str(&json)
+        .map_err(|e| CliError::BuilderError(format!("Failed to parse {:?}: {}", path, e)))
+}
+
+/// Apply `update` to the stats in `dir`. Concurrent commands take turns on an OS
+/// lock of `builders.json.lock`, so that none drops the update of another, and the
+/// file is replaced by a rename, so that a reader never sees it half written.
+pub fn update_builder_stats_in<U>(dir: &Path, update: U) -> Result<(), CliError>
+where
+    U: FnOnce(&mut BuilderStatsBook),
+{
+    let error = |path: &Path, e: std::io::Error| {
+        CliError::BuilderError(format!("Failed to write {:?}: {}", path, e))
+    };
+    fs::create_dir_all(dir).map_err(|e| error(dir, e))?;
+    let lock_path = dir.join("builders.json.lock");
+    let lock = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .and_then(|lock| lock.lock_exclusive().map(|_| lock))
+        .map_err(|e| error(&lock_path, e))?;
+
+    let mut book = load_builder_stats_in(dir)?;
+    update(&mut book);
+    let path = builders_path_in(dir);
+    let tmp_path = path.with_extension("json.tmp");
+    let result = fs::write(&tmp_path, serde_json::to_string_pretty(&book).unwrap())
+        .and_then(|_| fs::rename(&tmp_path, &path))
+        .map_err(|e| error(&path, e));
+    // the OS also releases the lock when the file is closed
+    let _ = lock.unlock();
+    result
+}
+
+// the stats only guide the choice of builder, so failing to save them does not
+// fail the command
+fn update_builder_stats<U>(url: &str, update: U)
+where
+    U: FnOnce(&mut BuilderStatsBook),
+{
+    if let Err(e) = local_dir().and_then(|dir| update_builder_stats_in(&dir, update)) {
+        log::warn!("Failed to update the stats of block builder {}: {}", url, e);
+    }
+}
+
+/// Add `outcome` to the stats of the builder at `url`
+pub fn record_builder_outcome(url: &str, outcome: BuilderOutcome) {
+    update_builder_stats(url, |book| book.record(url, outcome));
+}
+
+/// Count the tx of `tx_tree_root`, just finalized through the builder at `url`, in
+/// the inclusion rate of the builder once it is in a block or expired
+pub fn track_builder_inclusion(url: &str, tx_tree_root: Bytes32) {
+    let finalized_at = global_clock().server_now();
+    update_builder_stats(url, |book| {
+        book.track_inclusion(url, tx_tree_root, finalized_at)
+    });
+}
+
+/// Settle the pending inclusions of the stats in `dir` whose tx is now in a block,
+/// or in none after `tx_timeout`
+pub async fn check_builder_inclusions_in<V: ValidityProverClientInterface>(
+    dir: &Path,
+    validity_prover: &V,
+    tx_timeout: u64,
+) -> Result<(), CliError> {
+    let pending = load_builder_stats_in(dir)?.pending;
+    let mut settled = Vec::new();
+    for inclusion in pending {
+        let status = get_tx_status(
+            validity_prover,
+            inclusion.tx_tree_root,
+            Some(inclusion.finalized_at),
+            tx_timeout,
+        )
+        .await?;
+        if status.is_terminal() {
+            settled.push((inclusion.tx_tree_root, status));
+        }
+    }
+    if settled.is_empty() {
+        return Ok(());
+    }
+    update_builder_stats_in(dir, |book| {
+        for (tx_tree_root, status) in settled {
+            book.settle_inclusion(tx_tree_root, status);
+        }
+    })
+}
+
+/// `check_builder_inclusions_in` on `~/.intmax2`. A failure is only logged.
+pub async fn check_builder_inclusions(tx_timeout: u64) {
+    let result: Result<(), CliError> = async {
+        let client = get_client()?;
+        check_builder_inclusions_in(&local_dir()?, &client.validity_prover, tx_timeout).await
+    }
+    .await;
+    if let Err(e) = result {
+        log::warn!(
+            "Failed to check the inclusion of the txs of block builders: {}",
+            e
+        );
+    }
+}
+
+/// The address the indexer advertises for the builder at `url`, if any
+pub fn advertised_address<'a>(candidates: &'a [BlockBuilderInfo], url: &str) -> Option<&'a str> {
+    candidates
+        .iter()
+        .find(|info| normalize(&info.url) == normalize(url))
+        .and_then(|info| info.address.as_deref())
+}
+
+/// Print a warning if the builder forced with `--builder` or BLOCK_BUILDER_BASE_URL
+/// is blocked by its URL or by `address`, the one the indexer advertises for it.
+/// The builder is used anyway.
+pub fn warn_if_blocked(policy: &BuilderPolicy, url: &str, address: Option<&str>) {
+    if policy.is_blocked(url, address) {
+        eprintln!(
+            "{} {}",
+            "WARNING:".red().bold(),
+            format!(
+                "block builder {} is in the blocked_builders of the profile",
+                url
+            )
+            .red()
+        );
+    }
+}
+
+fn format_builder_stats(book: &BuilderStatsBook, policy: &BuilderPolicy) -> String {
+    let mut lines = vec![
+        "builder\tstatus\tproposals\ttimeouts\tfailures\tincluded\texpired\tinclusion\tlatency"
+            .to_string(),
+    ];
+    for (url, stats) in book.builders.iter() {
+        let status = if policy.is_blocked(url, None) {
+            "blocked"
+        } else if policy.is_trusted(url, None) {
+            "trusted"
+        } else {
+            "-"
+        };
+        let inclusion = stats
+            .inclusion_rate()
+            .map_or("-".to_string(), |rate| format!("{:.0}%", rate * 100.0));
+        let latency = stats.average_latency().map_or("-".to_string(), |latency| {
+            format!("{:.1}s", latency.as_secs_f64())
+        });
+        lines.push(format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            url,
+            status,
+            stats.proposals,
+            stats.timeouts,
+            stats.failures,
+            stats.included,
+            stats.expired,
+            inclusion,
+            latency
+        ));
+    }
+    lines.join("\n")
+}
+
+pub async fn builders_stats() -> Result<(), CliError> {
+    if let Ok(env) = load_env() {
+        check_builder_inclusions(env.tx_timeout).await;
+    }
+    let book = load_builder_stats()?;
+    if book.builders.is_empty() {
+        println!("{}", tr("msg.no_builder_txs"));
+        return Ok(());
+    }
+    println!("{}", format_builder_stats(&book, &BuilderPolicy::load()?));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use intmax2_client_sdk::external_api::{
+        utils::mock_server::start_binary_mock_server, validity_prover::ValidityProverClient,
+    };
+    use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+
+    use crate::cli::utils::test_dir;
+
+    use super::*;
+
+    const HEALTHY: &str = "http://healthy.example.com";
+    const FLAKY: &str = "http://flaky.example.com/";
+    const TX_TIMEOUT: u64 = 60;
+
+    // validity prover that has the tx tree roots of `posted` in block 7, and no other
+    fn validity_prover(posted: Arc<Mutex<Vec<Bytes32>>>) -> ValidityProverClient {
+        let (url, _) = start_binary_mock_server(move |request, _| {
+            let posted = posted.lock().unwrap();
+            let block_number = posted
+                .iter()
+                .any(|root| request.query.contains(&root.to_hex()))
+                .then_some(7);
+            let body = serde_json::json!({ "blockNumber": block_number }).to_string();
+            (200, "application/json", body.into_bytes())
+        });
+        ValidityProverClient::new(&url).with_bearer_token("token")
+    }
+
+    // a builder that answers every tx request with a proposal, and posts the block
+    // of the tx if `posts`
+    fn send_through(
+        dir: &Path,
+        url: &str,
+        posts: bool,
+        posted: &Mutex<Vec<Bytes32>>,
+        finalized_at: u64,
+    ) -> Bytes32 {
+        let tx_tree_root = Bytes32::rand(&mut rand::thread_rng());
+        update_builder_stats_in(dir, |book| {
+            book.record(url, BuilderOutcome::Proposal(Duration::from_secs(1)));
+            book.track_inclusion(url, tx_tree_root, finalized_at);
+        })
+        .unwrap();
+        if posts {
+            posted.lock().unwrap().push(tx_tree_root);
+        }
+        tx_tree_root
+    }
+
+    fn info(url: &str, address: Option<&str>) -> BlockBuilderInfo {
+        BlockBuilderInfo {
+            fee: 0.0,
+            speed: 0,
+            url: url.to_string(),
+            address: address.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_select_builder_prefers_healthy_builder() {
+        let candidates = vec![info(FLAKY, None), info(HEALTHY, None)];
+        let policy = BuilderPolicy::default();
+        let mut book = BuilderStatsBook::default();
+        // without history, the order of the indexer
+        assert_eq!(
+            select_builder(&candidates, &policy, &book).unwrap().url,
+            FLAKY
+        );
+
+        book.record(FLAKY, BuilderOutcome::Timeout);
+        book.record(HEALTHY, BuilderOutcome::Proposal(Duration::from_secs(2)));
+        assert_eq!(
+            select_builder(&candidates, &policy, &book).unwrap().url,
+            HEALTHY
+        );
+
+        // a trusted builder is preferred despite its record
+        let policy = BuilderPolicy::new(&["HTTP://FLAKY.example.com".to_string()], &[]);
+        assert_eq!(
+            select_builder(&candidates, &policy, &book).unwrap().url,
+            FLAKY
+        );
+    }
+
+    #[test]
+    fn test_select_builder_refuses_blocked_builders() {
+        let address = format!("0x{}", "ab".repeat(20));
+        let candidates = vec![info(HEALTHY, Some(&address)), info(FLAKY, None)];
+        let mut book = BuilderStatsBook::default();
+        book.record(HEALTHY, BuilderOutcome::Proposal(Duration::from_secs(1)));
+        book.record(FLAKY, BuilderOutcome::Failure);
+
+        // blocked by address, and trusting it does not override the block
+        let policy = BuilderPolicy::new(&[HEALTHY.to_string()], &[address.to_uppercase()]);
+        assert!(policy.is_blocked(HEALTHY, Some(&address)));
+        assert_eq!(
+            select_builder(&candidates, &policy, &book).unwrap().url,
+            FLAKY
+        );
+
+        let policy = BuilderPolicy::new(&[], &[address, FLAKY.to_string()]);
+        let CliError::BuilderError(message) =
+            select_builder(&candidates, &policy, &book).unwrap_err()
+        else {
+            panic!("expected a builder error");
+        };
+        assert!(message.contains("all 2"));
+    }
+
+    #[test]
+    fn test_builder_stats() {
+        let mut book = BuilderStatsBook::default();
+        book.record(
+            HEALTHY,
+            BuilderOutcome::Proposal(Duration::from_millis(1500)),
+        );
+        book.record(
+            HEALTHY,
+            BuilderOutcome::Proposal(Duration::from_millis(2500)),
+        );
+        book.record(FLAKY, BuilderOutcome::Proposal(Duration::from_secs(8)));
+        book.record(FLAKY, BuilderOutcome::Timeout);
+        book.record(FLAKY, BuilderOutcome::Timeout);
+        book.record(FLAKY, BuilderOutcome::Timeout);
+        book.record(FLAKY, BuilderOutcome::Failure);
+
+        let healthy = book.get(HEALTHY).unwrap();
+        assert_eq!(healthy.requests(), 2);
+        assert_eq!(healthy.average_latency(), Some(Duration::from_secs(2)));
+        let flaky = book.get(FLAKY).unwrap();
+        assert_eq!(flaky.requests(), 4);
+        // a proposal says nothing of the inclusion of the tx
+        assert_eq!(flaky.inclusion_rate(), None);
+
+        let mut rng = rand::thread_rng();
+        let roots = [0; 3].map(|_| Bytes32::rand(&mut rng));
+        book.track_inclusion(HEALTHY, roots[0], 0);
+        book.track_inclusion(FLAKY, roots[1], 0);
+        book.track_inclusion(FLAKY, roots[2], 0);
+        book.settle_inclusion(roots[0], TxStatus::Included { block_number: 1 });
+        book.settle_inclusion(roots[1], TxStatus::Included { block_number: 1 });
+        book.settle_inclusion(roots[2], TxStatus::Pending);
+        assert_eq!(book.pending.len(), 1);
+        book.settle_inclusion(roots[2], TxStatus::Expired);
+        assert!(book.pending.is_empty());
+        assert_eq!(book.get(HEALTHY).unwrap().inclusion_rate(), Some(1.0));
+        assert_eq!(book.get(FLAKY).unwrap().inclusion_rate(), Some(0.5));
+
+        let policy = BuilderPolicy::new(&[], &[FLAKY.to_string()]);
+        let output = format_builder_stats(&book, &policy);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            "http://flaky.example.com\tblocked\t1\t3\t1\t1\t1\t50%\t8.0s"
+        );
+        assert_eq!(
+            lines[2],
+            "http://healthy.example.com\t-\t2\t0\t0\t1\t0\t100%\t2.0s"
+        );
+
+        let parsed: BuilderStatsBook =
+            serde_json::from_str(&serde_json::to_string(&book).unwrap()).unwrap();
+        assert_eq!(parsed, book);
+    }
+
+    #[tokio::test]
+    async fn test_builder_that_drops_blocks_loses_its_rank() {
+        let dir = test_dir("builders-inclusion");
+        let posted = Arc::new(Mutex::new(Vec::new()));
+        let validity_prover = validity_prover(posted.clone());
+        let candidates = vec![info(FLAKY, None), info(HEALTHY, None)];
+        let policy = BuilderPolicy::default();
+
+        // both builders propose every tx, but only the healthy one posts the blocks
+        let expired_at = global_clock().server_now() - 2 * TX_TIMEOUT;
+        for _ in 0..3 {
+            send_through(&dir, FLAKY, false, &posted, expired_at);
+            send_through(&dir, HEALTHY, true, &posted, expired_at);
+        }
+        // a tx that may still be posted
+        let recent = send_through(&dir, FLAKY, false, &posted, global_clock().server_now());
+        let book = load_builder_stats_in(&dir).unwrap();
+        assert_eq!(book.pending.len(), 7);
+        assert_eq!(
+            select_builder(&candidates, &policy, &book).unwrap().url,
+            FLAKY
+        );
+
+        check_builder_inclusions_in(&dir, &validity_prover, TX_TIMEOUT)
+            .await
+            .unwrap();
+        let book = load_builder_stats_in(&dir).unwrap();
+        let flaky = book.get(FLAKY).unwrap();
+        assert_eq!((flaky.included, flaky.expired), (0, 3));
+        assert_eq!(flaky.inclusion_rate(), Some(0.0));
+        let healthy = book.get(HEALTHY).unwrap();
+        assert_eq!((healthy.included, healthy.expired), (3, 0));
+        assert_eq!(healthy.inclusion_rate(), Some(1.0));
+        assert_eq!(book.pending.len(), 1);
+        assert_eq!(book.pending[0].tx_tree_root, recent);
+        assert_eq!(
+            select_builder(&candidates, &policy, &book).unwrap().url,
+            HEALTHY
+        );
+
+        // the recent tx is counted once its block is posted
+        posted.lock().unwrap().push(recent);
+        check_builder_inclusions_in(&dir, &validity_prover, TX_TIMEOUT)
+            .await
+            .unwrap();
+        let book = load_builder_stats_in(&dir).unwrap();
+        assert!(book.pending.is_empty());
+        assert_eq!(book.get(FLAKY).unwrap().included, 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_concurrent_updates_are_kept() {
+        let dir = test_dir("builders-concurrent");
+        let threads = (0..8)
+            .map(|_| {
+                let dir = dir.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..5 {
+                        update_builder_stats_in(&dir, |book| {
+                            book.record(HEALTHY, BuilderOutcome::Timeout)
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let book = load_builder_stats_in(&dir).unwrap();
+        assert_eq!(book.get(HEALTHY).unwrap().timeouts, 40);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_forced_builder_is_blocked_by_its_address() {
+        let address = format!("0x{}", "cd".repeat(20));
+        let candidates = vec![info(HEALTHY, None), info(FLAKY, Some(&address))];
+        let policy = BuilderPolicy::new(&[], &[address.clone()]);
+        let forced = "HTTP://flaky.example.com";
+        assert!(!policy.is_blocked(forced, None));
+        let advertised = advertised_address(&candidates, forced);
+        assert_eq!(advertised, Some(address.as_str()));
+        assert!(policy.is_blocked(forced, advertised));
+        assert_eq!(advertised_address(&candidates, HEALTHY), None);
+    }
+
+    #[test]
+    fn test_policy_from_profile() {
+        let config: Config = toml::from_str(
+            r#"
+            [profiles.main]
+            pubkey = "0x01"
+            key = { storage = "keyring" }
+            trusted_builders = ["https://Builder.example.com/"]
+            blocked_builders = ["0xAB"]
+            env = {}
+            "#,
+        )
+        .unwrap();
+        let policy = BuilderPolicy::from_profile(&config.profiles["main"]);
+        assert!(policy.is_trusted("https://builder.example.com", None));
+        assert!(policy.is_blocked("https://other.example.com", Some("0xab")));
+    }
+}
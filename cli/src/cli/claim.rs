@@ -0,0 +1,116 @@
+use ethers::types::{Address, H256};
+use intmax2_client_sdk::external_api::contract::{
+    liquidity_contract::ClaimResult,
+    multicall_contract::{MulticallContract, MULTICALL3_ADDRESS},
+};
+use intmax2_interfaces::api::{
+    output_stream::types::ProgressStage, withdrawal_server::interface::WithdrawalStatus,
+};
+use intmax2_zkp::common::signature::key_set::KeySet;
+use serde_json::json;
+
+use super::{
+    client::{gas_config, get_client},
+    error::CliError,
+    i18n::{tr, tr_with},
+    output::print_notice,
+    stream::{emit_progress, set_stream_result},
+    utils::load_env,
+};
+
+const DEFAULT_CLAIM_BLOCK_GAS_FRACTION: f64 = 0.5;
+
+pub async fn claim_withdrawals(
+    key: KeySet,
+    eth_private_key: H256,
+    all: bool,
+    no_batch: bool,
+) -> Result<(), CliError> {
+    let env = load_env()?;
+    let client = get_client()?;
+    let withdrawal_info = client.get_withdrawal_info(key).await?;
+    let mut withdrawals = withdrawal_info
+        .into_iter()
+        .filter(|w| matches!(w.status, WithdrawalStatus::NeedClaim))
+        .map(|w| w.contract_withdrawal)
+        .collect::<Vec<_>>();
+    if withdrawals.is_empty() {
+        print_notice(tr("msg.no_withdrawals_to_claim"));
+        set_stream_result(&claim_results_json(&[]));
+        return Ok(());
+    }
+    if !all {
+        withdrawals.truncate(1);
+    }
+    let total = withdrawals.len() as u32;
+    let mut claimed = 0;
+    let on_claimed = |_: &ClaimResult| {
+        claimed += 1;
+        emit_progress(ProgressStage::Claim, claimed, total);
+    };
+
+    let liquidity_contract = client.liquidity_contract.clone();
+    let results = if no_batch {
+        liquidity_contract
+            .claim_withdrawals_sequential_with_progress(eth_private_key, &withdrawals, on_claimed)
+            .await?
+    } else {
+        let multicall_address = match env.multicall_address {
+            Some(address) => address,
+            None => MULTICALL3_ADDRESS.parse::<Address>().unwrap(),
+        };
+        let multicall = MulticallContract::new(&env.l1_rpc_url, env.l1_chain_id, multicall_address)
+            .with_gas_config(gas_config(&env)?);
+        liquidity_contract
+            .claim_withdrawals_batched_with_progress(
+                eth_private_key,
+                &withdrawals,
+                &multicall,
+                env.claim_block_gas_fraction
+                    .unwrap_or(DEFAULT_CLAIM_BLOCK_GAS_FRACTION),
+                on_claimed,
+            )
+            .await?
+    };
+    print_claim_results(&results);
+    set_stream_result(&claim_results_json(&results));
+    Ok(())
+}
+
+// the `result` line of `--output ndjson-stream`
+fn claim_results_json(results: &[ClaimResult]) -> serde_json::Value {
+    let claims = results
+        .iter()
+        .map(|result| {
+            json!({
+                "recipient": result.withdrawal.recipient.to_string(),
+                "tokenIndex": result.withdrawal.token_index,
+                "amount": result.withdrawal.amount.to_string(),
+                "success": result.success,
+                "txHash": result.tx_hash.map(|h| format!("{:?}", h)),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({ "claims": claims })
+}
+
+fn print_claim_results(results: &[ClaimResult]) {
+    for (i, result) in results.iter().enumerate() {
+        let withdrawal = &result.withdrawal;
+        let tx_hash = result
+            .tx_hash
+            .map(|h| format!("{:?}", h))
+            .unwrap_or("-".to_string());
+        print_notice(tr_with(
+            "msg.claim_result",
+            &[
+                ("index", &i),
+                ("recipient", &withdrawal.recipient),
+                ("token_index", &withdrawal.token_index),
+                ("amount", &withdrawal.amount),
+                ("success", &result.success),
+                ("tx_hash", &tx_hash),
+            ],
+        ));
+    }
+}
@@ -1,10 +1,21 @@
+use std::sync::Arc;
+
 use intmax2_client_sdk::{
-    client::{client::Client, config::ClientConfig},
+    client::{
+        client::Client, config::ClientConfig, withdrawal_onchain::DEFAULT_WITHDRAWAL_ONCHAIN_GRACE,
+    },
     external_api::{
         balance_prover::BalanceProverClient,
         block_builder::BlockBuilderClient,
-        contract::{liquidity_contract::LiquidityContract, rollup_contract::RollupContract},
+        cached_validity_prover::CachedValidityProver,
+        contract::{
+            gas::{GasConfig, GasStrategyConfig},
+            liquidity_contract::LiquidityContract,
+            rollup_contract::RollupContract,
+        },
+        mirrored_store_vault::MirroredStoreVaultClient,
         store_vault_server::StoreVaultServerClient,
+        utils::url::normalize_base_url,
         validity_prover::ValidityProverClient,
         withdrawal_server::WithdrawalServerClient,
     },
@@ -12,38 +23,105 @@ use intmax2_client_sdk::{
 
 use crate::Env;
 
-use super::error::CliError;
+use super::{
+    error::CliError,
+    mirror_queue::FileMirrorQueueStore,
+    provisional::FileProvisionalStore,
+    utils::{load_env, local_dir},
+    witness_cache::FileWitnessStore,
+};
 
 type BB = BlockBuilderClient;
-type S = StoreVaultServerClient;
-type V = ValidityProverClient;
+type S = MirroredStoreVaultClient<StoreVaultServerClient>;
+type V = CachedValidityProver<ValidityProverClient>;
 type B = BalanceProverClient;
 type W = WithdrawalServerClient;
 
 pub fn get_client() -> Result<Client<BB, S, V, B, W>, CliError> {
-    let env = envy::from_env::<Env>()?;
+    get_client_from(&load_env()?)
+}
+
+/// Client of the settings in `env`, e.g. those of a reloaded config
+pub fn get_client_from(env: &Env) -> Result<Client<BB, S, V, B, W>, CliError> {
     let block_builder = BB::new();
-    let store_vault_server = S::new(&env.store_vault_server_base_url);
+    let store_vault_server_url = normalize_base_url(
+        "STORE_VAULT_SERVER_BASE_URL",
+        &env.store_vault_server_base_url,
+    )?;
+    let store_vault_read_url = match &env.store_vault_read_url {
+        Some(url) => normalize_base_url("STORE_VAULT_READ_URL", url)?,
+        None => store_vault_server_url.clone(),
+    };
+    let store_vault_write_url = match &env.store_vault_write_url {
+        Some(url) => normalize_base_url("STORE_VAULT_WRITE_URL", url)?,
+        None => store_vault_server_url.clone(),
+    };
+    let secondary_store_vaults = env
+        .secondary_store_vault_urls
+        .iter()
+        .map(|url| {
+            normalize_base_url("SECONDARY_STORE_VAULT_URLS", url)
+                .map(|url| StoreVaultServerClient::new(&url))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let store_vault_server = S::new(
+        StoreVaultServerClient::with_urls(&store_vault_read_url, &store_vault_write_url),
+        secondary_store_vaults,
+    )
+    .with_queue_store(Arc::new(FileMirrorQueueStore::new(
+        local_dir()?.join("mirror_queue.json"),
+    )));
 
-    let validity_prover = V::new(&env.validity_prover_base_url);
-    let balance_prover = B::new(&env.balance_prover_base_url);
-    let withdrawal_server = W::new(&env.withdrawal_server_base_url);
+    let mut validity_prover_client = ValidityProverClient::new(&normalize_base_url(
+        "VALIDITY_PROVER_BASE_URL",
+        &env.validity_prover_base_url,
+    )?);
+    if let Some(token) = &env.validity_prover_bearer_token {
+        validity_prover_client = validity_prover_client.with_bearer_token(token);
+    }
+    let mut validity_prover = V::new(validity_prover_client);
+    // the cache is used once `watcher` has created it
+    let witness_store = FileWitnessStore::from_env(env)?;
+    if witness_store.dir().exists() {
+        validity_prover = validity_prover.with_witness_store(Arc::new(witness_store));
+    }
+    let mut balance_prover = B::new(&normalize_base_url(
+        "BALANCE_PROVER_BASE_URL",
+        &env.balance_prover_base_url,
+    )?);
+    if let Some(token) = &env.balance_prover_bearer_token {
+        balance_prover = balance_prover.with_bearer_token(token);
+    }
+    let withdrawal_server = W::new(&normalize_base_url(
+        "WITHDRAWAL_SERVER_BASE_URL",
+        &env.withdrawal_server_base_url,
+    )?);
 
+    let gas_config = gas_config(env)?;
     let liquidity_contract = LiquidityContract::new(
         &env.l1_rpc_url,
         env.l1_chain_id,
         env.liquidity_contract_address,
-    );
+    )
+    .with_gas_config(gas_config)
+    .with_deployed_block_number(env.liquidity_contract_deployed_block_number.unwrap_or(0));
     let rollup_contract = RollupContract::new(
         &env.l2_rpc_url,
         env.l2_chain_id,
         env.rollup_contract_address,
         env.rollup_contract_deployed_block_number,
-    );
+    )
+    .with_gas_config(gas_config);
 
     let config = ClientConfig {
         deposit_timeout: env.deposit_timeout,
         tx_timeout: env.tx_timeout,
+        checkpoint_interval: env.checkpoint_interval,
+        builder_max_staleness: env.builder_max_staleness,
+        strict_proposal_validation: env.strict_proposal_validation.unwrap_or(false),
+        withdrawal_onchain_grace: env
+            .withdrawal_onchain_grace
+            .unwrap_or(DEFAULT_WITHDRAWAL_ONCHAIN_GRACE),
     };
 
     let client = Client {
@@ -55,7 +133,24 @@ pub fn get_client() -> Result<Client<BB, S, V, B, W>, CliError> {
         liquidity_contract,
         rollup_contract,
         config,
+        provisional_store: Arc::new(FileProvisionalStore::new(local_dir()?.join("provisional"))),
     };
 
     Ok(client)
 }
+
+pub fn gas_config(env: &Env) -> Result<GasConfig, CliError> {
+    let mut gas_config = GasConfig::default();
+    if let Some(strategy) = &env.gas_strategy {
+        gas_config.strategy = strategy
+            .parse::<GasStrategyConfig>()
+            .map_err(CliError::UnexpectedError)?;
+    }
+    if let Some(timeout) = env.stuck_tx_timeout {
+        gas_config.stuck_timeout = Some(timeout);
+    }
+    if let Some(max_bumps) = env.max_fee_bumps {
+        gas_config.max_bumps = max_bumps;
+    }
+    Ok(gas_config)
+}
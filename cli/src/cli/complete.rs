@@ -0,0 +1,202 @@
+use std::path::Path;
+
+use clap::Command;
+
+use super::{address_book::load_address_book_in, profile::Config, utils::local_dir};
+
+const TOKEN_TYPES: [&str; 4] = ["NATIVE", "ERC20", "ERC721", "ERC1155"];
+
+/// Returns completion candidates for the given words, which are the command line
+/// arguments following `__complete`. The last word is the one being completed.
+///
+/// This must stay offline and side-effect free: no network calls, no key material,
+/// no prompts and no logging, because it is invoked on every tab press.
+pub fn complete(command: &Command, words: &[String]) -> Vec<String> {
+    complete_in(command, words, local_dir().ok().as_deref())
+}
+
+/// [`complete`], with the address book and the profiles read from `dir`
+pub fn complete_in(command: &Command, words: &[String], dir: Option<&Path>) -> Vec<String> {
+    let current = words.last().map(|s| s.as_str()).unwrap_or("");
+    let previous = &words[..words.len().saturating_sub(1)];
+    // global flags such as --profile may come before the subcommand
+    let subcommand = previous
+        .iter()
+        .find_map(|word| command.find_subcommand(word))
+        .unwrap_or(command);
+
+    if let Some(flag) = previous.last().and_then(|prev| prev.strip_prefix("--")) {
+        return filter(value_candidates(subcommand, flag, dir), current);
+    }
+    if !current.starts_with('-') && std::ptr::eq(subcommand, command) {
+        return filter(
+            command
+                .get_subcommands()
+                .filter(|c| !c.is_hide_set())
+                .map(|c| c.get_name().to_string()),
+            current,
+        );
+    }
+    if current.starts_with('-') || current.is_empty() {
+        return filter(
+            subcommand
+                .get_arguments()
+                .filter(|a| !a.is_hide_set())
+                .filter_map(|a| a.get_long().map(|long| format!("--{}", long))),
+            current,
+        );
+    }
+    Vec::new()
+}
+
+fn value_candidates(subcommand: &Command, flag: &str, dir: Option<&Path>) -> Vec<String> {
+    // unreadable files give no candidates rather than an error, as there is no one
+    // to report it to. Token indices are not completed: there is no token registry,
+    // and nothing about the tokens is kept locally.
+    match flag {
+        "token-type" => return TOKEN_TYPES.iter().map(|s| s.to_string()).collect(),
+        "to" => {
+            return dir
+                .and_then(|dir| load_address_book_in(dir).ok())
+                .map(|book| book.entries.into_keys().collect())
+                .unwrap_or_default()
+        }
+        "profile" => {
+            return dir
+                .and_then(|dir| Config::load_in(dir).ok())
+                .map(|config| config.profiles.into_keys().collect())
+                .unwrap_or_default()
+        }
+        _ => {}
+    }
+    subcommand
+        .get_arguments()
+        .find(|a| a.get_long() == Some(flag))
+        .map(|a| {
+            a.get_possible_values()
+                .iter()
+                .map(|v| v.get_name().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn filter(candidates: impl Iterator<Item = String>, prefix: &str) -> Vec<String> {
+    candidates.filter(|c| c.starts_with(prefix)).collect()
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+}
+
+/// Script for `completions <shell>`, which asks `__complete` for the candidates of
+/// the word being completed
+pub fn completion_script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH_SCRIPT,
+        Shell::Zsh => ZSH_SCRIPT,
+    }
+}
+
+const BASH_SCRIPT: &str = r#"_intmax2_cli() {
+    local IFS=$'\n'
+    COMPREPLY=($(intmax2-cli __complete "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+}
+complete -F _intmax2_cli intmax2-cli
+"#;
+
+const ZSH_SCRIPT: &str = r#"#compdef intmax2-cli
+_intmax2_cli() {
+    local -a candidates
+    candidates=("${(@f)$(intmax2-cli __complete "${(@)words[2,CURRENT]}" 2>/dev/null)}")
+    compadd -a candidates
+}
+compdef _intmax2_cli intmax2-cli
+"#;
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use clap::{Arg, Command};
+
+    use super::*;
+
+    fn command() -> Command {
+        Command::new("intmax2-cli")
+            .arg(Arg::new("profile").long("profile").global(true))
+            .subcommand(
+                Command::new("tx")
+                    .arg(Arg::new("to").long("to"))
+                    .arg(Arg::new("amount").long("amount"))
+                    .arg(Arg::new("token-index").long("token-index")),
+            )
+            .subcommand(Command::new("sync").arg(Arg::new("token").long("token")))
+            .subcommand(Command::new("__complete").hide(true))
+    }
+
+    fn seeded_dir() -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("intmax2-complete-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("address_book.json"),
+            r#"{"entries":{"alice":"0x01","albert":"0x02","bob":"0x03"}}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            r#"
+default_profile = "main"
+
+[profiles.main]
+pubkey = "0x01"
+key = { storage = "keyring" }
+env = {}
+
+[profiles.testnet]
+pubkey = "0x02"
+key = { storage = "keyring" }
+env = {}
+"#,
+        )
+        .unwrap();
+        dir
+    }
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_complete_seeded_values() {
+        let dir = seeded_dir();
+        let command = command();
+        let complete = |w: &[&str]| complete_in(&command, &words(w), Some(&dir));
+
+        assert_eq!(complete(&["tx", "--to", "al"]), ["albert", "alice"]);
+        assert_eq!(complete(&["tx", "--to", ""]), ["albert", "alice", "bob"]);
+        assert_eq!(complete(&["tx", "--profile", ""]), ["main", "testnet"]);
+        assert_eq!(complete(&["--profile", "t"]), ["testnet"]);
+        assert_eq!(complete(&["--profile", "main", "tx", "--to", "b"]), ["bob"]);
+        assert!(complete(&["tx", "--token-index", ""]).is_empty());
+        assert!(complete(&["sync", "--token", ""]).is_empty());
+        assert!(complete(&["tx", "--amount", ""]).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_complete_subcommands_and_flags() {
+        let command = command();
+        let complete = |w: &[&str]| complete_in(&command, &words(w), None);
+
+        assert_eq!(complete(&[""]), ["tx", "sync"]);
+        assert_eq!(complete(&["s"]), ["sync"]);
+        assert_eq!(complete(&["tx", "--t"]), ["--to", "--token-index"]);
+        // without a local directory there are no names to offer
+        assert!(complete(&["tx", "--to", ""]).is_empty());
+    }
+}
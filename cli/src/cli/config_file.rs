@@ -0,0 +1,513 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use super::{
+    error::CliError,
+    i18n::tr_with,
+    init::{preset_settings, Preset, DEFAULT_SETTINGS, REQUIRED_SETTINGS},
+    profile::Config,
+    support_bundle::{redact_toml_value, redact_url},
+    utils::{load_env, local_dir},
+};
+
+/// Optional settings of the template, with an example value
+const OPTIONAL_SETTINGS: &[(&str, &str, &str)] = &[
+    (
+        "BLOCK_BUILDER_BASE_URL",
+        "Block builder URL, instead of the one of the indexer",
+        "http://localhost:9004",
+    ),
+    (
+        "OFFSITE_BACKUP_ENDPOINT",
+        "S3-compatible storage of `backup push` and `backup pull`",
+        "http://localhost:9000",
+    ),
+    (
+        "GAS_STRATEGY",
+        "Fees of deposits and claims, e.g. legacy:1.2, eip1559:50:10 or fixed:30:2",
+        "legacy:1.2",
+    ),
+    (
+        "CHECKPOINT_INTERVAL",
+        "Sync actions between the checkpoints an interrupted sync resumes from",
+        "100",
+    ),
+];
+
+const TEMPLATE_HEADER: &str = "\
+# Settings of the intmax2 CLI, one per environment variable of the same name in
+# lower case. Variables of the environment, `.env` and the active profile take
+# precedence over them. Lists, e.g. secondary_store_vault_urls, are written as
+# TOML arrays.
+#
+# The values below are those of the local devnet; uncomment and edit them.
+[settings]
+";
+
+// file of `--config`, read instead of ~/.intmax2/config.toml
+static CONFIG_FILE: OnceLock<PathBuf> = OnceLock::new();
+// settings of the config file, read at startup by `load_config_file`
+static SETTINGS: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+/// Read the settings from `path` instead of ~/.intmax2/config.toml
+pub fn set_config_file(path: PathBuf) {
+    let _ = CONFIG_FILE.set(path);
+}
+
+/// The file of `--config` if given, else ~/.intmax2/config.toml, which also holds
+/// the profiles
+pub fn config_path() -> Result<PathBuf, CliError> {
+    match CONFIG_FILE.get() {
+        Some(path) => Ok(path.clone()),
+        None => Ok(Config::path_in(&local_dir()?)),
+    }
+}
+
+fn setting_value(key: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        // envy reads a list as comma separated values
+        toml::Value::Array(values) => values
+            .iter()
+            .map(|value| setting_value(key, value))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|values| values.join(",")),
+        _ => Err(format!(
+            "{} is not a string, number, boolean or list of them",
+            key
+        )),
+    }
+}
+
+/// Check the URL of the setting `key`, so that a typo fails at startup instead of
+/// deep inside a sync
+fn check_url(key: &str, url: &str) -> Result<(), String> {
+    let invalid = |reason: String| {
+        format!(
+            "{} = {:?} is not a valid URL: {}",
+            key,
+            redact_url(url),
+            reason
+        )
+    };
+    let parsed = url::Url::parse(url.trim()).map_err(|e| invalid(e.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https" | "ws" | "wss") {
+        return Err(invalid(format!(
+            "scheme {} is not http(s) or ws(s)",
+            parsed.scheme()
+        )));
+    }
+    if parsed.host_str().is_none() {
+        return Err(invalid("missing host".to_string()));
+    }
+    Ok(())
+}
+
+/// The `[settings]` of a config file by the name of their variable, the key in
+/// upper case, e.g. `L1_RPC_URL` for `l1_rpc_url`
+pub fn parse_config_file(toml: &str) -> Result<BTreeMap<String, String>, String> {
+    let table = toml.parse::<toml::Table>().map_err(|e| e.to_string())?;
+    let table = match table.get("settings") {
+        Some(toml::Value::Table(table)) => table.clone(),
+        Some(_) => return Err("settings is not a table".to_string()),
+        None => toml::Table::new(),
+    };
+    let mut settings = BTreeMap::new();
+    for (key, value) in table.iter() {
+        let name = key.to_uppercase();
+        let value = setting_value(key, value)?;
+        if name.ends_with("_URL") && !value.is_empty() {
+            check_url(key, &value)?;
+        }
+        if name.ends_with("_URLS") {
+            for url in value.split(',').filter(|url| !url.is_empty()) {
+                check_url(key, url)?;
+            }
+        }
+        settings.insert(name, value);
+    }
+    Ok(settings)
+}
+
+/// The settings of the config file at `path`, or none if there is no file
+pub fn read_config_file(path: &Path) -> Result<Option<BTreeMap<String, String>>, CliError> {
+    let toml = match fs::read_to_string(path) {
+        Ok(toml) => toml,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(CliError::ConfigFileError(format!(
+                "Failed to read {:?}: {}",
+                path, e
+            )))
+        }
+    };
+    parse_config_file(&toml)
+        .map(Some)
+        .map_err(|e| CliError::ConfigFileError(format!("Failed to parse {:?}: {}", path, e)))
+}
+
+/// The settings of `file` whose variable is not set, as the environment takes
+/// precedence
+pub fn missing_settings(
+    file: &BTreeMap<String, String>,
+    is_set: impl Fn(&str) -> bool,
+) -> BTreeMap<String, String> {
+    file.iter()
+        .filter(|(name, _)| !is_set(name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Read the settings of the config file, checking them at startup. A missing file
+/// is an error only if given by `--config`.
+pub fn load_config_file() -> Result<(), CliError> {
+    let path = config_path()?;
+    let settings = match read_config_file(&path)? {
+        Some(settings) => settings,
+        None if CONFIG_FILE.get().is_some() => {
+            return Err(CliError::ConfigFileError(format!(
+                "{:?} does not exist",
+                path
+            )))
+        }
+        None => BTreeMap::new(),
+    };
+    let _ = SETTINGS.set(settings);
+    Ok(())
+}
+
+/// The variables of the environment, completed by the settings of the config file
+/// it does not set. `apply_profile_env` runs first, so that the environment and
+/// `.env` take precedence over the active profile, which takes precedence over the
+/// config file.
+pub fn env_vars() -> BTreeMap<String, String> {
+    let mut vars = missing_settings(SETTINGS.get().unwrap_or(&BTreeMap::new()), |name| {
+        std::env::var_os(name).is_some()
+    });
+    vars.extend(std::env::vars());
+    vars
+}
+
+fn template_line(name: &str, value: &str) -> String {
+    let value = if value.parse::<u64>().is_ok() {
+        value.to_string()
+    } else {
+        format!("{:?}", value)
+    };
+    format!("# {} = {}\n", name.to_lowercase(), value)
+}
+
+/// Template written by `config init`: every setting commented out, with the values
+/// of the local devnet
+pub fn config_template() -> String {
+    let devnet = preset_settings(Preset::Devnet);
+    let mut template = TEMPLATE_HEADER.to_string();
+    for (name, description) in REQUIRED_SETTINGS {
+        let value = devnet.get(*name).map(String::as_str).unwrap_or_default();
+        template += &format!("\n# {}\n", description);
+        template += &template_line(name, value);
+    }
+    template += "\n# Timeouts of deposits and txs, and polling of the block builder (seconds)\n";
+    for (name, value) in DEFAULT_SETTINGS {
+        template += &template_line(name, value);
+    }
+    template += "\n# Optional settings\n";
+    for (name, description, value) in OPTIONAL_SETTINGS {
+        template += &format!("\n# {}\n", description);
+        template += &template_line(name, value);
+    }
+    template
+}
+
+/// Append the template to the config file, whose profiles are kept. A file that
+/// already has settings is only changed with `force`, which drops them.
+pub fn config_init(force: bool) -> Result<(), CliError> {
+    let path = config_path()?;
+    let existing = match fs::read_to_string(&path) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            return Err(CliError::ConfigFileError(format!(
+                "Failed to read {:?}: {}",
+                path, e
+            )))
+        }
+    };
+    let file = with_template(&existing, force)
+        .map_err(|e| CliError::ConfigFileError(format!("{:?}: {}", path, e)))?;
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, file))
+        .map_err(|e| CliError::ConfigFileError(format!("Failed to write {:?}: {}", path, e)))?;
+    println!(
+        "{}",
+        tr_with(
+            "msg.settings_template_written",
+            &[("path", &path.display())]
+        )
+    );
+    Ok(())
+}
+
+/// `existing` with the template appended in place of its settings
+fn with_template(existing: &str, force: bool) -> Result<String, String> {
+    let mut table = existing.parse::<toml::Table>().map_err(|e| e.to_string())?;
+    let rest = if table.remove("settings").is_none() {
+        existing.trim_end().to_string()
+    } else if force {
+        toml::to_string_pretty(&table)
+            .map_err(|e| e.to_string())?
+            .trim_end()
+            .to_string()
+    } else {
+        return Err("it already has settings, pass --force to replace them".to_string());
+    };
+    if rest.is_empty() {
+        return Ok(config_template());
+    }
+    Ok(rest + "\n\n" + &config_template())
+}
+
+/// Where the value of the variable `name` comes from
+fn source_of(
+    name: &str,
+    from_file: &BTreeSet<String>,
+    profile: Option<(&str, &BTreeMap<String, String>)>,
+    value: Option<&str>,
+) -> String {
+    if from_file.contains(name) {
+        return "config file".to_string();
+    }
+    match (profile, value) {
+        (Some((profile, env)), Some(value)) if env.get(name).map(String::as_str) == Some(value) => {
+            format!("profile {}", profile)
+        }
+        (_, Some(_)) => "environment".to_string(),
+        (_, None) => "default".to_string(),
+    }
+}
+
+/// Print the settings the commands use, after merging the environment, the active
+/// profile and the config file, with secrets and the paths of URLs redacted. Each
+/// setting is followed by where it comes from, and URLs that do not parse are
+/// flagged.
+pub fn config_show() -> Result<(), CliError> {
+    let path = config_path()?;
+    let config = Config::load()?;
+    let profile = config.active_profile();
+    let from_file = SETTINGS
+        .get()
+        .map(|settings| {
+            missing_settings(settings, |name| std::env::var_os(name).is_some())
+                .into_keys()
+                .collect::<BTreeSet<_>>()
+        })
+        .unwrap_or_default();
+
+    let env = load_env()?;
+    let toml::Value::Table(mut settings) = toml::Value::try_from(&env)
+        .map_err(|e| CliError::UnexpectedError(format!("Failed to serialize settings: {}", e)))?
+    else {
+        unreachable!("Env is a struct");
+    };
+    let mut problems = BTreeMap::new();
+    for (key, value) in settings.iter_mut() {
+        if let toml::Value::String(url) = value {
+            if key.ends_with("_url") {
+                if let Err(e) = check_url(key, url) {
+                    problems.insert(key.clone(), e);
+                }
+            }
+        }
+        redact_toml_value(key, value);
+    }
+
+    let file_state = if path.exists() { "" } else { " (not found)" };
+    println!("# config file: {}{}", path.display(), file_state);
+    if let Some((name, _)) = &profile {
+        println!("# profile: {}", name);
+    }
+    for (key, value) in settings.iter() {
+        let name = key.to_uppercase();
+        let source = source_of(
+            &name,
+            &from_file,
+            profile
+                .as_ref()
+                .map(|(name, profile)| (name.as_str(), &profile.env)),
+            std::env::var(&name).ok().as_deref(),
+        );
+        match problems.get(key) {
+            Some(problem) => println!("{} = {}  # {}, {}", key, value, source, problem),
+            None => println!("{} = {}  # {}", key, value, source),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Env;
+
+    // the template with every setting uncommented
+    fn uncommented(template: &str) -> String {
+        template
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(setting) if setting.split(' ').nth(1) == Some("=") => setting,
+                _ => line,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let settings = parse_config_file(
+            r#"
+            [settings]
+            l1_rpc_url = "https://rpc.example.com/v2/secret"
+            L1_CHAIN_ID = 11155111
+            strict_proposal_validation = true
+            claim_block_gas_fraction = 0.5
+            secondary_store_vault_urls = ["http://a.example.com", "http://b.example.com"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings["L1_RPC_URL"], "https://rpc.example.com/v2/secret");
+        assert_eq!(settings["L1_CHAIN_ID"], "11155111");
+        assert_eq!(settings["STRICT_PROPOSAL_VALIDATION"], "true");
+        assert_eq!(settings["CLAIM_BLOCK_GAS_FRACTION"], "0.5");
+        assert_eq!(
+            settings["SECONDARY_STORE_VAULT_URLS"],
+            "http://a.example.com,http://b.example.com"
+        );
+
+        // only the settings are read, the profiles are not
+        assert!(parse_config_file("[profiles.dev.env]\nENV = \"dev\"")
+            .unwrap()
+            .is_empty());
+        assert!(parse_config_file("[settings.profile]\nenv = \"dev\"").is_err());
+        assert!(parse_config_file("settings = 1").is_err());
+        assert!(parse_config_file("[settings]\nenv = ").is_err());
+        // a misconfigured URL fails with its setting, without the path of the URL
+        for url in [
+            "localhost:9000",
+            "http//localhost:9000",
+            "ftp://example.com",
+        ] {
+            let e = parse_config_file(&format!(
+                "[settings]\nstore_vault_server_base_url = {:?}",
+                url
+            ))
+            .unwrap_err();
+            assert!(e.contains("store_vault_server_base_url"), "{}", e);
+        }
+        let e = parse_config_file(
+            "[settings]\nsecondary_store_vault_urls = [\"http://a.example.com\", \"b\"]",
+        )
+        .unwrap_err();
+        assert!(e.contains("secondary_store_vault_urls"), "{}", e);
+    }
+
+    #[test]
+    fn test_environment_takes_precedence() {
+        let file = parse_config_file(&uncommented(&config_template())).unwrap();
+        let environment: BTreeMap<String, String> = [
+            ("L1_RPC_URL", "https://rpc.example.com"),
+            ("TX_TIMEOUT", "120"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let missing = missing_settings(&file, |name| environment.contains_key(name));
+        assert!(!missing.contains_key("L1_RPC_URL"));
+        assert!(!missing.contains_key("TX_TIMEOUT"));
+        assert_eq!(missing.len(), file.len() - 2);
+
+        // the merged variables load into the Env of the commands
+        let mut merged = environment.clone();
+        merged.extend(missing.clone());
+        let env = envy::from_iter::<_, Env>(merged).unwrap();
+        assert_eq!(env.l1_rpc_url, "https://rpc.example.com");
+        assert_eq!(env.tx_timeout, 120);
+        assert_eq!(env.l2_rpc_url, "http://127.0.0.1:8545");
+        assert_eq!(env.deposit_timeout, 80);
+        assert_eq!(env.block_builder_base_url.unwrap(), "http://localhost:9004");
+
+        let from_file = missing.into_keys().collect();
+        let profile_env = BTreeMap::from([("TX_TIMEOUT".to_string(), "120".to_string())]);
+        let profile = Some(("default", &profile_env));
+        assert_eq!(
+            source_of(
+                "L2_RPC_URL",
+                &from_file,
+                profile,
+                Some("http://127.0.0.1:8545")
+            ),
+            "config file"
+        );
+        assert_eq!(
+            source_of("TX_TIMEOUT", &from_file, profile, Some("120")),
+            "profile default"
+        );
+        assert_eq!(
+            source_of(
+                "L1_RPC_URL",
+                &from_file,
+                profile,
+                Some("https://rpc.example.com")
+            ),
+            "environment"
+        );
+        assert_eq!(
+            source_of("SECONDARY_STORE_VAULT_URLS", &from_file, profile, None),
+            "default"
+        );
+    }
+
+    #[test]
+    fn test_template_is_commented() {
+        let template = config_template();
+        assert!(parse_config_file(&template).unwrap().is_empty());
+        let settings = parse_config_file(&uncommented(&template)).unwrap();
+        for (name, _) in REQUIRED_SETTINGS.iter().chain(DEFAULT_SETTINGS) {
+            assert!(settings.contains_key(*name), "{} is missing", name);
+        }
+        envy::from_iter::<_, Env>(settings).unwrap();
+    }
+
+    #[test]
+    fn test_init_keeps_profiles() {
+        let profiles = r#"
+default_profile = "dev"
+
+[profiles.dev]
+pubkey = "0x01"
+key = { storage = "keyring" }
+env = { TX_TIMEOUT = "120" }
+"#;
+        let file = with_template(profiles, false).unwrap();
+        let config: Config = toml::from_str(&file).unwrap();
+        assert_eq!(config.default_profile.as_deref(), Some("dev"));
+        assert_eq!(config.profiles["dev"].env["TX_TIMEOUT"], "120");
+        assert!(config.settings.is_empty());
+
+        // settings are only replaced with --force
+        let file = uncommented(&file);
+        assert!(with_template(&file, false).is_err());
+        let replaced = with_template(&file, true).unwrap();
+        assert!(parse_config_file(&replaced).unwrap().is_empty());
+        let config: Config = toml::from_str(&replaced).unwrap();
+        assert_eq!(config.profiles["dev"].pubkey, "0x01");
+        assert_eq!(with_template("", false).unwrap(), config_template());
+    }
+}
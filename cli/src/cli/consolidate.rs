@@ -0,0 +1,135 @@
+use intmax2_client_sdk::client::{
+    client::FeeMode,
+    consolidation::{ConsolidationOptions, ConsolidationPlan},
+};
+use intmax2_zkp::common::signature::key_set::KeySet;
+
+use crate::cli::{
+    client::get_client,
+    send::block_builder_url,
+    utils::{convert_u256, display_id, load_env},
+};
+
+use super::{
+    error::CliError,
+    i18n::{tr, tr_with},
+};
+
+fn print_plan(plan: &ConsolidationPlan, options: &ConsolidationOptions) {
+    println!(
+        "{} incoming transfers and deposits were synced. These balances will be sent to \
+         yourself:",
+        plan.pending_receives
+    );
+    for (token_index, amount) in plan.amounts() {
+        println!(
+            "{}",
+            tr_with(
+                "msg.token_amount",
+                &[("token_index", &token_index), ("amount", &amount)]
+            )
+        );
+    }
+    match options.fee_mode {
+        FeeMode::SelfPaid => println!(
+            "{}",
+            tr_with("msg.consolidation_txs", &[("txs", &plan.chunks.len())])
+        ),
+        FeeMode::Sponsored { .. } => println!(
+            "{}",
+            tr_with(
+                "msg.consolidation_txs_sponsored",
+                &[("txs", &plan.chunks.len())]
+            )
+        ),
+    }
+    if let Some((token_index, amount)) = plan.fee_reserve {
+        println!(
+            "\t {} of token {} is kept for the fees",
+            amount, token_index
+        );
+    }
+    println!(
+        "{}",
+        tr_with(
+            "msg.consolidation_proofs",
+            &[("proofs", &plan.min_prover_calls())]
+        )
+    );
+}
+
+/// Send the balance of `token_indices` (all tokens if empty) to the account itself,
+/// asking for confirmation after the plan is shown unless `yes`
+pub async fn consolidate(
+    key: KeySet,
+    token_indices: Vec<u32>,
+    fee_reserve: Option<(u32, u128)>,
+    min_pending: Option<usize>,
+    sponsor_url: Option<String>,
+    yes: bool,
+) -> Result<(), CliError> {
+    let env = load_env()?;
+    let client = get_client()?;
+    let default = ConsolidationOptions::default();
+    let options = ConsolidationOptions {
+        fee_mode: match sponsor_url {
+            Some(sponsor_url) => FeeMode::Sponsored { sponsor_url },
+            None => FeeMode::SelfPaid,
+        },
+        fee_reserve: fee_reserve
+            .map(|(token_index, amount)| (token_index, convert_u256(amount.into()))),
+        min_pending_receives: min_pending.unwrap_or(default.min_pending_receives),
+        timeout: env.tx_timeout,
+        interval: env.block_builder_query_interval,
+    };
+
+    let block_builder_url = block_builder_url(&env).await?;
+    let plan = client
+        .plan_consolidation(&block_builder_url, key, &token_indices, &options)
+        .await?;
+    print_plan(&plan, &options);
+    if !yes
+        && !dialoguer::Confirm::new()
+            .with_prompt(tr("prompt.send_consolidation"))
+            .default(false)
+            .interact()
+            .map_err(|e| CliError::UnexpectedError(format!("Failed to read input: {}", e)))?
+    {
+        println!("{}", tr("msg.aborted"));
+        return Ok(());
+    }
+
+    let report = client
+        .complete_consolidation(&block_builder_url, key, &plan, &options)
+        .await?;
+    for consolidated in report.consolidated.iter() {
+        println!(
+            "{}",
+            tr_with(
+                "msg.consolidated",
+                &[
+                    ("amount", &consolidated.amount),
+                    ("token_index", &consolidated.token_index),
+                    ("root", &display_id(consolidated.tx_tree_root))
+                ]
+            )
+        );
+    }
+    if report.remaining_receives > 0 {
+        println!(
+            "{} transfers or deposits arrived meanwhile and are left for the next sync",
+            report.remaining_receives
+        );
+    }
+    println!("{}", tr("msg.balances"));
+    for (token_index, amount) in report.balances.iter() {
+        println!(
+            "{}",
+            tr_with(
+                "msg.token_amount",
+                &[("token_index", &token_index), ("amount", &amount)]
+            )
+        );
+    }
+    Ok(())
+}
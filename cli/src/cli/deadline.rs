@@ -0,0 +1,349 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use super::{
+    error::CliError,
+    i18n::{lang, tr_in, Lang},
+};
+
+/// Exit code of a command stopped by `--deadline` or `--timeout`
+pub const TIMEOUT_EXIT_CODE: i32 = 13;
+
+// how often the deadlines are checked
+const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+// time an open session gets to handle an expiry itself, draining its store vault
+// writes, before the whole command is dropped
+const SESSION_GRACE: Duration = Duration::from_secs(3);
+
+/// Part of a command that a timeout names, so that a script knows what stalled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Anything outside the phases below
+    Command,
+    Sync,
+    TxRequest,
+    ProposalWait,
+    Finalize,
+    WithdrawalSync,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = match self {
+            Phase::Command => "command",
+            Phase::Sync => "sync",
+            Phase::TxRequest => "tx_request",
+            Phase::ProposalWait => "proposal_wait",
+            Phase::Finalize => "finalize",
+            Phase::WithdrawalSync => "withdrawal_sync",
+        };
+        write!(f, "{}", phase)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutKind {
+    /// `--deadline` of the whole command
+    Deadline,
+    /// `--timeout` of the phase
+    Timeout,
+}
+
+/// Printed to stderr as JSON before exiting with `TIMEOUT_EXIT_CODE`
+#[derive(Debug, Clone, PartialEq, Serialize, thiserror::Error)]
+#[error("Timed out during {phase} ({kind:?} of {limit:?})")]
+#[serde(rename_all = "camelCase")]
+pub struct TimeoutError {
+    pub phase: Phase,
+    pub kind: TimeoutKind,
+    #[serde(serialize_with = "serialize_secs", rename = "limitSecs")]
+    pub limit: Duration,
+}
+
+impl TimeoutError {
+    pub fn localized_in(&self, lang: Lang) -> String {
+        tr_in(
+            lang,
+            "error.timeout",
+            &[
+                ("phase", &self.phase),
+                ("kind", &format!("{:?}", self.kind)),
+                ("limit", &format!("{:?}", self.limit)),
+            ],
+        )
+    }
+
+    /// The error with `"error": "timeout"`, its `messageId` and the `message`, for
+    /// stderr
+    pub fn to_json(&self) -> String {
+        let mut value = serde_json::to_value(self).unwrap();
+        value["error"] = "timeout".into();
+        value["messageId"] = "error.timeout".into();
+        value["message"] = self.localized_in(lang()).into();
+        value.to_string()
+    }
+}
+
+fn serialize_secs<S: serde::Serializer>(limit: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(limit.as_secs_f64())
+}
+
+struct State {
+    phase: Phase,
+    deadline: Option<(Instant, Duration)>,
+    phase_timeout: Option<(Instant, Duration)>,
+    open_sessions: usize,
+}
+
+/// Deadline of the command and timeout of its current phase
+pub struct Deadlines {
+    state: Mutex<State>,
+}
+
+impl Default for Deadlines {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deadlines {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                phase: Phase::Command,
+                deadline: None,
+                phase_timeout: None,
+                open_sessions: 0,
+            }),
+        }
+    }
+
+    /// The whole command must complete within `limit` from now
+    pub fn set_deadline(&self, limit: Duration) {
+        self.state.lock().unwrap().deadline = Some((Instant::now() + limit, limit));
+    }
+
+    /// Enter `phase`, which lasts until the next call. `timeout` limits the phase.
+    pub fn set_phase(&self, phase: Phase, timeout: Option<Duration>) {
+        let mut state = self.state.lock().unwrap();
+        state.phase = phase;
+        state.phase_timeout = timeout.map(|timeout| (Instant::now() + timeout, timeout));
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.state.lock().unwrap().phase
+    }
+
+    fn check(&self, now: Instant, grace: Duration) -> Option<TimeoutError> {
+        let state = self.state.lock().unwrap();
+        let grace = if state.open_sessions > 0 {
+            grace
+        } else {
+            Duration::ZERO
+        };
+        let expired = |limit: Option<(Instant, Duration)>| {
+            limit
+                .filter(|(at, _)| now >= *at + grace)
+                .map(|(_, limit)| limit)
+        };
+        let (kind, limit) = match (expired(state.deadline), expired(state.phase_timeout)) {
+            (Some(limit), _) => (TimeoutKind::Deadline, limit),
+            (None, Some(limit)) => (TimeoutKind::Timeout, limit),
+            (None, None) => return None,
+        };
+        Some(TimeoutError {
+            phase: state.phase,
+            kind,
+            limit,
+        })
+    }
+
+    async fn expired_after(&self, grace: Duration) -> TimeoutError {
+        loop {
+            if let Some(error) = self.check(Instant::now(), grace) {
+                return error;
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    }
+
+    /// Resolves once the deadline or the phase timeout has passed. Used by a session
+    /// to shut down cleanly before the command is dropped.
+    pub async fn expired(&self) -> TimeoutError {
+        self.expired_after(Duration::ZERO).await
+    }
+
+    /// Mark a session as open while the guard lives. An expiry then gives the
+    /// session time to handle it before `run` drops the command.
+    pub fn session(&self) -> SessionGuard<'_> {
+        self.state.lock().unwrap().open_sessions += 1;
+        SessionGuard { deadlines: self }
+    }
+
+    /// Run the whole command, failing with `CliError::Timeout` once a limit passes
+    pub async fn run<T, E, Fut>(&self, command: Fut) -> Result<T, E>
+    where
+        Fut: Future<Output = Result<T, E>>,
+        E: From<CliError>,
+    {
+        tokio::select! {
+            biased;
+            result = command => result,
+            error = self.expired_after(SESSION_GRACE) => Err(CliError::Timeout(error).into()),
+        }
+    }
+}
+
+pub struct SessionGuard<'a> {
+    deadlines: &'a Deadlines,
+}
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        self.deadlines.state.lock().unwrap().open_sessions -= 1;
+    }
+}
+
+pub fn global_deadlines() -> &'static Deadlines {
+    static DEADLINES: OnceLock<Deadlines> = OnceLock::new();
+    DEADLINES.get_or_init(Deadlines::new)
+}
+
+/// The timeout behind `error`, if the command timed out
+pub fn timeout_of(error: &anyhow::Error) -> Option<&TimeoutError> {
+    match error.downcast_ref::<CliError>() {
+        Some(CliError::Timeout(timeout)) => Some(timeout),
+        _ => None,
+    }
+}
+
+/// Parse `90`, `90s`, `500ms`, `5m` or `1h`
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("Invalid duration {:?}, e.g. 90s, 500ms, 5m or 1h", s))?;
+    match unit {
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "ms" => Ok(Duration::from_millis(value)),
+        "m" => Ok(Duration::from_secs(value * 60)),
+        "h" => Ok(Duration::from_secs(value * 3600)),
+        _ => Err(format!("Invalid unit {:?} of duration {:?}", unit, s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use tokio::{io::AsyncWriteExt as _, net::TcpListener};
+
+    use super::*;
+
+    // Accepts connections, but only answers them once `stalled` is cleared
+    async fn start_stalling_server(stalled: Arc<AtomicBool>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let stalled = stalled.clone();
+                tokio::spawn(async move {
+                    while stalled.load(Ordering::SeqCst) {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                    let _ = stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                        )
+                        .await;
+                });
+            }
+        });
+        url
+    }
+
+    async fn fetch(url: &str) -> Result<String, anyhow::Error> {
+        Ok(reqwest::get(url).await?.text().await?)
+    }
+
+    #[tokio::test]
+    async fn test_stalled_server() {
+        let stalled = Arc::new(AtomicBool::new(true));
+        let url = start_stalling_server(stalled.clone()).await;
+
+        let deadlines = Deadlines::new();
+        deadlines.set_deadline(Duration::from_millis(200));
+        deadlines.set_phase(Phase::Sync, None);
+        let error = deadlines.run(fetch(&url)).await.unwrap_err();
+        let timeout = timeout_of(&error).unwrap();
+        assert_eq!(timeout.phase, Phase::Sync);
+        assert_eq!(timeout.kind, TimeoutKind::Deadline);
+        let json: serde_json::Value = serde_json::from_str(&timeout.to_json()).unwrap();
+        assert_eq!(json["error"], "timeout");
+        assert_eq!(json["messageId"], "error.timeout");
+        assert_eq!(json["phase"], "sync");
+        assert_eq!(json["kind"], "deadline");
+        assert_eq!(json["limitSecs"], 0.2);
+
+        // the phase timeout fires first, and names its phase
+        let deadlines = Deadlines::new();
+        deadlines.set_deadline(Duration::from_secs(60));
+        deadlines.set_phase(Phase::ProposalWait, Some(Duration::from_millis(200)));
+        let error = deadlines.run(fetch(&url)).await.unwrap_err();
+        let timeout = timeout_of(&error).unwrap();
+        assert_eq!(timeout.phase, Phase::ProposalWait);
+        assert_eq!(timeout.kind, TimeoutKind::Timeout);
+
+        // a re-run completes once the server answers
+        stalled.store(false, Ordering::SeqCst);
+        let deadlines = Deadlines::new();
+        deadlines.set_deadline(Duration::from_secs(10));
+        deadlines.set_phase(Phase::Sync, None);
+        assert_eq!(deadlines.run(fetch(&url)).await.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_open_session_handles_expiry_first() {
+        let deadlines = Deadlines::new();
+        deadlines.set_deadline(Duration::from_millis(100));
+        let _session = deadlines.session();
+        let command = async {
+            // what run_in_session does: notice the expiry and shut down in time
+            let error = deadlines.expired().await;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Err::<(), _>(anyhow::Error::from(CliError::Timeout(error)))
+        };
+        let started = Instant::now();
+        let error = deadlines.run(command).await.unwrap_err();
+        assert!(timeout_of(&error).is_some());
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(started.elapsed() < SESSION_GRACE);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+}
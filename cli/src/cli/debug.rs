@@ -0,0 +1,95 @@
+use colored::Colorize as _;
+use intmax2_zkp::common::signature::key_set::KeySet;
+
+use super::{
+    client::get_client,
+    error::CliError,
+    i18n::{tr, tr_with},
+};
+
+pub async fn debug_nonce(key: KeySet) -> Result<(), CliError> {
+    let client = get_client()?;
+    let diagnosis = client.diagnose_nonce(key).await?;
+    println!(
+        "{}",
+        tr_with(
+            "msg.private_state_nonce",
+            &[("nonce", &diagnosis.private_state_nonce)]
+        )
+    );
+    println!(
+        "{}",
+        tr_with("msg.settled_nonce", &[("nonce", &diagnosis.settled_nonce)])
+    );
+    println!(
+        "{}",
+        tr_with(
+            "msg.pending_txs",
+            &[("count", &diagnosis.pending_tx_uuids.len())]
+        )
+    );
+    for uuid in diagnosis.pending_tx_uuids.iter() {
+        println!("\t {}", uuid);
+    }
+    if diagnosis.settled_nonce != diagnosis.private_state_nonce {
+        println!("{}", tr("msg.settled_txs_not_incorporated"));
+    }
+    Ok(())
+}
+
+/// Print the numbers support needs to triage a stuck account
+pub async fn print_diagnostics(key: KeySet) -> Result<(), CliError> {
+    let client = get_client()?;
+    let diagnostics = client.get_diagnostics(key).await?;
+    println!("{}", tr("msg.diagnostics"));
+    println!(
+        "{}",
+        tr_with("msg.diagnostics_nonce", &[("nonce", &diagnostics.nonce)])
+    );
+    println!(
+        "{}",
+        tr_with(
+            "msg.diagnostics_user_data_block",
+            &[("block", &diagnostics.block_number)]
+        )
+    );
+    match diagnostics.last_proof_block_number {
+        Some(block_number) => println!(
+            "{}",
+            tr_with(
+                "msg.diagnostics_balance_proof_block",
+                &[("block", &block_number)]
+            )
+        ),
+        None => println!("{}", tr("msg.diagnostics_no_balance_proof")),
+    }
+    println!(
+        "{}",
+        tr_with(
+            "msg.diagnostics_private_commitment",
+            &[("commitment", &diagnostics.private_commitment)]
+        )
+    );
+    println!(
+        "\t Processed: {} deposits, {} transfers, {} txs, {} withdrawals",
+        diagnostics.processed.deposits,
+        diagnostics.processed.transfers,
+        diagnostics.processed.txs,
+        diagnostics.processed.withdrawals
+    );
+    match diagnostics.account_id {
+        Some(account_id) => println!(
+            "{}",
+            tr_with("msg.diagnostics_account_id", &[("account_id", &account_id)])
+        ),
+        None => println!("{}", tr("msg.diagnostics_not_registered")),
+    }
+    println!(
+        "\t Validity prover block: {}",
+        diagnostics.validity_prover_block_number
+    );
+    for mismatch in diagnostics.mismatches.iter() {
+        println!("{}", format!("\t Mismatch: {}", mismatch).yellow());
+    }
+    Ok(())
+}
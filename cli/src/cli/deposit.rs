@@ -1,13 +1,20 @@
 use ethers::types::{Address, H256, U256};
+use intmax2_client_sdk::{
+    client::external_deposit::DepositSource,
+    external_api::contract::{
+        erc20_contract::ERC20Contract, interface::BlockchainError,
+        liquidity_contract::LiquidityContract, utils::get_address,
+    },
+    utils::id::format_plain_id,
+};
 use intmax2_interfaces::data::deposit_data::TokenType;
-use intmax2_zkp::common::signature::key_set::KeySet;
-
-use crate::Env;
+use intmax2_zkp::common::{salt::Salt, signature::key_set::KeySet};
 
 use super::{
     client::get_client,
     error::CliError,
-    utils::{convert_address, convert_u256, is_dev},
+    i18n::{tr, tr_with},
+    utils::{convert_address, convert_u256, is_dev, load_env},
 };
 
 pub async fn deposit(
@@ -19,6 +26,12 @@ pub async fn deposit(
     token_id: U256,
 ) -> Result<(), CliError> {
     let client = get_client()?;
+    let liquidity_contract = client.liquidity_contract.clone();
+    // checked before the deposit is backed up, so that nothing is left to sync
+    if token_type == TokenType::ERC20 {
+        approve_erc20(&liquidity_contract, eth_private_key, token_address, amount).await?;
+    }
+
     let amount = convert_u256(amount);
     let token_address = convert_address(token_address);
     let token_id = convert_u256(token_id);
@@ -26,8 +39,6 @@ pub async fn deposit(
         .prepare_deposit(key.pubkey, amount, token_type, token_address, token_id)
         .await?;
 
-    let liquidity_contract = client.liquidity_contract.clone();
-
     match token_type {
         TokenType::NATIVE => {
             liquidity_contract
@@ -39,7 +50,7 @@ pub async fn deposit(
                 .await?;
         }
         TokenType::ERC20 => {
-            liquidity_contract
+            let tx_hash = liquidity_contract
                 .deposit_erc20(
                     eth_private_key,
                     deposit_data.pubkey_salt_hash,
@@ -47,6 +58,19 @@ pub async fn deposit(
                     deposit_data.token_address,
                 )
                 .await?;
+            println!(
+                "{}",
+                tr_with("msg.deposit_tx", &[("tx_hash", &format!("{:?}", tx_hash))])
+            );
+            // registered by the first deposit of the token
+            let token_index = liquidity_contract
+                .get_token_index(token_type, token_address, token_id)
+                .await?
+                .ok_or(BlockchainError::TokenNotFound)?;
+            println!(
+                "{}",
+                tr_with("msg.token_index", &[("token_index", &token_index)])
+            );
         }
         TokenType::ERC721 => {
             liquidity_contract
@@ -92,8 +116,90 @@ pub async fn deposit(
     Ok(())
 }
 
+/// Fail if the ERC20 balance of the depositor is less than `amount`, and allow the
+/// liquidity contract to transfer `amount` if it is not allowed to yet
+async fn approve_erc20(
+    liquidity_contract: &LiquidityContract,
+    eth_private_key: H256,
+    token_address: Address,
+    amount: U256,
+) -> Result<(), CliError> {
+    let depositor = get_address(liquidity_contract.chain_id, eth_private_key);
+    let token = ERC20Contract::new(
+        liquidity_contract.rpc_url.clone(),
+        liquidity_contract.chain_id,
+        token_address,
+    )
+    .with_gas_config(liquidity_contract.gas_config);
+    let balance = token.balance_of(depositor).await?;
+    if balance < amount {
+        return Err(BlockchainError::InsufficientFunds(format!(
+            "ERC20 balance of {:?} is {} of token {:?}, less than the deposit of {}",
+            depositor, balance, token_address, amount
+        ))
+        .into());
+    }
+    let allowance = token
+        .allowance(depositor, liquidity_contract.address)
+        .await?;
+    if allowance < amount {
+        let tx_hash = token
+            .approve(eth_private_key, liquidity_contract.address, amount)
+            .await?;
+        println!(
+            "{}",
+            tr_with("msg.approve_tx", &[("tx_hash", &format!("{:?}", tx_hash))])
+        );
+    }
+    Ok(())
+}
+
+/// Print a pubkey salt hash for someone else to deposit to, and the salt to claim
+/// the deposit with `deposit_claim_external`
+pub fn deposit_request(key: KeySet) -> Result<(), CliError> {
+    let client = get_client()?;
+    let request = client.generate_deposit_request(key);
+    // in plain hex, as the depositor gives it to the contract
+    println!(
+        "{}",
+        tr_with(
+            "msg.deposit_to",
+            &[(
+                "pubkey_salt_hash",
+                &format_plain_id(request.pubkey_salt_hash)
+            )]
+        )
+    );
+    println!("{}", tr_with("msg.salt", &[("salt", &request.salt)]));
+    println!("{}", tr("msg.share_only_hash"));
+    Ok(())
+}
+
+/// Import the deposit given by `source`, made by someone else to the hash of
+/// `deposit_request` with `salt`
+pub async fn deposit_claim_external(
+    key: KeySet,
+    salt: Salt,
+    source: DepositSource,
+) -> Result<(), CliError> {
+    let client = get_client()?;
+    let deposit_data = client.claim_external_deposit(key, salt, source).await?;
+    println!(
+        "{}",
+        tr_with(
+            "msg.deposit_imported",
+            &[
+                ("amount", &deposit_data.amount),
+                ("token_index", &deposit_data.token_index.unwrap())
+            ]
+        )
+    );
+    println!("{}", tr("msg.included_by_next_sync"));
+    Ok(())
+}
+
 async fn post_empty_block() -> Result<(), CliError> {
-    let env = envy::from_env::<Env>()?;
+    let env = load_env()?;
     let block_builder_base_url = env.block_builder_base_url.ok_or(CliError::UnexpectedError(
         "BLOCK_BUILDER_BASE_URL".to_string(),
     ))?;
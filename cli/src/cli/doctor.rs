@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use colored::Colorize as _;
+use ethers::providers::{Http, Middleware as _, Provider};
+
+use super::{error::CliError, utils::load_env};
+
+const CHECK_TIMEOUT_SECS: u64 = 10;
+
+async fn check_http(url: &str) -> Result<(), String> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(CHECK_TIMEOUT_SECS))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("status {}", response.status()));
+    }
+    Ok(())
+}
+
+async fn check_rpc(rpc_url: &str, chain_id: u64) -> Result<(), String> {
+    let provider = Provider::<Http>::try_from(rpc_url).map_err(|e| e.to_string())?;
+    let actual = tokio::time::timeout(
+        Duration::from_secs(CHECK_TIMEOUT_SECS),
+        provider.get_chainid(),
+    )
+    .await
+    .map_err(|_| "timed out".to_string())?
+    .map_err(|e| e.to_string())?;
+    if actual.as_u64() != chain_id {
+        return Err(format!("chain id is {}, expected {}", actual, chain_id));
+    }
+    Ok(())
+}
+
+/// Check that the servers and RPC nodes of the environment respond, and print
+/// one line per check. Returns whether all passed.
+pub async fn doctor() -> Result<bool, CliError> {
+    let env = load_env()?;
+    let trim = |url: &str| url.trim_end_matches('/').to_string();
+    let mut http_checks = vec![
+        (
+            "store vault server",
+            format!("{}/health-check", trim(&env.store_vault_server_base_url)),
+        ),
+        (
+            "validity prover",
+            format!("{}/health-check", trim(&env.validity_prover_base_url)),
+        ),
+        (
+            "balance prover",
+            format!("{}/health-check", trim(&env.balance_prover_base_url)),
+        ),
+        (
+            "withdrawal server",
+            format!("{}/health-check", trim(&env.withdrawal_server_base_url)),
+        ),
+    ];
+    if let Some(block_builder_base_url) = &env.block_builder_base_url {
+        http_checks.push((
+            "block builder",
+            format!("{}/health-check", trim(block_builder_base_url)),
+        ));
+    }
+
+    let mut all_passed = true;
+    let mut report = |name: &str, result: Result<(), String>| match result {
+        Ok(()) => println!("{} {}", "ok  ".green(), name),
+        Err(e) => {
+            all_passed = false;
+            println!("{} {}: {}", "FAIL".red(), name, e);
+        }
+    };
+    for (name, url) in http_checks {
+        report(name, check_http(&url).await);
+    }
+    report("L1 RPC", check_rpc(&env.l1_rpc_url, env.l1_chain_id).await);
+    report("L2 RPC", check_rpc(&env.l2_rpc_url, env.l2_chain_id).await);
+    Ok(all_passed)
+}
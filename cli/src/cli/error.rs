@@ -1,9 +1,15 @@
 use envy::Error as EnvyError;
 use intmax2_client_sdk::{
-    client::error::ClientError, external_api::contract::interface::BlockchainError,
+    client::error::ClientError,
+    external_api::{contract::interface::BlockchainError, utils::url::InvalidUrl},
 };
 use intmax2_interfaces::api::error::ServerError;
 
+use super::{
+    deadline::TimeoutError,
+    i18n::{lang, tr_in, Lang},
+};
+
 #[derive(Debug, thiserror::Error)]
 pub enum CliError {
     #[error("Env error:{0}")]
@@ -18,12 +24,135 @@ pub enum CliError {
     #[error("Server error: {0}")]
     ServerError(#[from] ServerError),
 
+    #[error("Config error: {0}")]
+    InvalidUrl(#[from] InvalidUrl),
+
     #[error("Failed to request tx")]
     FailedToRequestTx,
 
     #[error("Failed to get proposal")]
     FailedToGetProposal,
 
+    #[error("Key error: {0}")]
+    KeyError(String),
+
+    #[error("Lock error: {0}")]
+    LockError(String),
+
+    #[error("Profile error: {0}")]
+    ProfileError(String),
+
+    #[error("Template error: {0}")]
+    TemplateError(String),
+
+    #[error("Receipt error: {0}")]
+    ReceiptError(String),
+
+    #[error("Webhook error: {0}")]
+    WebhookError(String),
+
+    #[error("Batch error: {0}")]
+    BatchError(String),
+
+    #[error("{0}")]
+    Timeout(TimeoutError),
+
+    #[error("Rebuild session error: {0}")]
+    RebuildSessionError(String),
+
+    #[error("Tx expired: {0}")]
+    TxExpired(String),
+
+    #[error("Tx status error: {0}")]
+    TxStatusError(String),
+
+    #[error("Address book error: {0}")]
+    AddressBookError(String),
+
+    #[error("Support bundle error: {0}")]
+    SupportBundleError(String),
+
+    #[error("Block builder error: {0}")]
+    BuilderError(String),
+
+    #[error("Offsite backup error: {0}")]
+    OffsiteBackupError(String),
+
+    #[error("Config file error: {0}")]
+    ConfigFileError(String),
+
+    #[error("Withdrawal error: {0}")]
+    WithdrawalError(String),
+
     #[error("Unexpected error: {0}")]
     UnexpectedError(String),
 }
+
+impl CliError {
+    /// Stable ID of the message of the error in the catalogs of `i18n`
+    pub fn message_id(&self) -> &'static str {
+        match self {
+            CliError::EnvError(_) => "error.env",
+            CliError::ClientError(_) => "error.client",
+            CliError::BlockchainError(_) => "error.blockchain",
+            CliError::ServerError(_) => "error.server",
+            CliError::InvalidUrl(_) => "error.config",
+            CliError::FailedToRequestTx => "error.failed_to_request_tx",
+            CliError::FailedToGetProposal => "error.failed_to_get_proposal",
+            CliError::KeyError(_) => "error.key",
+            CliError::LockError(_) => "error.lock",
+            CliError::ProfileError(_) => "error.profile",
+            CliError::TemplateError(_) => "error.template",
+            CliError::ReceiptError(_) => "error.receipt",
+            CliError::WebhookError(_) => "error.webhook",
+            CliError::BatchError(_) => "error.batch",
+            CliError::Timeout(_) => "error.timeout",
+            CliError::RebuildSessionError(_) => "error.rebuild_session",
+            CliError::TxExpired(_) => "error.tx_expired",
+            CliError::TxStatusError(_) => "error.tx_status",
+            CliError::AddressBookError(_) => "error.address_book",
+            CliError::SupportBundleError(_) => "error.support_bundle",
+            CliError::BuilderError(_) => "error.builder",
+            CliError::OffsiteBackupError(_) => "error.offsite_backup",
+            CliError::ConfigFileError(_) => "error.config_file",
+            CliError::WithdrawalError(_) => "error.withdrawal",
+            CliError::UnexpectedError(_) => "error.unexpected",
+        }
+    }
+
+    /// The message in `lang`. The details of the underlying errors stay in English.
+    pub fn localized_in(&self, lang: Lang) -> String {
+        let detail = match self {
+            CliError::EnvError(e) => e.to_string(),
+            CliError::ClientError(e) => e.to_string(),
+            CliError::BlockchainError(e) => e.to_string(),
+            CliError::ServerError(e) => e.to_string(),
+            CliError::InvalidUrl(e) => e.to_string(),
+            CliError::FailedToRequestTx | CliError::FailedToGetProposal => String::new(),
+            CliError::Timeout(timeout) => return timeout.localized_in(lang),
+            CliError::KeyError(detail)
+            | CliError::LockError(detail)
+            | CliError::ProfileError(detail)
+            | CliError::TemplateError(detail)
+            | CliError::ReceiptError(detail)
+            | CliError::WebhookError(detail)
+            | CliError::BatchError(detail)
+            | CliError::RebuildSessionError(detail)
+            | CliError::TxExpired(detail)
+            | CliError::TxStatusError(detail)
+            | CliError::AddressBookError(detail)
+            | CliError::SupportBundleError(detail)
+            | CliError::BuilderError(detail)
+            | CliError::OffsiteBackupError(detail)
+            | CliError::ConfigFileError(detail)
+            | CliError::WithdrawalError(detail)
+            | CliError::UnexpectedError(detail) => detail.clone(),
+        };
+        tr_in(lang, self.message_id(), &[("detail", &detail)])
+    }
+
+    /// The message in the language of the command
+    pub fn localized(&self) -> String {
+        self.localized_in(lang())
+    }
+}
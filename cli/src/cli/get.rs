@@ -1,46 +1,955 @@
-use intmax2_zkp::common::signature::key_set::KeySet;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use crate::cli::{client::get_client, sync::sync};
+use chrono::{DateTime, Utc};
+use intmax2_client_sdk::{
+    client::{
+        balance_history::{balance_at_block, balance_series, SeriesInterval},
+        fee_quote::{FeeAmount, FeeKind, FeeSource},
+        history::{GenericTransfer, HistoryEntry},
+        history_index::HistoryIndex,
+        withdrawal_onchain::OnchainConfirmation,
+    },
+    external_api::utils::clock::global_clock,
+};
+use intmax2_interfaces::{
+    api::withdrawal_server::interface::{
+        WithdrawalInfo, WithdrawalServerClientInterface, WithdrawalStatus,
+    },
+    data::deposit_data::TokenType,
+};
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{bytes32::Bytes32, u256::U256},
+};
+use tokio::time::Instant;
+
+use crate::cli::{
+    address_book::load_address_book,
+    client::get_client,
+    debug::print_diagnostics,
+    i18n::{tr, tr_with},
+    output::{
+        balance_output, history_labels, history_output, is_json_output, print_empty_on_error,
+        print_json, print_notice, withdrawal_output, BalanceOutput, HistoryEntryOutput,
+        WithdrawalOutput,
+    },
+    send::block_builder_url,
+    sync::sync,
+    utils::{display_id, load_env, local_dir},
+};
 
 use super::error::CliError;
 
-pub async fn balance(key: KeySet) -> Result<(), CliError> {
-    let client = get_client()?;
-    if !sync(key.clone()).await? {
+/// With `debug`, the diagnostics of the account are printed after the balances.
+/// With `--json`, an account that could not be synced has no balances.
+pub async fn balance(key: KeySet, debug: bool) -> Result<(), CliError> {
+    let empty = Vec::<BalanceOutput>::new();
+    let client = print_empty_on_error(get_client(), &empty)?;
+    if !print_empty_on_error(sync(key.clone()).await, &empty)? {
+        if is_json_output() {
+            print_json(&empty);
+        }
+        return Ok(());
+    }
+    let view = print_empty_on_error(client.get_balance_view(key).await, &empty)?;
+    if is_json_output() {
+        print_json(&balance_output(&view));
         return Ok(());
     }
-    let user_data = client.get_user_data(key).await?;
-    let balances = user_data.balances();
 
-    println!("Balances:");
-    for (i, leaf) in balances.iter() {
-        println!("\t Token {}: {}", i, leaf.amount);
+    println!("{}", tr("msg.balances"));
+    for balance in view.fungible.iter() {
+        match balance.token_type {
+            TokenType::NATIVE => println!(
+                "{}",
+                tr_with(
+                    "msg.token_amount",
+                    &[
+                        ("token_index", &balance.token_index),
+                        ("amount", &balance.amount)
+                    ]
+                )
+            ),
+            _ => println!(
+                "\t Token {} ({:?} {}): {}",
+                balance.token_index, balance.token_type, balance.token_address, balance.amount
+            ),
+        }
+    }
+    if !view.nfts.is_empty() {
+        println!("{}", tr("msg.nfts"));
+    }
+    for holding in view.nfts.iter() {
+        println!("\t {:?} {}:", holding.token_type, holding.token_address);
+        for token in holding.tokens.iter() {
+            match holding.token_type {
+                TokenType::ERC721 => {
+                    println!("\t\t id {} (token {})", token.token_id, token.token_index)
+                }
+                _ => println!(
+                    "\t\t id {} (token {}): {}",
+                    token.token_id, token.token_index, token.amount
+                ),
+            }
+        }
+    }
+    if debug {
+        print_diagnostics(key).await?;
     }
     Ok(())
 }
 
 pub async fn withdrawal_status(key: KeySet) -> Result<(), CliError> {
-    let client = get_client()?;
-    let withdrawal_info = client.get_withdrawal_info(key).await?;
-    for (i, withdrawal_info) in withdrawal_info.iter().enumerate() {
+    let empty = Vec::<WithdrawalOutput>::new();
+    let client = print_empty_on_error(get_client(), &empty)?;
+    let withdrawal_info =
+        print_empty_on_error(client.get_withdrawal_info_with_tags(key).await, &empty)?;
+    let infos = withdrawal_info
+        .iter()
+        .map(|(info, _)| info.clone())
+        .collect::<Vec<_>>();
+    let confirmations =
+        print_empty_on_error(client.verify_withdrawals_onchain(&infos).await, &empty)?;
+    if is_json_output() {
+        let outputs = withdrawal_info
+            .iter()
+            .zip(confirmations.iter())
+            .map(|((info, tag), confirmation)| withdrawal_output(info, tag.as_ref(), confirmation))
+            .collect::<Vec<_>>();
+        print_json(&outputs);
+        return Ok(());
+    }
+    for (i, ((withdrawal_info, tag), confirmation)) in
+        withdrawal_info.iter().zip(confirmations).enumerate()
+    {
         let withdrawal = withdrawal_info.contract_withdrawal.clone();
         println!(
-            "#{}: recipient: {}, token_index: {}, amount: {}, status: {}",
+            "#{}: recipient: {}, token_index: {}, amount: {}, nullifier: {}, status: {}{}{}",
             i,
             withdrawal.recipient,
             withdrawal.token_index,
             withdrawal.amount,
-            withdrawal_info.status
+            display_id(withdrawal.nullifier),
+            withdrawal_info.status,
+            format_confirmation(&confirmation),
+            tag.as_ref()
+                .map(|tag| format!(", tag: {}", tag))
+                .unwrap_or_default()
         );
     }
     Ok(())
 }
 
-pub async fn history(key: KeySet) -> Result<(), CliError> {
+/// Default seconds between two polls of `wait_for_withdrawal`
+pub const DEFAULT_WITHDRAWAL_POLL_INTERVAL: u64 = 30;
+
+/// The withdrawal whose nullifier (as printed by `withdrawal-status`) or
+/// withdrawal hash is `withdrawal_id`
+fn find_withdrawal(infos: &[WithdrawalInfo], withdrawal_id: Bytes32) -> Option<&WithdrawalInfo> {
+    infos.iter().find(|info| {
+        info.contract_withdrawal.nullifier == withdrawal_id
+            || info.contract_withdrawal.withdrawal_hash() == withdrawal_id
+    })
+}
+
+/// Whether the wait for a withdrawal in `status` is over: paid to the recipient,
+/// or with `claimable` also claimable with `claim-withdrawal`
+fn is_settled(status: &WithdrawalStatus, claimable: bool) -> bool {
+    match status {
+        WithdrawalStatus::Success => true,
+        WithdrawalStatus::NeedClaim => claimable,
+        _ => false,
+    }
+}
+
+/// Poll the withdrawals of `key` every `poll_interval` seconds until the one of
+/// `withdrawal_id` is settled (see `is_settled`), printing each change of its
+/// status. A withdrawal the aggregator does not list yet is polled for as well.
+/// Fails if the withdrawal failed, or with its last status after `timeout`.
+async fn poll_withdrawal<W: WithdrawalServerClientInterface>(
+    withdrawal_server: &W,
+    key: KeySet,
+    withdrawal_id: Bytes32,
+    timeout: Duration,
+    poll_interval: u64,
+    claimable: bool,
+) -> Result<WithdrawalInfo, CliError> {
+    let deadline = Instant::now() + timeout;
+    let mut last_status = None;
+    loop {
+        let withdrawal_info = withdrawal_server.get_withdrawal_info(key).await?;
+        if let Some(info) = find_withdrawal(&withdrawal_info, withdrawal_id) {
+            let status = info.status.to_string();
+            if last_status.as_ref() != Some(&status) {
+                print_notice(tr_with(
+                    "msg.withdrawal_status",
+                    &[("id", &display_id(withdrawal_id)), ("status", &status)],
+                ));
+            }
+            last_status = Some(status);
+            if matches!(info.status, WithdrawalStatus::Failed) {
+                return Err(CliError::WithdrawalError(format!(
+                    "withdrawal {} failed",
+                    display_id(withdrawal_id)
+                )));
+            }
+            if is_settled(&info.status, claimable) {
+                return Ok(info.clone());
+            }
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            let last_status =
+                last_status.unwrap_or("not listed by the withdrawal aggregator".to_string());
+            return Err(CliError::WithdrawalError(format!(
+                "withdrawal {} is still {} after {:?}",
+                display_id(withdrawal_id),
+                last_status,
+                timeout
+            )));
+        }
+        tokio::time::sleep(Duration::from_secs(poll_interval).min(deadline - now)).await;
+    }
+}
+
+/// Wait until the withdrawal of `withdrawal_id` is paid to its recipient, or with
+/// `claimable` until it can be claimed, and print it. See `poll_withdrawal`.
+pub async fn wait_for_withdrawal(
+    key: KeySet,
+    withdrawal_id: Bytes32,
+    timeout: Duration,
+    poll_interval: u64,
+    claimable: bool,
+) -> Result<(), CliError> {
     let client = get_client()?;
-    let history = client.fetch_history(key).await?;
+    let info = poll_withdrawal(
+        &client.withdrawal_server,
+        key,
+        withdrawal_id,
+        timeout,
+        poll_interval,
+        claimable,
+    )
+    .await?;
+    let confirmation = client
+        .verify_withdrawals_onchain(std::slice::from_ref(&info))
+        .await?
+        .remove(0);
+    let output = withdrawal_output(&info, None, &confirmation);
+    if is_json_output() {
+        print_json(&output);
+    } else {
+        println!(
+            "{}",
+            tr_with(
+                "msg.withdrawal_is",
+                &[
+                    ("id", &display_id(withdrawal_id)),
+                    ("status", &output.status),
+                    ("confirmation", &format_confirmation(&confirmation))
+                ]
+            )
+        );
+    }
+    Ok(())
+}
+
+fn format_confirmation(confirmation: &OnchainConfirmation) -> String {
+    match confirmation {
+        OnchainConfirmation::NotSubmitted => String::new(),
+        OnchainConfirmation::Pending => " (not on L1 yet)".to_string(),
+        OnchainConfirmation::Confirmed {
+            tx_hash,
+            block_number,
+        } => format!(" (L1 tx {:?} in block {})", tx_hash, block_number),
+        OnchainConfirmation::SuspectedAggregatorFault { reason } => {
+            format!(" (SUSPECTED AGGREGATOR FAULT: {})", reason)
+        }
+    }
+}
+
+pub async fn limits() -> Result<(), CliError> {
+    let client = get_client()?;
+    let limits = client.get_limits().await?;
+    println!(
+        "{}",
+        tr_with(
+            "msg.max_transfers_per_tx",
+            &[("max", &limits.max_transfers_per_tx)]
+        )
+    );
+    println!(
+        "{}",
+        tr_with(
+            "msg.max_withdrawals_per_request",
+            &[("max", &limits.max_withdrawals_per_request)]
+        )
+    );
+    println!("{}", tr("msg.min_withdrawal"));
+    if limits.min_withdrawal_per_token.is_empty() {
+        println!("{}", tr("msg.none"));
+    }
+    for (token_index, amount) in limits.min_withdrawal_per_token.iter() {
+        println!(
+            "{}",
+            tr_with(
+                "msg.token_amount",
+                &[("token_index", &token_index), ("amount", &amount)]
+            )
+        );
+    }
+    println!("{}", tr("msg.min_deposit"));
+    if limits.min_deposit_per_token.is_empty() {
+        println!("{}", tr("msg.none"));
+    }
+    for (token_index, amount) in limits.min_deposit_per_token.iter() {
+        println!(
+            "{}",
+            tr_with(
+                "msg.token_amount",
+                &[("token_index", &token_index), ("amount", &amount)]
+            )
+        );
+    }
+    Ok(())
+}
+
+/// What `fee estimate` quotes
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeKindArg {
+    /// Inclusion fee of the block builder
+    Transfer,
+    /// Inclusion fee and the fee of the withdrawal aggregator
+    Withdrawal,
+}
+
+impl From<FeeKindArg> for FeeKind {
+    fn from(kind: FeeKindArg) -> Self {
+        match kind {
+            FeeKindArg::Transfer => FeeKind::Transfer,
+            FeeKindArg::Withdrawal => FeeKind::Withdrawal,
+        }
+    }
+}
+
+fn print_fee_amounts(fees: &[FeeAmount]) {
+    if fees.is_empty() {
+        println!("{}", tr("msg.none"));
+    }
+    for fee in fees.iter() {
+        println!(
+            "{}",
+            tr_with(
+                "msg.token_amount",
+                &[("token_index", &fee.token_index), ("amount", &fee.amount)]
+            )
+        );
+    }
+}
+
+/// Print the fees a transfer or withdrawal of `amount` would pay, per token and
+/// in total. A server without a fee endpoint is reported instead of failing.
+pub async fn fee_estimate(
+    kind: FeeKindArg,
+    token_index: Option<u32>,
+    amount: u128,
+) -> Result<(), CliError> {
+    let env = load_env()?;
+    let client = get_client()?;
+    let block_builder_url = block_builder_url(&env).await?;
+    let estimate = client
+        .estimate_fees(
+            &block_builder_url,
+            kind.into(),
+            token_index,
+            U256::from(amount),
+        )
+        .await?;
+    if is_json_output() {
+        print_json(&estimate);
+        return Ok(());
+    }
+    for quote in estimate.quotes.iter() {
+        let id = match quote.source {
+            FeeSource::BlockBuilder => "msg.block_builder_fee",
+            FeeSource::WithdrawalAggregator => "msg.withdrawal_aggregator_fee",
+        };
+        println!("{}", tr(id));
+        match &quote.fees {
+            Some(fees) => print_fee_amounts(fees),
+            None => println!("{}", tr("msg.fee_not_exposed")),
+        }
+    }
+    println!("{}", tr("msg.total"));
+    print_fee_amounts(&estimate.totals());
+    if estimate.is_partial() {
+        println!("{}", tr("msg.total_leaves_out_fees"));
+    }
+    Ok(())
+}
+
+pub async fn expired_transfers(key: KeySet) -> Result<(), CliError> {
+    let client = get_client()?;
+    let expired = client.list_expired_outgoing_transfers(key).await?;
+    if expired.is_empty() {
+        println!("{}", tr("msg.no_expired_transfers"));
+        return Ok(());
+    }
+    println!("{}", tr("msg.expired_transfers"));
+    for transfer in expired.iter() {
+        println!(
+            "\t tx {}: recipient: {}, token_index: {}, amount: {}, deadline: block {}",
+            transfer.tx_uuid,
+            transfer.recipient,
+            transfer.token_index,
+            transfer.amount,
+            transfer.claim_deadline_block
+        );
+    }
+    Ok(())
+}
+
+/// Fees paid in txs whose block the block builder never posted. With `out`, the
+/// dispute documents are written there as JSON, to send to the builder operators.
+pub async fn fee_disputes(key: KeySet, out: Option<PathBuf>) -> Result<(), CliError> {
+    let client = get_client()?;
+    let disputes = client.list_fee_disputes(key).await?;
+    if let Some(out) = &out {
+        fs::write(out, serde_json::to_string_pretty(&disputes).unwrap())
+            .map_err(|e| CliError::UnexpectedError(format!("Failed to write {:?}: {}", out, e)))?;
+    }
+    if is_json_output() {
+        print_json(&disputes);
+        return Ok(());
+    }
+    if disputes.is_empty() {
+        println!("{}", tr("msg.no_fee_disputes"));
+        return Ok(());
+    }
+    println!("{}", tr("msg.fee_disputes"));
+    for dispute in disputes.iter() {
+        println!(
+            "\t tx {}: builder: {}, token_index: {}, amount: {}, expired at: {}",
+            dispute.tx_hash,
+            dispute.block_builder_url,
+            dispute.fee.token_index,
+            dispute.fee.amount,
+            dispute.non_inclusion.expired_at()
+        );
+    }
+    if let Some(out) = &out {
+        println!(
+            "{}",
+            tr_with(
+                "msg.disputes_written",
+                &[("count", &disputes.len()), ("path", &format!("{:?}", out))]
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Balances without syncing first, with the txs finalized since the last sync and
+/// the incoming transfers the next sync will receive
+pub async fn balance_optimistic(key: KeySet) -> Result<(), CliError> {
+    let client = get_client()?;
+    let balances = client.get_balances_optimistic(key).await?;
+    println!("{}", tr("msg.balances_optimistic"));
+    for balance in balances.balances.iter() {
+        if balance.amount == balance.settled {
+            println!(
+                "{}",
+                tr_with(
+                    "msg.token_amount",
+                    &[
+                        ("token_index", &balance.token_index),
+                        ("amount", &balance.amount)
+                    ]
+                )
+            );
+        } else {
+            println!(
+                "	 Token {}: {} (settled: {})",
+                balance.token_index, balance.amount, balance.settled
+            );
+        }
+    }
+    if !balances.deltas.is_empty() {
+        println!("{}", tr("msg.not_yet_synced"));
+    }
+    for delta in balances.deltas.iter() {
+        println!(
+            "	 {:?} of token {}: {}, tx {}, {:?}, confidence {:?}",
+            delta.kind,
+            delta.token_index,
+            delta.amount,
+            display_id(delta.tx_tree_root),
+            delta.status,
+            delta.confidence()
+        );
+    }
+    Ok(())
+}
+
+// The index goes through the data encryption of the store vault blobs, which does
+// not encrypt yet, so the file holds the history in plaintext
+pub(crate) fn history_index_path(key: KeySet) -> Result<PathBuf, CliError> {
+    Ok(local_dir()?
+        .join("history")
+        .join(format!("{}.idx", key.pubkey.to_hex())))
+}
+
+// An unreadable index is rebuilt from scratch, as it only caches the store vault
+pub(crate) fn load_history_index(path: &Path, key: KeySet) -> HistoryIndex {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(_) => return HistoryIndex::new(key.pubkey),
+    };
+    match HistoryIndex::decrypt(&bytes, key) {
+        Ok(index) => index,
+        Err(e) => {
+            log::warn!(
+                "Failed to read history index {:?}, rebuilding it: {}",
+                path,
+                e
+            );
+            HistoryIndex::new(key.pubkey)
+        }
+    }
+}
+
+pub(crate) fn save_history_index(path: &Path, index: &HistoryIndex) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, index.encrypt(index.pubkey)));
+    if let Err(e) = result {
+        log::warn!("Failed to save history index {:?}: {}", path, e);
+    }
+}
+
+async fn indexed_history(key: KeySet, rebuild_index: bool) -> Result<Vec<HistoryEntry>, CliError> {
+    let client = get_client()?;
+    let path = history_index_path(key)?;
+    let index = if rebuild_index {
+        HistoryIndex::new(key.pubkey)
+    } else {
+        load_history_index(&path, key)
+    };
+    let (history, index) = client.fetch_history_with_index(key, index).await?;
+    save_history_index(&path, &index);
+    Ok(history)
+}
+
+/// Status of a history entry, for `history --status`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryStatus {
+    /// Not included in a block yet
+    Pending,
+    /// Included in a block
+    Settled,
+    Rejected,
+    /// Incoming transfer not received before its claim deadline
+    Expired,
+}
+
+pub fn history_status(entry: &HistoryEntry) -> HistoryStatus {
+    let (is_rejected, is_expired, block_number) = match entry {
+        HistoryEntry::Deposit {
+            is_rejected,
+            block_number,
+            ..
+        }
+        | HistoryEntry::Send {
+            is_rejected,
+            block_number,
+            ..
+        } => (*is_rejected, false, block_number),
+        HistoryEntry::Receive {
+            is_rejected,
+            is_expired,
+            block_number,
+            ..
+        } => (*is_rejected, *is_expired, block_number),
+    };
+    if is_rejected {
+        HistoryStatus::Rejected
+    } else if is_expired {
+        HistoryStatus::Expired
+    } else if block_number.is_some() {
+        HistoryStatus::Settled
+    } else {
+        HistoryStatus::Pending
+    }
+}
+
+// token indices of the entry, one per transfer of a send; none for a deposit of a
+// token not registered yet
+fn history_token_indices(entry: &HistoryEntry) -> Vec<u32> {
+    match entry {
+        HistoryEntry::Deposit { token_index, .. } => token_index.iter().copied().collect(),
+        HistoryEntry::Receive { token_index, .. } => vec![*token_index],
+        HistoryEntry::Send { transfers, .. } => transfers
+            .iter()
+            .map(|transfer| match transfer {
+                GenericTransfer::Transfer { token_index, .. }
+                | GenericTransfer::Withdrawal { token_index, .. } => *token_index,
+            })
+            .collect(),
+    }
+}
+
+/// Filters of `history`. A send matches a token index if any of its transfers does.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HistoryFilter {
+    pub token_index: Option<u32>,
+    pub status: Option<HistoryStatus>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.token_index
+            .map_or(true, |index| history_token_indices(entry).contains(&index))
+            && self
+                .status
+                .map_or(true, |status| history_status(entry) == status)
+    }
+}
+
+pub async fn history(
+    key: KeySet,
+    rebuild_index: bool,
+    filter: HistoryFilter,
+) -> Result<(), CliError> {
+    let mut history = print_empty_on_error(
+        indexed_history(key, rebuild_index).await,
+        &Vec::<HistoryEntryOutput>::new(),
+    )?;
+    history.retain(|entry| filter.matches(entry));
+    // labels are a convenience, and an unreadable address book does not fail the
+    // command
+    let book = load_address_book().unwrap_or_default();
+    if is_json_output() {
+        let outputs = history
+            .iter()
+            .map(|entry| history_output(entry, &book))
+            .collect::<Vec<_>>();
+        print_json(&outputs);
+        return Ok(());
+    }
     for entry in history {
-        println!("{}", entry);
+        let labels = history_labels(&entry, &book);
+        if labels.is_empty() {
+            println!("{}", entry);
+        } else {
+            println!("{}, label: {}", entry, labels.join(", "));
+        }
+    }
+    Ok(())
+}
+
+fn print_excluded_entries(excluded_entries: usize) {
+    if excluded_entries > 0 {
+        println!(
+            "{}",
+            tr_with("msg.entries_left_out", &[("count", &excluded_entries)])
+        );
+    }
+}
+
+/// Balances as of `block_number`, replayed from the history
+pub async fn balance_at(key: KeySet, block_number: u32) -> Result<(), CliError> {
+    let history = indexed_history(key, false).await?;
+    let balance = balance_at_block(&history, block_number);
+    println!(
+        "{}",
+        tr_with("msg.balances_at_block", &[("block", &block_number)])
+    );
+    for (token_index, amount) in balance.balances.iter() {
+        println!(
+            "{}",
+            tr_with(
+                "msg.token_amount",
+                &[("token_index", &token_index), ("amount", &amount)]
+            )
+        );
+    }
+    print_excluded_entries(balance.excluded_entries);
+    Ok(())
+}
+
+fn format_date(timestamp: u64) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp as i64, 0)
+        .map(|date| date.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// Write the balances at the end of each period as CSV rows
+/// `period_start,period_end,token_index,amount`, to `out` or stdout
+pub async fn balance_series_csv(
+    key: KeySet,
+    interval: SeriesInterval,
+    out: Option<PathBuf>,
+) -> Result<(), CliError> {
+    let history = indexed_history(key, false).await?;
+    let series = balance_series(&history, interval, global_clock().server_now());
+    let mut csv = "period_start,period_end,token_index,amount\n".to_string();
+    for point in series.points.iter() {
+        for (token_index, amount) in point.balances.iter() {
+            csv += &format!(
+                "{},{},{},{}\n",
+                format_date(point.start),
+                format_date(point.end),
+                token_index,
+                amount
+            );
+        }
+    }
+    match out {
+        Some(out) => {
+            fs::write(&out, csv).map_err(|e| {
+                CliError::UnexpectedError(format!("Failed to write {:?}: {}", out, e))
+            })?;
+            println!(
+                "{}",
+                tr_with(
+                    "msg.periods_written",
+                    &[
+                        ("count", &series.points.len()),
+                        ("path", &format!("{:?}", out))
+                    ]
+                )
+            );
+        }
+        None => print!("{}", csv),
     }
+    print_excluded_entries(series.excluded_entries);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use intmax2_client_sdk::external_api::{
+        utils::mock_server::{start_mock_server, MockRequests},
+        withdrawal_server::WithdrawalServerClient,
+    };
+    use intmax2_interfaces::api::withdrawal_server::{
+        interface::ContractWithdrawal, types::GetWithdrawalInfoResponse,
+    };
+    use intmax2_zkp::ethereum_types::{
+        address::Address, u256::U256, u32limb_trait::U32LimbTrait as _,
+    };
+
+    use super::*;
+
+    fn withdrawal_info(nullifier: Bytes32, status: WithdrawalStatus) -> WithdrawalInfo {
+        WithdrawalInfo {
+            status,
+            contract_withdrawal: ContractWithdrawal {
+                recipient: Address::default(),
+                token_index: 0,
+                amount: U256::from(10u128),
+                nullifier,
+            },
+            attribution_tag_hash: None,
+            requested_at: None,
+        }
+    }
+
+    #[test]
+    fn test_find_withdrawal() {
+        let mut rng = rand::thread_rng();
+        let infos = vec![
+            withdrawal_info(Bytes32::rand(&mut rng), WithdrawalStatus::Requested),
+            withdrawal_info(Bytes32::rand(&mut rng), WithdrawalStatus::NeedClaim),
+        ];
+        let nullifier = infos[1].contract_withdrawal.nullifier;
+        let withdrawal_hash = infos[1].contract_withdrawal.withdrawal_hash();
+        for id in [nullifier, withdrawal_hash] {
+            let found = find_withdrawal(&infos, id).unwrap();
+            assert_eq!(found.contract_withdrawal.nullifier, nullifier);
+            assert!(!is_settled(&found.status, false));
+            assert!(is_settled(&found.status, true));
+        }
+        assert!(find_withdrawal(&infos, Bytes32::rand(&mut rng)).is_none());
+        assert!(!is_settled(&infos[0].status, true));
+        assert!(is_settled(&WithdrawalStatus::Success, false));
+        assert!(!is_settled(&WithdrawalStatus::Failed, true));
+    }
+
+    // a withdrawal server listing the withdrawal of `nullifier` with the next
+    // status of `statuses` at each poll, and the last one from then on
+    fn scripted_withdrawal_server(
+        nullifier: Bytes32,
+        statuses: Vec<Option<WithdrawalStatus>>,
+    ) -> (WithdrawalServerClient, MockRequests) {
+        let polls = AtomicUsize::new(0);
+        let (url, requests) = start_mock_server(move |path, _| {
+            assert_eq!(path, "/withdrawal-server/get-withdrawal-info");
+            let poll = polls.fetch_add(1, Ordering::SeqCst).min(statuses.len() - 1);
+            let withdrawal_info = statuses[poll]
+                .iter()
+                .map(|status| withdrawal_info(nullifier, status.clone()))
+                .collect();
+            serde_json::to_string(&GetWithdrawalInfoResponse { withdrawal_info }).unwrap()
+        });
+        (
+            WithdrawalServerClient::new(&url).with_timeout(Duration::from_secs(5)),
+            requests,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_withdrawal() {
+        let mut rng = rand::thread_rng();
+        let key = KeySet::rand(&mut rng);
+        let nullifier = Bytes32::rand(&mut rng);
+        let timeout = Duration::from_secs(60);
+        let statuses = vec![
+            None,
+            Some(WithdrawalStatus::Relayed),
+            Some(WithdrawalStatus::NeedClaim),
+            Some(WithdrawalStatus::Success),
+        ];
+
+        // claimable is not paid
+        let (server, requests) = scripted_withdrawal_server(nullifier, statuses.clone());
+        let info = poll_withdrawal(&server, key, nullifier, timeout, 1, false)
+            .await
+            .unwrap();
+        assert!(matches!(info.status, WithdrawalStatus::Success));
+        assert_eq!(requests.lock().unwrap().len(), 4);
+
+        // unless asked for
+        let (server, requests) = scripted_withdrawal_server(nullifier, statuses);
+        let info = poll_withdrawal(&server, key, nullifier, timeout, 1, true)
+            .await
+            .unwrap();
+        assert!(matches!(info.status, WithdrawalStatus::NeedClaim));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        // found by its withdrawal hash
+        let hash = withdrawal_info(nullifier, WithdrawalStatus::Success)
+            .contract_withdrawal
+            .withdrawal_hash();
+        let (server, _) =
+            scripted_withdrawal_server(nullifier, vec![Some(WithdrawalStatus::Success)]);
+        let info = poll_withdrawal(&server, key, hash, timeout, 1, false)
+            .await
+            .unwrap();
+        assert_eq!(info.contract_withdrawal.nullifier, nullifier);
+
+        let (server, _) = scripted_withdrawal_server(
+            nullifier,
+            vec![
+                Some(WithdrawalStatus::Relayed),
+                Some(WithdrawalStatus::Failed),
+            ],
+        );
+        let err = poll_withdrawal(&server, key, nullifier, timeout, 1, true)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CliError::WithdrawalError(e) if e.ends_with("failed")));
+
+        // the last status is reported after the timeout
+        let (server, requests) =
+            scripted_withdrawal_server(nullifier, vec![Some(WithdrawalStatus::NeedClaim)]);
+        let err = poll_withdrawal(&server, key, nullifier, Duration::from_secs(1), 1, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CliError::WithdrawalError(e) if e.contains("still need_claim")));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    fn receive(token_index: u32, is_expired: bool, block_number: Option<u32>) -> HistoryEntry {
+        HistoryEntry::Receive {
+            amount: U256::default(),
+            token_index,
+            from: U256::default(),
+            is_rejected: false,
+            is_expired,
+            timestamp: None,
+            block_number,
+        }
+    }
+
+    #[test]
+    fn test_history_filter() {
+        let send = HistoryEntry::Send {
+            transfers: vec![
+                GenericTransfer::Transfer {
+                    recipient: U256::default(),
+                    token_index: 0,
+                    amount: U256::default(),
+                },
+                GenericTransfer::Withdrawal {
+                    recipient: Address::default(),
+                    token_index: 2,
+                    amount: U256::default(),
+                },
+            ],
+            fee: None,
+            is_rejected: true,
+            claim_deadline_block: None,
+            timestamp: None,
+            block_number: Some(5),
+        };
+        let deposit = HistoryEntry::Deposit {
+            token_type: TokenType::ERC20,
+            token_address: Address::default(),
+            token_id: U256::default(),
+            token_index: None,
+            amount: U256::default(),
+            is_rejected: false,
+            timestamp: None,
+            block_number: None,
+        };
+        assert_eq!(history_status(&send), HistoryStatus::Rejected);
+        assert_eq!(history_status(&deposit), HistoryStatus::Pending);
+        assert_eq!(
+            history_status(&receive(2, false, Some(3))),
+            HistoryStatus::Settled
+        );
+        assert_eq!(
+            history_status(&receive(2, true, None)),
+            HistoryStatus::Expired
+        );
+
+        let entries = [
+            send,
+            deposit,
+            receive(2, false, Some(3)),
+            receive(1, false, Some(4)),
+        ];
+        let matching = |filter: HistoryFilter| {
+            entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| filter.matches(entry))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matching(HistoryFilter::default()), vec![0, 1, 2, 3]);
+        let token_2 = HistoryFilter {
+            token_index: Some(2),
+            status: None,
+        };
+        assert_eq!(matching(token_2), vec![0, 2]);
+        let settled_token_2 = HistoryFilter {
+            status: Some(HistoryStatus::Settled),
+            ..token_2
+        };
+        assert_eq!(matching(settled_token_2), vec![2]);
+        let pending = HistoryFilter {
+            token_index: None,
+            status: Some(HistoryStatus::Pending),
+        };
+        assert_eq!(matching(pending), vec![1]);
+    }
+}
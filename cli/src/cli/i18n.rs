@@ -0,0 +1,238 @@
+//! Catalog of the user-facing messages: errors, prompts and summaries. Each has a
+//! stable message ID, e.g. `error.key`, so that a wrapper of the CLI can show its
+//! own text from the `messageId` of the JSON errors. Log lines stay in English.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        OnceLock,
+    },
+};
+
+use clap::ValueEnum;
+
+const EN: &str = include_str!("../../locales/en.toml");
+const JA: &str = include_str!("../../locales/ja.toml");
+
+/// Language of the messages, given by `--lang` or the `LANG` environment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Lang {
+    #[default]
+    En,
+    Ja,
+}
+
+impl Lang {
+    /// The language of a locale such as `ja_JP.UTF-8`, if it has a catalog
+    pub fn from_locale(locale: &str) -> Option<Self> {
+        let language = locale.split(['_', '.', '-', '@']).next()?;
+        match language.to_lowercase().as_str() {
+            "en" => Some(Lang::En),
+            "ja" => Some(Lang::Ja),
+            _ => None,
+        }
+    }
+
+    /// The first of `LC_ALL`, `LC_MESSAGES` and `LANG` that is set, as gettext
+    /// does. English if none has a catalog.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|locale| !locale.is_empty())
+            .and_then(|locale| Lang::from_locale(&locale))
+            .unwrap_or_default()
+    }
+
+    fn source(self) -> &'static str {
+        match self {
+            Lang::En => EN,
+            Lang::Ja => JA,
+        }
+    }
+}
+
+static LANG: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        1 => Lang::Ja,
+        _ => Lang::En,
+    }
+}
+
+type Catalog = HashMap<String, String>;
+
+// `[error] key = "..."` is `error.key`
+fn flatten(prefix: &str, table: &toml::Table, catalog: &mut Catalog) {
+    for (name, value) in table {
+        let id = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match value {
+            toml::Value::Table(table) => flatten(&id, table, catalog),
+            toml::Value::String(message) => {
+                catalog.insert(id, message.clone());
+            }
+            _ => panic!("message {} is not a string", id),
+        }
+    }
+}
+
+fn parse_catalog(source: &str) -> Catalog {
+    let table = source
+        .parse::<toml::Table>()
+        .expect("embedded message catalog is invalid");
+    let mut catalog = Catalog::new();
+    flatten("", &table, &mut catalog);
+    catalog
+}
+
+fn catalog(lang: Lang) -> &'static Catalog {
+    static CATALOGS: OnceLock<HashMap<Lang, Catalog>> = OnceLock::new();
+    &CATALOGS.get_or_init(|| {
+        [Lang::En, Lang::Ja]
+            .into_iter()
+            .map(|lang| (lang, parse_catalog(lang.source())))
+            .collect()
+    })[&lang]
+}
+
+/// Message `id` in `lang` with its `{name}` placeholders replaced by `args`. A
+/// message missing in `lang` is taken from the English catalog, and an unknown
+/// ID is returned as is.
+pub fn tr_in(lang: Lang, id: &str, args: &[(&str, &dyn Display)]) -> String {
+    let template = catalog(lang)
+        .get(id)
+        .or_else(|| catalog(Lang::En).get(id))
+        .map(String::as_str)
+        .unwrap_or(id);
+    args.iter()
+        .fold(template.to_string(), |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), &value.to_string())
+        })
+}
+
+/// Message `id` in the language of the command
+pub fn tr(id: &str) -> String {
+    tr_in(lang(), id, &[])
+}
+
+/// Message `id` in the language of the command, with `args` filled in
+pub fn tr_with(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    tr_in(lang(), id, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::cli::{
+        deadline::{Phase, TimeoutError, TimeoutKind},
+        error::CliError,
+    };
+
+    const NAMESPACES: [&str; 3] = ["error.", "prompt.", "msg."];
+
+    // string literals such as "error.key" in the sources under `dir`, outside of
+    // their tests
+    fn referenced_ids(dir: &Path, ids: &mut Vec<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                referenced_ids(&path, ids);
+                continue;
+            }
+            if path.extension().map_or(true, |ext| ext != "rs") {
+                continue;
+            }
+            let source = fs::read_to_string(&path).unwrap();
+            let source = source.split("#[cfg(test)]").next().unwrap();
+            for ns in NAMESPACES {
+                for (start, _) in source.match_indices(&format!("\"{}", ns)) {
+                    let rest = &source[start + 1..];
+                    let Some(end) = rest.find('"') else {
+                        continue;
+                    };
+                    let literal = &rest[..end];
+                    if literal
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._".contains(c))
+                    {
+                        ids.push(literal.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_catalogs_have_every_referenced_id() {
+        let mut ids = Vec::new();
+        referenced_ids(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src"), &mut ids);
+        assert!(ids.contains(&"error.key".to_string()));
+        for lang in [Lang::En, Lang::Ja] {
+            let missing = ids
+                .iter()
+                .filter(|id| !catalog(lang).contains_key(*id))
+                .collect::<Vec<_>>();
+            assert!(missing.is_empty(), "{:?} is missing {:?}", lang, missing);
+        }
+        // and the catalogs have the same messages
+        let mut en = catalog(Lang::En).keys().collect::<Vec<_>>();
+        let mut ja = catalog(Lang::Ja).keys().collect::<Vec<_>>();
+        en.sort();
+        ja.sort();
+        assert_eq!(en, ja);
+    }
+
+    #[test]
+    fn test_japanese_error() {
+        let lang = Lang::from_str("ja", true).unwrap();
+        let error = CliError::KeyError("no such key".to_string());
+        assert_eq!(error.message_id(), "error.key");
+        assert_eq!(error.localized_in(lang), "鍵のエラー: no such key");
+        assert_eq!(error.localized_in(Lang::En), "Key error: no such key");
+        let timeout = CliError::Timeout(TimeoutError {
+            phase: Phase::Sync,
+            kind: TimeoutKind::Deadline,
+            limit: std::time::Duration::from_secs(5),
+        });
+        assert!(timeout.localized_in(lang).contains("タイムアウト"));
+    }
+
+    #[test]
+    fn test_japanese_summary() {
+        let root = "0x01";
+        assert_eq!(
+            tr_in(Lang::Ja, "msg.tx_finalized", &[("root", &root)]),
+            "トランザクションを確定しました。トランザクションツリーのルート 0x01"
+        );
+        assert_eq!(
+            tr_in(Lang::En, "msg.tx_finalized", &[("root", &root)]),
+            "Tx finalized, tx tree root 0x01"
+        );
+    }
+
+    #[test]
+    fn test_lang_from_locale() {
+        assert_eq!(Lang::from_locale("ja_JP.UTF-8"), Some(Lang::Ja));
+        assert_eq!(Lang::from_locale("en_US"), Some(Lang::En));
+        assert_eq!(Lang::from_locale("C"), None);
+        // an unknown ID is printed as it is
+        assert_eq!(tr_in(Lang::Ja, "error.unknown", &[]), "error.unknown");
+        assert_eq!(
+            tr_in(Lang::En, "msg.batch_sent", &[("count", &3)]),
+            "Sent 3 transfers"
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use std::{collections::BTreeMap, path::Path};
+
+use ethers::types::H256;
+use intmax2_client_sdk::{
+    client::key_file::export_key_file, external_api::utils::url::normalize_base_url,
+};
+use intmax2_zkp::{
+    common::signature::key_set::KeySet, ethereum_types::u32limb_trait::U32LimbTrait as _,
+};
+
+use super::{
+    doctor::doctor,
+    error::CliError,
+    i18n::{tr, tr_with},
+    key::{h256_to_keyset, passphrase, store_key_file_in},
+    profile::{set_key_in_keyring, Config, KeyStorage, Profile},
+    utils::local_dir,
+};
+
+/// Known environments whose settings `init` can fill in
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+    /// The local devnet of `docker compose up` and the contract deployment of the README
+    Devnet,
+}
+
+/// Settings every command needs, with a description for the prompt
+pub(crate) const REQUIRED_SETTINGS: &[(&str, &str)] = &[
+    ("ENV", "Environment (dev, staging or prod)"),
+    ("INDEXER_BASE_URL", "Indexer URL"),
+    ("STORE_VAULT_SERVER_BASE_URL", "Store vault server URL"),
+    ("BALANCE_PROVER_BASE_URL", "Balance prover URL"),
+    ("VALIDITY_PROVER_BASE_URL", "Validity prover URL"),
+    ("WITHDRAWAL_SERVER_BASE_URL", "Withdrawal server URL"),
+    ("L1_RPC_URL", "L1 RPC URL"),
+    ("L1_CHAIN_ID", "L1 chain id"),
+    ("LIQUIDITY_CONTRACT_ADDRESS", "Liquidity contract address"),
+    ("L2_RPC_URL", "L2 RPC URL"),
+    ("L2_CHAIN_ID", "L2 chain id"),
+    ("ROLLUP_CONTRACT_ADDRESS", "Rollup contract address"),
+    (
+        "ROLLUP_CONTRACT_DEPLOYED_BLOCK_NUMBER",
+        "Deployment block of the rollup contract",
+    ),
+];
+
+/// Settings with a default that is right for every environment
+pub(crate) const DEFAULT_SETTINGS: &[(&str, &str)] = &[
+    ("DEPOSIT_TIMEOUT", "80"),
+    ("TX_TIMEOUT", "80"),
+    ("BLOCK_BUILDER_REQUEST_INTERVAL", "5"),
+    ("BLOCK_BUILDER_REQUEST_LIMIT", "4"),
+    ("BLOCK_BUILDER_QUERY_WAIT_TIME", "5"),
+    ("BLOCK_BUILDER_QUERY_INTERVAL", "5"),
+    ("BLOCK_BUILDER_QUERY_LIMIT", "20"),
+];
+
+pub(crate) fn preset_settings(preset: Preset) -> BTreeMap<String, String> {
+    let settings: &[(&str, &str)] = match preset {
+        Preset::Devnet => &[
+            ("ENV", "dev"),
+            ("INDEXER_BASE_URL", "http://localhost:3000"),
+            ("STORE_VAULT_SERVER_BASE_URL", "http://localhost:9000"),
+            ("BALANCE_PROVER_BASE_URL", "http://localhost:9001"),
+            ("VALIDITY_PROVER_BASE_URL", "http://localhost:9002"),
+            ("WITHDRAWAL_SERVER_BASE_URL", "http://localhost:9003"),
+            ("BLOCK_BUILDER_BASE_URL", "http://localhost:9004"),
+            ("L1_RPC_URL", "http://127.0.0.1:8545"),
+            ("L1_CHAIN_ID", "31337"),
+            (
+                "LIQUIDITY_CONTRACT_ADDRESS",
+                "0xdc64a140aa3e981100a9beca4e685f962f0cf6c9",
+            ),
+            ("L2_RPC_URL", "http://127.0.0.1:8545"),
+            ("L2_CHAIN_ID", "31337"),
+            (
+                "ROLLUP_CONTRACT_ADDRESS",
+                "0xe7f1725e7734ce288f8367e1bb143e90bb3f0512",
+            ),
+            ("ROLLUP_CONTRACT_DEPLOYED_BLOCK_NUMBER", "1"),
+        ],
+    };
+    settings
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct InitOptions {
+    pub profile: String,
+    pub preset: Option<Preset>,
+    /// `NAME=VALUE` settings given on the command line, overriding the preset
+    pub settings: Vec<(String, String)>,
+    /// Key to import instead of generating one
+    pub private_key: Option<H256>,
+    /// Keep the key in an encrypted key file even if the OS keychain is available
+    pub no_keyring: bool,
+    /// Fail instead of prompting for anything missing
+    pub non_interactive: bool,
+    pub force: bool,
+    pub skip_doctor: bool,
+}
+
+/// Parse a `NAME=VALUE` setting of `init --set`
+pub fn parse_setting(setting: &str) -> Result<(String, String), String> {
+    let (name, value) = setting
+        .split_once('=')
+        .ok_or(format!("{} is not NAME=VALUE", setting))?;
+    Ok((name.trim().to_uppercase(), value.trim().to_string()))
+}
+
+fn prompt_error(e: dialoguer::Error) -> CliError {
+    CliError::ProfileError(format!("Failed to read input: {}", e))
+}
+
+fn collect_settings(options: &InitOptions) -> Result<BTreeMap<String, String>, CliError> {
+    let preset = match options.preset {
+        Some(preset) => Some(preset),
+        None if !options.non_interactive => dialoguer::Confirm::new()
+            .with_prompt(tr("prompt.use_devnet_preset"))
+            .default(true)
+            .interact()
+            .map_err(prompt_error)?
+            .then_some(Preset::Devnet),
+        None => None,
+    };
+    let mut settings = preset.map(preset_settings).unwrap_or_default();
+    for (name, value) in DEFAULT_SETTINGS {
+        settings
+            .entry(name.to_string())
+            .or_insert(value.to_string());
+    }
+    settings.extend(options.settings.iter().cloned());
+
+    let mut missing = Vec::new();
+    for (name, description) in REQUIRED_SETTINGS {
+        // a preset is already confirmed, but a setting from the environment is not
+        let current = settings
+            .get(*name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok());
+        let value = if options.non_interactive || (preset.is_some() && settings.contains_key(*name))
+        {
+            current
+        } else {
+            let mut input = dialoguer::Input::<String>::new().with_prompt(*description);
+            if let Some(current) = current {
+                input = input.default(current);
+            }
+            Some(input.interact_text().map_err(prompt_error)?)
+        };
+        match value {
+            Some(value) => {
+                settings.insert(name.to_string(), value);
+            }
+            None => missing.push(*name),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(CliError::ProfileError(format!(
+            "missing settings {}, give them with --set NAME=VALUE or --preset",
+            missing.join(", ")
+        )));
+    }
+    for (name, value) in settings.iter_mut() {
+        if name.ends_with("_BASE_URL") {
+            *value = normalize_base_url(name, value)?;
+        }
+    }
+    Ok(settings)
+}
+
+fn choose_key(options: &InitOptions) -> Result<KeySet, CliError> {
+    if let Some(private_key) = options.private_key {
+        return Ok(h256_to_keyset(private_key));
+    }
+    if options.non_interactive {
+        return Ok(KeySet::rand(&mut rand::thread_rng()));
+    }
+    let choice = dialoguer::Select::new()
+        .with_prompt(tr("prompt.key_of_account"))
+        .items(&[tr("prompt.generate_key"), tr("prompt.import_key")])
+        .default(0)
+        .interact()
+        .map_err(prompt_error)?;
+    if choice == 0 {
+        return Ok(KeySet::rand(&mut rand::thread_rng()));
+    }
+    let private_key = dialoguer::Password::new()
+        .with_prompt(tr("prompt.private_key"))
+        .interact()
+        .map_err(prompt_error)?;
+    let private_key: H256 = private_key
+        .trim()
+        .parse()
+        .map_err(|e| CliError::KeyError(format!("Invalid private key: {}", e)))?;
+    Ok(h256_to_keyset(private_key))
+}
+
+fn store_key(dir: &Path, options: &InitOptions, key: KeySet) -> Result<KeyStorage, CliError> {
+    if !options.no_keyring {
+        match set_key_in_keyring(&options.profile, key) {
+            Ok(()) => return Ok(KeyStorage::Keyring),
+            Err(e) => println!("{}. Keeping the key in an encrypted key file instead", e),
+        }
+    }
+    if options.non_interactive && std::env::var("KEY_FILE_PASSPHRASE").is_err() {
+        return Err(CliError::KeyError(
+            "KEY_FILE_PASSPHRASE is required to write the key file without prompting".to_string(),
+        ));
+    }
+    let passphrase = passphrase(&tr("prompt.new_key_file_passphrase"), true, false)?;
+    let path = store_key_file_in(
+        &dir.join("keys"),
+        key,
+        &export_key_file(key, Some(&passphrase)),
+    )?;
+    Ok(KeyStorage::File { path })
+}
+
+/// Create a profile in `~/.intmax2/config.toml`: the settings of the services, and
+/// a generated or imported key kept in the OS keychain or an encrypted key file.
+/// Commands given no key use the key of the profile.
+pub async fn init(options: InitOptions) -> Result<KeySet, CliError> {
+    init_in(&local_dir()?, options).await
+}
+
+pub async fn init_in(dir: &Path, options: InitOptions) -> Result<KeySet, CliError> {
+    let mut config = Config::load_in(dir)?;
+    if config.profiles.contains_key(&options.profile) && !options.force {
+        return Err(CliError::ProfileError(format!(
+            "profile {} already exists in {:?}, pass --force to overwrite it",
+            options.profile,
+            Config::path_in(dir)
+        )));
+    }
+
+    let settings = collect_settings(&options)?;
+    let key = choose_key(&options)?;
+    let key_storage = store_key(dir, &options, key)?;
+    // the builder lists are edited by hand, so overwriting the profile keeps them
+    let (trusted_builders, blocked_builders) = config
+        .profiles
+        .get(&options.profile)
+        .map(|old| (old.trusted_builders.clone(), old.blocked_builders.clone()))
+        .unwrap_or_default();
+    config.profiles.insert(
+        options.profile.clone(),
+        Profile {
+            pubkey: key.pubkey.to_hex(),
+            key: key_storage,
+            trusted_builders,
+            blocked_builders,
+            env: settings.clone(),
+        },
+    );
+    if config.default_profile.is_none() {
+        config.default_profile = Some(options.profile.clone());
+    }
+    config.save_in(dir)?;
+    println!(
+        "{}",
+        tr_with(
+            "msg.profile_written",
+            &[
+                ("profile", &options.profile),
+                ("path", &format!("{:?}", Config::path_in(dir)))
+            ]
+        )
+    );
+
+    if !options.skip_doctor {
+        for (name, value) in settings.iter() {
+            if std::env::var_os(name).is_none() {
+                std::env::set_var(name, value);
+            }
+        }
+        println!("{}", tr("msg.checking_connectivity"));
+        if !doctor().await? {
+            println!("{}", tr("msg.checks_failed"));
+        }
+    }
+
+    println!(
+        "{}",
+        tr_with(
+            "msg.public_key_address",
+            &[("pubkey", &key.pubkey.to_hex())]
+        )
+    );
+    println!("{}", tr("msg.next_steps"));
+    println!("  intmax2-cli deposit --eth-private-key <KEY> --amount <AMOUNT> --token-type NATIVE");
+    println!("  intmax2-cli balance");
+    if config.default_profile.as_deref() != Some(options.profile.as_str()) {
+        println!("  Pass --profile {} to use this profile", options.profile);
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::cli::{key::read_key_file, profile::profile_key_in};
+
+    use super::*;
+
+    fn options(profile: &str) -> InitOptions {
+        InitOptions {
+            profile: profile.to_string(),
+            preset: Some(Preset::Devnet),
+            settings: vec![parse_setting("tx_timeout=120").unwrap()],
+            private_key: None,
+            no_keyring: true,
+            non_interactive: true,
+            force: false,
+            skip_doctor: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_interactive_init() {
+        let dir = std::env::temp_dir().join(format!("intmax2-init-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        std::env::set_var("KEY_FILE_PASSPHRASE", "init test passphrase");
+
+        let key = init_in(&dir, options("default")).await.unwrap();
+        let config = Config::load_in(&dir).unwrap();
+        assert_eq!(config.default_profile.as_deref(), Some("default"));
+        let profile = &config.profiles["default"];
+        assert_eq!(profile.pubkey, key.pubkey.to_hex());
+        assert_eq!(profile.env["TX_TIMEOUT"], "120");
+        assert_eq!(profile.env["DEPOSIT_TIMEOUT"], "80");
+        let KeyStorage::File { path } = &profile.key else {
+            panic!("key should be in a key file");
+        };
+        assert_eq!(read_key_file(path, false).unwrap().pubkey, key.pubkey);
+        assert_eq!(profile_key_in(&dir).unwrap().pubkey, key.pubkey);
+
+        // an existing profile is kept unless --force is given
+        assert!(init_in(&dir, options("default")).await.is_err());
+        let mut forced = options("default");
+        forced.force = true;
+        let new_key = init_in(&dir, forced).await.unwrap();
+        assert_ne!(new_key.pubkey, key.pubkey);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_missing_settings_without_preset() {
+        let mut options = options("other");
+        options.preset = None;
+        options.settings.clear();
+        // variables of a local .env would fill in the settings
+        if std::env::var("INDEXER_BASE_URL").is_err() {
+            assert!(collect_settings(&options).is_err());
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{Read as _, Write as _},
+    path::{Path, PathBuf},
+};
+
+use ethers::types::H256;
+use intmax2_client_sdk::client::{
+    account::{derive_key_from_mnemonic, generate_mnemonic},
+    key_file::{export_json_key_file, export_key_file, import_key_file, is_encrypted_key_file},
+    signer::remote_signer_key,
+};
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait},
+};
+use num_bigint::BigUint;
+
+use crate::Env;
+
+use super::{
+    error::CliError,
+    i18n::{tr, tr_with},
+    profile::profile_key,
+    utils::local_dir,
+};
+
+/// Key of the account, given as a hex private key or a key file. The key of the
+/// active profile is used if none is given.
+#[derive(clap::Args, Debug, Clone)]
+#[group(required = false, multiple = false)]
+pub struct KeyArgs {
+    #[clap(long)]
+    pub private_key: Option<H256>,
+    /// Key file, e.g. imported with `key import`, binary or JSON. The passphrase
+    /// of an encrypted file is read from KEY_FILE_PASSPHRASE or prompted for.
+    #[clap(long)]
+    pub key_file: Option<PathBuf>,
+}
+
+impl KeyArgs {
+    pub fn resolve(&self) -> Result<KeySet, CliError> {
+        if let Some(private_key) = self.private_key {
+            return Ok(h256_to_keyset(private_key));
+        }
+        match &self.key_file {
+            Some(path) => read_key_file(path, false),
+            None => profile_key(),
+        }
+    }
+
+    /// Same as `resolve`, except that the proposals are signed by the remote signer
+    /// of `env` if it is set, so without --private-key or --key-file the account
+    /// of REMOTE_SIGNER_PUBKEY is used without its private key
+    pub fn resolve_with_signer(&self, env: &Env) -> Result<KeySet, CliError> {
+        let given = self.private_key.is_some() || self.key_file.is_some();
+        match (&env.remote_signer_url, &env.remote_signer_pubkey) {
+            (Some(_), Some(pubkey)) if !given => {
+                let pubkey = U256::from_hex(pubkey).map_err(|_| {
+                    CliError::KeyError(format!("REMOTE_SIGNER_PUBKEY {} is not a pubkey", pubkey))
+                })?;
+                Ok(remote_signer_key(pubkey)?)
+            }
+            _ => self.resolve(),
+        }
+    }
+}
+
+pub fn h256_to_keyset(h256: H256) -> KeySet {
+    KeySet::new(BigUint::from_bytes_be(h256.as_bytes()).into())
+}
+
+pub(crate) fn passphrase(
+    prompt: &str,
+    confirm: bool,
+    force_prompt: bool,
+) -> Result<String, CliError> {
+    passphrase_from("KEY_FILE_PASSPHRASE", prompt, confirm, force_prompt)
+}
+
+pub(crate) fn passphrase_from(
+    var: &str,
+    prompt: &str,
+    confirm: bool,
+    force_prompt: bool,
+) -> Result<String, CliError> {
+    if !force_prompt {
+        if let Ok(passphrase) = std::env::var(var) {
+            return Ok(passphrase);
+        }
+    }
+    let mut input = dialoguer::Password::new().with_prompt(prompt);
+    if confirm {
+        input = input.with_confirmation(
+            tr("prompt.repeat_passphrase"),
+            tr("prompt.passphrase_mismatch"),
+        );
+    }
+    input
+        .interact()
+        .map_err(|e| CliError::KeyError(format!("Failed to read passphrase: {}", e)))
+}
+
+fn read_bytes(path: &Path) -> Result<Vec<u8>, CliError> {
+    fs::read(path).map_err(|e| CliError::KeyError(format!("Failed to read {:?}: {}", path, e)))
+}
+
+pub(crate) fn read_key_file(path: &Path, force_prompt: bool) -> Result<KeySet, CliError> {
+    let bytes = read_bytes(path)?;
+    let passphrase = if is_encrypted_key_file(&bytes) {
+        Some(passphrase(
+            &tr("prompt.key_file_passphrase"),
+            false,
+            force_prompt,
+        )?)
+    } else {
+        None
+    };
+    import_key_file(&bytes, passphrase.as_deref())
+        .map_err(|e| CliError::KeyError(format!("{:?}: {}", path, e)))
+}
+
+// never overwrites, so that an existing key is not lost
+fn write_new_file(path: &Path, bytes: &[u8]) -> Result<(), CliError> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(bytes))
+        .map_err(|e| CliError::KeyError(format!("Failed to write {:?}: {}", path, e)))
+}
+
+/// Check a key file and keep an encrypted copy under `~/.intmax2/keys`. A plain
+/// key file is encrypted with a new passphrase first.
+pub fn key_import(file: &Path, passphrase_prompt: bool) -> Result<(), CliError> {
+    let bytes = read_bytes(file)?;
+    let key = read_key_file(file, passphrase_prompt)?;
+    let encrypted = if is_encrypted_key_file(&bytes) {
+        bytes
+    } else {
+        let passphrase = passphrase(&tr("prompt.new_stored_key_passphrase"), true, true)?;
+        export_key_file(key, Some(&passphrase))
+    };
+
+    let path = store_key_file_in(&local_dir()?.join("keys"), key, &encrypted)?;
+    println!(
+        "{}",
+        tr_with("msg.public_key", &[("pubkey", &key.pubkey.to_hex())])
+    );
+    println!(
+        "{}",
+        tr_with("msg.use_key_file", &[("path", &path.display())])
+    );
+    Ok(())
+}
+
+/// Encrypt a private key into the JSON key file `json`, for `--key-file`. The key
+/// is read from `file` if given, otherwise it is prompted for, so that it does not
+/// appear on the command line.
+pub fn key_import_json(
+    json: &Path,
+    file: Option<&Path>,
+    passphrase_prompt: bool,
+) -> Result<(), CliError> {
+    let key = match file {
+        Some(file) => read_key_file(file, passphrase_prompt)?,
+        None => {
+            let private_key = dialoguer::Password::new()
+                .with_prompt(tr("prompt.private_key"))
+                .interact()
+                .map_err(|e| CliError::KeyError(format!("Failed to read private key: {}", e)))?;
+            let private_key: H256 = private_key
+                .trim()
+                .parse()
+                .map_err(|e| CliError::KeyError(format!("Invalid private key: {}", e)))?;
+            h256_to_keyset(private_key)
+        }
+    };
+    let passphrase = passphrase(
+        &tr("prompt.new_key_file_passphrase"),
+        true,
+        passphrase_prompt,
+    )?;
+    write_new_file(json, &export_json_key_file(key, &passphrase))?;
+    println!(
+        "{}",
+        tr_with("msg.public_key", &[("pubkey", &key.pubkey.to_hex())])
+    );
+    println!(
+        "{}",
+        tr_with("msg.use_key_file", &[("path", &json.display())])
+    );
+    Ok(())
+}
+
+/// Keep the encrypted key file `encrypted` of `key` as `<dir>/<pubkey>.key`, unless
+/// there is one already
+pub(crate) fn store_key_file_in(
+    dir: &Path,
+    key: KeySet,
+    encrypted: &[u8],
+) -> Result<PathBuf, CliError> {
+    fs::create_dir_all(dir)
+        .map_err(|e| CliError::KeyError(format!("Failed to create {:?}: {}", dir, e)))?;
+    let path = dir.join(format!("{}.key", key.pubkey.to_hex()));
+    if !path.exists() {
+        write_new_file(&path, encrypted)?;
+    }
+    Ok(path)
+}
+
+/// Write the key to `file`. Only encrypted key files are written.
+pub fn key_export(key: KeySet, file: &Path, encrypt: bool) -> Result<(), CliError> {
+    if !encrypt {
+        return Err(CliError::KeyError(
+            "Refusing to write an unencrypted key file, pass --encrypt".to_string(),
+        ));
+    }
+    let passphrase = passphrase(&tr("prompt.new_key_file_passphrase"), true, false)?;
+    write_new_file(file, &export_key_file(key, Some(&passphrase)))?;
+    println!(
+        "{}",
+        tr_with(
+            "msg.key_written",
+            &[("pubkey", &key.pubkey.to_hex()), ("path", &file.display())]
+        )
+    );
+    Ok(())
+}
+
+fn print_key_pair(key: KeySet) {
+    let private_key: U256 = BigUint::from(key.privkey).try_into().unwrap();
+    println!(
+        "{}",
+        tr_with("msg.private_key", &[("private_key", &private_key.to_hex())])
+    );
+    println!(
+        "{}",
+        tr_with("msg.public_key", &[("pubkey", &key.pubkey.to_hex())])
+    );
+}
+
+/// Create a key with a 24-word mnemonic to back it up, see `key_recover`
+pub fn key_generate() -> Result<(), CliError> {
+    let mnemonic = generate_mnemonic(&mut rand::thread_rng());
+    let key = derive_key_from_mnemonic(&mnemonic, 0)?;
+    println!("{}", tr_with("msg.mnemonic", &[("mnemonic", &mnemonic)]));
+    print_key_pair(key);
+    println!("{}", tr("msg.write_mnemonic_down"));
+    Ok(())
+}
+
+/// The key of a mnemonic of `key_generate`. The mnemonic is prompted for, or read
+/// from standard input with `from_stdin`, never taken as an argument.
+pub fn key_recover(from_stdin: bool) -> Result<(), CliError> {
+    let mnemonic = if from_stdin {
+        let mut mnemonic = String::new();
+        std::io::stdin()
+            .read_to_string(&mut mnemonic)
+            .map_err(|e| CliError::KeyError(format!("Failed to read mnemonic: {}", e)))?;
+        mnemonic
+    } else {
+        dialoguer::Password::new()
+            .with_prompt(tr("prompt.mnemonic"))
+            .interact()
+            .map_err(|e| CliError::KeyError(format!("Failed to read mnemonic: {}", e)))?
+    };
+    let key = derive_key_from_mnemonic(&mnemonic, 0)?;
+    print_key_pair(key);
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read as _, Seek as _, SeekFrom, Write as _},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use fs2::FileExt as _;
+use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
+
+use super::{error::CliError, utils::local_dir};
+
+/// Advisory lock on a pubkey, held by commands that mutate the user state so that
+/// e.g. `sync` and `tx` on the same key don't race. The lock is an OS file lock,
+/// which the kernel releases when the holding process exits, so a lock is never
+/// left behind by a crashed command. Released on drop.
+#[derive(Debug)]
+pub struct AccountLock {
+    file: File,
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LockHolder {
+    pid: u32,
+    started_at: DateTime<Utc>,
+}
+
+impl AccountLock {
+    /// Acquire the lock under `~/.intmax2/locks`. If `wait` is false, fails fast
+    /// when another process holds it.
+    pub fn acquire(pubkey: U256, wait: bool) -> Result<Self, CliError> {
+        Self::acquire_in(&local_dir()?.join("locks"), pubkey, wait)
+    }
+
+    pub fn acquire_in(dir: &Path, pubkey: U256, wait: bool) -> Result<Self, CliError> {
+        fs::create_dir_all(dir).map_err(|e| {
+            CliError::LockError(format!("Failed to create lock dir {:?}: {}", dir, e))
+        })?;
+        // the lock file is never removed, so that every process locks the same file
+        let path = dir.join(format!("{}.lock", pubkey.to_hex()));
+        let file = open_lock_file(&path)?;
+        if file.try_lock_exclusive().is_ok() {
+            return Self::hold(file, path);
+        }
+        // the holder's PID and start time are only used for the message
+        let holder = read_holder(&path);
+        if !wait {
+            return Err(CliError::LockError(contention_message(holder.as_ref())));
+        }
+        log::info!("{}. Waiting...", contention_message(holder.as_ref()));
+        file.lock_exclusive()
+            .map_err(|e| CliError::LockError(format!("Failed to wait for lock: {}", e)))?;
+        Self::hold(file, path)
+    }
+
+    fn hold(mut file: File, path: PathBuf) -> Result<Self, CliError> {
+        let holder = format!("{}\n{}\n", std::process::id(), Utc::now().to_rfc3339());
+        file.set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(holder.as_bytes()))
+            .map_err(|e| CliError::LockError(format!("Failed to write lock file: {}", e)))?;
+        Ok(Self { file, path })
+    }
+}
+
+impl Drop for AccountLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        if let Err(e) = self.file.unlock() {
+            log::warn!("Failed to release lock {:?}: {}", self.path, e);
+        }
+    }
+}
+
+fn open_lock_file(path: &Path) -> Result<File, CliError> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| CliError::LockError(format!("Failed to open lock file {:?}: {}", path, e)))
+}
+
+fn read_holder(path: &Path) -> Option<LockHolder> {
+    let mut content = String::new();
+    File::open(path).ok()?.read_to_string(&mut content).ok()?;
+    let mut lines = content.lines();
+    let pid = lines.next()?.parse().ok()?;
+    let started_at = DateTime::parse_from_rfc3339(lines.next()?).ok()?.into();
+    Some(LockHolder { pid, started_at })
+}
+
+fn contention_message(holder: Option<&LockHolder>) -> String {
+    match holder {
+        Some(holder) => format!(
+            "Another command is using this account (PID {}, started at {}). Use --wait to wait for it",
+            holder.pid, holder.started_at
+        ),
+        None => "Another command is using this account. Use --wait to wait for it".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::common::signature::key_set::KeySet;
+
+    use crate::cli::utils::test_dir;
+
+    use super::*;
+
+    fn rand_pubkey() -> U256 {
+        KeySet::rand(&mut rand::thread_rng()).pubkey
+    }
+
+    #[test]
+    fn test_lock_contention() {
+        let dir = test_dir("lock-contention");
+        let pubkey = rand_pubkey();
+        let lock = AccountLock::acquire_in(&dir, pubkey, false).unwrap();
+        let result = AccountLock::acquire_in(&dir, pubkey, false);
+        match result {
+            Err(CliError::LockError(message)) => {
+                assert!(message.contains(&format!("PID {}", std::process::id())))
+            }
+            _ => panic!("expected lock error"),
+        }
+        // other keys are not affected
+        AccountLock::acquire_in(&dir, rand_pubkey(), false).unwrap();
+        drop(lock);
+        AccountLock::acquire_in(&dir, pubkey, false).unwrap();
+    }
+
+    #[test]
+    fn test_lock_is_held_by_the_os_lock() {
+        let dir = test_dir("lock-os-lock");
+        let pubkey = rand_pubkey();
+        let lock = AccountLock::acquire_in(&dir, pubkey, false).unwrap();
+        // the holder recorded in the file does not matter while the lock is held
+        fs::write(
+            &lock.path,
+            format!("{}\n{}\n", u32::MAX, Utc::now().to_rfc3339()),
+        )
+        .unwrap();
+        assert!(matches!(
+            AccountLock::acquire_in(&dir, pubkey, false),
+            Err(CliError::LockError(_))
+        ));
+
+        drop(lock);
+        AccountLock::acquire_in(&dir, pubkey, false).unwrap();
+
+        // the file left by a process that exited without dropping the lock, as after
+        // a crash, holds no lock
+        let pubkey = rand_pubkey();
+        fs::write(
+            dir.join(format!("{}.lock", pubkey.to_hex())),
+            format!("{}\n{}\n", u32::MAX, Utc::now().to_rfc3339()),
+        )
+        .unwrap();
+        AccountLock::acquire_in(&dir, pubkey, false).unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+use std::{fs, path::PathBuf};
+
+use intmax2_client_sdk::external_api::mirrored_store_vault::{MirrorQueueStore, QueuedMirrorOp};
+
+/// Writes waiting to be mirrored to the secondary store vaults, kept in one JSON
+/// file so that the next command retries them
+#[derive(Debug, Clone)]
+pub struct FileMirrorQueueStore {
+    path: PathBuf,
+}
+
+impl FileMirrorQueueStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl MirrorQueueStore for FileMirrorQueueStore {
+    fn load(&self) -> Vec<QueuedMirrorOp> {
+        let Ok(data) = fs::read_to_string(&self.path) else {
+            return Vec::new();
+        };
+        serde_json::from_str(&data).unwrap_or_else(|e| {
+            log::warn!("Ignoring unreadable mirror queue {:?}: {}", self.path, e);
+            Vec::new()
+        })
+    }
+
+    fn save(&self, ops: &[QueuedMirrorOp]) {
+        let result = if ops.is_empty() {
+            match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        } else {
+            let data = serde_json::to_string(ops).unwrap();
+            let tmp = self.path.with_extension("json.tmp");
+            self.path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&tmp, data))
+                .and_then(|_| fs::rename(&tmp, &self.path))
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to save the mirror queue {:?}: {}", self.path, e);
+        }
+    }
+}
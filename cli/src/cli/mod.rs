@@ -1,7 +1,45 @@
+pub mod address_book;
+pub mod backup;
+pub mod batch;
+pub mod builders;
+pub mod claim;
 pub mod client;
+pub mod complete;
+pub mod config_file;
+pub mod consolidate;
+pub mod deadline;
+pub mod debug;
 pub mod deposit;
+pub mod doctor;
 pub mod error;
 pub mod get;
+pub mod i18n;
+pub mod init;
+pub mod key;
+pub mod lock;
+pub mod mirror_queue;
+pub mod observe;
+#[cfg(feature = "offsite-backup")]
+pub mod offsite_backup;
+pub mod output;
+pub mod profile;
+pub mod provisional;
+pub mod receipt;
+pub mod recover;
+pub mod reload;
+pub mod rotate;
+#[cfg(feature = "offsite-backup")]
+pub mod s3;
 pub mod send;
+pub mod session;
+pub mod stream;
+pub mod support_bundle;
 pub mod sync;
+pub mod template;
+pub mod tx_status;
 pub mod utils;
+pub mod version_check;
+pub mod watcher;
+pub mod webhook;
+pub mod witness_bundle;
+pub mod witness_cache;
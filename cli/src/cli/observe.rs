@@ -0,0 +1,257 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use chrono::{DateTime, Utc};
+use colored::Colorize as _;
+use futures::{Stream, StreamExt as _};
+use intmax2_client_sdk::{
+    client::observer::{
+        BlockInclusion, ObservedDeposit, Observer, ObserverConfig, RegistrationStatus,
+    },
+    external_api::contract::{
+        interface::{BlockchainError, ContractReader},
+        liquidity_contract::OnchainDeposit,
+        liquidity_ws::WsLiquidityReader,
+    },
+    utils::id::format_plain_id,
+};
+use intmax2_interfaces::api::validity_prover::interface::ValidityProverClientInterface;
+use intmax2_zkp::ethereum_types::{bytes32::Bytes32, u256::U256};
+use serde_json::json;
+
+use super::{
+    client::get_client_from,
+    error::CliError,
+    i18n::{tr, tr_with},
+    output::{is_json_output, print_json, print_notice, print_warning},
+    utils::{display_id, load_env},
+    watcher::watcher_interval,
+};
+
+/// Print what is public of the account of `pubkey`: its registration, the deposits
+/// to `salt_hashes` and the blocks with its txs from `from_block` (the blocks posted
+/// from now on by default). With `follow`, keep printing the new deposits and
+/// blocks until Ctrl-C. No key is used, so balances and transfers are not shown.
+///
+/// With `L1_WS_RPC_URL`, the liquidity contract is read over a WebSocket, which
+/// also pushes the new deposits instead of having them polled.
+pub async fn observe(
+    pubkey: U256,
+    salt_hashes: Vec<Bytes32>,
+    from_block: Option<u32>,
+    follow: bool,
+    interval: Option<u64>,
+) -> Result<(), CliError> {
+    let env = load_env()?;
+    let client = get_client_from(&env)?;
+    let interval = watcher_interval(interval, &env);
+    let Some(ws_rpc_url) = &env.l1_ws_rpc_url else {
+        let observer = new_observer(
+            client.validity_prover,
+            client.liquidity_contract,
+            pubkey,
+            salt_hashes,
+            from_block,
+        );
+        return run_observer(observer, None, follow, interval).await;
+    };
+    let reader = WsLiquidityReader::connect(ws_rpc_url, client.liquidity_contract.address())
+        .await?
+        .with_deployed_block_number(env.liquidity_contract_deployed_block_number.unwrap_or(0));
+    let observer = new_observer(
+        client.validity_prover,
+        reader.clone(),
+        pubkey,
+        salt_hashes,
+        from_block,
+    );
+    run_observer(observer, Some(&reader), follow, interval).await
+}
+
+fn new_observer<V: ValidityProverClientInterface, R: ContractReader>(
+    validity_prover: V,
+    liquidity_contract: R,
+    pubkey: U256,
+    salt_hashes: Vec<Bytes32>,
+    from_block: Option<u32>,
+) -> Observer<V, R> {
+    let mut observer = Observer::new(
+        ObserverConfig {
+            validity_prover,
+            liquidity_contract,
+            from_block_number: from_block,
+        },
+        pubkey,
+    );
+    for salt_hash in salt_hashes {
+        observer.register_salt_hash(salt_hash);
+    }
+    observer
+}
+
+async fn run_observer<V: ValidityProverClientInterface, R: ContractReader>(
+    mut observer: Observer<V, R>,
+    ws_reader: Option<&WsLiquidityReader>,
+    follow: bool,
+    interval: u64,
+) -> Result<(), CliError> {
+    // subscribed before the first read, so that no deposit falls in between
+    let mut pushed_deposits = match ws_reader {
+        Some(reader) => Some(Box::pin(reader.subscribe_deposited_events().await?)),
+        None => None,
+    };
+    let registration = observer.get_registration_status().await?;
+    let deposits = observer.get_onchain_deposits().await?;
+    let mut inclusions = observer.get_block_inclusions().await?;
+    while !observer.is_caught_up() {
+        inclusions.extend(observer.get_block_inclusions().await?);
+    }
+    if is_json_output() {
+        print_json(&json!({
+            "registration": registration,
+            "deposits": deposits,
+            "inclusions": inclusions,
+            "nextBlockNumber": observer.next_block_number(),
+        }));
+    } else {
+        print_registration(&registration);
+        println!(
+            "{}",
+            tr_with(
+                "msg.observed_deposits",
+                &[("count", &observer.salt_hashes().len())]
+            )
+        );
+        deposits.iter().for_each(print_deposit);
+        println!("{}", tr("msg.blocks_with_tx"));
+        inclusions.iter().for_each(print_inclusion);
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    let mut seen_deposits = deposits
+        .iter()
+        .map(|deposit| deposit.deposit_id)
+        .collect::<BTreeSet<_>>();
+    let mut registered = registration.is_registered();
+    print_notice(tr_with(
+        "msg.observing",
+        &[("block", &observer.next_block_number().unwrap_or_default())],
+    ));
+    loop {
+        let mut new_deposits = Vec::new();
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(interval)) => {
+                if pushed_deposits.is_none() {
+                    new_deposits = observer.get_onchain_deposits().await?;
+                }
+            }
+            pushed = next_deposit(&mut pushed_deposits) => match pushed {
+                Some(pushed) => {
+                    let deposit = ObservedDeposit::from(pushed?);
+                    if observer.salt_hashes().contains(&deposit.pubkey_salt_hash) {
+                        new_deposits.push(deposit);
+                    }
+                }
+                None => {
+                    print_warning("The deposit subscription was closed, polling from now on");
+                    pushed_deposits = None;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                print_notice(tr("msg.observer_stopped"));
+                return Ok(());
+            }
+        }
+        for deposit in new_deposits {
+            if seen_deposits.insert(deposit.deposit_id) {
+                if is_json_output() {
+                    print_json(&json!({ "deposit": deposit }));
+                } else {
+                    print_deposit(&deposit);
+                }
+            }
+        }
+        for inclusion in observer.get_block_inclusions().await? {
+            if is_json_output() {
+                print_json(&json!({ "inclusion": inclusion }));
+            } else {
+                print_inclusion(&inclusion);
+            }
+        }
+        if !registered {
+            let registration = observer.get_registration_status().await?;
+            if registration.is_registered() {
+                registered = true;
+                if is_json_output() {
+                    print_json(&json!({ "registration": registration }));
+                } else {
+                    print_registration(&registration);
+                }
+            }
+        }
+    }
+}
+
+/// The next deposit pushed by the subscription, pending forever without one
+async fn next_deposit<S: Stream<Item = Result<OnchainDeposit, BlockchainError>> + Unpin>(
+    subscription: &mut Option<S>,
+) -> Option<Result<OnchainDeposit, BlockchainError>> {
+    match subscription {
+        Some(subscription) => subscription.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn print_registration(registration: &RegistrationStatus) {
+    match registration.account_id {
+        Some(account_id) => println!(
+            "{}",
+            tr_with(
+                "msg.registered",
+                &[
+                    ("account_id", &account_id),
+                    ("block", &registration.block_number)
+                ]
+            )
+        ),
+        None => println!(
+            "{}",
+            tr_with(
+                "msg.not_registered",
+                &[("block", &registration.block_number)]
+            )
+        ),
+    }
+}
+
+fn print_deposit(deposit: &ObservedDeposit) {
+    let deposited_at = DateTime::<Utc>::from_timestamp(deposit.deposited_at as i64, 0)
+        .map(|date| date.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| deposit.deposited_at.to_string());
+    println!(
+        "\t Deposit {}: {} of token {} from {} at {} (salt hash {})",
+        deposit.deposit_id,
+        deposit.amount,
+        deposit.token_index,
+        deposit.sender,
+        deposited_at,
+        format_plain_id(deposit.pubkey_salt_hash)
+    );
+}
+
+fn print_inclusion(inclusion: &BlockInclusion) {
+    let status = if inclusion.is_executed() {
+        "executed".green()
+    } else if !inclusion.did_return_sig {
+        "not signed".yellow()
+    } else {
+        "invalid block".red()
+    };
+    println!(
+        "\t Block {}: tx tree root {} {}",
+        inclusion.block_number,
+        display_id(inclusion.tx_tree_root),
+        status
+    );
+}
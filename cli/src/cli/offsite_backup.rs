@@ -0,0 +1,616 @@
+//! Offsite backups of the local store (`~/.intmax2`) to S3-compatible storage,
+//! readable only with the key of the account and the backup passphrase.
+//!
+//! Each file is a separate object named hex(HMAC(naming key, path)) and holding
+//! nonce || XChaCha20-Poly1305(contents) with the object name as associated data.
+//! The paths, times and hashes of the files are in a manifest encrypted the same
+//! way under a name no path can have, and a plain copy of it is kept locally. Both
+//! keys are derived from scrypt(passphrase, sha256(DOMAIN || private key)).
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use colored::Colorize as _;
+use hmac::{Hmac, Mac as _};
+use intmax2_zkp::common::signature::key_set::KeySet;
+use num_bigint::BigUint;
+use rand::RngCore as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    error::CliError,
+    i18n::tr,
+    key::passphrase_from,
+    s3::{ObjectStore, S3Store, S3Target},
+    utils::{load_env, local_dir},
+};
+
+const DOMAIN: &[u8] = b"intmax2 offsite backup";
+const NONCE_LEN: usize = 24;
+/// scrypt cost of the backup keys (N = 2^SCRYPT_LOG_N, r = 8, p = 1)
+const SCRYPT_LOG_N: u8 = 15;
+// paths never contain NUL, so no file can be stored under the manifest's name
+const MANIFEST_LABEL: &[u8] = b"\0manifest";
+const LOCAL_MANIFEST_FILE: &str = "offsite_manifest.json";
+// lock files only make sense on the machine that holds them
+const EXCLUDED: [&str; 2] = ["locks", LOCAL_MANIFEST_FILE];
+const DEFAULT_SCHEDULE_INTERVAL: u64 = 3600;
+
+fn offsite_error(e: impl std::fmt::Display) -> CliError {
+    CliError::OffsiteBackupError(e.to_string())
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Keys of the backups of one account with one passphrase
+pub struct BackupKeys {
+    encryption: [u8; 32],
+    naming: [u8; 32],
+}
+
+impl BackupKeys {
+    pub fn derive(key: KeySet, passphrase: &str) -> Result<Self, CliError> {
+        Self::derive_with_cost(key, passphrase, SCRYPT_LOG_N)
+    }
+
+    fn derive_with_cost(key: KeySet, passphrase: &str, log_n: u8) -> Result<Self, CliError> {
+        let salt = Sha256::new()
+            .chain_update(DOMAIN)
+            .chain_update(BigUint::from(key.privkey).to_bytes_be())
+            .finalize();
+        let params = scrypt::Params::new(log_n, 8, 1, 32).map_err(offsite_error)?;
+        let mut master = [0u8; 32];
+        scrypt::scrypt(passphrase.as_bytes(), &salt, &params, &mut master)
+            .map_err(offsite_error)?;
+        Ok(Self {
+            encryption: hmac_sha256(&master, b"encryption"),
+            naming: hmac_sha256(&master, b"naming"),
+        })
+    }
+
+    fn object_name(&self, path: &str) -> String {
+        hex::encode(hmac_sha256(&self.naming, path.as_bytes()))
+    }
+
+    fn manifest_name(&self) -> String {
+        hex::encode(hmac_sha256(&self.naming, MANIFEST_LABEL))
+    }
+
+    fn encrypt(&self, name: &str, plain: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = XChaCha20Poly1305::new(&self.encryption.into())
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plain,
+                    aad: name.as_bytes(),
+                },
+            )
+            .expect("encryption of a backup object cannot fail");
+        let mut bytes = nonce.to_vec();
+        bytes.extend(ciphertext);
+        bytes
+    }
+
+    fn decrypt(&self, name: &str, bytes: &[u8]) -> Option<Vec<u8>> {
+        if bytes.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        XChaCha20Poly1305::new(&self.encryption.into())
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: name.as_bytes(),
+                },
+            )
+            .ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    pub object: String,
+    // unix seconds of the last modification of the file when it was pushed
+    pub modified: u64,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Files of a backup, by path relative to the local store with `/` separators
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OffsiteManifest {
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PushReport {
+    pub uploaded: Vec<String>,
+    pub unchanged: usize,
+    // in the backup but no longer in the local store, kept in the backup
+    pub only_in_backup: Vec<String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PullReport {
+    // missing locally
+    pub restored: Vec<String>,
+    // older locally than in the backup
+    pub updated: Vec<String>,
+    // newer locally than in the backup, left as they are
+    pub kept_local: Vec<String>,
+    pub unchanged: usize,
+}
+
+struct LocalFile {
+    modified: u64,
+    bytes: Vec<u8>,
+}
+
+fn read_local_file(path: &Path) -> Result<LocalFile, CliError> {
+    let read_error = |e: std::io::Error| offsite_error(format!("Failed to read {:?}: {}", path, e));
+    let bytes = fs::read(path).map_err(read_error)?;
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(read_error)?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Ok(LocalFile { modified, bytes })
+}
+
+// paths of the files to back up, relative to `root`
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> Result<(), CliError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(offsite_error(format!("Failed to read {:?}: {}", dir, e))),
+    };
+    for entry in entries {
+        let entry = entry.map_err(offsite_error)?;
+        let path = entry.path();
+        let relative = path
+            .strip_prefix(root)
+            .expect("entries are under the root")
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        if EXCLUDED.contains(&relative.as_str()) {
+            continue;
+        }
+        let file_type = entry.file_type().map_err(offsite_error)?;
+        if file_type.is_dir() {
+            list_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+// None if nothing was pushed with these keys, as a wrong key or passphrase
+// leads to another manifest name
+async fn fetch_manifest<S: ObjectStore>(
+    store: &S,
+    keys: &BackupKeys,
+) -> Result<Option<OffsiteManifest>, CliError> {
+    let name = keys.manifest_name();
+    let Some(bytes) = store.get(&name).await? else {
+        return Ok(None);
+    };
+    let plain = keys.decrypt(&name, &bytes).ok_or_else(|| {
+        offsite_error("The backup manifest cannot be decrypted, wrong key or passphrase")
+    })?;
+    serde_json::from_slice(&plain)
+        .map(Some)
+        .map_err(offsite_error)
+}
+
+fn save_local_manifest(dir: &Path, manifest: &OffsiteManifest) -> Result<(), CliError> {
+    let path = dir.join(LOCAL_MANIFEST_FILE);
+    fs::write(&path, serde_json::to_string_pretty(manifest).unwrap())
+        .map_err(|e| offsite_error(format!("Failed to write {:?}: {}", path, e)))
+}
+
+/// Upload the files of `dir` that changed since the last push. Files deleted
+/// locally stay in the backup, so that pushing from an empty store cannot wipe it.
+pub async fn push_in<S: ObjectStore>(
+    dir: &Path,
+    store: &S,
+    keys: &BackupKeys,
+) -> Result<PushReport, CliError> {
+    let mut manifest = fetch_manifest(store, keys).await?.unwrap_or_default();
+    let mut paths = Vec::new();
+    list_files(dir, dir, &mut paths)?;
+    paths.sort();
+
+    let mut report = PushReport::default();
+    for path in paths.iter() {
+        let file = read_local_file(&dir.join(path))?;
+        let sha256 = hex::encode(Sha256::digest(&file.bytes));
+        if manifest
+            .files
+            .get(path)
+            .is_some_and(|entry| entry.sha256 == sha256)
+        {
+            report.unchanged += 1;
+            continue;
+        }
+        let object = keys.object_name(path);
+        store
+            .put(&object, keys.encrypt(&object, &file.bytes))
+            .await?;
+        manifest.files.insert(
+            path.clone(),
+            ManifestEntry {
+                object,
+                modified: file.modified,
+                size: file.bytes.len() as u64,
+                sha256,
+            },
+        );
+        report.uploaded.push(path.clone());
+    }
+    report.only_in_backup = manifest
+        .files
+        .keys()
+        .filter(|path| !paths.contains(path))
+        .cloned()
+        .collect();
+
+    if !report.uploaded.is_empty() {
+        let name = keys.manifest_name();
+        let plain = serde_json::to_vec(&manifest).unwrap();
+        store.put(&name, keys.encrypt(&name, &plain)).await?;
+    }
+    save_local_manifest(dir, &manifest)?;
+    Ok(report)
+}
+
+/// Restore the backup into `dir`. A file present on both sides is taken from
+/// the backup only if the local one is older; the report lists what was kept.
+pub async fn pull_in<S: ObjectStore>(
+    dir: &Path,
+    store: &S,
+    keys: &BackupKeys,
+) -> Result<PullReport, CliError> {
+    let manifest = fetch_manifest(store, keys)
+        .await?
+        .ok_or_else(|| offsite_error("No backup at the target for this key and passphrase"))?;
+    let mut report = PullReport::default();
+    for (path, entry) in manifest.files.iter() {
+        let local_path = dir.join(path);
+        let is_update = match read_local_file(&local_path) {
+            Ok(local) => {
+                if hex::encode(Sha256::digest(&local.bytes)) == entry.sha256 {
+                    report.unchanged += 1;
+                    continue;
+                }
+                if local.modified >= entry.modified {
+                    report.kept_local.push(path.clone());
+                    continue;
+                }
+                true
+            }
+            Err(_) if !local_path.exists() => false,
+            Err(e) => return Err(e),
+        };
+        let bytes = store.get(&entry.object).await?.ok_or_else(|| {
+            offsite_error(format!("The object of {} is missing from the backup", path))
+        })?;
+        let plain = keys
+            .decrypt(&entry.object, &bytes)
+            .ok_or_else(|| offsite_error(format!("The object of {} cannot be decrypted", path)))?;
+        // an older object of the same file also decrypts, e.g. one put back by the
+        // storage
+        if hex::encode(Sha256::digest(&plain)) != entry.sha256 {
+            return Err(offsite_error(format!(
+                "The object of {} does not match its hash in the manifest",
+                path
+            )));
+        }
+        write_restored_file(&local_path, &plain, entry.modified)?;
+        if is_update {
+            report.updated.push(path.clone());
+        } else {
+            report.restored.push(path.clone());
+        }
+    }
+    save_local_manifest(dir, &manifest)?;
+    Ok(report)
+}
+
+// the restored file keeps its time in the backup, so that it does not look newer
+// than the copies on other machines
+fn write_restored_file(path: &Path, bytes: &[u8], modified: u64) -> Result<(), CliError> {
+    let write_error =
+        |e: std::io::Error| offsite_error(format!("Failed to write {:?}: {}", path, e));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(write_error)?;
+    }
+    fs::write(path, bytes).map_err(write_error)?;
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(UNIX_EPOCH + Duration::from_secs(modified)))
+        .map_err(write_error)
+}
+
+fn backup_keys(key: KeySet) -> Result<BackupKeys, CliError> {
+    let passphrase = passphrase_from(
+        "OFFSITE_BACKUP_PASSPHRASE",
+        &tr("prompt.offsite_backup_passphrase"),
+        false,
+        false,
+    )?;
+    BackupKeys::derive(key, &passphrase)
+}
+
+fn print_paths(label: &str, paths: &[String]) {
+    for path in paths {
+        println!("{}: {}", label, path);
+    }
+}
+
+pub async fn backup_push(key: KeySet, target: S3Target) -> Result<(), CliError> {
+    let env = load_env()?;
+    let store = S3Store::from_env(&env, target)?;
+    let keys = backup_keys(key)?;
+    let report = push_in(&local_dir()?, &store, &keys).await?;
+    print_paths("Uploaded", &report.uploaded);
+    print_paths("Only in backup", &report.only_in_backup);
+    println!(
+        "{} uploaded, {} unchanged",
+        report.uploaded.len(),
+        report.unchanged
+    );
+    Ok(())
+}
+
+pub async fn backup_pull(key: KeySet, target: S3Target) -> Result<(), CliError> {
+    let env = load_env()?;
+    let store = S3Store::from_env(&env, target)?;
+    let keys = backup_keys(key)?;
+    let report = pull_in(&local_dir()?, &store, &keys).await?;
+    print_paths("Restored", &report.restored);
+    print_paths("Updated", &report.updated);
+    for path in report.kept_local.iter() {
+        eprintln!(
+            "{} {}",
+            "WARNING:".red().bold(),
+            format!("{} is newer locally, kept the local copy", path).red()
+        );
+    }
+    println!(
+        "{} restored, {} updated, {} kept local, {} unchanged",
+        report.restored.len(),
+        report.updated.len(),
+        report.kept_local.len(),
+        report.unchanged
+    );
+    Ok(())
+}
+
+/// Push every `interval` seconds (default 3600) until Ctrl-C. A failed push is
+/// reported and retried at the next one.
+pub async fn backup_schedule(
+    key: KeySet,
+    target: S3Target,
+    interval: Option<u64>,
+) -> Result<(), CliError> {
+    let env = load_env()?;
+    let store = S3Store::from_env(&env, target)?;
+    let keys = backup_keys(key)?;
+    let dir = local_dir()?;
+    let interval = Duration::from_secs(interval.unwrap_or(DEFAULT_SCHEDULE_INTERVAL));
+    loop {
+        match push_in(&dir, &store, &keys).await {
+            Ok(report) => println!(
+                "{}: {} uploaded, {} unchanged",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                report.uploaded.len(),
+                report.unchanged
+            ),
+            Err(e) => eprintln!(
+                "{} {}",
+                "WARNING:".red().bold(),
+                format!("Backup push failed: {}", e).red()
+            ),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => {
+                println!("{}", tr("msg.backup_schedule_stopped"));
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, time::SystemTime};
+
+    use async_trait::async_trait;
+
+    use crate::cli::utils::test_dir;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        objects: RefCell<HashMap<String, Vec<u8>>>,
+    }
+
+    #[async_trait(?Send)]
+    impl ObjectStore for MemoryStore {
+        async fn put(&self, name: &str, bytes: Vec<u8>) -> Result<(), CliError> {
+            self.objects.borrow_mut().insert(name.to_string(), bytes);
+            Ok(())
+        }
+
+        async fn get(&self, name: &str) -> Result<Option<Vec<u8>>, CliError> {
+            Ok(self.objects.borrow().get(name).cloned())
+        }
+    }
+
+    fn keys(key: KeySet, passphrase: &str) -> BackupKeys {
+        // cheap scrypt cost for tests
+        BackupKeys::derive_with_cost(key, passphrase, 4).unwrap()
+    }
+
+    fn write(dir: &Path, path: &str, contents: &str) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn set_modified(dir: &Path, path: &str, modified: SystemTime) {
+        fs::File::options()
+            .write(true)
+            .open(dir.join(path))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    const FILES: [(&str, &str); 4] = [
+        ("templates/0xabc.tpl", "rent: 100 to bob"),
+        ("address_book.json", r#"{"entries":{"bob":"0x1234"}}"#),
+        ("rebuild/0xabc.json", r#"{"checkpoint":{"blockNumber":42}}"#),
+        ("locks/0xabc.lock", ""),
+    ];
+
+    #[tokio::test]
+    async fn test_destroy_and_restore() {
+        let dir = test_dir("offsite-backup-restore");
+        for (path, contents) in FILES {
+            write(&dir, path, contents);
+        }
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let store = MemoryStore::default();
+
+        let report = push_in(&dir, &store, &keys(key, "correct horse"))
+            .await
+            .unwrap();
+        assert_eq!(
+            report.uploaded,
+            vec![
+                "address_book.json",
+                "rebuild/0xabc.json",
+                "templates/0xabc.tpl"
+            ]
+        );
+        // the object names and contents say nothing about the files
+        for (name, bytes) in store.objects.borrow().iter() {
+            assert_eq!(name.len(), 64);
+            assert!(!FILES.iter().any(|(path, _)| name.contains(path)));
+            assert!(!String::from_utf8_lossy(bytes).contains("bob"));
+        }
+        let again = push_in(&dir, &store, &keys(key, "correct horse"))
+            .await
+            .unwrap();
+        assert!(again.uploaded.is_empty());
+        assert_eq!(again.unchanged, 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(pull_in(&dir, &store, &keys(key, "wrong")).await.is_err());
+        let other_key = KeySet::rand(&mut rand::thread_rng());
+        assert!(pull_in(&dir, &store, &keys(other_key, "correct horse"))
+            .await
+            .is_err());
+
+        let report = pull_in(&dir, &store, &keys(key, "correct horse"))
+            .await
+            .unwrap();
+        assert_eq!(report.restored.len(), 3);
+        for (path, contents) in FILES.iter().take(3) {
+            assert_eq!(fs::read_to_string(dir.join(path)).unwrap(), *contents);
+        }
+        assert!(!dir.join("locks").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pull_newer_wins() {
+        let dir = test_dir("offsite-backup-conflict");
+        write(&dir, "address_book.json", "old contacts");
+        write(&dir, "templates/0xabc.tpl", "old templates");
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let keys = keys(key, "correct horse");
+        let store = MemoryStore::default();
+        push_in(&dir, &store, &keys).await.unwrap();
+
+        // the contacts changed after the push, the templates were an older copy
+        write(&dir, "address_book.json", "new contacts");
+        set_modified(
+            &dir,
+            "address_book.json",
+            SystemTime::now() + Duration::from_secs(60),
+        );
+        write(&dir, "templates/0xabc.tpl", "stale templates");
+        set_modified(&dir, "templates/0xabc.tpl", UNIX_EPOCH);
+
+        let report = pull_in(&dir, &store, &keys).await.unwrap();
+        assert_eq!(report.kept_local, vec!["address_book.json"]);
+        assert_eq!(report.updated, vec!["templates/0xabc.tpl"]);
+        assert_eq!(
+            fs::read_to_string(dir.join("address_book.json")).unwrap(),
+            "new contacts"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("templates/0xabc.tpl")).unwrap(),
+            "old templates"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pull_refuses_replaced_object() {
+        let dir = test_dir("offsite-backup-replaced");
+        write(&dir, "address_book.json", "old contacts");
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let keys = keys(key, "correct horse");
+        let store = MemoryStore::default();
+        push_in(&dir, &store, &keys).await.unwrap();
+        let object = keys.object_name("address_book.json");
+        let old_object = store.objects.borrow()[&object].clone();
+
+        write(&dir, "address_book.json", "new contacts");
+        set_modified(
+            &dir,
+            "address_book.json",
+            SystemTime::now() + Duration::from_secs(60),
+        );
+        push_in(&dir, &store, &keys).await.unwrap();
+        // the storage serves the object of the first push with the new manifest
+        store.objects.borrow_mut().insert(object, old_object);
+
+        fs::remove_dir_all(&dir).unwrap();
+        let CliError::OffsiteBackupError(message) = pull_in(&dir, &store, &keys).await.unwrap_err()
+        else {
+            panic!("expected an offsite backup error");
+        };
+        assert!(message.contains("does not match its hash"));
+        assert!(!dir.join("address_book.json").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
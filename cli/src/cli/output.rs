@@ -0,0 +1,509 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use clap::ValueEnum;
+use intmax2_client_sdk::client::{
+    balance_view::BalanceView,
+    fee_dispute::FeeStatus,
+    history::{GenericTransfer, HistoryEntry},
+    withdrawal_onchain::OnchainConfirmation,
+};
+use intmax2_interfaces::api::{
+    output_stream::types::StreamEvent, withdrawal_server::interface::WithdrawalInfo,
+};
+use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+use serde::Serialize;
+
+use super::{
+    address_book::AddressBook,
+    error::CliError,
+    i18n::{tr, tr_with},
+    stream::{emit_warning, finish_stream, is_ndjson_stream},
+    utils::display_id,
+};
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// `--output` of the long-running commands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputMode {
+    /// Messages for the user as the command goes
+    #[default]
+    Text,
+    /// One JSON event per line as the command goes, ending with its result or
+    /// error. See `StreamEvent`.
+    NdjsonStream,
+}
+
+/// Print the results of the `get` commands as JSON, for `--json`
+pub fn set_json_output(json: bool) {
+    JSON_OUTPUT.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Print a message for the user, to stderr with `--json` or `--output
+/// ndjson-stream` so that stdout only has the JSON result or the events
+pub fn print_notice(message: impl Display) {
+    if is_json_output() || is_ndjson_stream() {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// `print_notice` of a warning, which is a `warning` event of the output stream
+/// with `--output ndjson-stream`
+pub fn print_warning(message: impl Display) {
+    if is_ndjson_stream() {
+        // colors are disabled while streaming
+        emit_warning(message.to_string());
+    } else {
+        print_notice(message);
+    }
+}
+
+/// Print the error that ended the command to stderr, in the language of the
+/// command. With `--json` it is a JSON object with the `messageId` of the message,
+/// for wrappers that show their own text, and with `--output ndjson-stream` the
+/// `error` line ending the stream.
+pub fn print_error(error: &anyhow::Error) {
+    let (message_id, message) = match error.downcast_ref::<CliError>() {
+        Some(e) => (e.message_id(), e.localized()),
+        None => (
+            "error.other",
+            tr_with("error.other", &[("detail", &format!("{:#}", error))]),
+        ),
+    };
+    if is_ndjson_stream() {
+        finish_stream(Some(StreamEvent::Error {
+            error: message_id.trim_start_matches("error.").to_string(),
+            message_id: message_id.to_string(),
+            message,
+        }));
+    } else if is_json_output() {
+        let value = serde_json::json!({
+            "error": message_id.trim_start_matches("error."),
+            "messageId": message_id,
+            "message": message,
+        });
+        eprintln!("{}", value);
+    } else {
+        eprintln!("{}: {}", tr("error.prefix"), message);
+    }
+}
+
+pub fn print_json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+/// With `--json`, print `empty` if `result` is an error, so that stdout is still
+/// valid JSON while the error goes to stderr
+pub fn print_empty_on_error<T, E, O: Serialize>(result: Result<T, E>, empty: &O) -> Result<T, E> {
+    if result.is_err() && is_json_output() {
+        print_json(empty);
+    }
+    result
+}
+
+/// Amounts are decimal strings, as u256 values do not fit in a JSON number
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BalanceOutput {
+    pub token_index: u32,
+    pub amount: String,
+}
+
+/// Fungible balances and NFTs together, in token index order. This array is the
+/// whole output of `balance --json`, as documented in the README.
+pub fn balance_output(view: &BalanceView) -> Vec<BalanceOutput> {
+    let mut balances = view
+        .fungible
+        .iter()
+        .map(|balance| BalanceOutput {
+            token_index: balance.token_index,
+            amount: balance.amount.to_string(),
+        })
+        .chain(view.nfts.iter().flat_map(|holding| {
+            holding.tokens.iter().map(|token| BalanceOutput {
+                token_index: token.token_index,
+                amount: token.amount.to_string(),
+            })
+        }))
+        .collect::<Vec<_>>();
+    balances.sort_by_key(|balance| balance.token_index);
+    balances
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WithdrawalOutput {
+    pub recipient: String,
+    pub token_index: u32,
+    pub amount: String,
+    pub nullifier: String,
+    pub status: String,
+    pub onchain: OnchainOutput,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum OnchainOutput {
+    NotSubmitted,
+    Pending,
+    Confirmed { tx_hash: String, block_number: u64 },
+    SuspectedAggregatorFault { reason: String },
+}
+
+impl From<&OnchainConfirmation> for OnchainOutput {
+    fn from(confirmation: &OnchainConfirmation) -> Self {
+        match confirmation {
+            OnchainConfirmation::NotSubmitted => OnchainOutput::NotSubmitted,
+            OnchainConfirmation::Pending => OnchainOutput::Pending,
+            OnchainConfirmation::Confirmed {
+                tx_hash,
+                block_number,
+            } => OnchainOutput::Confirmed {
+                tx_hash: format!("{:?}", tx_hash),
+                block_number: *block_number,
+            },
+            OnchainConfirmation::SuspectedAggregatorFault { reason } => {
+                OnchainOutput::SuspectedAggregatorFault {
+                    reason: reason.clone(),
+                }
+            }
+        }
+    }
+}
+
+pub fn withdrawal_output(
+    info: &WithdrawalInfo,
+    tag: Option<&String>,
+    confirmation: &OnchainConfirmation,
+) -> WithdrawalOutput {
+    let withdrawal = &info.contract_withdrawal;
+    WithdrawalOutput {
+        recipient: withdrawal.recipient.to_hex(),
+        token_index: withdrawal.token_index,
+        amount: withdrawal.amount.to_string(),
+        nullifier: display_id(withdrawal.nullifier),
+        status: info.status.to_string(),
+        onchain: confirmation.into(),
+        tag: tag.cloned(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryEntryOutput {
+    Deposit {
+        token_type: String,
+        token_address: String,
+        token_id: String,
+        token_index: Option<u32>,
+        amount: String,
+        is_rejected: bool,
+        timestamp: Option<u64>,
+        block_number: Option<u32>,
+    },
+    Receive {
+        from: String,
+        /// Name of `from` in the address book
+        #[serde(skip_serializing_if = "Option::is_none")]
+        from_label: Option<String>,
+        token_index: u32,
+        amount: String,
+        is_rejected: bool,
+        is_expired: bool,
+        timestamp: Option<u64>,
+        block_number: Option<u32>,
+    },
+    Send {
+        transfers: Vec<TransferOutput>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        fee: Option<FeeOutput>,
+        is_rejected: bool,
+        claim_deadline_block: Option<u32>,
+        timestamp: Option<u64>,
+        block_number: Option<u32>,
+    },
+}
+
+/// A transfer of a sent tx. `recipient` is a pubkey for transfers and an L1
+/// address for withdrawals.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TransferOutput {
+    pub is_withdrawal: bool,
+    pub recipient: String,
+    /// Name of the recipient in the address book
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_label: Option<String>,
+    pub token_index: u32,
+    pub amount: String,
+}
+
+/// Block builder fee of a sent tx, with its own status
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeeOutput {
+    pub block_builder_url: String,
+    #[serde(flatten)]
+    pub transfer: TransferOutput,
+    pub status: FeeStatus,
+}
+
+impl From<&GenericTransfer> for TransferOutput {
+    fn from(transfer: &GenericTransfer) -> Self {
+        match transfer {
+            GenericTransfer::Transfer {
+                recipient,
+                token_index,
+                amount,
+            } => TransferOutput {
+                is_withdrawal: false,
+                recipient: recipient.to_hex(),
+                recipient_label: None,
+                token_index: *token_index,
+                amount: amount.to_string(),
+            },
+            GenericTransfer::Withdrawal {
+                recipient,
+                token_index,
+                amount,
+            } => TransferOutput {
+                is_withdrawal: true,
+                recipient: recipient.to_hex(),
+                recipient_label: None,
+                token_index: *token_index,
+                amount: amount.to_string(),
+            },
+        }
+    }
+}
+
+impl From<&HistoryEntry> for HistoryEntryOutput {
+    fn from(entry: &HistoryEntry) -> Self {
+        match entry {
+            HistoryEntry::Deposit {
+                token_type,
+                token_address,
+                token_id,
+                token_index,
+                amount,
+                is_rejected,
+                timestamp,
+                block_number,
+            } => HistoryEntryOutput::Deposit {
+                token_type: format!("{:?}", token_type),
+                token_address: token_address.to_hex(),
+                token_id: token_id.to_string(),
+                token_index: *token_index,
+                amount: amount.to_string(),
+                is_rejected: *is_rejected,
+                timestamp: *timestamp,
+                block_number: *block_number,
+            },
+            HistoryEntry::Receive {
+                amount,
+                token_index,
+                from,
+                is_rejected,
+                is_expired,
+                timestamp,
+                block_number,
+            } => HistoryEntryOutput::Receive {
+                from: from.to_hex(),
+                from_label: None,
+                token_index: *token_index,
+                amount: amount.to_string(),
+                is_rejected: *is_rejected,
+                is_expired: *is_expired,
+                timestamp: *timestamp,
+                block_number: *block_number,
+            },
+            HistoryEntry::Send {
+                transfers,
+                fee,
+                is_rejected,
+                claim_deadline_block,
+                timestamp,
+                block_number,
+            } => HistoryEntryOutput::Send {
+                transfers: transfers.iter().map(TransferOutput::from).collect(),
+                fee: fee.as_ref().map(|fee| FeeOutput {
+                    block_builder_url: fee.block_builder_url.clone(),
+                    transfer: TransferOutput::from(&fee.transfer),
+                    status: fee.status,
+                }),
+                is_rejected: *is_rejected,
+                claim_deadline_block: *claim_deadline_block,
+                timestamp: *timestamp,
+                block_number: *block_number,
+            },
+        }
+    }
+}
+
+/// Output of `entry`, with the names of its counterparties in `book`
+pub fn history_output(entry: &HistoryEntry, book: &AddressBook) -> HistoryEntryOutput {
+    let mut output = HistoryEntryOutput::from(entry);
+    match (&mut output, entry) {
+        (HistoryEntryOutput::Receive { from_label, .. }, HistoryEntry::Receive { from, .. }) => {
+            *from_label = book.label(*from);
+        }
+        (
+            HistoryEntryOutput::Send { transfers, .. },
+            HistoryEntry::Send {
+                transfers: sent, ..
+            },
+        ) => {
+            for (output, transfer) in transfers.iter_mut().zip(sent) {
+                if let GenericTransfer::Transfer { recipient, .. } = transfer {
+                    output.recipient_label = book.label(*recipient);
+                }
+            }
+        }
+        _ => {}
+    }
+    output
+}
+
+/// Names in `book` of the counterparties of `entry`, for the text output
+pub fn history_labels(entry: &HistoryEntry, book: &AddressBook) -> Vec<String> {
+    match entry {
+        HistoryEntry::Receive { from, .. } => book.label(*from).into_iter().collect(),
+        HistoryEntry::Send { transfers, .. } => transfers
+            .iter()
+            .filter_map(|transfer| match transfer {
+                GenericTransfer::Transfer { recipient, .. } => book.label(*recipient),
+                GenericTransfer::Withdrawal { .. } => None,
+            })
+            .collect(),
+        HistoryEntry::Deposit { .. } => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_client_sdk::client::{
+        balance_view::{NftHolding, OwnedNft, TokenBalance},
+        history::FeeLine,
+    };
+    use intmax2_interfaces::data::deposit_data::TokenType;
+    use intmax2_zkp::ethereum_types::{address::Address, u256::U256};
+
+    use super::*;
+
+    #[test]
+    fn test_balance_output() {
+        let view = BalanceView {
+            fungible: vec![TokenBalance {
+                token_index: 3,
+                token_type: TokenType::ERC20,
+                token_address: Address::default(),
+                token_id: U256::default(),
+                amount: U256::from(100u128),
+                is_insufficient: false,
+            }],
+            nfts: vec![NftHolding {
+                token_type: TokenType::ERC721,
+                token_address: Address::default(),
+                tokens: vec![OwnedNft {
+                    token_index: 1,
+                    token_id: U256::from(7u128),
+                    amount: U256::from(1u128),
+                }],
+            }],
+        };
+        let json = serde_json::to_value(balance_output(&view)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"token_index": 1, "amount": "1"},
+                {"token_index": 3, "amount": "100"},
+            ])
+        );
+
+        // an empty account, or a failed sync, still prints an array
+        let json = serde_json::to_string(&balance_output(&BalanceView::default())).unwrap();
+        assert_eq!(json, "[]");
+    }
+
+    #[test]
+    fn test_history_output() {
+        let entry = HistoryEntry::Send {
+            transfers: vec![GenericTransfer::Withdrawal {
+                recipient: Address::default(),
+                token_index: 0,
+                amount: U256::from(5u128),
+            }],
+            fee: Some(FeeLine {
+                block_builder_url: "http://builder.example".to_string(),
+                transfer: GenericTransfer::Transfer {
+                    recipient: U256::from(9u128),
+                    token_index: 0,
+                    amount: U256::from(1u128),
+                },
+                status: FeeStatus::Unsettled,
+            }),
+            is_rejected: false,
+            claim_deadline_block: None,
+            timestamp: Some(1700000000),
+            block_number: Some(12),
+        };
+        let json = serde_json::to_value(HistoryEntryOutput::from(&entry)).unwrap();
+        assert_eq!(json["type"], "send");
+        assert_eq!(json["block_number"], 12);
+        assert_eq!(json["transfers"][0]["is_withdrawal"], true);
+        assert_eq!(json["transfers"][0]["amount"], "5");
+        assert!(json["transfers"][0].get("recipient_label").is_none());
+        // the fee is a line of its own, not one of the transfers
+        assert_eq!(json["transfers"].as_array().unwrap().len(), 1);
+        assert_eq!(json["fee"]["amount"], "1");
+        assert_eq!(json["fee"]["status"], "unsettled");
+    }
+
+    #[test]
+    fn test_history_labels() {
+        let alice = U256::from(7u128);
+        let mut book = AddressBook::default();
+        book.add("alice", &alice.to_hex()).unwrap();
+        let entry = HistoryEntry::Send {
+            transfers: vec![
+                GenericTransfer::Transfer {
+                    recipient: alice,
+                    token_index: 0,
+                    amount: U256::from(5u128),
+                },
+                GenericTransfer::Transfer {
+                    recipient: U256::from(8u128),
+                    token_index: 0,
+                    amount: U256::from(5u128),
+                },
+            ],
+            fee: None,
+            is_rejected: false,
+            claim_deadline_block: None,
+            timestamp: None,
+            block_number: None,
+        };
+        let json = serde_json::to_value(history_output(&entry, &book)).unwrap();
+        assert_eq!(json["transfers"][0]["recipient_label"], "alice");
+        assert!(json["transfers"][1].get("recipient_label").is_none());
+        assert_eq!(history_labels(&entry, &book), vec!["alice".to_string()]);
+
+        let entry = HistoryEntry::Receive {
+            amount: U256::from(5u128),
+            token_index: 0,
+            from: alice,
+            is_rejected: false,
+            is_expired: false,
+            timestamp: None,
+            block_number: None,
+        };
+        let json = serde_json::to_value(history_output(&entry, &book)).unwrap();
+        assert_eq!(json["from_label"], "alice");
+    }
+}
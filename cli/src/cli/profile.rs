@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{error::CliError, key::read_key_file, utils::local_dir};
+
+pub const DEFAULT_PROFILE: &str = "default";
+const KEYRING_SERVICE: &str = "intmax2-cli";
+
+/// Where the key of a profile is kept
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "storage", rename_all = "snake_case")]
+pub enum KeyStorage {
+    /// OS keychain, under the service `intmax2-cli` and the profile name
+    Keyring,
+    /// Encrypted key file
+    File { path: PathBuf },
+}
+
+/// Settings of one account: the environment of the commands and its key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub pubkey: String,
+    pub key: KeyStorage,
+    /// Block builders, by URL or advertised address, preferred when chosen from
+    /// the indexer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_builders: Vec<String>,
+    /// Block builders, by URL or advertised address, never chosen from the indexer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_builders: Vec<String>,
+    /// Variables of the `.env` file, e.g. `STORE_VAULT_SERVER_BASE_URL`
+    pub env: BTreeMap<String, String>,
+}
+
+/// `~/.intmax2/config.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub default_profile: Option<String>,
+    /// Settings of the commands, keyed by variable name in lower case. The
+    /// environment, `.env` and the active profile take precedence.
+    #[serde(default, skip_serializing_if = "toml::Table::is_empty")]
+    pub settings: toml::Table,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    pub fn path_in(dir: &Path) -> PathBuf {
+        dir.join("config.toml")
+    }
+
+    pub fn load() -> Result<Self, CliError> {
+        Self::load_in(&local_dir()?)
+    }
+
+    /// The config in `dir`, or an empty one if there is none
+    pub fn load_in(dir: &Path) -> Result<Self, CliError> {
+        let path = Self::path_in(dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = fs::read_to_string(&path)
+            .map_err(|e| CliError::ProfileError(format!("Failed to read {:?}: {}", path, e)))?;
+        toml::from_str(&data)
+            .map_err(|e| CliError::ProfileError(format!("Failed to parse {:?}: {}", path, e)))
+    }
+
+    pub fn save_in(&self, dir: &Path) -> Result<(), CliError> {
+        fs::create_dir_all(dir)
+            .map_err(|e| CliError::ProfileError(format!("Failed to create {:?}: {}", dir, e)))?;
+        let path = Self::path_in(dir);
+        let data = toml::to_string_pretty(self)
+            .map_err(|e| CliError::ProfileError(format!("Failed to serialize config: {}", e)))?;
+        fs::write(&path, data)
+            .map_err(|e| CliError::ProfileError(format!("Failed to write {:?}: {}", path, e)))
+    }
+
+    /// Name of the profile in use: `INTMAX_PROFILE` (set by `--profile`), else the
+    /// default profile
+    pub fn active_profile_name(&self) -> String {
+        std::env::var("INTMAX_PROFILE")
+            .ok()
+            .or_else(|| self.default_profile.clone())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    pub fn active_profile(&self) -> Option<(String, &Profile)> {
+        let name = self.active_profile_name();
+        self.profiles.get(&name).map(|profile| (name, profile))
+    }
+}
+
+/// Set the variables of the active profile that are not already set, so that the
+/// environment and `.env` take precedence over the profile
+pub fn apply_profile_env() -> Result<(), CliError> {
+    let config = Config::load()?;
+    if let Some((_, profile)) = config.active_profile() {
+        for (name, value) in profile.env.iter() {
+            if std::env::var_os(name).is_none() {
+                std::env::set_var(name, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn keyring_entry(profile: &str) -> Result<keyring::Entry, CliError> {
+    keyring::Entry::new(KEYRING_SERVICE, profile)
+        .map_err(|e| CliError::KeyError(format!("OS keychain is not available: {}", e)))
+}
+
+/// Store the private key of `profile` in the OS keychain
+pub fn set_key_in_keyring(profile: &str, key: KeySet) -> Result<(), CliError> {
+    keyring_entry(profile)?
+        .set_password(&key.privkey.to_hex())
+        .map_err(|e| CliError::KeyError(format!("Failed to store the key in the keychain: {}", e)))
+}
+
+pub fn get_key_from_keyring(profile: &str) -> Result<KeySet, CliError> {
+    let privkey = keyring_entry(profile)?.get_password().map_err(|e| {
+        CliError::KeyError(format!(
+            "Failed to read the key of profile {} from the keychain: {}",
+            profile, e
+        ))
+    })?;
+    let privkey = U256::from_hex(&privkey).map_err(|_| {
+        CliError::KeyError(format!(
+            "Invalid key of profile {} in the keychain",
+            profile
+        ))
+    })?;
+    Ok(KeySet::new(privkey))
+}
+
+/// Key of the active profile, used when a command is given no key
+pub fn profile_key() -> Result<KeySet, CliError> {
+    profile_key_in(&local_dir()?)
+}
+
+pub fn profile_key_in(dir: &Path) -> Result<KeySet, CliError> {
+    let config = Config::load_in(dir)?;
+    let (name, profile) = config.active_profile().ok_or(CliError::KeyError(
+        "--private-key or --key-file is required, or run `init` to create a profile".to_string(),
+    ))?;
+    let key = match &profile.key {
+        KeyStorage::Keyring => get_key_from_keyring(&name)?,
+        KeyStorage::File { path } => read_key_file(path, false)?,
+    };
+    if key.pubkey.to_hex() != profile.pubkey {
+        return Err(CliError::KeyError(format!(
+            "Key of profile {} does not match its pubkey {}",
+            name, profile.pubkey
+        )));
+    }
+    Ok(key)
+}
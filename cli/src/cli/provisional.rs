@@ -0,0 +1,50 @@
+use std::{fs, path::PathBuf};
+
+use intmax2_client_sdk::client::provisional::{ProvisionalDelta, ProvisionalStore};
+use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
+
+/// Provisional debits kept as `<pubkey>.json`, so that `balance --optimistic`
+/// sees the txs finalized by earlier commands
+#[derive(Debug, Clone)]
+pub struct FileProvisionalStore {
+    dir: PathBuf,
+}
+
+impl FileProvisionalStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, pubkey: U256) -> PathBuf {
+        self.dir.join(format!("{}.json", pubkey.to_hex()))
+    }
+}
+
+impl ProvisionalStore for FileProvisionalStore {
+    fn load(&self, pubkey: U256) -> Vec<ProvisionalDelta> {
+        let path = self.path(pubkey);
+        let Ok(data) = fs::read_to_string(&path) else {
+            return Vec::new();
+        };
+        serde_json::from_str(&data).unwrap_or_else(|e| {
+            log::warn!("Ignoring unreadable provisional deltas {:?}: {}", path, e);
+            Vec::new()
+        })
+    }
+
+    fn save(&self, pubkey: U256, deltas: &[ProvisionalDelta]) {
+        let path = self.path(pubkey);
+        let result = if deltas.is_empty() {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        } else {
+            let data = serde_json::to_string_pretty(deltas).unwrap();
+            fs::create_dir_all(&self.dir).and_then(|_| fs::write(&path, data))
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to save provisional deltas {:?}: {}", path, e);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::{fs, path::Path};
+
+use intmax2_client_sdk::client::{
+    error::ClientError,
+    receipt::{verify_receive_receipt, ReceiveReceipt, RollupReference},
+};
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{bytes32::Bytes32, u256::U256},
+};
+
+use super::{client::get_client, error::CliError, i18n::tr_with};
+
+/// Write the receipt of the received transfer `transfer_uuid` to `out`, or to
+/// stdout
+pub async fn receipt_export(
+    key: KeySet,
+    transfer_uuid: &str,
+    out: Option<&Path>,
+) -> Result<(), CliError> {
+    let client = get_client()?;
+    let receipt = client.export_receive_receipt(key, transfer_uuid).await?;
+    let json = serde_json::to_string_pretty(&receipt).unwrap();
+    match out {
+        Some(path) => {
+            fs::write(path, json).map_err(|e| {
+                CliError::ReceiptError(format!("Failed to write {:?}: {}", path, e))
+            })?;
+            println!(
+                "{}",
+                tr_with("msg.receipt_written", &[("path", &format!("{:?}", path))])
+            );
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// Check the receipt in `file` against `expected_recipient`. The block's tx tree
+/// root is `tx_tree_root` if given, otherwise the one known to the validity
+/// prover, which also tells whether the block is valid and the sender signed.
+/// Returns false if the receipt does not hold.
+pub async fn receipt_verify(
+    file: &Path,
+    expected_recipient: U256,
+    tx_tree_root: Option<Bytes32>,
+) -> Result<bool, CliError> {
+    let json = fs::read_to_string(file)
+        .map_err(|e| CliError::ReceiptError(format!("Failed to read {:?}: {}", file, e)))?;
+    let receipt: ReceiveReceipt = serde_json::from_str(&json)
+        .map_err(|e| CliError::ReceiptError(format!("Failed to parse {:?}: {}", file, e)))?;
+    let client = get_client()?;
+    let mut reference =
+        RollupReference::from_validity_prover(&client.validity_prover, receipt.block_number)
+            .await
+            .map_err(ClientError::from)?;
+    if let Some(tx_tree_root) = tx_tree_root {
+        reference.tx_tree_root = tx_tree_root;
+    }
+    match verify_receive_receipt(&receipt, expected_recipient, &reference) {
+        Ok(()) => {
+            println!(
+                "{}",
+                tr_with(
+                    "msg.receipt_valid",
+                    &[
+                        ("token_index", &receipt.transfer.token_index),
+                        ("amount", &receipt.transfer.amount),
+                        ("block", &receipt.block_number)
+                    ]
+                )
+            );
+            Ok(true)
+        }
+        Err(e) => {
+            println!("{}", tr_with("msg.receipt_invalid", &[("reason", &e)]));
+            Ok(false)
+        }
+    }
+}
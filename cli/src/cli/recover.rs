@@ -0,0 +1,509 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use colored::Colorize as _;
+use intmax2_client_sdk::client::client::SyncStatus;
+use intmax2_interfaces::{
+    api::{
+        output_stream::types::ProgressStage,
+        store_vault_server::interface::StoreVaultClientInterface as _,
+        validity_prover::interface::ValidityProverClientInterface as _,
+    },
+    data::user_data::UserData,
+};
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+    utils::poseidon_hash_out::PoseidonHashOut,
+};
+use rand::Rng as _;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+    client::get_client,
+    error::CliError,
+    i18n::tr_with,
+    output::{print_notice, print_warning},
+    stream::{emit_progress, set_stream_result},
+    utils::{load_env, local_dir},
+};
+
+const DEFAULT_RETENTION_DAYS: u64 = 7;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletedAction {
+    pub uuid: String,
+    pub block_number: u32,
+}
+
+/// Balance proof the rebuild has reached, which is saved in the store vault
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofCheckpoint {
+    pub block_number: u32,
+    pub private_commitment: PoseidonHashOut,
+}
+
+/// Progress of rebuilding the user data from scratch, persisted under
+/// `~/.intmax2/rebuild` so that an interrupted rebuild can be resumed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebuildSession {
+    pub id: String,
+    pub pubkey: U256,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Actions completed so far, in the order they were applied
+    pub completed_actions: Vec<CompletedAction>,
+    pub checkpoint: Option<ProofCheckpoint>,
+    /// Encrypted user data replaced when the session started
+    pub prev_user_data: Option<Vec<u8>>,
+}
+
+impl RebuildSession {
+    fn new(pubkey: U256, prev_user_data: Option<Vec<u8>>) -> Self {
+        let id: [u8; 8] = rand::thread_rng().gen();
+        Self {
+            id: hex::encode(id),
+            pubkey,
+            started_at: Utc::now(),
+            completed_at: None,
+            completed_actions: Vec::new(),
+            checkpoint: None,
+            prev_user_data,
+        }
+    }
+
+    fn path(dir: &Path, id: &str) -> PathBuf {
+        dir.join(format!("{}.json", id))
+    }
+
+    fn load(dir: &Path, id: &str) -> Result<Self, CliError> {
+        let content = fs::read_to_string(Self::path(dir, id)).map_err(|e| {
+            CliError::RebuildSessionError(format!("Failed to read session {}: {}", id, e))
+        })?;
+        serde_json::from_str(&content).map_err(|e| {
+            CliError::RebuildSessionError(format!("Failed to parse session {}: {}", id, e))
+        })
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), CliError> {
+        fs::create_dir_all(dir).map_err(|e| {
+            CliError::RebuildSessionError(format!("Failed to create {:?}: {}", dir, e))
+        })?;
+        // write to a temporary file first so that an interruption never leaves a
+        // truncated session behind
+        let path = Self::path(dir, &self.id);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(self).unwrap())
+            .and_then(|_| fs::rename(&tmp_path, &path))
+            .map_err(|e| {
+                CliError::RebuildSessionError(format!("Failed to save session {}: {}", self.id, e))
+            })
+    }
+
+    // record the actions applied since the last call, and the new checkpoint
+    fn record_progress(&mut self, user_data: &UserData) {
+        let recorded: HashSet<String> = self
+            .completed_actions
+            .iter()
+            .map(|action| action.uuid.clone())
+            .collect();
+        let new_uuids = user_data
+            .processed_deposit_uuids
+            .iter()
+            .chain(user_data.processed_transfer_uuids.iter())
+            .chain(user_data.processed_tx_uuids.iter())
+            .filter(|uuid| !recorded.contains(*uuid))
+            .cloned()
+            .collect::<Vec<_>>();
+        for uuid in new_uuids {
+            self.completed_actions.push(CompletedAction {
+                uuid,
+                block_number: user_data.block_number,
+            });
+        }
+        self.checkpoint = Some(ProofCheckpoint {
+            block_number: user_data.block_number,
+            private_commitment: user_data.private_commitment(),
+        });
+    }
+}
+
+fn session_dir() -> Result<PathBuf, CliError> {
+    Ok(local_dir()?.join("rebuild"))
+}
+
+fn list_sessions(dir: &Path) -> Vec<RebuildSession> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "json"))
+        .filter_map(|entry| {
+            let content = fs::read_to_string(entry.path()).ok()?;
+            serde_json::from_str(&content).ok()
+        })
+        .collect()
+}
+
+fn backup_dir() -> Result<PathBuf, CliError> {
+    Ok(local_dir()?.join("user_data_backups"))
+}
+
+// backups of `pubkey`, oldest first
+fn list_backups(dir: &Path, pubkey: U256) -> Vec<PathBuf> {
+    let prefix = format!("{}-", pubkey.to_hex());
+    let mut backups = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .map_or(false, |name| {
+                            name.starts_with(&prefix) && name.ends_with(".bin")
+                        })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    // the names end with a zero-padded timestamp
+    backups.sort();
+    backups
+}
+
+/// Keep the user data a rebuild replaces as `<pubkey>-<unix time>.bin` in `dir`,
+/// unless the latest backup of the account already holds it. Unlike the sessions,
+/// the backups are never removed by the cleanup.
+fn backup_user_data(dir: &Path, pubkey: U256, data: &[u8]) -> Result<PathBuf, CliError> {
+    if let Some(latest) = list_backups(dir, pubkey).pop() {
+        if fs::read(&latest).map_or(false, |latest_data| latest_data == data) {
+            return Ok(latest);
+        }
+    }
+    let path = dir.join(format!(
+        "{}-{:020}.bin",
+        pubkey.to_hex(),
+        Utc::now().timestamp_micros()
+    ));
+    let tmp_path = path.with_extension("bin.tmp");
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&tmp_path, data))
+        .and_then(|_| fs::rename(&tmp_path, &path))
+        .map_err(|e| {
+            CliError::RebuildSessionError(format!("Failed to back up the user data: {}", e))
+        })?;
+    Ok(path)
+}
+
+/// Remove sessions that completed more than `retention` ago
+fn cleanup_sessions(dir: &Path, retention: Duration) {
+    let now = Utc::now();
+    for session in list_sessions(dir) {
+        if let Some(completed_at) = session.completed_at {
+            if now - completed_at > retention {
+                let _ = fs::remove_file(RebuildSession::path(dir, &session.id));
+            }
+        }
+    }
+}
+
+/// Rebuild the user data and balance proof from the first action. Progress is
+/// persisted after every action, and an unfinished session is resumed with `resume`
+/// or discarded with `restart`.
+pub async fn recover(key: KeySet, resume: Option<String>, restart: bool) -> Result<(), CliError> {
+    let env = load_env()?;
+    let dir = session_dir()?;
+    let retention_days = env
+        .rebuild_session_retention_days
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    cleanup_sessions(&dir, Duration::days(retention_days as i64));
+
+    let client = get_client()?;
+    let unfinished = list_sessions(&dir)
+        .into_iter()
+        .filter(|session| session.pubkey == key.pubkey && session.completed_at.is_none())
+        .collect::<Vec<_>>();
+
+    let mut session = match resume {
+        Some(id) => {
+            let session = RebuildSession::load(&dir, &id)?;
+            if session.pubkey != key.pubkey {
+                return Err(CliError::RebuildSessionError(format!(
+                    "Session {} belongs to another account",
+                    id
+                )));
+            }
+            if session.completed_at.is_some() {
+                return Err(CliError::RebuildSessionError(format!(
+                    "Session {} is already completed",
+                    id
+                )));
+            }
+            let user_data = client.get_user_data(key).await?;
+            let latest_block_number = client.validity_prover.get_block_number().await?;
+            verify_not_diverged(&session, &user_data, latest_block_number)?;
+            log::info!(
+                "Resuming rebuild session {} after {} actions",
+                session.id,
+                session.completed_actions.len()
+            );
+            session
+        }
+        None => {
+            if !unfinished.is_empty() && !restart {
+                for session in unfinished.iter() {
+                    print_notice(tr_with(
+                        "msg.rebuild_unfinished",
+                        &[
+                            ("id", &session.id),
+                            ("started_at", &session.started_at),
+                            ("actions", &session.completed_actions.len()),
+                        ],
+                    ));
+                }
+                print_warning(
+                    "Use --resume <id> to continue it or --restart to start over.".yellow(),
+                );
+                let ids = unfinished.iter().map(|s| &s.id).collect::<Vec<_>>();
+                set_stream_result(&json!({ "status": "unfinished", "sessionIds": ids }));
+                return Ok(());
+            }
+            for session in unfinished.iter() {
+                log::info!("Discarding rebuild session {}", session.id);
+                let _ = fs::remove_file(RebuildSession::path(&dir, &session.id));
+            }
+            // the oldest backup of the user data is kept across restarts
+            let prev_user_data = match unfinished.into_iter().find_map(|s| s.prev_user_data) {
+                Some(prev_user_data) => Some(prev_user_data),
+                None => client.store_vault_server.get_user_data(key.pubkey).await?,
+            };
+            // written before the reset, so that the user data is never only in
+            // the store vault being overwritten
+            if let Some(prev_user_data) = &prev_user_data {
+                let path = backup_user_data(&backup_dir()?, key.pubkey, prev_user_data)?;
+                print_notice(tr_with(
+                    "msg.user_data_backed_up",
+                    &[("path", &format!("{:?}", path))],
+                ));
+            }
+            client.reset_user_data(key).await?;
+            let session = RebuildSession::new(key.pubkey, prev_user_data);
+            session.save(&dir)?;
+            print_notice(tr_with("msg.rebuild_started", &[("id", &session.id)]));
+            session
+        }
+    };
+
+    loop {
+        let status = client.sync_single(key).await?;
+        let user_data = client.get_user_data(key).await?;
+        session.record_progress(&user_data);
+        emit_progress(
+            ProgressStage::Rebuild,
+            session.completed_actions.len() as u32,
+            0,
+        );
+        match status {
+            SyncStatus::Continue => {
+                session.save(&dir)?;
+                log::info!(
+                    "Rebuild progress: {} actions, block {}",
+                    session.completed_actions.len(),
+                    user_data.block_number
+                );
+            }
+            SyncStatus::Complete => {
+                session.completed_at = Some(Utc::now());
+                session.save(&dir)?;
+                print_notice(tr_with(
+                    "msg.rebuild_completed",
+                    &[("actions", &session.completed_actions.len())],
+                ));
+                set_stream_result(&rebuild_result("completed", &session));
+                return Ok(());
+            }
+            SyncStatus::Pending => {
+                session.save(&dir)?;
+                print_warning(
+                    format!(
+                        "There are pending actions. Please resume later with --resume {}",
+                        session.id
+                    )
+                    .red(),
+                );
+                set_stream_result(&rebuild_result("pending", &session));
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Put back the user data that a rebuild replaced, from `backup` or else from the
+/// latest backup of the account, and discard its unfinished rebuild sessions
+pub async fn restore_user_data(key: KeySet, backup: Option<PathBuf>) -> Result<(), CliError> {
+    let path = match backup {
+        Some(path) => path,
+        None => list_backups(&backup_dir()?, key.pubkey)
+            .pop()
+            .ok_or_else(|| {
+                CliError::RebuildSessionError(
+                    "No backup of the user data of this account".to_string(),
+                )
+            })?,
+    };
+    let data = fs::read(&path)
+        .map_err(|e| CliError::RebuildSessionError(format!("Failed to read {:?}: {}", path, e)))?;
+    let user_data = UserData::decrypt(&data, key).map_err(|e| {
+        CliError::RebuildSessionError(format!("{:?} is not user data of this key: {}", path, e))
+    })?;
+    if user_data.pubkey != key.pubkey {
+        return Err(CliError::RebuildSessionError(format!(
+            "{:?} is the user data of another account",
+            path
+        )));
+    }
+
+    let client = get_client()?;
+    client
+        .store_vault_server
+        .save_user_data(key.pubkey, data)
+        .await?;
+    let dir = session_dir()?;
+    for session in list_sessions(&dir)
+        .into_iter()
+        .filter(|session| session.pubkey == key.pubkey && session.completed_at.is_none())
+    {
+        log::info!("Discarding rebuild session {}", session.id);
+        let _ = fs::remove_file(RebuildSession::path(&dir, &session.id));
+    }
+    print_notice(tr_with(
+        "msg.user_data_restored",
+        &[
+            ("block", &user_data.block_number),
+            ("path", &format!("{:?}", path)),
+        ],
+    ));
+    Ok(())
+}
+
+// result line of `--output ndjson-stream`
+fn rebuild_result(status: &str, session: &RebuildSession) -> serde_json::Value {
+    json!({
+        "status": status,
+        "sessionId": session.id,
+        "actions": session.completed_actions.len(),
+    })
+}
+
+// Check that the user data in the store vault is still at the session's checkpoint,
+// and that the chain has not gone back past the blocks of the completed actions.
+fn verify_not_diverged(
+    session: &RebuildSession,
+    user_data: &UserData,
+    latest_block_number: u32,
+) -> Result<(), CliError> {
+    if let Some(checkpoint) = &session.checkpoint {
+        if user_data.block_number != checkpoint.block_number
+            || user_data.private_commitment() != checkpoint.private_commitment
+        {
+            return Err(CliError::RebuildSessionError(format!(
+                "User data changed since the session was saved (block {} instead of {}). Use --restart",
+                user_data.block_number, checkpoint.block_number
+            )));
+        }
+    }
+    if let Some(action) = session
+        .completed_actions
+        .iter()
+        .find(|action| action.block_number > latest_block_number)
+    {
+        return Err(CliError::RebuildSessionError(format!(
+            "Action {} refers to block {}, but the latest block is {}. Use --restart",
+            action.uuid, action.block_number, latest_block_number
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::utils::test_dir;
+
+    use super::*;
+
+    #[test]
+    fn test_session_save_and_cleanup() {
+        let dir = test_dir("rebuild-cleanup");
+        let key = KeySet::rand(&mut rand::thread_rng());
+
+        let mut unfinished = RebuildSession::new(key.pubkey, None);
+        unfinished.completed_actions.push(CompletedAction {
+            uuid: "a".to_string(),
+            block_number: 1,
+        });
+        unfinished.save(&dir).unwrap();
+        let loaded = RebuildSession::load(&dir, &unfinished.id).unwrap();
+        assert_eq!(loaded.completed_actions, unfinished.completed_actions);
+
+        let mut completed = RebuildSession::new(key.pubkey, None);
+        completed.completed_at = Some(Utc::now() - Duration::days(8));
+        completed.save(&dir).unwrap();
+
+        cleanup_sessions(&dir, Duration::days(7));
+        let ids = list_sessions(&dir)
+            .into_iter()
+            .map(|session| session.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![unfinished.id]);
+    }
+
+    #[test]
+    fn test_backup_user_data() {
+        let dir = test_dir("rebuild-backup");
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let other = KeySet::rand(&mut rand::thread_rng());
+
+        let first = backup_user_data(&dir, key.pubkey, b"first").unwrap();
+        // the same data is not backed up twice
+        assert_eq!(backup_user_data(&dir, key.pubkey, b"first").unwrap(), first);
+        let second = backup_user_data(&dir, key.pubkey, b"second").unwrap();
+        backup_user_data(&dir, other.pubkey, b"other").unwrap();
+        assert_eq!(list_backups(&dir, key.pubkey), vec![first, second.clone()]);
+
+        // the session cleanup leaves the backups alone
+        cleanup_sessions(&dir, Duration::zero());
+        assert_eq!(list_backups(&dir, key.pubkey).pop(), Some(second));
+        assert_eq!(list_backups(&dir, other.pubkey).len(), 1);
+    }
+
+    #[test]
+    fn test_verify_not_diverged() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let mut user_data = UserData::new(key.pubkey);
+        user_data.block_number = 3;
+        user_data.processed_deposit_uuids.push("a".to_string());
+
+        let mut session = RebuildSession::new(key.pubkey, None);
+        session.record_progress(&user_data);
+        assert_eq!(session.completed_actions.len(), 1);
+        verify_not_diverged(&session, &user_data, 3).unwrap();
+
+        // the chain is behind the completed actions
+        assert!(verify_not_diverged(&session, &user_data, 2).is_err());
+
+        // the user data moved on without the session
+        user_data.block_number = 4;
+        assert!(verify_not_diverged(&session, &user_data, 4).is_err());
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use notify::{RecursiveMode, Watcher as _};
+
+use crate::Env;
+
+use super::{
+    config_file::env_vars,
+    error::CliError,
+    profile::{Config, KeyStorage, Profile},
+    utils::local_dir,
+};
+
+// editors write a file in several steps
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Variables that can change while a command runs: urls, bearer tokens, poll
+/// intervals and webhook secrets. The others, e.g. the chain id or the contract
+/// addresses, are only read at startup.
+fn is_reloadable(name: &str) -> bool {
+    ["_URL", "_URLS", "_TOKEN", "_INTERVAL", "_SECRET"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+}
+
+/// Part of the active profile the running command was started with
+#[derive(Debug, Clone, PartialEq)]
+struct AppliedProfile {
+    name: String,
+    pubkey: Option<String>,
+    key: Option<KeyStorage>,
+    // variables set from the profile; the others come from the environment or
+    // `.env`, which take precedence as in `apply_profile_env`
+    env: BTreeMap<String, String>,
+    // variables the profile set at startup and no longer has, which are still in
+    // the environment of the process
+    removed: BTreeSet<String>,
+}
+
+impl AppliedProfile {
+    fn from_config(config: &Config) -> Self {
+        let name = config.active_profile_name();
+        let profile = config.profiles.get(&name);
+        let env = profile
+            .map(|profile| {
+                profile
+                    .env
+                    .iter()
+                    .filter(|(var, value)| std::env::var(var).ok().as_ref() == Some(*value))
+                    .map(|(var, value)| (var.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            name,
+            pubkey: profile.map(|profile| profile.pubkey.clone()),
+            key: profile.map(|profile| profile.key.clone()),
+            env,
+            removed: BTreeSet::new(),
+        }
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.env.get(name) {
+            return Some(value.clone());
+        }
+        if self.removed.contains(name) {
+            return None;
+        }
+        env_vars().remove(name)
+    }
+
+    // the environment of the process and the config file with the profile applied
+    fn vars(&self) -> BTreeMap<String, String> {
+        env_vars()
+            .into_iter()
+            .filter(|(var, _)| !self.removed.contains(var))
+            .chain(self.env.clone())
+            .collect()
+    }
+}
+
+/// Result of a reload. Names only, the values may be secrets.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigChange {
+    pub set: BTreeMap<String, String>,
+    pub removed: Vec<String>,
+    /// Changes that need a restart, e.g. the key of the profile
+    pub rejected: Vec<String>,
+}
+
+impl ConfigChange {
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.removed.is_empty() && self.rejected.is_empty()
+    }
+
+    /// Whether a client built before the change uses an outdated url
+    pub fn changes_urls(&self) -> bool {
+        self.set
+            .keys()
+            .chain(self.removed.iter())
+            .any(|name| name.ends_with("_URL") || name.ends_with("_URLS"))
+    }
+}
+
+/// Changes from `applied` to the profile `name` of the new config. `is_overridden`
+/// tells whether a variable is set outside of the profile.
+fn diff(
+    applied: &AppliedProfile,
+    name: &str,
+    profile: Option<&Profile>,
+    is_overridden: impl Fn(&str) -> bool,
+) -> ConfigChange {
+    let mut change = ConfigChange::default();
+    let Some(profile) = profile.filter(|_| name == applied.name) else {
+        change.rejected.push("profile".to_string());
+        return change;
+    };
+    if applied.pubkey.as_ref() != Some(&profile.pubkey) {
+        change.rejected.push("pubkey".to_string());
+    }
+    if applied.key.as_ref() != Some(&profile.key) {
+        change.rejected.push("key".to_string());
+    }
+    for (var, value) in profile.env.iter() {
+        if applied.env.get(var) == Some(value)
+            || (!applied.env.contains_key(var) && is_overridden(var))
+        {
+            continue;
+        }
+        if is_reloadable(var) {
+            change.set.insert(var.clone(), value.clone());
+        } else {
+            change.rejected.push(var.clone());
+        }
+    }
+    for var in applied.env.keys() {
+        if profile.env.contains_key(var) {
+            continue;
+        }
+        if is_reloadable(var) {
+            change.removed.push(var.clone());
+        } else {
+            change.rejected.push(var.clone());
+        }
+    }
+    change
+}
+
+/// Check that `vars` with `change` applied are still a valid config
+fn validate(vars: &BTreeMap<String, String>, change: &ConfigChange) -> Result<(), CliError> {
+    for (var, value) in change.set.iter() {
+        if var.ends_with("_URL") {
+            url::Url::parse(value)
+                .map_err(|e| CliError::ProfileError(format!("Invalid {}: {}", var, e)))?;
+        }
+    }
+    // a partial environment, e.g. of a command that does not need the whole
+    // config, is left for the command to report
+    if envy::from_iter::<_, Env>(vars.clone()).is_err() {
+        return Ok(());
+    }
+    let env = vars
+        .clone()
+        .into_iter()
+        .filter(|(var, _)| !change.removed.contains(var))
+        .filter(|(var, _)| !change.set.contains_key(var))
+        .chain(change.set.clone());
+    envy::from_iter::<_, Env>(env)
+        .map_err(|e| CliError::ProfileError(format!("Invalid config: {}", e)))?;
+    Ok(())
+}
+
+/// Applies the changes of `~/.intmax2/config.toml` to a long-running command, on
+/// write of the file or on SIGHUP. The environment of the process is left as it
+/// was at startup: the command reads the reloaded settings with `var` and `env`,
+/// and builds its client from `env` again when `take_client_outdated` says so.
+/// A request in flight completes with the client it started with.
+#[derive(Debug)]
+pub struct ConfigReloader {
+    dir: PathBuf,
+    applied: Arc<RwLock<AppliedProfile>>,
+    client_outdated: AtomicBool,
+}
+
+impl ConfigReloader {
+    pub fn new() -> Result<Self, CliError> {
+        Self::new_in(&local_dir()?)
+    }
+
+    /// Called after `apply_profile_env`, with the environment set from the config
+    /// in `dir`
+    pub fn new_in(dir: &Path) -> Result<Self, CliError> {
+        let config = Config::load_in(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            applied: Arc::new(RwLock::new(AppliedProfile::from_config(&config))),
+            client_outdated: AtomicBool::new(false),
+        })
+    }
+
+    /// Read the config again and apply its reloadable changes. An invalid config
+    /// is not applied at all.
+    pub fn reload(&self) -> Result<ConfigChange, CliError> {
+        let config = Config::load_in(&self.dir)?;
+        let mut applied = self.applied.write().unwrap();
+        let name = config.active_profile_name();
+        let change = diff(&applied, &name, config.profiles.get(&name), |var| {
+            !applied.removed.contains(var) && std::env::var_os(var).is_some()
+        });
+        validate(&applied.vars(), &change)?;
+        for (var, value) in change.set.iter() {
+            applied.removed.remove(var);
+            applied.env.insert(var.clone(), value.clone());
+        }
+        for var in change.removed.iter() {
+            applied.env.remove(var);
+            applied.removed.insert(var.clone());
+        }
+        if !change.set.is_empty() || !change.removed.is_empty() {
+            self.client_outdated.store(true, Ordering::SeqCst);
+        }
+        if !change.is_empty() {
+            log::info!(
+                "config reloaded: profile={} changed={:?} removed={:?} rejected={:?}",
+                applied.name,
+                change.set.keys().collect::<Vec<_>>(),
+                change.removed,
+                change.rejected
+            );
+        }
+        if !change.rejected.is_empty() {
+            log::warn!("not applied until restart: {}", change.rejected.join(", "));
+        }
+        Ok(change)
+    }
+
+    /// Value of `name` in the reloaded config, or in the environment if the
+    /// profile does not set it
+    pub fn var(&self, name: &str) -> Option<String> {
+        self.applied.read().unwrap().var(name)
+    }
+
+    /// Settings of the reloaded config
+    pub fn env(&self) -> Result<Env, CliError> {
+        let vars = self.applied.read().unwrap().vars();
+        Ok(envy::from_iter(vars)?)
+    }
+
+    /// Whether a setting changed since the last call, so that the client has to
+    /// be built again
+    pub fn take_client_outdated(&self) -> bool {
+        self.client_outdated.swap(false, Ordering::SeqCst)
+    }
+
+    /// Reload on changes of the config file and on SIGHUP, until the returned file
+    /// watcher is dropped
+    pub fn spawn(self: &Arc<Self>) -> Result<Option<notify::RecommendedWatcher>, CliError> {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        // the directory only exists once a profile was created
+        let file_watcher = if self.dir.exists() {
+            let path = Config::path_in(&self.dir);
+            let file_tx = tx.clone();
+            let mut file_watcher =
+                notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                    if let Ok(event) = event {
+                        if !event.kind.is_access() && event.paths.contains(&path) {
+                            let _ = file_tx.send(());
+                        }
+                    }
+                })
+                .map_err(|e| {
+                    CliError::UnexpectedError(format!("Failed to watch the config: {}", e))
+                })?;
+            file_watcher
+                .watch(&self.dir, RecursiveMode::NonRecursive)
+                .map_err(|e| {
+                    CliError::UnexpectedError(format!("Failed to watch {:?}: {}", self.dir, e))
+                })?;
+            Some(file_watcher)
+        } else {
+            None
+        };
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangup = signal(SignalKind::hangup()).map_err(|e| {
+                CliError::UnexpectedError(format!("Failed to listen for SIGHUP: {}", e))
+            })?;
+            tokio::spawn(async move {
+                while hangup.recv().await.is_some() {
+                    if tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            while rx.recv().await.is_some() {
+                tokio::time::sleep(RELOAD_DEBOUNCE).await;
+                while rx.try_recv().is_ok() {}
+                if let Err(e) = reloader.reload() {
+                    log::warn!("config not reloaded, keeping the previous one: {}", e);
+                }
+            }
+        });
+        Ok(file_watcher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use intmax2_client_sdk::external_api::validity_prover::ValidityProverClient;
+    use intmax2_interfaces::api::validity_prover::interface::ValidityProverClientInterface;
+
+    use super::*;
+
+    fn profile(key_path: &str, env: &[(&str, &str)]) -> Profile {
+        Profile {
+            pubkey: "0x01".to_string(),
+            key: KeyStorage::File {
+                path: PathBuf::from(key_path),
+            },
+            trusted_builders: Vec::new(),
+            blocked_builders: Vec::new(),
+            env: env
+                .iter()
+                .map(|(var, value)| (var.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn save(dir: &Path, profile: Profile) {
+        let config = Config {
+            default_profile: Some("reload-test".to_string()),
+            settings: Default::default(),
+            profiles: BTreeMap::from([("reload-test".to_string(), profile)]),
+        };
+        config.save_in(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_new_token_and_interval() {
+        let dir = std::env::temp_dir().join(format!("intmax2-reload-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut server = mockito::Server::new_async().await;
+        let old_token = server
+            .mock("GET", "/validity-prover/block-number")
+            .match_header("authorization", "Bearer old-token")
+            .with_body(r#"{"blockNumber":1}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let new_token = server
+            .mock("GET", "/validity-prover/block-number")
+            .match_header("authorization", "Bearer new-token")
+            .with_body(r#"{"blockNumber":2}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let token_var = "VALIDITY_PROVER_BEARER_TOKEN";
+        let process_token = std::env::var_os(token_var);
+        let validity_prover = |reloader: &ConfigReloader| {
+            ValidityProverClient::new(&server.url())
+                .with_bearer_token(&reloader.var(token_var).unwrap())
+        };
+
+        save(&dir, profile("key.json", &[]));
+        let reloader = ConfigReloader::new_in(&dir).unwrap();
+        save(
+            &dir,
+            profile(
+                "key.json",
+                &[(token_var, "old-token"), ("WATCHER_INTERVAL", "10")],
+            ),
+        );
+        reloader.reload().unwrap();
+        assert!(reloader.take_client_outdated());
+        assert_eq!(
+            validity_prover(&reloader).get_block_number().await.unwrap(),
+            1
+        );
+
+        // the token is rotated and the key moved while the command runs
+        save(
+            &dir,
+            profile(
+                "moved-key.json",
+                &[
+                    (token_var, "new-token"),
+                    ("WATCHER_INTERVAL", "2"),
+                    ("L1_CHAIN_ID", "1"),
+                ],
+            ),
+        );
+        let change = reloader.reload().unwrap();
+        assert_eq!(
+            change.set.keys().collect::<Vec<_>>(),
+            vec![token_var, "WATCHER_INTERVAL"]
+        );
+        assert_eq!(change.rejected, vec!["key", "L1_CHAIN_ID"]);
+        assert!(reloader.take_client_outdated());
+        assert_eq!(reloader.var("WATCHER_INTERVAL").unwrap(), "2");
+        assert_eq!(
+            reloader.var("L1_CHAIN_ID"),
+            std::env::var("L1_CHAIN_ID").ok()
+        );
+        assert_eq!(
+            validity_prover(&reloader).get_block_number().await.unwrap(),
+            2
+        );
+        old_token.assert_async().await;
+        new_token.assert_async().await;
+
+        // nothing new since, the rejected changes are still reported
+        let change = reloader.reload().unwrap();
+        assert!(change.set.is_empty() && change.removed.is_empty());
+        assert_eq!(change.rejected, vec!["key", "L1_CHAIN_ID"]);
+        assert!(!reloader.take_client_outdated());
+
+        // a variable dropped from the profile is gone
+        save(&dir, profile("moved-key.json", &[(token_var, "new-token")]));
+        let change = reloader.reload().unwrap();
+        assert_eq!(change.removed, vec!["WATCHER_INTERVAL"]);
+        assert_eq!(reloader.var("WATCHER_INTERVAL"), None);
+
+        // the environment of the process is never changed
+        assert_eq!(std::env::var_os(token_var), process_token);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_diff_keeps_overridden_variables() {
+        let applied = AppliedProfile {
+            name: "a".to_string(),
+            pubkey: Some("0x01".to_string()),
+            key: Some(KeyStorage::Keyring),
+            env: BTreeMap::from([("INDEXER_BASE_URL".to_string(), "http://old".to_string())]),
+            removed: BTreeSet::new(),
+        };
+        let mut new = profile("unused", &[("WEBHOOK_SECRET", "s")]);
+        new.key = KeyStorage::Keyring;
+        // set by the environment, which keeps precedence
+        let change = diff(&applied, "a", Some(&new), |var| var == "WEBHOOK_SECRET");
+        assert!(change.set.is_empty());
+        assert_eq!(change.removed, vec!["INDEXER_BASE_URL"]);
+        assert!(change.changes_urls());
+        // switching to another profile needs a restart
+        let change = diff(&applied, "b", Some(&new), |_| false);
+        assert_eq!(change.rejected, vec!["profile"]);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{fs, path::PathBuf};
+
+use intmax2_client_sdk::client::{
+    client::FeeMode,
+    rotation::{LeftBehindReason, RotationOptions, RotationPlan, RotationProgress},
+};
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+
+use crate::cli::{
+    client::get_client,
+    send::block_builder_url,
+    template::{load_templates, save_templates},
+    utils::{convert_u256, display_id, load_env, local_dir},
+};
+
+use super::{
+    error::CliError,
+    i18n::{tr, tr_with},
+};
+
+fn confirm(prompt: &str, yes: bool) -> Result<bool, CliError> {
+    if yes {
+        return Ok(true);
+    }
+    dialoguer::Confirm::new()
+        .with_prompt(prompt)
+        .default(false)
+        .interact()
+        .map_err(|e| CliError::UnexpectedError(format!("Failed to read input: {}", e)))
+}
+
+// the progress of the rotation of the old key, kept until the rotation completes
+fn progress_path(old_pubkey: U256) -> Result<PathBuf, CliError> {
+    Ok(local_dir()?
+        .join("rotations")
+        .join(format!("{}.json", old_pubkey.to_hex())))
+}
+
+fn load_progress(old_key: KeySet) -> Result<Option<RotationProgress>, CliError> {
+    let path = progress_path(old_key.pubkey)?;
+    let json = match fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(CliError::UnexpectedError(format!(
+                "Failed to read {:?}: {}",
+                path, e
+            )))
+        }
+    };
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|e| CliError::UnexpectedError(format!("Failed to parse {:?}: {}", path, e)))
+}
+
+fn save_progress(progress: &RotationProgress) -> Result<(), CliError> {
+    let path = progress_path(progress.plan.old_pubkey)?;
+    let tmp = path.with_extension("json.tmp");
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&tmp, serde_json::to_string_pretty(progress).unwrap()))
+        .and_then(|_| fs::rename(&tmp, &path))
+        .map_err(|e| CliError::UnexpectedError(format!("Failed to write {:?}: {}", path, e)))
+}
+
+fn print_plan(plan: &RotationPlan) {
+    println!(
+        "{}",
+        tr_with(
+            "msg.rotation",
+            &[("old", &plan.old_pubkey), ("new", &plan.new_pubkey)]
+        )
+    );
+    println!(
+        "{}",
+        tr_with("msg.rotation_txs", &[("txs", &plan.chunks.len())])
+    );
+    for (token_index, amount) in plan.amounts() {
+        println!(
+            "{}",
+            tr_with(
+                "msg.token_amount",
+                &[("token_index", &token_index), ("amount", &amount)]
+            )
+        );
+    }
+    if !plan.left_behind.is_empty() {
+        println!("{}", tr("msg.left_behind"));
+    }
+    for left in plan.left_behind.iter() {
+        let reason = match left.reason {
+            LeftBehindReason::Dust => "below the dust threshold",
+            LeftBehindReason::Insufficient => "insufficient balance",
+            LeftBehindReason::PendingWithdrawal => {
+                "pending withdrawal, completes under the old key"
+            }
+        };
+        println!(
+            "\t Token {}: {} ({})",
+            left.token_index, left.amount, reason
+        );
+    }
+}
+
+/// Move the balance of `old_key` to `new_key`, asking for confirmation before
+/// anything is sent unless `yes`. The progress is kept under
+/// `~/.intmax2/rotations` after each tx, and a rotation that failed midway is
+/// resumed from it by running the command again.
+pub async fn rotate_key(
+    old_key: KeySet,
+    new_key: KeySet,
+    include_metadata: bool,
+    dust_threshold: u128,
+    sponsor_url: Option<String>,
+    yes: bool,
+) -> Result<(), CliError> {
+    let env = load_env()?;
+    let client = get_client()?;
+    let options = RotationOptions {
+        fee_mode: match sponsor_url {
+            Some(sponsor_url) => FeeMode::Sponsored { sponsor_url },
+            None => FeeMode::SelfPaid,
+        },
+        dust_threshold: convert_u256(dust_threshold.into()),
+        templates: if include_metadata {
+            Some(load_templates(old_key)?)
+        } else {
+            None
+        },
+        timeout: env.tx_timeout,
+        interval: env.block_builder_query_interval,
+    };
+
+    let progress = match load_progress(old_key)? {
+        Some(progress) if progress.plan.new_pubkey == new_key.pubkey => {
+            println!(
+                "{}",
+                tr_with(
+                    "msg.rotation_resumed",
+                    &[
+                        ("new", &new_key.pubkey),
+                        ("sent", &progress.tx_tree_roots.len()),
+                        ("txs", &progress.plan.chunks.len())
+                    ]
+                )
+            );
+            progress
+        }
+        Some(progress) => {
+            return Err(CliError::UnexpectedError(format!(
+                "A rotation to {} is in progress, see {:?}",
+                progress.plan.new_pubkey,
+                progress_path(old_key.pubkey)?
+            )))
+        }
+        None => {
+            let plan = client.plan_rotation(old_key, new_key, &options).await?;
+            print_plan(&plan);
+            if plan.chunks.is_empty() {
+                println!("{}", tr("msg.nothing_to_move"));
+            } else if !confirm(
+                &tr_with("prompt.send_rotation", &[("pubkey", &new_key.pubkey)]),
+                yes,
+            )? {
+                println!("{}", tr("msg.aborted"));
+                return Ok(());
+            }
+            let progress = RotationProgress::new(plan);
+            save_progress(&progress)?;
+            progress
+        }
+    };
+
+    let block_builder_url = block_builder_url(&env).await?;
+    let num_chunks = progress.plan.chunks.len();
+    let mut save_error = None;
+    let report = client
+        .resume_rotation(
+            &block_builder_url,
+            old_key,
+            new_key,
+            progress,
+            &options,
+            |progress| {
+                let sent = progress.tx_tree_roots.len();
+                println!(
+                    "{}",
+                    tr_with(
+                        "msg.rotation_tx_sent",
+                        &[
+                            ("tx", &sent),
+                            ("txs", &num_chunks),
+                            ("root", &display_id(progress.tx_tree_roots[sent - 1]))
+                        ]
+                    )
+                );
+                if sent == num_chunks {
+                    println!("{}", tr("msg.syncing_new_account"));
+                }
+                if let Err(e) = save_progress(progress) {
+                    save_error.get_or_insert(e);
+                }
+            },
+        )
+        .await;
+    if let Some(e) = save_error {
+        log::warn!("The rotation progress was not saved: {}", e);
+    }
+    let report = report?;
+    let _ = fs::remove_file(progress_path(old_key.pubkey)?);
+    if let Some(templates) = &report.templates {
+        if !templates.templates.is_empty()
+            && confirm(
+                &format!(
+                    "Copy {} template(s) to the new account?",
+                    templates.templates.len()
+                ),
+                yes,
+            )?
+        {
+            save_templates(new_key, templates)?;
+            println!(
+                "{}",
+                tr_with(
+                    "msg.templates_copied",
+                    &[("count", &templates.templates.len())]
+                )
+            );
+        }
+    }
+    println!("{}", tr("msg.new_account_balances"));
+    for (token_index, amount) in report.new_balances.iter() {
+        println!(
+            "{}",
+            tr_with(
+                "msg.token_amount",
+                &[("token_index", &token_index), ("amount", &amount)]
+            )
+        );
+    }
+    if !report.left_behind.is_empty() {
+        println!(
+            "{} item(s) remain with the old key, see above",
+            report.left_behind.len()
+        );
+    }
+    Ok(())
+}
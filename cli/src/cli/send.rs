@@ -1,64 +1,198 @@
+use std::time::{Duration, Instant};
+
 use ethers::types::U256;
-use intmax2_client_sdk::external_api::indexer::IndexerClient;
-use intmax2_interfaces::api::indexer::interface::IndexerClientInterface;
-use intmax2_zkp::common::{
-    generic_address::GenericAddress, salt::Salt, signature::key_set::KeySet, transfer::Transfer,
+use intmax2_client_sdk::{
+    client::{
+        client::{FeeMode, TxSendOptions},
+        error::ClientError,
+        signer::RemoteSigner,
+    },
+    external_api::{indexer::IndexerClient, utils::url::normalize_base_url},
+};
+use intmax2_interfaces::api::{
+    indexer::interface::IndexerClientInterface, output_stream::types::ProgressStage,
+};
+use intmax2_zkp::{
+    common::{
+        generic_address::GenericAddress, salt::Salt, signature::key_set::KeySet, transfer::Transfer,
+    },
+    ethereum_types::bytes32::Bytes32,
 };
 
 use crate::{
-    cli::{client::get_client, sync::sync, utils::convert_u256},
+    cli::{
+        builders::{
+            advertised_address, check_builder_inclusions, load_builder_stats,
+            record_builder_outcome, select_builder, track_builder_inclusion, warn_if_blocked,
+            BuilderOutcome, BuilderPolicy,
+        },
+        client::get_client,
+        deadline::{global_deadlines, Phase},
+        i18n::{tr, tr_with},
+        output::{is_json_output, print_json, print_notice},
+        stream::{emit_progress, is_ndjson_stream, set_stream_result},
+        sync::sync,
+        utils::{convert_u256, display_id, load_env},
+    },
     Env,
 };
 
 use super::error::CliError;
 
-pub async fn tx(
+/// A transfer of `amount` to `recipient` with a fresh salt
+pub fn single_transfer(recipient: GenericAddress, amount: U256, token_index: u32) -> Transfer {
+    let mut rng = rand::thread_rng();
+    Transfer {
+        recipient,
+        amount: convert_u256(amount),
+        token_index,
+        salt: Salt::rand(&mut rng),
+    }
+}
+
+/// BLOCK_BUILDER_BASE_URL if set, else the block builder of the indexer chosen
+/// with the trusted and blocked builders of the profile and the stats of the
+/// earlier txs
+pub(crate) async fn block_builder_url(env: &Env) -> Result<String, CliError> {
+    let policy = BuilderPolicy::load()?;
+    let indexer = IndexerClient::new(&env.indexer_base_url.to_string());
+    if let Some(block_builder_base_url) = &env.block_builder_base_url {
+        // the builder may be blocked by the address the indexer advertises for it
+        let address = match indexer.get_block_builder_info().await {
+            Ok(block_builder_info) => {
+                advertised_address(&block_builder_info, block_builder_base_url).map(str::to_string)
+            }
+            Err(e) => {
+                log::warn!("Failed to get the block builders of the indexer: {}", e);
+                None
+            }
+        };
+        warn_if_blocked(&policy, block_builder_base_url, address.as_deref());
+        return Ok(block_builder_base_url.to_string());
+    }
+    let block_builder_info = indexer.get_block_builder_info().await?;
+    if block_builder_info.is_empty() {
+        return Err(CliError::UnexpectedError(
+            "Block builder info is empty".to_string(),
+        ));
+    }
+    check_builder_inclusions(env.tx_timeout).await;
+    let selected = select_builder(&block_builder_info, &policy, &load_builder_stats()?)?;
+    log::info!("Using block builder {}", selected.url);
+    Ok(selected.url.clone())
+}
+
+/// Run the checks of `tx` on the transfers without contacting the block builder,
+/// and print the transfer tree root and the balance changes the tx would have.
+/// Syncs first and fails like `tx` if a check does.
+pub async fn tx_dry_run(
     key: KeySet,
-    recipient: GenericAddress,
-    amount: U256,
-    token_index: u32,
+    transfers: Vec<Transfer>,
+    nonce: Option<u32>,
+    claim_deadline_block: Option<u32>,
+    attribution_tag: Option<String>,
+    fee_transfer: Option<Transfer>,
 ) -> Result<(), CliError> {
-    let env = envy::from_env::<Env>()?;
     let client = get_client()?;
-
-    if !sync(key.clone()).await? {
+    let options = TxSendOptions {
+        nonce_override: nonce,
+        claim_deadline_block,
+        attribution_tag,
+        fee_transfer,
+        ..Default::default()
+    };
+    let dry_run = client.dry_run_tx_request(key, transfers, &options).await?;
+    if is_ndjson_stream() {
+        set_stream_result(&dry_run);
+        return Ok(());
+    }
+    if is_json_output() {
+        print_json(&dry_run);
         return Ok(());
     }
+    println!(
+        "{}",
+        tr_with("msg.dry_run_nonce", &[("nonce", &dry_run.nonce)])
+    );
+    println!(
+        "{}",
+        tr_with(
+            "msg.dry_run_transfer_tree_root",
+            &[("root", &Bytes32::from(dry_run.transfer_tree_root))],
+        )
+    );
+    println!("{}", tr("msg.dry_run_balance_changes"));
+    for change in dry_run.balance_changes.iter() {
+        println!(
+            "\t Token {}: {} - {} = {}",
+            change.token_index, change.balance, change.amount, change.remaining
+        );
+    }
+    println!("{}", tr("msg.dry_run"));
+    Ok(())
+}
 
-    // override block builder base url if it is set in the env
-    let block_builder_url = if let Some(block_builder_base_url) = env.block_builder_base_url {
-        block_builder_base_url.to_string()
-    } else {
-        // get block builder info
-        let indexer = IndexerClient::new(&env.indexer_base_url.to_string());
-        let block_builder_info = indexer.get_block_builder_info().await?;
-        if block_builder_info.is_empty() {
-            return Err(CliError::UnexpectedError(
-                "Block builder info is empty".to_string(),
-            ));
-        }
-        block_builder_info.first().unwrap().url.clone()
-    };
+/// `timeout` limits the wait for the block proposal. `fee_transfer`, if given, pays
+/// the block builder and is appended to the transfers. Returns the tx tree root, or
+/// `None` if the account has pending actions and nothing was sent.
+#[allow(clippy::too_many_arguments)]
+pub async fn tx(
+    key: KeySet,
+    transfers: Vec<Transfer>,
+    nonce: Option<u32>,
+    claim_deadline_block: Option<u32>,
+    force: bool,
+    sponsor_url: Option<String>,
+    attribution_tag: Option<String>,
+    fee_transfer: Option<Transfer>,
+    timeout: Option<Duration>,
+) -> Result<Option<Bytes32>, CliError> {
+    let env = load_env()?;
+    let client = get_client()?;
+    let deadlines = global_deadlines();
 
-    let mut rng = rand::thread_rng();
-    let salt = Salt::rand(&mut rng);
+    deadlines.set_phase(Phase::Sync, None);
+    if !sync(key.clone()).await? {
+        return Ok(None);
+    }
+
+    deadlines.set_phase(Phase::TxRequest, None);
+    emit_progress(ProgressStage::TxRequest, 0, 0);
+    let block_builder_url = block_builder_url(&env).await?;
 
-    let amount = convert_u256(amount);
-    let transfer = Transfer {
-        recipient,
-        amount,
-        token_index,
-        salt,
-    };
     let mut tries = 0;
     let memo = loop {
+        let options = TxSendOptions {
+            nonce_override: nonce,
+            claim_deadline_block,
+            skip_health_check: force,
+            skip_sync: false,
+            fee_mode: match &sponsor_url {
+                Some(sponsor_url) => FeeMode::Sponsored {
+                    sponsor_url: sponsor_url.clone(),
+                },
+                None => FeeMode::SelfPaid,
+            },
+            attribution_tag: attribution_tag.clone(),
+            fee_transfer: fee_transfer.clone(),
+        };
         let res = client
-            .send_tx_request(&block_builder_url, key, vec![transfer])
+            .send_tx_request_with_options(&block_builder_url, key, transfers.clone(), options)
             .await;
-        if let Ok(memo) = res {
-            break memo;
+        match res {
+            Ok(memo) => break memo,
+            // retrying does not fix a nonce mismatch or a past claim deadline
+            Err(e @ ClientError::InvalidNonce(_, _))
+            | Err(e @ ClientError::InvalidClaimDeadline(_, _))
+            | Err(e @ ClientError::InvalidAttributionTag(_)) => return Err(e.into()),
+            Err(e @ ClientError::BuilderUnhealthy { .. }) => {
+                log::error!("{}. Use --force to send anyway", e);
+                return Err(e.into());
+            }
+            Err(_) => {}
         }
         if tries > env.block_builder_query_limit {
+            record_builder_outcome(&block_builder_url, BuilderOutcome::Failure);
             return Err(CliError::FailedToRequestTx);
         }
         tries += 1;
@@ -74,6 +208,10 @@ pub async fn tx(
     let is_registration_block = memo.is_registration_block;
     let tx = memo.tx.clone();
 
+    let requested_at = Instant::now();
+
+    deadlines.set_phase(Phase::ProposalWait, timeout);
+    emit_progress(ProgressStage::ProposalWait, 0, 0);
     log::info!("Waiting for block builder to build the block");
     tokio::time::sleep(std::time::Duration::from_secs(
         env.block_builder_query_wait_time,
@@ -86,12 +224,18 @@ pub async fn tx(
             .query_proposal(&block_builder_url, key, is_registration_block, tx)
             .await?;
         if proposal.is_some() {
+            record_builder_outcome(
+                &block_builder_url,
+                BuilderOutcome::Proposal(requested_at.elapsed()),
+            );
             break proposal.unwrap();
         }
         if tries > env.block_builder_query_limit {
+            record_builder_outcome(&block_builder_url, BuilderOutcome::Timeout);
             return Err(CliError::FailedToGetProposal);
         }
         tries += 1;
+        emit_progress(ProgressStage::ProposalWait, tries as u32, 0);
         log::info!(
             "Failed to get proposal, retrying in {} seconds",
             env.block_builder_query_interval
@@ -102,10 +246,28 @@ pub async fn tx(
         .await;
     };
 
+    deadlines.set_phase(Phase::Finalize, None);
+    emit_progress(ProgressStage::Finalize, 0, 0);
     log::info!("Finalizing tx");
-    client
-        .finalize_tx(&block_builder_url, key, &memo, &proposal)
-        .await?;
+    let tx_tree_root = if let Some(remote_signer_url) = &env.remote_signer_url {
+        let signer = RemoteSigner::new(
+            &normalize_base_url("REMOTE_SIGNER_URL", remote_signer_url)?,
+            env.remote_signer_token.clone(),
+            key.pubkey,
+        );
+        client
+            .finalize_tx_with_signer(&block_builder_url, key, &signer, &memo, &proposal)
+            .await?
+    } else {
+        client
+            .finalize_tx(&block_builder_url, key, &memo, &proposal)
+            .await?
+    };
+    track_builder_inclusion(&block_builder_url, tx_tree_root);
+    print_notice(tr_with(
+        "msg.tx_finalized",
+        &[("root", &display_id(tx_tree_root))],
+    ));
 
-    Ok(())
+    Ok(Some(tx_tree_root))
 }
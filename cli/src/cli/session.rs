@@ -0,0 +1,115 @@
+use std::{fs, future::Future, path::PathBuf, time::Duration};
+
+use intmax2_client_sdk::client::session::{account_session, SessionMarker, SHUTDOWN_TIMEOUT_MS};
+use intmax2_interfaces::api::output_stream::types::StreamEvent;
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+
+use super::{
+    client::get_client,
+    deadline::global_deadlines,
+    error::CliError,
+    i18n::{tr, tr_with},
+    output::print_notice,
+    stream::{finish_stream, is_ndjson_stream},
+    utils::local_dir,
+};
+
+/// Exit code after an interrupt, as for a shell killed by SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// File under `~/.intmax2/sessions` that exists while a command runs on a pubkey
+pub struct FileSessionMarker {
+    path: PathBuf,
+}
+
+impl FileSessionMarker {
+    pub fn new(pubkey: U256) -> Result<Self, CliError> {
+        let dir = local_dir()?.join("sessions");
+        fs::create_dir_all(&dir).map_err(|e| {
+            CliError::UnexpectedError(format!("Failed to create session dir {:?}: {}", dir, e))
+        })?;
+        Ok(Self {
+            path: dir.join(format!("{}.open", pubkey.to_hex())),
+        })
+    }
+}
+
+impl SessionMarker for FileSessionMarker {
+    fn is_open(&self) -> bool {
+        self.path.exists()
+    }
+
+    fn set_open(&self, open: bool) {
+        let result = if open {
+            fs::write(&self.path, std::process::id().to_string())
+        } else {
+            fs::remove_file(&self.path)
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to update session marker {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Run a command that writes to the store vault. On Ctrl-C, new writes are refused
+/// and the ones already sent get `SHUTDOWN_TIMEOUT_MS` to finish before exiting.
+/// The same happens when `--deadline` or `--timeout` passes, and the command then
+/// fails with `CliError::Timeout`. If the previous command was killed, the local
+/// state is checked first.
+pub async fn run_in_session<T, Fut>(key: KeySet, command: Fut) -> Result<T, CliError>
+where
+    Fut: Future<Output = Result<T, CliError>>,
+{
+    let client = get_client()?;
+    let marker = FileSessionMarker::new(key.pubkey)?;
+    let checked = client.open_session(key, &marker).await.map_err(|e| {
+        CliError::UnexpectedError(format!(
+            "Previous command was interrupted and the local state is inconsistent: {}. Run `recover` to rebuild it",
+            e
+        ))
+    })?;
+    if checked {
+        print_notice(tr("msg.session_checked"));
+    }
+
+    let session = account_session(key.pubkey);
+    let _session = global_deadlines().session();
+    tokio::pin!(command);
+    let result = tokio::select! {
+        result = &mut command => result,
+        error = global_deadlines().expired() => {
+            session.close();
+            let timeout = Duration::from_millis(SHUTDOWN_TIMEOUT_MS);
+            let _ = tokio::time::timeout(timeout, &mut command).await;
+            let report = session.shutdown(&marker, 0).await;
+            if !report.is_clean() {
+                eprintln!("{}", tr_with("msg.timed_out_with_writes", &[("count", &report.abandoned_writes)]));
+            }
+            return Err(CliError::Timeout(error));
+        }
+        _ = tokio::signal::ctrl_c() => {
+            session.close();
+            // keep polling the command so that the writes already sent complete
+            let timeout = Duration::from_millis(SHUTDOWN_TIMEOUT_MS);
+            let _ = tokio::time::timeout(timeout, &mut command).await;
+            // already waited above
+            let report = session.shutdown(&marker, 0).await;
+            if !report.is_clean() {
+                eprintln!("{}", tr_with("msg.interrupted_with_writes", &[("count", &report.abandoned_writes)]));
+            }
+            if is_ndjson_stream() {
+                finish_stream(Some(StreamEvent::Error {
+                    error: "interrupted".to_string(),
+                    message_id: "error.interrupted".to_string(),
+                    message: tr("error.interrupted"),
+                }));
+            }
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    };
+    client.shutdown(key.pubkey, &marker).await;
+    result
+}
@@ -0,0 +1,279 @@
+//! `--output ndjson-stream`: the events of a long-running command are written to
+//! stdout as they happen, one `StreamEvent` per line, so that a wrapper can show
+//! live progress. The last line is always a `result` or an `error`.
+
+use std::{
+    io::{self, Write},
+    sync::Mutex,
+};
+
+use intmax2_client_sdk::client::client::{SyncProgress, SyncProgressCallback, SyncStage};
+use intmax2_interfaces::api::output_stream::types::{ProgressStage, StreamEvent};
+use serde::Serialize;
+
+static STREAM: Mutex<Option<NdjsonStream<Box<dyn Write + Send>>>> = Mutex::new(None);
+
+/// Writer of the events of one command
+pub struct NdjsonStream<W: Write> {
+    out: W,
+    result: Option<serde_json::Value>,
+    finished: bool,
+}
+
+impl<W: Write> NdjsonStream<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            result: None,
+            finished: false,
+        }
+    }
+
+    /// Write `event` as one line and flush it, so that the reader gets it at once.
+    /// Nothing is written after the terminal line.
+    pub fn emit(&mut self, event: &StreamEvent) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = event.is_terminal();
+        let line = serde_json::to_string(event).map_err(io::Error::other)?;
+        writeln!(self.out, "{}", line)?;
+        self.out.flush()
+    }
+
+    /// Output of the command, for the `result` line
+    pub fn set_result(&mut self, value: serde_json::Value) {
+        self.result = Some(value);
+    }
+
+    /// Write the terminal line: `error` if given, else `result` with the output set
+    /// by the command
+    pub fn finish(&mut self, error: Option<StreamEvent>) -> io::Result<()> {
+        let event = error.unwrap_or_else(|| StreamEvent::Result {
+            value: self.result.take().unwrap_or_default(),
+        });
+        self.emit(&event)
+    }
+}
+
+/// Write the events of the command to stdout from now on
+pub fn start_ndjson_stream() {
+    // the messages of the events are read by programs
+    colored::control::set_override(false);
+    start_stream_to(Box::new(io::stdout()));
+}
+
+fn start_stream_to(out: Box<dyn Write + Send>) {
+    *STREAM.lock().unwrap() = Some(NdjsonStream::new(out));
+}
+
+pub fn is_ndjson_stream() -> bool {
+    STREAM.lock().unwrap().is_some()
+}
+
+fn with_stream(f: impl FnOnce(&mut NdjsonStream<Box<dyn Write + Send>>) -> io::Result<()>) {
+    if let Some(stream) = STREAM.lock().unwrap().as_mut() {
+        // a wrapper that stopped reading does not fail the command
+        if let Err(e) = f(stream) {
+            log::warn!("Failed to write to the output stream: {}", e);
+        }
+    }
+}
+
+pub fn emit_progress(stage: ProgressStage, current: u32, total: u32) {
+    with_stream(|stream| {
+        stream.emit(&StreamEvent::Progress {
+            stage,
+            current,
+            total,
+        })
+    });
+}
+
+pub fn emit_warning(message: String) {
+    with_stream(|stream| stream.emit(&StreamEvent::Warning { message }));
+}
+
+/// Output of the command, written in the `result` line when it succeeds
+pub fn set_stream_result<T: Serialize>(value: &T) {
+    let value = serde_json::to_value(value).unwrap();
+    with_stream(|stream| {
+        stream.set_result(value);
+        Ok(())
+    });
+}
+
+/// Write the terminal line, `result` if `error` is None
+pub fn finish_stream(error: Option<StreamEvent>) {
+    with_stream(|stream| stream.finish(error));
+}
+
+pub fn sync_progress_event(progress: SyncProgress) -> StreamEvent {
+    let stage = match progress.stage {
+        SyncStage::Actions => ProgressStage::SyncActions,
+        SyncStage::Blocks => ProgressStage::Sync,
+        SyncStage::Withdrawals => ProgressStage::WithdrawalSync,
+    };
+    StreamEvent::Progress {
+        stage,
+        current: progress.current,
+        total: progress.total,
+    }
+}
+
+/// Callback emitting the progress of a sync, None if there is no stream
+pub fn sync_progress_callback() -> Option<SyncProgressCallback> {
+    if !is_ndjson_stream() {
+        return None;
+    }
+    Some(Box::new(|progress| {
+        with_stream(|stream| stream.emit(&sync_progress_event(progress)))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::cli::output::print_warning;
+
+    // the tests share the stream of the process
+    static STREAM_TEST: Mutex<()> = Mutex::new(());
+
+    #[derive(Default)]
+    struct Recorded {
+        bytes: Vec<u8>,
+        // length of `bytes` at each flush
+        flushes: Vec<usize>,
+    }
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Recorded>>);
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            let mut recorded = self.0.lock().unwrap();
+            let len = recorded.bytes.len();
+            recorded.flushes.push(len);
+            Ok(())
+        }
+    }
+
+    impl Recorder {
+        fn events(&self) -> Vec<StreamEvent> {
+            let recorded = self.0.lock().unwrap();
+            String::from_utf8(recorded.bytes.clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<StreamEvent>(line).unwrap())
+                .collect()
+        }
+
+        fn flushes(&self) -> Vec<usize> {
+            self.0.lock().unwrap().flushes.clone()
+        }
+    }
+
+    // the calls of `Client::sync_with_progress` for 2 blocks of 3 actions, one
+    // of which arrives during the sync, then of `sync_withdrawals_with_progress`
+    // with nothing to prove
+    fn scripted_sync(on_progress: SyncProgressCallback) {
+        let script = [
+            (SyncStage::Actions, 1, 2),
+            (SyncStage::Actions, 2, 3),
+            (SyncStage::Blocks, 1, 2),
+            (SyncStage::Actions, 3, 3),
+            (SyncStage::Blocks, 2, 2),
+            (SyncStage::Withdrawals, 0, 0),
+        ];
+        for (stage, current, total) in script {
+            on_progress(SyncProgress {
+                current,
+                total,
+                stage,
+            });
+        }
+    }
+
+    fn progress(stage: ProgressStage, current: u32, total: u32) -> StreamEvent {
+        StreamEvent::Progress {
+            stage,
+            current,
+            total,
+        }
+    }
+
+    #[test]
+    fn test_stream_of_sync() {
+        let _guard = STREAM_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        *STREAM.lock().unwrap() = None;
+        assert!(sync_progress_callback().is_none());
+
+        let recorder = Recorder::default();
+        start_stream_to(Box::new(recorder.clone()));
+        let on_progress = sync_progress_callback().unwrap();
+        scripted_sync(on_progress);
+        // each line is flushed as it is written
+        let flushes = recorder.flushes();
+        assert_eq!(flushes.len(), 6);
+        assert!(flushes.windows(2).all(|w| w[0] < w[1]));
+
+        print_warning("Clock skew: 30s");
+        set_stream_result(&serde_json::json!({ "synced": true }));
+        finish_stream(None);
+        // nothing follows the terminal line
+        emit_progress(ProgressStage::Sync, 3, 3);
+        finish_stream(None);
+        *STREAM.lock().unwrap() = None;
+
+        let events = recorder.events();
+        assert_eq!(
+            events,
+            vec![
+                progress(ProgressStage::SyncActions, 1, 2),
+                progress(ProgressStage::SyncActions, 2, 3),
+                progress(ProgressStage::Sync, 1, 2),
+                progress(ProgressStage::SyncActions, 3, 3),
+                progress(ProgressStage::Sync, 2, 2),
+                progress(ProgressStage::WithdrawalSync, 0, 0),
+                StreamEvent::Warning {
+                    message: "Clock skew: 30s".to_string()
+                },
+                StreamEvent::Result {
+                    value: serde_json::json!({ "synced": true })
+                },
+            ]
+        );
+        assert_eq!(recorder.flushes().len(), events.len());
+    }
+
+    #[test]
+    fn test_stream_ends_with_error() {
+        let _guard = STREAM_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        let recorder = Recorder::default();
+        start_stream_to(Box::new(recorder.clone()));
+        let on_progress = sync_progress_callback().unwrap();
+        on_progress(SyncProgress {
+            current: 1,
+            total: 2,
+            stage: SyncStage::Blocks,
+        });
+        set_stream_result(&serde_json::json!({ "synced": true }));
+        finish_stream(Some(StreamEvent::Error {
+            error: "timeout".to_string(),
+            message_id: "error.timeout".to_string(),
+            message: "Timed out during sync".to_string(),
+        }));
+        *STREAM.lock().unwrap() = None;
+
+        let events = recorder.events();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[1], StreamEvent::Error { ref error, .. } if error == "timeout"));
+    }
+}
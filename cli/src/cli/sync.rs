@@ -1,23 +1,119 @@
 use colored::Colorize as _;
-use intmax2_client_sdk::client::error::ClientError;
+use intmax2_client_sdk::{
+    client::{client::SyncReport, error::ClientError},
+    external_api::{
+        mirrored_store_vault::MirroredStoreVaultClient, store_vault_server::StoreVaultServerClient,
+        utils::clock::global_clock,
+    },
+};
 use intmax2_zkp::common::signature::key_set::KeySet;
 
-use super::{client::get_client, error::CliError};
+use super::{
+    client::get_client,
+    error::CliError,
+    i18n::{tr, tr_with},
+    output::{print_notice, print_warning},
+    stream::sync_progress_callback,
+    webhook::notify_account_events,
+};
 
 pub async fn sync(key: KeySet) -> Result<bool, CliError> {
     let client = get_client()?;
-    match client.sync(key).await {
-        Ok(_) => {
-            log::info!("Synced successfully");
+    let synced = handle_sync_result(
+        client
+            .sync_with_progress(key, sync_progress_callback())
+            .await,
+    )?;
+    flush_mirrors(&client.store_vault_server).await;
+    if synced {
+        notify_account_events(key);
+    }
+    Ok(synced)
+}
+
+/// Sync, checking the balances of each synced action against the replayed history
+/// before it is saved
+pub async fn sync_verified(key: KeySet) -> Result<bool, CliError> {
+    let client = get_client()?;
+    let synced = handle_sync_result(client.sync_verified(key).await)?;
+    flush_mirrors(&client.store_vault_server).await;
+    if synced {
+        notify_account_events(key);
+    }
+    Ok(synced)
+}
+
+/// Sync only the deposits and incoming transfers of `token_index`
+pub async fn sync_token(key: KeySet, token_index: u32) -> Result<bool, CliError> {
+    let client = get_client()?;
+    let synced = handle_sync_result(client.sync_token(key, token_index).await)?;
+    flush_mirrors(&client.store_vault_server).await;
+    Ok(synced)
+}
+
+/// Mirror the backups that this or an earlier command failed to write to the
+/// secondary store vaults
+pub(crate) async fn flush_mirrors(store_vault: &MirroredStoreVaultClient<StoreVaultServerClient>) {
+    let pending = store_vault.pending_mirror_ops();
+    if pending == 0 {
+        return;
+    }
+    let flushed = store_vault.flush_mirror_queue().await;
+    log::info!(
+        "Mirrored {} of {} queued backups to the secondary store vaults",
+        flushed,
+        pending
+    );
+}
+
+pub(crate) fn handle_sync_result(
+    result: Result<SyncReport, ClientError>,
+) -> Result<bool, CliError> {
+    match result {
+        Ok(report) => {
+            for checkpoint in report.checkpoints.iter() {
+                log::info!(
+                    "Checkpoint at block {} after {} actions",
+                    checkpoint.block_number,
+                    checkpoint.num_actions
+                );
+            }
+            for uuid in report.expired_transfer_uuids.iter() {
+                print_warning(
+                    format!(
+                        "Transfer {} was not received before its claim deadline",
+                        uuid
+                    )
+                    .yellow(),
+                );
+            }
+            for action in report.skipped_actions.iter() {
+                print_notice(tr_with("msg.sync_skipped", &[("action", action)]));
+            }
+            print_skew_warnings(&report.clock_skew_warnings);
+            log::info!("Synced successfully: {} actions", report.num_actions);
         }
         Err(e) => match e {
             ClientError::PendingError(_) => {
-                println!(
-                    "{}",
-                    "There are pending actions. Please try again later.".red()
+                // a skewed clock makes pending actions look timed out or not yet due
+                print_skew_warnings(&global_clock().skew_warnings());
+                print_warning("There are pending actions. Please try again later.".red());
+                return Ok(false);
+            }
+            ClientError::RequiresFullSync { blocking_action } => {
+                print_warning(
+                    format!(
+                        "{} must be processed first. Run `sync` without --token",
+                        blocking_action
+                    )
+                    .red(),
                 );
                 return Ok(false);
             }
+            ClientError::BalanceMismatch { .. } => {
+                print_notice(tr("msg.sync_not_saved").red());
+                return Err(CliError::ClientError(e));
+            }
             _ => {
                 return Err(CliError::UnexpectedError(format!("{:?}", e)));
             }
@@ -26,8 +122,17 @@ pub async fn sync(key: KeySet) -> Result<bool, CliError> {
     Ok(true)
 }
 
+fn print_skew_warnings(warnings: &[String]) {
+    for warning in warnings {
+        print_warning(format!("Clock skew: {}", warning).yellow().bold());
+    }
+}
+
 pub async fn sync_withdrawals(key: KeySet) -> Result<(), CliError> {
     let client = get_client()?;
-    client.sync_withdrawals(key).await?;
+    client
+        .sync_withdrawals_with_progress(key, sync_progress_callback())
+        .await?;
+    notify_account_events(key);
     Ok(())
 }
@@ -0,0 +1,162 @@
+use std::{fs, path::PathBuf};
+
+use ethers::types::U256;
+use intmax2_client_sdk::client::template::{
+    contact_name, TemplateBook, TemplateOverrides, TemplateTransfer, TxTemplate,
+};
+use intmax2_zkp::{
+    common::{generic_address::GenericAddress, signature::key_set::KeySet, transfer::Transfer},
+    ethereum_types::u32limb_trait::U32LimbTrait as _,
+};
+
+use super::{
+    address_book::load_address_book,
+    error::CliError,
+    i18n::{tr, tr_with},
+    utils::{convert_u256, local_dir},
+};
+
+fn templates_path(key: KeySet) -> Result<PathBuf, CliError> {
+    Ok(local_dir()?
+        .join("templates")
+        .join(format!("{}.tpl", key.pubkey.to_hex())))
+}
+
+// unlike the history index, templates cannot be rebuilt, so an unreadable file is
+// an error rather than a reason to start over
+pub(crate) fn load_templates(key: KeySet) -> Result<TemplateBook, CliError> {
+    let path = templates_path(key)?;
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(TemplateBook::new(key.pubkey))
+        }
+        Err(e) => {
+            return Err(CliError::TemplateError(format!(
+                "Failed to read {:?}: {}",
+                path, e
+            )))
+        }
+    };
+    TemplateBook::decrypt(&bytes, key)
+        .map_err(|e| CliError::TemplateError(format!("Failed to decrypt {:?}: {}", path, e)))
+}
+
+pub(crate) fn save_templates(key: KeySet, book: &TemplateBook) -> Result<(), CliError> {
+    let path = templates_path(key)?;
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, book.encrypt(key.pubkey)))
+        .map_err(|e| CliError::TemplateError(format!("Failed to write {:?}: {}", path, e)))
+}
+
+/// Save a template of one transfer per `--to`, in the order given. A `@name`
+/// recipient is kept as a reference to the address book, resolved on each use.
+pub fn template_save(
+    key: KeySet,
+    name: String,
+    to: Vec<String>,
+    token_index: Vec<u32>,
+    amount: Vec<u128>,
+    memo: Option<String>,
+) -> Result<(), CliError> {
+    if to.len() != token_index.len() || to.len() != amount.len() {
+        return Err(CliError::TemplateError(
+            "Give one --token-index and one --amount for each --to".to_string(),
+        ));
+    }
+    if to.iter().any(|recipient| contact_name(recipient).is_some()) {
+        let contacts = load_address_book()?;
+        for contact in to.iter().filter_map(|recipient| contact_name(recipient)) {
+            if contacts.pubkey(contact).is_none() {
+                return Err(CliError::AddressBookError(format!(
+                    "no entry named {}",
+                    contact
+                )));
+            }
+        }
+    }
+    let transfers = to
+        .into_iter()
+        .zip(token_index)
+        .zip(amount)
+        .map(|((recipient, token_index), amount)| TemplateTransfer {
+            recipient,
+            token_index,
+            amount: convert_u256(U256::from(amount)),
+        })
+        .collect();
+    let mut book = load_templates(key)?;
+    book.save_template(TxTemplate {
+        name: name.clone(),
+        transfers,
+        memo,
+    })?;
+    save_templates(key, &book)?;
+    println!("{}", tr_with("msg.template_saved", &[("name", &name)]));
+    Ok(())
+}
+
+pub fn template_list(key: KeySet) -> Result<(), CliError> {
+    let book = load_templates(key)?;
+    let templates = book.get_templates();
+    if templates.is_empty() {
+        println!("{}", tr("msg.no_templates"));
+    }
+    for template in templates {
+        match &template.memo {
+            Some(memo) => println!("{} ({})", template.name, memo),
+            None => println!("{}", template.name),
+        }
+        for transfer in template.transfers.iter() {
+            println!(
+                "  {} of token {} to {}",
+                transfer.amount, transfer.token_index, transfer.recipient
+            );
+        }
+    }
+    Ok(())
+}
+
+fn format_recipient(recipient: &GenericAddress) -> String {
+    if recipient.is_pubkey {
+        recipient.to_pubkey().unwrap().to_hex()
+    } else {
+        recipient.to_address().unwrap().to_string()
+    }
+}
+
+/// Turn the template into transfers and ask for confirmation. Returns `None` if
+/// the user declines.
+pub fn use_template(
+    key: KeySet,
+    name: &str,
+    amount_override: Option<u128>,
+    yes: bool,
+) -> Result<Option<Vec<Transfer>>, CliError> {
+    let book = load_templates(key)?;
+    let overrides = TemplateOverrides {
+        amount: amount_override.map(|amount| convert_u256(U256::from(amount))),
+    };
+    let contacts = load_address_book()?;
+    let transfers =
+        book.apply_template_with_contacts(name, &overrides, |contact| contacts.pubkey(contact))?;
+    println!("{}", tr_with("msg.template", &[("name", &name)]));
+    for transfer in transfers.iter() {
+        println!(
+            "  {} of token {} to {}",
+            transfer.amount,
+            transfer.token_index,
+            format_recipient(&transfer.recipient)
+        );
+    }
+    if yes {
+        return Ok(Some(transfers));
+    }
+    let confirmed = dialoguer::Confirm::new()
+        .with_prompt(tr("prompt.send_transfers"))
+        .default(false)
+        .interact()
+        .map_err(|e| CliError::TemplateError(format!("Failed to read confirmation: {}", e)))?;
+    Ok(confirmed.then_some(transfers))
+}
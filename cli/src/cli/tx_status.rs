@@ -0,0 +1,73 @@
+use intmax2_client_sdk::client::tx_status::{get_tx_status, wait_for_tx_status, TxStatus};
+use intmax2_zkp::ethereum_types::bytes32::Bytes32;
+
+use crate::cli::{
+    client::get_client,
+    key::KeyArgs,
+    output::{is_json_output, print_json},
+    utils::{display_id, load_env},
+};
+
+use super::error::CliError;
+
+/// Print whether the tx of `tx_tree_root` is pending, included or expired. With
+/// `wait`, poll until it is included or expired. The tx timeout counts from
+/// `finalized_at`, or if not given from when the account of `key` finalized the
+/// tx, which is an error if the account has no record of it. An expired tx is an
+/// error.
+pub async fn tx_status(
+    key: &KeyArgs,
+    tx_tree_root: Bytes32,
+    finalized_at: Option<u64>,
+    wait: bool,
+) -> Result<(), CliError> {
+    let env = load_env()?;
+    let client = get_client()?;
+    let finalized_at = match finalized_at {
+        Some(finalized_at) => finalized_at,
+        None => client
+            .tx_finalized_at(key.resolve()?, tx_tree_root)
+            .await?
+            .ok_or_else(|| {
+                CliError::TxStatusError(format!(
+                    "no record of tx tree root {} for this account, give --finalized-at",
+                    display_id(tx_tree_root)
+                ))
+            })?,
+    };
+    let status = if wait {
+        wait_for_tx_status(
+            &client.validity_prover,
+            tx_tree_root,
+            Some(finalized_at),
+            env.tx_timeout,
+            env.block_builder_query_interval,
+        )
+        .await?
+    } else {
+        get_tx_status(
+            &client.validity_prover,
+            tx_tree_root,
+            Some(finalized_at),
+            env.tx_timeout,
+        )
+        .await?
+    };
+    if is_json_output() {
+        print_json(&status);
+    } else {
+        match status {
+            TxStatus::Pending => println!("pending"),
+            TxStatus::Included { block_number } => println!("included in block {}", block_number),
+            TxStatus::Expired => println!("expired"),
+        }
+    }
+    if status == TxStatus::Expired {
+        return Err(CliError::TxExpired(format!(
+            "tx tree root {} is not in a block {} seconds after the tx",
+            display_id(tx_tree_root),
+            env.tx_timeout
+        )));
+    }
+    Ok(())
+}
@@ -1,9 +1,18 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
 use ethers::types::{Address, U256};
-use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+use intmax2_client_sdk::utils::id::{format_id, format_plain_id, parse_id};
+use intmax2_zkp::{
+    common::salt::Salt,
+    ethereum_types::{bytes32::Bytes32, u32limb_trait::U32LimbTrait as _},
+};
 
 use crate::{Env, EnvType};
 
-use super::error::CliError;
+use super::{config_file::env_vars, error::CliError};
 
 pub fn convert_u256(input: U256) -> intmax2_zkp::ethereum_types::u256::U256 {
     let mut bytes = [0u8; 32];
@@ -18,11 +27,59 @@ pub fn convert_address(input: Address) -> intmax2_zkp::ethereum_types::address::
     address
 }
 
+/// Settings of the environment, `.env` and the active profile, completed by the
+/// `[settings]` of the config file
 pub fn load_env() -> Result<Env, CliError> {
-    let env = envy::from_env::<Env>()?;
+    let env = envy::from_iter::<_, Env>(env_vars())?;
     Ok(env)
 }
 
 pub fn is_dev() -> Result<bool, CliError> {
     Ok(load_env()?.env == EnvType::Dev)
 }
+
+/// Directory for local CLI state (`~/.intmax2`)
+pub fn local_dir() -> Result<PathBuf, CliError> {
+    let home = std::env::var("HOME")
+        .map_err(|_| CliError::UnexpectedError("HOME is not set".to_string()))?;
+    Ok(PathBuf::from(home).join(".intmax2"))
+}
+
+/// A directory of the temp dir for the test `name`, removed if a previous run left
+/// it behind. Names are unique across the tests of the crate, which share the
+/// process id.
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("intmax2-cli-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+static PLAIN_IDS: AtomicBool = AtomicBool::new(false);
+
+/// Print ids without their checksum, for `--plain-ids`
+pub fn set_plain_ids(plain: bool) {
+    PLAIN_IDS.store(plain, Ordering::Relaxed);
+}
+
+/// A tx tree root, nullifier or other 32-byte id as printed for the user, with a
+/// checksum unless `--plain-ids`
+pub fn display_id(id: Bytes32) -> String {
+    if PLAIN_IDS.load(Ordering::Relaxed) {
+        format_plain_id(id)
+    } else {
+        format_id(id)
+    }
+}
+
+/// Parser of id arguments, in plain hex or with a checksum
+pub fn parse_id_arg(input: &str) -> Result<Bytes32, String> {
+    parse_id(input).map_err(|e| e.to_string())
+}
+
+/// Parser of salt arguments, in hex as printed by `deposit request`
+pub fn parse_salt_arg(input: &str) -> Result<Salt, String> {
+    let bytes = Bytes32::from_hex(input).map_err(|_| format!("invalid salt: {}", input))?;
+    Ok(Salt(bytes.reduce_to_hash_out()))
+}
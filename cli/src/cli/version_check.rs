@@ -0,0 +1,226 @@
+use std::{fs, path::Path, time::Duration};
+
+use colored::Colorize as _;
+use intmax2_client_sdk::client::compatibility::{
+    check_compatibility, CompatibilityReport, ServiceStatus, ServiceUrls,
+};
+use serde::{Deserialize, Serialize};
+
+use super::utils::{load_env, local_dir};
+
+// the check runs before every online command, so a slow server must not hold it up
+const VERSION_CHECK_TIMEOUT_SECS: u64 = 3;
+// servers are not updated more often than this
+const VERSION_CHECK_CACHE_SECS: u64 = 24 * 60 * 60;
+
+/// Outcome of the last check that reached every server, in `version_check.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedCheck {
+    /// Version of the CLI and urls of the servers that were checked
+    key: String,
+    checked_at: u64,
+    warnings: Vec<String>,
+}
+
+fn cache_key(urls: &ServiceUrls, client_version: &str) -> String {
+    format!(
+        "{} {} {} {} {} {}",
+        client_version,
+        urls.store_vault_server,
+        urls.validity_prover,
+        urls.balance_prover,
+        urls.withdrawal_server,
+        urls.block_builder.as_deref().unwrap_or("-"),
+    )
+}
+
+// the warnings of a check of the same servers less than a day ago
+fn cached_warnings_in(dir: &Path, key: &str, now: u64) -> Option<Vec<String>> {
+    let json = fs::read_to_string(dir.join("version_check.json")).ok()?;
+    let cached: CachedCheck = serde_json::from_str(&json).ok()?;
+    (cached.key == key && now < cached.checked_at + VERSION_CHECK_CACHE_SECS)
+        .then_some(cached.warnings)
+}
+
+fn save_check_in(dir: &Path, check: &CachedCheck) -> std::io::Result<()> {
+    let path = dir.join("version_check.json");
+    let tmp_path = path.with_extension("json.tmp");
+    fs::create_dir_all(dir)?;
+    fs::write(&tmp_path, serde_json::to_string_pretty(check).unwrap())?;
+    fs::rename(&tmp_path, &path)
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+/// Lines to print for the servers that are incompatible with this CLI
+fn incompatibility_warnings(report: &CompatibilityReport) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for service in report.services.iter() {
+        match &service.status {
+            ServiceStatus::ClientTooOld { reason } => warnings.push(format!(
+                "{} at {} needs a newer CLI: {}",
+                service.service, service.base_url, reason
+            )),
+            ServiceStatus::ServerTooOld { reason } => warnings.push(format!(
+                "{} at {} is too old for this CLI: {}",
+                service.service, service.base_url, reason
+            )),
+            ServiceStatus::Unreachable { error } => {
+                log::warn!("version of {} not known: {}", service.service, error)
+            }
+            ServiceStatus::Compatible => {}
+        }
+    }
+    if let Some(version) = &report.min_required_frontend_version {
+        warnings.push(format!(
+            "update the CLI from {} to at least {}",
+            report.client_version, version
+        ));
+    }
+    warnings
+}
+
+/// Compare the versions of the servers of the environment with this CLI, and warn
+/// on stderr about the incompatible ones. The command runs anyway, as the check
+/// can be wrong about a server behind a proxy. A check that reached every server
+/// is reused for a day.
+pub async fn check_versions() {
+    // a command reports an incomplete environment itself
+    let Ok(env) = load_env() else {
+        return;
+    };
+    let urls = ServiceUrls {
+        store_vault_server: env.store_vault_server_base_url,
+        validity_prover: env.validity_prover_base_url,
+        balance_prover: env.balance_prover_base_url,
+        withdrawal_server: env.withdrawal_server_base_url,
+        block_builder: env.block_builder_base_url,
+    };
+    let key = cache_key(&urls, env!("CARGO_PKG_VERSION"));
+    let dir = local_dir().ok();
+    let cached = dir
+        .as_deref()
+        .and_then(|dir| cached_warnings_in(dir, &key, now()));
+    let warnings = match cached {
+        Some(warnings) => warnings,
+        None => {
+            let report = match tokio::time::timeout(
+                Duration::from_secs(VERSION_CHECK_TIMEOUT_SECS),
+                check_compatibility(&urls, env!("CARGO_PKG_VERSION")),
+            )
+            .await
+            {
+                Ok(report) => report,
+                Err(_) => {
+                    log::warn!("version check timed out");
+                    return;
+                }
+            };
+            let warnings = incompatibility_warnings(&report);
+            let reached_all = report
+                .services
+                .iter()
+                .all(|service| !matches!(service.status, ServiceStatus::Unreachable { .. }));
+            if let (Some(dir), true) = (&dir, reached_all) {
+                let check = CachedCheck {
+                    key,
+                    checked_at: now(),
+                    warnings: warnings.clone(),
+                };
+                if let Err(e) = save_check_in(dir, &check) {
+                    log::warn!("Failed to save the version check: {}", e);
+                }
+            }
+            warnings
+        }
+    };
+    for warning in warnings {
+        eprintln!("{} {}", "WARNING:".red().bold(), warning.red());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_client_sdk::client::compatibility::ServiceCompatibility;
+
+    use super::*;
+
+    #[test]
+    fn test_incompatibility_warnings() {
+        let service = |name: &str, status: ServiceStatus| ServiceCompatibility {
+            service: name.to_string(),
+            base_url: "http://localhost".to_string(),
+            server_version: None,
+            protocol_version: None,
+            status,
+        };
+        let report = CompatibilityReport {
+            client_version: "0.1.0".to_string(),
+            compatible: false,
+            services: vec![
+                service("validity-prover", ServiceStatus::Compatible),
+                service(
+                    "balance-prover",
+                    ServiceStatus::Unreachable {
+                        error: "connection refused".to_string(),
+                    },
+                ),
+                service(
+                    "withdrawal-server",
+                    ServiceStatus::ClientTooOld {
+                        reason: "client 0.1.0 is older than the required 0.2.0".to_string(),
+                    },
+                ),
+            ],
+            min_required_frontend_version: Some("0.2.0".to_string()),
+        };
+        let warnings = incompatibility_warnings(&report);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("withdrawal-server"));
+        assert_eq!(warnings[1], "update the CLI from 0.1.0 to at least 0.2.0");
+    }
+
+    #[test]
+    fn test_cached_check() {
+        let dir =
+            std::env::temp_dir().join(format!("intmax2-version-check-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let urls = ServiceUrls {
+            store_vault_server: "http://localhost:9000".to_string(),
+            ..Default::default()
+        };
+        let key = cache_key(&urls, "0.1.0");
+        assert_eq!(cached_warnings_in(&dir, &key, 1000), None);
+
+        let check = CachedCheck {
+            key: key.clone(),
+            checked_at: 1000,
+            warnings: vec!["update the CLI".to_string()],
+        };
+        save_check_in(&dir, &check).unwrap();
+        assert_eq!(
+            cached_warnings_in(&dir, &key, 1000 + 60),
+            Some(check.warnings.clone())
+        );
+        // a day later, or for other servers or another version, the check is redone
+        assert_eq!(
+            cached_warnings_in(&dir, &key, 1000 + VERSION_CHECK_CACHE_SECS),
+            None
+        );
+        assert_eq!(
+            cached_warnings_in(&dir, &cache_key(&urls, "0.2.0"), 1000),
+            None
+        );
+        let other = ServiceUrls {
+            block_builder: Some("http://localhost:9004".to_string()),
+            ..urls
+        };
+        assert_eq!(
+            cached_warnings_in(&dir, &cache_key(&other, "0.1.0"), 1000),
+            None
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use std::{collections::BTreeMap, fs, path::Path, sync::Arc, time::Duration};
+
+use intmax2_interfaces::api::{
+    output_stream::types::ProgressStage, validity_prover::interface::ValidityProverClientInterface,
+};
+use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::Env;
+
+use super::{
+    client::get_client_from,
+    error::CliError,
+    i18n::{tr, tr_with},
+    output::print_notice,
+    reload::ConfigReloader,
+    stream::{emit_progress, set_stream_result},
+    witness_cache::FileWitnessStore,
+};
+
+const WATCHER_STATE_FILE: &str = "watcher.json";
+const DEFAULT_WATCHER_INTERVAL: u64 = 10;
+
+/// Progress of the watcher, kept in the witness cache directory
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatcherState {
+    pub next_block_number: u32,
+    // last block in which each watched pubkey sent a tx, by hex pubkey
+    pub last_send_blocks: BTreeMap<String, u32>,
+}
+
+impl WatcherState {
+    pub fn load(dir: &Path) -> Result<Option<Self>, CliError> {
+        let path = dir.join(WATCHER_STATE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read_to_string(&path)
+            .map_err(|e| CliError::UnexpectedError(format!("Failed to read {:?}: {}", path, e)))?;
+        let state = serde_json::from_str(&data)
+            .map_err(|e| CliError::UnexpectedError(format!("Failed to parse {:?}: {}", path, e)))?;
+        Ok(Some(state))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), CliError> {
+        let path = dir.join(WATCHER_STATE_FILE);
+        let data = serde_json::to_string_pretty(self).unwrap();
+        fs::write(&path, data)
+            .map_err(|e| CliError::UnexpectedError(format!("Failed to write {:?}: {}", path, e)))
+    }
+}
+
+/// Follow new blocks and store the update witnesses the watched accounts will
+/// need in their next sync, until Ctrl-C. No private key is needed: the sends of
+/// an account are visible in the sender leaves of each block, and the witness of
+/// a send goes from the previous send block to the new one. Transitions through
+/// received transfers or deposits cannot be seen from the pubkey alone and are
+/// fetched by the sync as before.
+///
+/// Changes of the profile config (urls, tokens, `WATCHER_INTERVAL`) are applied
+/// between polls, without a restart.
+pub async fn watcher(
+    pubkeys: Vec<U256>,
+    from_block: Option<u32>,
+    interval: Option<u64>,
+) -> Result<(), CliError> {
+    let reloader = Arc::new(ConfigReloader::new()?);
+    let _config_watcher = reloader.spawn()?;
+    let env = reloader.env()?;
+    let store = FileWitnessStore::from_env(&env)?;
+    fs::create_dir_all(store.dir()).map_err(|e| {
+        CliError::UnexpectedError(format!(
+            "Failed to create witness cache dir {:?}: {}",
+            store.dir(),
+            e
+        ))
+    })?;
+    // the client stores the witnesses it fetches since the directory exists
+    let mut client = get_client_from(&env)?;
+
+    let mut state = WatcherState::load(store.dir())?.unwrap_or_default();
+    if let Some(from_block) = from_block {
+        state.next_block_number = from_block;
+    } else if state.next_block_number == 0 {
+        state.next_block_number = client.validity_prover.get_block_number().await? + 1;
+    }
+    print_notice(tr_with(
+        "msg.watching",
+        &[
+            ("accounts", &pubkeys.len()),
+            ("block", &state.next_block_number),
+            ("dir", &format!("{:?}", store.dir())),
+        ],
+    ));
+
+    loop {
+        let env = reloader.env()?;
+        if reloader.take_client_outdated() {
+            client = get_client_from(&env)?;
+        }
+        let interval = watcher_interval(interval, &env);
+        tokio::select! {
+            result = follow_blocks(&client.validity_prover, &pubkeys, &mut state, store.dir(), interval) => result?,
+            _ = tokio::signal::ctrl_c() => {
+                print_notice(tr("msg.watcher_stopped"));
+                set_stream_result(&json!({ "nextBlockNumber": state.next_block_number }));
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Seconds between polls: `--interval`, else `WATCHER_INTERVAL` of `env`
+pub fn watcher_interval(interval: Option<u64>, env: &Env) -> u64 {
+    interval
+        .or(env.watcher_interval)
+        .unwrap_or(DEFAULT_WATCHER_INTERVAL)
+}
+
+/// Process the blocks up to the latest one, then wait for the next poll
+async fn follow_blocks<V: ValidityProverClientInterface>(
+    validity_prover: &V,
+    pubkeys: &[U256],
+    state: &mut WatcherState,
+    dir: &Path,
+    interval: u64,
+) -> Result<(), CliError> {
+    let latest_block_number = validity_prover.get_block_number().await?;
+    while state.next_block_number <= latest_block_number {
+        let block_number = state.next_block_number;
+        let fetched = process_block(validity_prover, pubkeys, state, block_number).await?;
+        if fetched > 0 {
+            print_notice(tr_with(
+                "msg.witnesses_cached",
+                &[("block", &block_number), ("count", &fetched)],
+            ));
+        }
+        state.next_block_number += 1;
+        state.save(dir)?;
+        emit_progress(ProgressStage::Watch, block_number, latest_block_number);
+    }
+    tokio::time::sleep(Duration::from_secs(interval)).await;
+    Ok(())
+}
+
+/// Fetch the send witnesses of the watched accounts for `block_number`, and
+/// return how many were fetched
+async fn process_block<V: ValidityProverClientInterface>(
+    validity_prover: &V,
+    pubkeys: &[U256],
+    state: &mut WatcherState,
+    block_number: u32,
+) -> Result<usize, CliError> {
+    let sender_leaves = validity_prover
+        .get_sender_leaves(block_number)
+        .await?
+        .ok_or(CliError::UnexpectedError(format!(
+            "sender leaves not found for block number {}",
+            block_number
+        )))?;
+    let mut fetched = 0;
+    for leaf in sender_leaves {
+        // a sender that did not return its signature did not send
+        if !leaf.did_return_sig || !pubkeys.contains(&leaf.sender) {
+            continue;
+        }
+        let pubkey = leaf.sender.to_hex();
+        if let Some(&prev_block_number) = state.last_send_blocks.get(&pubkey) {
+            // stored by the client's witness store
+            validity_prover
+                .get_update_witness(leaf.sender, block_number, prev_block_number, true)
+                .await?;
+            fetched += 1;
+        }
+        state.last_send_blocks.insert(pubkey, block_number);
+    }
+    Ok(fetched)
+}
@@ -0,0 +1,701 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use intmax2_client_sdk::client::history::HistoryEntry;
+use intmax2_interfaces::api::{
+    webhook::types::{sign_webhook_body, WebhookEvent, WebhookPayload, WEBHOOK_SIGNATURE_HEADER},
+    withdrawal_server::interface::{WithdrawalInfo, WithdrawalStatus},
+};
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use tokio::task::JoinHandle;
+
+use crate::Env;
+
+use super::{
+    client::get_client,
+    error::CliError,
+    get::{history_index_path, load_history_index, save_history_index},
+    i18n::tr_with,
+    output::print_notice,
+    utils::{load_env, local_dir},
+};
+
+const QUEUE_FILE: &str = "queue.json";
+const DEAD_LETTER_FILE: &str = "dead_letter.json";
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+// how long a command waits at exit for the deliveries it started
+const DRAIN_TIMEOUT_SECS: u64 = 10;
+
+// deliveries started by this process, awaited by `finish_webhook_deliveries`
+static DELIVERIES: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+// serializes the flushes of this process, which share the queue file
+static FLUSH_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Posts signed `WebhookEvent`s to `WEBHOOK_URL`. Events are queued in a file
+/// before the first attempt. Each flush makes one attempt per queued event, and an
+/// event that failed `max_attempts` flushes is moved to `dead_letter.json`, so
+/// that it does not hold back the others.
+#[derive(Debug, Clone)]
+pub struct WebhookDispatcher {
+    url: String,
+    secret: String,
+    dir: PathBuf,
+    max_attempts: u32,
+}
+
+/// Queued event with the number of failed attempts to deliver it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedEvent {
+    pub event: WebhookEvent,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlushReport {
+    pub delivered: usize,
+    pub pending: usize,
+    /// Events moved to the dead letter file by this flush
+    pub dropped: usize,
+}
+
+/// What was notified for an account, so that each call only derives events from
+/// what is new. Incoming transfers are received by the sync in block order, so
+/// a block number is enough for them. Failed txs and settled withdrawals have no
+/// block and are remembered by event id.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookCursor {
+    /// Last block of the history already notified
+    pub block_number: u32,
+    pub notified_ids: BTreeSet<String>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(url: &str, secret: &str, dir: PathBuf) -> Self {
+        Self {
+            url: url.to_string(),
+            secret: secret.to_string(),
+            dir,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// The dispatcher of `WEBHOOK_URL` and `WEBHOOK_SECRET`, or `None` if no url is set
+    pub fn from_env(env: &Env) -> Result<Option<Self>, CliError> {
+        let Some(url) = &env.webhook_url else {
+            return Ok(None);
+        };
+        let secret = env.webhook_secret.as_ref().ok_or(CliError::WebhookError(
+            "WEBHOOK_SECRET is required with WEBHOOK_URL".to_string(),
+        ))?;
+        Ok(Some(Self::new(url, secret, local_dir()?.join("webhooks"))))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Post `event` once, without retries
+    pub async fn post(&self, event: &WebhookEvent) -> Result<(), String> {
+        let body = serde_json::to_vec(event).map_err(|e| e.to_string())?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+            .build()
+            .map_err(|e| e.to_string())?;
+        let response = client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header(
+                WEBHOOK_SIGNATURE_HEADER,
+                sign_webhook_body(&self.secret, &body),
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("status {}", response.status()));
+        }
+        Ok(())
+    }
+
+    /// Add `events` to the queue, to be delivered by the next flush
+    pub fn enqueue(&self, events: Vec<WebhookEvent>) -> Result<(), CliError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let mut queue = self.load_queue()?;
+        queue.extend(events.into_iter().map(|event| QueuedEvent {
+            event,
+            attempts: 0,
+            last_error: None,
+        }));
+        self.save_queue(&queue)
+    }
+
+    /// Make one attempt to deliver each queued event, in order. A failed event
+    /// stays queued until it has failed `max_attempts` times, without holding back
+    /// the events after it.
+    pub async fn flush(&self) -> Result<FlushReport, CliError> {
+        let _lock = FLUSH_LOCK.lock().await;
+        let mut queue = self.load_queue()?;
+        let mut report = FlushReport {
+            delivered: 0,
+            pending: 0,
+            dropped: 0,
+        };
+        let mut i = 0;
+        while i < queue.len() {
+            let entry = &mut queue[i];
+            match self.post(&entry.event).await {
+                Ok(()) => {
+                    queue.remove(i);
+                    report.delivered += 1;
+                }
+                Err(e) => {
+                    entry.attempts += 1;
+                    entry.last_error = Some(e.clone());
+                    if entry.attempts >= self.max_attempts {
+                        log::warn!(
+                            "Webhook {} failed {} times, moved to {}: {}",
+                            entry.event.id,
+                            entry.attempts,
+                            DEAD_LETTER_FILE,
+                            e
+                        );
+                        let entry = queue.remove(i);
+                        let mut dead = self.load_dead_letters()?;
+                        dead.push(entry);
+                        write_json(&self.dir, DEAD_LETTER_FILE, &dead)?;
+                        report.dropped += 1;
+                    } else {
+                        log::warn!(
+                            "Webhook {} attempt {} failed: {}",
+                            entry.event.id,
+                            entry.attempts,
+                            e
+                        );
+                        i += 1;
+                    }
+                }
+            }
+            self.save_queue(&queue)?;
+        }
+        report.pending = queue.len();
+        Ok(report)
+    }
+
+    fn load_queue(&self) -> Result<Vec<QueuedEvent>, CliError> {
+        let path = self.dir.join(QUEUE_FILE);
+        // queues written before the attempts were counted hold bare events
+        if let Ok(Some(events)) = read_json::<Vec<WebhookEvent>>(&path) {
+            return Ok(events
+                .into_iter()
+                .map(|event| QueuedEvent {
+                    event,
+                    attempts: 0,
+                    last_error: None,
+                })
+                .collect());
+        }
+        Ok(read_json(&path)?.unwrap_or_default())
+    }
+
+    fn save_queue(&self, queue: &[QueuedEvent]) -> Result<(), CliError> {
+        write_json(&self.dir, QUEUE_FILE, &queue)
+    }
+
+    /// Events given up on, with the error of their last attempt
+    pub fn load_dead_letters(&self) -> Result<Vec<QueuedEvent>, CliError> {
+        Ok(read_json(&self.dir.join(DEAD_LETTER_FILE))?.unwrap_or_default())
+    }
+
+    fn cursor_file(pubkey: U256) -> String {
+        format!("cursor_{}.json", pubkey.to_hex())
+    }
+
+    /// Cursor of the account, or `None` before its first notification
+    pub fn load_cursor(&self, pubkey: U256) -> Result<Option<WebhookCursor>, CliError> {
+        read_json(&self.dir.join(Self::cursor_file(pubkey)))
+    }
+
+    pub fn save_cursor(&self, pubkey: U256, cursor: &WebhookCursor) -> Result<(), CliError> {
+        write_json(&self.dir, &Self::cursor_file(pubkey), cursor)
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, CliError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = fs::read_to_string(path)
+        .map_err(|e| CliError::WebhookError(format!("Failed to read {:?}: {}", path, e)))?;
+    let value = serde_json::from_str(&data)
+        .map_err(|e| CliError::WebhookError(format!("Failed to parse {:?}: {}", path, e)))?;
+    Ok(Some(value))
+}
+
+fn write_json<T: Serialize>(dir: &Path, file_name: &str, value: &T) -> Result<(), CliError> {
+    let path = dir.join(file_name);
+    let tmp_path = path.with_extension("tmp");
+    let data = serde_json::to_string_pretty(value).unwrap();
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&tmp_path, data))
+        .and_then(|_| fs::rename(&tmp_path, &path))
+        .map_err(|e| CliError::WebhookError(format!("Failed to write {:?}: {}", path, e)))
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+fn event_id(pubkey: U256, kind: &str, fields: &str, occurrence: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{}:{}:{}:{}",
+        pubkey.to_hex(),
+        kind,
+        fields,
+        occurrence
+    ));
+    hex::encode(hasher.finalize())
+}
+
+/// Webhook events of the history and withdrawals of an account not notified as of
+/// `cursor`, and the cursor after them. The history has no ids, so identical
+/// entries of a block are told apart by their order.
+pub fn account_events(
+    pubkey: U256,
+    history: &[HistoryEntry],
+    withdrawal_info: &[WithdrawalInfo],
+    cursor: &WebhookCursor,
+) -> (Vec<WebhookEvent>, WebhookCursor) {
+    let created_at = now();
+    let mut occurrences = HashMap::<String, usize>::new();
+    let mut event = |kind: &str, fields: String, payload: WebhookPayload| {
+        let occurrence = occurrences
+            .entry(format!("{}:{}", kind, fields))
+            .or_default();
+        *occurrence += 1;
+        WebhookEvent {
+            id: event_id(pubkey, kind, &fields, *occurrence),
+            pubkey,
+            created_at,
+            payload,
+        }
+    };
+    let mut next_cursor = cursor.clone();
+    let mut events = Vec::new();
+    for entry in history {
+        match entry {
+            HistoryEntry::Receive {
+                amount,
+                token_index,
+                from,
+                is_rejected: false,
+                is_expired: false,
+                timestamp,
+                block_number: Some(block_number),
+            } if *block_number > cursor.block_number => events.push(event(
+                "incomingTransfer",
+                format!(
+                    "{}:{}:{}:{}",
+                    block_number,
+                    from.to_hex(),
+                    token_index,
+                    amount
+                ),
+                WebhookPayload::IncomingTransfer {
+                    from: *from,
+                    token_index: *token_index,
+                    amount: *amount,
+                    timestamp: *timestamp,
+                },
+            )),
+            HistoryEntry::Send {
+                transfers,
+                is_rejected: true,
+                timestamp,
+                ..
+            } => {
+                let failed = event(
+                    "txFailed",
+                    serde_json::to_string(transfers).unwrap(),
+                    WebhookPayload::TxFailed {
+                        num_transfers: transfers.len() as u32,
+                        timestamp: *timestamp,
+                    },
+                );
+                if next_cursor.notified_ids.insert(failed.id.clone()) {
+                    events.push(failed);
+                }
+            }
+            _ => {}
+        }
+        let (HistoryEntry::Deposit { block_number, .. }
+        | HistoryEntry::Receive { block_number, .. }
+        | HistoryEntry::Send { block_number, .. }) = entry;
+        if let Some(block_number) = block_number {
+            next_cursor.block_number = next_cursor.block_number.max(*block_number);
+        }
+    }
+    for info in withdrawal_info {
+        if !matches!(info.status, WithdrawalStatus::Success) {
+            continue;
+        }
+        let withdrawal = &info.contract_withdrawal;
+        let settled = event(
+            "withdrawalSettled",
+            withdrawal.nullifier.to_hex(),
+            WebhookPayload::WithdrawalSettled {
+                recipient: withdrawal.recipient,
+                token_index: withdrawal.token_index,
+                amount: withdrawal.amount,
+                nullifier: withdrawal.nullifier,
+            },
+        );
+        if next_cursor.notified_ids.insert(settled.id.clone()) {
+            events.push(settled);
+        }
+    }
+    (events, next_cursor)
+}
+
+/// Queue the events of the account that appeared since the last call and deliver
+/// them in the background, if `WEBHOOK_URL` is set. Failures are logged and never
+/// fail the command that synced. See `finish_webhook_deliveries`.
+pub fn notify_account_events(key: KeySet) {
+    let handle = tokio::spawn(async move {
+        if let Err(e) = notify_account_events_inner(key).await {
+            log::warn!("Failed to notify webhook: {}", e);
+        }
+    });
+    DELIVERIES.lock().unwrap().push(handle);
+}
+
+/// Wait up to `DRAIN_TIMEOUT_SECS` for the deliveries started by this process.
+/// The events not delivered by then stay queued for the next sync or `webhook
+/// flush`.
+pub async fn finish_webhook_deliveries() {
+    let handles = std::mem::take(&mut *DELIVERIES.lock().unwrap());
+    if handles.is_empty() {
+        return;
+    }
+    let wait = async {
+        for handle in handles {
+            let _ = handle.await;
+        }
+    };
+    if tokio::time::timeout(Duration::from_secs(DRAIN_TIMEOUT_SECS), wait)
+        .await
+        .is_err()
+    {
+        log::warn!("Webhook delivery still running at exit, the rest stays queued");
+    }
+}
+
+async fn notify_account_events_inner(key: KeySet) -> Result<(), CliError> {
+    let env = load_env()?;
+    let Some(dispatcher) = WebhookDispatcher::from_env(&env)? else {
+        return Ok(());
+    };
+    let client = get_client()?;
+    // only the blobs saved since the last call are fetched, through the history index
+    let path = history_index_path(key)?;
+    let (history, index) = client
+        .fetch_history_with_index(key, load_history_index(&path, key))
+        .await?;
+    save_history_index(&path, &index);
+    let withdrawal_info = client.get_withdrawal_info(key).await?;
+    let cursor = dispatcher.load_cursor(key.pubkey)?;
+    let is_first = cursor.is_none();
+    let (events, cursor) = account_events(
+        key.pubkey,
+        &history,
+        &withdrawal_info,
+        &cursor.unwrap_or_default(),
+    );
+    // the first call for an account only records the cursor, so that enabling
+    // webhooks does not replay the whole history
+    if !is_first {
+        dispatcher.enqueue(events)?;
+    }
+    dispatcher.save_cursor(key.pubkey, &cursor)?;
+    let report = dispatcher.flush().await?;
+    if report.delivered > 0 || report.pending > 0 || report.dropped > 0 {
+        print_notice(tr_with(
+            "msg.webhook_report",
+            &[
+                ("delivered", &report.delivered),
+                ("pending", &report.pending),
+                ("dropped", &report.dropped),
+            ],
+        ));
+    }
+    Ok(())
+}
+
+/// Send a signed ping to `WEBHOOK_URL`
+pub async fn webhook_test(pubkey: U256) -> Result<(), CliError> {
+    let env = load_env()?;
+    let dispatcher = WebhookDispatcher::from_env(&env)?
+        .ok_or(CliError::WebhookError("WEBHOOK_URL is not set".to_string()))?;
+    let created_at = now();
+    let event = WebhookEvent {
+        id: event_id(pubkey, "ping", "", created_at as usize),
+        pubkey,
+        created_at,
+        payload: WebhookPayload::Ping,
+    };
+    dispatcher
+        .post(&event)
+        .await
+        .map_err(|e| CliError::WebhookError(format!("Ping failed: {}", e)))?;
+    println!(
+        "{}",
+        tr_with("msg.ping_delivered", &[("url", &dispatcher.url)])
+    );
+    Ok(())
+}
+
+/// Make one more attempt to deliver the events left in the queue by earlier runs
+pub async fn webhook_flush() -> Result<(), CliError> {
+    let env = load_env()?;
+    let dispatcher = WebhookDispatcher::from_env(&env)?
+        .ok_or(CliError::WebhookError("WEBHOOK_URL is not set".to_string()))?;
+    let report = dispatcher.flush().await?;
+    println!(
+        "{} events delivered, {} pending, {} given up",
+        report.delivered, report.pending, report.dropped
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    };
+
+    use intmax2_interfaces::api::webhook::types::verify_webhook_signature;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+    };
+
+    use crate::cli::utils::test_dir;
+
+    use super::*;
+
+    type Received = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+    // Answers every request with 503 while `down` is set, and those of the event
+    // "bad" always, else records the signature header and body and answers 200
+    async fn start_receiver(down: Arc<AtomicBool>) -> (String, Received) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let received = Received::default();
+        let received_clone = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let header_end = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break i + 4;
+                    }
+                };
+                let head = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+                let header = |name: &str| {
+                    head.lines()
+                        .find_map(|line| line.strip_prefix(&format!("{}: ", name)))
+                        .map(|value| value.trim().to_string())
+                };
+                let content_length: usize = header("content-length").unwrap().parse().unwrap();
+                while request.len() < header_end + content_length {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let body = request[header_end..].to_vec();
+                let is_bad = serde_json::from_slice::<WebhookEvent>(&body).unwrap().id == "bad";
+                let status = if down.load(Ordering::SeqCst) || is_bad {
+                    "503 Service Unavailable"
+                } else {
+                    let signature = header(&WEBHOOK_SIGNATURE_HEADER.to_lowercase()).unwrap();
+                    received_clone.lock().unwrap().push((signature, body));
+                    "200 OK"
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn test_event(id: &str) -> WebhookEvent {
+        WebhookEvent {
+            id: id.to_string(),
+            pubkey: U256::default(),
+            created_at: 0,
+            payload: WebhookPayload::Ping,
+        }
+    }
+
+    fn received_ids(received: &Received) -> Vec<String> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(signature, body)| {
+                assert!(verify_webhook_signature("secret", body, signature));
+                serde_json::from_slice::<WebhookEvent>(body).unwrap().id
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery_and_redelivery() {
+        let dir = test_dir("webhook-delivery");
+        let down = Arc::new(AtomicBool::new(true));
+        let (url, received) = start_receiver(down.clone()).await;
+        let dispatcher = WebhookDispatcher::new(&url, "secret", dir.clone());
+
+        // the receiver is down: both events stay queued
+        dispatcher
+            .enqueue(vec![test_event("a"), test_event("b")])
+            .unwrap();
+        let report = dispatcher.flush().await.unwrap();
+        assert_eq!(
+            report,
+            FlushReport {
+                delivered: 0,
+                pending: 2,
+                dropped: 0
+            }
+        );
+        assert!(received.lock().unwrap().is_empty());
+
+        // back up: a later flush delivers them in order
+        down.store(false, Ordering::SeqCst);
+        let report = dispatcher.flush().await.unwrap();
+        assert_eq!(
+            report,
+            FlushReport {
+                delivered: 2,
+                pending: 0,
+                dropped: 0
+            }
+        );
+        assert_eq!(received_ids(&received), vec!["a", "b"]);
+        assert_eq!(dispatcher.flush().await.unwrap().delivered, 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_failing_event_is_given_up() {
+        let dir = test_dir("webhook-give-up");
+        let (url, received) = start_receiver(Arc::new(AtomicBool::new(false))).await;
+        let dispatcher = WebhookDispatcher::new(&url, "secret", dir.clone()).with_max_attempts(2);
+
+        // the event after the failing one is delivered anyway
+        dispatcher
+            .enqueue(vec![test_event("bad"), test_event("a")])
+            .unwrap();
+        let report = dispatcher.flush().await.unwrap();
+        assert_eq!(
+            report,
+            FlushReport {
+                delivered: 1,
+                pending: 1,
+                dropped: 0
+            }
+        );
+
+        dispatcher.enqueue(vec![test_event("b")]).unwrap();
+        let report = dispatcher.flush().await.unwrap();
+        assert_eq!(
+            report,
+            FlushReport {
+                delivered: 1,
+                pending: 0,
+                dropped: 1
+            }
+        );
+        assert_eq!(received_ids(&received), vec!["a", "b"]);
+        let dead = dispatcher.load_dead_letters().unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].event, test_event("bad"));
+        assert_eq!(dead[0].attempts, 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn receive(from: u32, block_number: Option<u32>) -> HistoryEntry {
+        HistoryEntry::Receive {
+            amount: U256::from(1u128),
+            token_index: 0,
+            from: U256::from(from as u128),
+            is_rejected: false,
+            is_expired: false,
+            timestamp: None,
+            block_number,
+        }
+    }
+
+    #[test]
+    fn test_events_after_cursor() {
+        let pubkey = U256::default();
+        let failed = HistoryEntry::Send {
+            transfers: Vec::new(),
+            fee: None,
+            is_rejected: true,
+            claim_deadline_block: None,
+            timestamp: None,
+            block_number: None,
+        };
+        let mut history = vec![receive(1, Some(5)), receive(2, None), failed];
+        let (events, cursor) = account_events(pubkey, &history, &[], &WebhookCursor::default());
+        assert_eq!(events.len(), 2);
+        assert_eq!(cursor.block_number, 5);
+        assert_eq!(cursor.notified_ids.len(), 1);
+
+        // nothing new
+        let (events, next_cursor) = account_events(pubkey, &history, &[], &cursor);
+        assert!(events.is_empty());
+        assert_eq!(next_cursor, cursor);
+
+        // the pending transfer is received in a later block
+        history[1] = receive(2, Some(7));
+        let (events, cursor) = account_events(pubkey, &history, &[], &cursor);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0].payload,
+            WebhookPayload::IncomingTransfer { from, .. } if from == U256::from(2u128)
+        ));
+        assert_eq!(cursor.block_number, 7);
+    }
+}
@@ -0,0 +1,68 @@
+use std::{fs, path::Path};
+
+use ethers::types::{Address as EthAddress, H256};
+use intmax2_client_sdk::client::{
+    error::ClientError,
+    witness_bundle::{prepare_public_witness_bundle, SignedWitnessBundle},
+};
+use intmax2_interfaces::api::validity_prover::interface::ValidityProverClientInterface as _;
+use intmax2_zkp::{common::signature::key_set::KeySet, ethereum_types::u256::U256};
+
+use super::{
+    client::get_client, error::CliError, i18n::tr_with, sync::handle_sync_result,
+    webhook::notify_account_events,
+};
+
+/// Write the witness bundle of `pubkey` for the blocks `from_block..=to_block`, or
+/// up to the latest block, signed with `signer_key`, to `output`
+pub async fn witness_bundle_create(
+    pubkey: U256,
+    from_block: u32,
+    to_block: Option<u32>,
+    signer_key: H256,
+    output: &Path,
+) -> Result<(), CliError> {
+    let client = get_client()?;
+    let to_block = match to_block {
+        Some(to_block) => to_block,
+        None => client.validity_prover.get_block_number().await?,
+    };
+    let bundle =
+        prepare_public_witness_bundle(&client.validity_prover, pubkey, from_block, to_block)
+            .await?;
+    let signed = SignedWitnessBundle::sign(bundle, signer_key).map_err(ClientError::from)?;
+    fs::write(output, signed.to_bytes())
+        .map_err(|e| CliError::UnexpectedError(format!("Failed to write {:?}: {}", output, e)))?;
+    println!(
+        "{}",
+        tr_with(
+            "msg.witness_bundle_written",
+            &[
+                ("from", &from_block),
+                ("to", &to_block),
+                ("sends", &signed.bundle.send_witnesses.len()),
+                ("path", &format!("{:?}", output)),
+                ("signer", &format!("{:?}", signed.signer))
+            ]
+        )
+    );
+    Ok(())
+}
+
+/// Sync `key` with the witness bundle in `file`, which must be signed by `signer`
+/// if given
+pub async fn witness_bundle_use(
+    key: KeySet,
+    file: &Path,
+    signer: Option<EthAddress>,
+) -> Result<bool, CliError> {
+    let bytes = fs::read(file)
+        .map_err(|e| CliError::UnexpectedError(format!("Failed to read {:?}: {}", file, e)))?;
+    let bundle = SignedWitnessBundle::from_bytes(&bytes).map_err(ClientError::from)?;
+    let client = get_client()?;
+    let synced = handle_sync_result(client.sync_with_witness_bundle(key, &bundle, signer).await)?;
+    if synced {
+        notify_account_events(key);
+    }
+    Ok(synced)
+}
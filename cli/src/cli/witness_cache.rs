@@ -0,0 +1,281 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use intmax2_client_sdk::external_api::cached_validity_prover::{WitnessKey, WitnessStore};
+use intmax2_zkp::{
+    common::witness::update_witness::UpdateWitness,
+    ethereum_types::u32limb_trait::U32LimbTrait as _,
+};
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+
+use crate::Env;
+
+use super::{error::CliError, utils::local_dir};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+pub const DEFAULT_WITNESS_CACHE_MAX_MB: u64 = 512;
+
+#[derive(Serialize, Deserialize)]
+struct WitnessFile {
+    // sha256 of `witness`, computed when the file is written
+    checksum: [u8; 32],
+    // the bincode of the update witness
+    witness: Vec<u8>,
+}
+
+fn checksum(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+/// Sizes of the witness files, read from the directory once and then kept up to
+/// date by `put`
+#[derive(Debug, Default)]
+struct CacheUsage {
+    total: u64,
+    // size of each file by root block and file name, oldest root blocks first
+    files: BTreeMap<(u32, String), u64>,
+}
+
+impl CacheUsage {
+    fn scan(dir: &Path) -> Self {
+        let mut usage = Self::default();
+        let Ok(entries) = fs::read_dir(dir) else {
+            return usage;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if let (Some(file), Ok(metadata)) = (file_key(&path), entry.metadata()) {
+                usage.insert(file, metadata.len());
+            }
+        }
+        usage
+    }
+
+    fn insert(&mut self, file: (u32, String), size: u64) {
+        if let Some(old_size) = self.files.insert(file, size) {
+            self.total -= old_size;
+        }
+        self.total += size;
+    }
+
+    fn remove(&mut self, file: &(u32, String)) {
+        if let Some(size) = self.files.remove(file) {
+            self.total -= size;
+        }
+    }
+}
+
+/// Update witnesses kept as files named `<root>_<leaf>_<pubkey>_<is_prev>.bin`.
+/// Once the directory exceeds `max_bytes`, the witnesses of the oldest root blocks
+/// are removed first.
+#[derive(Debug, Clone)]
+pub struct FileWitnessStore {
+    dir: PathBuf,
+    max_bytes: u64,
+    usage: Arc<Mutex<Option<CacheUsage>>>,
+}
+
+impl FileWitnessStore {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            usage: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The store at `WITNESS_CACHE_DIR`, or `~/.intmax2/witness_cache` if it is unset
+    pub fn from_env(env: &Env) -> Result<Self, CliError> {
+        let dir = match &env.witness_cache_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => local_dir()?.join("witness_cache"),
+        };
+        let max_mb = env
+            .witness_cache_max_mb
+            .unwrap_or(DEFAULT_WITNESS_CACHE_MAX_MB);
+        Ok(Self::new(dir, max_mb * 1024 * 1024))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &WitnessKey) -> PathBuf {
+        self.dir.join(format!(
+            "{}_{}_{}_{}.bin",
+            key.root_block_number,
+            key.leaf_block_number,
+            key.pubkey.to_hex(),
+            key.is_prev_account_tree as u8
+        ))
+    }
+
+    /// The bytes written to `path` by `write`, or `None` if they do not match
+    /// their checksum
+    fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        let bytes = fs::read(path).ok()?;
+        let file: WitnessFile = bincode::deserialize(&bytes).ok()?;
+        (checksum(&file.witness) == file.checksum).then_some(file.witness)
+    }
+
+    /// Write `witness` to `path` with its checksum, then evict the oldest root
+    /// blocks beyond `max_bytes`
+    fn write(&self, path: &Path, witness: Vec<u8>) -> std::io::Result<()> {
+        let file = WitnessFile {
+            checksum: checksum(&witness),
+            witness,
+        };
+        let bytes = bincode::serialize(&file).map_err(std::io::Error::other)?;
+        let size = bytes.len() as u64;
+        // written under another name first so that a reader never sees a partial file
+        let tmp_path = path.with_extension("tmp");
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&tmp_path, bytes))
+            .and_then(|_| fs::rename(&tmp_path, path))?;
+
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.get_or_insert_with(|| CacheUsage::scan(&self.dir));
+        if let Some(file) = file_key(path) {
+            usage.insert(file, size);
+        }
+        while usage.total > self.max_bytes {
+            let Some((file, size)) = usage.files.pop_first() else {
+                break;
+            };
+            usage.total -= size;
+            if let Err(e) = fs::remove_file(self.dir.join(&file.1)) {
+                log::warn!("Failed to evict witness cache file {}: {}", file.1, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) {
+        let _ = fs::remove_file(path);
+        if let (Some(usage), Some(file)) = (self.usage.lock().unwrap().as_mut(), file_key(path)) {
+            usage.remove(&file);
+        }
+    }
+}
+
+impl WitnessStore for FileWitnessStore {
+    fn get(&self, key: &WitnessKey) -> Option<UpdateWitness<F, C, D>> {
+        let path = self.path(key);
+        if !path.exists() {
+            return None;
+        }
+        let witness = self
+            .read(&path)
+            .and_then(|bytes| bincode::deserialize(&bytes).ok());
+        if witness.is_none() {
+            log::warn!("Removing corrupted witness cache file {:?}", path);
+            self.remove(&path);
+        }
+        witness
+    }
+
+    fn put(&self, key: &WitnessKey, witness: &UpdateWitness<F, C, D>) {
+        let bytes = match bincode::serialize(witness) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::warn!("Failed to serialize update witness {:?}: {}", key, e);
+                return;
+            }
+        };
+        let path = self.path(key);
+        if let Err(e) = self.write(&path, bytes) {
+            log::warn!("Failed to write witness cache file {:?}: {}", path, e);
+        }
+    }
+}
+
+/// Root block and name of a witness file, by which files are evicted
+fn file_key(path: &Path) -> Option<(u32, String)> {
+    if path.extension()? != "bin" {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?;
+    let root_block_number = name.split('_').next()?.parse().ok()?;
+    Some((root_block_number, name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_evict_oldest_roots_first() {
+        let dir =
+            std::env::temp_dir().join(format!("intmax2-witness-cache-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // written before the store was opened, counted by its first write
+        fs::write(dir.join("9_7_0x01_1.bin"), vec![0u8; 100]).unwrap();
+        fs::write(dir.join("watcher.json"), vec![0u8; 500]).unwrap();
+        let store = FileWitnessStore::new(dir.clone(), 250);
+        store
+            .write(&dir.join("12_10_0x01_1.bin"), vec![1u8; 60])
+            .unwrap();
+        let size = fs::metadata(dir.join("12_10_0x01_1.bin")).unwrap().len();
+        assert_eq!(
+            store.usage.lock().unwrap().as_ref().unwrap().total,
+            100 + size
+        );
+
+        store
+            .write(&dir.join("15_12_0x01_0.bin"), vec![2u8; 60])
+            .unwrap();
+        // files other than witnesses are neither counted nor removed
+        assert_eq!(
+            names(&dir),
+            vec!["12_10_0x01_1.bin", "15_12_0x01_0.bin", "watcher.json"]
+        );
+        assert_eq!(
+            store.usage.lock().unwrap().as_ref().unwrap().total,
+            2 * size
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_corrupted_file_is_detected() {
+        let dir = std::env::temp_dir().join(format!(
+            "intmax2-witness-cache-checksum-test-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let store = FileWitnessStore::new(dir.clone(), 1024);
+        let path = dir.join("3_2_0x01_0.bin");
+        store.write(&path, b"witness".to_vec()).unwrap();
+        assert_eq!(store.read(&path).unwrap(), b"witness".to_vec());
+
+        // a flipped byte of the witness no longer matches the checksum written
+        // with it
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, bytes).unwrap();
+        assert!(store.read(&path).is_none());
+        store.remove(&path);
+        assert!(names(&dir).is_empty());
+        assert_eq!(store.usage.lock().unwrap().as_ref().unwrap().total, 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -3,18 +3,28 @@ use serde::{Deserialize, Serialize};
 
 pub mod cli;
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Env {
     pub env: EnvType,
 
     // client settings
     pub indexer_base_url: String,
     pub store_vault_server_base_url: String,
+    // read and write endpoints of the store vault, both default to store_vault_server_base_url
+    pub store_vault_read_url: Option<String>,
+    pub store_vault_write_url: Option<String>,
+    #[serde(default)]
+    pub secondary_store_vault_urls: Vec<String>,
     pub validity_prover_base_url: String,
     pub balance_prover_base_url: String,
+    // bearer tokens of the provers (optional, read by the clients from the
+    // environment when not set here)
+    pub validity_prover_bearer_token: Option<String>,
+    pub balance_prover_bearer_token: Option<String>,
     pub withdrawal_server_base_url: String,
     pub deposit_timeout: u64,
     pub tx_timeout: u64,
+    pub checkpoint_interval: Option<u32>,
 
     // block builder settings
     pub block_builder_request_interval: u64,
@@ -22,18 +32,64 @@ pub struct Env {
     pub block_builder_query_wait_time: u64,
     pub block_builder_query_interval: u64,
     pub block_builder_query_limit: u64,
+    // seconds without a posted block after which the block builder is considered stalled (optional)
+    pub builder_max_staleness: Option<u64>,
+    // fail instead of polling again when the block builder returns a proposal for another tx (optional)
+    pub strict_proposal_validation: Option<bool>,
+    // seconds a withdrawal reported as done may take to appear on L1 (optional)
+    pub withdrawal_onchain_grace: Option<u64>,
 
     // blockchain settings
     pub l1_rpc_url: String,
+    // ws:// or wss:// RPC through which `observe --follow` is pushed the deposits (optional)
+    pub l1_ws_rpc_url: Option<String>,
     pub l1_chain_id: u64,
     pub liquidity_contract_address: Address,
+    // block the events of the liquidity contract are searched from (optional, default 0)
+    pub liquidity_contract_deployed_block_number: Option<u64>,
     pub l2_rpc_url: String,
     pub l2_chain_id: u64,
     pub rollup_contract_address: Address,
     pub rollup_contract_deployed_block_number: u64,
 
+    // optional gas settings of deposits and claims
+    pub gas_strategy: Option<String>,
+    pub stuck_tx_timeout: Option<u64>,
+    pub max_fee_bumps: Option<u32>,
+
+    // optional multicall settings for claiming withdrawals
+    pub multicall_address: Option<Address>,
+    pub claim_block_gas_fraction: Option<f64>,
+
+    // days to keep completed rebuild sessions of `recover` (optional, default 7)
+    pub rebuild_session_retention_days: Option<u64>,
+
     // optional block builder base url
     pub block_builder_base_url: Option<String>,
+
+    // optional remote service signing the block proposals, with its bearer token and
+    // the pubkey of the account whose key it holds, used by `tx` if no key is given
+    pub remote_signer_url: Option<String>,
+    pub remote_signer_token: Option<String>,
+    pub remote_signer_pubkey: Option<String>,
+
+    // update witnesses cached by `watcher` (optional, default ~/.intmax2/witness_cache, 512 MB)
+    pub witness_cache_dir: Option<String>,
+    pub witness_cache_max_mb: Option<u64>,
+    // seconds between polls of `watcher` when not given by --interval (optional, default 10)
+    pub watcher_interval: Option<u64>,
+
+    // optional url notified of the account events detected by `sync` and
+    // `sync-withdrawals`, with the secret of the signature header
+    pub webhook_url: Option<String>,
+    pub webhook_secret: Option<String>,
+
+    // S3-compatible storage of `backup push` and `backup pull` (optional, default
+    // the AWS endpoint of the region, us-east-1, unsigned requests)
+    pub offsite_backup_endpoint: Option<String>,
+    pub offsite_backup_region: Option<String>,
+    pub offsite_backup_access_key_id: Option<String>,
+    pub offsite_backup_secret_access_key: Option<String>,
 }
 
 #[derive(Clone, Debug, Copy, PartialEq, Serialize, Deserialize)]
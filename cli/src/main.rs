@@ -1,21 +1,69 @@
+use std::{path::PathBuf, time::Duration};
+
 use anyhow::{bail, ensure};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory as _, Parser, Subcommand};
 use ethers::types::{Address as EthAddress, H256, U256 as EthU256};
 use intmax2_cli::cli::{
-    deposit::deposit,
-    get::{balance, history, withdrawal_status},
-    send::tx,
-    sync::{sync, sync_withdrawals},
+    address_book::{address_book_add, address_book_list, address_book_remove, resolve_recipient},
+    backup::backup_status,
+    batch::send_batch,
+    builders::builders_stats,
+    claim::claim_withdrawals,
+    complete::{complete, completion_script, Shell},
+    config_file::{config_init, config_show, load_config_file, set_config_file},
+    consolidate::consolidate,
+    deadline::{global_deadlines, parse_duration, timeout_of, Phase, TIMEOUT_EXIT_CODE},
+    debug::debug_nonce,
+    deposit::{deposit, deposit_claim_external, deposit_request},
+    doctor::doctor,
+    get::{
+        balance, balance_at, balance_optimistic, balance_series_csv, expired_transfers,
+        fee_disputes, fee_estimate, history, limits, wait_for_withdrawal, withdrawal_status,
+        FeeKindArg, HistoryFilter, HistoryStatus, DEFAULT_WITHDRAWAL_POLL_INTERVAL,
+    },
+    i18n::{lang, set_lang, tr, tr_with, Lang},
+    init::{init, parse_setting, InitOptions, Preset},
+    key::{key_export, key_generate, key_import, key_import_json, key_recover, KeyArgs},
+    lock::AccountLock,
+    observe::observe,
+    output::{print_error, set_json_output, OutputMode},
+    profile::{apply_profile_env, DEFAULT_PROFILE},
+    receipt::{receipt_export, receipt_verify},
+    recover::{recover, restore_user_data},
+    rotate::rotate_key,
+    send::{single_transfer, tx, tx_dry_run},
+    session::run_in_session,
+    stream::{finish_stream, is_ndjson_stream, set_stream_result, start_ndjson_stream},
+    support_bundle::{support_bundle, support_bundle_inspect},
+    sync::{sync, sync_token, sync_verified, sync_withdrawals},
+    template::{template_list, template_save, use_template},
+    tx_status::tx_status,
+    utils::{load_env, parse_id_arg, parse_salt_arg, set_plain_ids},
+    version_check::check_versions,
+    watcher::watcher,
+    webhook::{finish_webhook_deliveries, webhook_flush, webhook_test},
+    witness_bundle::{witness_bundle_create, witness_bundle_use},
+};
+#[cfg(feature = "offsite-backup")]
+use intmax2_cli::cli::{
+    offsite_backup::{backup_pull, backup_push, backup_schedule},
+    s3::S3Target,
 };
-use intmax2_client_sdk::utils::init_logger::init_logger;
-use intmax2_interfaces::data::deposit_data::TokenType;
+use intmax2_client_sdk::{
+    client::{balance_history::SeriesInterval, external_deposit::DepositSource},
+    external_api::contract::gas::GasStrategyConfig,
+    utils::init_logger::{init_logger, init_logger_with_filter},
+};
+use intmax2_interfaces::{api::output_stream::types::StreamEvent, data::deposit_data::TokenType};
 use intmax2_zkp::{
-    common::{generic_address::GenericAddress, signature::key_set::KeySet},
+    common::{generic_address::GenericAddress, salt::Salt, signature::key_set::KeySet},
     ethereum_types::{
-        address::Address as IAddress, u256::U256 as IU256, u32limb_trait::U32LimbTrait,
+        address::Address as IAddress, bytes32::Bytes32, u256::U256 as IU256,
+        u32limb_trait::U32LimbTrait,
     },
 };
 use num_bigint::BigUint;
+use serde_json::json;
 
 #[derive(Parser)]
 #[clap(name = "intmax2_cli")]
@@ -23,125 +71,1448 @@ use num_bigint::BigUint;
 struct Args {
     #[clap(subcommand)]
     command: Commands,
+    /// Wait for other commands using the same account to finish instead of failing.
+    /// With `tx status`, wait until the tx is included or expired.
+    #[clap(long, global = true)]
+    wait: bool,
+    /// Print debug logs, including the fees chosen for contract calls
+    #[clap(long, global = true)]
+    verbose: bool,
+    /// Fees of deposits and claims, overriding GAS_STRATEGY (e.g. legacy:1.2,
+    /// eip1559:50:10, fixed:30:2)
+    #[clap(long, global = true)]
+    gas_strategy: Option<String>,
+    /// Profile of ~/.intmax2/config.toml to use instead of the default one
+    #[clap(long, global = true)]
+    profile: Option<String>,
+    /// Config file whose [settings] are read instead of those of
+    /// ~/.intmax2/config.toml
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+    /// Stop the command if it has not completed within this time (e.g. 90s, 5m),
+    /// with exit code 13 and the phase in progress printed as JSON to stderr
+    #[clap(long, global = true, value_parser = parse_duration)]
+    deadline: Option<Duration>,
+    /// Print tx tree roots and nullifiers as plain hex, without the `#` checksum
+    #[clap(long, global = true)]
+    plain_ids: bool,
+    /// Do not compare the versions of the servers with this CLI before the command
+    #[clap(long, global = true)]
+    skip_version_check: bool,
+    /// Print the result of `balance`, `withdrawal-status`, `history` and `tx status`
+    /// as JSON on stdout. Other messages go to stderr.
+    #[clap(long, global = true)]
+    json: bool,
+    /// `ndjson-stream` prints the progress of `sync`, `sync-withdrawals`, `recover`,
+    /// `tx`, `tx send-batch`, `claim-withdrawal` and `watcher` as it happens, one
+    /// JSON event per line, ending with a `result` or `error` line
+    #[clap(long, global = true, value_enum, default_value_t = OutputMode::Text, conflicts_with = "json")]
+    output: OutputMode,
+    /// Language of the errors and prompts. Defaults to the one of LANG, or English.
+    #[clap(long, global = true, value_enum)]
+    lang: Option<Lang>,
+    /// Block builder to send txs to, overriding BLOCK_BUILDER_BASE_URL and the
+    /// choice among those of the indexer. Warns if the profile blocks it.
+    #[clap(long, global = true)]
+    builder: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    #[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Tx {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Pubkey, L1 address for a withdrawal, or name saved with `address-book add`
+        #[clap(long, required_unless_present = "template")]
+        to: Option<String>,
+        #[clap(long, required_unless_present = "template")]
+        amount: Option<u128>,
+        #[clap(long, required_unless_present = "template")]
+        token_index: Option<u32>,
+        /// Send the transfers of a template saved with `template save`
+        #[clap(long, conflicts_with_all = ["to", "amount", "token_index"])]
+        template: Option<String>,
+        /// Amount replacing the one of a single-transfer template
+        #[clap(long, requires = "template")]
+        amount_override: Option<u128>,
+        /// Send the template without asking for confirmation
+        #[clap(long, requires = "template")]
+        yes: bool,
+        /// Enable advanced options
         #[clap(long)]
-        private_key: H256,
+        expert: bool,
+        /// Nonce the tx must be sent at (requires --expert)
+        #[clap(long, hide = true, requires = "expert")]
+        nonce: Option<u32>,
+        /// Block number after which the recipient refuses the transfer
         #[clap(long)]
-        to: String,
+        claim_deadline_block: Option<u32>,
+        /// Send even if the block builder looks unhealthy
         #[clap(long)]
-        amount: u128,
+        force: bool,
+        /// Ask this sponsor service to pay the block builder fee
         #[clap(long)]
-        token_index: u32,
-    },
-    Deposit {
+        sponsor_url: Option<String>,
+        /// Attribution tag of the withdrawal, e.g. the memo code of the receiving
+        /// exchange. Only its hash is given to the withdrawal server.
         #[clap(long)]
-        eth_private_key: H256,
-        #[clap(long)]
-        private_key: H256,
+        tag: Option<String>,
+        /// Pay the block builder a fee of this amount. The builder's attestation of
+        /// the proposal is backed up with the tx, to dispute the fee if the block is
+        /// never posted (see fee-disputes).
+        #[clap(long, requires = "fee_recipient")]
+        fee: Option<u128>,
+        /// Pubkey, or name saved with `address-book add`, of the block builder
+        /// receiving --fee
+        #[clap(long, requires = "fee")]
+        fee_recipient: Option<String>,
+        /// Token of --fee, 0 (ETH) by default
+        #[clap(long, requires = "fee")]
+        fee_token_index: Option<u32>,
+        /// Limit on the wait for the block proposal (e.g. 90s, 5m)
+        #[clap(long, value_parser = parse_duration)]
+        timeout: Option<Duration>,
+        /// Only check the transfers against the limits and the balances, and print
+        /// the transfer tree root and the balance changes, without contacting the
+        /// block builder
         #[clap(long)]
-        amount: u128,
+        dry_run: bool,
+        #[clap(subcommand)]
+        command: Option<TxCommands>,
+    },
+    #[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Deposit {
+        #[clap(long, required = true)]
+        eth_private_key: Option<H256>,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Defaults to 1 for ERC721, the only amount it allows
         #[clap(long)]
-        token_type: TokenType,
+        amount: Option<u128>,
+        /// Defaults to ERC20 if --token-address is given
+        #[clap(long, required_unless_present = "token_address")]
+        token_type: Option<TokenType>,
+        /// Contract of the token. Deposits of ERC20 tokens are approved first if
+        /// the allowance toward the liquidity contract is too low.
         #[clap(long)]
         token_address: Option<EthAddress>,
         #[clap(long)]
         token_id: Option<u128>,
+        #[clap(subcommand)]
+        command: Option<DepositCommands>,
     },
     Sync {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Only sync the deposits and incoming transfers of this token
         #[clap(long)]
-        private_key: H256,
+        token: Option<u32>,
+        /// Check the balances of each synced action against the replayed history,
+        /// and stop without saving it if they differ
+        #[clap(long, conflicts_with = "token")]
+        verify: bool,
+        /// Limit on the sync (e.g. 90s, 5m)
+        #[clap(long, value_parser = parse_duration)]
+        timeout: Option<Duration>,
     },
     SyncWithdrawals {
-        #[clap(long)]
-        private_key: H256,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Limit on the sync of the withdrawals (e.g. 90s, 5m)
+        #[clap(long, value_parser = parse_duration)]
+        timeout: Option<Duration>,
     },
     Balance {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Skip the sync, and include the txs finalized and the transfers received
+        /// since the last sync
+        #[clap(long, conflicts_with = "at_block")]
+        optimistic: bool,
+        /// Balances as of this block, replayed from the history
         #[clap(long)]
-        private_key: H256,
+        at_block: Option<u32>,
+        /// Also print the nonce, the balance proof block, the private commitment
+        /// and the other numbers support asks for
+        #[clap(long, conflicts_with_all = ["optimistic", "at_block"])]
+        debug: bool,
     },
     History {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Discard the local history index and decrypt every backup again
+        #[clap(long)]
+        rebuild_index: bool,
+        /// Only the entries of this token. A send matches if any of its transfers does.
         #[clap(long)]
-        private_key: H256,
+        token_index: Option<u32>,
+        /// Only the entries with this status
+        #[clap(long, value_enum)]
+        status: Option<HistoryStatus>,
+        #[clap(subcommand)]
+        command: Option<HistoryCommands>,
     },
     WithdrawalStatus {
+        #[clap(flatten)]
+        key: KeyArgs,
+    },
+    Withdrawal {
+        #[clap(subcommand)]
+        command: WithdrawalCommands,
+    },
+    /// Print the minimum withdrawal and deposit amounts and the other limits
+    Limits,
+    Fee {
+        #[clap(subcommand)]
+        command: FeeCommands,
+    },
+    /// List sent transfers whose claim deadline has passed
+    ExpiredTransfers {
+        #[clap(flatten)]
+        key: KeyArgs,
+    },
+    /// List fees paid for blocks the block builder never posted, with the
+    /// dispute documents to claim a refund
+    FeeDisputes {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Write the dispute documents to this file as JSON
         #[clap(long)]
-        private_key: H256,
+        out: Option<PathBuf>,
+    },
+    ClaimWithdrawal {
+        #[clap(long)]
+        eth_private_key: H256,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Claim all claimable withdrawals instead of the oldest one
+        #[clap(long)]
+        all: bool,
+        /// Send one transaction per withdrawal instead of batching with Multicall3
+        #[clap(long)]
+        no_batch: bool,
+    },
+    /// Rebuild the balance proof of the account from the first action
+    Recover {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Resume the unfinished rebuild session with this id
+        #[clap(long, conflicts_with = "restart")]
+        resume: Option<String>,
+        /// Discard unfinished rebuild sessions and start over
+        #[clap(long)]
+        restart: bool,
+        /// Put back the user data backed up when a rebuild started, instead of
+        /// rebuilding
+        #[clap(long, conflicts_with_all = ["resume", "restart"])]
+        restore: bool,
+        /// Backup file to restore, instead of the latest one of the account
+        #[clap(long, requires = "restore")]
+        backup: Option<PathBuf>,
+    },
+    /// Sync the incoming transfers and deposits, then send the balance to the
+    /// account itself. The self-transfers cost prover work of their own; the costs
+    /// are shown and confirmed before anything is sent.
+    Consolidate {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Token to consolidate, repeatable. All tokens if not given.
+        #[clap(long = "token")]
+        tokens: Vec<u32>,
+        /// Amount kept out of the self-transfers to pay the block builder fee with
+        #[clap(long)]
+        fee_reserve: Option<u128>,
+        /// Token of --fee-reserve
+        #[clap(long, default_value_t = 0, requires = "fee_reserve")]
+        fee_token_index: u32,
+        /// Fewest pending incoming transfers and deposits worth a consolidation
+        /// (default 8)
+        #[clap(long)]
+        min_pending: Option<usize>,
+        /// Ask this sponsor service to pay the block builder fees
+        #[clap(long)]
+        sponsor_url: Option<String>,
+        /// Do not ask for confirmation
+        #[clap(long)]
+        yes: bool,
     },
     GenerateKey,
+    /// Move the whole balance to a new key, e.g. after the key may have been
+    /// exposed. The plan is shown and confirmed before anything is sent.
+    RotateKey {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Private key of the new account, which is synced at the end
+        #[clap(long, required_unless_present = "new_key_file")]
+        new_key: Option<H256>,
+        #[clap(long, conflicts_with = "new_key")]
+        new_key_file: Option<PathBuf>,
+        /// Also copy the templates to the new account
+        #[clap(long)]
+        include_metadata: bool,
+        /// Balances at or below this amount are left with the old key
+        #[clap(long, default_value_t = 0)]
+        dust_threshold: u128,
+        /// Ask this sponsor service to pay the block builder fees
+        #[clap(long)]
+        sponsor_url: Option<String>,
+        /// Do not ask for confirmation
+        #[clap(long)]
+        yes: bool,
+    },
+    /// Create a profile: service settings and a key kept in the OS keychain or an
+    /// encrypted key file. Commands given no key then use the profile's key.
+    Init {
+        /// Fill in the settings of a known environment
+        #[clap(long, value_enum)]
+        preset: Option<Preset>,
+        /// Setting of the profile, e.g. --set L1_RPC_URL=https://..., repeatable
+        #[clap(long = "set", value_parser = parse_setting)]
+        settings: Vec<(String, String)>,
+        /// Import this key instead of generating one
+        #[clap(long)]
+        private_key: Option<H256>,
+        /// Keep the key in an encrypted key file even if the OS keychain is available
+        #[clap(long)]
+        no_keyring: bool,
+        /// Fail instead of prompting, for scripts (the key file passphrase is then
+        /// read from KEY_FILE_PASSPHRASE)
+        #[clap(long)]
+        non_interactive: bool,
+        /// Overwrite an existing profile of the same name
+        #[clap(long)]
+        force: bool,
+        #[clap(long)]
+        skip_doctor: bool,
+    },
+    /// Check that the servers and RPC nodes of the environment respond
+    Doctor,
+    /// Follow new blocks and cache the update witnesses of the given accounts in
+    /// WITNESS_CACHE_DIR, so that their next sync fetches less. Runs until Ctrl-C.
+    Watcher {
+        /// Public keys of the accounts, comma separated
+        #[clap(long, value_delimiter = ',', required = true)]
+        pubkeys: Vec<String>,
+        /// Block to start from instead of where the previous run stopped
+        #[clap(long)]
+        from_block: Option<u32>,
+        /// Seconds between polls of the validity prover, instead of
+        /// WATCHER_INTERVAL (default 10)
+        #[clap(long)]
+        interval: Option<u64>,
+    },
+    /// Print what is public of an account from its pubkey alone: its registration,
+    /// the deposits to the salt hashes its owner disclosed, and the blocks with its
+    /// txs. Balances and transfers are encrypted to the key and are not shown.
+    Observe {
+        /// Public key of the account
+        pubkey: String,
+        /// Pubkey salt hashes disclosed by the owner, as printed by `deposit
+        /// request`, comma separated
+        #[clap(long, value_delimiter = ',', value_parser = parse_id_arg)]
+        salt_hashes: Vec<Bytes32>,
+        /// First block scanned for txs. By default only the blocks posted from now
+        /// on are scanned
+        #[clap(long)]
+        from_block: Option<u32>,
+        /// Keep printing the new deposits and blocks until Ctrl-C. With
+        /// L1_WS_RPC_URL, the deposits are pushed by the RPC instead of polled
+        #[clap(long)]
+        follow: bool,
+        /// Seconds between polls with --follow, instead of WATCHER_INTERVAL
+        /// (default 10)
+        #[clap(long)]
+        interval: Option<u64>,
+    },
+    Debug {
+        #[clap(subcommand)]
+        command: DebugCommands,
+    },
+    Backup {
+        #[clap(subcommand)]
+        command: BackupCommands,
+    },
+    Receipt {
+        #[clap(subcommand)]
+        command: ReceiptCommands,
+    },
+    Key {
+        #[clap(subcommand)]
+        command: KeyCommands,
+    },
+    Template {
+        #[clap(subcommand)]
+        command: TemplateCommands,
+    },
+    /// Outcomes of the txs sent through each block builder, which guide the choice
+    /// among the block builders of the indexer
+    Builders {
+        #[clap(subcommand)]
+        command: BuildersCommands,
+    },
+    /// Names for intmax pubkeys, accepted by `tx --to`
+    AddressBook {
+        #[clap(subcommand)]
+        command: AddressBookCommands,
+    },
+    /// Notifications of incoming transfers, settled withdrawals and failed txs,
+    /// posted to WEBHOOK_URL by `sync` and `sync-withdrawals`
+    Webhook {
+        #[clap(subcommand)]
+        command: WebhookCommands,
+    },
+    /// Witnesses of a block range gathered for an account by someone knowing only
+    /// its pubkey, to sync with fewer round trips
+    WitnessBundle {
+        #[clap(subcommand)]
+        command: WitnessBundleCommands,
+    },
+    /// Write what the maintainers need to triage the account to a file encrypted to
+    /// their key: the redacted config and logs, diagnostics, the actions the next
+    /// sync would process, and the server versions
+    #[clap(args_conflicts_with_subcommands = true)]
+    SupportBundle {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// File to write the bundle to, e.g. bundle.intmax
+        #[clap(long)]
+        out: Option<PathBuf>,
+        /// Log file whose last lines to include, redacted. Can be repeated.
+        #[clap(long)]
+        log_file: Vec<PathBuf>,
+        /// Number of lines of each log file
+        #[clap(long, default_value_t = 200)]
+        log_lines: usize,
+        /// Offer to include the encrypted data of the account, after a prompt
+        #[clap(long)]
+        include_blobs: bool,
+        #[clap(subcommand)]
+        command: Option<SupportBundleCommands>,
+    },
+    /// The [settings] of ~/.intmax2/config.toml, or of --config
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Print the completion script of a shell, e.g. `source <(intmax2-cli completions
+    /// bash)`. It completes the names of --to and --profile from the address book and
+    /// the profiles.
+    Completions {
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+    /// Print completion candidates for the given command line (used by shell scripts)
+    #[clap(name = "__complete", hide = true)]
+    Complete {
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TxCommands {
+    /// Send the transfers of a CSV file of `recipient,token_index,amount` rows, one
+    /// tx per NUM_TRANSFERS_IN_TX rows. If a tx fails, the rows not sent are written
+    /// to FILE.remainder.csv.
+    SendBatch {
+        #[clap(flatten)]
+        key: KeyArgs,
+        #[clap(long)]
+        file: PathBuf,
+        /// Only validate the rows and print the txs they would be sent in
+        #[clap(long)]
+        dry_run: bool,
+        /// Ask this sponsor service to pay the block builder fees
+        #[clap(long)]
+        sponsor_url: Option<String>,
+    },
+    /// Print whether a finalized tx is pending, included in a block, or expired
+    /// after TX_TIMEOUT. With --wait, poll until it is included or expired; an
+    /// expired tx exits with an error.
+    Status {
+        /// Key of the account that sent the tx
+        #[clap(flatten)]
+        key: KeyArgs,
+        #[clap(value_parser = parse_id_arg)]
+        tx_tree_root: Bytes32,
+        /// Unix time the tx was finalized at, from which the tx timeout counts.
+        /// Taken from the local record or the backup of the tx if not given.
+        #[clap(long)]
+        finalized_at: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryCommands {
+    /// Export the balances at the end of each day, week or month as CSV
+    BalanceSeries {
+        #[clap(flatten)]
+        key: KeyArgs,
+        #[clap(long, group = "interval")]
+        daily: bool,
+        #[clap(long, group = "interval")]
+        weekly: bool,
+        /// The default
+        #[clap(long, group = "interval")]
+        monthly: bool,
+        /// CSV file to write, stdout if not given
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WithdrawalCommands {
+    /// Wait until a withdrawal is paid to its recipient, or with --claimable until
+    /// it can be claimed
+    Wait {
+        /// Nullifier of the withdrawal as printed by `withdrawal-status`, or its
+        /// withdrawal hash
+        #[clap(value_parser = parse_id_arg)]
+        withdrawal_id: Bytes32,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Limit on the wait (e.g. 3600, 90m, 2h), in seconds without a unit
+        #[clap(long, value_parser = parse_duration)]
+        timeout: Duration,
+        /// Seconds between two polls of the withdrawal aggregator, at least 1
+        #[clap(
+            long,
+            default_value_t = DEFAULT_WITHDRAWAL_POLL_INTERVAL,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        poll_interval: u64,
+        /// Also stop when the withdrawal is to be claimed with `claim-withdrawal`
+        #[clap(long)]
+        claimable: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum FeeCommands {
+    /// Print the fees a transfer or a withdrawal would pay, per token and in total
+    Estimate {
+        #[clap(long, value_enum)]
+        kind: FeeKindArg,
+        /// Only the fee of this token
+        #[clap(long)]
+        token_index: Option<u32>,
+        /// Amount the fees proportional to it are computed for
+        #[clap(long, default_value_t = 0)]
+        amount: u128,
+    },
+}
+
+#[derive(Subcommand)]
+enum DebugCommands {
+    /// Compare the private state nonce with settled and pending txs
+    Nonce {
+        #[clap(flatten)]
+        key: KeyArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeyCommands {
+    /// Check a key file (32-byte seed and checksum, optionally encrypted) and keep an
+    /// encrypted copy under ~/.intmax2/keys for use with --key-file
+    ///
+    /// With --json, the key of --file, or a private key prompted for, is encrypted
+    /// into a JSON key file at that path instead, also for use with --key-file.
+    Import {
+        #[clap(long, required_unless_present = "json")]
+        file: Option<PathBuf>,
+        /// Write a JSON key file (laid out like an Ethereum v3 keystore) to this path
+        #[clap(long)]
+        json: Option<PathBuf>,
+        /// Prompt for the passphrase even if KEY_FILE_PASSPHRASE is set
+        #[clap(long)]
+        passphrase_prompt: bool,
+    },
+    /// Write the key of the account to a passphrase-encrypted key file
+    Export {
+        #[clap(flatten)]
+        key: KeyArgs,
+        #[clap(long)]
+        file: PathBuf,
+        /// Encrypt the key file with a passphrase (unencrypted files are never written)
+        #[clap(long)]
+        encrypt: bool,
+    },
+    /// Create a key and print the 24-word BIP-39 mnemonic backing it up
+    Generate,
+    /// Print the key of a mnemonic of `key generate`. The mnemonic is prompted for,
+    /// so that it does not appear in the shell history or the process list.
+    Recover {
+        /// Read the mnemonic from standard input instead, e.g. from a file
+        #[clap(long)]
+        stdin: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TemplateCommands {
+    /// Save transfers under a name, for `tx --template`. Repeat --to, --token-index
+    /// and --amount for each transfer of a batch.
+    Save {
+        #[clap(flatten)]
+        key: KeyArgs,
+        name: String,
+        /// Hex pubkey or address, or @name of the address book
+        #[clap(long, required = true)]
+        to: Vec<String>,
+        #[clap(long, required = true)]
+        token_index: Vec<u32>,
+        #[clap(long, required = true)]
+        amount: Vec<u128>,
+        /// Note kept with the template, not sent
+        #[clap(long)]
+        memo: Option<String>,
+    },
+    List {
+        #[clap(flatten)]
+        key: KeyArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum BuildersCommands {
+    /// Print the proposals, timeouts, failed requests, txs included and expired,
+    /// inclusion rate and average proposal latency of each block builder
+    Stats,
+}
+
+#[derive(Subcommand)]
+enum AddressBookCommands {
+    /// Save a pubkey under a name, replacing the pubkey saved under it if any
+    Add {
+        name: String,
+        pubkey: String,
+    },
+    List,
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum WebhookCommands {
+    /// Send a signed ping to WEBHOOK_URL
+    Test {
+        #[clap(flatten)]
+        key: KeyArgs,
+    },
+    /// Make one more attempt to deliver the events left undelivered by earlier
+    /// runs. Events that failed 5 times are moved to webhooks/dead_letter.json.
+    Flush,
+}
+
+#[derive(Subcommand)]
+enum WitnessBundleCommands {
+    /// Gather and sign the public witnesses of PUBKEY, e.g. from the last block
+    /// its account synced to
+    Create {
+        pubkey: String,
+        #[clap(long)]
+        from_block: u32,
+        /// The latest block if omitted
+        #[clap(long)]
+        to_block: Option<u32>,
+        /// Ethereum private key to sign the bundle with
+        #[clap(long)]
+        signer_key: H256,
+        #[clap(long)]
+        output: PathBuf,
+    },
+    /// Sync with a bundle, after checking it. What it lacks is fetched as usual.
+    Use {
+        #[clap(flatten)]
+        key: KeyArgs,
+        file: PathBuf,
+        /// Address the bundle must be signed by
+        #[clap(long)]
+        signer: Option<EthAddress>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Add a [settings] template with every setting commented out, keeping the
+    /// profiles
+    Init {
+        /// Overwrite an existing file
+        #[clap(long)]
+        force: bool,
+    },
+    /// Print the settings in effect, merged from the environment, `.env`, the
+    /// profile and the config file, with secrets redacted
+    Show,
+}
+
+#[derive(Subcommand)]
+enum SupportBundleCommands {
+    /// Print what a bundle created on this machine contains, to review before
+    /// sending it
+    Inspect { file: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum BackupCommands {
+    /// Check that everything needed to recover the account is in the store vault.
+    /// Exits with a non-zero code if anything is missing.
+    Status {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Tx tree root of an own tx that must be backed up once included in a block
+        #[clap(long, value_parser = parse_id_arg)]
+        tx_tree_root: Vec<Bytes32>,
+    },
+    /// Encrypt the local store and upload the files changed since the last push
+    #[cfg(feature = "offsite-backup")]
+    Push {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// s3://bucket/prefix
+        #[clap(long)]
+        target: S3Target,
+    },
+    /// Restore the local store from a backup. A file newer locally than in the
+    /// backup is kept and reported.
+    #[cfg(feature = "offsite-backup")]
+    Pull {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// s3://bucket/prefix
+        #[clap(long)]
+        target: S3Target,
+    },
+    /// Push periodically until Ctrl-C
+    #[cfg(feature = "offsite-backup")]
+    Schedule {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// s3://bucket/prefix
+        #[clap(long)]
+        target: S3Target,
+        /// Seconds between pushes (default 3600)
+        #[clap(long)]
+        interval: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DepositCommands {
+    /// Print a hash for someone else to deposit to, e.g. from their L1 wallet, and
+    /// the salt to claim the deposit with
+    Request {
+        #[clap(flatten)]
+        key: KeyArgs,
+    },
+    /// Import a deposit made by someone else to the hash of `deposit request`
+    ClaimExternal {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Salt printed by `deposit request`
+        #[clap(long, value_parser = parse_salt_arg)]
+        salt: Salt,
+        /// L1 tx hash of the deposit, or its deposit id
+        deposit: DepositSource,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReceiptCommands {
+    /// Export a proof that the transfer TRANSFER_UUID was received, for a third party
+    Export {
+        #[clap(flatten)]
+        key: KeyArgs,
+        transfer_uuid: String,
+        /// File to write the receipt to, instead of stdout
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Check an exported receipt. Exits with a non-zero code if it does not hold.
+    Verify {
+        file: PathBuf,
+        /// Public key the transfer must pay
+        #[clap(long)]
+        recipient: String,
+        /// Tx tree root posted in the receipt's block, e.g. from the rollup contract.
+        /// Fetched from the validity prover if omitted.
+        #[clap(long, value_parser = parse_id_arg)]
+        tx_tree_root: Option<Bytes32>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_logger();
     let args = Args::parse();
+    if let Commands::Complete { words } = &args.command {
+        // completion must not log or touch the network
+        for candidate in complete(&Args::command(), words) {
+            println!("{}", candidate);
+        }
+        return Ok(());
+    }
+    if let Commands::Completions { shell } = &args.command {
+        print!("{}", completion_script(*shell));
+        return Ok(());
+    }
+    if args.verbose {
+        init_logger_with_filter("info,intmax2_client_sdk=debug");
+    } else {
+        init_logger();
+    }
 
     dotenv::dotenv().ok();
+    if let Some(profile) = &args.profile {
+        std::env::set_var("INTMAX_PROFILE", profile);
+    }
+    if let Some(config) = &args.config {
+        set_config_file(config.clone());
+    }
+    // the environment and .env take precedence over the profile, which takes
+    // precedence over the config file
+    apply_profile_env()?;
+    // a config file that does not parse can still be replaced
+    if !matches!(
+        args.command,
+        Commands::Config {
+            command: ConfigCommands::Init { .. }
+        }
+    ) {
+        load_config_file()?;
+    }
+    if let Some(gas_strategy) = &args.gas_strategy {
+        // checked here so that a typo fails before anything is sent
+        gas_strategy
+            .parse::<GasStrategyConfig>()
+            .map_err(|e| anyhow::anyhow!(e))?;
+        std::env::set_var("GAS_STRATEGY", gas_strategy);
+    }
+    if let Some(builder) = &args.builder {
+        std::env::set_var("BLOCK_BUILDER_BASE_URL", builder);
+    }
 
+    set_plain_ids(args.plain_ids);
+    set_json_output(args.json);
+    if args.output == OutputMode::NdjsonStream {
+        ensure!(
+            matches!(
+                args.command,
+                Commands::Sync { .. }
+                    | Commands::SyncWithdrawals { .. }
+                    | Commands::Recover { .. }
+                    | Commands::Tx {
+                        command: None,
+                        template: None,
+                        ..
+                    }
+                    | Commands::Tx {
+                        command: Some(TxCommands::SendBatch { .. }),
+                        ..
+                    }
+                    | Commands::ClaimWithdrawal { .. }
+                    | Commands::Watcher { .. }
+            ),
+            "--output ndjson-stream only applies to sync, sync-withdrawals, recover, tx without --template, tx send-batch, claim-withdrawal and watcher"
+        );
+        start_ndjson_stream();
+    }
+    set_lang(args.lang.unwrap_or_else(Lang::from_env));
+    // commands that do not talk to the servers
+    let offline = matches!(
+        args.command,
+        Commands::GenerateKey
+            | Commands::Init { .. }
+            | Commands::Key { .. }
+            | Commands::Template { .. }
+            | Commands::AddressBook { .. }
+            | Commands::Builders { .. }
+            | Commands::Config { .. }
+            | Commands::SupportBundle {
+                command: Some(SupportBundleCommands::Inspect { .. }),
+                ..
+            }
+    );
+    if !args.skip_version_check && !offline {
+        check_versions().await;
+    }
+    if let Some(deadline) = args.deadline {
+        global_deadlines().set_deadline(deadline);
+    }
+    let result = global_deadlines().run(run(args)).await;
+    finish_webhook_deliveries().await;
+    if let Some(timeout) = result.as_ref().err().and_then(timeout_of) {
+        if is_ndjson_stream() {
+            finish_stream(Some(StreamEvent::Error {
+                error: "timeout".to_string(),
+                message_id: "error.timeout".to_string(),
+                message: timeout.localized_in(lang()),
+            }));
+        }
+        eprintln!("{}", timeout.to_json());
+        std::process::exit(TIMEOUT_EXIT_CODE);
+    }
+    if let Err(e) = &result {
+        print_error(e);
+        std::process::exit(1);
+    }
+    finish_stream(None);
+    Ok(())
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    let wait = args.wait;
     match args.command {
         Commands::Tx {
-            private_key,
+            command:
+                Some(TxCommands::SendBatch {
+                    key,
+                    file,
+                    dry_run,
+                    sponsor_url,
+                }),
+            ..
+        } => {
+            let key = key.resolve_with_signer(&load_env()?)?;
+            let _lock = AccountLock::acquire(key.pubkey, wait)?;
+            run_in_session(key, send_batch(key, &file, dry_run, sponsor_url)).await?;
+        }
+        Commands::Tx {
+            command:
+                Some(TxCommands::Status {
+                    key,
+                    tx_tree_root,
+                    finalized_at,
+                }),
+            ..
+        } => {
+            tx_status(&key, tx_tree_root, finalized_at, wait).await?;
+        }
+        Commands::Tx {
+            key,
             to,
             amount,
             token_index,
+            template,
+            amount_override,
+            yes,
+            expert: _,
+            nonce,
+            claim_deadline_block,
+            force,
+            sponsor_url,
+            tag,
+            fee,
+            fee_recipient,
+            fee_token_index,
+            timeout,
+            dry_run,
+            command: None,
         } => {
-            let to = parse_generic_address(&to)?;
-            let key = h256_to_keyset(private_key);
-            tx(key, to, amount.into(), token_index).await?;
+            let key = key.resolve_with_signer(&load_env()?)?;
+            // --fee-recipient is present with --fee
+            let fee_transfer = match fee {
+                Some(fee) => Some(single_transfer(
+                    resolve_recipient(&fee_recipient.unwrap())?,
+                    fee.into(),
+                    fee_token_index.unwrap_or(0),
+                )),
+                None => None,
+            };
+            let transfers = match template {
+                Some(name) => match use_template(key, &name, amount_override, yes)? {
+                    Some(transfers) => transfers,
+                    None => {
+                        println!("{}", tr("msg.cancelled"));
+                        return Ok(());
+                    }
+                },
+                // present unless --template is given
+                None => vec![single_transfer(
+                    resolve_recipient(&to.unwrap())?,
+                    amount.unwrap().into(),
+                    token_index.unwrap(),
+                )],
+            };
+            if dry_run {
+                tx_dry_run(
+                    key,
+                    transfers,
+                    nonce,
+                    claim_deadline_block,
+                    tag,
+                    fee_transfer,
+                )
+                .await?;
+                return Ok(());
+            }
+            let _lock = AccountLock::acquire(key.pubkey, wait)?;
+            let tx_tree_root = run_in_session(
+                key,
+                tx(
+                    key,
+                    transfers,
+                    nonce,
+                    claim_deadline_block,
+                    force,
+                    sponsor_url,
+                    tag,
+                    fee_transfer,
+                    timeout,
+                ),
+            )
+            .await?;
+            set_stream_result(&json!({ "txTreeRoot": tx_tree_root }));
         }
+        Commands::Deposit {
+            command: Some(command),
+            ..
+        } => match command {
+            DepositCommands::Request { key } => {
+                let key = key.resolve()?;
+                deposit_request(key)?;
+            }
+            DepositCommands::ClaimExternal { key, salt, deposit } => {
+                let key = key.resolve()?;
+                let _lock = AccountLock::acquire(key.pubkey, wait)?;
+                run_in_session(key, deposit_claim_external(key, salt, deposit)).await?;
+            }
+        },
         Commands::Deposit {
             eth_private_key,
-            private_key,
+            key,
             amount,
             token_type,
             token_address,
             token_id,
+            command: None,
         } => {
-            let key = h256_to_keyset(private_key);
+            // required unless a subcommand is given
+            let token_type = token_type.or(token_address.map(|_| TokenType::ERC20));
+            let (Some(eth_private_key), Some(token_type)) = (eth_private_key, token_type) else {
+                bail!("Missing --eth-private-key, or --token-type or --token-address");
+            };
+            let key = key.resolve()?;
+            let _lock = AccountLock::acquire(key.pubkey, wait)?;
+            let amount = match (amount, token_type) {
+                (Some(amount), _) => amount,
+                (None, TokenType::ERC721) => 1,
+                (None, _) => bail!("Missing amount"),
+            };
             let token_id = token_id.map(|x| x.into());
             let (token_address, token_id) = format_token_info(token_type, token_address, token_id)?;
-            deposit(
+            run_in_session(
                 key,
-                eth_private_key,
-                amount.into(),
-                token_type,
-                token_address,
-                token_id,
+                deposit(
+                    key,
+                    eth_private_key,
+                    amount.into(),
+                    token_type,
+                    token_address,
+                    token_id,
+                ),
             )
             .await?;
         }
-        Commands::Sync { private_key } => {
-            let key = h256_to_keyset(private_key);
-            sync(key).await?;
-        }
-        Commands::SyncWithdrawals { private_key } => {
-            let key = h256_to_keyset(private_key);
-            sync_withdrawals(key).await?;
+        Commands::Sync {
+            key,
+            token,
+            verify,
+            timeout,
+        } => {
+            let key = key.resolve()?;
+            let _lock = AccountLock::acquire(key.pubkey, wait)?;
+            global_deadlines().set_phase(Phase::Sync, timeout);
+            let synced = match token {
+                Some(token_index) => run_in_session(key, sync_token(key, token_index)).await?,
+                None if verify => run_in_session(key, sync_verified(key)).await?,
+                None => run_in_session(key, sync(key)).await?,
+            };
+            set_stream_result(&json!({ "synced": synced }));
         }
-        Commands::Balance { private_key } => {
-            let key = h256_to_keyset(private_key);
-            balance(key).await?;
+        Commands::SyncWithdrawals { key, timeout } => {
+            let key = key.resolve()?;
+            let _lock = AccountLock::acquire(key.pubkey, wait)?;
+            global_deadlines().set_phase(Phase::WithdrawalSync, timeout);
+            run_in_session(key, sync_withdrawals(key)).await?;
+            set_stream_result(&json!({ "synced": true }));
         }
-        Commands::History { private_key } => {
-            let key = h256_to_keyset(private_key);
-            history(key).await?;
+        Commands::Balance {
+            key,
+            optimistic,
+            at_block,
+            debug,
+        } => {
+            ensure!(
+                !args.json || !(optimistic || at_block.is_some() || debug),
+                "--json only applies to the synced balance"
+            );
+            let key = key.resolve()?;
+            if let Some(block_number) = at_block {
+                balance_at(key, block_number).await?;
+            } else if optimistic {
+                run_in_session(key, balance_optimistic(key)).await?;
+            } else {
+                // the sync saves the user data, as the other commands holding the lock do
+                let _lock = AccountLock::acquire(key.pubkey, wait)?;
+                global_deadlines().set_phase(Phase::Sync, None);
+                run_in_session(key, balance(key, debug)).await?;
+            }
         }
-        Commands::WithdrawalStatus { private_key } => {
-            let key = h256_to_keyset(private_key);
+        Commands::History {
+            key,
+            rebuild_index,
+            token_index,
+            status,
+            command,
+        } => match command {
+            None => {
+                let key = key.resolve()?;
+                let filter = HistoryFilter {
+                    token_index,
+                    status,
+                };
+                history(key, rebuild_index, filter).await?;
+            }
+            Some(HistoryCommands::BalanceSeries {
+                key,
+                daily,
+                weekly,
+                out,
+                ..
+            }) => {
+                let key = key.resolve()?;
+                let interval = if daily {
+                    SeriesInterval::Daily
+                } else if weekly {
+                    SeriesInterval::Weekly
+                } else {
+                    SeriesInterval::Monthly
+                };
+                balance_series_csv(key, interval, out).await?;
+            }
+        },
+        Commands::WithdrawalStatus { key } => {
+            let key = key.resolve()?;
             withdrawal_status(key).await?;
         }
+        Commands::Withdrawal { command } => match command {
+            WithdrawalCommands::Wait {
+                withdrawal_id,
+                key,
+                timeout,
+                poll_interval,
+                claimable,
+            } => {
+                let key = key.resolve()?;
+                wait_for_withdrawal(key, withdrawal_id, timeout, poll_interval, claimable).await?;
+            }
+        },
+        Commands::Limits => {
+            limits().await?;
+        }
+        Commands::Fee { command } => match command {
+            FeeCommands::Estimate {
+                kind,
+                token_index,
+                amount,
+            } => fee_estimate(kind, token_index, amount).await?,
+        },
+        Commands::ExpiredTransfers { key } => {
+            let key = key.resolve()?;
+            expired_transfers(key).await?;
+        }
+        Commands::FeeDisputes { key, out } => {
+            let key = key.resolve()?;
+            fee_disputes(key, out).await?;
+        }
+        Commands::ClaimWithdrawal {
+            eth_private_key,
+            key,
+            all,
+            no_batch,
+        } => {
+            let key = key.resolve()?;
+            let _lock = AccountLock::acquire(key.pubkey, wait)?;
+            run_in_session(key, claim_withdrawals(key, eth_private_key, all, no_batch)).await?;
+        }
+        Commands::Recover {
+            key,
+            resume,
+            restart,
+            restore,
+            backup,
+        } => {
+            let key = key.resolve()?;
+            let _lock = AccountLock::acquire(key.pubkey, wait)?;
+            if restore {
+                restore_user_data(key, backup).await?;
+            } else {
+                recover(key, resume, restart).await?;
+            }
+        }
+        Commands::RotateKey {
+            key,
+            new_key,
+            new_key_file,
+            include_metadata,
+            dust_threshold,
+            sponsor_url,
+            yes,
+        } => {
+            let old_key = key.resolve()?;
+            let new_key = KeyArgs {
+                private_key: new_key,
+                key_file: new_key_file,
+            }
+            .resolve()?;
+            let _lock = AccountLock::acquire(old_key.pubkey, wait)?;
+            run_in_session(
+                old_key,
+                rotate_key(
+                    old_key,
+                    new_key,
+                    include_metadata,
+                    dust_threshold,
+                    sponsor_url,
+                    yes,
+                ),
+            )
+            .await?;
+        }
+        Commands::Consolidate {
+            key,
+            tokens,
+            fee_reserve,
+            fee_token_index,
+            min_pending,
+            sponsor_url,
+            yes,
+        } => {
+            let key = key.resolve()?;
+            let _lock = AccountLock::acquire(key.pubkey, wait)?;
+            let fee_reserve = fee_reserve.map(|amount| (fee_token_index, amount));
+            run_in_session(
+                key,
+                consolidate(key, tokens, fee_reserve, min_pending, sponsor_url, yes),
+            )
+            .await?;
+        }
         Commands::GenerateKey => {
-            println!("Generating key");
+            println!("{}", tr("msg.generating_key"));
             let mut rng = rand::thread_rng();
             let key = KeySet::rand(&mut rng);
             let private_key = BigUint::from(key.privkey);
             let private_key: IU256 = private_key.try_into().unwrap();
-            println!("Private key: {}", private_key.to_hex());
-            println!("Public key: {}", key.pubkey.to_hex());
+            println!(
+                "{}",
+                tr_with("msg.private_key", &[("private_key", &private_key.to_hex())])
+            );
+            println!(
+                "{}",
+                tr_with("msg.public_key", &[("pubkey", &key.pubkey.to_hex())])
+            );
         }
+        Commands::Init {
+            preset,
+            settings,
+            private_key,
+            no_keyring,
+            non_interactive,
+            force,
+            skip_doctor,
+        } => {
+            init(InitOptions {
+                profile: args
+                    .profile
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+                preset,
+                settings,
+                private_key,
+                no_keyring,
+                non_interactive,
+                force,
+                skip_doctor,
+            })
+            .await?;
+        }
+        Commands::Doctor => {
+            if !doctor().await? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Watcher {
+            pubkeys,
+            from_block,
+            interval,
+        } => {
+            let pubkeys = pubkeys
+                .iter()
+                .map(|pubkey| parse_pubkey(pubkey))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            watcher(pubkeys, from_block, interval).await?;
+        }
+        Commands::Observe {
+            pubkey,
+            salt_hashes,
+            from_block,
+            follow,
+            interval,
+        } => {
+            let pubkey = parse_pubkey(&pubkey)?;
+            observe(pubkey, salt_hashes, from_block, follow, interval).await?;
+        }
+        Commands::Debug { command } => match command {
+            DebugCommands::Nonce { key } => {
+                let key = key.resolve()?;
+                debug_nonce(key).await?;
+            }
+        },
+        Commands::Backup { command } => match command {
+            BackupCommands::Status { key, tx_tree_root } => {
+                let key = key.resolve()?;
+                if !backup_status(key, &tx_tree_root).await? {
+                    std::process::exit(1);
+                }
+            }
+            #[cfg(feature = "offsite-backup")]
+            BackupCommands::Push { key, target } => {
+                let key = key.resolve()?;
+                backup_push(key, target).await?;
+            }
+            #[cfg(feature = "offsite-backup")]
+            BackupCommands::Pull { key, target } => {
+                let key = key.resolve()?;
+                backup_pull(key, target).await?;
+            }
+            #[cfg(feature = "offsite-backup")]
+            BackupCommands::Schedule {
+                key,
+                target,
+                interval,
+            } => {
+                let key = key.resolve()?;
+                backup_schedule(key, target, interval).await?;
+            }
+        },
+        Commands::Receipt { command } => match command {
+            ReceiptCommands::Export {
+                key,
+                transfer_uuid,
+                out,
+            } => {
+                let key = key.resolve()?;
+                receipt_export(key, &transfer_uuid, out.as_deref()).await?;
+            }
+            ReceiptCommands::Verify {
+                file,
+                recipient,
+                tx_tree_root,
+            } => {
+                let recipient = parse_pubkey(&recipient)?;
+                if !receipt_verify(&file, recipient, tx_tree_root).await? {
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::Key { command } => match command {
+            KeyCommands::Import {
+                file,
+                json,
+                passphrase_prompt,
+            } => match (json, file) {
+                (Some(json), file) => key_import_json(&json, file.as_deref(), passphrase_prompt)?,
+                (None, Some(file)) => key_import(&file, passphrase_prompt)?,
+                (None, None) => unreachable!("--file is required without --json"),
+            },
+            KeyCommands::Export { key, file, encrypt } => {
+                let key = key.resolve()?;
+                key_export(key, &file, encrypt)?;
+            }
+            KeyCommands::Generate => key_generate()?,
+            KeyCommands::Recover { stdin } => key_recover(stdin)?,
+        },
+        Commands::Template { command } => match command {
+            TemplateCommands::Save {
+                key,
+                name,
+                to,
+                token_index,
+                amount,
+                memo,
+            } => {
+                let key = key.resolve()?;
+                template_save(key, name, to, token_index, amount, memo)?;
+            }
+            TemplateCommands::List { key } => {
+                let key = key.resolve()?;
+                template_list(key)?;
+            }
+        },
+        Commands::Builders { command } => match command {
+            BuildersCommands::Stats => builders_stats().await?,
+        },
+        Commands::AddressBook { command } => match command {
+            AddressBookCommands::Add { name, pubkey } => address_book_add(name, pubkey)?,
+            AddressBookCommands::List => address_book_list()?,
+            AddressBookCommands::Remove { name } => address_book_remove(name)?,
+        },
+        Commands::Webhook { command } => match command {
+            WebhookCommands::Test { key } => {
+                let key = key.resolve()?;
+                webhook_test(key.pubkey).await?;
+            }
+            WebhookCommands::Flush => {
+                webhook_flush().await?;
+            }
+        },
+        Commands::WitnessBundle { command } => match command {
+            WitnessBundleCommands::Create {
+                pubkey,
+                from_block,
+                to_block,
+                signer_key,
+                output,
+            } => {
+                let pubkey = parse_pubkey(&pubkey)?;
+                witness_bundle_create(pubkey, from_block, to_block, signer_key, &output).await?;
+            }
+            WitnessBundleCommands::Use { key, file, signer } => {
+                let key = key.resolve()?;
+                let _lock = AccountLock::acquire(key.pubkey, wait)?;
+                global_deadlines().set_phase(Phase::Sync, None);
+                run_in_session(key, witness_bundle_use(key, &file, signer)).await?;
+            }
+        },
+        Commands::SupportBundle {
+            command: Some(SupportBundleCommands::Inspect { file }),
+            ..
+        } => {
+            support_bundle_inspect(&file)?;
+        }
+        Commands::SupportBundle {
+            key,
+            out,
+            log_file,
+            log_lines,
+            include_blobs,
+            command: None,
+        } => {
+            let Some(out) = out else {
+                bail!("Missing --out");
+            };
+            let key = key.resolve()?;
+            support_bundle(key, &out, &log_file, log_lines, include_blobs).await?;
+        }
+        Commands::Config { command } => match command {
+            ConfigCommands::Init { force } => config_init(force)?,
+            ConfigCommands::Show => config_show()?,
+        },
+        Commands::Completions { .. } | Commands::Complete { .. } => unreachable!(),
     }
     Ok(())
 }
@@ -181,6 +1552,9 @@ fn parse_generic_address(address: &str) -> anyhow::Result<GenericAddress> {
     }
 }
 
-fn h256_to_keyset(h256: H256) -> KeySet {
-    KeySet::new(BigUint::from_bytes_be(h256.as_bytes()).into())
+fn parse_pubkey(pubkey: &str) -> anyhow::Result<IU256> {
+    match parse_generic_address(pubkey)? {
+        address if address.is_pubkey => Ok(address.to_pubkey().unwrap()),
+        _ => bail!("{} is not an intmax public key", pubkey),
+    }
 }
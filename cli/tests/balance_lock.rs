@@ -0,0 +1,83 @@
+//! `balance` syncs the account, so it takes the account lock like the other commands
+//! that save the user data. Checked with the lock held by the test process and
+//! `balance` run as a second process.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr as _,
+    thread,
+    time::Duration,
+};
+
+use assert_cmd::cargo::CommandCargoExt as _;
+use ethers::types::H256;
+use intmax2_cli::cli::{key::h256_to_keyset, lock::AccountLock};
+
+const PRIVATE_KEY: &str = "0x186aab4d91978e03f84890147e0e4bc114c8188588deb2c58bd877f5911ad78c";
+
+const CONTENTION: &str = "Another command is using this account";
+
+fn home_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "intmax2-balance-lock-test-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+// the lock `balance` takes with `home` as the home directory
+fn lock_account(home: &Path) -> AccountLock {
+    let pubkey = h256_to_keyset(H256::from_str(PRIVATE_KEY).unwrap()).pubkey;
+    AccountLock::acquire_in(&home.join(".intmax2").join("locks"), pubkey, false).unwrap()
+}
+
+fn balance(home: &Path, wait: bool) -> Command {
+    let mut command = Command::cargo_bin("intmax2-cli").unwrap();
+    command
+        .env("HOME", home)
+        .env("LC_ALL", "en_US.UTF-8")
+        .args(["balance", "--private-key", PRIVATE_KEY])
+        // without servers, the sync past the lock fails or runs into the deadline
+        .args(["--skip-version-check", "--deadline", "10s"]);
+    if wait {
+        command.arg("--wait");
+    }
+    command
+}
+
+#[test]
+fn test_balance_fails_while_the_account_is_locked() {
+    let home = home_dir("fail");
+    let _lock = lock_account(&home);
+
+    let output = balance(&home, false).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(CONTENTION), "{}", stderr);
+}
+
+#[test]
+fn test_balance_waits_for_the_account_lock() {
+    let home = home_dir("wait");
+    let lock = lock_account(&home);
+
+    let mut child = balance(&home, true)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(2));
+    assert!(
+        child.try_wait().unwrap().is_none(),
+        "balance did not wait for the lock"
+    );
+
+    drop(lock);
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains(CONTENTION), "{}", stderr);
+}
@@ -0,0 +1,374 @@
+//! Benchmarks of the client-side critical paths.
+//!
+//! Inputs are deterministic: random values come from a ChaCha20 rng seeded with 0,
+//! and the encrypted payloads are the fixtures of `interfaces/test-assets/fixtures`
+//! generated with seed 0. Benchmarks needing the fixtures are skipped
+//! with a message when they are not generated. Benchmarks needing circuit data are
+//! behind the `bench-heavy` feature. The parallel decryption benchmark needs the
+//! `parallel` feature.
+//!
+//! Compare two runs with
+//!
+//! ```sh
+//! cargo bench -p intmax2-client-sdk -- --save-baseline before
+//! # apply the change
+//! cargo bench -p intmax2-client-sdk -- --save-baseline after
+//! cargo run -p tests --bin bench-compare -- before after 10
+//! ```
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use criterion::{black_box, BatchSize, Criterion};
+use intmax2_client_sdk::client::{
+    history_index::{HistoryIndex, KeyDecoder},
+    strategy::decrypt::decrypt_blobs_sequential,
+    utils::generate_transfer_tree,
+};
+use intmax2_interfaces::{
+    api::store_vault_server::interface::DataType,
+    data::{
+        deposit_data::{DepositData, TokenType},
+        encryption::ENCRYPTION_VERSION,
+        fixtures::{FixtureDataType, FixtureManifest, FIXTURE_MANIFEST_FILE},
+        meta_data::MetaData,
+        transfer_data::TransferData,
+        tx_data::TxData,
+        user_data::UserData,
+    },
+};
+use intmax2_zkp::{
+    common::{
+        generic_address::GenericAddress, salt::Salt, signature::key_set::KeySet, transfer::Transfer,
+    },
+    constants::NUM_TRANSFERS_IN_TX,
+    ethereum_types::{address::Address, u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use num_bigint::BigUint;
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use rand::{Rng as _, SeedableRng as _};
+use rand_chacha::ChaCha20Rng;
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Number of blobs indexed by the history index benchmark
+const HISTORY_BLOBS: usize = 256;
+
+/// Number of blobs decrypted by the sync decryption benchmark
+const SYNC_BLOBS: usize = 5000;
+
+// (benchmark, expected order of magnitude on a recent laptop)
+const EXPECTED: &[(&str, &str)] = &[
+    ("transfer_tree/full", "~1 ms"),
+    (
+        "payload/deposit_encrypt",
+        "~100 us, skipped until encryption version 1",
+    ),
+    ("payload/deposit_decrypt", "~100 us"),
+    (
+        "payload/fixture_decrypt/*",
+        "100 us - 10 ms, tx blobs are the largest",
+    ),
+    ("witness_serde/tx_data_*", "1 - 10 ms"),
+    ("history_index/index_blobs", "~10 ms for 256 blobs"),
+    (
+        "decrypt_blobs/*",
+        "~1 s sequential for 5000 blobs, divided by the cores in parallel",
+    ),
+    ("heavy/load_circuit_verifiers", "~100 ms"),
+];
+
+fn print_expectations() {
+    println!("Expected orders of magnitude:");
+    for (name, expected) in EXPECTED {
+        println!("  {:<32} {}", name, expected);
+    }
+    println!();
+}
+
+fn amount(value: u32) -> U256 {
+    U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, value])
+}
+
+struct Fixtures {
+    manifest: FixtureManifest,
+    dir: PathBuf,
+}
+
+impl Fixtures {
+    fn load() -> Option<Self> {
+        let dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../interfaces/test-assets/fixtures");
+        match fs::read(dir.join(FIXTURE_MANIFEST_FILE)) {
+            Ok(bytes) => Some(Self {
+                manifest: serde_json::from_slice(&bytes).expect("invalid fixture manifest"),
+                dir,
+            }),
+            Err(_) => {
+                println!(
+                    "Fixtures not found in {:?}, skipping the fixture benchmarks. Generate them with `cargo run -p tests --bin gen-fixtures -- 0 interfaces/test-assets/fixtures`",
+                    dir
+                );
+                None
+            }
+        }
+    }
+
+    fn key(&self, name: &str) -> KeySet {
+        let key = self
+            .manifest
+            .keys
+            .iter()
+            .find(|key| key.name == name)
+            .unwrap_or_else(|| panic!("unknown fixture key {}", name));
+        let bytes = hex::decode(key.privkey.trim_start_matches("0x")).unwrap();
+        KeySet::new(BigUint::from_bytes_be(&bytes).into())
+    }
+
+    /// Blobs that decrypt, with their key
+    fn positive_blobs(&self) -> Vec<(String, FixtureDataType, KeySet, Vec<u8>)> {
+        self.manifest
+            .blobs
+            .iter()
+            .filter(|blob| blob.decrypted.is_some())
+            .map(|blob| {
+                let data = fs::read(self.dir.join(&blob.file)).unwrap();
+                (blob.file.clone(), blob.data_type, self.key(&blob.key), data)
+            })
+            .collect()
+    }
+}
+
+fn bench_transfer_tree(c: &mut Criterion) {
+    let mut rng = ChaCha20Rng::seed_from_u64(0);
+    let transfers = (0..NUM_TRANSFERS_IN_TX)
+        .map(|i| Transfer {
+            recipient: if i % 2 == 0 {
+                GenericAddress::from_pubkey(U256::rand(&mut rng))
+            } else {
+                GenericAddress::from_address(Address::rand(&mut rng))
+            },
+            token_index: rng.gen_range(0..8),
+            amount: amount(rng.gen()),
+            salt: Salt::rand(&mut rng),
+        })
+        .collect::<Vec<_>>();
+    c.bench_function("transfer_tree/full", |b| {
+        b.iter(|| generate_transfer_tree(black_box(&transfers)).get_root())
+    });
+}
+
+fn bench_payload(c: &mut Criterion, fixtures: Option<&Fixtures>) {
+    let mut rng = ChaCha20Rng::seed_from_u64(0);
+    let key = KeySet::rand(&mut rng);
+    let deposit = DepositData {
+        deposit_salt: Salt::rand(&mut rng),
+        pubkey_salt_hash: Default::default(),
+        token_type: TokenType::NATIVE,
+        token_address: Address::default(),
+        token_id: U256::default(),
+        amount: amount(100),
+        token_index: None,
+    };
+    let encrypted = deposit.encrypt(key.pubkey);
+
+    let mut group = c.benchmark_group("payload");
+    // version 0 copies the data instead of encrypting it, which is not worth a baseline
+    if ENCRYPTION_VERSION == 0 {
+        println!("Encryption version 0 does not encrypt, skipping payload/deposit_encrypt");
+    } else {
+        group.bench_function("deposit_encrypt", |b| {
+            b.iter(|| black_box(&deposit).encrypt(key.pubkey))
+        });
+    }
+    group.bench_function("deposit_decrypt", |b| {
+        b.iter(|| DepositData::decrypt(black_box(&encrypted), key).unwrap())
+    });
+    if let Some(fixtures) = fixtures {
+        for (file, data_type, key, data) in fixtures.positive_blobs() {
+            group.bench_function(format!("fixture_decrypt/{}", file), |b| {
+                b.iter(|| decrypt_fixture(data_type, black_box(&data), key))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn decrypt_fixture(data_type: FixtureDataType, data: &[u8], key: KeySet) {
+    match data_type {
+        FixtureDataType::Deposit => {
+            DepositData::decrypt(data, key).unwrap();
+        }
+        FixtureDataType::Transfer => {
+            TransferData::<F, C, D>::decrypt(data, key).unwrap();
+        }
+        FixtureDataType::Tx => {
+            TxData::<F, C, D>::decrypt(data, key).unwrap();
+        }
+        FixtureDataType::UserData => {
+            UserData::decrypt(data, key).unwrap();
+        }
+    }
+}
+
+fn bench_witness_serde(c: &mut Criterion, fixtures: &Fixtures) {
+    // the tx data carries a spent proof and witness, the largest payload
+    let Some((_, _, key, data)) = fixtures
+        .positive_blobs()
+        .into_iter()
+        .find(|(_, data_type, _, _)| *data_type == FixtureDataType::Tx)
+    else {
+        println!("No tx fixture, skipping the witness serde benchmarks");
+        return;
+    };
+    let tx_data = TxData::<F, C, D>::decrypt(&data, key).unwrap();
+    let json = serde_json::to_vec(&tx_data).unwrap();
+    let bin = bincode::serialize(&tx_data).unwrap();
+
+    let mut group = c.benchmark_group("witness_serde");
+    group.bench_function("tx_data_json_serialize", |b| {
+        b.iter(|| serde_json::to_vec(black_box(&tx_data)).unwrap())
+    });
+    group.bench_function("tx_data_json_deserialize", |b| {
+        b.iter(|| serde_json::from_slice::<TxData<F, C, D>>(black_box(&json)).unwrap())
+    });
+    group.bench_function("tx_data_bincode_serialize", |b| {
+        b.iter(|| bincode::serialize(black_box(&tx_data)).unwrap())
+    });
+    group.bench_function("tx_data_bincode_deserialize", |b| {
+        b.iter(|| bincode::deserialize::<TxData<F, C, D>>(black_box(&bin)).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_history_index(c: &mut Criterion, fixtures: &Fixtures) {
+    // the recipient's deposits and received transfers, repeated under distinct uuids
+    let key = fixtures.key("recipient");
+    let blobs = fixtures
+        .positive_blobs()
+        .into_iter()
+        .filter(|(_, _, blob_key, _)| blob_key.pubkey == key.pubkey)
+        .filter_map(|(_, data_type, _, data)| match data_type {
+            FixtureDataType::Deposit => Some((DataType::Deposit, data)),
+            FixtureDataType::Transfer => Some((DataType::Transfer, data)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if blobs.is_empty() {
+        println!("No deposit or transfer fixture, skipping the history index benchmark");
+        return;
+    }
+    let by_type = |data_type: DataType| {
+        (0..HISTORY_BLOBS)
+            .filter_map(|i| {
+                let (blob_type, data) = &blobs[i % blobs.len()];
+                (*blob_type == data_type).then(|| {
+                    let meta = MetaData {
+                        uuid: format!("blob-{}", i),
+                        timestamp: i as u64,
+                        block_number: None,
+                    };
+                    (meta, data.clone())
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    let deposits = by_type(DataType::Deposit);
+    let transfers = by_type(DataType::Transfer);
+    let decoder = KeyDecoder(key);
+
+    c.bench_function("history_index/index_blobs", |b| {
+        b.iter_batched(
+            || (deposits.clone(), transfers.clone()),
+            |(deposits, transfers)| {
+                let mut index = HistoryIndex::new(key.pubkey);
+                index.index_blobs(DataType::Deposit, deposits, &decoder);
+                index.index_blobs(DataType::Transfer, transfers, &decoder);
+                index
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_decrypt_blobs(c: &mut Criterion, fixtures: &Fixtures) {
+    // the recipient's received transfers, as fetched by a sync
+    let key = fixtures.key("recipient");
+    let transfers = fixtures
+        .positive_blobs()
+        .into_iter()
+        .filter(|(_, data_type, blob_key, _)| {
+            *data_type == FixtureDataType::Transfer && blob_key.pubkey == key.pubkey
+        })
+        .map(|(_, _, _, data)| data)
+        .collect::<Vec<_>>();
+    if transfers.is_empty() {
+        println!("No transfer fixture, skipping the decryption benchmark");
+        return;
+    }
+    let blobs = (0..SYNC_BLOBS)
+        .map(|i| {
+            let meta = MetaData {
+                uuid: format!("blob-{}", i),
+                timestamp: i as u64,
+                block_number: None,
+            };
+            (meta, transfers[i % transfers.len()].clone())
+        })
+        .collect::<Vec<_>>();
+    let decrypt = |data: &[u8]| TransferData::<F, C, D>::decrypt(data, key).is_ok();
+
+    let mut group = c.benchmark_group("decrypt_blobs");
+    group.sample_size(10);
+    group.bench_function("sequential_5000", |b| {
+        b.iter_batched(
+            || blobs.clone(),
+            |blobs| decrypt_blobs_sequential(blobs, decrypt),
+            BatchSize::LargeInput,
+        )
+    });
+    #[cfg(feature = "parallel")]
+    group.bench_function("parallel_5000", |b| {
+        use intmax2_client_sdk::client::strategy::decrypt::decrypt_blobs_parallel;
+
+        b.iter_batched(
+            || blobs.clone(),
+            |blobs| decrypt_blobs_parallel(blobs, decrypt),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+#[cfg(feature = "bench-heavy")]
+fn bench_heavy(c: &mut Criterion) {
+    use intmax2_client_sdk::utils::circuit_verifiers::CircuitVerifiers;
+
+    let mut group = c.benchmark_group("heavy");
+    group.sample_size(10);
+    group.bench_function("load_circuit_verifiers", |b| {
+        b.iter(|| CircuitVerifiers::load())
+    });
+    group.finish();
+}
+
+fn main() {
+    print_expectations();
+    let mut c = Criterion::default()
+        .measurement_time(Duration::from_secs(5))
+        .configure_from_args();
+
+    bench_transfer_tree(&mut c);
+    let fixtures = Fixtures::load();
+    bench_payload(&mut c, fixtures.as_ref());
+    if let Some(fixtures) = fixtures.as_ref() {
+        bench_witness_serde(&mut c, fixtures);
+        bench_history_index(&mut c, fixtures);
+        bench_decrypt_blobs(&mut c, fixtures);
+    }
+    #[cfg(feature = "bench-heavy")]
+    bench_heavy(&mut c);
+
+    c.final_summary();
+}
@@ -0,0 +1,140 @@
+//! Setup shared by the examples: the client built from the environment, and the
+//! send flow of a tx.
+//!
+//! The variables are the ones of the CLI, e.g. `STORE_VAULT_SERVER_BASE_URL` and
+//! `L1_RPC_URL`, plus `BLOCK_BUILDER_URL`. A `.env` file is read if present.
+
+// each example uses only part of this module
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use ethers::types::{Address as EthAddress, H256, U256 as EthU256};
+use intmax2_client_sdk::{
+    client::{client::Client, config::ClientConfig, provisional::MemoryProvisionalStore},
+    external_api::{
+        balance_prover::BalanceProverClient,
+        block_builder::BlockBuilderClient,
+        contract::{liquidity_contract::LiquidityContract, rollup_contract::RollupContract},
+        store_vault_server::StoreVaultServerClient,
+        validity_prover::ValidityProverClient,
+        withdrawal_server::WithdrawalServerClient,
+    },
+};
+use intmax2_zkp::{
+    common::{signature::key_set::KeySet, transfer::Transfer},
+    ethereum_types::{bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use num_bigint::BigUint;
+use serde::Deserialize;
+
+pub type ExampleClient = Client<
+    BlockBuilderClient,
+    StoreVaultServerClient,
+    ValidityProverClient,
+    BalanceProverClient,
+    WithdrawalServerClient,
+>;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExampleEnv {
+    pub block_builder_url: String,
+    pub store_vault_server_base_url: String,
+    pub validity_prover_base_url: String,
+    pub balance_prover_base_url: String,
+    pub withdrawal_server_base_url: String,
+    pub deposit_timeout: u64,
+    pub tx_timeout: u64,
+
+    pub l1_rpc_url: String,
+    pub l1_chain_id: u64,
+    pub liquidity_contract_address: EthAddress,
+    pub l2_rpc_url: String,
+    pub l2_chain_id: u64,
+    pub rollup_contract_address: EthAddress,
+    pub rollup_contract_deployed_block_number: u64,
+}
+
+pub fn load_env() -> anyhow::Result<ExampleEnv> {
+    dotenv::dotenv().ok();
+    Ok(envy::from_env::<ExampleEnv>()?)
+}
+
+pub fn build_client(env: &ExampleEnv) -> ExampleClient {
+    Client {
+        block_builder: BlockBuilderClient::new(),
+        store_vault_server: StoreVaultServerClient::new(&env.store_vault_server_base_url),
+        validity_prover: ValidityProverClient::new(&env.validity_prover_base_url),
+        balance_prover: BalanceProverClient::new(&env.balance_prover_base_url),
+        withdrawal_server: WithdrawalServerClient::new(&env.withdrawal_server_base_url),
+        liquidity_contract: LiquidityContract::new(
+            &env.l1_rpc_url,
+            env.l1_chain_id,
+            env.liquidity_contract_address,
+        ),
+        rollup_contract: RollupContract::new(
+            &env.l2_rpc_url,
+            env.l2_chain_id,
+            env.rollup_contract_address,
+            env.rollup_contract_deployed_block_number,
+        ),
+        config: ClientConfig {
+            deposit_timeout: env.deposit_timeout,
+            tx_timeout: env.tx_timeout,
+            ..Default::default()
+        },
+        provisional_store: Arc::new(MemoryProvisionalStore::default()),
+    }
+}
+
+/// The intmax key of a hex private key
+pub fn parse_key(private_key: &str) -> anyhow::Result<KeySet> {
+    let private_key: H256 = private_key.trim().parse()?;
+    Ok(KeySet::new(
+        BigUint::from_bytes_be(private_key.as_bytes()).into(),
+    ))
+}
+
+/// The intmax key of the hex private key in the variable `name`
+pub fn key_from_env(name: &str) -> anyhow::Result<KeySet> {
+    let private_key = std::env::var(name).map_err(|_| anyhow::anyhow!("{} is not set", name))?;
+    parse_key(&private_key)
+}
+
+/// The amount in the variable `name`, or `default` if it is not set
+pub fn amount_from_env(name: &str, default: u128) -> anyhow::Result<U256> {
+    let amount = match std::env::var(name) {
+        Ok(amount) => amount.parse::<u128>()?,
+        Err(_) => default,
+    };
+    let mut bytes = [0u8; 32];
+    EthU256::from(amount).to_big_endian(&mut bytes);
+    Ok(U256::from_bytes_be(&bytes))
+}
+
+/// Send `transfers` and wait until the tx is in a block: request the tx, wait for
+/// the block builder's proposal, back up and sign it, then wait for the validity
+/// prover to see the block. Returns the tx tree root.
+pub async fn send_and_wait(
+    client: &ExampleClient,
+    env: &ExampleEnv,
+    key: KeySet,
+    transfers: Vec<Transfer>,
+) -> anyhow::Result<Bytes32> {
+    println!("  requesting the tx from the block builder");
+    let memo = client
+        .send_tx_request(&env.block_builder_url, key, transfers)
+        .await?;
+    println!("  waiting for the block proposal");
+    let proposal = client
+        .wait_for_proposal(&env.block_builder_url, key, &memo, 60, 2)
+        .await?;
+    println!("  signing the proposal");
+    let tx_tree_root = client
+        .finalize_tx(&env.block_builder_url, key, &memo, &proposal)
+        .await?;
+    println!("  waiting for the block with tx tree root {}", tx_tree_root);
+    let block_number = client.wait_for_inclusion(tx_tree_root, 120, 5).await?;
+    println!("  included in block {}", block_number);
+    Ok(tx_tree_root)
+}
@@ -0,0 +1,84 @@
+//! Deposit native tokens from an ethereum account, then sync until the deposit
+//! shows in the intmax balance.
+//!
+//! ```sh
+//! ETH_PRIVATE_KEY=0x.. INTMAX_PRIVATE_KEY=0x.. cargo run --example deposit_and_sync
+//! ```
+//!
+//! `DEPOSIT_AMOUNT` defaults to 1 gwei. The deposit is only seen once it is relayed
+//! to the rollup and a block is posted after it, which can take several minutes.
+
+mod common;
+
+use std::time::Duration;
+
+use ethers::types::H256;
+use intmax2_interfaces::data::deposit_data::TokenType;
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{address::Address, u256::U256},
+};
+
+use common::{amount_from_env, build_client, key_from_env, load_env, ExampleClient};
+
+const NATIVE_TOKEN_INDEX: u64 = 0;
+const MAX_SYNC_ATTEMPTS: usize = 60;
+
+pub async fn run() -> anyhow::Result<()> {
+    let env = load_env()?;
+    let client = build_client(&env);
+    let eth_private_key: H256 = std::env::var("ETH_PRIVATE_KEY")?.parse()?;
+    let key = key_from_env("INTMAX_PRIVATE_KEY")?;
+    let amount = amount_from_env("DEPOSIT_AMOUNT", 1_000_000_000)?;
+
+    println!("1. syncing {} to read the balance before", key.pubkey);
+    client.sync(key).await?;
+    let before = native_balance(&client, key).await?;
+    println!("  native balance: {}", before);
+
+    // the deposit data is backed up to the store vault before the contract call,
+    // so the deposit can be found again if the process dies in between
+    println!("2. backing up the deposit data");
+    let deposit_data = client
+        .prepare_deposit(
+            key.pubkey,
+            amount,
+            TokenType::NATIVE,
+            Address::default(),
+            U256::default(),
+        )
+        .await?;
+
+    println!("3. depositing {} on the liquidity contract", amount);
+    client
+        .liquidity_contract
+        .deposit_native(eth_private_key, deposit_data.pubkey_salt_hash, amount)
+        .await?;
+
+    println!("4. syncing until the deposit is in the balance");
+    for attempt in 1..=MAX_SYNC_ATTEMPTS {
+        client.sync(key).await?;
+        let balance = native_balance(&client, key).await?;
+        if balance != before {
+            println!("  native balance: {}", balance);
+            return Ok(());
+        }
+        println!("  attempt {}: not relayed yet", attempt);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+    anyhow::bail!("the deposit did not show up in the balance")
+}
+
+async fn native_balance(client: &ExampleClient, key: KeySet) -> anyhow::Result<U256> {
+    let user_data = client.get_user_data(key).await?;
+    Ok(user_data
+        .balances()
+        .get(&NATIVE_TOKEN_INDEX)
+        .map(|leaf| leaf.amount)
+        .unwrap_or_default())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    run().await
+}
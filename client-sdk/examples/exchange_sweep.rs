@@ -0,0 +1,67 @@
+//! Sweep the balances of many deposit accounts into one hot account, as an
+//! exchange giving every customer their own deposit key would.
+//!
+//! ```sh
+//! SWEEP_PRIVATE_KEYS=0x..,0x.. HOT_WALLET_PUBKEY=0x.. cargo run --example exchange_sweep
+//! ```
+//!
+//! Every account with a balance sends all of it to the hot account, one tx per
+//! account with one transfer per token. Accounts are swept one after the other
+//! since each tx waits for its block.
+
+mod common;
+
+use intmax2_client_sdk::client::utils::generate_salt;
+use intmax2_zkp::{
+    common::{generic_address::GenericAddress, transfer::Transfer},
+    constants::NUM_TRANSFERS_IN_TX,
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+
+use common::{build_client, load_env, parse_key, send_and_wait};
+
+pub async fn run() -> anyhow::Result<()> {
+    let env = load_env()?;
+    let client = build_client(&env);
+    let hot_wallet = U256::from_hex(&std::env::var("HOT_WALLET_PUBKEY")?)
+        .map_err(|_| anyhow::anyhow!("HOT_WALLET_PUBKEY is not a hex pubkey"))?;
+    let keys = std::env::var("SWEEP_PRIVATE_KEYS")?
+        .split(',')
+        .map(parse_key)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut swept = 0;
+    for (i, key) in keys.iter().enumerate() {
+        println!("{}/{}. account {}", i + 1, keys.len(), key.pubkey);
+        client.sync(*key).await?;
+        let user_data = client.get_user_data(*key).await?;
+        let transfers = user_data
+            .balances()
+            .iter()
+            // an insufficient balance cannot be spent until it is topped up
+            .filter(|(_, leaf)| !leaf.is_insufficient && leaf.amount != U256::default())
+            .map(|(token_index, leaf)| Transfer {
+                recipient: GenericAddress::from_pubkey(hot_wallet),
+                token_index: *token_index as u32,
+                amount: leaf.amount,
+                salt: generate_salt(),
+            })
+            .collect::<Vec<_>>();
+        if transfers.is_empty() {
+            println!("  nothing to sweep");
+            continue;
+        }
+        for chunk in transfers.chunks(NUM_TRANSFERS_IN_TX) {
+            println!("  sweeping {} tokens", chunk.len());
+            send_and_wait(&client, &env, *key, chunk.to_vec()).await?;
+        }
+        swept += 1;
+    }
+    println!("swept {} of {} accounts", swept, keys.len());
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    run().await
+}
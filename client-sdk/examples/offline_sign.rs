@@ -0,0 +1,101 @@
+//! Sign a tx on a machine that holds the key and has no network access, with an
+//! online machine doing everything else.
+//!
+//! ```sh
+//! INTMAX_PRIVATE_KEY=0x.. RECIPIENT_PUBKEY=0x.. cargo run --example offline_sign
+//! ```
+//!
+//! Both sides run in this process, and the data crossing between them is passed
+//! as JSON, as it would be through a file or QR code. Note that the online side
+//! also needs the key: building the spent proof and the backups of the tx are
+//! encrypted with it. Only the signature of the block, which authorizes the tx,
+//! is kept offline. `TRANSFER_AMOUNT` defaults to 1000.
+
+mod common;
+
+use intmax2_client_sdk::client::utils::generate_salt;
+use intmax2_interfaces::api::block_builder::interface::BlockBuilderClientInterface as _;
+use intmax2_zkp::{
+    common::{
+        block_builder::BlockProposal, generic_address::GenericAddress, signature::key_set::KeySet,
+        transfer::Transfer, tx::Tx,
+    },
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use serde::{Deserialize, Serialize};
+
+use common::{amount_from_env, build_client, key_from_env, load_env};
+
+/// What the online machine hands to the signer
+#[derive(Serialize, Deserialize)]
+struct SigningRequest {
+    tx: Tx,
+    proposal: BlockProposal,
+}
+
+/// The offline side: check that the block builder included our tx as requested,
+/// then sign. Returns the signature as JSON.
+fn sign_offline(key: KeySet, request_json: &str) -> anyhow::Result<String> {
+    let request: SigningRequest = serde_json::from_str(request_json)?;
+    // a proposal for another tx would make us sign a block we did not ask for
+    request.proposal.verify(request.tx)?;
+    let signature = request.proposal.sign(key);
+    Ok(serde_json::to_string(&signature.signature)?)
+}
+
+pub async fn run() -> anyhow::Result<()> {
+    let env = load_env()?;
+    let client = build_client(&env);
+    let key = key_from_env("INTMAX_PRIVATE_KEY")?;
+    let recipient = U256::from_hex(&std::env::var("RECIPIENT_PUBKEY")?)
+        .map_err(|_| anyhow::anyhow!("RECIPIENT_PUBKEY is not a hex pubkey"))?;
+    let amount = amount_from_env("TRANSFER_AMOUNT", 1000)?;
+
+    println!("1. online: syncing and requesting the tx");
+    client.sync(key).await?;
+    let transfer = Transfer {
+        recipient: GenericAddress::from_pubkey(recipient),
+        token_index: 0,
+        amount,
+        salt: generate_salt(),
+    };
+    let memo = client
+        .send_tx_request(&env.block_builder_url, key, vec![transfer])
+        .await?;
+    let proposal = client
+        .wait_for_proposal(&env.block_builder_url, key, &memo, 60, 2)
+        .await?;
+    // the backups have to be saved before the signature is posted, or the
+    // transfer could be settled without the recipient being able to find it
+    client.backup_tx(key, &memo, &proposal).await?;
+    let request_json = serde_json::to_string(&SigningRequest {
+        tx: memo.tx,
+        proposal: proposal.clone(),
+    })?;
+
+    println!("2. offline: verifying and signing the proposal");
+    let signature_json = sign_offline(key, &request_json)?;
+
+    println!("3. online: posting the signature");
+    let signature = serde_json::from_str(&signature_json)?;
+    client
+        .block_builder
+        .post_signature(
+            &env.block_builder_url,
+            memo.is_registration_block,
+            key.pubkey,
+            memo.tx,
+            signature,
+        )
+        .await?;
+    let block_number = client
+        .wait_for_inclusion(proposal.tx_tree_root, 120, 5)
+        .await?;
+    println!("  included in block {}", block_number);
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    run().await
+}
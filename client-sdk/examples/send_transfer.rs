@@ -0,0 +1,51 @@
+//! Send native tokens to another intmax account, and sync the recipient to see
+//! them arrive.
+//!
+//! ```sh
+//! INTMAX_PRIVATE_KEY=0x.. RECIPIENT_PRIVATE_KEY=0x.. cargo run --example send_transfer
+//! ```
+//!
+//! Only the recipient's pubkey is needed to send. The private key is used here to
+//! sync the recipient too. `TRANSFER_AMOUNT` defaults to 1000.
+
+mod common;
+
+use intmax2_client_sdk::client::utils::generate_salt;
+use intmax2_zkp::common::{generic_address::GenericAddress, transfer::Transfer};
+
+use common::{amount_from_env, build_client, key_from_env, load_env, send_and_wait};
+
+pub async fn run() -> anyhow::Result<()> {
+    let env = load_env()?;
+    let client = build_client(&env);
+    let key = key_from_env("INTMAX_PRIVATE_KEY")?;
+    let recipient = key_from_env("RECIPIENT_PRIVATE_KEY")?;
+    let amount = amount_from_env("TRANSFER_AMOUNT", 1000)?;
+
+    // the balance proof has to be up to date before a tx is sent
+    println!("1. syncing the sender {}", key.pubkey);
+    client.sync(key).await?;
+
+    println!("2. sending {} to {}", amount, recipient.pubkey);
+    let transfer = Transfer {
+        recipient: GenericAddress::from_pubkey(recipient.pubkey),
+        token_index: 0,
+        amount,
+        salt: generate_salt(),
+    };
+    send_and_wait(&client, &env, key, vec![transfer]).await?;
+
+    // the transfer is received when the recipient syncs
+    println!("3. syncing the recipient");
+    client.sync(recipient).await?;
+    let user_data = client.get_user_data(recipient).await?;
+    for (token_index, leaf) in user_data.balances().iter() {
+        println!("  token {}: {}", token_index, leaf.amount);
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    run().await
+}
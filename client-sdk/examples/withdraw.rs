@@ -0,0 +1,68 @@
+//! Withdraw native tokens to an ethereum address, and follow the withdrawal
+//! until it can be claimed on the liquidity contract.
+//!
+//! ```sh
+//! INTMAX_PRIVATE_KEY=0x.. WITHDRAWAL_RECIPIENT=0x.. cargo run --example withdraw
+//! ```
+//!
+//! A withdrawal is a transfer to an ethereum address. After the tx is in a block,
+//! its proof is sent to the withdrawal server, which relays it to ethereum.
+//! `WITHDRAWAL_AMOUNT` defaults to 1000.
+
+mod common;
+
+use std::time::Duration;
+
+use ethers::types::Address as EthAddress;
+use intmax2_client_sdk::client::utils::generate_salt;
+use intmax2_interfaces::api::withdrawal_server::interface::WithdrawalStatus;
+use intmax2_zkp::{
+    common::{generic_address::GenericAddress, transfer::Transfer},
+    ethereum_types::{address::Address, u32limb_trait::U32LimbTrait as _},
+};
+
+use common::{amount_from_env, build_client, key_from_env, load_env, send_and_wait};
+
+const MAX_STATUS_ATTEMPTS: usize = 30;
+
+pub async fn run() -> anyhow::Result<()> {
+    let env = load_env()?;
+    let client = build_client(&env);
+    let key = key_from_env("INTMAX_PRIVATE_KEY")?;
+    let recipient: EthAddress = std::env::var("WITHDRAWAL_RECIPIENT")?.parse()?;
+    let amount = amount_from_env("WITHDRAWAL_AMOUNT", 1000)?;
+
+    println!("1. syncing {}", key.pubkey);
+    client.sync(key).await?;
+
+    println!("2. sending {} to {:?}", amount, recipient);
+    let transfer = Transfer {
+        recipient: GenericAddress::from_address(Address::from_bytes_be(recipient.as_bytes())),
+        token_index: 0,
+        amount,
+        salt: generate_salt(),
+    };
+    send_and_wait(&client, &env, key, vec![transfer]).await?;
+
+    // proves the withdrawal and sends the proof to the withdrawal server
+    println!("3. syncing the withdrawal");
+    client.sync_withdrawals(key).await?;
+
+    println!("4. waiting for the withdrawal to be relayed");
+    for attempt in 1..=MAX_STATUS_ATTEMPTS {
+        let withdrawals = client.get_withdrawal_info(key).await?;
+        if let Some(latest) = withdrawals.last() {
+            println!("  attempt {}: {}", attempt, latest.status);
+            if !matches!(latest.status, WithdrawalStatus::Requested) {
+                return Ok(());
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+    anyhow::bail!("the withdrawal was not relayed in time")
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    run().await
+}
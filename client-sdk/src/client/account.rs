@@ -1,10 +1,17 @@
 use ark_bn254::Fr;
-use ethers::types::H256;
+use bip39::{Language, Mnemonic};
+use ethers::{
+    signers::{coins_bip39::English, MnemonicBuilder},
+    types::H256,
+};
 use intmax2_zkp::common::signature::key_set::KeySet;
 use num_bigint::BigUint;
 use num_traits::identities::Zero;
+use rand::Rng;
 use sha2::{Digest, Sha512};
 
+use super::error::ClientError;
+
 pub fn generate_intmax_account_from_eth_key(eth_private_key: H256) -> KeySet {
     let mut hasher = Sha512::new();
     loop {
@@ -19,12 +26,90 @@ pub fn generate_intmax_account_from_eth_key(eth_private_key: H256) -> KeySet {
     }
 }
 
+// the key at the BIP-32 `path` of the seed of `mnemonic`, whose words may be
+// separated by any whitespace
+fn derive_key_at_path(mnemonic: &str, path: &str) -> Result<H256, ClientError> {
+    let phrase = mnemonic.split_whitespace().collect::<Vec<_>>().join(" ");
+    // checks the word count, the words and the checksum
+    let mnemonic = Mnemonic::parse_in_normalized(Language::English, &phrase)
+        .map_err(|e| ClientError::InvalidMnemonic(e.to_string()))?;
+    let wallet = MnemonicBuilder::<English>::default()
+        .phrase(mnemonic.to_string().as_str())
+        .derivation_path(path)
+        .map_err(|e| ClientError::InvalidMnemonic(e.to_string()))?
+        .build()
+        .map_err(|e| ClientError::InvalidMnemonic(e.to_string()))?;
+    Ok(H256::from_slice(&wallet.signer().to_bytes()))
+}
+
+/// Private key of the Ethereum account `account_index` of a BIP-39 mnemonic, at
+/// the path `m/44'/60'/0'/0/{account_index}` that wallets such as MetaMask use
+pub fn derive_eth_key_from_mnemonic(
+    mnemonic: &str,
+    account_index: u32,
+) -> Result<H256, ClientError> {
+    derive_key_at_path(mnemonic, &format!("m/44'/60'/0'/0/{}", account_index))
+}
+
+/// The intmax account of the Ethereum account `account_index` of a mnemonic, the
+/// same as the one `generate_intmax_account_from_eth_key` gives for the key a
+/// wallet derives from the phrase. Fails if a word or the checksum is invalid.
+pub fn generate_intmax_account_from_mnemonic(
+    mnemonic: &str,
+    account_index: u32,
+) -> Result<KeySet, ClientError> {
+    let eth_private_key = derive_eth_key_from_mnemonic(mnemonic, account_index)?;
+    Ok(generate_intmax_account_from_eth_key(eth_private_key))
+}
+
+/// The intmax key of the HD account `account_index` of a BIP-39 mnemonic: the key
+/// at `m/44'/60'/{account_index}'/0/0` is the provisional private key of
+/// `KeySet::generate_from_provisional`. This is not the account
+/// `generate_intmax_account_from_mnemonic` gives for the same index.
+pub fn derive_key_from_mnemonic(mnemonic: &str, account_index: u32) -> Result<KeySet, ClientError> {
+    let child_key = derive_key_at_path(mnemonic, &format!("m/44'/60'/{}'/0/0", account_index))?;
+    let provisional_private_key: Fr = BigUint::from_bytes_be(child_key.as_bytes()).into();
+    if provisional_private_key.is_zero() {
+        return Err(ClientError::InvalidMnemonic(format!(
+            "the key of account {} is zero",
+            account_index
+        )));
+    }
+    Ok(KeySet::generate_from_provisional(provisional_private_key))
+}
+
+/// Number of words of the mnemonics of `generate_mnemonic`
+pub const MNEMONIC_WORD_COUNT: usize = 24;
+
+/// A new BIP-39 mnemonic of 24 English words, backing up the key
+/// `derive_key_from_mnemonic(mnemonic, 0)`
+pub fn generate_mnemonic<R: Rng>(rng: &mut R) -> String {
+    // 256 bits of entropy give 24 words
+    let entropy: [u8; 32] = rng.gen();
+    Mnemonic::from_entropy_in(Language::English, &entropy)
+        .expect("32 bytes is a valid entropy length")
+        .to_string()
+}
+
 #[cfg(test)]
 mod test {
     use ethers::types::H256;
-    use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait;
+    use intmax2_zkp::{
+        common::signature::key_set::KeySet, ethereum_types::u32limb_trait::U32LimbTrait,
+    };
+    use num_bigint::BigUint;
 
-    use crate::client::account::generate_intmax_account_from_eth_key;
+    use crate::client::{
+        account::{
+            derive_eth_key_from_mnemonic, derive_key_from_mnemonic,
+            generate_intmax_account_from_eth_key, generate_intmax_account_from_mnemonic,
+            generate_mnemonic, MNEMONIC_WORD_COUNT,
+        },
+        error::ClientError,
+    };
+
+    // the mnemonic of the default hardhat and anvil accounts
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
 
     struct TestCase {
         private_key: H256,
@@ -70,4 +155,124 @@ mod test {
             assert_eq!(account.pubkey.to_hex(), test_case.public_key);
         }
     }
+
+    #[test]
+    fn test_account_from_mnemonic() {
+        let eth_key = derive_eth_key_from_mnemonic(TEST_MNEMONIC, 0).unwrap();
+        let expected: H256 = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+            .parse()
+            .unwrap();
+        assert_eq!(eth_key, expected);
+        let second: H256 = "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            derive_eth_key_from_mnemonic(TEST_MNEMONIC, 1).unwrap(),
+            second
+        );
+
+        // extra whitespace is ignored
+        let account = generate_intmax_account_from_mnemonic(
+            &format!("  {}\n", TEST_MNEMONIC.replace(' ', "  ")),
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            account.pubkey,
+            generate_intmax_account_from_eth_key(expected).pubkey
+        );
+
+        // a bad checksum and an unknown word
+        let bad_checksum = ["abandon"; 12].join(" ");
+        assert!(matches!(
+            generate_intmax_account_from_mnemonic(&bad_checksum, 0),
+            Err(ClientError::InvalidMnemonic(_))
+        ));
+        let unknown_word = TEST_MNEMONIC.replace("junk", "junko");
+        assert!(matches!(
+            generate_intmax_account_from_mnemonic(&unknown_word, 0),
+            Err(ClientError::InvalidMnemonic(_))
+        ));
+    }
+
+    #[test]
+    fn test_derive_key_from_mnemonic() {
+        let first = derive_key_from_mnemonic(TEST_MNEMONIC, 0).unwrap();
+        let second = derive_key_from_mnemonic(TEST_MNEMONIC, 1).unwrap();
+        assert_ne!(first.pubkey, second.pubkey);
+        assert_eq!(
+            derive_key_from_mnemonic(TEST_MNEMONIC, 1).unwrap().pubkey,
+            second.pubkey
+        );
+
+        // the key at m/44'/60'/0'/0/0, which is also the first key of a wallet, is
+        // the provisional key of account 0, not hashed as an Ethereum key is
+        let eth_key = derive_eth_key_from_mnemonic(TEST_MNEMONIC, 0).unwrap();
+        let provisional =
+            KeySet::generate_from_provisional(BigUint::from_bytes_be(eth_key.as_bytes()).into());
+        assert_eq!(first.pubkey, provisional.pubkey);
+        assert_ne!(
+            first.pubkey,
+            generate_intmax_account_from_mnemonic(TEST_MNEMONIC, 0)
+                .unwrap()
+                .pubkey
+        );
+
+        let eleven_words = TEST_MNEMONIC.trim_end_matches(" junk");
+        assert!(matches!(
+            derive_key_from_mnemonic(eleven_words, 0),
+            Err(ClientError::InvalidMnemonic(_))
+        ));
+    }
+
+    struct MnemonicTestCase {
+        mnemonic: &'static str,
+        account_index: u32,
+        child_key: &'static str,
+    }
+
+    // pinned, so that a change of the path, which would make backups recover other
+    // accounts, fails here
+    #[test]
+    fn test_derive_key_from_mnemonic_vectors() {
+        let test_cases = [
+            MnemonicTestCase {
+                mnemonic: TEST_MNEMONIC,
+                account_index: 0,
+                child_key: "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            },
+            MnemonicTestCase {
+                mnemonic: TEST_MNEMONIC,
+                account_index: 1,
+                child_key: "7797c0f3db8b946604ec2039dfd9763e4ffdc53174342a2ed9b14fa3eda666a5",
+            },
+            MnemonicTestCase {
+                mnemonic: TEST_MNEMONIC,
+                account_index: 2,
+                child_key: "19d1b9afaf5b1f79f708bd95673df2203213fdbbdafe50e70f056c2fecaa799e",
+            },
+        ];
+        for test_case in test_cases.iter() {
+            let key =
+                derive_key_from_mnemonic(test_case.mnemonic, test_case.account_index).unwrap();
+            let child_key: H256 = test_case.child_key.parse().unwrap();
+            let expected = KeySet::generate_from_provisional(
+                BigUint::from_bytes_be(child_key.as_bytes()).into(),
+            );
+            assert_eq!(key.pubkey, expected.pubkey);
+            // the private key alone gives the same account
+            assert_eq!(KeySet::new(key.privkey).pubkey, key.pubkey);
+        }
+    }
+
+    #[test]
+    fn test_generate_mnemonic() {
+        let mnemonic = generate_mnemonic(&mut rand::thread_rng());
+        assert_eq!(mnemonic.split(' ').count(), MNEMONIC_WORD_COUNT);
+        let key = derive_key_from_mnemonic(&mnemonic, 0).unwrap();
+        assert_eq!(
+            derive_key_from_mnemonic(&mnemonic, 0).unwrap().pubkey,
+            key.pubkey
+        );
+    }
 }
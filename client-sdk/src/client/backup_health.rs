@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+
+use intmax2_interfaces::{
+    api::{
+        store_vault_server::interface::{DataType, StoreVaultClientInterface},
+        validity_prover::interface::ValidityProverClientInterface,
+    },
+    data::{tx_data::TxData, user_data::UserData},
+};
+use intmax2_zkp::{common::signature::key_set::KeySet, ethereum_types::bytes32::Bytes32};
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use serde::{Deserialize, Serialize};
+
+use super::error::ClientError;
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// How many of the expected backups of one data type are in the store vault
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupCoverage {
+    pub expected: usize,
+    pub backed_up: usize,
+    // uuids of the missing backups, or tx tree roots for txs only known locally
+    pub missing: Vec<String>,
+}
+
+impl BackupCoverage {
+    fn new(expected: Vec<String>, stored: &HashSet<String>) -> Self {
+        let missing = expected
+            .iter()
+            .filter(|id| !stored.contains(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+        Self {
+            expected: expected.len(),
+            backed_up: expected.len() - missing.len(),
+            missing,
+        }
+    }
+}
+
+/// Whether everything needed to recover the account is in the store vault
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupHealth {
+    pub deposits: BackupCoverage,
+    pub transfers: BackupCoverage,
+    pub txs: BackupCoverage,
+    pub user_data_present: bool,
+    pub last_backup_at: Option<u64>, // timestamp of the latest backup
+}
+
+impl BackupHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.user_data_present
+            && self.deposits.missing.is_empty()
+            && self.transfers.missing.is_empty()
+            && self.txs.missing.is_empty()
+    }
+}
+
+/// Cross-reference the actions recorded in the user data, and the own txs known
+/// locally (`known_tx_tree_roots`, e.g. from pending memos) that are included in a
+/// block, with the backups in the store vault.
+pub async fn check_backup_health<S, V>(
+    store_vault_server: &S,
+    validity_prover: &V,
+    key: KeySet,
+    known_tx_tree_roots: &[Bytes32],
+) -> Result<BackupHealth, ClientError>
+where
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+{
+    let user_data = store_vault_server.get_user_data(key.pubkey).await?;
+    let user_data_present = user_data.is_some();
+    let user_data = match user_data {
+        Some(bytes) => UserData::decrypt(&bytes, key)
+            .map_err(|e| ClientError::DecryptionError(e.to_string()))?,
+        None => UserData::new(key.pubkey),
+    };
+
+    let mut stored = HashMap::new();
+    let mut last_backup_at = None;
+    for data_type in [DataType::Deposit, DataType::Transfer, DataType::Tx] {
+        let blobs = store_vault_server
+            .get_data_all_after(data_type, key.pubkey, 0)
+            .await?;
+        last_backup_at = blobs
+            .iter()
+            .map(|(meta, _)| meta.timestamp)
+            .chain(last_backup_at)
+            .max();
+        stored.insert(data_type, blobs);
+    }
+    let uuids = |data_type: DataType| -> HashSet<String> {
+        stored[&data_type]
+            .iter()
+            .map(|(meta, _)| meta.uuid.clone())
+            .collect()
+    };
+
+    let deposits = BackupCoverage::new(
+        user_data.processed_deposit_uuids.clone(),
+        &uuids(DataType::Deposit),
+    );
+    let transfers = BackupCoverage::new(
+        user_data.processed_transfer_uuids.clone(),
+        &uuids(DataType::Transfer),
+    );
+    let mut txs = BackupCoverage::new(user_data.processed_tx_uuids.clone(), &uuids(DataType::Tx));
+
+    if !known_tx_tree_roots.is_empty() {
+        let backed_up_roots = stored[&DataType::Tx]
+            .iter()
+            .filter_map(|(_, data)| TxData::<F, C, D>::decrypt(data, key).ok())
+            .map(|tx_data| tx_data.common.tx_tree_root.to_string())
+            .collect::<HashSet<_>>();
+        for tx_tree_root in known_tx_tree_roots {
+            let block_number = validity_prover
+                .get_block_number_by_tx_tree_root(*tx_tree_root)
+                .await?;
+            if block_number.is_none() {
+                // not on chain, so there is nothing to recover yet
+                continue;
+            }
+            txs.expected += 1;
+            if backed_up_roots.contains(&tx_tree_root.to_string()) {
+                txs.backed_up += 1;
+            } else {
+                txs.missing.push(tx_tree_root.to_string());
+            }
+        }
+    }
+
+    Ok(BackupHealth {
+        deposits,
+        transfers,
+        txs,
+        user_data_present,
+        last_backup_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_interfaces::{
+        api::store_vault_server::interface::{DataType, StoreVaultClientInterface as _},
+        data::user_data::UserData,
+    };
+    use intmax2_zkp::common::signature::key_set::KeySet;
+
+    use crate::external_api::{
+        utils::in_memory_store_vault::InMemoryStoreVault, validity_prover::ValidityProverClient,
+    };
+
+    use super::check_backup_health;
+
+    #[tokio::test]
+    async fn test_missing_tx_backup_is_flagged() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let store_vault = InMemoryStoreVault::new();
+        // never called without known tx tree roots
+        let validity_prover = ValidityProverClient::new("http://localhost:1");
+
+        let mut user_data = UserData::new(key.pubkey);
+        for _ in 0..2 {
+            let uuid = store_vault.insert(DataType::Tx, key.pubkey, vec![0u8; 8]);
+            user_data.processed_tx_uuids.push(uuid);
+        }
+        let uuid = store_vault.insert(DataType::Deposit, key.pubkey, vec![1u8; 8]);
+        user_data.processed_deposit_uuids.push(uuid);
+        store_vault
+            .save_user_data(key.pubkey, user_data.encrypt(key.pubkey))
+            .await
+            .unwrap();
+
+        let health = check_backup_health(&store_vault, &validity_prover, key, &[])
+            .await
+            .unwrap();
+        assert!(health.is_healthy());
+        assert_eq!(health.txs.backed_up, 2);
+        assert!(health.last_backup_at.is_some());
+
+        let deleted = user_data.processed_tx_uuids[1].clone();
+        assert!(store_vault.remove(&deleted));
+        let health = check_backup_health(&store_vault, &validity_prover, key, &[])
+            .await
+            .unwrap();
+        assert!(!health.is_healthy());
+        assert_eq!(health.txs.expected, 2);
+        assert_eq!(health.txs.backed_up, 1);
+        assert_eq!(health.txs.missing, vec![deleted]);
+        assert!(health.deposits.missing.is_empty());
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike as _, Duration, NaiveDate, Utc};
+use intmax2_zkp::ethereum_types::u256::U256;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use super::history::{GenericTransfer, HistoryEntry};
+
+/// Balances per token index as of a block, replayed from the history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoricalBalance {
+    pub block_number: u32,
+    pub balances: BTreeMap<u32, U256>,
+    /// Entries left out because they are not synced yet, so their block is unknown
+    pub excluded_entries: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SeriesInterval {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+/// Balances at the end of a period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalancePoint {
+    /// Unix timestamps of the start (inclusive) and end (exclusive) of the period
+    pub start: u64,
+    pub end: u64,
+    pub balances: BTreeMap<u32, U256>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceSeries {
+    pub points: Vec<BalancePoint>,
+    /// Entries left out because they are not synced yet
+    pub excluded_entries: usize,
+}
+
+// (block number, timestamp, signed amounts by token) of an entry that changed the
+// balance, `None` for rejected entries and deposits of unregistered tokens
+type Effect = (Option<u32>, Option<u64>, Vec<(u32, BigUint, bool)>);
+
+fn effect(entry: &HistoryEntry) -> Option<Effect> {
+    match entry {
+        HistoryEntry::Deposit {
+            token_index: Some(token_index),
+            amount,
+            is_rejected: false,
+            timestamp,
+            block_number,
+            ..
+        } => Some((
+            *block_number,
+            *timestamp,
+            vec![(*token_index, BigUint::from(*amount), true)],
+        )),
+        HistoryEntry::Receive {
+            amount,
+            token_index,
+            is_rejected: false,
+            is_expired: false,
+            timestamp,
+            block_number,
+            ..
+        } => Some((
+            *block_number,
+            *timestamp,
+            vec![(*token_index, BigUint::from(*amount), true)],
+        )),
+        HistoryEntry::Send {
+            transfers,
+            fee,
+            is_rejected: false,
+            timestamp,
+            block_number,
+            ..
+        } => {
+            let amounts = transfers
+                .iter()
+                .chain(fee.iter().map(|fee| &fee.transfer))
+                .map(|transfer| match transfer {
+                    GenericTransfer::Transfer {
+                        token_index,
+                        amount,
+                        ..
+                    }
+                    | GenericTransfer::Withdrawal {
+                        token_index,
+                        amount,
+                        ..
+                    } => (*token_index, BigUint::from(*amount), false),
+                })
+                .collect();
+            Some((*block_number, *timestamp, amounts))
+        }
+        _ => None,
+    }
+}
+
+fn apply(balances: &mut BTreeMap<u32, BigUint>, amounts: &[(u32, BigUint, bool)]) {
+    for (token_index, amount, is_credit) in amounts {
+        let balance = balances.entry(*token_index).or_default();
+        if *is_credit {
+            *balance += amount;
+        } else if *balance >= *amount {
+            *balance -= amount;
+        } else {
+            // the history lacks the entry that funded it, e.g. an unreadable backup
+            *balance = BigUint::default();
+        }
+    }
+}
+
+fn to_u256(balances: &BTreeMap<u32, BigUint>) -> BTreeMap<u32, U256> {
+    balances
+        .iter()
+        .map(|(token_index, amount)| (*token_index, amount.clone().try_into().unwrap()))
+        .collect()
+}
+
+/// Replay the entries included up to `block_number`
+pub fn balance_at_block(history: &[HistoryEntry], block_number: u32) -> HistoricalBalance {
+    let mut balances = BTreeMap::new();
+    let mut excluded_entries = 0;
+    for (entry_block_number, _, amounts) in history.iter().filter_map(effect) {
+        match entry_block_number {
+            Some(entry_block_number) if entry_block_number <= block_number => {
+                apply(&mut balances, &amounts)
+            }
+            Some(_) => {}
+            None => excluded_entries += 1,
+        }
+    }
+    HistoricalBalance {
+        block_number,
+        balances: to_u256(&balances),
+        excluded_entries,
+    }
+}
+
+fn period_start(interval: SeriesInterval, timestamp: u64) -> NaiveDate {
+    let date = DateTime::<Utc>::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .date_naive();
+    match interval {
+        SeriesInterval::Daily => date,
+        SeriesInterval::Weekly => {
+            date - Duration::days(date.weekday().num_days_from_monday() as i64)
+        }
+        SeriesInterval::Monthly => date.with_day(1).unwrap(),
+    }
+}
+
+fn next_period_start(interval: SeriesInterval, start: NaiveDate) -> NaiveDate {
+    match interval {
+        SeriesInterval::Daily => start + Duration::days(1),
+        SeriesInterval::Weekly => start + Duration::days(7),
+        SeriesInterval::Monthly => {
+            let (year, month) = if start.month() == 12 {
+                (start.year() + 1, 1)
+            } else {
+                (start.year(), start.month() + 1)
+            };
+            NaiveDate::from_ymd_opt(year, month, 1).unwrap()
+        }
+    }
+}
+
+fn unix(date: NaiveDate) -> u64 {
+    date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() as u64
+}
+
+/// Balances at the end of every period (UTC) from the first entry to `now`. Entries
+/// are placed by their timestamp.
+pub fn balance_series(
+    history: &[HistoryEntry],
+    interval: SeriesInterval,
+    now: u64,
+) -> BalanceSeries {
+    let mut effects = Vec::new();
+    let mut excluded_entries = 0;
+    for (_, timestamp, amounts) in history.iter().filter_map(effect) {
+        match timestamp {
+            Some(timestamp) => effects.push((timestamp, amounts)),
+            None => excluded_entries += 1,
+        }
+    }
+    effects.sort_by_key(|(timestamp, _)| *timestamp);
+    let Some((first_timestamp, _)) = effects.first() else {
+        return BalanceSeries {
+            points: Vec::new(),
+            excluded_entries,
+        };
+    };
+
+    let mut points = Vec::new();
+    let mut balances = BTreeMap::new();
+    let mut effects = effects.iter().peekable();
+    let mut start = period_start(interval, *first_timestamp);
+    while unix(start) <= now {
+        let end = next_period_start(interval, start);
+        while let Some((_, amounts)) = effects.next_if(|(timestamp, _)| *timestamp < unix(end)) {
+            apply(&mut balances, amounts);
+        }
+        points.push(BalancePoint {
+            start: unix(start),
+            end: unix(end),
+            balances: to_u256(&balances),
+        });
+        start = end;
+    }
+    BalanceSeries {
+        points,
+        excluded_entries,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_interfaces::data::deposit_data::TokenType;
+    use intmax2_zkp::ethereum_types::{address::Address, u32limb_trait::U32LimbTrait as _};
+
+    use super::*;
+
+    fn value(value: u32) -> U256 {
+        U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, value])
+    }
+
+    fn deposit(amount: u32, block_number: Option<u32>, timestamp: Option<u64>) -> HistoryEntry {
+        HistoryEntry::Deposit {
+            token_type: TokenType::NATIVE,
+            token_address: Address::default(),
+            token_id: U256::default(),
+            token_index: Some(0),
+            amount: value(amount),
+            is_rejected: false,
+            timestamp,
+            block_number,
+        }
+    }
+
+    fn send(amounts: &[(u32, u32)], block_number: u32, timestamp: u64) -> HistoryEntry {
+        HistoryEntry::Send {
+            transfers: amounts
+                .iter()
+                .map(|(token_index, amount)| GenericTransfer::Transfer {
+                    recipient: U256::default(),
+                    token_index: *token_index,
+                    amount: value(*amount),
+                })
+                .collect(),
+            fee: None,
+            is_rejected: false,
+            claim_deadline_block: None,
+            timestamp: Some(timestamp),
+            block_number: Some(block_number),
+        }
+    }
+
+    fn receive(token_index: u32, amount: u32, block_number: u32, timestamp: u64) -> HistoryEntry {
+        HistoryEntry::Receive {
+            amount: value(amount),
+            token_index,
+            from: U256::default(),
+            is_rejected: false,
+            is_expired: false,
+            timestamp: Some(timestamp),
+            block_number: Some(block_number),
+        }
+    }
+
+    // 2026-01-10, 2026-01-20, 2026-02-05 and 2026-03-15 UTC
+    const JAN_10: u64 = 1768003200;
+    const JAN_20: u64 = 1768867200;
+    const FEB_05: u64 = 1770249600;
+    const MAR_15: u64 = 1773532800;
+
+    fn history() -> Vec<HistoryEntry> {
+        let mut rejected = send(&[(0, 1000)], 11, JAN_20);
+        if let HistoryEntry::Send { is_rejected, .. } = &mut rejected {
+            *is_rejected = true;
+        }
+        vec![
+            deposit(100, Some(10), Some(JAN_10)),
+            rejected,
+            send(&[(0, 30), (0, 5)], 12, JAN_20),
+            receive(1, 7, 15, FEB_05),
+            send(&[(1, 2)], 20, MAR_15),
+            // not synced yet
+            deposit(50, None, None),
+        ]
+    }
+
+    #[test]
+    fn test_balance_at_block() {
+        let history = history();
+        let at = |block_number| balance_at_block(&history, block_number).balances;
+
+        assert!(at(9).is_empty());
+        assert_eq!(at(10), BTreeMap::from([(0, value(100))]));
+        assert_eq!(at(11), BTreeMap::from([(0, value(100))]));
+        assert_eq!(at(14), BTreeMap::from([(0, value(65))]));
+        assert_eq!(at(15), BTreeMap::from([(0, value(65)), (1, value(7))]));
+        assert_eq!(at(100), BTreeMap::from([(0, value(65)), (1, value(5))]));
+        assert_eq!(balance_at_block(&history, 100).excluded_entries, 1);
+    }
+
+    #[test]
+    fn test_monthly_series() {
+        let series = balance_series(&history(), SeriesInterval::Monthly, MAR_15 + 1);
+        assert_eq!(series.excluded_entries, 1);
+        let balances = series
+            .points
+            .iter()
+            .map(|point| point.balances.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            balances,
+            vec![
+                BTreeMap::from([(0, value(65))]),
+                BTreeMap::from([(0, value(65)), (1, value(7))]),
+                BTreeMap::from([(0, value(65)), (1, value(5))]),
+            ]
+        );
+        // 2026-01-01 to 2026-02-01
+        assert_eq!(series.points[0].start, 1767225600);
+        assert_eq!(series.points[0].end, 1769904000);
+    }
+}
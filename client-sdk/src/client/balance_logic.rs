@@ -6,13 +6,16 @@ use intmax2_interfaces::{
     data::{common_tx_data::CommonTxData, deposit_data::DepositData, transfer_data::TransferData},
 };
 use intmax2_zkp::{
-    circuits::balance::{
-        balance_pis::BalancePublicInputs, balance_processor::get_prev_balance_pis,
+    circuits::{
+        balance::{balance_pis::BalancePublicInputs, balance_processor::get_prev_balance_pis},
+        validity::validity_pis::ValidityPublicInputs,
     },
     common::{
+        deposit::Deposit,
         private_state::FullPrivateState,
         salt::Salt,
         signature::key_set::KeySet,
+        trees::{block_hash_tree::BlockHashMerkleProof, deposit_tree::DepositMerkleProof},
         witness::{
             deposit_witness::DepositWitness, private_transition_witness::PrivateTransitionWitness,
             receive_deposit_witness::ReceiveDepositWitness,
@@ -21,6 +24,7 @@ use intmax2_zkp::{
         },
     },
     ethereum_types::{bytes32::Bytes32, u256::U256},
+    utils::leafable::Leafable as _,
 };
 use plonky2::{
     field::goldilocks_field::GoldilocksField,
@@ -70,13 +74,21 @@ pub async fn process_deposit<V: ValidityProverClientInterface, B: BalanceProverC
     let deposit_merkle_proof = validity_prover
         .get_deposit_merkle_proof(receive_block_number, deposit_info.deposit_index)
         .await?;
+    let deposit = deposit_data.deposit().unwrap();
+    let receive_validity_pis = get_validity_pis(validity_prover, receive_block_number).await?;
+    verify_deposit_merkle_proof(
+        &deposit,
+        deposit_info.deposit_hash,
+        deposit_info.deposit_index,
+        &deposit_merkle_proof,
+        receive_validity_pis.public_state.deposit_tree_root,
+    )?;
     let deposit_witness = DepositWitness {
         deposit_salt: deposit_data.deposit_salt,
         deposit_index: deposit_info.deposit_index as u32,
-        deposit: deposit_data.deposit().unwrap(),
+        deposit: deposit.clone(),
         deposit_merkle_proof,
     };
-    let deposit = deposit_data.deposit().unwrap();
     let nullifier: Bytes32 = deposit.poseidon_hash().into();
     let private_transition_witness = PrivateTransitionWitness::new(
         full_private_state,
@@ -120,9 +132,10 @@ pub async fn process_transfer<V: ValidityProverClientInterface, B: BalanceProver
 ) -> Result<ProofWithPublicInputs<F, C, D>, ClientError> {
     let sender_balance_pis = BalancePublicInputs::from_pis(&sender_balance_proof.public_inputs);
     if sender_balance_pis.public_state.block_number > receive_block_number {
-        return Err(ClientError::InternalError(
-            "Sender's block number is greater than receive block number".to_string(),
-        ));
+        return Err(ClientError::InvalidProof(format!(
+            "sender's balance proof is at block {}, after the receive block {}",
+            sender_balance_pis.public_state.block_number, receive_block_number
+        )));
     }
 
     // update balance proof up to the deposit block
@@ -158,6 +171,12 @@ pub async fn process_transfer<V: ValidityProverClientInterface, B: BalanceProver
             sender_balance_pis.public_state.block_number,
         )
         .await?;
+    let receive_validity_pis = get_validity_pis(validity_prover, receive_block_number).await?;
+    verify_block_merkle_proof(
+        &block_merkle_proof,
+        &sender_balance_pis,
+        &receive_validity_pis,
+    )?;
     let receive_trasfer_witness = ReceiveTransferWitness {
         transfer_witness,
         private_transition_witness,
@@ -204,13 +223,7 @@ pub async fn process_common_tx<
     }
 
     // get witness
-    let validity_pis = validity_prover
-        .get_validity_pis(tx_block_number)
-        .await?
-        .ok_or(ClientError::InternalError(format!(
-            "validity public inputs not found for block number {}",
-            tx_block_number
-        )))?;
+    let validity_pis = get_validity_pis(validity_prover, tx_block_number).await?;
 
     let sender_leaves = validity_prover
         .get_sender_leaves(tx_block_number)
@@ -235,6 +248,11 @@ pub async fn process_common_tx<
             true,
         )
         .await?;
+    verify_block_merkle_proof(
+        &update_witness.block_merkle_proof,
+        &prev_balance_pis,
+        &ValidityPublicInputs::from_pis(&update_witness.validity_proof.public_inputs),
+    )?;
 
     // prove tx send
     let balance_proof = balance_processor
@@ -287,14 +305,128 @@ async fn update_balance_proof<V: ValidityProverClientInterface, B: BalanceProver
             false,
         )
         .await?;
+    verify_block_merkle_proof(
+        &update_witness.block_merkle_proof,
+        &prev_balance_pis,
+        &ValidityPublicInputs::from_pis(&update_witness.validity_proof.public_inputs),
+    )?;
     let last_block_number = update_witness.get_last_block_number();
     if last_block_number > block_number {
-        return Err(ClientError::InternalError(
-            "There is a sent tx after prev balance proof".to_string(),
-        ));
+        return Err(ClientError::InvalidProof(format!(
+            "update witness has a tx sent at block {}, after block {}",
+            last_block_number, block_number
+        )));
     }
     let balance_proof = balance_processor
         .prove_update(key, pubkey, &update_witness, &prev_balance_proof)
         .await?;
     Ok(balance_proof)
 }
+
+async fn get_validity_pis<V: ValidityProverClientInterface>(
+    validity_prover: &V,
+    block_number: u32,
+) -> Result<ValidityPublicInputs, ClientError> {
+    validity_prover
+        .get_validity_pis(block_number)
+        .await?
+        .ok_or(ClientError::InternalError(format!(
+            "validity public inputs not found for block number {}",
+            block_number
+        )))
+}
+
+// Check the deposit merkle proof returned by the validity prover against our own deposit,
+// so that an inconsistent proof fails here instead of in the circuit.
+fn verify_deposit_merkle_proof(
+    deposit: &Deposit,
+    deposit_hash: Bytes32,
+    deposit_index: u32,
+    deposit_merkle_proof: &DepositMerkleProof,
+    deposit_tree_root: Bytes32,
+) -> Result<(), ClientError> {
+    if deposit.hash() != deposit_hash {
+        return Err(ClientError::InvalidDepositProof {
+            deposit_index,
+            reason: format!(
+                "deposit hash {} returned by the validity prover does not match the deposit {}",
+                deposit_hash,
+                deposit.hash()
+            ),
+        });
+    }
+    deposit_merkle_proof
+        .verify(deposit, deposit_index as u64, deposit_tree_root)
+        .map_err(|e| ClientError::InvalidDepositProof {
+            deposit_index,
+            reason: format!(
+                "merkle proof does not lead to deposit tree root {}: {}",
+                deposit_tree_root, e
+            ),
+        })
+}
+
+// Check that the block hash of `leaf_pis` is included in the block tree of `root_pis`.
+fn verify_block_merkle_proof(
+    block_merkle_proof: &BlockHashMerkleProof,
+    leaf_pis: &BalancePublicInputs,
+    root_pis: &ValidityPublicInputs,
+) -> Result<(), ClientError> {
+    let leaf_block_number = leaf_pis.public_state.block_number;
+    let root_block_number = root_pis.public_state.block_number;
+    block_merkle_proof
+        .verify(
+            &leaf_pis.public_state.block_hash,
+            leaf_block_number as u64,
+            root_pis.public_state.block_tree_root,
+        )
+        .map_err(|e| ClientError::InvalidBlockMerkleProof {
+            root_block_number,
+            leaf_block_number,
+            reason: format!(
+                "merkle proof does not lead to block tree root {}: {}",
+                root_pis.public_state.block_tree_root, e
+            ),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{common::trees::deposit_tree::DepositTree, constants::DEPOSIT_TREE_HEIGHT};
+
+    use super::*;
+
+    #[test]
+    fn test_verify_deposit_merkle_proof() {
+        let mut rng = rand::thread_rng();
+        let mut deposit_tree = DepositTree::new(DEPOSIT_TREE_HEIGHT);
+        for _ in 0..4 {
+            deposit_tree.push(Deposit::rand(&mut rng));
+        }
+        let root = deposit_tree.get_root();
+        let deposit = deposit_tree.get_leaf(2);
+        let proof = deposit_tree.prove(2);
+        verify_deposit_merkle_proof(&deposit, deposit.hash(), 2, &proof, root).unwrap();
+
+        // proof of another index
+        let result = verify_deposit_merkle_proof(&deposit, deposit.hash(), 1, &proof, root);
+        assert!(matches!(
+            result,
+            Err(ClientError::InvalidDepositProof {
+                deposit_index: 1,
+                ..
+            })
+        ));
+
+        // deposit info of another deposit
+        let other = deposit_tree.get_leaf(1);
+        let result = verify_deposit_merkle_proof(&deposit, other.hash(), 2, &proof, root);
+        assert!(matches!(
+            result,
+            Err(ClientError::InvalidDepositProof {
+                deposit_index: 2,
+                ..
+            })
+        ));
+    }
+}
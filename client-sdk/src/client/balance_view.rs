@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+
+use intmax2_interfaces::data::deposit_data::TokenType;
+use intmax2_zkp::ethereum_types::{address::Address, u256::U256};
+use serde::{Deserialize, Serialize};
+
+/// Balance of a token index with the token it stands for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenBalance {
+    pub token_index: u32,
+    pub token_type: TokenType,
+    pub token_address: Address,
+    pub token_id: U256,
+    pub amount: U256,
+    pub is_insufficient: bool,
+}
+
+/// The NFTs owned of one contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftHolding {
+    pub token_type: TokenType,
+    pub token_address: Address,
+    /// Owned ids in ascending token index order, with the amount for ERC1155
+    pub tokens: Vec<OwnedNft>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedNft {
+    pub token_index: u32,
+    pub token_id: U256,
+    pub amount: U256,
+}
+
+/// Balances split by token type: fungible tokens as amounts, NFTs as the ids
+/// owned of each contract. Every id of an NFT contract has its own token index.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceView {
+    pub fungible: Vec<TokenBalance>,
+    pub nfts: Vec<NftHolding>,
+}
+
+impl BalanceView {
+    pub fn new(balances: Vec<TokenBalance>) -> Self {
+        let mut fungible = Vec::new();
+        let mut nfts = BTreeMap::<(u8, Vec<u8>), NftHolding>::new();
+        for balance in balances {
+            if !balance.token_type.is_nft() {
+                fungible.push(balance);
+                continue;
+            }
+            // an NFT that was sent away leaves a zero leaf behind
+            if balance.amount == U256::default() {
+                continue;
+            }
+            let key = (
+                balance.token_type as u8,
+                balance.token_address.to_bytes_be(),
+            );
+            nfts.entry(key)
+                .or_insert_with(|| NftHolding {
+                    token_type: balance.token_type,
+                    token_address: balance.token_address,
+                    tokens: Vec::new(),
+                })
+                .tokens
+                .push(OwnedNft {
+                    token_index: balance.token_index,
+                    token_id: balance.token_id,
+                    amount: balance.amount,
+                });
+        }
+        fungible.sort_by_key(|balance| balance.token_index);
+        let mut nfts = nfts.into_values().collect::<Vec<_>>();
+        for holding in nfts.iter_mut() {
+            holding.tokens.sort_by_key(|token| token.token_index);
+        }
+        Self { fungible, nfts }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_interfaces::data::deposit_data::{DepositData, TokenType};
+    use intmax2_zkp::{
+        common::{deposit::get_pubkey_salt_hash, signature::key_set::KeySet},
+        ethereum_types::{address::Address, u256::U256, u32limb_trait::U32LimbTrait as _},
+    };
+
+    use crate::client::utils::generate_salt;
+
+    use super::{BalanceView, TokenBalance};
+
+    fn value(value: u32) -> U256 {
+        U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, value])
+    }
+
+    fn balance(
+        token_index: u32,
+        token_type: TokenType,
+        token_id: u32,
+        amount: u32,
+    ) -> TokenBalance {
+        let token_address = match token_type {
+            TokenType::NATIVE => Address::default(),
+            _ => Address::from_u32_slice(&[0, 0, 0, 0, token_type as u32]),
+        };
+        TokenBalance {
+            token_index,
+            token_type,
+            token_address,
+            token_id: value(token_id),
+            amount: value(amount),
+            is_insufficient: false,
+        }
+    }
+
+    #[test]
+    fn test_nfts_are_listed_by_id() {
+        let view = BalanceView::new(vec![
+            balance(5, TokenType::ERC721, 42, 1),
+            balance(0, TokenType::NATIVE, 0, 100),
+            balance(3, TokenType::ERC721, 7, 1),
+            balance(4, TokenType::ERC721, 8, 0),
+            balance(6, TokenType::ERC1155, 1, 30),
+            balance(1, TokenType::ERC20, 0, 0),
+        ]);
+        assert_eq!(
+            view.fungible
+                .iter()
+                .map(|b| b.token_index)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert_eq!(view.nfts.len(), 2);
+        let erc721 = &view.nfts[0];
+        assert_eq!(erc721.token_type, TokenType::ERC721);
+        assert_eq!(
+            erc721.tokens.iter().map(|t| t.token_id).collect::<Vec<_>>(),
+            vec![value(7), value(42)]
+        );
+        assert_eq!(view.nfts[1].tokens[0].amount, value(30));
+    }
+
+    #[test]
+    fn test_check_amount() {
+        assert!(TokenType::ERC721.check_amount(value(1), value(7)).is_ok());
+        assert!(TokenType::ERC721.check_amount(value(2), value(7)).is_err());
+        assert!(TokenType::ERC1155.check_amount(value(2), value(7)).is_ok());
+        assert!(TokenType::ERC20.check_amount(value(2), value(7)).is_err());
+        assert!(TokenType::NATIVE
+            .check_amount(U256::default(), U256::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_erc721_deposit_to_withdrawal() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let deposit_salt = generate_salt();
+        let token_address = Address::from_u32_slice(&[0, 0, 0, 0, 721]);
+        let deposit_data = DepositData {
+            deposit_salt,
+            pubkey_salt_hash: get_pubkey_salt_hash(key.pubkey, deposit_salt),
+            amount: value(1),
+            token_type: TokenType::ERC721,
+            token_address,
+            token_id: value(42),
+            token_index: Some(3),
+        };
+        TokenType::ERC721
+            .check_amount(deposit_data.amount, deposit_data.token_id)
+            .unwrap();
+
+        // the backup keeps the token type and id
+        let restored = DepositData::decrypt(&deposit_data.encrypt(key.pubkey), key).unwrap();
+        assert_eq!(restored.token_type, TokenType::ERC721);
+        assert_eq!(restored.token_address, token_address);
+        assert_eq!(restored.token_id, value(42));
+
+        // the balance lists the owned id, not an amount
+        let view = BalanceView::new(vec![
+            balance(0, TokenType::NATIVE, 0, 100),
+            TokenBalance {
+                token_index: restored.token_index.unwrap(),
+                token_type: restored.token_type,
+                token_address: restored.token_address,
+                token_id: restored.token_id,
+                amount: restored.amount,
+                is_insufficient: false,
+            },
+        ]);
+        assert_eq!(view.fungible.len(), 1);
+        assert_eq!(view.nfts.len(), 1);
+        assert_eq!(view.nfts[0].token_address, token_address);
+        assert_eq!(view.nfts[0].tokens[0].token_id, value(42));
+
+        // it is withdrawn whole
+        assert!(TokenType::ERC721.check_amount(value(1), value(42)).is_ok());
+        assert!(TokenType::ERC721.check_amount(value(2), value(42)).is_err());
+
+        // once withdrawn, its zero leaf is no longer listed
+        let view = BalanceView::new(vec![balance(3, TokenType::ERC721, 42, 0)]);
+        assert!(view.nfts.is_empty());
+    }
+}
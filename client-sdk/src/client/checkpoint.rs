@@ -0,0 +1,355 @@
+use std::cell::RefCell;
+
+use async_trait::async_trait;
+use intmax2_interfaces::{
+    api::{
+        error::ServerError,
+        store_vault_server::interface::{DataType, StoreVaultClientInterface},
+    },
+    data::meta_data::MetaData,
+};
+use intmax2_zkp::{
+    circuits::balance::balance_pis::BalancePublicInputs, ethereum_types::u256::U256,
+    utils::poseidon_hash_out::PoseidonHashOut,
+};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+};
+use serde::{Deserialize, Serialize};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Progress of a sync saved to the store vault: the user data and balance proofs
+/// of its first `num_actions` actions, up to the end of `block_number`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncCheckpoint {
+    pub block_number: u32,
+    pub num_actions: u32,
+}
+
+/// Store vault client that keeps the balance proofs and the user data saved by a
+/// sync until `flush`, and answers reads of them itself, so that a sync only
+/// writes to the store vault at its checkpoints. Other data goes to the inner
+/// store vault right away.
+pub struct CheckpointStoreVault<'a, S: StoreVaultClientInterface> {
+    inner: &'a S,
+    balance_proofs: RefCell<Vec<(U256, ProofWithPublicInputs<F, C, D>)>>,
+    user_data: RefCell<Vec<(U256, Vec<u8>)>>,
+}
+
+impl<'a, S: StoreVaultClientInterface> CheckpointStoreVault<'a, S> {
+    pub fn new(inner: &'a S) -> Self {
+        Self {
+            inner,
+            balance_proofs: RefCell::new(Vec::new()),
+            user_data: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Whether there are writes not saved to the inner store vault yet
+    pub fn is_dirty(&self) -> bool {
+        !self.balance_proofs.borrow().is_empty() || !self.user_data.borrow().is_empty()
+    }
+
+    /// Save the kept writes to the inner store vault and drop them. The balance
+    /// proofs are saved before the user data that refers to them. On a failure the
+    /// writes are kept, for a later `flush`.
+    pub async fn flush(&self) -> Result<(), ServerError> {
+        let balance_proofs = self.balance_proofs.borrow().clone();
+        for (pubkey, proof) in balance_proofs.iter() {
+            self.inner.save_balance_proof(*pubkey, proof).await?;
+        }
+        self.balance_proofs.borrow_mut().clear();
+        let user_data = self.user_data.borrow().clone();
+        for (pubkey, encrypted_data) in user_data {
+            self.inner.save_user_data(pubkey, encrypted_data).await?;
+        }
+        self.user_data.borrow_mut().clear();
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: StoreVaultClientInterface> StoreVaultClientInterface for CheckpointStoreVault<'_, S> {
+    async fn save_balance_proof(
+        &self,
+        pubkey: U256,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<(), ServerError> {
+        self.balance_proofs
+            .borrow_mut()
+            .push((pubkey, proof.clone()));
+        Ok(())
+    }
+
+    async fn get_balance_proof(
+        &self,
+        pubkey: U256,
+        block_number: u32,
+        private_commitment: PoseidonHashOut,
+    ) -> Result<Option<ProofWithPublicInputs<F, C, D>>, ServerError> {
+        let kept = self
+            .balance_proofs
+            .borrow()
+            .iter()
+            .find(|(p, proof)| {
+                let pis = BalancePublicInputs::from_pis(&proof.public_inputs);
+                *p == pubkey
+                    && pis.public_state.block_number == block_number
+                    && pis.private_commitment == private_commitment
+            })
+            .map(|(_, proof)| proof.clone());
+        if kept.is_some() {
+            return Ok(kept);
+        }
+        self.inner
+            .get_balance_proof(pubkey, block_number, private_commitment)
+            .await
+    }
+
+    async fn save_data(
+        &self,
+        data_type: DataType,
+        pubkey: U256,
+        encrypted_data: &[u8],
+    ) -> Result<(), ServerError> {
+        self.inner
+            .save_data(data_type, pubkey, encrypted_data)
+            .await
+    }
+
+    async fn get_data(
+        &self,
+        data_type: DataType,
+        uuid: &str,
+    ) -> Result<Option<(MetaData, Vec<u8>)>, ServerError> {
+        self.inner.get_data(data_type, uuid).await
+    }
+
+    async fn get_data_all_after(
+        &self,
+        data_type: DataType,
+        pubkey: U256,
+        timestamp: u64,
+    ) -> Result<Vec<(MetaData, Vec<u8>)>, ServerError> {
+        self.inner
+            .get_data_all_after(data_type, pubkey, timestamp)
+            .await
+    }
+
+    async fn save_user_data(
+        &self,
+        pubkey: U256,
+        encrypted_data: Vec<u8>,
+    ) -> Result<(), ServerError> {
+        let mut user_data = self.user_data.borrow_mut();
+        user_data.retain(|(p, _)| *p != pubkey);
+        user_data.push((pubkey, encrypted_data));
+        Ok(())
+    }
+
+    async fn get_user_data(&self, pubkey: U256) -> Result<Option<Vec<u8>>, ServerError> {
+        let kept = self
+            .user_data
+            .borrow()
+            .iter()
+            .find(|(p, _)| *p == pubkey)
+            .map(|(_, data)| data.clone());
+        if kept.is_some() {
+            return Ok(kept);
+        }
+        self.inner.get_user_data(pubkey).await
+    }
+}
+
+/// Decides when a sync saves its progress. With an interval, a checkpoint is
+/// made at the end of the first block that brings the actions since the previous
+/// checkpoint to the interval, so that a block is never saved half processed.
+/// Without one, the sync is only saved when it ends.
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    interval: Option<u32>,
+    since_last: u32,
+    saved: Vec<SyncCheckpoint>,
+}
+
+impl Checkpoints {
+    pub fn new(interval: Option<u32>) -> Self {
+        Self {
+            interval,
+            since_last: 0,
+            saved: Vec::new(),
+        }
+    }
+
+    /// Count an action of `block_number`, which is the last of its block if
+    /// `completes_block`, and flush `store` if a checkpoint is due. `num_actions` is
+    /// the number of actions processed by the sync so far.
+    pub async fn after_action<S: StoreVaultClientInterface>(
+        &mut self,
+        store: &CheckpointStoreVault<'_, S>,
+        block_number: u32,
+        completes_block: bool,
+        num_actions: u32,
+    ) -> Result<(), ServerError> {
+        self.since_last += 1;
+        let due = self
+            .interval
+            .is_some_and(|interval| self.since_last >= interval);
+        if !due || !completes_block {
+            return Ok(());
+        }
+        store.flush().await?;
+        self.saved.push(SyncCheckpoint {
+            block_number,
+            num_actions,
+        });
+        self.since_last = 0;
+        Ok(())
+    }
+
+    /// The checkpoints made so far, in order
+    pub fn saved(&self) -> &[SyncCheckpoint] {
+        &self.saved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_interfaces::data::user_data::UserData;
+    use intmax2_zkp::common::signature::key_set::KeySet;
+
+    use crate::external_api::utils::in_memory_store_vault::InMemoryStoreVault;
+
+    use super::*;
+
+    const ACTIONS_PER_BLOCK: u32 = 3;
+
+    async fn user_data_of<S: StoreVaultClientInterface>(store: &S, key: KeySet) -> UserData {
+        store
+            .get_user_data(key.pubkey)
+            .await
+            .unwrap()
+            .map(|encrypted| UserData::decrypt(&encrypted, key).unwrap())
+            .unwrap_or(UserData::new(key.pubkey))
+    }
+
+    // Sync the synthetic actions `1..=total` after the saved user data, as the sync
+    // does: read the user data from the checkpoint store, apply the next action and
+    // save it back. Stops without flushing after `kill_after` actions, as a killed
+    // process would.
+    async fn run(
+        inner: &InMemoryStoreVault,
+        key: KeySet,
+        interval: u32,
+        total: u32,
+        kill_after: Option<u32>,
+    ) -> Vec<SyncCheckpoint> {
+        let store = CheckpointStoreVault::new(inner);
+        let mut checkpoints = Checkpoints::new(Some(interval));
+        let mut num_actions = 0;
+        loop {
+            let mut user_data = user_data_of(&store, key).await;
+            let done = user_data.processed_deposit_uuids.len() as u32;
+            if done == total {
+                break;
+            }
+            let action = done + 1;
+            let block_number = (action - 1) / ACTIONS_PER_BLOCK + 1;
+            user_data.block_number = block_number;
+            user_data.deposit_lpt = action as u64;
+            user_data
+                .processed_deposit_uuids
+                .push(format!("deposit-{}", action));
+            store
+                .save_user_data(key.pubkey, user_data.encrypt(key.pubkey))
+                .await
+                .unwrap();
+            num_actions += 1;
+            checkpoints
+                .after_action(
+                    &store,
+                    block_number,
+                    action % ACTIONS_PER_BLOCK == 0,
+                    num_actions,
+                )
+                .await
+                .unwrap();
+            if kill_after == Some(num_actions) {
+                return checkpoints.saved().to_vec();
+            }
+        }
+        store.flush().await.unwrap();
+        checkpoints.saved().to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_checkpoint() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let inner = InMemoryStoreVault::new();
+
+        // killed after 30 of 60 actions: the checkpoint is at the end of the block
+        // of the 25th action, which is the 27th
+        let checkpoints = run(&inner, key, 25, 60, Some(30)).await;
+        assert_eq!(
+            checkpoints,
+            vec![SyncCheckpoint {
+                block_number: 9,
+                num_actions: 27
+            }]
+        );
+        let saved = user_data_of(&inner, key).await;
+        assert_eq!(saved.block_number, 9);
+        assert_eq!(saved.deposit_lpt, 27);
+        assert_eq!(saved.processed_deposit_uuids.len(), 27);
+
+        // the resumed sync starts after the checkpoint, not from the first action
+        let checkpoints = run(&inner, key, 25, 60, None).await;
+        assert_eq!(
+            checkpoints,
+            vec![SyncCheckpoint {
+                block_number: 18,
+                num_actions: 27
+            }]
+        );
+        let saved = user_data_of(&inner, key).await;
+        assert_eq!(saved.block_number, 20);
+        let expected = (1..=60)
+            .map(|action| format!("deposit-{}", action))
+            .collect::<Vec<_>>();
+        assert_eq!(saved.processed_deposit_uuids, expected);
+    }
+
+    #[tokio::test]
+    async fn test_without_interval_saves_at_the_end() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let inner = InMemoryStoreVault::new();
+        let store = CheckpointStoreVault::new(&inner);
+        let mut checkpoints = Checkpoints::new(None);
+        for action in 1..=6 {
+            let mut user_data = UserData::new(key.pubkey);
+            user_data.deposit_lpt = action;
+            store
+                .save_user_data(key.pubkey, user_data.encrypt(key.pubkey))
+                .await
+                .unwrap();
+            checkpoints
+                .after_action(&store, 1, true, action as u32)
+                .await
+                .unwrap();
+            // the kept user data is read back before it is saved
+            assert_eq!(user_data_of(&store, key).await.deposit_lpt, action);
+        }
+        assert!(checkpoints.saved().is_empty());
+        assert!(store.is_dirty());
+        assert!(inner.get_user_data(key.pubkey).await.unwrap().is_none());
+
+        store.flush().await.unwrap();
+        assert!(!store.is_dirty());
+        assert_eq!(user_data_of(&inner, key).await.deposit_lpt, 6);
+    }
+}
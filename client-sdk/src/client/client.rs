@@ -1,17 +1,24 @@
 use intmax2_interfaces::{
     api::{
         balance_prover::interface::BalanceProverClientInterface,
-        block_builder::interface::BlockBuilderClientInterface,
+        block_builder::{
+            interface::{BlockBuilderClientInterface, ProposalAttestation},
+            types::QueryProposalRequest,
+        },
+        error::ServerError,
         store_vault_server::interface::{DataType, StoreVaultClientInterface},
         validity_prover::interface::ValidityProverClientInterface,
-        withdrawal_server::interface::{WithdrawalInfo, WithdrawalServerClientInterface},
+        withdrawal_server::interface::{
+            attribution_tag_hash, validate_attribution_tag, WithdrawalInfo,
+            WithdrawalServerClientInterface,
+        },
     },
     data::{
         common_tx_data::CommonTxData,
         deposit_data::{DepositData, TokenType},
         meta_data::MetaData,
-        transfer_data::TransferData,
-        tx_data::TxData,
+        transfer_data::{is_claim_expired, TransferData},
+        tx_data::{TxData, TxFee},
         user_data::UserData,
     },
 };
@@ -20,10 +27,12 @@ use intmax2_zkp::{
     common::{
         block_builder::BlockProposal,
         deposit::get_pubkey_salt_hash,
+        salt::Salt,
         signature::key_set::KeySet,
         transfer::Transfer,
         trees::transfer_tree::TransferTree,
         tx::Tx,
+        withdrawal::Withdrawal,
         witness::{
             spent_witness::SpentWitness, transfer_witness::TransferWitness,
             withdrawal_witness::WithdrawalWitness,
@@ -31,9 +40,15 @@ use intmax2_zkp::{
     },
     constants::{NUM_TRANSFERS_IN_TX, TRANSFER_TREE_HEIGHT},
     ethereum_types::{address::Address, bytes32::Bytes32, u256::U256},
-    utils::poseidon_hash_out::PoseidonHashOut,
+    utils::{conversion::ToU64 as _, leafable::Leafable as _, poseidon_hash_out::PoseidonHashOut},
+};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
 };
 
+use num_bigint::BigUint;
 use plonky2::{
     field::goldilocks_field::GoldilocksField,
     plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
@@ -45,27 +60,69 @@ use crate::{
         balance_logic::{process_common_tx, process_transfer},
         utils::generate_salt,
     },
-    external_api::contract::{
-        liquidity_contract::LiquidityContract, rollup_contract::RollupContract,
+    external_api::{
+        block_builder::{sign_cancel_tx_request, verify_proposal_attestation},
+        contract::{liquidity_contract::LiquidityContract, rollup_contract::RollupContract},
+        mirrored_store_vault::MirroredStoreVaultClient,
+        sponsor::{fetch_fee_voucher, send_tx_request_with_voucher, SponsorClient},
+        utils::{clock::global_clock, time::sleep_for},
     },
 };
 
 use super::{
+    backup_health::{check_backup_health, BackupHealth},
+    balance_history::{
+        balance_at_block, balance_series, BalanceSeries, HistoricalBalance, SeriesInterval,
+    },
     balance_logic::process_deposit,
+    balance_view::{BalanceView, TokenBalance},
+    checkpoint::{CheckpointStoreVault, Checkpoints, SyncCheckpoint},
     config::ClientConfig,
+    consolidation::{
+        complete_consolidation, consolidate, plan_consolidation, ConsolidationOptions,
+        ConsolidationPlan, ConsolidationReport,
+    },
+    diagnostics::{get_diagnostics, AccountDiagnostics},
     error::ClientError,
-    history::{fetch_history, HistoryEntry},
+    external_deposit::{
+        claim_external_deposit, generate_deposit_request, DepositRequest, DepositSource,
+    },
+    fee_dispute::{list_fee_disputes, FeeDispute},
+    fee_quote::{quote_fees, FeeEstimate, FeeKind},
+    history::{fetch_history, fetch_history_with_index, HistoryEntry},
+    history_index::HistoryIndex,
+    limits::{fetch_limits, ProtocolLimits},
+    provisional::{
+        debits_of_tx, reconcile_debits, DeltaKind, DeltaStatus, OptimisticBalances,
+        ProvisionalDelta, ProvisionalStore,
+    },
+    receipt::{export_receive_receipt, ReceiveReceipt},
+    replay::{save_synced, SyncVerifier},
+    rotation::{
+        complete_rotation, plan_rotation, resume_rotation, rotate_key, send_rotation_chunk,
+        RotationOptions, RotationPlan, RotationProgress, RotationReport,
+    },
+    session::{account_session, SessionMarker, ShutdownReport, SHUTDOWN_TIMEOUT_MS},
+    signer::{LocalSigner, Signer},
     strategy::{
-        strategy::{determin_next_action, Action},
+        deposit::{fetch_deposit_info, unprocessed_deposits, PendingDeposit},
+        strategy::{block_progress, determin_next_action, plan_token_sync, Action, ActionSummary},
+        transfer::fetch_transfer_info,
+        tx::fetch_tx_info,
         withdrawal::fetch_withdrawal_info,
     },
-    utils::generate_transfer_tree,
+    tx_status::find_tx_finalized_at,
+    utils::{check_builder_health, filter_proposal, generate_transfer_tree},
+    withdrawal_onchain::{verify_withdrawals_onchain, OnchainConfirmation},
 };
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
 const D: usize = 2;
 
+/// Index of the native token in the liquidity contract
+pub const NATIVE_TOKEN_INDEX: u32 = 0;
+
 pub struct Client<
     BB: BlockBuilderClientInterface,
     S: StoreVaultClientInterface,
@@ -83,6 +140,9 @@ pub struct Client<
 
     pub liquidity_contract: LiquidityContract,
     pub rollup_contract: RollupContract,
+
+    /// Debits of the finalized txs, see `get_balances_optimistic`
+    pub provisional_store: Arc<dyn ProvisionalStore>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -92,6 +152,143 @@ pub enum SyncStatus {
     Pending,  // there are pending actions
 }
 
+/// What a `SyncProgress` counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStage {
+    /// Deposits, transfers and txs incorporated into the balance proof
+    Actions,
+    /// Blocks of the actions incorporated into the balance proof
+    Blocks,
+    /// Settled withdrawals proved by `sync_withdrawals`
+    Withdrawals,
+}
+
+impl SyncStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncStage::Actions => "actions",
+            SyncStage::Blocks => "blocks",
+            SyncStage::Withdrawals => "withdrawals",
+        }
+    }
+}
+
+/// `current` of `total` items of `stage` processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncProgress {
+    pub current: u32,
+    pub total: u32,
+    pub stage: SyncStage,
+}
+
+impl SyncProgress {
+    fn new(stage: SyncStage, current: u32, total: u32) -> Self {
+        Self {
+            current,
+            total,
+            stage,
+        }
+    }
+}
+
+/// Called by `sync_with_progress` after each action and after each block, and by
+/// `sync_withdrawals_with_progress` after each withdrawal
+pub type SyncProgressCallback = Box<dyn Fn(SyncProgress)>;
+
+// result of processing the next action of the sync
+struct SingleSync {
+    status: SyncStatus,
+    // block of the processed action
+    block_number: Option<u32>,
+    // whether the processed action was the last of its block
+    completes_block: bool,
+    // blocks of the actions left, besides the block of the processed action
+    remaining_blocks: u32,
+    // actions left besides the processed one
+    remaining_actions: u32,
+    expired_transfer_uuids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub num_actions: u32,
+    /// Uuids of transfers skipped because their claim deadline has passed
+    pub expired_transfer_uuids: Vec<String>,
+    /// Servers whose clock is far from the local clock, see `SkewAwareClock`
+    #[serde(default)]
+    pub clock_skew_warnings: Vec<String>,
+    /// Actions left unprocessed by `sync_token` because they are of another token
+    #[serde(default)]
+    pub skipped_actions: Vec<ActionSummary>,
+    /// Progress saved to the store vault before the end of the sync, see
+    /// `ClientConfig::checkpoint_interval`
+    #[serde(default)]
+    pub checkpoints: Vec<SyncCheckpoint>,
+}
+
+/// Advanced options for `send_tx_request_with_options`
+#[derive(Debug, Clone, Default)]
+pub struct TxSendOptions {
+    /// Nonce the tx must be sent at. It is checked against the nonce of the
+    /// private state behind the latest balance proof.
+    pub nonce_override: Option<u32>,
+    /// Block number after which the recipients refuse to receive the transfers.
+    /// See `list_expired_outgoing_transfers` for the accounting of expired transfers.
+    pub claim_deadline_block: Option<u32>,
+    /// Send even if the block builder reports unhealthy dependencies or has
+    /// not posted a block within `ClientConfig::builder_max_staleness`. A builder
+    /// that does not serve `/block-builder/health` is not checked.
+    pub skip_health_check: bool,
+    /// Build the tx on the balance proof as it is, without syncing it first. For
+    /// callers that synced just before.
+    pub skip_sync: bool,
+    /// Who pays the block builder fee
+    pub fee_mode: FeeMode,
+    /// Attribution tag of the withdrawals of the tx, e.g. the memo code of the
+    /// exchange receiving them. It is kept in the encrypted backup, and only its
+    /// hash is given to the withdrawal server.
+    pub attribution_tag: Option<String>,
+    /// Transfer paying the block builder fee, appended to the transfers. The
+    /// backup records it with the block builder's attestation of the proposal, if
+    /// the block builder gives one, so that the fee of a block that is never posted
+    /// can be disputed, see `list_fee_disputes`.
+    pub fee_transfer: Option<Transfer>,
+}
+
+/// Who pays the block builder fee of a tx
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum FeeMode {
+    #[default]
+    SelfPaid,
+    /// Ask the sponsor service at `sponsor_url` for a fee voucher. Falls back to
+    /// `SelfPaid` with a warning if the sponsor declines or fails, or if the block
+    /// builder rejects the voucher.
+    Sponsored { sponsor_url: String },
+}
+
+/// Outgoing transfer whose claim deadline has passed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpiredTransfer {
+    pub tx_uuid: String,
+    pub recipient: U256,
+    pub token_index: u32,
+    pub amount: U256,
+    pub claim_deadline_block: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NonceDiagnosis {
+    /// Nonce of the private state incorporated into the balance proof
+    pub private_state_nonce: u32,
+    /// Nonce after incorporating all txs settled on chain
+    pub settled_nonce: u32,
+    /// Uuids of txs sent but not settled yet
+    pub pending_tx_uuids: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TxRequestMemo {
@@ -102,6 +299,129 @@ pub struct TxRequestMemo {
     pub spent_proof: ProofWithPublicInputs<F, C, D>,
     pub prev_block_number: u32,
     pub prev_private_commitment: PoseidonHashOut,
+    #[serde(default)]
+    pub claim_deadline_block: Option<u32>,
+    #[serde(default)]
+    pub attribution_tag: Option<String>,
+    /// Block builder the tx request was sent to, `None` in memos of older versions
+    #[serde(default)]
+    pub block_builder_url: Option<String>,
+    /// Index of the fee transfer in `transfers`
+    #[serde(default)]
+    pub fee_transfer_index: Option<u32>,
+}
+
+/// Result of `Client::dry_run_tx_request`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxDryRun {
+    /// Nonce the tx would be sent at
+    pub nonce: u32,
+    pub transfer_tree_root: PoseidonHashOut,
+    pub balance_changes: Vec<BalanceChange>,
+}
+
+/// Amount a tx takes from the balance of a token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceChange {
+    pub token_index: u32,
+    pub balance: U256,
+    pub amount: U256,
+    pub remaining: U256,
+}
+
+// the transfers with the fee transfer of `options` appended, and its index
+fn with_fee_transfer(
+    mut transfers: Vec<Transfer>,
+    options: &TxSendOptions,
+) -> Result<(Vec<Transfer>, Option<u32>), ClientError> {
+    if transfers.is_empty() {
+        return Err(ClientError::InternalError("transfers is empty".to_string()));
+    }
+    let fee_transfer_index = options.fee_transfer.clone().map(|fee_transfer| {
+        transfers.push(fee_transfer);
+        transfers.len() as u32 - 1
+    });
+    if transfers.len() > NUM_TRANSFERS_IN_TX {
+        return Err(ClientError::InternalError(
+            "transfers is too long".to_string(),
+        ));
+    }
+    Ok((transfers, fee_transfer_index))
+}
+
+// check the transfers of each token together against its balance in the private
+// state of `user_data`, and the requested nonce, if any, against the nonce of that
+// private state, which fixes the nonce of the tx. Return the nonce and the amount
+// the tx takes from each balance.
+fn check_tx_against_state(
+    user_data: &UserData,
+    transfers: &[Transfer],
+    nonce_override: Option<u32>,
+) -> Result<(u32, Vec<BalanceChange>), ClientError> {
+    let mut spent = BTreeMap::<u32, BigUint>::new();
+    for transfer in transfers {
+        *spent.entry(transfer.token_index).or_default() += BigUint::from(transfer.amount);
+    }
+    let balances = user_data.balances();
+    let mut balance_changes = Vec::new();
+    for (token_index, amount) in spent {
+        let balance = balances
+            .get(&(token_index as u64))
+            .cloned()
+            .unwrap_or_default();
+        if balance.is_insufficient {
+            return Err(ClientError::BalanceError(format!(
+                "Already insufficient: token index {}",
+                token_index
+            )));
+        }
+        if BigUint::from(balance.amount) < amount {
+            return Err(ClientError::BalanceError(format!(
+                "Insufficient balance: {} < {} for token index {}",
+                balance.amount, amount, token_index
+            )));
+        }
+        let remaining = BigUint::from(balance.amount) - &amount;
+        balance_changes.push(BalanceChange {
+            token_index,
+            balance: balance.amount,
+            amount: amount.try_into().unwrap(),
+            remaining: remaining.try_into().unwrap(),
+        });
+    }
+    let nonce = user_data.full_private_state.nonce;
+    if let Some(nonce_override) = nonce_override {
+        if nonce_override != nonce {
+            return Err(ClientError::InvalidNonce(nonce_override, nonce));
+        }
+    }
+    Ok((nonce, balance_changes))
+}
+
+/// Fail if `token_index` has no registered token. The contract returns an empty
+/// token info, which reads as the native token, for an index it does not know,
+/// while the native token is always index 0.
+fn check_deposit_token(
+    token_index: u32,
+    token_type: TokenType,
+    token_address: Address,
+) -> Result<(), ClientError> {
+    let is_native = token_type == TokenType::NATIVE;
+    if is_native != (token_index == NATIVE_TOKEN_INDEX) {
+        return Err(ClientError::UnsupportedToken {
+            token_index,
+            reason: "no token is registered for this index".to_string(),
+        });
+    }
+    if !is_native && token_address == Address::default() {
+        return Err(ClientError::UnsupportedToken {
+            token_index,
+            reason: format!("{:?} token without an address", token_type),
+        });
+    }
+    Ok(())
 }
 
 impl<BB, S, V, B, W> Client<BB, S, V, B, W>
@@ -129,6 +449,71 @@ where
             token_address,
             token_id
         );
+        self.backup_deposit(pubkey, amount, token_type, token_address, token_id, None)
+            .await
+    }
+
+    /// Same as `prepare_deposit` for the token registered as `token_index`, whose
+    /// type, address and id are read from the liquidity contract and backed up with
+    /// the index. Fails with `UnsupportedToken` if no token is registered as
+    /// `token_index`.
+    pub async fn prepare_deposit_by_token_index(
+        &self,
+        pubkey: U256,
+        amount: U256,
+        token_index: u32,
+    ) -> Result<DepositData, ClientError> {
+        log::info!(
+            "prepare_deposit_by_token_index: pubkey {}, amount {}, token_index {}",
+            pubkey,
+            amount,
+            token_index
+        );
+        let (token_type, token_address, token_id) =
+            self.liquidity_contract.get_token_info(token_index).await?;
+        check_deposit_token(token_index, token_type, token_address)?;
+        self.backup_deposit(
+            pubkey,
+            amount,
+            token_type,
+            token_address,
+            token_id,
+            Some(token_index),
+        )
+        .await
+    }
+
+    // `token_index` is None for a token that may not be registered yet
+    async fn backup_deposit(
+        &self,
+        pubkey: U256,
+        amount: U256,
+        token_type: TokenType,
+        token_address: Address,
+        token_id: U256,
+        token_index: Option<u32>,
+    ) -> Result<DepositData, ClientError> {
+        token_type
+            .check_amount(amount, token_id)
+            .map_err(|reason| ClientError::InvalidTokenAmount {
+                token_index,
+                reason,
+            })?;
+        let limits = self.get_limits().await?;
+        if !limits.min_deposit_per_token.is_empty() {
+            // unregistered tokens have no index, and so no minimum yet
+            let token_index = match token_index {
+                Some(token_index) => Some(token_index),
+                None => {
+                    self.liquidity_contract
+                        .get_token_index(token_type, token_address, token_id)
+                        .await?
+                }
+            };
+            if let Some(token_index) = token_index {
+                limits.check_deposit(token_index, amount)?;
+            }
+        }
         let deposit_salt = generate_salt();
 
         // backup before contract call
@@ -140,7 +525,7 @@ where
             token_type,
             token_address,
             token_id,
-            token_index: None,
+            token_index,
         };
         self.store_vault_server
             .save_data(DataType::Deposit, pubkey, &deposit_data.encrypt(pubkey))
@@ -156,18 +541,46 @@ where
         key: KeySet,
         transfers: Vec<Transfer>,
     ) -> Result<TxRequestMemo, ClientError> {
-        // input validation
-        if transfers.len() == 0 {
-            return Err(ClientError::InternalError("transfers is empty".to_string()));
-        }
-        if transfers.len() > NUM_TRANSFERS_IN_TX {
-            return Err(ClientError::InternalError(
-                "transfers is too long".to_string(),
-            ));
+        self.send_tx_request_with_options(
+            block_builder_url,
+            key,
+            transfers,
+            TxSendOptions::default(),
+        )
+        .await
+    }
+
+    /// Send a transaction request to the block builder with advanced options
+    pub async fn send_tx_request_with_options(
+        &self,
+        block_builder_url: &str,
+        key: KeySet,
+        transfers: Vec<Transfer>,
+        options: TxSendOptions,
+    ) -> Result<TxRequestMemo, ClientError> {
+        let (transfers, fee_transfer_index) = with_fee_transfer(transfers, &options)?;
+        self.validate_tx_request(&transfers, &options).await?;
+
+        if !options.skip_health_check {
+            match self.block_builder.get_health(block_builder_url).await {
+                Ok(dependencies) => check_builder_health(
+                    &dependencies,
+                    global_clock().server_now_for(block_builder_url),
+                    self.config.builder_max_staleness,
+                )?,
+                // a builder that does not report its dependencies is not checked
+                Err(ServerError::ServerError(404, ..)) => log::warn!(
+                    "{} does not report its health, sending without checking it",
+                    block_builder_url
+                ),
+                Err(e) => return Err(e.into()),
+            }
         }
 
         // sync balance proof
-        self.sync(key).await?;
+        if !options.skip_sync {
+            self.sync(key).await?;
+        }
 
         let user_data = self.get_user_data(key).await?;
         let _balance_proof = self
@@ -180,31 +593,12 @@ where
             .await?
             .ok_or_else(|| ClientError::InternalError("balance proof not found".to_string()))?;
 
-        // balance check
-        let balances = user_data.balances();
-        for transfer in &transfers {
-            let balance = balances
-                .get(&(transfer.token_index as u64))
-                .cloned()
-                .unwrap_or_default();
-            if balance.is_insufficient {
-                return Err(ClientError::BalanceError(format!(
-                    "Already insufficient: token index {}",
-                    transfer.token_index
-                )));
-            }
-            if balance.amount < transfer.amount {
-                return Err(ClientError::BalanceError(format!(
-                    "Insufficient balance: {} < {} for token index {}",
-                    balance.amount, transfer.amount, transfer.token_index
-                )));
-            }
-        }
+        let (nonce, _) = check_tx_against_state(&user_data, &transfers, options.nonce_override)?;
 
         // generate spent proof
         let transfer_tree = generate_transfer_tree(&transfers);
         let tx = Tx {
-            nonce: user_data.full_private_state.nonce,
+            nonce,
             transfer_tree_root: transfer_tree.get_root(),
         };
         let new_salt = generate_salt();
@@ -224,15 +618,28 @@ where
         let account_info = self.validity_prover.get_account_info(key.pubkey).await?;
         let is_registration_block = account_info.account_id.is_none();
 
-        self.block_builder
-            .send_tx_request(
-                block_builder_url,
-                is_registration_block,
-                key.pubkey,
-                tx,
-                None,
-            )
-            .await?;
+        let fee_voucher = match &options.fee_mode {
+            FeeMode::SelfPaid => None,
+            FeeMode::Sponsored { sponsor_url } => {
+                fetch_fee_voucher(
+                    &SponsorClient::default(),
+                    sponsor_url,
+                    key.pubkey,
+                    tx.hash().into(),
+                )
+                .await
+            }
+        };
+
+        send_tx_request_with_voucher(
+            &self.block_builder,
+            block_builder_url,
+            is_registration_block,
+            key.pubkey,
+            tx,
+            fee_voucher,
+        )
+        .await?;
 
         let memo = TxRequestMemo {
             is_registration_block,
@@ -242,10 +649,115 @@ where
             spent_proof,
             prev_block_number: user_data.block_number,
             prev_private_commitment: user_data.private_commitment(),
+            claim_deadline_block: options.claim_deadline_block,
+            attribution_tag: options.attribution_tag,
+            block_builder_url: Some(block_builder_url.to_string()),
+            fee_transfer_index,
         };
         Ok(memo)
     }
 
+    /// Run the checks of `send_tx_request_with_options` without contacting the block
+    /// builder, and return the transfer tree root the tx would have and its effect
+    /// on the balances. The account is synced first unless `options.skip_sync` is
+    /// set, as before a send, and nothing else is saved.
+    pub async fn dry_run_tx_request(
+        &self,
+        key: KeySet,
+        transfers: Vec<Transfer>,
+        options: &TxSendOptions,
+    ) -> Result<TxDryRun, ClientError> {
+        let (transfers, _) = with_fee_transfer(transfers, options)?;
+        self.validate_tx_request(&transfers, options).await?;
+        if !options.skip_sync {
+            self.sync(key).await?;
+        }
+        let user_data = self.get_user_data(key).await?;
+        let (nonce, balance_changes) =
+            check_tx_against_state(&user_data, &transfers, options.nonce_override)?;
+        Ok(TxDryRun {
+            nonce,
+            transfer_tree_root: generate_transfer_tree(&transfers).get_root(),
+            balance_changes,
+        })
+    }
+
+    // checks of a tx request that need neither the block builder nor the private state
+    async fn validate_tx_request(
+        &self,
+        transfers: &[Transfer],
+        options: &TxSendOptions,
+    ) -> Result<(), ClientError> {
+        if transfers
+            .iter()
+            .any(|transfer| !transfer.recipient.is_pubkey)
+        {
+            self.get_limits().await?.check_transfers(transfers)?;
+            self.check_withdrawal_token_types(transfers).await?;
+        }
+
+        if let Some(tag) = &options.attribution_tag {
+            validate_attribution_tag(tag).map_err(ClientError::InvalidAttributionTag)?;
+            if transfers
+                .iter()
+                .all(|transfer| transfer.recipient.is_pubkey)
+            {
+                return Err(ClientError::InvalidAttributionTag(
+                    "attribution tag given for a tx without withdrawals".to_string(),
+                ));
+            }
+        }
+
+        if let Some(claim_deadline_block) = options.claim_deadline_block {
+            let block_number = self.validity_prover.get_block_number().await?;
+            if claim_deadline_block <= block_number {
+                return Err(ClientError::InvalidClaimDeadline(
+                    claim_deadline_block,
+                    block_number,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask the block builder of the memo to drop the tx request, when the tx is not
+    /// going to be finalized. The tx is only backed up to the store vault by
+    /// `backup_tx`, so nothing of a memo that was not finalized is stored there. A
+    /// request the block builder no longer has, or a memo without a block builder,
+    /// is nothing to cancel. Fails with `TxAlreadyProposed` once the tx is in a
+    /// proposed block, whose signature the block builder is waiting for. The
+    /// cancellation is signed with `key`, the key of the sender.
+    pub async fn cancel_tx_request(
+        &self,
+        key: KeySet,
+        memo: &TxRequestMemo,
+    ) -> Result<(), ClientError> {
+        let Some(block_builder_url) = &memo.block_builder_url else {
+            return Ok(());
+        };
+        let tx_hash: Bytes32 = memo.tx.hash().into();
+        let signature = sign_cancel_tx_request(key, tx_hash);
+        match self
+            .block_builder
+            .cancel_tx_request(
+                block_builder_url,
+                tx_hash,
+                signature.pubkey,
+                signature.signature,
+            )
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(ServerError::ServerError(404, ..)) => Ok(()),
+            Err(ServerError::ServerError(409, ..)) => {
+                Err(ClientError::TxAlreadyProposed(tx_hash.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Query the proposal for `tx` of `key`. A proposal for another tx or sender is
+    /// treated as not available yet, or rejected if `strict_proposal_validation` is set.
     pub async fn query_proposal(
         &self,
         block_builder_url: &str,
@@ -257,7 +769,102 @@ where
             .block_builder
             .query_proposal(block_builder_url, is_registration_block, key.pubkey, tx)
             .await?;
-        Ok(proposal)
+        filter_proposal(
+            proposal,
+            key.pubkey,
+            tx,
+            self.config.strict_proposal_validation,
+        )
+    }
+
+    /// Query the proposals of several txs of `key` sent to the same block builder in
+    /// one request. `txs` are pairs of `is_registration_block` and the tx.
+    pub async fn query_proposals_batch(
+        &self,
+        block_builder_url: &str,
+        key: KeySet,
+        txs: &[(bool, Tx)],
+    ) -> Result<Vec<Option<BlockProposal>>, ClientError> {
+        let requests = txs
+            .iter()
+            .map(|(is_registration_block, tx)| QueryProposalRequest {
+                is_registration_block: *is_registration_block,
+                pubkey: key.pubkey,
+                tx: *tx,
+            })
+            .collect::<Vec<_>>();
+        let proposals = self
+            .block_builder
+            .query_proposals(block_builder_url, &requests)
+            .await?;
+        proposals
+            .into_iter()
+            .zip(txs)
+            .map(|(proposal, (_, tx))| {
+                filter_proposal(
+                    proposal,
+                    key.pubkey,
+                    *tx,
+                    self.config.strict_proposal_validation,
+                )
+            })
+            .collect()
+    }
+
+    /// Poll the block builder until the proposal for the tx is available.
+    /// Fails with `PendingError` if it is not available within `timeout` seconds.
+    pub async fn wait_for_proposal(
+        &self,
+        block_builder_url: &str,
+        key: KeySet,
+        memo: &TxRequestMemo,
+        timeout: u64,
+        interval: u64,
+    ) -> Result<BlockProposal, ClientError> {
+        let deadline = global_clock().local_now() + timeout;
+        loop {
+            if let Some(proposal) = self
+                .query_proposal(block_builder_url, key, memo.is_registration_block, memo.tx)
+                .await?
+            {
+                return Ok(proposal);
+            }
+            if global_clock().local_now() >= deadline {
+                return Err(ClientError::PendingError(format!(
+                    "no block proposal within {} seconds",
+                    timeout
+                )));
+            }
+            sleep_for(interval).await;
+        }
+    }
+
+    /// Poll the validity prover until the block containing the tx tree root is
+    /// synced, and return its block number. Fails with `PendingError` if it is not
+    /// synced within `timeout` seconds.
+    pub async fn wait_for_inclusion(
+        &self,
+        tx_tree_root: Bytes32,
+        timeout: u64,
+        interval: u64,
+    ) -> Result<u32, ClientError> {
+        let deadline = global_clock().local_now() + timeout;
+        loop {
+            if let Some(block_number) = self
+                .validity_prover
+                .get_block_number_by_tx_tree_root(tx_tree_root)
+                .await?
+            {
+                return Ok(block_number);
+            }
+            if global_clock().local_now() >= deadline {
+                return Err(ClientError::PendingError(format!(
+                    "tx tree root {} is not included within {} seconds",
+                    tx_tree_root, timeout
+                )));
+            }
+            sleep_for(interval).await;
+        }
     }
 
     /// Verify the proposal, and send the signature to the block builder
@@ -268,6 +875,94 @@ where
         memo: &TxRequestMemo,
         proposal: &BlockProposal,
     ) -> Result<Bytes32, ClientError> {
+        self.backup_tx(key, memo, proposal).await?;
+        self.sign_proposal(block_builder_url, key, memo, proposal)
+            .await
+    }
+
+    /// Same as `finalize_tx`, with the proposal signed by `signer`. `key` is still
+    /// used to back up the tx, and has to be the key of the signer's account.
+    pub async fn finalize_tx_with_signer(
+        &self,
+        block_builder_url: &str,
+        key: KeySet,
+        signer: &dyn Signer,
+        memo: &TxRequestMemo,
+        proposal: &BlockProposal,
+    ) -> Result<Bytes32, ClientError> {
+        if signer.pubkey() != key.pubkey {
+            return Err(ClientError::SignerError(format!(
+                "signer pubkey {} is not the pubkey {} of the key",
+                signer.pubkey(),
+                key.pubkey
+            )));
+        }
+        self.backup_tx(key, memo, proposal).await?;
+        self.sign_proposal_with_signer(block_builder_url, signer, memo, proposal)
+            .await
+    }
+
+    fn record_provisional_debits(&self, pubkey: U256, memo: &TxRequestMemo, tx_tree_root: Bytes32) {
+        let mut deltas = self.provisional_store.load(pubkey);
+        // a proposal signed again must not be debited twice
+        if deltas
+            .iter()
+            .any(|delta| delta.tx_tree_root == tx_tree_root)
+        {
+            return;
+        }
+        deltas.extend(debits_of_tx(
+            &memo.transfers,
+            tx_tree_root,
+            global_clock().server_now(),
+        ));
+        self.provisional_store.save(pubkey, &deltas);
+    }
+
+    // the block builder's attestation of the proposal of a tx paying it a fee, which
+    // is the evidence of a fee dispute. `None` if the block builder has no
+    // attestation endpoint or does not attest the proposal, in which case the tx is
+    // backed up without evidence. An attestation that does not match the proposal
+    // is refused.
+    async fn fetch_proposal_attestation(
+        &self,
+        block_builder_url: &str,
+        pubkey: U256,
+        is_registration_block: bool,
+        tx: Tx,
+        tx_tree_root: Bytes32,
+    ) -> Result<Option<ProposalAttestation>, ClientError> {
+        let attestation = match self
+            .block_builder
+            .get_proposal_attestation(block_builder_url, is_registration_block, pubkey, tx)
+            .await
+        {
+            Ok(Some(attestation)) => attestation,
+            Ok(None) | Err(ServerError::ServerError(404 | 405 | 501, ..)) => {
+                log::warn!(
+                    "Block builder {} did not attest the proposal of a tx paying it a fee, \
+                     so the backup has no evidence for a fee dispute",
+                    block_builder_url
+                );
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        verify_proposal_attestation(&attestation, pubkey, tx.hash().into(), tx_tree_root)
+            .map_err(|e| ClientError::InvalidBlockProposal(e.to_string()))?;
+        Ok(Some(attestation))
+    }
+
+    /// Verify the proposal, and save the tx and transfer data to the store vault.
+    /// This has to be done before signing the proposal. The backup of a tx with a
+    /// fee transfer records the block builder's attestation of the proposal, if the
+    /// block builder gives one.
+    pub async fn backup_tx(
+        &self,
+        key: KeySet,
+        memo: &TxRequestMemo,
+        proposal: &BlockProposal,
+    ) -> Result<(), ClientError> {
         // verify proposal
         proposal
             .verify(memo.tx)
@@ -284,9 +979,29 @@ where
         };
 
         // save tx data
+        let fee = match (memo.fee_transfer_index, &memo.block_builder_url) {
+            (Some(transfer_index), Some(block_builder_url)) => self
+                .fetch_proposal_attestation(
+                    block_builder_url,
+                    key.pubkey,
+                    memo.is_registration_block,
+                    memo.tx,
+                    proposal.tx_tree_root,
+                )
+                .await?
+                .map(|attestation| TxFee {
+                    block_builder_url: block_builder_url.clone(),
+                    transfer_index,
+                    proposal: proposal.clone(),
+                    attestation,
+                }),
+            _ => None,
+        };
         let tx_data = TxData {
             common: common_tx_data.clone(),
             spent_witness: memo.spent_witness.clone(),
+            claim_deadline_block: memo.claim_deadline_block,
+            fee,
         };
         self.store_vault_server
             .save_data(DataType::Tx, key.pubkey, &tx_data.encrypt(key.pubkey))
@@ -307,6 +1022,17 @@ where
                 transfer: transfer.clone(),
                 transfer_index: i as u32,
                 transfer_merkle_proof,
+                // withdrawals are not received by anyone, so they never expire
+                claim_deadline_block: if transfer.recipient.is_pubkey {
+                    memo.claim_deadline_block
+                } else {
+                    None
+                },
+                attribution_tag: if transfer.recipient.is_pubkey {
+                    None
+                } else {
+                    memo.attribution_tag.clone()
+                },
             };
             if transfer.recipient.is_pubkey {
                 let recipient = transfer.recipient.to_pubkey().unwrap();
@@ -327,9 +1053,30 @@ where
                     .await?;
             }
         }
+        Ok(())
+    }
+
+    /// Sign the proposal and post the signature to the block builder
+    pub async fn sign_proposal(
+        &self,
+        block_builder_url: &str,
+        key: KeySet,
+        memo: &TxRequestMemo,
+        proposal: &BlockProposal,
+    ) -> Result<Bytes32, ClientError> {
+        self.sign_proposal_with_signer(block_builder_url, &LocalSigner(key), memo, proposal)
+            .await
+    }
 
-        // sign and post signature
-        let signature = proposal.sign(key);
+    /// Sign the proposal with `signer` and post the signature to the block builder
+    pub async fn sign_proposal_with_signer(
+        &self,
+        block_builder_url: &str,
+        signer: &dyn Signer,
+        memo: &TxRequestMemo,
+        proposal: &BlockProposal,
+    ) -> Result<Bytes32, ClientError> {
+        let signature = signer.sign_proposal(&memo.tx, proposal).await?;
         self.block_builder
             .post_signature(
                 block_builder_url,
@@ -339,66 +1086,335 @@ where
                 signature.signature,
             )
             .await?;
+        // the balance drops right away in `get_balances_optimistic`
+        self.record_provisional_debits(signature.pubkey, memo, proposal.tx_tree_root);
 
         Ok(proposal.tx_tree_root)
     }
 
     /// Sync the client's balance proof with the latest block
     pub async fn sync(&self, key: KeySet) -> Result<(), ClientError> {
-        let mut sync_status = SyncStatus::Continue;
-        while sync_status == SyncStatus::Continue {
-            sync_status = self.sync_single(key).await?;
-        }
-        if sync_status == SyncStatus::Pending {
-            return Err(ClientError::PendingError(
-                "there is pending actions".to_string(),
-            ));
-        }
+        self.sync_with_report(key).await?;
         Ok(())
     }
 
-    pub async fn sync_single(&self, key: KeySet) -> Result<SyncStatus, ClientError> {
-        let next_action = determin_next_action(
-            &self.store_vault_server,
-            &self.validity_prover,
-            &self.liquidity_contract,
-            key,
-            self.config.deposit_timeout,
-            self.config.tx_timeout,
-        )
-        .await?;
+    /// Sync the client's balance proof with the latest block, and return what the
+    /// sync did.
+    ///
+    /// User data and balance proofs are kept in memory and saved to the store vault
+    /// at the checkpoints of `ClientConfig::checkpoint_interval` and at the end of
+    /// the sync, or when it fails. An interrupted sync resumes from the last
+    /// checkpoint.
+    pub async fn sync_with_report(&self, key: KeySet) -> Result<SyncReport, ClientError> {
+        self.sync_inner(key, |_| {}, None).await
+    }
 
-        // if there are pending actions, return pending
-        // todo: process non-pending actions if possible
-        if next_action.pending_deposits.len() > 0
-            || next_action.pending_transfers.len() > 0
-            || next_action.pending_txs.len() > 0
-        {
-            return Ok(SyncStatus::Pending);
-        }
+    /// `sync_with_report`, checking before the user data of each action is saved
+    /// that its balances are those replayed from the decrypted history, see
+    /// `SyncVerifier`. On a mismatch the sync stops with `BalanceMismatch`, and
+    /// neither the balance proof nor the user data of the action is saved.
+    pub async fn sync_verified(&self, key: KeySet) -> Result<SyncReport, ClientError> {
+        let verifier = SyncVerifier::new(key);
+        self.sync_inner(key, |_| {}, Some(&verifier)).await
+    }
 
-        if next_action.action.is_none() {
-            return Ok(SyncStatus::Complete);
+    /// `sync_with_report`, calling `on_progress` after each action with the actions
+    /// processed and to process in total, and after each block with the blocks. The
+    /// totals grow if new actions arrive during the sync. `on_progress` is called
+    /// with `0` of `0` blocks if there was nothing to process, so that it is called
+    /// at least once.
+    pub async fn sync_with_progress(
+        &self,
+        key: KeySet,
+        on_progress: Option<SyncProgressCallback>,
+    ) -> Result<SyncReport, ClientError> {
+        match on_progress {
+            Some(on_progress) => self.sync_inner(key, on_progress, None).await,
+            None => self.sync_with_report(key).await,
         }
+    }
 
-        match next_action.action.unwrap() {
+    // generic over the callback, so that `sync` passes a closure instead of a box
+    async fn sync_inner<P: Fn(SyncProgress)>(
+        &self,
+        key: KeySet,
+        on_progress: P,
+        verifier: Option<&SyncVerifier>,
+    ) -> Result<SyncReport, ClientError> {
+        let store = CheckpointStoreVault::new(&self.store_vault_server);
+        let synced = self.sync_actions(key, &store, &on_progress, verifier).await;
+        // the actions completed before a failure are saved as well
+        let flushed = store.flush().await;
+        let mut report = match synced {
+            Ok(report) => report,
+            Err(e) => {
+                if let Err(flush_error) = flushed {
+                    log::warn!("Failed to save the sync: {}", flush_error);
+                }
+                return Err(e);
+            }
+        };
+        flushed?;
+        report.clock_skew_warnings = global_clock().skew_warnings();
+        // the sync is saved already, stale debits are only shown until the next one
+        let reconciled = match self.get_user_data(key).await {
+            Ok(user_data) => self
+                .reconcile_provisional_debits(key, &user_data)
+                .await
+                .map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = reconciled {
+            log::warn!("Failed to reconcile provisional debits: {}", e);
+        }
+        Ok(report)
+    }
+
+    // process the actions until there is none left, saving them to the store vault
+    // behind `store` at the checkpoints
+    async fn sync_actions<P: Fn(SyncProgress)>(
+        &self,
+        key: KeySet,
+        store: &CheckpointStoreVault<'_, S>,
+        on_progress: &P,
+        verifier: Option<&SyncVerifier>,
+    ) -> Result<SyncReport, ClientError> {
+        let mut report = SyncReport::default();
+        let mut checkpoints = Checkpoints::new(self.config.checkpoint_interval);
+        let mut actions_done = 0;
+        let mut blocks_done = 0;
+        loop {
+            let single = self.sync_single_inner(store, key, verifier).await?;
+            if single.status == SyncStatus::Continue {
+                actions_done += 1;
+                on_progress(SyncProgress::new(
+                    SyncStage::Actions,
+                    actions_done,
+                    actions_done + single.remaining_actions,
+                ));
+            }
+            for uuid in single.expired_transfer_uuids {
+                if !report.expired_transfer_uuids.contains(&uuid) {
+                    report.expired_transfer_uuids.push(uuid);
+                }
+            }
+            if let Some(block_number) = single.block_number {
+                report.num_actions += 1;
+                checkpoints
+                    .after_action(
+                        store,
+                        block_number,
+                        single.completes_block,
+                        report.num_actions,
+                    )
+                    .await?;
+            }
+            if single.completes_block {
+                blocks_done += 1;
+                on_progress(SyncProgress::new(
+                    SyncStage::Blocks,
+                    blocks_done,
+                    blocks_done + single.remaining_blocks,
+                ));
+            }
+            match single.status {
+                SyncStatus::Continue => continue,
+                SyncStatus::Complete => break,
+                SyncStatus::Pending => {
+                    return Err(ClientError::PendingError(
+                        "there is pending actions".to_string(),
+                    ));
+                }
+            }
+        }
+        if blocks_done == 0 {
+            on_progress(SyncProgress::new(SyncStage::Blocks, 0, 0));
+        }
+        report.checkpoints = checkpoints.saved().to_vec();
+        Ok(report)
+    }
+
+    pub async fn sync_single(&self, key: KeySet) -> Result<SyncStatus, ClientError> {
+        Ok(self
+            .sync_single_inner(&self.store_vault_server, key, None)
+            .await?
+            .status)
+    }
+
+    // process the next action, reading and saving through `store`
+    async fn sync_single_inner<T: StoreVaultClientInterface>(
+        &self,
+        store: &T,
+        key: KeySet,
+        verifier: Option<&SyncVerifier>,
+    ) -> Result<SingleSync, ClientError> {
+        let next_action = determin_next_action(
+            store,
+            &self.validity_prover,
+            &self.liquidity_contract,
+            key,
+            self.config.deposit_timeout,
+            self.config.tx_timeout,
+        )
+        .await?;
+        let expired_transfer_uuids = next_action
+            .expired_transfers
+            .iter()
+            .map(|meta| meta.uuid.clone())
+            .collect::<Vec<_>>();
+        let idle = |status| SingleSync {
+            status,
+            block_number: None,
+            completes_block: false,
+            remaining_blocks: 0,
+            remaining_actions: 0,
+            expired_transfer_uuids: expired_transfer_uuids.clone(),
+        };
+
+        // if there are pending actions, return pending
+        // todo: process non-pending actions if possible
+        if next_action.pending_deposits.len() > 0
+            || next_action.pending_transfers.len() > 0
+            || next_action.pending_txs.len() > 0
+        {
+            return Ok(idle(SyncStatus::Pending));
+        }
+
+        if next_action.action.is_none() {
+            return Ok(idle(SyncStatus::Complete));
+        }
+
+        let summaries = next_action
+            .all_actions
+            .iter()
+            .map(Action::summary)
+            .collect::<Vec<_>>();
+        let progress = block_progress(&summaries);
+        let block_number = self
+            .process_action(store, key, next_action.action.unwrap(), verifier)
+            .await?;
+
+        Ok(SingleSync {
+            status: SyncStatus::Continue,
+            block_number,
+            completes_block: progress.completes_block,
+            remaining_blocks: progress.remaining_blocks,
+            remaining_actions: summaries.len() as u32 - 1,
+            expired_transfer_uuids,
+        })
+    }
+
+    // process an action, and return its block number
+    async fn process_action<T: StoreVaultClientInterface>(
+        &self,
+        store: &T,
+        key: KeySet,
+        action: Action,
+        verifier: Option<&SyncVerifier>,
+    ) -> Result<Option<u32>, ClientError> {
+        let block_number = match action {
             Action::Deposit(meta, deposit_data) => {
-                self.sync_deposit(key, &meta, &deposit_data).await?;
+                self.sync_deposit(store, key, &meta, &deposit_data, verifier)
+                    .await?;
+                meta.block_number
             }
             Action::Transfer(meta, transfer_data) => {
-                self.sync_transfer(key, &meta, &transfer_data).await?;
+                self.sync_transfer(store, key, &meta, &transfer_data, verifier)
+                    .await?;
+                meta.block_number
+            }
+            Action::Tx(meta, tx_data) => {
+                self.sync_tx(store, key, &meta, &tx_data, verifier).await?;
+                meta.block_number
+            }
+        };
+        Ok(block_number)
+    }
+
+    /// Sync only the deposits and incoming transfers of `token_index`, for
+    /// integrations that track a single token. The actions of other tokens queued
+    /// after them are left for a later full sync and listed in the report.
+    ///
+    /// Fails with `RequiresFullSync` if an action of another token, or a tx, is
+    /// queued before one of the token, since the balance proof cannot go back to
+    /// it afterwards. See `plan_token_sync`.
+    pub async fn sync_token(
+        &self,
+        key: KeySet,
+        token_index: u32,
+    ) -> Result<SyncReport, ClientError> {
+        let mut report = SyncReport::default();
+        loop {
+            let next_action = determin_next_action(
+                &self.store_vault_server,
+                &self.validity_prover,
+                &self.liquidity_contract,
+                key,
+                self.config.deposit_timeout,
+                self.config.tx_timeout,
+            )
+            .await?;
+            for meta in next_action.expired_transfers.iter() {
+                if !report.expired_transfer_uuids.contains(&meta.uuid) {
+                    report.expired_transfer_uuids.push(meta.uuid.clone());
+                }
+            }
+            if next_action.pending_deposits.len() > 0
+                || next_action.pending_transfers.len() > 0
+                || next_action.pending_txs.len() > 0
+            {
+                return Err(ClientError::PendingError(
+                    "there is pending actions".to_string(),
+                ));
             }
-            Action::Tx(meta, tx_data) => self.sync_tx(key, &meta, &tx_data).await?,
-        }
 
-        Ok(SyncStatus::Continue)
+            let summaries = next_action
+                .all_actions
+                .iter()
+                .map(Action::summary)
+                .collect::<Vec<_>>();
+            let plan = plan_token_sync(&summaries, token_index)?;
+            if plan.num_eligible == 0 {
+                report.skipped_actions = plan.skipped;
+                break;
+            }
+            // the actions are fetched again after each one, as in `sync`
+            let action = next_action.all_actions.into_iter().next().unwrap();
+            self.process_action(&self.store_vault_server, key, action, None)
+                .await?;
+            report.num_actions += 1;
+        }
+        report.clock_skew_warnings = global_clock().skew_warnings();
+        Ok(report)
     }
 
     pub async fn sync_withdrawals(&self, key: KeySet) -> Result<(), ClientError> {
+        self.sync_withdrawals_inner(key, |_| {}).await
+    }
+
+    /// `sync_withdrawals`, calling `on_progress` as `sync_with_progress` does while
+    /// the balance proof is synced, then after each withdrawal with the withdrawals
+    /// processed and to process in total, or `0` of `0` if there is none
+    pub async fn sync_withdrawals_with_progress(
+        &self,
+        key: KeySet,
+        on_progress: Option<SyncProgressCallback>,
+    ) -> Result<(), ClientError> {
+        match on_progress {
+            Some(on_progress) => self.sync_withdrawals_inner(key, on_progress).await,
+            None => self.sync_withdrawals(key).await,
+        }
+    }
+
+    async fn sync_withdrawals_inner<P: Fn(SyncProgress)>(
+        &self,
+        key: KeySet,
+        on_progress: P,
+    ) -> Result<(), ClientError> {
         log::info!("sync_withdrawals: {:?}", key);
 
         // sync balance proof
-        self.sync(key).await?;
+        self.sync_inner(key, &on_progress, None).await?;
 
         let user_data = self.get_user_data(key).await?;
 
@@ -413,23 +1429,33 @@ where
         if withdrawal_info.pending.len() > 0 {
             return Err(ClientError::PendingError("pending withdrawals".to_string()));
         }
-        for (meta, data) in &withdrawal_info.settled {
+        let total = withdrawal_info.settled.len() as u32;
+        for (done, (meta, data)) in withdrawal_info.settled.iter().enumerate() {
             self.sync_withdrawal(key, meta, data).await?;
+            on_progress(SyncProgress::new(
+                SyncStage::Withdrawals,
+                done as u32 + 1,
+                total,
+            ));
+        }
+        if total == 0 {
+            on_progress(SyncProgress::new(SyncStage::Withdrawals, 0, 0));
         }
         Ok(())
     }
 
-    async fn sync_deposit(
+    async fn sync_deposit<T: StoreVaultClientInterface>(
         &self,
+        store: &T,
         key: KeySet,
         meta: &MetaData,
         deposit_data: &DepositData,
+        verifier: Option<&SyncVerifier>,
     ) -> Result<(), ClientError> {
-        let mut user_data = self.get_user_data(key).await?;
+        let mut user_data = load_user_data(store, key).await?;
 
         // user's balance proof before applying the tx
-        let prev_balance_proof = self
-            .store_vault_server
+        let prev_balance_proof = store
             .get_balance_proof(
                 key.pubkey,
                 user_data.block_number,
@@ -457,27 +1483,31 @@ where
         user_data.processed_deposit_uuids.push(meta.uuid.clone());
 
         // save proof and user data
-        self.store_vault_server
-            .save_balance_proof(key.pubkey, &new_balance_proof)
-            .await?;
-        self.store_vault_server
-            .save_user_data(key.pubkey, user_data.encrypt(key.pubkey))
-            .await?;
+        save_synced(
+            store,
+            &self.liquidity_contract,
+            key,
+            Some(&new_balance_proof),
+            &mut user_data,
+            verifier,
+        )
+        .await?;
 
         Ok(())
     }
 
-    async fn sync_transfer(
+    async fn sync_transfer<T: StoreVaultClientInterface>(
         &self,
+        store: &T,
         key: KeySet,
         meta: &MetaData,
         transfer_data: &TransferData<F, C, D>,
+        verifier: Option<&SyncVerifier>,
     ) -> Result<(), ClientError> {
         log::info!("sync_transfer: {:?}", meta);
-        let mut user_data = self.get_user_data(key).await?;
+        let mut user_data = load_user_data(store, key).await?;
         // user's balance proof before applying the tx
-        let prev_balance_proof = self
-            .store_vault_server
+        let prev_balance_proof = store
             .get_balance_proof(
                 key.pubkey,
                 user_data.block_number,
@@ -488,6 +1518,7 @@ where
         // sender balance proof after applying the tx
         let new_sender_balance_proof = self
             .generate_new_sender_balance_proof(
+                store,
                 key,
                 transfer_data.sender,
                 meta.block_number.unwrap(),
@@ -516,26 +1547,32 @@ where
         user_data.processed_transfer_uuids.push(meta.uuid.clone());
 
         // save proof and user data
-        self.store_vault_server
-            .save_balance_proof(key.pubkey, &new_balance_proof)
-            .await?;
-        self.store_vault_server
-            .save_user_data(key.pubkey, user_data.encrypt(key.pubkey))
-            .await?;
+        save_synced(
+            store,
+            &self.liquidity_contract,
+            key,
+            Some(&new_balance_proof),
+            &mut user_data,
+            verifier,
+        )
+        .await?;
 
         Ok(())
     }
 
-    async fn sync_tx(
+    async fn sync_tx<T: StoreVaultClientInterface>(
         &self,
+        store: &T,
         key: KeySet,
         meta: &MetaData,
         tx_data: &TxData<F, C, D>,
+        verifier: Option<&SyncVerifier>,
     ) -> Result<(), ClientError> {
         log::info!("sync_tx: {:?}", meta);
-        let mut user_data = self.get_user_data(key).await?;
+        let mut user_data = load_user_data(store, key).await?;
         let balance_proof = self
             .generate_new_sender_balance_proof(
+                store,
                 key,
                 key.pubkey,
                 meta.block_number.unwrap(),
@@ -544,7 +1581,12 @@ where
             .await?;
         let balance_pis = BalancePublicInputs::from_pis(&balance_proof.public_inputs);
         if balance_pis.public_state.block_number != meta.block_number.unwrap() {
-            return Err(ClientError::SyncError("block number mismatch".to_string()));
+            return Err(ClientError::InvalidProof(format!(
+                "balance proof of tx {} is at block {}, not at block {}",
+                meta.uuid,
+                balance_pis.public_state.block_number,
+                meta.block_number.unwrap()
+            )));
         }
 
         // update user data
@@ -560,15 +1602,22 @@ where
 
         // validation
         if balance_pis.private_commitment != user_data.private_commitment() {
-            return Err(ClientError::InternalError(
-                "private commitment mismatch".to_string(),
-            ));
+            return Err(ClientError::InvalidProof(format!(
+                "private commitment of the balance proof of tx {} does not match the user data",
+                meta.uuid
+            )));
         }
 
         // save user data
-        self.store_vault_server
-            .save_user_data(key.pubkey, user_data.encrypt(key.pubkey))
-            .await?;
+        save_synced(
+            store,
+            &self.liquidity_contract,
+            key,
+            None,
+            &mut user_data,
+            verifier,
+        )
+        .await?;
         Ok(())
     }
 
@@ -589,6 +1638,7 @@ where
 
         let new_user_balance_proof = self
             .generate_new_sender_balance_proof(
+                &self.store_vault_server,
                 key,
                 key.pubkey,
                 meta.block_number.unwrap(),
@@ -610,9 +1660,14 @@ where
             .prove_single_withdrawal(key, &withdrawal_witness)
             .await?;
 
-        // send withdrawal request
+        // send withdrawal request, with the tag hashed by the withdrawal's nullifier
+        let attribution_tag_hash = withdrawal_data.attribution_tag.as_ref().map(|tag| {
+            let withdrawal =
+                Withdrawal::from_u64_slice(&single_withdrawal_proof.public_inputs.to_u64_vec());
+            attribution_tag_hash(withdrawal.nullifier, tag)
+        });
         self.withdrawal_server
-            .request_withdrawal(key.pubkey, &single_withdrawal_proof)
+            .request_withdrawal(key.pubkey, &single_withdrawal_proof, attribution_tag_hash)
             .await?;
 
         // update user data
@@ -629,9 +1684,10 @@ where
     }
 
     // generate sender's balance proof after applying the tx
-    // save the proof to the data store server
-    async fn generate_new_sender_balance_proof(
+    // save the proof to `store`
+    async fn generate_new_sender_balance_proof<T: StoreVaultClientInterface>(
         &self,
+        store: &T,
         key: KeySet,
         sender: U256,
         block_number: u32,
@@ -645,8 +1701,7 @@ where
         let spent_proof_pis =
             SpentPublicInputs::from_pis(&common_tx_data.spent_proof.public_inputs);
 
-        let new_sender_balance_proof = self
-            .store_vault_server
+        let new_sender_balance_proof = store
             .get_balance_proof(sender, block_number, spent_proof_pis.new_private_commitment)
             .await?;
         if new_sender_balance_proof.is_some() {
@@ -654,8 +1709,7 @@ where
             return Ok(new_sender_balance_proof.unwrap());
         }
 
-        let prev_sender_balance_proof = self
-            .store_vault_server
+        let prev_sender_balance_proof = store
             .get_balance_proof(
                 sender,
                 common_tx_data.sender_prev_block_number,
@@ -677,26 +1731,303 @@ where
         )
         .await?;
 
-        self.store_vault_server
+        store
             .save_balance_proof(sender, &new_sender_balance_proof)
             .await?;
 
         Ok(new_sender_balance_proof)
     }
 
+    /// Check that the withdrawals among `transfers` move NFTs whole, since the
+    /// contract cannot pay out part of an ERC721 token
+    async fn check_withdrawal_token_types(
+        &self,
+        transfers: &[Transfer],
+    ) -> Result<(), ClientError> {
+        for transfer in transfers.iter().filter(|t| !t.recipient.is_pubkey) {
+            let (token_type, _, token_id) = self
+                .liquidity_contract
+                .get_token_info(transfer.token_index)
+                .await?;
+            token_type
+                .check_amount(transfer.amount, token_id)
+                .map_err(|reason| ClientError::InvalidTokenAmount {
+                    token_index: Some(transfer.token_index),
+                    reason,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// The balances of the user with the token of each index, NFTs grouped by
+    /// contract. It is recommended to sync before calling this function.
+    pub async fn get_balance_view(&self, key: KeySet) -> Result<BalanceView, ClientError> {
+        let user_data = self.get_user_data(key).await?;
+        let balances = user_data.balances();
+        let token_indices = balances
+            .keys()
+            .map(|token_index| *token_index as u32)
+            .collect::<Vec<_>>();
+        let token_infos = self
+            .liquidity_contract
+            .get_token_infos(&token_indices)
+            .await?;
+        let balances = token_indices
+            .into_iter()
+            .zip(balances.values())
+            .zip(token_infos)
+            .map(
+                |((token_index, leaf), (token_type, token_address, token_id))| TokenBalance {
+                    token_index,
+                    token_type,
+                    token_address,
+                    token_id,
+                    amount: leaf.amount,
+                    is_insufficient: leaf.is_insufficient,
+                },
+            )
+            .collect();
+        Ok(BalanceView::new(balances))
+    }
+
+    /// The balances of the balance proof, with the debits of the finalized txs and
+    /// the credits of the incoming transfers that the next sync will reflect. Each
+    /// delta comes with its status, and failed txs no longer count.
+    pub async fn get_balances_optimistic(
+        &self,
+        key: KeySet,
+    ) -> Result<OptimisticBalances, ClientError> {
+        let user_data = self.get_user_data(key).await?;
+        let mut deltas = match self.reconcile_provisional_debits(key, &user_data).await {
+            Ok(deltas) => deltas,
+            Err(e) => {
+                log::warn!("Failed to reconcile provisional debits: {}", e);
+                self.provisional_store.load(key.pubkey)
+            }
+        };
+        let transfer_info = fetch_transfer_info(
+            &self.store_vault_server,
+            &self.validity_prover,
+            key,
+            user_data.transfer_lpt,
+            self.config.tx_timeout,
+        )
+        .await?;
+        // pending transfers are left out, their amounts cannot be known yet
+        let mut latest_block_number = None;
+        for (meta, transfer_data) in transfer_info.settled {
+            if user_data.processed_transfer_uuids.contains(&meta.uuid) {
+                continue;
+            }
+            // the sync refuses a transfer whose claim deadline has passed
+            if transfer_data.claim_deadline_block.is_some() {
+                let block_number = match latest_block_number {
+                    Some(block_number) => block_number,
+                    None => {
+                        *latest_block_number.insert(self.validity_prover.get_block_number().await?)
+                    }
+                };
+                if transfer_data.is_expired(block_number) {
+                    continue;
+                }
+            }
+            deltas.push(ProvisionalDelta {
+                kind: DeltaKind::Credit,
+                token_index: transfer_data.transfer.token_index,
+                amount: transfer_data.transfer.amount,
+                tx_tree_root: transfer_data.tx_data.tx_tree_root,
+                created_at: meta.timestamp,
+                status: DeltaStatus::Settled,
+                block_number: meta.block_number,
+            });
+        }
+        let settled = user_data
+            .balances()
+            .iter()
+            .map(|(token_index, leaf)| (*token_index as u32, leaf.amount))
+            .collect::<BTreeMap<_, _>>();
+        Ok(OptimisticBalances::new(settled, deltas))
+    }
+
+    /// Drop the debits reflected in the balance proof of `user_data`, and mark those
+    /// whose tx was not included in time as failed
+    async fn reconcile_provisional_debits(
+        &self,
+        key: KeySet,
+        user_data: &UserData,
+    ) -> Result<Vec<ProvisionalDelta>, ClientError> {
+        let deltas = self.provisional_store.load(key.pubkey);
+        if deltas.is_empty() {
+            return Ok(deltas);
+        }
+        let mut inclusion_blocks = HashMap::new();
+        for delta in deltas.iter() {
+            if inclusion_blocks.contains_key(&delta.tx_tree_root) {
+                continue;
+            }
+            if let Some(block_number) = self
+                .validity_prover
+                .get_block_number_by_tx_tree_root(delta.tx_tree_root)
+                .await?
+            {
+                inclusion_blocks.insert(delta.tx_tree_root, block_number);
+            }
+        }
+        let deltas = reconcile_debits(
+            deltas,
+            user_data.block_number,
+            &inclusion_blocks,
+            global_clock().server_now(),
+            self.config.tx_timeout,
+        );
+        self.provisional_store.save(key.pubkey, &deltas);
+        Ok(deltas)
+    }
+
     /// Get the latest user data from the data store server
     pub async fn get_user_data(&self, key: KeySet) -> Result<UserData, ClientError> {
-        let user_data = self
+        load_user_data(&self.store_vault_server, key).await
+    }
+
+    /// Replace the user data with an empty one, so that the next sync rebuilds the
+    /// balance proof from the first action. Returns the encrypted user data that was
+    /// replaced.
+    pub async fn reset_user_data(&self, key: KeySet) -> Result<Option<Vec<u8>>, ClientError> {
+        let prev_user_data = self.store_vault_server.get_user_data(key.pubkey).await?;
+        self.store_vault_server
+            .save_user_data(key.pubkey, UserData::new(key.pubkey).encrypt(key.pubkey))
+            .await?;
+        Ok(prev_user_data)
+    }
+
+    /// Deposits not incorporated by a sync yet, with the token index set. Those
+    /// not in a block of the validity prover yet are marked as pending, while
+    /// timed-out deposits are left out.
+    pub async fn get_pending_deposits(
+        &self,
+        key: KeySet,
+    ) -> Result<Vec<PendingDeposit>, ClientError> {
+        let user_data = self.get_user_data(key).await?;
+        let deposit_info = fetch_deposit_info(
+            &self.store_vault_server,
+            &self.validity_prover,
+            &self.liquidity_contract,
+            key,
+            user_data.deposit_lpt,
+            self.config.deposit_timeout,
+        )
+        .await?;
+        Ok(unprocessed_deposits(
+            deposit_info,
+            &user_data.processed_deposit_uuids,
+        ))
+    }
+
+    /// Compare the nonce of the private state with the txs settled on chain and the
+    /// pending txs, to diagnose a nonce that got out of sync.
+    pub async fn diagnose_nonce(&self, key: KeySet) -> Result<NonceDiagnosis, ClientError> {
+        let user_data = self.get_user_data(key).await?;
+        let tx_info = fetch_tx_info(
+            &self.store_vault_server,
+            &self.validity_prover,
+            key,
+            user_data.tx_lpt,
+            self.config.tx_timeout,
+        )
+        .await?;
+        let private_state_nonce = user_data.full_private_state.nonce;
+        Ok(NonceDiagnosis {
+            private_state_nonce,
+            settled_nonce: private_state_nonce + tx_info.settled.len() as u32,
+            pending_tx_uuids: tx_info.pending.into_iter().map(|meta| meta.uuid).collect(),
+        })
+    }
+
+    /// Nonce, balance proof block and private commitment of `key`, with the
+    /// account as the validity prover sees it, for support to triage a stuck
+    /// account
+    pub async fn get_diagnostics(&self, key: KeySet) -> Result<AccountDiagnostics, ClientError> {
+        get_diagnostics(self, key).await
+    }
+
+    /// List the transfers sent with a claim deadline that has passed.
+    ///
+    /// The amount of a transfer leaves the sender's balance when the tx settles, and the
+    /// protocol has no way to return it. A recipient that refuses an expired transfer
+    /// leaves the amount unspendable, so the sender should account for it as never
+    /// delivered (e.g. refund it by other means). The sender cannot see whether the
+    /// recipient received the transfer before the deadline, so the result includes
+    /// transfers that were received in time.
+    pub async fn list_expired_outgoing_transfers(
+        &self,
+        key: KeySet,
+    ) -> Result<Vec<ExpiredTransfer>, ClientError> {
+        let block_number = self.validity_prover.get_block_number().await?;
+        let user_data = self.get_user_data(key).await?;
+        let all_tx_data = self
             .store_vault_server
-            .get_user_data(key.pubkey)
-            .await?
-            .map(|encrypted| UserData::decrypt(&encrypted, key))
-            .transpose()
-            .map_err(|e| {
-                ClientError::DecryptionError(format!("failed to decrypt user data: {}", e))
-            })?
-            .unwrap_or(UserData::new(key.pubkey));
-        Ok(user_data)
+            .get_data_all_after(DataType::Tx, key.pubkey, 0)
+            .await?;
+        let mut expired = Vec::new();
+        for (meta, data) in all_tx_data {
+            // only settled txs actually sent the transfers
+            if !user_data.processed_tx_uuids.contains(&meta.uuid) {
+                continue;
+            }
+            let tx_data = match TxData::<F, C, D>::decrypt(&data, key) {
+                Ok(tx_data) => tx_data,
+                Err(e) => {
+                    log::warn!("Failed to decrypt tx data: {}", e);
+                    continue;
+                }
+            };
+            let claim_deadline_block = match tx_data.claim_deadline_block {
+                Some(deadline) if is_claim_expired(Some(deadline), block_number) => deadline,
+                _ => continue,
+            };
+            for transfer in tx_data.spent_witness.transfers.iter() {
+                let recipient = transfer.recipient;
+                if !recipient.is_pubkey
+                    || (recipient.data == U256::dummy_pubkey()
+                        && transfer.amount == U256::default())
+                {
+                    continue;
+                }
+                expired.push(ExpiredTransfer {
+                    tx_uuid: meta.uuid.clone(),
+                    recipient: recipient.to_pubkey().unwrap(),
+                    token_index: transfer.token_index,
+                    amount: transfer.amount,
+                    claim_deadline_block,
+                });
+            }
+        }
+        Ok(expired)
+    }
+
+    /// List the fees paid with `TxSendOptions::fee_transfer` in txs whose block the
+    /// block builder never posted, each with the evidence to claim a refund from
+    /// the builder operator: the signed proposal, and the tx tree root not being in
+    /// any block after the tx timeout.
+    pub async fn list_fee_disputes(&self, key: KeySet) -> Result<Vec<FeeDispute>, ClientError> {
+        list_fee_disputes(
+            &self.store_vault_server,
+            &self.validity_prover,
+            key,
+            self.config.tx_timeout,
+        )
+        .await
+    }
+
+    /// When the tx of `tx_tree_root` sent by `key` was finalized, from the local
+    /// provisional debits or the backup of the tx. None if neither has the tx.
+    pub async fn tx_finalized_at(
+        &self,
+        key: KeySet,
+        tx_tree_root: Bytes32,
+    ) -> Result<Option<u64>, ClientError> {
+        let deltas = self.provisional_store.load(key.pubkey);
+        find_tx_finalized_at(&self.store_vault_server, &deltas, key, tx_tree_root).await
     }
 
     pub async fn get_withdrawal_info(
@@ -707,7 +2038,683 @@ where
         Ok(withdrawal_info)
     }
 
+    /// The withdrawal info with the attribution tag of each withdrawal, found by
+    /// matching the hashes published by the withdrawal server with the tags of the
+    /// withdrawal backups. Only the owner can see the tags.
+    pub async fn get_withdrawal_info_with_tags(
+        &self,
+        key: KeySet,
+    ) -> Result<Vec<(WithdrawalInfo, Option<String>)>, ClientError> {
+        let withdrawal_info = self.withdrawal_server.get_withdrawal_info(key).await?;
+        if withdrawal_info
+            .iter()
+            .all(|info| info.attribution_tag_hash.is_none())
+        {
+            return Ok(withdrawal_info
+                .into_iter()
+                .map(|info| (info, None))
+                .collect());
+        }
+        let mut tags = Vec::new();
+        for (_, encrypted) in self
+            .store_vault_server
+            .get_data_all_after(DataType::Withdrawal, key.pubkey, 0)
+            .await?
+        {
+            // unreadable backups are reported by the sync, not here
+            if let Ok(withdrawal_data) = TransferData::<F, C, D>::decrypt(&encrypted, key) {
+                if let Some(tag) = withdrawal_data.attribution_tag {
+                    tags.push(tag);
+                }
+            }
+        }
+        Ok(withdrawal_info
+            .into_iter()
+            .map(|info| {
+                let tag = info.attribution_tag_hash.and_then(|hash| {
+                    let nullifier = info.contract_withdrawal.nullifier;
+                    tags.iter()
+                        .find(|tag| attribution_tag_hash(nullifier, tag) == hash)
+                        .cloned()
+                });
+                (info, tag)
+            })
+            .collect())
+    }
+
+    /// Check the withdrawals the aggregator reports as submitted against the events
+    /// of the liquidity contract, in the order of `withdrawal_info`
+    pub async fn verify_withdrawals_onchain(
+        &self,
+        withdrawal_info: &[WithdrawalInfo],
+    ) -> Result<Vec<OnchainConfirmation>, ClientError> {
+        verify_withdrawals_onchain(
+            &self.liquidity_contract,
+            withdrawal_info,
+            self.config.withdrawal_onchain_grace,
+        )
+        .await
+    }
+
     pub async fn fetch_history(&self, key: KeySet) -> Result<Vec<HistoryEntry>, ClientError> {
         fetch_history(self, key).await
     }
+
+    /// Same as `fetch_history`, reusing an index returned by a previous call
+    pub async fn fetch_history_with_index(
+        &self,
+        key: KeySet,
+        index: HistoryIndex,
+    ) -> Result<(Vec<HistoryEntry>, HistoryIndex), ClientError> {
+        fetch_history_with_index(self, key, index).await
+    }
+
+    /// Balances as of `block_number`, replayed from the history. Entries not yet
+    /// synced are left out and counted in `excluded_entries`.
+    pub async fn get_balance_at_block(
+        &self,
+        key: KeySet,
+        block_number: u32,
+    ) -> Result<HistoricalBalance, ClientError> {
+        let history = fetch_history(self, key).await?;
+        Ok(balance_at_block(&history, block_number))
+    }
+
+    /// Balances at the end of each period up to now, replayed from the history
+    pub async fn get_balance_series(
+        &self,
+        key: KeySet,
+        interval: SeriesInterval,
+    ) -> Result<BalanceSeries, ClientError> {
+        let history = fetch_history(self, key).await?;
+        Ok(balance_series(
+            &history,
+            interval,
+            global_clock().server_now(),
+        ))
+    }
+
+    /// Receipt of the transfer `transfer_uuid` received by `key`, which a third
+    /// party checks with `verify_receive_receipt`
+    pub async fn export_receive_receipt(
+        &self,
+        key: KeySet,
+        transfer_uuid: &str,
+    ) -> Result<ReceiveReceipt, ClientError> {
+        export_receive_receipt(self, key, transfer_uuid).await
+    }
+
+    /// A pubkey salt hash to give to someone depositing to `key`, and the salt to
+    /// keep for `claim_external_deposit`
+    pub fn generate_deposit_request(&self, key: KeySet) -> DepositRequest {
+        generate_deposit_request(key.pubkey)
+    }
+
+    /// Import a deposit someone else made to a hash from `generate_deposit_request`
+    /// with `salt`, identified by its L1 tx hash or deposit id
+    pub async fn claim_external_deposit(
+        &self,
+        key: KeySet,
+        salt: Salt,
+        source: DepositSource,
+    ) -> Result<DepositData, ClientError> {
+        claim_external_deposit(self, key, salt, source).await
+    }
+
+    /// Move the whole balance of `old_key` to `new_key`, for a key that may have
+    /// been exposed. See `plan_rotation`, `send_rotation_chunk` and
+    /// `complete_rotation` for the stages.
+    pub async fn rotate_key(
+        &self,
+        block_builder_url: &str,
+        old_key: KeySet,
+        new_key: KeySet,
+        options: &RotationOptions,
+    ) -> Result<RotationReport, ClientError> {
+        rotate_key(self, block_builder_url, old_key, new_key, options).await
+    }
+
+    /// Sync both keys and plan the txs of a rotation from `old_key` to `new_key`
+    pub async fn plan_rotation(
+        &self,
+        old_key: KeySet,
+        new_key: KeySet,
+        options: &RotationOptions,
+    ) -> Result<RotationPlan, ClientError> {
+        plan_rotation(self, old_key, new_key, options).await
+    }
+
+    /// Send the chunks of a rotation not sent yet, and complete it. See
+    /// `resume_rotation`.
+    pub async fn resume_rotation(
+        &self,
+        block_builder_url: &str,
+        old_key: KeySet,
+        new_key: KeySet,
+        progress: RotationProgress,
+        options: &RotationOptions,
+        on_progress: impl FnMut(&RotationProgress),
+    ) -> Result<RotationReport, ClientError> {
+        resume_rotation(
+            self,
+            block_builder_url,
+            old_key,
+            new_key,
+            progress,
+            options,
+            on_progress,
+        )
+        .await
+    }
+
+    /// Send one chunk of a rotation plan and wait for its block
+    pub async fn send_rotation_chunk(
+        &self,
+        block_builder_url: &str,
+        old_key: KeySet,
+        transfers: Vec<Transfer>,
+        options: &RotationOptions,
+    ) -> Result<Bytes32, ClientError> {
+        send_rotation_chunk(self, block_builder_url, old_key, transfers, options).await
+    }
+
+    /// Sync `new_key` until it received the rotation, and report it
+    pub async fn complete_rotation(
+        &self,
+        new_key: KeySet,
+        plan: &RotationPlan,
+        tx_tree_roots: &[Bytes32],
+        options: &RotationOptions,
+    ) -> Result<RotationReport, ClientError> {
+        complete_rotation(self, new_key, plan, tx_tree_roots, options).await
+    }
+
+    /// Send the whole balance of `token_indices` (all tokens if empty) to `key`
+    /// itself, after syncing the incoming transfers and deposits it has pending.
+    /// See `plan_consolidation` and `complete_consolidation` for the stages.
+    pub async fn consolidate(
+        &self,
+        block_builder_url: &str,
+        key: KeySet,
+        token_indices: &[u32],
+        options: &ConsolidationOptions,
+    ) -> Result<ConsolidationReport, ClientError> {
+        consolidate(self, block_builder_url, key, token_indices, options).await
+    }
+
+    /// Check that a consolidation is worth it, sync `key` and plan the
+    /// self-transfers, keeping the fees of `block_builder_url` out of them
+    pub async fn plan_consolidation(
+        &self,
+        block_builder_url: &str,
+        key: KeySet,
+        token_indices: &[u32],
+        options: &ConsolidationOptions,
+    ) -> Result<ConsolidationPlan, ClientError> {
+        plan_consolidation(self, block_builder_url, key, token_indices, options).await
+    }
+
+    /// Send the self-transfers of a consolidation plan and sync their receipt
+    pub async fn complete_consolidation(
+        &self,
+        block_builder_url: &str,
+        key: KeySet,
+        plan: &ConsolidationPlan,
+        options: &ConsolidationOptions,
+    ) -> Result<ConsolidationReport, ClientError> {
+        complete_consolidation(self, block_builder_url, key, plan, options).await
+    }
+
+    /// Check that everything needed to recover the account is in the store vault.
+    /// `known_tx_tree_roots` are own txs known locally, e.g. from pending memos.
+    pub async fn backup_health(
+        &self,
+        key: KeySet,
+        known_tx_tree_roots: &[Bytes32],
+    ) -> Result<BackupHealth, ClientError> {
+        check_backup_health(
+            &self.store_vault_server,
+            &self.validity_prover,
+            key,
+            known_tx_tree_roots,
+        )
+        .await
+    }
+
+    /// Limits on withdrawals, deposits and txs, cached for `LIMITS_CACHE_TTL` seconds
+    pub async fn get_limits(&self) -> Result<ProtocolLimits, ClientError> {
+        fetch_limits(&self.withdrawal_server).await
+    }
+
+    /// Fees of a transfer or a withdrawal of `amount` through the block builder at
+    /// `block_builder_url`, per token or only of `token_index`. See `quote_fees`.
+    pub async fn estimate_fees(
+        &self,
+        block_builder_url: &str,
+        kind: FeeKind,
+        token_index: Option<u32>,
+        amount: U256,
+    ) -> Result<FeeEstimate, ClientError> {
+        quote_fees(
+            &self.block_builder,
+            block_builder_url,
+            &self.withdrawal_server,
+            kind,
+            token_index,
+            amount,
+        )
+        .await
+    }
+
+    /// Start a session for `key`. If the previous session was not shut down
+    /// cleanly, store vault writes are refused until the user data and the balance
+    /// proof of its last checkpoint are checked. Returns whether the check ran.
+    pub async fn open_session<M: SessionMarker>(
+        &self,
+        key: KeySet,
+        marker: &M,
+    ) -> Result<bool, ClientError> {
+        account_session(key.pubkey)
+            .open(&self.store_vault_server, key, marker)
+            .await
+    }
+
+    /// Refuse new store vault writes of the account `pubkey` and wait up to
+    /// `SHUTDOWN_TIMEOUT_MS` for the in-flight ones, then mark its session as
+    /// cleanly closed
+    pub async fn shutdown<M: SessionMarker>(&self, pubkey: U256, marker: &M) -> ShutdownReport {
+        account_session(pubkey)
+            .shutdown(marker, SHUTDOWN_TIMEOUT_MS)
+            .await
+    }
+
+    /// Accept store vault writes of the account `pubkey` again after a `shutdown`
+    /// whose teardown did not happen
+    pub fn cancel_shutdown<M: SessionMarker>(&self, pubkey: U256, marker: &M) {
+        account_session(pubkey).cancel_shutdown(marker);
+    }
+}
+
+impl<BB, S, V, B, W> Client<BB, MirroredStoreVaultClient<S>, V, B, W>
+where
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+{
+    /// Retry backups that failed to be mirrored to the secondary store vaults.
+    /// Returns the number of backups mirrored.
+    pub async fn flush_mirror_queue(&self) -> usize {
+        self.store_vault_server.flush_mirror_queue().await
+    }
+}
+
+// the user data of `key` in `store_vault_server`, or an empty one if there is none
+async fn load_user_data<S: StoreVaultClientInterface>(
+    store_vault_server: &S,
+    key: KeySet,
+) -> Result<UserData, ClientError> {
+    let user_data = store_vault_server
+        .get_user_data(key.pubkey)
+        .await?
+        .map(|encrypted| UserData::decrypt(&encrypted, key))
+        .transpose()
+        .map_err(|e| ClientError::DecryptionError(format!("failed to decrypt user data: {}", e)))?
+        .unwrap_or(UserData::new(key.pubkey));
+    Ok(user_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::types::Address as EthAddress;
+    use intmax2_zkp::{
+        common::{generic_address::GenericAddress, salt::Salt},
+        ethereum_types::u32limb_trait::U32LimbTrait as _,
+    };
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        client::provisional::MemoryProvisionalStore,
+        external_api::{
+            balance_prover::BalanceProverClient,
+            block_builder::BlockBuilderClient,
+            utils::{
+                in_memory_store_vault::InMemoryStoreVault,
+                mock_server::{start_mock_server, start_scripted_mock_server, MockRequest},
+            },
+            validity_prover::ValidityProverClient,
+            withdrawal_server::WithdrawalServerClient,
+        },
+    };
+
+    fn transfer(amount: u32) -> Transfer {
+        Transfer {
+            recipient: GenericAddress::from_pubkey(U256::from(1u128)),
+            token_index: 0,
+            amount: U256::from(amount as u128),
+            salt: Salt::default(),
+        }
+    }
+
+    // user data with `amount` of the native token
+    fn funded_user_data(pubkey: U256, amount: u32) -> UserData {
+        let mut user_data = UserData::new(pubkey);
+        let asset_tree = &mut user_data.full_private_state.asset_tree;
+        let leaf = asset_tree.get_leaf(0);
+        asset_tree.update(0, leaf.add(U256::from(amount as u128)));
+        user_data
+    }
+
+    #[test]
+    fn test_tx_request_checks() {
+        let options = TxSendOptions {
+            fee_transfer: Some(transfer(1)),
+            ..Default::default()
+        };
+        let (transfers, fee_transfer_index) =
+            with_fee_transfer(vec![transfer(5)], &options).unwrap();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(fee_transfer_index, Some(1));
+        assert!(with_fee_transfer(Vec::new(), &options).is_err());
+        let full = vec![transfer(5); NUM_TRANSFERS_IN_TX];
+        assert!(with_fee_transfer(full, &options).is_err());
+
+        let user_data = UserData::new(U256::from(2u128));
+        assert_eq!(check_tx_against_state(&user_data, &[], None).unwrap().0, 0);
+        assert!(matches!(
+            check_tx_against_state(&user_data, &[], Some(3)),
+            Err(ClientError::InvalidNonce(3, 0))
+        ));
+        assert!(matches!(
+            check_tx_against_state(&user_data, &transfers, None),
+            Err(ClientError::BalanceError(_))
+        ));
+
+        let user_data = funded_user_data(U256::from(2u128), 10);
+        let (_, balance_changes) =
+            check_tx_against_state(&user_data, &[transfer(6), transfer(4)], None).unwrap();
+        assert_eq!(balance_changes.len(), 1);
+        assert_eq!(balance_changes[0].amount, U256::from(10u128));
+        assert_eq!(balance_changes[0].remaining, U256::default());
+        // each transfer fits the balance, but not both
+        assert!(matches!(
+            check_tx_against_state(&user_data, &[transfer(6), transfer(6)], None),
+            Err(ClientError::BalanceError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_tx_request() {
+        let timeout = Duration::from_secs(5);
+        let key = KeySet::rand(&mut rand::thread_rng());
+        // a validity prover that knows no tx tree root
+        let (validity_prover_url, requests) =
+            start_mock_server(|_, _| r#"{"blockNumber":null}"#.to_string());
+        let store_vault = InMemoryStoreVault::new();
+        store_vault
+            .save_user_data(
+                key.pubkey,
+                funded_user_data(key.pubkey, 10).encrypt(key.pubkey),
+            )
+            .await
+            .unwrap();
+        // a sync looks up the tx of the debit
+        let provisional_store = MemoryProvisionalStore::default();
+        provisional_store.save(
+            key.pubkey,
+            &[ProvisionalDelta {
+                kind: DeltaKind::Debit,
+                token_index: 0,
+                amount: U256::from(1u128),
+                tx_tree_root: Bytes32::default(),
+                created_at: global_clock().server_now(),
+                status: DeltaStatus::Pending,
+                block_number: None,
+            }],
+        );
+        let unused = "http://127.0.0.1:1";
+        let client = Client {
+            config: ClientConfig::default(),
+            block_builder: BlockBuilderClient::new().with_timeout(timeout),
+            store_vault_server: store_vault,
+            validity_prover: ValidityProverClient::new(&validity_prover_url)
+                .with_bearer_token("token")
+                .with_timeout(timeout),
+            balance_prover: BalanceProverClient::new(unused).with_timeout(timeout),
+            withdrawal_server: WithdrawalServerClient::new(unused).with_timeout(timeout),
+            liquidity_contract: LiquidityContract::new(unused, 1, EthAddress::random()),
+            rollup_contract: RollupContract::new(unused, 1, EthAddress::random(), 0),
+            provisional_store: Arc::new(provisional_store),
+        };
+        let is_lookup =
+            |request: &MockRequest| request.path.ends_with("/get-block-number-by-tx-tree-root");
+
+        let options = TxSendOptions::default();
+        let dry_run = client
+            .dry_run_tx_request(key, vec![transfer(6), transfer(4)], &options)
+            .await
+            .unwrap();
+        assert_eq!(dry_run.nonce, 0);
+        assert_eq!(dry_run.balance_changes.len(), 1);
+        assert_eq!(dry_run.balance_changes[0].remaining, U256::default());
+        assert!(requests.lock().unwrap().iter().any(is_lookup));
+        // refused like a send, although each transfer fits the balance
+        assert!(matches!(
+            client
+                .dry_run_tx_request(key, vec![transfer(6), transfer(6)], &options)
+                .await,
+            Err(ClientError::BalanceError(_))
+        ));
+
+        // without the sync the validity prover is not asked
+        let lookups = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| is_lookup(r))
+            .count();
+        let options = TxSendOptions {
+            skip_sync: true,
+            ..Default::default()
+        };
+        client
+            .dry_run_tx_request(key, vec![transfer(1)], &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            requests
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|r| is_lookup(r))
+                .count(),
+            lookups
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backup_without_attestation() {
+        let timeout = Duration::from_secs(5);
+        let unused = "http://127.0.0.1:1";
+        let client = Client {
+            config: ClientConfig::default(),
+            block_builder: BlockBuilderClient::new().with_timeout(timeout),
+            store_vault_server: InMemoryStoreVault::new(),
+            validity_prover: ValidityProverClient::new(unused).with_timeout(timeout),
+            balance_prover: BalanceProverClient::new(unused).with_timeout(timeout),
+            withdrawal_server: WithdrawalServerClient::new(unused).with_timeout(timeout),
+            liquidity_contract: LiquidityContract::new(unused, 1, EthAddress::random()),
+            rollup_contract: RollupContract::new(unused, 1, EthAddress::random(), 0),
+            provisional_store: Arc::new(MemoryProvisionalStore::default()),
+        };
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let tx = Tx::default();
+
+        // a block builder without the endpoint, and one that does not attest
+        let (without_endpoint, _) =
+            start_scripted_mock_server(|_, _| (404, r#"{"error":"not found"}"#.to_string()));
+        let (without_attestation, _) =
+            start_mock_server(|_, _| r#"{"attestation":null}"#.to_string());
+        for block_builder_url in [without_endpoint, without_attestation] {
+            let attestation = client
+                .fetch_proposal_attestation(
+                    &block_builder_url,
+                    key.pubkey,
+                    false,
+                    tx,
+                    Bytes32::default(),
+                )
+                .await
+                .unwrap();
+            assert!(attestation.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recover_desynced_nonce() {
+        let timeout = Duration::from_secs(5);
+        let key = KeySet::rand(&mut rand::thread_rng());
+        // the user expects nonce 2, but the private state is at nonce 3, e.g. after
+        // a tx whose finalization failed on the client was settled anyway
+        let mut user_data = funded_user_data(key.pubkey, 10);
+        user_data.full_private_state.nonce = 3;
+        let store_vault = InMemoryStoreVault::new();
+        store_vault
+            .save_user_data(key.pubkey, user_data.encrypt(key.pubkey))
+            .await
+            .unwrap();
+        let unused = "http://127.0.0.1:1";
+        let client = Client {
+            config: ClientConfig::default(),
+            block_builder: BlockBuilderClient::new().with_timeout(timeout),
+            store_vault_server: store_vault,
+            validity_prover: ValidityProverClient::new(unused).with_timeout(timeout),
+            balance_prover: BalanceProverClient::new(unused).with_timeout(timeout),
+            withdrawal_server: WithdrawalServerClient::new(unused).with_timeout(timeout),
+            liquidity_contract: LiquidityContract::new(unused, 1, EthAddress::random()),
+            rollup_contract: RollupContract::new(unused, 1, EthAddress::random(), 0),
+            provisional_store: Arc::new(MemoryProvisionalStore::default()),
+        };
+
+        let diagnosis = client.diagnose_nonce(key).await.unwrap();
+        assert_eq!(diagnosis.private_state_nonce, 3);
+        assert_eq!(diagnosis.settled_nonce, 3);
+        assert!(diagnosis.pending_tx_uuids.is_empty());
+
+        // the tx at the expected nonce is refused with the valid one
+        let options = TxSendOptions {
+            nonce_override: Some(2),
+            skip_sync: true,
+            ..Default::default()
+        };
+        let valid_nonce = match client
+            .dry_run_tx_request(key, vec![transfer(1)], &options)
+            .await
+        {
+            Err(ClientError::InvalidNonce(2, valid_nonce)) => valid_nonce,
+            other => panic!(
+                "unexpected result: {:?}",
+                other.map(|dry_run| dry_run.nonce)
+            ),
+        };
+        assert_eq!(valid_nonce, 3);
+
+        // and goes through at it
+        let options = TxSendOptions {
+            nonce_override: Some(valid_nonce),
+            skip_sync: true,
+            ..Default::default()
+        };
+        let dry_run = client
+            .dry_run_tx_request(key, vec![transfer(1)], &options)
+            .await
+            .unwrap();
+        assert_eq!(dry_run.nonce, 3);
+    }
+
+    #[test]
+    fn test_check_deposit_token() {
+        let token_address = Address::from_u32_slice(&[1, 2, 3, 4, 5]);
+        assert!(check_deposit_token(0, TokenType::NATIVE, Address::default()).is_ok());
+        assert!(check_deposit_token(1, TokenType::ERC20, token_address).is_ok());
+        assert!(check_deposit_token(2, TokenType::ERC1155, token_address).is_ok());
+        // the empty token info of an index without a token
+        assert!(matches!(
+            check_deposit_token(7, TokenType::NATIVE, Address::default()),
+            Err(ClientError::UnsupportedToken { token_index: 7, .. })
+        ));
+        assert!(check_deposit_token(0, TokenType::ERC20, token_address).is_err());
+        assert!(check_deposit_token(1, TokenType::ERC20, Address::default()).is_err());
+    }
+
+    // an RPC whose liquidity contract has the ERC1155 token `token_id` of
+    // `token_address` at every index
+    fn start_token_rpc(token_address: EthAddress, token_id: u64) -> String {
+        let (url, _) = start_mock_server(move |_, body| {
+            let request: Value = serde_json::from_str(body).unwrap();
+            let result = match request["method"].as_str().unwrap() {
+                "eth_call" => {
+                    let token_info = ethers::abi::encode(&[
+                        ethers::abi::Token::Uint((TokenType::ERC1155 as u8).into()),
+                        ethers::abi::Token::Address(token_address),
+                        ethers::abi::Token::Uint(token_id.into()),
+                    ]);
+                    json!(format!("0x{}", hex::encode(token_info)))
+                }
+                method => panic!("unexpected {}", method),
+            };
+            json!({"jsonrpc": "2.0", "id": request["id"], "result": result}).to_string()
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_prepare_deposit_by_token_index() {
+        let timeout = Duration::from_secs(5);
+        let token_address = EthAddress::random();
+        let rpc_url = start_token_rpc(token_address, 42);
+        // a withdrawal server without limits
+        let (withdrawal_server_url, _) =
+            start_scripted_mock_server(|_, _| (404, r#"{"error":"not found"}"#.to_string()));
+        let unused = "http://127.0.0.1:1";
+        let client = Client {
+            config: ClientConfig::default(),
+            block_builder: BlockBuilderClient::new().with_timeout(timeout),
+            store_vault_server: InMemoryStoreVault::new(),
+            validity_prover: ValidityProverClient::new(unused).with_timeout(timeout),
+            balance_prover: BalanceProverClient::new(unused).with_timeout(timeout),
+            withdrawal_server: WithdrawalServerClient::new(&withdrawal_server_url)
+                .with_timeout(timeout),
+            liquidity_contract: LiquidityContract::new(&rpc_url, 1, EthAddress::random()),
+            rollup_contract: RollupContract::new(unused, 1, EthAddress::random(), 0),
+            provisional_store: Arc::new(MemoryProvisionalStore::default()),
+        };
+        let key = KeySet::rand(&mut rand::thread_rng());
+
+        let deposit_data = client
+            .prepare_deposit_by_token_index(key.pubkey, U256::from(1u128), 5)
+            .await
+            .unwrap();
+        let saved = client
+            .store_vault_server
+            .get_data_all_after(DataType::Deposit, key.pubkey, 0)
+            .await
+            .unwrap();
+        assert_eq!(saved.len(), 1);
+        let saved = DepositData::decrypt(&saved[0].1, key).unwrap();
+        for data in [&deposit_data, &saved] {
+            assert_eq!(data.token_type, TokenType::ERC1155);
+            assert_eq!(
+                data.token_address,
+                Address::from_bytes_be(token_address.as_bytes())
+            );
+            assert_eq!(data.token_id, U256::from(42u128));
+            assert_eq!(data.token_index, Some(5));
+        }
+        assert_eq!(saved.pubkey_salt_hash, deposit_data.pubkey_salt_hash);
+    }
 }
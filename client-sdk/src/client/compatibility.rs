@@ -0,0 +1,331 @@
+//! Preflight check of the client against the versions of the servers, so that an
+//! app built against an older protocol can ask for an update at startup instead
+//! of failing in the middle of a transfer.
+
+use std::cmp::Ordering;
+
+use intmax2_interfaces::api::{
+    error::ServerError,
+    version::{VersionResponse, MIN_SUPPORTED_PROTOCOL_VERSION, PROTOCOL_VERSION},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::utils::query::{get_request, HttpClient};
+
+/// Base urls of the servers to check
+#[derive(Debug, Clone, Default)]
+pub struct ServiceUrls {
+    pub store_vault_server: String,
+    pub validity_prover: String,
+    pub balance_prover: String,
+    pub withdrawal_server: String,
+    pub block_builder: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum ServiceStatus {
+    Compatible,
+    /// The server needs a newer client
+    ClientTooOld {
+        reason: String,
+    },
+    /// The server speaks a protocol this client no longer supports
+    ServerTooOld {
+        reason: String,
+    },
+    /// No version could be read, e.g. the server is down or predates `/version`
+    Unreachable {
+        error: String,
+    },
+}
+
+impl ServiceStatus {
+    pub fn is_incompatible(&self) -> bool {
+        matches!(self, Self::ClientTooOld { .. } | Self::ServerTooOld { .. })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceCompatibility {
+    pub service: String,
+    pub base_url: String,
+    pub server_version: Option<String>,
+    pub protocol_version: Option<u32>,
+    #[serde(flatten)]
+    pub status: ServiceStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompatibilityReport {
+    pub client_version: String,
+    /// No reachable server is incompatible. An unreachable one is reported, but
+    /// is no reason to ask for an update.
+    pub compatible: bool,
+    pub services: Vec<ServiceCompatibility>,
+    /// Oldest client version all the servers accept, when one needs a newer client
+    pub min_required_frontend_version: Option<String>,
+}
+
+// `major.minor.patch`, with an optional `v` prefix and ignoring a pre-release or
+// build suffix
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    let mut parts = version.split('.').map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    Some((major, minor, patch))
+}
+
+/// `None` if one of the versions is not of the form `major.minor.patch`
+pub fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    Some(parse_version(a)?.cmp(&parse_version(b)?))
+}
+
+/// Whether a client of package `client_version` can use the server of `response`
+pub fn check_version(client_version: &str, response: &VersionResponse) -> ServiceStatus {
+    if response.protocol_version > PROTOCOL_VERSION {
+        return ServiceStatus::ClientTooOld {
+            reason: format!(
+                "server protocol {} is newer than the supported {}",
+                response.protocol_version, PROTOCOL_VERSION
+            ),
+        };
+    }
+    if response.protocol_version < MIN_SUPPORTED_PROTOCOL_VERSION {
+        return ServiceStatus::ServerTooOld {
+            reason: format!(
+                "server protocol {} is older than the supported {}",
+                response.protocol_version, MIN_SUPPORTED_PROTOCOL_VERSION
+            ),
+        };
+    }
+    if let Some(min_client_version) = &response.min_client_version {
+        // an unparsable requirement is the server's mistake, not a reason to block
+        if compare_versions(client_version, min_client_version) == Some(Ordering::Less) {
+            return ServiceStatus::ClientTooOld {
+                reason: format!(
+                    "client {} is older than the required {}",
+                    client_version, min_client_version
+                ),
+            };
+        }
+    }
+    ServiceStatus::Compatible
+}
+
+/// Report of the `/version` responses of the servers, given as
+/// `(service, base_url, response)`
+pub fn compatibility_report(
+    client_version: &str,
+    responses: Vec<(&str, &str, Result<VersionResponse, ServerError>)>,
+) -> CompatibilityReport {
+    let mut services = Vec::with_capacity(responses.len());
+    let mut min_required_frontend_version: Option<String> = None;
+    for (service, base_url, response) in responses {
+        let (status, response) = match response {
+            Ok(response) => (check_version(client_version, &response), Some(response)),
+            Err(e) => (
+                ServiceStatus::Unreachable {
+                    error: e.to_string(),
+                },
+                None,
+            ),
+        };
+        if let (ServiceStatus::ClientTooOld { .. }, Some(required)) = (
+            &status,
+            response
+                .as_ref()
+                .and_then(|response| response.min_client_version.as_ref()),
+        ) {
+            let is_higher = min_required_frontend_version.as_ref().map_or(true, |max| {
+                compare_versions(required, max) == Some(Ordering::Greater)
+            });
+            if is_higher {
+                min_required_frontend_version = Some(required.clone());
+            }
+        }
+        services.push(ServiceCompatibility {
+            service: service.to_string(),
+            base_url: base_url.to_string(),
+            server_version: response.as_ref().map(|response| response.version.clone()),
+            protocol_version: response.as_ref().map(|response| response.protocol_version),
+            status,
+        });
+    }
+    CompatibilityReport {
+        client_version: client_version.to_string(),
+        compatible: !services
+            .iter()
+            .any(|service| service.status.is_incompatible()),
+        services,
+        min_required_frontend_version,
+    }
+}
+
+async fn get_version(service: &str, base_url: &str) -> Result<VersionResponse, ServerError> {
+    get_request::<(), _>(
+        &HttpClient::default(),
+        base_url,
+        &format!("/{}/version", service),
+        None,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Ask all the servers of `urls` for their version at once, and compare them with
+/// the client of package `client_version`
+pub async fn check_compatibility(urls: &ServiceUrls, client_version: &str) -> CompatibilityReport {
+    let (store_vault, validity, balance, withdrawal, block_builder) = tokio::join!(
+        get_version("store-vault-server", &urls.store_vault_server),
+        get_version("validity-prover", &urls.validity_prover),
+        get_version("balance-prover", &urls.balance_prover),
+        get_version("withdrawal-server", &urls.withdrawal_server),
+        async {
+            match &urls.block_builder {
+                Some(url) => Some(get_version("block-builder", url).await),
+                None => None,
+            }
+        },
+    );
+    let mut responses = vec![
+        (
+            "store-vault-server",
+            urls.store_vault_server.as_str(),
+            store_vault,
+        ),
+        ("validity-prover", urls.validity_prover.as_str(), validity),
+        ("balance-prover", urls.balance_prover.as_str(), balance),
+        (
+            "withdrawal-server",
+            urls.withdrawal_server.as_str(),
+            withdrawal,
+        ),
+    ];
+    if let (Some(url), Some(block_builder)) = (&urls.block_builder, block_builder) {
+        responses.push(("block-builder", url.as_str(), block_builder));
+    }
+    compatibility_report(client_version, responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(protocol_version: u32, min_client_version: Option<&str>) -> VersionResponse {
+        VersionResponse {
+            service: "validity-prover".to_string(),
+            version: "0.1.0".to_string(),
+            protocol_version,
+            min_client_version: min_client_version.map(|version| version.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_compatible_servers() {
+        let report = compatibility_report(
+            "0.2.0",
+            vec![
+                (
+                    "validity-prover",
+                    "http://a",
+                    Ok(response(PROTOCOL_VERSION, None)),
+                ),
+                (
+                    "balance-prover",
+                    "http://b",
+                    Ok(response(PROTOCOL_VERSION, Some("0.1.5"))),
+                ),
+            ],
+        );
+        assert!(report.compatible);
+        assert!(report
+            .services
+            .iter()
+            .all(|service| service.status == ServiceStatus::Compatible));
+        assert_eq!(report.min_required_frontend_version, None);
+    }
+
+    #[test]
+    fn test_too_old_client() {
+        let report = compatibility_report(
+            "0.1.0",
+            vec![
+                (
+                    "validity-prover",
+                    "http://a",
+                    Ok(response(PROTOCOL_VERSION, Some("0.2.0"))),
+                ),
+                (
+                    "balance-prover",
+                    "http://b",
+                    Ok(response(PROTOCOL_VERSION + 1, Some("0.3.1"))),
+                ),
+                (
+                    "withdrawal-server",
+                    "http://c",
+                    Ok(response(PROTOCOL_VERSION, Some("0.1.0"))),
+                ),
+            ],
+        );
+        assert!(!report.compatible);
+        assert!(matches!(
+            report.services[0].status,
+            ServiceStatus::ClientTooOld { .. }
+        ));
+        assert!(matches!(
+            report.services[1].status,
+            ServiceStatus::ClientTooOld { .. }
+        ));
+        assert_eq!(report.services[2].status, ServiceStatus::Compatible);
+        // the version that satisfies every server
+        assert_eq!(
+            report.min_required_frontend_version.as_deref(),
+            Some("0.3.1")
+        );
+    }
+
+    #[test]
+    fn test_unreachable_service() {
+        let report = compatibility_report(
+            "0.1.0",
+            vec![
+                (
+                    "validity-prover",
+                    "http://a",
+                    Ok(response(PROTOCOL_VERSION, None)),
+                ),
+                (
+                    "balance-prover",
+                    "http://b",
+                    Err(ServerError::NetworkError("connection refused".to_string())),
+                ),
+            ],
+        );
+        // reported, but not a reason to ask for an update
+        assert!(report.compatible);
+        assert!(matches!(
+            report.services[1].status,
+            ServiceStatus::Unreachable { .. }
+        ));
+        assert_eq!(report.services[1].server_version, None);
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("0.1.0", "0.1.0"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("0.1.9", "0.1.10"), Some(Ordering::Less));
+        assert_eq!(compare_versions("v1.2", "1.1.9"), Some(Ordering::Greater));
+        assert_eq!(
+            compare_versions("1.0.0-rc.1", "1.0.0"),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(compare_versions("latest", "1.0.0"), None);
+    }
+}
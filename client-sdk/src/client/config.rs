@@ -1,10 +1,50 @@
 use serde::{Deserialize, Serialize};
 
+use super::withdrawal_onchain::DEFAULT_WITHDRAWAL_ONCHAIN_GRACE;
+
+/// Number of chained actions after which a sync saves a checkpoint in wasm, where
+/// the balance proofs kept until then can exhaust the available memory.
+pub const WASM_CHECKPOINT_INTERVAL: u32 = 25;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ClientConfig {
     pub deposit_timeout: u64,
     pub tx_timeout: u64,
+
+    /// Number of chained actions after which a sync saves its progress to the store
+    /// vault, at the end of the block of the last one. `None` means unlimited: the
+    /// sync is only saved when it ends.
+    #[serde(default = "default_checkpoint_interval")]
+    pub checkpoint_interval: Option<u32>,
+
+    /// Seconds since the block builder's last posted block after which it is
+    /// considered stalled and txs are not sent to it. `None` disables the check.
+    #[serde(default)]
+    pub builder_max_staleness: Option<u64>,
+
+    /// Fail with `InvalidBlockProposal` when the block builder returns a proposal
+    /// that is not for the queried tx, instead of ignoring it and polling again
+    #[serde(default)]
+    pub strict_proposal_validation: bool,
+
+    /// Seconds after a withdrawal request within which a withdrawal the aggregator
+    /// reports as done has to appear on chain, before it is reported as a
+    /// suspected aggregator fault
+    #[serde(default = "default_withdrawal_onchain_grace")]
+    pub withdrawal_onchain_grace: u64,
+}
+
+pub fn default_withdrawal_onchain_grace() -> u64 {
+    DEFAULT_WITHDRAWAL_ONCHAIN_GRACE
+}
+
+pub fn default_checkpoint_interval() -> Option<u32> {
+    if cfg!(target_arch = "wasm32") {
+        Some(WASM_CHECKPOINT_INTERVAL)
+    } else {
+        None
+    }
 }
 
 impl Default for ClientConfig {
@@ -12,6 +52,10 @@ impl Default for ClientConfig {
         Self {
             deposit_timeout: 0,
             tx_timeout: 0,
+            checkpoint_interval: default_checkpoint_interval(),
+            builder_max_staleness: None,
+            strict_proposal_validation: false,
+            withdrawal_onchain_grace: DEFAULT_WITHDRAWAL_ONCHAIN_GRACE,
         }
     }
 }
@@ -0,0 +1,462 @@
+//! Consolidation of an account that received many small transfers. Every incoming
+//! transfer and deposit costs a receive proof when the account syncs. A
+//! consolidation syncs them all, then sends the whole balance of the chosen tokens
+//! to the account itself and syncs the receipt, so that a spend right after does
+//! not wait for the backlog. The self-transfers do not make later syncs cheaper:
+//! they cost prover work of their own, see `ConsolidationPlan::min_prover_calls`.
+
+use std::collections::BTreeMap;
+
+use intmax2_interfaces::api::{
+    balance_prover::interface::BalanceProverClientInterface,
+    block_builder::interface::BlockBuilderClientInterface,
+    store_vault_server::interface::StoreVaultClientInterface,
+    validity_prover::interface::ValidityProverClientInterface,
+    withdrawal_server::interface::{Fee, WithdrawalServerClientInterface},
+};
+use intmax2_zkp::{
+    common::{signature::key_set::KeySet, transfer::Transfer},
+    ethereum_types::{bytes32::Bytes32, u256::U256},
+};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    client::{Client, FeeMode},
+    error::ClientError,
+    fee_quote::{fee_amount, unless_not_exposed},
+    rotation::{
+        chunk_amounts, plan_transfers, send_and_wait_for_inclusion, sync_until_received, MovedToken,
+    },
+    strategy::strategy::{determin_next_action, Action},
+};
+
+/// Default of `ConsolidationOptions::min_pending_receives`
+pub const DEFAULT_MIN_PENDING_RECEIVES: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct ConsolidationOptions {
+    /// Who pays the block builder fee of the self-transfers
+    pub fee_mode: FeeMode,
+    /// Token index and amount kept out of the self-transfers to pay the block
+    /// builder fee with. If None and the fees are self-paid, the fee the block
+    /// builder quotes is kept for every tx.
+    pub fee_reserve: Option<(u32, U256)>,
+    /// Fewest incoming transfers and deposits waiting to be synced for the
+    /// consolidation to be worth its txs
+    pub min_pending_receives: usize,
+    /// Seconds to wait for each proposal, block and the final sync
+    pub timeout: u64,
+    /// Seconds between polls
+    pub interval: u64,
+}
+
+impl Default for ConsolidationOptions {
+    fn default() -> Self {
+        Self {
+            fee_mode: FeeMode::SelfPaid,
+            fee_reserve: None,
+            min_pending_receives: DEFAULT_MIN_PENDING_RECEIVES,
+            timeout: 120,
+            interval: 5,
+        }
+    }
+}
+
+/// What a consolidation will send, computed from the synced account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsolidationPlan {
+    pub pubkey: U256,
+    /// One tx of self-transfers per chunk, sent in order
+    pub chunks: Vec<Vec<Transfer>>,
+    /// Incoming transfers and deposits the sync before the chunks proved
+    pub pending_receives: usize,
+    /// Token index and amount kept out of the chunks for the fees
+    pub fee_reserve: Option<(u32, U256)>,
+    /// Synced balances of the sent tokens before the chunks, which the account has
+    /// again once it received them
+    pub balances_before: BTreeMap<u32, U256>,
+}
+
+impl ConsolidationPlan {
+    /// Total amount per token the chunks send
+    pub fn amounts(&self) -> BTreeMap<u32, U256> {
+        chunk_amounts(&self.chunks)
+    }
+
+    /// Fewest balance prover calls the chunks cost: a spent proof when each tx is
+    /// sent, a send proof when it is synced, and a receive proof per transfer, plus
+    /// an update proof for every block gap the syncs cross
+    pub fn min_prover_calls(&self) -> usize {
+        self.chunks.iter().map(|chunk| 2 + chunk.len()).sum()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsolidationReport {
+    pub pubkey: U256,
+    pub consolidated: Vec<MovedToken>,
+    /// Incoming transfers and deposits synced before the self-transfers
+    pub absorbed_receives: usize,
+    /// Incoming transfers and deposits left for the next sync, i.e. those received
+    /// while the consolidation ran
+    pub remaining_receives: usize,
+    /// Balances after the receipt of the self-transfers
+    pub balances: BTreeMap<u32, U256>,
+}
+
+/// Self-transfers of the whole spendable balance of `token_indices` (all tokens if
+/// empty), less the `fee_reserve`, in txs of up to `NUM_TRANSFERS_IN_TX` transfers.
+/// `balances` are (token index, amount, is insufficient).
+pub fn plan_self_transfers(
+    balances: &[(u32, U256, bool)],
+    pubkey: U256,
+    token_indices: &[u32],
+    fee_reserve: Option<(u32, U256)>,
+) -> Vec<Vec<Transfer>> {
+    let balances = balances
+        .iter()
+        .filter(|(token_index, _, _)| {
+            token_indices.is_empty() || token_indices.contains(token_index)
+        })
+        .map(
+            |&(token_index, amount, is_insufficient)| match fee_reserve {
+                Some((fee_token_index, reserve)) if fee_token_index == token_index => {
+                    let amount = BigUint::from(amount);
+                    let reserve = BigUint::from(reserve);
+                    let rest = if amount > reserve {
+                        (amount - reserve).try_into().unwrap()
+                    } else {
+                        U256::default()
+                    };
+                    (token_index, rest, is_insufficient)
+                }
+                _ => (token_index, amount, is_insufficient),
+            },
+        )
+        .collect::<Vec<_>>();
+    // insufficient balances cannot be spent, and are not sent
+    let (chunks, _) = plan_transfers(&balances, pubkey, U256::default());
+    chunks
+}
+
+/// Fee kept for `num_txs` txs when the account pays them itself: the fee of the
+/// native token if the block builder quotes it, else of the first token it quotes,
+/// on the whole balance of that token. None if it quotes no fee.
+pub fn quoted_fee_reserve(
+    fees: &[Fee],
+    balances: &[(u32, U256, bool)],
+    num_txs: usize,
+) -> Result<Option<(u32, U256)>, ClientError> {
+    let Some(fee) = fees
+        .iter()
+        .find(|fee| fee.token_index == 0)
+        .or_else(|| fees.first())
+    else {
+        return Ok(None);
+    };
+    let balance = balances
+        .iter()
+        .find(|(token_index, _, _)| *token_index == fee.token_index)
+        .map(|(_, amount, _)| *amount)
+        .unwrap_or_default();
+    let reserve = BigUint::from(fee_amount(fee, balance)?) * num_txs;
+    let reserve = reserve.try_into().map_err(|_| {
+        ClientError::ConsolidationError(format!(
+            "the fee reserve of token {} does not fit in 256 bits",
+            fee.token_index
+        ))
+    })?;
+    Ok(Some((fee.token_index, reserve)))
+}
+
+/// Number of settled incoming transfers and deposits that the next sync of `key`
+/// proves
+pub async fn count_pending_receives<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    key: KeySet,
+) -> Result<usize, ClientError> {
+    let next_action = determin_next_action(
+        &client.store_vault_server,
+        &client.validity_prover,
+        &client.liquidity_contract,
+        key,
+        client.config.deposit_timeout,
+        client.config.tx_timeout,
+    )
+    .await?;
+    Ok(next_action
+        .all_actions
+        .iter()
+        .filter(|action| matches!(action, Action::Deposit(..) | Action::Transfer(..)))
+        .count())
+}
+
+/// Check that the account has enough to absorb, sync it and plan the self-transfers
+/// of `token_indices` (all tokens if empty), keeping the fees of `block_builder_url`
+/// out of them
+pub async fn plan_consolidation<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    block_builder_url: &str,
+    key: KeySet,
+    token_indices: &[u32],
+    options: &ConsolidationOptions,
+) -> Result<ConsolidationPlan, ClientError> {
+    let pending_receives = count_pending_receives(client, key).await?;
+    if pending_receives == 0 {
+        return Err(ClientError::ConsolidationError(
+            "no incoming transfers or deposits are pending".to_string(),
+        ));
+    }
+    if pending_receives < options.min_pending_receives {
+        return Err(ClientError::ConsolidationError(format!(
+            "the balance is already compact: {} incoming transfers or deposits pending, \
+             fewer than {}",
+            pending_receives, options.min_pending_receives
+        )));
+    }
+    client.sync(key).await?;
+    let balances = client
+        .get_user_data(key)
+        .await?
+        .balances()
+        .iter()
+        .map(|(token_index, leaf)| (*token_index as u32, leaf.amount, leaf.is_insufficient))
+        .collect::<Vec<_>>();
+    let fee_reserve = match (&options.fee_mode, options.fee_reserve) {
+        (FeeMode::SelfPaid, None) => {
+            let fees =
+                unless_not_exposed(client.block_builder.get_fee_info(block_builder_url).await)?
+                    .unwrap_or_default();
+            // the reserve only shortens the chunks, so their count without it is an
+            // upper bound
+            let num_txs = plan_self_transfers(&balances, key.pubkey, token_indices, None).len();
+            quoted_fee_reserve(&fees, &balances, num_txs)?
+        }
+        (_, fee_reserve) => fee_reserve,
+    };
+    let chunks = plan_self_transfers(&balances, key.pubkey, token_indices, fee_reserve);
+    if chunks.is_empty() {
+        return Err(ClientError::ConsolidationError(
+            "no spendable balance of the given tokens".to_string(),
+        ));
+    }
+    let amounts = chunk_amounts(&chunks);
+    let balances_before = balances
+        .iter()
+        .filter(|(token_index, _, _)| amounts.contains_key(token_index))
+        .map(|&(token_index, amount, _)| (token_index, amount))
+        .collect();
+    Ok(ConsolidationPlan {
+        pubkey: key.pubkey,
+        chunks,
+        pending_receives,
+        fee_reserve,
+        balances_before,
+    })
+}
+
+/// Send every chunk of `plan`, then sync until the account has its balances from
+/// before the chunks again, i.e. received every self-transfer
+pub async fn complete_consolidation<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    block_builder_url: &str,
+    key: KeySet,
+    plan: &ConsolidationPlan,
+    options: &ConsolidationOptions,
+) -> Result<ConsolidationReport, ClientError> {
+    if key.pubkey != plan.pubkey {
+        return Err(ClientError::ConsolidationError(format!(
+            "the plan is of {}, not {}",
+            plan.pubkey, key.pubkey
+        )));
+    }
+    let mut tx_tree_roots = Vec::<Bytes32>::new();
+    for (i, chunk) in plan.chunks.iter().enumerate() {
+        log::info!("consolidation tx {}/{}", i + 1, plan.chunks.len());
+        let tx_tree_root = send_and_wait_for_inclusion(
+            client,
+            block_builder_url,
+            key,
+            chunk.clone(),
+            options.fee_mode.clone(),
+            options.timeout,
+            options.interval,
+        )
+        .await?;
+        tx_tree_roots.push(tx_tree_root);
+    }
+    let balances = sync_until_received(
+        client,
+        key,
+        &plan.balances_before,
+        options.timeout,
+        options.interval,
+    )
+    .await?;
+    let remaining_receives = count_pending_receives(client, key).await?;
+
+    let consolidated = plan
+        .chunks
+        .iter()
+        .zip(tx_tree_roots)
+        .flat_map(|(chunk, tx_tree_root)| {
+            chunk.iter().map(move |transfer| MovedToken {
+                token_index: transfer.token_index,
+                amount: transfer.amount,
+                tx_tree_root,
+            })
+        })
+        .collect();
+    Ok(ConsolidationReport {
+        pubkey: key.pubkey,
+        consolidated,
+        absorbed_receives: plan.pending_receives,
+        remaining_receives,
+        balances,
+    })
+}
+
+/// Plan and run the consolidation of `token_indices` (all tokens if empty)
+pub async fn consolidate<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    block_builder_url: &str,
+    key: KeySet,
+    token_indices: &[u32],
+    options: &ConsolidationOptions,
+) -> Result<ConsolidationReport, ClientError> {
+    let plan = plan_consolidation(client, block_builder_url, key, token_indices, options).await?;
+    complete_consolidation(client, block_builder_url, key, &plan, options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{
+        common::generic_address::GenericAddress, constants::NUM_TRANSFERS_IN_TX,
+        ethereum_types::u32limb_trait::U32LimbTrait as _,
+    };
+
+    use super::*;
+
+    fn value(value: u32) -> U256 {
+        U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, value])
+    }
+
+    #[test]
+    fn test_plan_self_transfers() {
+        let pubkey = value(7);
+        let balances = vec![
+            (0, value(1000), false),
+            (1, value(500), false),
+            (2, value(40), true),
+            (3, value(3), false),
+        ];
+
+        // all tokens, with the fee kept out of token 0
+        let chunks = plan_self_transfers(&balances, pubkey, &[], Some((0, value(10))));
+        assert_eq!(chunks.len(), 1);
+        let sent = chunks[0]
+            .iter()
+            .map(|transfer| (transfer.token_index, transfer.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(sent, vec![(0, value(990)), (1, value(500)), (3, value(3))]);
+        assert!(chunks[0]
+            .iter()
+            .all(|transfer| transfer.recipient == GenericAddress::from_pubkey(pubkey)));
+
+        // only the given tokens, and nothing if the fee takes the whole balance
+        let chunks = plan_self_transfers(&balances, pubkey, &[1, 3], Some((3, value(3))));
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].len(), 1);
+        assert_eq!(chunks[0][0].token_index, 1);
+        assert!(plan_self_transfers(&balances, pubkey, &[2], None).is_empty());
+    }
+
+    #[test]
+    fn test_plan_self_transfers_chunks() {
+        let balances = (0..2 * NUM_TRANSFERS_IN_TX as u32 + 1)
+            .map(|token_index| (token_index, value(1), false))
+            .collect::<Vec<_>>();
+        let chunks = plan_self_transfers(&balances, value(7), &[], None);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[..2]
+            .iter()
+            .all(|chunk| chunk.len() == NUM_TRANSFERS_IN_TX));
+        assert_eq!(chunks[2].len(), 1);
+        let amounts = chunk_amounts(&chunks);
+        assert_eq!(amounts.len(), 2 * NUM_TRANSFERS_IN_TX + 1);
+    }
+
+    #[test]
+    fn test_min_prover_calls() {
+        let balances = (0..NUM_TRANSFERS_IN_TX as u32 + 2)
+            .map(|token_index| (token_index, value(1), false))
+            .collect::<Vec<_>>();
+        let chunks = plan_self_transfers(&balances, value(7), &[], None);
+        let plan = ConsolidationPlan {
+            pubkey: value(7),
+            chunks,
+            pending_receives: 8,
+            fee_reserve: None,
+            balances_before: BTreeMap::new(),
+        };
+        // two txs, each proved spent and sent, and every transfer received
+        assert_eq!(plan.min_prover_calls(), 2 * 2 + NUM_TRANSFERS_IN_TX + 2);
+        // the consolidation costs more proofs than the transfers it absorbs
+        assert!(plan.min_prover_calls() > plan.pending_receives);
+    }
+
+    #[test]
+    fn test_quoted_fee_reserve() {
+        let balances = vec![(0, value(1000), false), (1, value(500), false)];
+        let native = Fee {
+            token_index: 0,
+            constant: 10,
+            coefficient: 0.0,
+        };
+        let other = Fee {
+            token_index: 1,
+            constant: 3,
+            coefficient: 0.01,
+        };
+
+        // the native token is preferred, and the fee is kept for every tx
+        let reserve = quoted_fee_reserve(&[other.clone(), native], &balances, 3).unwrap();
+        assert_eq!(reserve, Some((0, value(30))));
+        // else the first quoted token, with its fee on its balance
+        let reserve = quoted_fee_reserve(&[other], &balances, 2).unwrap();
+        assert_eq!(reserve, Some((1, value(16))));
+        // a builder without fees reserves nothing
+        assert_eq!(quoted_fee_reserve(&[], &balances, 2).unwrap(), None);
+
+        // the reserve is kept out of the self-transfers
+        let chunks = plan_self_transfers(&balances, value(7), &[], Some((0, value(30))));
+        assert_eq!(chunk_amounts(&chunks)[&0], value(970));
+    }
+}
@@ -0,0 +1,249 @@
+//! Numbers support needs to triage a stuck account, put side by side with what the
+//! validity prover knows of the account, so that a disagreement shows in one place.
+
+use intmax2_interfaces::{
+    api::{
+        balance_prover::interface::BalanceProverClientInterface,
+        block_builder::interface::BlockBuilderClientInterface,
+        store_vault_server::interface::StoreVaultClientInterface,
+        validity_prover::interface::{AccountInfo, ValidityProverClientInterface},
+        withdrawal_server::interface::WithdrawalServerClientInterface,
+    },
+    data::user_data::UserData,
+};
+use intmax2_zkp::{
+    circuits::balance::balance_pis::BalancePublicInputs, common::signature::key_set::KeySet,
+    ethereum_types::u256::U256, utils::poseidon_hash_out::PoseidonHashOut,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{client::Client, error::ClientError};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessedCounts {
+    pub deposits: usize,
+    pub transfers: usize,
+    pub txs: usize,
+    pub withdrawals: usize,
+}
+
+impl ProcessedCounts {
+    pub fn of(user_data: &UserData) -> Self {
+        Self {
+            deposits: user_data.processed_deposit_uuids.len(),
+            transfers: user_data.processed_transfer_uuids.len(),
+            txs: user_data.processed_tx_uuids.len(),
+            withdrawals: user_data.processed_withdrawal_uuids.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiagnostics {
+    pub pubkey: U256,
+    /// Nonce of the private state, i.e. the number of own txs incorporated
+    pub nonce: u32,
+    /// Block number of the user data
+    pub block_number: u32,
+    /// Block number of the balance proof of the private state, `None` if there is
+    /// no such proof in the store vault
+    pub last_proof_block_number: Option<u32>,
+    pub private_commitment: PoseidonHashOut,
+    pub processed: ProcessedCounts,
+    /// Account id in the account tree, `None` until the first tx is included
+    pub account_id: Option<u64>,
+    /// Last block the validity prover has processed
+    pub validity_prover_block_number: u32,
+    /// Disagreements between the numbers above
+    pub mismatches: Vec<String>,
+}
+
+/// Diagnostics of `user_data`, whose stored balance proof has `balance_pis`, as the
+/// validity prover sees the account in `account_info`
+pub fn account_diagnostics(
+    user_data: &UserData,
+    balance_pis: Option<&BalancePublicInputs>,
+    account_info: &AccountInfo,
+) -> AccountDiagnostics {
+    let nonce = user_data.full_private_state.nonce;
+    let private_commitment = user_data.private_commitment();
+    let mut mismatches = Vec::new();
+    match balance_pis {
+        Some(pis) => {
+            if pis.public_state.block_number != user_data.block_number {
+                mismatches.push(format!(
+                    "balance proof is of block {}, but the user data is of block {}",
+                    pis.public_state.block_number, user_data.block_number
+                ));
+            }
+            if pis.private_commitment != private_commitment {
+                mismatches.push(
+                    "balance proof is of another private commitment than the user data".to_string(),
+                );
+            }
+        }
+        // an account that has processed nothing has no balance proof yet
+        None if user_data.block_number > 0 => mismatches.push(format!(
+            "no balance proof of the private state at block {}",
+            user_data.block_number
+        )),
+        None => {}
+    }
+    if user_data.block_number > account_info.block_number {
+        mismatches.push(format!(
+            "user data is of block {}, ahead of the validity prover at block {}",
+            user_data.block_number, account_info.block_number
+        ));
+    }
+    if nonce > 0 && account_info.account_id.is_none() {
+        mismatches.push(format!(
+            "{} txs are incorporated, but the validity prover has no account id",
+            nonce
+        ));
+    }
+    AccountDiagnostics {
+        pubkey: user_data.pubkey,
+        nonce,
+        block_number: user_data.block_number,
+        last_proof_block_number: balance_pis.map(|pis| pis.public_state.block_number),
+        private_commitment,
+        processed: ProcessedCounts::of(user_data),
+        account_id: account_info.account_id,
+        validity_prover_block_number: account_info.block_number,
+        mismatches,
+    }
+}
+
+/// Block number of the stored balance proof of the private state of `user_data`,
+/// `None` if there is none
+pub async fn fetch_last_proof_block_number<S: StoreVaultClientInterface>(
+    store_vault_server: &S,
+    user_data: &UserData,
+) -> Result<Option<u32>, ClientError> {
+    let balance_proof = store_vault_server
+        .get_balance_proof(
+            user_data.pubkey,
+            user_data.block_number,
+            user_data.private_commitment(),
+        )
+        .await?;
+    Ok(balance_proof.map(|proof| {
+        BalancePublicInputs::from_pis(&proof.public_inputs)
+            .public_state
+            .block_number
+    }))
+}
+
+pub async fn get_diagnostics<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    key: KeySet,
+) -> Result<AccountDiagnostics, ClientError> {
+    let user_data = client.get_user_data(key).await?;
+    let balance_proof = client
+        .store_vault_server
+        .get_balance_proof(
+            key.pubkey,
+            user_data.block_number,
+            user_data.private_commitment(),
+        )
+        .await?;
+    let balance_pis = balance_proof
+        .as_ref()
+        .map(|proof| BalancePublicInputs::from_pis(&proof.public_inputs));
+    let account_info = client.validity_prover.get_account_info(key.pubkey).await?;
+    Ok(account_diagnostics(
+        &user_data,
+        balance_pis.as_ref(),
+        &account_info,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::utils::generate_salt;
+
+    // the pis of the balance proof the sync would store for `user_data`
+    fn balance_pis_of(user_data: &UserData) -> BalancePublicInputs {
+        let mut pis = BalancePublicInputs::new(user_data.pubkey);
+        pis.public_state.block_number = user_data.block_number;
+        pis.private_commitment = user_data.private_commitment();
+        pis
+    }
+
+    #[test]
+    fn test_diagnostics_follow_deposit_sync_send() {
+        let mut rng = rand::thread_rng();
+        let key = KeySet::rand(&mut rng);
+        let mut user_data = UserData::new(key.pubkey);
+        let unregistered = AccountInfo {
+            account_id: None,
+            block_number: 3,
+        };
+
+        let initial = account_diagnostics(&user_data, None, &unregistered);
+        assert_eq!(initial.nonce, 0);
+        assert_eq!(initial.last_proof_block_number, None);
+        assert_eq!(initial.processed, ProcessedCounts::default());
+        assert!(initial.mismatches.is_empty());
+
+        // a deposit synced in block 2 changes the commitment, not the nonce
+        user_data.block_number = 2;
+        user_data.full_private_state.salt = generate_salt();
+        user_data
+            .processed_deposit_uuids
+            .push("deposit".to_string());
+        let deposited =
+            account_diagnostics(&user_data, Some(&balance_pis_of(&user_data)), &unregistered);
+        assert_eq!(deposited.nonce, 0);
+        assert_eq!(deposited.last_proof_block_number, Some(2));
+        assert_ne!(deposited.private_commitment, initial.private_commitment);
+        assert_eq!(deposited.processed.deposits, 1);
+        assert!(deposited.mismatches.is_empty());
+
+        // a tx synced in block 3 increments the nonce and registers the account
+        user_data.block_number = 3;
+        user_data.full_private_state.nonce += 1;
+        user_data.processed_tx_uuids.push("tx".to_string());
+        let registered = AccountInfo {
+            account_id: Some(2),
+            block_number: 3,
+        };
+        let sent = account_diagnostics(&user_data, Some(&balance_pis_of(&user_data)), &registered);
+        assert_eq!(sent.nonce, 1);
+        assert_eq!(sent.last_proof_block_number, Some(3));
+        assert_ne!(sent.private_commitment, deposited.private_commitment);
+        assert_eq!(sent.processed.txs, 1);
+        assert_eq!(sent.account_id, Some(2));
+        assert!(sent.mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_diagnostics_report_mismatches() {
+        let mut rng = rand::thread_rng();
+        let key = KeySet::rand(&mut rng);
+        let mut user_data = UserData::new(key.pubkey);
+        user_data.block_number = 5;
+        user_data.full_private_state.nonce = 1;
+        let stale_pis = balance_pis_of(&user_data);
+        user_data.full_private_state.salt = generate_salt();
+        let lagging = AccountInfo {
+            account_id: None,
+            block_number: 4,
+        };
+
+        let diagnostics = account_diagnostics(&user_data, Some(&stale_pis), &lagging);
+        // another commitment, ahead of the validity prover, not registered
+        assert_eq!(diagnostics.mismatches.len(), 3);
+        let missing_proof = account_diagnostics(&user_data, None, &lagging);
+        assert!(missing_proof.mismatches[0].contains("no balance proof"));
+    }
+}
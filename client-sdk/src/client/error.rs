@@ -2,6 +2,8 @@ use intmax2_interfaces::api::error::ServerError;
 
 use crate::external_api::contract::interface::BlockchainError;
 
+use super::{observer::KeyedData, receipt::ReceiptError, witness_bundle::WitnessBundleError};
+
 #[derive(Debug, thiserror::Error)]
 pub enum ClientError {
     #[error("Server error: {0}")]
@@ -28,9 +30,101 @@ pub enum ClientError {
     #[error("Invalid block proposal: {0}")]
     InvalidBlockProposal(String),
 
+    /// A balance or update proof, or the witness it was proved from, does not match
+    /// the state it is supposed to prove
+    #[error("Invalid proof: {0}")]
+    InvalidProof(String),
+
+    #[error("Signer error: {0}")]
+    SignerError(String),
+
+    #[error("Invalid deposit proof for deposit index {deposit_index}: {reason}")]
+    InvalidDepositProof { deposit_index: u32, reason: String },
+
+    #[error("Invalid block merkle proof of block {leaf_block_number} in block {root_block_number}: {reason}")]
+    InvalidBlockMerkleProof {
+        root_block_number: u32,
+        leaf_block_number: u32,
+        reason: String,
+    },
+
+    #[error(
+        "Invalid nonce: requested {0}, but the private state is at nonce {1}, the only valid one"
+    )]
+    InvalidNonce(u32, u32),
+
+    #[error("Invalid claim deadline: block {0} is not after the latest block {1}")]
+    InvalidClaimDeadline(u32, u32),
+
+    #[error("Limit violated: {limit} is {value}, got {actual}")]
+    LimitViolation {
+        limit: String,
+        value: String,
+        actual: String,
+    },
+
+    #[error("Block builder is unhealthy: {detail}")]
+    BuilderUnhealthy { detail: String },
+
+    #[error("Token sync blocked by {blocking_action}, a full sync is required")]
+    RequiresFullSync { blocking_action: String },
+
+    #[error("Balance of token {token} is {proved} in the proved user data, but {replayed} in the replayed history")]
+    BalanceMismatch {
+        token: u32,
+        proved: String,
+        replayed: String,
+    },
+
+    #[error("Invalid template {name}: {reason}")]
+    InvalidTemplate { name: String, reason: String },
+
+    #[error("Invalid amount for token index {token_index:?}: {reason}")]
+    InvalidTokenAmount {
+        token_index: Option<u32>,
+        reason: String,
+    },
+
+    #[error("Token index {token_index} cannot be deposited: {reason}")]
+    UnsupportedToken { token_index: u32, reason: String },
+
+    #[error("Invalid attribution tag: {0}")]
+    InvalidAttributionTag(String),
+
+    #[error("Receipt error: {0}")]
+    ReceiptError(#[from] ReceiptError),
+
+    #[error("Deposit not found: {0}")]
+    DepositNotFound(String),
+
+    #[error("Deposit {deposit_id} is not to this key with the given salt")]
+    DepositRecipientMismatch { deposit_id: u64 },
+
+    #[error("Deposit {0} is already imported")]
+    DepositAlreadyImported(u64),
+
+    #[error("Key rotation error: {0}")]
+    RotationError(String),
+
+    #[error("Consolidation error: {0}")]
+    ConsolidationError(String),
+
     #[error("Pending error: {0}")]
     PendingError(String),
 
+    #[error("Tx {0} is already in a block proposal and can no longer be cancelled, finalize it or let it expire")]
+    TxAlreadyProposed(String),
+
+    #[error("Invalid mnemonic: {0}")]
+    InvalidMnemonic(String),
+
     #[error("Unexpected error: {0}")]
     UnexpectedError(String),
+
+    #[error("Witness bundle error: {0}")]
+    WitnessBundleError(#[from] WitnessBundleError),
+
+    /// Asked a watch-only observer for data encrypted to the key of the account
+    #[error("{0} cannot be observed without the key of the account")]
+    RequiresKey(KeyedData),
 }
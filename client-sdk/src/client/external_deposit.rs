@@ -0,0 +1,236 @@
+//! Deposits made by someone else to a pubkey salt hash the recipient generated,
+//! e.g. a parent depositing to their child's account. The depositor only learns
+//! the hash, so the recipient has no deposit backup until it imports the deposit.
+
+use std::str::FromStr;
+
+use ethers::types::H256;
+use intmax2_interfaces::{
+    api::{
+        balance_prover::interface::BalanceProverClientInterface,
+        block_builder::interface::BlockBuilderClientInterface,
+        store_vault_server::interface::{DataType, StoreVaultClientInterface},
+        validity_prover::interface::ValidityProverClientInterface,
+        withdrawal_server::interface::WithdrawalServerClientInterface,
+    },
+    data::deposit_data::{DepositData, TokenType},
+};
+use intmax2_zkp::{
+    common::{deposit::get_pubkey_salt_hash, salt::Salt, signature::key_set::KeySet},
+    ethereum_types::{address::Address, bytes32::Bytes32, u256::U256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::contract::liquidity_contract::OnchainDeposit;
+
+use super::{
+    client::Client, error::ClientError, strategy::decrypt::decrypt_blobs, utils::generate_salt,
+};
+
+/// Generated by the recipient. Only `pubkey_salt_hash` is given to the depositor,
+/// the salt is kept to claim the deposit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepositRequest {
+    pub pubkey_salt_hash: Bytes32,
+    pub salt: Salt,
+}
+
+pub fn generate_deposit_request(pubkey: U256) -> DepositRequest {
+    let salt = generate_salt();
+    DepositRequest {
+        pubkey_salt_hash: get_pubkey_salt_hash(pubkey, salt),
+        salt,
+    }
+}
+
+/// How the depositor identified the deposit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepositSource {
+    TxHash(H256),
+    DepositId(u64),
+}
+
+impl FromStr for DepositSource {
+    type Err = String;
+
+    /// A 32-byte hex L1 tx hash, or a decimal deposit id
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("0x") {
+            let tx_hash = H256::from_str(s).map_err(|_| format!("invalid L1 tx hash: {}", s))?;
+            return Ok(Self::TxHash(tx_hash));
+        }
+        s.parse::<u64>()
+            .map(Self::DepositId)
+            .map_err(|_| format!("expected an L1 tx hash or a deposit id, got {}", s))
+    }
+}
+
+/// The backup of `deposit` for the recipient `pubkey`, who generated the pubkey
+/// salt hash with `salt`. The token is given by the contract for the token index.
+pub fn external_deposit_data(
+    pubkey: U256,
+    salt: Salt,
+    deposit: &OnchainDeposit,
+    token: (TokenType, Address, U256),
+) -> Result<DepositData, ClientError> {
+    if get_pubkey_salt_hash(pubkey, salt) != deposit.pubkey_salt_hash {
+        return Err(ClientError::DepositRecipientMismatch {
+            deposit_id: deposit.deposit_id,
+        });
+    }
+    let (token_type, token_address, token_id) = token;
+    Ok(DepositData {
+        deposit_salt: salt,
+        pubkey_salt_hash: deposit.pubkey_salt_hash,
+        amount: deposit.amount,
+        token_type,
+        token_address,
+        token_id,
+        token_index: Some(deposit.token_index),
+    })
+}
+
+/// Check the on-chain deposit given by `source` against `key` and `salt`, and save
+/// the backup the depositor could not make, so that the next sync includes it
+pub async fn claim_external_deposit<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    key: KeySet,
+    salt: Salt,
+    source: DepositSource,
+) -> Result<DepositData, ClientError> {
+    let pubkey_salt_hash = get_pubkey_salt_hash(key.pubkey, salt);
+    let deposit = match source {
+        DepositSource::DepositId(deposit_id) => client
+            .liquidity_contract
+            .get_deposit(deposit_id)
+            .await?
+            .ok_or_else(|| ClientError::DepositNotFound(deposit_id.to_string()))?,
+        DepositSource::TxHash(tx_hash) => {
+            let deposits = client
+                .liquidity_contract
+                .get_deposits_in_tx(tx_hash)
+                .await?;
+            // a tx may deposit to several recipients
+            match deposits
+                .iter()
+                .find(|deposit| deposit.pubkey_salt_hash == pubkey_salt_hash)
+            {
+                Some(deposit) => deposit.clone(),
+                // rejected below as not to this key
+                None => match deposits.first() {
+                    Some(deposit) => deposit.clone(),
+                    None => return Err(ClientError::DepositNotFound(format!("{:?}", tx_hash))),
+                },
+            }
+        }
+    };
+    let token = client
+        .liquidity_contract
+        .get_token_info(deposit.token_index)
+        .await?;
+    let deposit_data = external_deposit_data(key.pubkey, salt, &deposit, token)?;
+
+    let existing = client
+        .store_vault_server
+        .get_data_all_after(DataType::Deposit, key.pubkey, 0)
+        .await?;
+    let already_imported = decrypt_blobs(existing, |data| DepositData::decrypt(data, key))
+        .into_iter()
+        .filter_map(|(_, decrypted)| decrypted.ok())
+        .any(|existing| {
+            existing.pubkey_salt_hash == pubkey_salt_hash
+                && existing.amount == deposit_data.amount
+                && existing.token_address == deposit_data.token_address
+                && existing.token_id == deposit_data.token_id
+        });
+    if already_imported {
+        return Err(ClientError::DepositAlreadyImported(deposit.deposit_id));
+    }
+    log::info!(
+        "importing external deposit {} with hash {}",
+        deposit.deposit_id,
+        deposit_data.deposit_hash().unwrap()
+    );
+    client
+        .store_vault_server
+        .save_data(
+            DataType::Deposit,
+            key.pubkey,
+            &deposit_data.encrypt(key.pubkey),
+        )
+        .await?;
+    Ok(deposit_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::Address as EthAddress;
+    use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+
+    use super::*;
+
+    fn deposit_to(pubkey_salt_hash: Bytes32) -> OnchainDeposit {
+        // as read from the event of a deposit made directly on the contract
+        OnchainDeposit {
+            deposit_id: 7,
+            sender: EthAddress::repeat_byte(1),
+            pubkey_salt_hash,
+            token_index: 0,
+            amount: U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, 100]),
+            deposited_at: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_external_deposit_is_claimed_with_the_salt() {
+        let mut rng = rand::thread_rng();
+        let key = KeySet::rand(&mut rng);
+        let request = generate_deposit_request(key.pubkey);
+        let deposit = deposit_to(request.pubkey_salt_hash);
+        let token = (TokenType::NATIVE, Address::default(), U256::default());
+
+        let data = external_deposit_data(key.pubkey, request.salt, &deposit, token).unwrap();
+        assert_eq!(data.amount, deposit.amount);
+        assert_eq!(data.token_index, Some(0));
+        // the backup is one the recipient can decrypt and sync
+        let decrypted = DepositData::decrypt(&data.encrypt(key.pubkey), key).unwrap();
+        assert_eq!(decrypted.deposit_hash(), data.deposit_hash());
+    }
+
+    #[test]
+    fn test_external_deposit_with_wrong_salt_is_rejected() {
+        let mut rng = rand::thread_rng();
+        let key = KeySet::rand(&mut rng);
+        let request = generate_deposit_request(key.pubkey);
+        let deposit = deposit_to(request.pubkey_salt_hash);
+        let token = (TokenType::NATIVE, Address::default(), U256::default());
+
+        let result = external_deposit_data(key.pubkey, generate_salt(), &deposit, token);
+        assert!(matches!(
+            result,
+            Err(ClientError::DepositRecipientMismatch { deposit_id: 7 })
+        ));
+        // nor with the salt but another key
+        let other = KeySet::rand(&mut rng);
+        assert!(external_deposit_data(other.pubkey, request.salt, &deposit, token).is_err());
+    }
+
+    #[test]
+    fn test_parse_deposit_source() {
+        assert_eq!("42".parse(), Ok(DepositSource::DepositId(42)));
+        let tx_hash = format!("0x{}", "ab".repeat(32));
+        assert_eq!(
+            tx_hash.parse(),
+            Ok(DepositSource::TxHash(H256::repeat_byte(0xab)))
+        );
+        assert!("0x1234".parse::<DepositSource>().is_err());
+        assert!("latest".parse::<DepositSource>().is_err());
+    }
+}
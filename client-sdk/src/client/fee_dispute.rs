@@ -0,0 +1,473 @@
+use intmax2_interfaces::{
+    api::{
+        block_builder::interface::ProposalAttestation,
+        error::ServerError,
+        store_vault_server::interface::{DataType, StoreVaultClientInterface},
+        validity_prover::interface::ValidityProverClientInterface,
+    },
+    data::tx_data::TxData,
+};
+use intmax2_zkp::{
+    common::{
+        block_builder::BlockProposal,
+        signature::key_set::KeySet,
+        transfer::Transfer,
+        trees::transfer_tree::{TransferMerkleProof, TransferTree},
+        tx::Tx,
+    },
+    constants::TRANSFER_TREE_HEIGHT,
+    ethereum_types::{bytes32::Bytes32, u256::U256},
+};
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::{block_builder::verify_proposal_attestation, utils::clock::global_clock};
+
+use super::{
+    error::ClientError,
+    strategy::decrypt::decrypt_blobs,
+    tx_status::{classify_tx_status, TxStatus},
+};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Outcome of the block builder fee of a tx
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FeeStatus {
+    /// The block of the tx is not posted yet, and may still be
+    Pending,
+    /// The block was posted, so the builder earned the fee
+    Settled,
+    /// The block was never posted. The builder is owed nothing, and a fee it
+    /// collected anyway has to be refunded, see `list_fee_disputes`.
+    Unsettled,
+}
+
+impl From<TxStatus> for FeeStatus {
+    fn from(status: TxStatus) -> Self {
+        match status {
+            TxStatus::Pending => FeeStatus::Pending,
+            TxStatus::Included { .. } => FeeStatus::Settled,
+            TxStatus::Expired => FeeStatus::Unsettled,
+        }
+    }
+}
+
+/// Check that the tx tree root of the proposal was not in any block when the tx
+/// timeout had passed, after which the block builder can no longer post it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NonInclusionCheck {
+    /// When the tx was backed up, right before the proposal was signed
+    pub finalized_at: u64,
+    pub tx_timeout: u64,
+    pub checked_at: u64,
+    /// Latest block of the validity prover at `checked_at`. None of the blocks up
+    /// to it has the tx tree root.
+    pub latest_block_number: u32,
+}
+
+impl NonInclusionCheck {
+    pub fn expired_at(&self) -> u64 {
+        self.finalized_at + self.tx_timeout
+    }
+}
+
+/// Evidence that the sender paid a fee to a block builder that did not post the
+/// block, to send to the builder operator. It links the fee transfer to the tx,
+/// the tx to the proposal of the builder, and carries the builder's attestation
+/// of the proposal, which the sender cannot forge.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeDispute {
+    pub tx_uuid: String,
+    pub tx_hash: Bytes32,
+    pub block_builder_url: String,
+    pub sender: U256,
+    pub fee: Transfer,
+    pub fee_transfer_index: u32,
+    pub fee_transfer_merkle_proof: TransferMerkleProof,
+    pub tx: Tx,
+    pub proposal: BlockProposal,
+    /// Signature of the proposal by the block builder
+    pub attestation: ProposalAttestation,
+    pub non_inclusion: NonInclusionCheck,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FeeDisputeError {
+    #[error("Tx hash {dispute} is not the hash {actual} of the tx")]
+    TxHashMismatch { dispute: Bytes32, actual: Bytes32 },
+
+    #[error("Fee transfer is not in the tx: {0}")]
+    InvalidFeeProof(String),
+
+    #[error("Proposal is not for the tx: {0}")]
+    InvalidProposal(String),
+
+    #[error("Attestation of the proposal does not verify: {0}")]
+    InvalidAttestation(String),
+
+    #[error("Checked at {checked_at}, before the tx expired at {expired_at}")]
+    NotExpired { checked_at: u64, expired_at: u64 },
+
+    #[error("Tx tree root {0} is in block {1}")]
+    Included(Bytes32, u32),
+
+    #[error("Server error: {0}")]
+    ServerError(#[from] ServerError),
+}
+
+/// Check the document of `dispute`: the fee transfer is in the tx, the tx is in
+/// the proposal at the sender's index, the block builder attested the proposal,
+/// and the non-inclusion check was made after the tx expired. The operator checks
+/// that `attestation.block_builder` is the address of its builder. Whether the tx tree root
+/// is really missing from the rollup is checked by `check_non_inclusion`.
+pub fn verify_fee_dispute(dispute: &FeeDispute) -> Result<(), FeeDisputeError> {
+    let tx_hash: Bytes32 = dispute.tx.hash().into();
+    if dispute.tx_hash != tx_hash {
+        return Err(FeeDisputeError::TxHashMismatch {
+            dispute: dispute.tx_hash,
+            actual: tx_hash,
+        });
+    }
+    dispute
+        .fee_transfer_merkle_proof
+        .verify(
+            &dispute.fee,
+            dispute.fee_transfer_index as u64,
+            dispute.tx.transfer_tree_root,
+        )
+        .map_err(|e| FeeDisputeError::InvalidFeeProof(e.to_string()))?;
+    let proposal = &dispute.proposal;
+    proposal
+        .verify(dispute.tx)
+        .map_err(|e| FeeDisputeError::InvalidProposal(e.to_string()))?;
+    if proposal.pubkeys.get(proposal.tx_index as usize) != Some(&dispute.sender) {
+        return Err(FeeDisputeError::InvalidProposal(format!(
+            "sender {} is not at index {} of the proposal",
+            dispute.sender, proposal.tx_index
+        )));
+    }
+    verify_proposal_attestation(
+        &dispute.attestation,
+        dispute.sender,
+        tx_hash,
+        proposal.tx_tree_root,
+    )
+    .map_err(|e| FeeDisputeError::InvalidAttestation(e.to_string()))?;
+    let check = &dispute.non_inclusion;
+    if check.checked_at <= check.expired_at() {
+        return Err(FeeDisputeError::NotExpired {
+            checked_at: check.checked_at,
+            expired_at: check.expired_at(),
+        });
+    }
+    Ok(())
+}
+
+/// Ask `validity_prover` again whether the tx tree root of the dispute is in a
+/// block, e.g. by the builder operator receiving the document
+pub async fn check_non_inclusion<V: ValidityProverClientInterface>(
+    validity_prover: &V,
+    dispute: &FeeDispute,
+) -> Result<(), FeeDisputeError> {
+    let tx_tree_root = dispute.proposal.tx_tree_root;
+    match validity_prover
+        .get_block_number_by_tx_tree_root(tx_tree_root)
+        .await?
+    {
+        Some(block_number) => Err(FeeDisputeError::Included(tx_tree_root, block_number)),
+        None => Ok(()),
+    }
+}
+
+/// Disputes of the fees paid by `key` in txs whose block was never posted
+pub async fn list_fee_disputes<S, V>(
+    store_vault_server: &S,
+    validity_prover: &V,
+    key: KeySet,
+    tx_timeout: u64,
+) -> Result<Vec<FeeDispute>, ClientError>
+where
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+{
+    let latest_block_number = validity_prover.get_block_number().await?;
+    let blobs = store_vault_server
+        .get_data_all_after(DataType::Tx, key.pubkey, 0)
+        .await?;
+    let mut disputes = Vec::new();
+    for (meta, decrypted) in decrypt_blobs(blobs, |data| TxData::<F, C, D>::decrypt(data, key)) {
+        let tx_data = match decrypted {
+            Ok(tx_data) => tx_data,
+            Err(e) => {
+                log::warn!("Failed to decrypt tx data {}: {}", meta.uuid, e);
+                continue;
+            }
+        };
+        let Some(fee) = tx_data.fee else {
+            continue;
+        };
+        let tx_tree_root = tx_data.common.tx_tree_root;
+        let block_number = validity_prover
+            .get_block_number_by_tx_tree_root(tx_tree_root)
+            .await?;
+        let status = classify_tx_status(block_number, Some(meta.timestamp), tx_timeout);
+        if FeeStatus::from(status) != FeeStatus::Unsettled {
+            continue;
+        }
+        let transfers = &tx_data.spent_witness.transfers;
+        let Some(fee_transfer) = transfers.get(fee.transfer_index as usize) else {
+            log::warn!(
+                "Fee transfer index {} of tx {} is out of range",
+                fee.transfer_index,
+                meta.uuid
+            );
+            continue;
+        };
+        let mut transfer_tree = TransferTree::new(TRANSFER_TREE_HEIGHT);
+        for transfer in transfers {
+            transfer_tree.push(transfer.clone());
+        }
+        disputes.push(FeeDispute {
+            tx_uuid: meta.uuid,
+            tx_hash: tx_data.common.tx.hash().into(),
+            block_builder_url: fee.block_builder_url,
+            sender: key.pubkey,
+            fee: fee_transfer.clone(),
+            fee_transfer_index: fee.transfer_index,
+            fee_transfer_merkle_proof: transfer_tree.prove(fee.transfer_index as u64),
+            tx: tx_data.common.tx,
+            proposal: fee.proposal,
+            attestation: fee.attestation,
+            non_inclusion: NonInclusionCheck {
+                finalized_at: meta.timestamp,
+                tx_timeout,
+                checked_at: global_clock().server_now(),
+                latest_block_number,
+            },
+        });
+    }
+    Ok(disputes)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+    use intmax2_interfaces::{
+        api::store_vault_server::interface::StoreVaultClientInterface as _,
+        data::{common_tx_data::CommonTxData, tx_data::TxFee},
+    };
+    use intmax2_zkp::{
+        common::{
+            generic_address::GenericAddress, private_state::FullPrivateState, salt::Salt,
+            signature::utils::get_pubkey_hash, trees::tx_tree::TxTree,
+            witness::spent_witness::SpentWitness,
+        },
+        constants::{NUM_TRANSFERS_IN_TX, TX_TREE_HEIGHT},
+    };
+    use plonky2::{
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_builder::CircuitBuilder, circuit_data::CircuitConfig,
+            proof::ProofWithPublicInputs,
+        },
+    };
+
+    use crate::external_api::{
+        block_builder::sign_proposal_attestation,
+        utils::{in_memory_store_vault::InMemoryStoreVault, mock_server::start_mock_server},
+        validity_prover::ValidityProverClient,
+    };
+
+    use super::*;
+
+    // the spent proof is not read by the dispute, so any proof will do
+    fn dummy_proof() -> ProofWithPublicInputs<F, C, D> {
+        let builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let data = builder.build::<C>();
+        data.prove(PartialWitness::new()).unwrap()
+    }
+
+    // Ethereum key of the block builder attesting the proposals
+    fn builder_key() -> H256 {
+        H256::from_low_u64_be(1)
+    }
+
+    // tx of `key` paying 5 to `recipient` and a fee of 1 to `builder`, signed in a
+    // proposal with another sender and attested by the builder
+    fn tx_with_fee(key: KeySet, recipient: U256, builder: U256) -> TxData<F, C, D> {
+        let mut rng = rand::thread_rng();
+        let transfer = |recipient: U256, amount: u32| Transfer {
+            recipient: GenericAddress::from_pubkey(recipient),
+            token_index: 0,
+            amount: U256::from(amount as u128),
+            salt: Salt::rand(&mut rng),
+        };
+        let mut transfers = vec![transfer(recipient, 5), transfer(builder, 1)];
+        transfers.resize(NUM_TRANSFERS_IN_TX, Transfer::default());
+        let mut transfer_tree = TransferTree::new(TRANSFER_TREE_HEIGHT);
+        for transfer in &transfers {
+            transfer_tree.push(transfer.clone());
+        }
+        let state = FullPrivateState::new();
+        let tx = Tx {
+            nonce: state.nonce,
+            transfer_tree_root: transfer_tree.get_root(),
+        };
+        let spent_witness = SpentWitness::new(
+            &state.asset_tree,
+            &state.to_private_state(),
+            &transfer_tree.leaves(),
+            tx,
+            Salt::rand(&mut rng),
+        )
+        .unwrap();
+
+        let mut tx_tree = TxTree::new(TX_TREE_HEIGHT);
+        tx_tree.push(Tx::default());
+        tx_tree.push(tx);
+        let pubkeys = vec![U256::from(1u128), key.pubkey];
+        let proposal = BlockProposal {
+            tx_tree_root: tx_tree.get_root().into(),
+            tx_index: 1,
+            tx_merkle_proof: tx_tree.prove(1),
+            pubkeys_hash: get_pubkey_hash(&pubkeys),
+            pubkeys,
+        };
+        let attestation =
+            sign_proposal_attestation(builder_key(), key.pubkey, tx, &proposal).unwrap();
+        TxData {
+            common: CommonTxData {
+                spent_proof: dummy_proof(),
+                sender_prev_block_number: 0,
+                tx,
+                tx_index: proposal.tx_index,
+                tx_merkle_proof: proposal.tx_merkle_proof.clone(),
+                tx_tree_root: proposal.tx_tree_root,
+            },
+            spent_witness,
+            claim_deadline_block: None,
+            fee: Some(TxFee {
+                block_builder_url: "http://builder.example".to_string(),
+                transfer_index: 1,
+                proposal,
+                attestation,
+            }),
+        }
+    }
+
+    // validity prover at block 10 that has none of the tx tree roots
+    fn validity_prover_without_blocks() -> ValidityProverClient {
+        let (url, _) = start_mock_server(|path, _| match path {
+            "/validity-prover/block-number" => r#"{"blockNumber":10}"#.to_string(),
+            _ => r#"{"blockNumber":null}"#.to_string(),
+        });
+        ValidityProverClient::new(&url).with_bearer_token("token")
+    }
+
+    #[tokio::test]
+    async fn test_builder_drops_block() {
+        let mut rng = rand::thread_rng();
+        let key = KeySet::rand(&mut rng);
+        let builder = KeySet::rand(&mut rng).pubkey;
+        let store_vault = InMemoryStoreVault::new();
+        let tx_data = tx_with_fee(key, KeySet::rand(&mut rng).pubkey, builder);
+        store_vault
+            .save_data(DataType::Tx, key.pubkey, &tx_data.encrypt(key.pubkey))
+            .await
+            .unwrap();
+        let validity_prover = validity_prover_without_blocks();
+
+        // the blob timestamps are far in the past, so the tx has expired
+        let disputes = list_fee_disputes(&store_vault, &validity_prover, key, 60)
+            .await
+            .unwrap();
+        assert_eq!(disputes.len(), 1);
+        let dispute = &disputes[0];
+        assert_eq!(dispute.block_builder_url, "http://builder.example");
+        assert_eq!(dispute.fee.recipient, GenericAddress::from_pubkey(builder));
+        assert_eq!(dispute.fee.amount, U256::from(1u128));
+        assert_eq!(dispute.non_inclusion.latest_block_number, 10);
+        assert!(dispute.non_inclusion.checked_at > dispute.non_inclusion.expired_at());
+
+        // the exported document verifies on its own
+        let json = serde_json::to_string(dispute).unwrap();
+        let parsed: FeeDispute = serde_json::from_str(&json).unwrap();
+        verify_fee_dispute(&parsed).unwrap();
+        check_non_inclusion(&validity_prover, &parsed)
+            .await
+            .unwrap();
+
+        // a tx whose timeout has not passed is not disputed yet
+        let disputes = list_fee_disputes(&store_vault, &validity_prover, key, u32::MAX as u64)
+            .await
+            .unwrap();
+        assert!(disputes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tampered_dispute_fails() {
+        let mut rng = rand::thread_rng();
+        let key = KeySet::rand(&mut rng);
+        let store_vault = InMemoryStoreVault::new();
+        let tx_data = tx_with_fee(key, U256::from(2u128), U256::from(3u128));
+        store_vault
+            .save_data(DataType::Tx, key.pubkey, &tx_data.encrypt(key.pubkey))
+            .await
+            .unwrap();
+        let validity_prover = validity_prover_without_blocks();
+        let dispute = list_fee_disputes(&store_vault, &validity_prover, key, 60)
+            .await
+            .unwrap()
+            .remove(0);
+
+        let mut tampered = dispute.clone();
+        tampered.fee.amount = U256::from(100u128);
+        assert!(matches!(
+            verify_fee_dispute(&tampered),
+            Err(FeeDisputeError::InvalidFeeProof(_))
+        ));
+
+        // attested by another key than the builder's
+        let mut tampered = dispute.clone();
+        tampered.attestation.signature = sign_proposal_attestation(
+            H256::from_low_u64_be(2),
+            key.pubkey,
+            tx_data.common.tx,
+            &dispute.proposal,
+        )
+        .unwrap()
+        .signature;
+        assert!(matches!(
+            verify_fee_dispute(&tampered),
+            Err(FeeDisputeError::InvalidAttestation(_))
+        ));
+
+        // attestation of another tx
+        let mut tampered = dispute.clone();
+        tampered.attestation.tx_hash = Bytes32::default();
+        assert!(matches!(
+            verify_fee_dispute(&tampered),
+            Err(FeeDisputeError::InvalidAttestation(_))
+        ));
+
+        let mut tampered = dispute.clone();
+        tampered.sender = U256::from(1u128);
+        assert!(matches!(
+            verify_fee_dispute(&tampered),
+            Err(FeeDisputeError::InvalidProposal(_))
+        ));
+
+        let mut tampered = dispute.clone();
+        tampered.non_inclusion.checked_at = tampered.non_inclusion.finalized_at;
+        assert!(matches!(
+            verify_fee_dispute(&tampered),
+            Err(FeeDisputeError::NotExpired { .. })
+        ));
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+
+use intmax2_interfaces::api::{
+    block_builder::interface::BlockBuilderClientInterface,
+    error::ServerError,
+    withdrawal_server::interface::{Fee, WithdrawalServerClientInterface},
+};
+use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use super::error::ClientError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FeeKind {
+    Transfer,
+    Withdrawal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FeeSource {
+    /// Inclusion of the tx in a block, paid by transfers and withdrawals
+    BlockBuilder,
+    /// Aggregation of the withdrawal and its claim on L1
+    WithdrawalAggregator,
+}
+
+/// Fee of a token, in its smallest unit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeAmount {
+    pub token_index: u32,
+    pub amount: U256,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeQuote {
+    pub source: FeeSource,
+    /// None if the server does not expose its fees
+    pub fees: Option<Vec<FeeAmount>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeEstimate {
+    pub kind: FeeKind,
+    pub quotes: Vec<FeeQuote>,
+}
+
+impl FeeEstimate {
+    /// Sum of the fees of each token over the quotes, leaving out the servers
+    /// that do not expose their fees
+    pub fn totals(&self) -> Vec<FeeAmount> {
+        let mut totals = BTreeMap::<u32, BigUint>::new();
+        for fee in self.quotes.iter().filter_map(|q| q.fees.as_ref()).flatten() {
+            *totals.entry(fee.token_index).or_default() += BigUint::from(fee.amount);
+        }
+        totals
+            .into_iter()
+            .map(|(token_index, amount)| FeeAmount {
+                token_index,
+                // saturates, as only absurd fees add up beyond 2^256
+                amount: amount
+                    .try_into()
+                    .unwrap_or_else(|_| U256::from_u32_slice(&[u32::MAX; 8])),
+            })
+            .collect()
+    }
+
+    /// Whether some server did not expose its fees, so that the totals may be
+    /// below what is charged
+    pub fn is_partial(&self) -> bool {
+        self.quotes.iter().any(|q| q.fees.is_none())
+    }
+}
+
+// `coefficient` as the decimal fraction `numerator / 10^scale` it was written as,
+// so that the fee is computed with integers. `Display` writes the shortest decimal
+// that reads back as the same f64, and never with an exponent.
+fn decimal_fraction(coefficient: f64) -> Option<(BigUint, u32)> {
+    if !coefficient.is_finite() || coefficient < 0.0 {
+        return None;
+    }
+    let text = coefficient.abs().to_string();
+    let (integer, fraction) = text.split_once('.').unwrap_or((&text, ""));
+    let numerator = BigUint::parse_bytes(format!("{}{}", integer, fraction).as_bytes(), 10)?;
+    Some((numerator, fraction.len() as u32))
+}
+
+/// `constant + coefficient * amount`, rounded up
+pub fn fee_amount(fee: &Fee, amount: U256) -> Result<U256, ServerError> {
+    let (numerator, scale) = decimal_fraction(fee.coefficient).ok_or_else(|| {
+        ServerError::InvalidResponse(format!(
+            "Invalid fee coefficient {} of token {}",
+            fee.coefficient, fee.token_index
+        ))
+    })?;
+    let denominator = BigUint::from(10u32).pow(scale);
+    let proportional = (numerator * BigUint::from(amount) + &denominator - 1u32) / &denominator;
+    (BigUint::from(fee.constant) + proportional)
+        .try_into()
+        .map_err(|_| {
+            ServerError::InvalidResponse(format!(
+                "Fee of token {} does not fit in 256 bits",
+                fee.token_index
+            ))
+        })
+}
+
+// None if the server answers that it has no such endpoint
+pub(crate) fn unless_not_exposed(
+    result: Result<Vec<Fee>, ServerError>,
+) -> Result<Option<Vec<Fee>>, ServerError> {
+    match result {
+        Ok(fees) => Ok(Some(fees)),
+        Err(ServerError::ServerError(404 | 405 | 501, ..)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn to_quote(
+    source: FeeSource,
+    fees: Option<Vec<Fee>>,
+    token_index: Option<u32>,
+    amount: U256,
+) -> Result<FeeQuote, ServerError> {
+    let fees = fees
+        .map(|fees| {
+            fees.iter()
+                .filter(|fee| token_index.map_or(true, |index| fee.token_index == index))
+                .map(|fee| {
+                    Ok(FeeAmount {
+                        token_index: fee.token_index,
+                        amount: fee_amount(fee, amount)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, ServerError>>()
+        })
+        .transpose()?;
+    Ok(FeeQuote { source, fees })
+}
+
+/// Quote the fees of a `kind` of `amount`, of the tokens of the servers or only of
+/// `token_index`. A transfer pays the block builder, and a withdrawal also pays the
+/// withdrawal aggregator. A server without a fee endpoint gets a quote without
+/// fees instead of an error.
+pub async fn quote_fees<BB, W>(
+    block_builder: &BB,
+    block_builder_url: &str,
+    withdrawal_server: &W,
+    kind: FeeKind,
+    token_index: Option<u32>,
+    amount: U256,
+) -> Result<FeeEstimate, ClientError>
+where
+    BB: BlockBuilderClientInterface,
+    W: WithdrawalServerClientInterface,
+{
+    let builder_fees = unless_not_exposed(block_builder.get_fee_info(block_builder_url).await)?;
+    let mut quotes = vec![to_quote(
+        FeeSource::BlockBuilder,
+        builder_fees,
+        token_index,
+        amount,
+    )?];
+    if kind == FeeKind::Withdrawal {
+        let aggregator_fees = unless_not_exposed(withdrawal_server.fee().await)?;
+        quotes.push(to_quote(
+            FeeSource::WithdrawalAggregator,
+            aggregator_fees,
+            token_index,
+            amount,
+        )?);
+    }
+    Ok(FeeEstimate { kind, quotes })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::external_api::{
+        block_builder::BlockBuilderClient,
+        utils::{mock_server::start_scripted_mock_server, retry::RetryConfig},
+        withdrawal_server::WithdrawalServerClient,
+    };
+
+    fn no_retry() -> RetryConfig {
+        RetryConfig {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fee_amount() {
+        let fee = Fee {
+            token_index: 0,
+            constant: 100,
+            coefficient: 0.001,
+        };
+        assert_eq!(
+            fee_amount(&fee, U256::from(0u128)).unwrap(),
+            U256::from(100u128)
+        );
+        // 1.5 rounded up
+        assert_eq!(
+            fee_amount(&fee, U256::from(1500u128)).unwrap(),
+            U256::from(102u128)
+        );
+        // 0.01 * 100 is 1 exactly, although the f64 0.01 is slightly above 0.01
+        let fee = Fee {
+            constant: 0,
+            coefficient: 0.01,
+            ..fee
+        };
+        assert_eq!(
+            fee_amount(&fee, U256::from(100u128)).unwrap(),
+            U256::from(1u128)
+        );
+        // exact beyond the 53 bits of an f64
+        let amount = U256::from(u128::MAX);
+        assert_eq!(
+            fee_amount(&fee, amount).unwrap(),
+            U256::from(u128::MAX / 100 + 1)
+        );
+        let fee = Fee {
+            coefficient: 1e-7,
+            ..fee
+        };
+        assert_eq!(
+            fee_amount(&fee, U256::from(30_000_000u128)).unwrap(),
+            U256::from(3u128)
+        );
+        for coefficient in [-1.0, f64::NAN, f64::INFINITY] {
+            let fee = Fee {
+                coefficient,
+                ..fee.clone()
+            };
+            assert!(fee_amount(&fee, U256::from(1500u128)).is_err());
+        }
+    }
+
+    const AGGREGATOR_FEES: &str = r#"{"fees":[
+        {"tokenIndex":0,"constant":10,"coefficient":0.0},
+        {"tokenIndex":1,"constant":5,"coefficient":0.5}
+    ]}"#;
+
+    #[tokio::test]
+    async fn test_quote_fees() {
+        // the block builder has no fee endpoint, the aggregator charges tokens 0 and 1
+        let (url, _) = start_scripted_mock_server(|path, _| match path {
+            "/withdrawal-server/fee" => (200, AGGREGATOR_FEES.to_string()),
+            _ => (404, r#"{"error":"not found"}"#.to_string()),
+        });
+        let block_builder = BlockBuilderClient::new()
+            .with_timeout(Duration::from_secs(5))
+            .with_retry_config(no_retry());
+        let withdrawal_server = WithdrawalServerClient::new(&url)
+            .with_timeout(Duration::from_secs(5))
+            .with_retry_config(no_retry());
+
+        let estimate = quote_fees(
+            &block_builder,
+            &url,
+            &withdrawal_server,
+            FeeKind::Transfer,
+            None,
+            U256::from(0u128),
+        )
+        .await
+        .unwrap();
+        assert_eq!(estimate.quotes.len(), 1);
+        assert_eq!(estimate.quotes[0].fees, None);
+        assert!(estimate.is_partial());
+        assert!(estimate.totals().is_empty());
+
+        let estimate = quote_fees(
+            &block_builder,
+            &url,
+            &withdrawal_server,
+            FeeKind::Withdrawal,
+            Some(1),
+            U256::from(10u128),
+        )
+        .await
+        .unwrap();
+        assert_eq!(estimate.quotes[1].source, FeeSource::WithdrawalAggregator);
+        assert_eq!(
+            estimate.totals(),
+            vec![FeeAmount {
+                token_index: 1,
+                amount: U256::from(10u128),
+            }]
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use std::fmt::Display;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Mutex, OnceLock},
+};
 
 use intmax2_interfaces::{
     api::{
@@ -8,24 +12,20 @@ use intmax2_interfaces::{
         validity_prover::interface::ValidityProverClientInterface,
         withdrawal_server::interface::WithdrawalServerClientInterface,
     },
-    data::{
-        deposit_data::{DepositData, TokenType},
-        transfer_data::TransferData,
-        tx_data::TxData,
-    },
+    data::deposit_data::TokenType,
 };
 use intmax2_zkp::{
     common::signature::key_set::KeySet,
-    ethereum_types::{address::Address, u256::U256},
+    ethereum_types::{address::Address, u256::U256, u32limb_trait::U32LimbTrait as _},
 };
-use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
 use serde::{Deserialize, Serialize};
 
-use super::{client::Client, error::ClientError};
-
-type F = GoldilocksField;
-type C = PoseidonGoldilocksConfig;
-const D: usize = 2;
+use super::{
+    client::Client,
+    error::ClientError,
+    fee_dispute::FeeStatus,
+    history_index::{HistoryIndex, InclusionKey, KeyDecoder, RowPayload},
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -38,18 +38,25 @@ pub enum HistoryEntry {
         amount: U256,
         is_rejected: bool,
         timestamp: Option<u64>, // timestamp of the block where the deposit was included
+        block_number: Option<u32>, // block of the deposit, once received
     },
     Receive {
         amount: U256,
         token_index: u32,
         from: U256,
         is_rejected: bool,
-        timestamp: Option<u64>, // timestamp of the block where the receive was included
+        is_expired: bool,          // not received before the claim deadline
+        timestamp: Option<u64>,    // timestamp of the block where the receive was included
+        block_number: Option<u32>, // block of the transfer, once received
     },
     Send {
         transfers: Vec<GenericTransfer>,
+        fee: Option<FeeLine>, // block builder fee, which is not in `transfers`
         is_rejected: bool,
+        // transfers not received by this block are lost, see `list_expired_outgoing_transfers`
+        claim_deadline_block: Option<u32>,
         timestamp: Option<u64>, // timestamp of the block where the send was included
+        block_number: Option<u32>, // block of the tx, once synced
     },
 }
 
@@ -64,11 +71,12 @@ impl Display for HistoryEntry {
                 amount,
                 is_rejected,
                 timestamp,
+                block_number,
             } => {
                 write!(
                     f,
-                    "Deposit: token_type: {:?}, token_address: {:?}, token_id: {:?}, token_index: {:?}, amount: {:?}, is_rejected: {:?}, timestamp: {:?}",
-                    token_type, token_address, token_id, token_index, amount, is_rejected, timestamp
+                    "Deposit: token_type: {:?}, token_address: {:?}, token_id: {:?}, token_index: {:?}, amount: {:?}, is_rejected: {:?}, timestamp: {:?}, block_number: {:?}",
+                    token_type, token_address, token_id, token_index, amount, is_rejected, timestamp, block_number
                 )
             }
             HistoryEntry::Receive {
@@ -76,23 +84,28 @@ impl Display for HistoryEntry {
                 token_index,
                 from,
                 is_rejected,
+                is_expired,
                 timestamp,
+                block_number,
             } => {
                 write!(
                     f,
-                    "Receive: amount: {:?}, token_index: {:?}, from: {:?}, is_rejected: {:?}, timestamp: {:?}",
-                    amount, token_index, from, is_rejected, timestamp
+                    "Receive: amount: {:?}, token_index: {:?}, from: {:?}, is_rejected: {:?}, is_expired: {:?}, timestamp: {:?}, block_number: {:?}",
+                    amount, token_index, from, is_rejected, is_expired, timestamp, block_number
                 )
             }
             HistoryEntry::Send {
                 transfers,
+                fee,
                 is_rejected,
+                claim_deadline_block,
                 timestamp,
+                block_number,
             } => {
                 write!(
                     f,
-                    "Send: transfers: {:?}, is_rejected: {:?}, timestamp: {:?}",
-                    transfers, is_rejected, timestamp
+                    "Send: transfers: {:?}, fee: {:?}, is_rejected: {:?}, claim_deadline_block: {:?}, timestamp: {:?}, block_number: {:?}",
+                    transfers, fee, is_rejected, claim_deadline_block, timestamp, block_number
                 )
             }
         }
@@ -115,6 +128,18 @@ pub enum GenericTransfer {
     },
 }
 
+/// Block builder fee of a send, with its own status: the fee of a tx whose block
+/// is never posted is unsettled, see `Client::list_fee_disputes`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeLine {
+    pub block_builder_url: String,
+    pub transfer: GenericTransfer,
+    pub status: FeeStatus,
+}
+
+/// The history of `key`, decrypting only the blobs saved since the previous call
+/// of the process for the same account
 pub async fn fetch_history<
     BB: BlockBuilderClientInterface,
     S: StoreVaultClientInterface,
@@ -125,172 +150,115 @@ pub async fn fetch_history<
     client: &Client<BB, S, V, B, W>,
     key: KeySet,
 ) -> Result<Vec<HistoryEntry>, ClientError> {
-    let user_data = client.get_user_data(key).await?;
+    let cache_key = format!(
+        "{}:{:?}:{}",
+        client.liquidity_contract.chain_id,
+        client.liquidity_contract.address,
+        key.pubkey.to_hex()
+    );
+    let index = cached_history_index(&cache_key).unwrap_or_else(|| HistoryIndex::new(key.pubkey));
+    let (history, index) = fetch_history_with_index(client, key, index).await?;
+    cache_history_index(cache_key, index);
+    Ok(history)
+}
 
-    let mut history = Vec::new();
+static HISTORY_INDEX_CACHE: OnceLock<Mutex<HashMap<String, HistoryIndex>>> = OnceLock::new();
 
-    // Deposits
-    let all_deposit_data = client
-        .store_vault_server
-        .get_data_all_after(DataType::Deposit, key.pubkey, 0)
-        .await?;
-    for (meta, data) in all_deposit_data {
-        let decrypted = match DepositData::decrypt(&data, key) {
-            Ok(decrypted) => decrypted,
-            Err(e) => {
-                log::warn!("Failed to decrypt deposit data: {:?}", e);
-                continue;
-            }
-        };
-        let token_index = client
-            .liquidity_contract
-            .get_token_index(
-                decrypted.token_type,
-                decrypted.token_address,
-                decrypted.token_id,
-            )
+// Indexes of `fetch_history` kept for the process, so that environments such as
+// the browser, where a client is built for every call, only decrypt the new blobs.
+// They are keyed by chain, liquidity contract and pubkey.
+fn cached_history_index(cache_key: &str) -> Option<HistoryIndex> {
+    HISTORY_INDEX_CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .get(cache_key)
+        .cloned()
+}
+
+fn cache_history_index(cache_key: String, index: HistoryIndex) {
+    HISTORY_INDEX_CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .insert(cache_key, index);
+}
+
+/// Fetch the history, decrypting only the blobs saved after those already in `index`.
+/// Returns the history and the updated index, to pass to the next call.
+pub async fn fetch_history_with_index<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    key: KeySet,
+    mut index: HistoryIndex,
+) -> Result<(Vec<HistoryEntry>, HistoryIndex), ClientError> {
+    if !index.is_valid_for(key.pubkey) {
+        log::info!("History index is outdated, rebuilding it");
+        index = HistoryIndex::new(key.pubkey);
+    }
+    let user_data = client.get_user_data(key).await?;
+    let current_block_number = client.validity_prover.get_block_number().await?;
+
+    let decoder = KeyDecoder(key);
+    for data_type in [DataType::Deposit, DataType::Transfer, DataType::Tx] {
+        let mut blobs = client
+            .store_vault_server
+            .get_data_all_after(data_type, key.pubkey, index.fetch_after(data_type))
             .await?;
-        if meta.timestamp <= user_data.deposit_lpt {
-            if user_data.processed_deposit_uuids.contains(&meta.uuid) {
-                history.push(HistoryEntry::Deposit {
-                    token_type: decrypted.token_type,
-                    token_address: decrypted.token_address,
-                    token_id: decrypted.token_id,
-                    token_index,
-                    amount: decrypted.amount,
-                    is_rejected: false,
-                    timestamp: Some(meta.timestamp),
-                });
-            } else {
-                history.push(HistoryEntry::Deposit {
-                    token_type: decrypted.token_type,
-                    token_address: decrypted.token_address,
-                    token_id: decrypted.token_id,
-                    token_index,
-                    amount: decrypted.amount,
-                    is_rejected: true,
-                    timestamp: None,
-                });
+        for uuid in index.uuids_to_retry(data_type) {
+            if let Some(blob) = client.store_vault_server.get_data(data_type, &uuid).await? {
+                blobs.push(blob);
             }
-        } else {
-            history.push(HistoryEntry::Deposit {
-                token_type: decrypted.token_type,
-                token_address: decrypted.token_address,
-                token_id: decrypted.token_id,
-                token_index,
-                amount: decrypted.amount,
-                is_rejected: false,
-                timestamp: None,
-            });
         }
+        let decoded = index.index_blobs(data_type, blobs, &decoder);
+        log::debug!("Indexed {} new {} blobs", decoded, data_type.to_string());
     }
 
-    let all_transfer_data = client
-        .store_vault_server
-        .get_data_all_after(DataType::Transfer, key.pubkey, 0)
-        .await?;
-    for (meta, data) in all_transfer_data {
-        let decrypted = match TransferData::<F, C, D>::decrypt(&data, key) {
-            Ok(decrypted) => decrypted,
-            Err(e) => {
-                log::warn!("Failed to deserialize transfer data: {:?}", e);
-                continue;
-            }
-        };
-        if meta.timestamp <= user_data.transfer_lpt {
-            if user_data.processed_transfer_uuids.contains(&meta.uuid) {
-                history.push(HistoryEntry::Receive {
-                    amount: decrypted.transfer.amount,
-                    token_index: decrypted.transfer.token_index,
-                    from: decrypted.sender,
-                    is_rejected: false,
-                    timestamp: Some(meta.timestamp),
-                });
-            } else {
-                history.push(HistoryEntry::Receive {
-                    amount: decrypted.transfer.amount,
-                    token_index: decrypted.transfer.token_index,
-                    from: decrypted.sender,
-                    is_rejected: true,
-                    timestamp: None,
-                });
+    // deposits of tokens not registered yet are the only rows that can change
+    for row in index.rows.iter_mut() {
+        if let RowPayload::Deposit {
+            token_type,
+            token_address,
+            token_id,
+            token_index,
+            ..
+        } = &mut row.payload
+        {
+            if token_index.is_none() {
+                *token_index = client
+                    .liquidity_contract
+                    .get_token_index(*token_type, *token_address, *token_id)
+                    .await?;
             }
-        } else {
-            history.push(HistoryEntry::Receive {
-                amount: decrypted.transfer.amount,
-                token_index: decrypted.transfer.token_index,
-                from: decrypted.sender,
-                is_rejected: false,
-                timestamp: None,
-            });
         }
     }
 
-    let all_tx_data = client
-        .store_vault_server
-        .get_data_all_after(DataType::Tx, key.pubkey, 0)
-        .await?;
-    for (meta, data) in all_tx_data {
-        let tx_data = match TxData::<F, C, D>::decrypt(&data, key) {
-            Ok(tx_data) => tx_data,
-            Err(e) => {
-                log::warn!("Failed to deserialize tx data: {:?}", e);
-                continue;
-            }
-        };
-        let mut transfers = Vec::new();
-        for transfer in tx_data.spent_witness.transfers.iter() {
-            let recipient = transfer.recipient;
-            if recipient.is_pubkey
-                && recipient.data == U256::dummy_pubkey()
-                && transfer.amount == U256::default()
-            {
-                // dummy transfer
-                continue;
-            }
-            if recipient.is_pubkey {
-                transfers.push(GenericTransfer::Transfer {
-                    recipient: recipient.to_pubkey().unwrap(),
-                    token_index: transfer.token_index,
-                    amount: transfer.amount,
-                });
-            } else {
-                transfers.push(GenericTransfer::Withdrawal {
-                    recipient: recipient.to_address().unwrap(),
-                    token_index: transfer.token_index,
-                    amount: transfer.amount,
-                });
-            }
+    // blocks are fixed once an action is incorporated, so each is looked up once
+    for row in index.rows.iter_mut() {
+        if row.block_number.is_some() || !row.is_processed(&user_data) {
+            continue;
         }
-        if meta.timestamp <= user_data.tx_lpt {
-            if user_data.processed_tx_uuids.contains(&meta.uuid) {
-                history.push(HistoryEntry::Send {
-                    transfers,
-                    is_rejected: false,
-                    timestamp: Some(meta.timestamp),
-                });
-            } else {
-                history.push(HistoryEntry::Send {
-                    transfers,
-                    is_rejected: true,
-                    timestamp: None,
-                });
+        row.block_number = match row.inclusion_key() {
+            Some(InclusionKey::DepositHash(deposit_hash)) => client
+                .validity_prover
+                .get_deposit_info(deposit_hash)
+                .await?
+                .map(|info| info.block_number),
+            Some(InclusionKey::TxTreeRoot(tx_tree_root)) => {
+                client
+                    .validity_prover
+                    .get_block_number_by_tx_tree_root(tx_tree_root)
+                    .await?
             }
-        } else {
-            history.push(HistoryEntry::Send {
-                transfers,
-                is_rejected: false,
-                timestamp: None,
-            });
-        }
+            None => None,
+        };
     }
 
-    // sort history
-    history.sort_by_key(|entry| match entry {
-        HistoryEntry::Deposit { timestamp, .. } => *timestamp,
-        HistoryEntry::Receive { timestamp, .. } => *timestamp,
-        HistoryEntry::Send { timestamp, .. } => *timestamp,
-    });
-
-    Ok(history)
+    let history = index.to_history(&user_data, current_block_number);
+    Ok((history, index))
 }
@@ -0,0 +1,689 @@
+use std::collections::HashSet;
+
+use intmax2_interfaces::{
+    api::store_vault_server::interface::DataType,
+    data::{
+        deposit_data::{DepositData, TokenType},
+        encryption::{decrypt, encrypt, ENCRYPTION_VERSION},
+        meta_data::MetaData,
+        transfer_data::{is_claim_expired, TransferData},
+        tx_data::TxData,
+        user_data::UserData,
+    },
+};
+use intmax2_zkp::{
+    common::{deposit::Deposit, signature::key_set::KeySet, transfer::Transfer},
+    ethereum_types::{address::Address, bytes32::Bytes32, u256::U256},
+    utils::leafable::Leafable as _,
+};
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    fee_dispute::FeeStatus,
+    history::{FeeLine, GenericTransfer, HistoryEntry},
+    strategy::decrypt::decrypt_blobs,
+};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Version of the index format. Indexes of another version are rebuilt.
+pub const HISTORY_INDEX_VERSION: u32 = 4;
+
+/// Number of times a blob that failed to decode is fetched and decoded again,
+/// e.g. after a transient error, before it is left out of the history
+pub const MAX_DECODE_ATTEMPTS: u32 = 3;
+
+/// The parts of a history entry that are fixed once its blob is decrypted. The
+/// status (settled, rejected, expired) depends on the user data, and is derived
+/// on every call by `HistoryIndex::to_history`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RowPayload {
+    Deposit {
+        token_type: TokenType,
+        token_address: Address,
+        token_id: U256,
+        token_index: Option<u32>, // None until the token is registered in the liquidity contract
+        amount: U256,
+        pubkey_salt_hash: Bytes32,
+    },
+    Receive {
+        amount: U256,
+        token_index: u32,
+        from: U256,
+        claim_deadline_block: Option<u32>,
+        tx_tree_root: Bytes32,
+    },
+    Send {
+        transfers: Vec<GenericTransfer>, // without the fee transfer
+        fee: Option<SendFee>,
+        claim_deadline_block: Option<u32>,
+        tx_tree_root: Bytes32,
+    },
+}
+
+/// Fee transfer of a send to its block builder
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendFee {
+    pub block_builder_url: String,
+    pub transfer: GenericTransfer,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedRow {
+    pub uuid: String,
+    pub timestamp: u64, // timestamp of the blob in the store vault
+    pub payload: RowPayload,
+    // block the action was included in, resolved once it is incorporated into the
+    // balance proof
+    pub block_number: Option<u32>,
+}
+
+impl IndexedRow {
+    /// Whether the action was incorporated into the balance proof of `user_data`
+    pub fn is_processed(&self, user_data: &UserData) -> bool {
+        let processed_uuids = match self.payload {
+            RowPayload::Deposit { .. } => &user_data.processed_deposit_uuids,
+            RowPayload::Receive { .. } => &user_data.processed_transfer_uuids,
+            RowPayload::Send { .. } => &user_data.processed_tx_uuids,
+        };
+        processed_uuids.contains(&self.uuid)
+    }
+
+    /// Hash of the deposit, or tx tree root of the transfer or tx, by which the
+    /// validity prover knows the block of the action. `None` for deposits of
+    /// unregistered tokens.
+    pub fn inclusion_key(&self) -> Option<InclusionKey> {
+        match &self.payload {
+            RowPayload::Deposit {
+                token_index,
+                amount,
+                pubkey_salt_hash,
+                ..
+            } => token_index.map(|token_index| {
+                let deposit = Deposit {
+                    pubkey_salt_hash: *pubkey_salt_hash,
+                    token_index,
+                    amount: *amount,
+                };
+                InclusionKey::DepositHash(deposit.hash())
+            }),
+            RowPayload::Receive { tx_tree_root, .. } | RowPayload::Send { tx_tree_root, .. } => {
+                Some(InclusionKey::TxTreeRoot(*tx_tree_root))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InclusionKey {
+    DepositHash(Bytes32),
+    TxTreeRoot(Bytes32),
+}
+
+/// Blob that failed to decode
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedBlob {
+    pub data_type: DataType,
+    pub uuid: String,
+    pub attempts: u32,
+}
+
+/// History rows already derived from the store vault, so that only blobs saved
+/// after the last call have to be decrypted.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryIndex {
+    pub version: u32,
+    pub encryption_version: u32,
+    pub pubkey: U256,
+
+    // timestamp of the latest indexed blob of each data type
+    pub deposit_cursor: u64,
+    pub transfer_cursor: u64,
+    pub tx_cursor: u64,
+
+    pub rows: Vec<IndexedRow>,
+    // blobs that failed to decode, retried up to `MAX_DECODE_ATTEMPTS` times
+    pub skipped: Vec<SkippedBlob>,
+}
+
+/// Turns a blob into a history row
+pub trait RowDecoder {
+    fn decode(&self, data_type: DataType, data: &[u8]) -> Option<RowPayload>;
+}
+
+/// Decrypts blobs with the user's key
+pub struct KeyDecoder(pub KeySet);
+
+impl RowDecoder for KeyDecoder {
+    fn decode(&self, data_type: DataType, data: &[u8]) -> Option<RowPayload> {
+        let key = self.0;
+        let result = match data_type {
+            DataType::Deposit => {
+                DepositData::decrypt(data, key).map(|deposit| RowPayload::Deposit {
+                    token_type: deposit.token_type,
+                    token_address: deposit.token_address,
+                    token_id: deposit.token_id,
+                    token_index: None,
+                    amount: deposit.amount,
+                    pubkey_salt_hash: deposit.pubkey_salt_hash,
+                })
+            }
+            DataType::Transfer => {
+                TransferData::<F, C, D>::decrypt(data, key).map(|transfer| RowPayload::Receive {
+                    amount: transfer.transfer.amount,
+                    token_index: transfer.transfer.token_index,
+                    from: transfer.sender,
+                    claim_deadline_block: transfer.claim_deadline_block,
+                    tx_tree_root: transfer.tx_data.tx_tree_root,
+                })
+            }
+            DataType::Tx => TxData::<F, C, D>::decrypt(data, key).map(|tx_data| {
+                let fee_index = tx_data.fee.as_ref().map(|fee| fee.transfer_index as usize);
+                let transfers = tx_data.spent_witness.transfers.iter().enumerate();
+                RowPayload::Send {
+                    transfers: transfers
+                        .filter(|(i, _)| Some(*i) != fee_index)
+                        .filter_map(|(_, transfer)| generic_transfer(transfer))
+                        .collect(),
+                    fee: tx_data.fee.as_ref().and_then(|fee| {
+                        let transfer = tx_data
+                            .spent_witness
+                            .transfers
+                            .get(fee.transfer_index as usize)?;
+                        Some(SendFee {
+                            block_builder_url: fee.block_builder_url.clone(),
+                            transfer: generic_transfer(transfer)?,
+                        })
+                    }),
+                    claim_deadline_block: tx_data.claim_deadline_block,
+                    tx_tree_root: tx_data.common.tx_tree_root,
+                }
+            }),
+            DataType::Withdrawal => return None,
+        };
+        match result {
+            Ok(payload) => Some(payload),
+            Err(e) => {
+                log::warn!("Failed to decrypt {} data: {:?}", data_type.to_string(), e);
+                None
+            }
+        }
+    }
+}
+
+// `None` for the dummy transfers padding the tx
+fn generic_transfer(transfer: &Transfer) -> Option<GenericTransfer> {
+    let recipient = transfer.recipient;
+    if recipient.is_pubkey
+        && recipient.data == U256::dummy_pubkey()
+        && transfer.amount == U256::default()
+    {
+        return None;
+    }
+    if recipient.is_pubkey {
+        Some(GenericTransfer::Transfer {
+            recipient: recipient.to_pubkey().unwrap(),
+            token_index: transfer.token_index,
+            amount: transfer.amount,
+        })
+    } else {
+        Some(GenericTransfer::Withdrawal {
+            recipient: recipient.to_address().unwrap(),
+            token_index: transfer.token_index,
+            amount: transfer.amount,
+        })
+    }
+}
+
+impl HistoryIndex {
+    pub fn new(pubkey: U256) -> Self {
+        Self {
+            version: HISTORY_INDEX_VERSION,
+            encryption_version: ENCRYPTION_VERSION,
+            pubkey,
+            deposit_cursor: 0,
+            transfer_cursor: 0,
+            tx_cursor: 0,
+            rows: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Whether the index was built for `pubkey` with the current index format and
+    /// encryption scheme
+    pub fn is_valid_for(&self, pubkey: U256) -> bool {
+        self.version == HISTORY_INDEX_VERSION
+            && self.encryption_version == ENCRYPTION_VERSION
+            && self.pubkey == pubkey
+    }
+
+    /// Serialize the index with the data encryption scheme of the store vault
+    /// blobs. The scheme is not implemented yet (`ENCRYPTION_VERSION` 0), so the
+    /// bytes are the plaintext index; it is rebuilt once the scheme changes.
+    pub fn encrypt(&self, pubkey: U256) -> Vec<u8> {
+        encrypt(pubkey, &bincode::serialize(self).unwrap())
+    }
+
+    pub fn decrypt(bytes: &[u8], key: KeySet) -> anyhow::Result<Self> {
+        let data = decrypt(key, bytes)?;
+        let index: Self = bincode::deserialize(&data)?;
+        anyhow::ensure!(
+            index.pubkey == key.pubkey,
+            "history index of another account"
+        );
+        Ok(index)
+    }
+
+    fn cursor_mut(&mut self, data_type: DataType) -> &mut u64 {
+        match data_type {
+            DataType::Deposit => &mut self.deposit_cursor,
+            DataType::Transfer => &mut self.transfer_cursor,
+            DataType::Tx => &mut self.tx_cursor,
+            DataType::Withdrawal => unreachable!("withdrawals are not in the history"),
+        }
+    }
+
+    /// Timestamp to fetch blobs after. The store vault only returns blobs strictly
+    /// after it, and a blob may be saved in the same second as the latest indexed one,
+    /// so the last second is fetched again. Blobs already indexed are skipped.
+    pub fn fetch_after(&self, data_type: DataType) -> u64 {
+        let cursor = match data_type {
+            DataType::Deposit => self.deposit_cursor,
+            DataType::Transfer => self.transfer_cursor,
+            DataType::Tx => self.tx_cursor,
+            DataType::Withdrawal => unreachable!("withdrawals are not in the history"),
+        };
+        cursor.saturating_sub(1)
+    }
+
+    /// Uuids of the blobs of `data_type` that failed to decode and are to be
+    /// fetched again and passed to `index_blobs`
+    pub fn uuids_to_retry(&self, data_type: DataType) -> Vec<String> {
+        self.skipped
+            .iter()
+            .filter(|skipped| {
+                skipped.data_type == data_type && skipped.attempts < MAX_DECODE_ATTEMPTS
+            })
+            .map(|skipped| skipped.uuid.clone())
+            .collect()
+    }
+
+    /// Add rows for the blobs that are not indexed yet, and return the number of
+    /// blobs decoded. Blobs that failed to decode before are decoded again until
+    /// they ran out of attempts.
+    pub fn index_blobs<R: RowDecoder + Sync>(
+        &mut self,
+        data_type: DataType,
+        blobs: Vec<(MetaData, Vec<u8>)>,
+        decoder: &R,
+    ) -> usize {
+        let known: HashSet<String> = self
+            .rows
+            .iter()
+            .map(|row| row.uuid.clone())
+            .chain(
+                self.skipped
+                    .iter()
+                    .filter(|skipped| skipped.attempts >= MAX_DECODE_ATTEMPTS)
+                    .map(|skipped| skipped.uuid.clone()),
+            )
+            .collect();
+        let mut seen = HashSet::new();
+        let blobs = blobs
+            .into_iter()
+            .filter(|(meta, _)| !known.contains(&meta.uuid) && seen.insert(meta.uuid.clone()))
+            .collect::<Vec<_>>();
+        let decoded = blobs.len();
+        for (meta, payload) in decrypt_blobs(blobs, |data| decoder.decode(data_type, data)) {
+            let cursor = self.cursor_mut(data_type);
+            *cursor = (*cursor).max(meta.timestamp);
+            let skipped = self
+                .skipped
+                .iter()
+                .position(|skipped| skipped.uuid == meta.uuid);
+            match (payload, skipped) {
+                (Some(payload), skipped) => {
+                    if let Some(position) = skipped {
+                        self.skipped.remove(position);
+                    }
+                    self.rows.push(IndexedRow {
+                        uuid: meta.uuid,
+                        timestamp: meta.timestamp,
+                        payload,
+                        block_number: None,
+                    });
+                }
+                (None, Some(position)) => self.skipped[position].attempts += 1,
+                (None, None) => self.skipped.push(SkippedBlob {
+                    data_type,
+                    uuid: meta.uuid,
+                    attempts: 1,
+                }),
+            }
+        }
+        decoded
+    }
+
+    /// Derive the history from the indexed rows and the current user data
+    pub fn to_history(&self, user_data: &UserData, current_block_number: u32) -> Vec<HistoryEntry> {
+        let mut history = self
+            .rows
+            .iter()
+            .map(|row| row_to_entry(row, user_data, current_block_number))
+            .collect::<Vec<_>>();
+        history.sort_by_key(|entry| match entry {
+            HistoryEntry::Deposit { timestamp, .. } => *timestamp,
+            HistoryEntry::Receive { timestamp, .. } => *timestamp,
+            HistoryEntry::Send { timestamp, .. } => *timestamp,
+        });
+        history
+    }
+}
+
+// (is_rejected, timestamp) of an action, given whether it was incorporated into the
+// balance proof and the last processed timestamp of its kind
+fn status(row: &IndexedRow, is_processed: bool, lpt: u64) -> (bool, Option<u64>) {
+    if row.timestamp <= lpt {
+        if is_processed {
+            (false, Some(row.timestamp))
+        } else {
+            (true, None)
+        }
+    } else {
+        (false, None)
+    }
+}
+
+fn row_to_entry(row: &IndexedRow, user_data: &UserData, current_block_number: u32) -> HistoryEntry {
+    match &row.payload {
+        RowPayload::Deposit {
+            token_type,
+            token_address,
+            token_id,
+            token_index,
+            amount,
+            ..
+        } => {
+            let is_processed = row.is_processed(user_data);
+            let (is_rejected, timestamp) = status(row, is_processed, user_data.deposit_lpt);
+            HistoryEntry::Deposit {
+                token_type: *token_type,
+                token_address: *token_address,
+                token_id: *token_id,
+                token_index: *token_index,
+                amount: *amount,
+                is_rejected,
+                timestamp,
+                block_number: row.block_number,
+            }
+        }
+        RowPayload::Receive {
+            amount,
+            token_index,
+            from,
+            claim_deadline_block,
+            ..
+        } => {
+            let is_processed = row.is_processed(user_data);
+            let is_expired =
+                !is_processed && is_claim_expired(*claim_deadline_block, current_block_number);
+            let (is_rejected, timestamp) = status(row, is_processed, user_data.transfer_lpt);
+            HistoryEntry::Receive {
+                amount: *amount,
+                token_index: *token_index,
+                from: *from,
+                // a transfer refused after its deadline is expired rather than rejected
+                is_rejected: is_rejected && !is_expired,
+                is_expired,
+                timestamp,
+                block_number: row.block_number,
+            }
+        }
+        RowPayload::Send {
+            transfers,
+            fee,
+            claim_deadline_block,
+            ..
+        } => {
+            let is_processed = row.is_processed(user_data);
+            let (is_rejected, timestamp) = status(row, is_processed, user_data.tx_lpt);
+            // a tx the sync skipped as rejected never made it into a block
+            let fee_status = if is_processed {
+                FeeStatus::Settled
+            } else if is_rejected {
+                FeeStatus::Unsettled
+            } else {
+                FeeStatus::Pending
+            };
+            HistoryEntry::Send {
+                transfers: transfers.clone(),
+                fee: fee.as_ref().map(|fee| FeeLine {
+                    block_builder_url: fee.block_builder_url.clone(),
+                    transfer: fee.transfer.clone(),
+                    status: fee_status,
+                }),
+                is_rejected,
+                claim_deadline_block: *claim_deadline_block,
+                timestamp,
+                block_number: row.block_number,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use intmax2_zkp::common::{deposit::get_pubkey_salt_hash, salt::Salt};
+
+    use super::*;
+
+    struct CountingDecoder {
+        inner: KeyDecoder,
+        count: AtomicUsize,
+    }
+
+    impl RowDecoder for CountingDecoder {
+        fn decode(&self, data_type: DataType, data: &[u8]) -> Option<RowPayload> {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            self.inner.decode(data_type, data)
+        }
+    }
+
+    fn deposit_blob(key: KeySet, uuid: &str, timestamp: u64) -> (MetaData, Vec<u8>) {
+        let mut rng = rand::thread_rng();
+        let deposit_salt = Salt::rand(&mut rng);
+        let deposit_data = DepositData {
+            deposit_salt,
+            pubkey_salt_hash: get_pubkey_salt_hash(key.pubkey, deposit_salt),
+            amount: U256::default(),
+            token_type: TokenType::NATIVE,
+            token_address: Address::default(),
+            token_id: U256::default(),
+            token_index: None,
+        };
+        let meta = MetaData {
+            uuid: uuid.to_string(),
+            timestamp,
+            block_number: None,
+        };
+        (meta, deposit_data.encrypt(key.pubkey))
+    }
+
+    #[test]
+    fn test_only_new_blobs_are_decrypted() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let decoder = CountingDecoder {
+            inner: KeyDecoder(key),
+            count: AtomicUsize::new(0),
+        };
+        let mut blobs = vec![
+            deposit_blob(key, "a", 10),
+            deposit_blob(key, "b", 20),
+            (
+                MetaData {
+                    uuid: "broken".to_string(),
+                    timestamp: 20,
+                    block_number: None,
+                },
+                vec![1, 2, 3],
+            ),
+        ];
+        let mut index = HistoryIndex::new(key.pubkey);
+        index.index_blobs(DataType::Deposit, blobs.clone(), &decoder);
+        assert_eq!(decoder.count.load(Ordering::SeqCst), 3);
+        assert_eq!(index.rows.len(), 2);
+        assert_eq!(index.fetch_after(DataType::Deposit), 19);
+
+        // the refetched second contains the known blobs and a new one, and the
+        // broken blob is decoded again
+        blobs.push(deposit_blob(key, "c", 20));
+        index.index_blobs(DataType::Deposit, blobs, &decoder);
+        assert_eq!(decoder.count.load(Ordering::SeqCst), 5);
+        assert_eq!(index.rows.len(), 3);
+
+        // status changes without decrypting again
+        let mut user_data = UserData::new(key.pubkey);
+        assert!(index.to_history(&user_data, 0).iter().all(|entry| matches!(
+            entry,
+            HistoryEntry::Deposit {
+                timestamp: None,
+                ..
+            }
+        )));
+        user_data.deposit_lpt = 20;
+        user_data.processed_deposit_uuids = vec!["a".to_string(), "c".to_string()];
+        let settled = index
+            .to_history(&user_data, 0)
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    entry,
+                    HistoryEntry::Deposit {
+                        timestamp: Some(_),
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(settled, 2);
+        assert_eq!(decoder.count.load(Ordering::SeqCst), 5);
+    }
+
+    // decodes nothing until `fail` is cleared
+    struct FlakyDecoder {
+        inner: KeyDecoder,
+        fail: std::sync::atomic::AtomicBool,
+    }
+
+    impl RowDecoder for FlakyDecoder {
+        fn decode(&self, data_type: DataType, data: &[u8]) -> Option<RowPayload> {
+            if self.fail.load(Ordering::SeqCst) {
+                return None;
+            }
+            self.inner.decode(data_type, data)
+        }
+    }
+
+    #[test]
+    fn test_skipped_blobs_are_retried() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let decoder = FlakyDecoder {
+            inner: KeyDecoder(key),
+            fail: true.into(),
+        };
+        let blob = deposit_blob(key, "a", 10);
+        let mut index = HistoryIndex::new(key.pubkey);
+        index.index_blobs(DataType::Deposit, vec![blob.clone()], &decoder);
+        assert!(index.rows.is_empty());
+        assert_eq!(index.uuids_to_retry(DataType::Deposit), vec!["a"]);
+        assert!(index.uuids_to_retry(DataType::Transfer).is_empty());
+
+        decoder.fail.store(false, Ordering::SeqCst);
+        index.index_blobs(DataType::Deposit, vec![blob], &decoder);
+        assert_eq!(index.rows.len(), 1);
+        assert!(index.skipped.is_empty());
+
+        // a blob that never decodes is given up after its attempts
+        let broken = (
+            MetaData {
+                uuid: "broken".to_string(),
+                timestamp: 20,
+                block_number: None,
+            },
+            vec![1, 2, 3],
+        );
+        for _ in 0..MAX_DECODE_ATTEMPTS {
+            assert_eq!(index.uuids_to_retry(DataType::Deposit).len(), 1);
+            index.index_blobs(DataType::Deposit, vec![broken.clone()], &decoder);
+        }
+        assert!(index.uuids_to_retry(DataType::Deposit).is_empty());
+        assert_eq!(
+            index.index_blobs(DataType::Deposit, vec![broken], &decoder),
+            0
+        );
+    }
+
+    #[test]
+    fn test_fee_line_status() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let send = |uuid: &str, timestamp: u64| IndexedRow {
+            uuid: uuid.to_string(),
+            timestamp,
+            payload: RowPayload::Send {
+                transfers: Vec::new(),
+                fee: Some(SendFee {
+                    block_builder_url: "http://builder.example".to_string(),
+                    transfer: GenericTransfer::Transfer {
+                        recipient: U256::default(),
+                        token_index: 0,
+                        amount: U256::default(),
+                    },
+                }),
+                claim_deadline_block: None,
+                tx_tree_root: Bytes32::default(),
+            },
+            block_number: None,
+        };
+        let mut index = HistoryIndex::new(key.pubkey);
+        index.rows = vec![send("posted", 10), send("dropped", 20), send("new", 30)];
+        let mut user_data = UserData::new(key.pubkey);
+        user_data.tx_lpt = 20;
+        user_data.processed_tx_uuids = vec!["posted".to_string()];
+
+        let statuses = index
+            .to_history(&user_data, 0)
+            .into_iter()
+            .map(|entry| match entry {
+                HistoryEntry::Send { fee: Some(fee), .. } => fee.status,
+                _ => panic!("not a send with a fee"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![FeeStatus::Settled, FeeStatus::Unsettled, FeeStatus::Pending]
+        );
+    }
+
+    #[test]
+    fn test_index_invalidation() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let mut index = HistoryIndex::new(key.pubkey);
+        let restored = HistoryIndex::decrypt(&index.encrypt(key.pubkey), key).unwrap();
+        assert!(restored.is_valid_for(key.pubkey));
+
+        index.version = HISTORY_INDEX_VERSION - 1;
+        assert!(!index.is_valid_for(key.pubkey));
+        let other = KeySet::rand(&mut rand::thread_rng());
+        assert!(!HistoryIndex::new(key.pubkey).is_valid_for(other.pubkey));
+    }
+}
@@ -0,0 +1,464 @@
+use aes_gcm::{
+    aead::{Aead as _, NewAead as _},
+    Aes256Gcm, Key, Nonce,
+};
+use ark_bn254::Fr;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    XChaCha20Poly1305, XNonce,
+};
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use num_bigint::BigUint;
+use rand::RngCore as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Plain key file: seed || checksum, where checksum is the first 4 bytes of
+// sha256(seed). Encrypted key file: MAGIC || VERSION || log_n || salt || nonce ||
+// XChaCha20-Poly1305(plain key file) with a key derived by scrypt. JSON key file:
+// the layout of Ethereum keystore v3, with AES-256-GCM instead of AES-128-CTR and
+// a MAC (the tag of GCM authenticates the ciphertext), encrypting the seed.
+const SEED_LEN: usize = 32;
+const CHECKSUM_LEN: usize = 4;
+const PLAIN_LEN: usize = SEED_LEN + CHECKSUM_LEN;
+const MAGIC: &[u8; 4] = b"IMXK";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + 2 + SALT_LEN + NONCE_LEN;
+
+const JSON_VERSION: u32 = 1;
+const JSON_CIPHER: &str = "aes-256-gcm";
+const JSON_KDF: &str = "scrypt";
+const JSON_SALT_LEN: usize = 32;
+const JSON_NONCE_LEN: usize = 12;
+const DKLEN: usize = 32;
+
+/// scrypt cost of new encrypted key files (N = 2^SCRYPT_LOG_N, r = 8, p = 1)
+pub const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// Highest scrypt cost accepted from a key file (1 GiB at r = 8), so that a crafted
+/// file cannot pin the CPU and memory
+pub const MAX_SCRYPT_LOG_N: u8 = 20;
+/// Highest scrypt r and p accepted from a JSON key file, for the same reason
+pub const MAX_SCRYPT_R: u32 = 8;
+pub const MAX_SCRYPT_P: u32 = 16;
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum KeyFileError {
+    #[error("Invalid key file: {0}")]
+    InvalidFormat(String),
+
+    #[error("Key file checksum mismatch, the file is corrupted")]
+    ChecksumMismatch,
+
+    #[error("Key file is encrypted, a passphrase is required")]
+    PassphraseRequired,
+
+    #[error("Wrong passphrase")]
+    WrongPassphrase,
+
+    #[error("Key file is of {expected}, but decrypts to the key of {actual}")]
+    PubkeyMismatch { expected: String, actual: String },
+}
+
+/// JSON key file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonKeyFile {
+    pub version: u32,
+    /// Public key of the encrypted key, so that the account is known without the
+    /// passphrase
+    pub pubkey: String,
+    pub crypto: JsonKeyFileCrypto,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonKeyFileCrypto {
+    pub cipher: String,
+    pub ciphertext: String, // hex, followed by the tag
+    pub cipherparams: CipherParams,
+    pub kdf: String,
+    pub kdfparams: ScryptParams,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub nonce: String, // hex
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScryptParams {
+    pub n: u32,
+    pub r: u32,
+    pub p: u32,
+    pub dklen: u32,
+    pub salt: String, // hex
+}
+
+/// Whether `bytes` is an encrypted key file, binary or JSON
+pub fn is_encrypted_key_file(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC) || is_json_key_file(bytes)
+}
+
+fn is_json_key_file(bytes: &[u8]) -> bool {
+    bytes.trim_ascii_start().starts_with(b"{")
+}
+
+/// Read a key file of any layout. `passphrase` is required for encrypted key files
+/// and ignored for plain ones.
+pub fn import_key_file(bytes: &[u8], passphrase: Option<&str>) -> Result<KeySet, KeyFileError> {
+    if is_json_key_file(bytes) {
+        let passphrase = passphrase.ok_or(KeyFileError::PassphraseRequired)?;
+        return JsonKeyFile::from_json(bytes)?.decrypt(passphrase);
+    }
+    let plain = if is_encrypted_key_file(bytes) {
+        let passphrase = passphrase.ok_or(KeyFileError::PassphraseRequired)?;
+        decrypt(bytes, passphrase)?
+    } else {
+        bytes.to_vec()
+    };
+    if plain.len() != PLAIN_LEN {
+        return Err(KeyFileError::InvalidFormat(format!(
+            "expected {} bytes, got {}",
+            PLAIN_LEN,
+            plain.len()
+        )));
+    }
+    let (seed, checksum) = plain.split_at(SEED_LEN);
+    if checksum != seed_checksum(seed) {
+        return Err(KeyFileError::ChecksumMismatch);
+    }
+    let privkey: Fr = BigUint::from_bytes_be(seed).into();
+    Ok(KeySet::new(privkey))
+}
+
+/// Write the private key of `key` as a key file, encrypted if `passphrase` is given
+pub fn export_key_file(key: KeySet, passphrase: Option<&str>) -> Vec<u8> {
+    let seed = key_seed(key);
+    let mut plain = seed.to_vec();
+    plain.extend_from_slice(&seed_checksum(&seed));
+    match passphrase {
+        Some(passphrase) => encrypt(&plain, passphrase, SCRYPT_LOG_N),
+        None => plain,
+    }
+}
+
+/// Write the private key of `key` as a JSON key file encrypted with `passphrase`
+pub fn export_json_key_file(key: KeySet, passphrase: &str) -> Vec<u8> {
+    JsonKeyFile::encrypt(key, passphrase, SCRYPT_LOG_N)
+        .to_json()
+        .into_bytes()
+}
+
+fn key_seed(key: KeySet) -> [u8; SEED_LEN] {
+    let bytes = BigUint::from(key.privkey).to_bytes_be();
+    let mut seed = [0u8; SEED_LEN];
+    seed[SEED_LEN - bytes.len()..].copy_from_slice(&bytes);
+    seed
+}
+
+fn seed_checksum(seed: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let digest = Sha256::digest(seed);
+    let mut checksum = [0u8; CHECKSUM_LEN];
+    checksum.copy_from_slice(&digest[..CHECKSUM_LEN]);
+    checksum
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    log_n: u8,
+    r: u32,
+    p: u32,
+) -> Result<[u8; DKLEN], KeyFileError> {
+    if log_n > MAX_SCRYPT_LOG_N || r > MAX_SCRYPT_R || p > MAX_SCRYPT_P {
+        return Err(KeyFileError::InvalidFormat(format!(
+            "scrypt cost (log_n {}, r {}, p {}) is above (log_n {}, r {}, p {})",
+            log_n, r, p, MAX_SCRYPT_LOG_N, MAX_SCRYPT_R, MAX_SCRYPT_P
+        )));
+    }
+    let params = scrypt::Params::new(log_n, r, p, DKLEN)
+        .map_err(|e| KeyFileError::InvalidFormat(format!("invalid scrypt params: {}", e)))?;
+    let mut key = [0u8; DKLEN];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|e| KeyFileError::InvalidFormat(format!("scrypt failed: {}", e)))?;
+    Ok(key)
+}
+
+fn encrypt(plain: &[u8], passphrase: &str, log_n: u8) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut nonce);
+    let key =
+        derive_key(passphrase, &salt, log_n, SCRYPT_R, SCRYPT_P).expect("scrypt params are valid");
+    let ciphertext = XChaCha20Poly1305::new(&key.into())
+        .encrypt(XNonce::from_slice(&nonce), plain)
+        .expect("encryption of a key file cannot fail");
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.push(log_n);
+    bytes.extend_from_slice(&salt);
+    bytes.extend_from_slice(&nonce);
+    bytes.extend_from_slice(&ciphertext);
+    bytes
+}
+
+fn decrypt(bytes: &[u8], passphrase: &str) -> Result<Vec<u8>, KeyFileError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(KeyFileError::InvalidFormat("truncated header".to_string()));
+    }
+    let version = bytes[MAGIC.len()];
+    if version != VERSION {
+        return Err(KeyFileError::InvalidFormat(format!(
+            "unsupported version {}",
+            version
+        )));
+    }
+    let log_n = bytes[MAGIC.len() + 1];
+    let salt = &bytes[MAGIC.len() + 2..MAGIC.len() + 2 + SALT_LEN];
+    let nonce = &bytes[HEADER_LEN - NONCE_LEN..HEADER_LEN];
+    let key = derive_key(passphrase, salt, log_n, SCRYPT_R, SCRYPT_P)?;
+    // the tag authenticates the whole plaintext, so a corrupted ciphertext is
+    // indistinguishable from a wrong passphrase
+    XChaCha20Poly1305::new(&key.into())
+        .decrypt(XNonce::from_slice(nonce), &bytes[HEADER_LEN..])
+        .map_err(|_| KeyFileError::WrongPassphrase)
+}
+
+impl JsonKeyFile {
+    fn encrypt(key: KeySet, passphrase: &str, log_n: u8) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut salt = [0u8; JSON_SALT_LEN];
+        rng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; JSON_NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+        let derived = derive_key(passphrase, &salt, log_n, SCRYPT_R, SCRYPT_P)
+            .expect("scrypt params are valid");
+        let ciphertext = Aes256Gcm::new(Key::from_slice(&derived))
+            .encrypt(Nonce::from_slice(&nonce), key_seed(key).as_ref())
+            .expect("encryption of a key file cannot fail");
+        Self {
+            version: JSON_VERSION,
+            pubkey: key.pubkey.to_hex(),
+            crypto: JsonKeyFileCrypto {
+                cipher: JSON_CIPHER.to_string(),
+                ciphertext: hex::encode(ciphertext),
+                cipherparams: CipherParams {
+                    nonce: hex::encode(nonce),
+                },
+                kdf: JSON_KDF.to_string(),
+                kdfparams: ScryptParams {
+                    n: 1 << log_n,
+                    r: SCRYPT_R,
+                    p: SCRYPT_P,
+                    dklen: DKLEN as u32,
+                    salt: hex::encode(salt),
+                },
+            },
+        }
+    }
+
+    /// Decrypt the key. Fails with `WrongPassphrase` if the tag does not match.
+    pub fn decrypt(&self, passphrase: &str) -> Result<KeySet, KeyFileError> {
+        if self.version != JSON_VERSION {
+            return Err(KeyFileError::InvalidFormat(format!(
+                "unsupported version {}",
+                self.version
+            )));
+        }
+        let crypto = &self.crypto;
+        if crypto.cipher != JSON_CIPHER || crypto.kdf != JSON_KDF {
+            return Err(KeyFileError::InvalidFormat(format!(
+                "unsupported cipher {} or kdf {}",
+                crypto.cipher, crypto.kdf
+            )));
+        }
+        let params = &crypto.kdfparams;
+        if !params.n.is_power_of_two() || params.dklen as usize != DKLEN {
+            return Err(KeyFileError::InvalidFormat(format!(
+                "unsupported scrypt n {} or dklen {}",
+                params.n, params.dklen
+            )));
+        }
+        let nonce = decode_hex("nonce", &crypto.cipherparams.nonce)?;
+        if nonce.len() != JSON_NONCE_LEN {
+            return Err(KeyFileError::InvalidFormat(format!(
+                "nonce of {} bytes",
+                nonce.len()
+            )));
+        }
+        let salt = decode_hex("salt", &params.salt)?;
+        let ciphertext = decode_hex("ciphertext", &crypto.ciphertext)?;
+        let log_n = params.n.trailing_zeros() as u8;
+        let derived = derive_key(passphrase, &salt, log_n, params.r, params.p)?;
+        // the tag authenticates the whole plaintext, so a corrupted ciphertext is
+        // indistinguishable from a wrong passphrase
+        let seed = Aes256Gcm::new(Key::from_slice(&derived))
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| KeyFileError::WrongPassphrase)?;
+        let privkey: Fr = BigUint::from_bytes_be(&seed).into();
+        let key = KeySet::new(privkey);
+        if key.pubkey.to_hex() != self.pubkey {
+            return Err(KeyFileError::PubkeyMismatch {
+                expected: self.pubkey.clone(),
+                actual: key.pubkey.to_hex(),
+            });
+        }
+        Ok(key)
+    }
+
+    /// Public key of the encrypted key
+    pub fn pubkey(&self) -> Result<U256, KeyFileError> {
+        U256::from_hex(&self.pubkey)
+            .map_err(|e| KeyFileError::InvalidFormat(format!("invalid pubkey: {}", e)))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self, KeyFileError> {
+        serde_json::from_slice(bytes).map_err(|e| KeyFileError::InvalidFormat(e.to_string()))
+    }
+}
+
+fn decode_hex(name: &str, value: &str) -> Result<Vec<u8>, KeyFileError> {
+    hex::decode(value.trim_start_matches("0x"))
+        .map_err(|e| KeyFileError::InvalidFormat(format!("invalid {}: {}", name, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{
+        common::signature::key_set::KeySet, ethereum_types::u32limb_trait::U32LimbTrait as _,
+    };
+
+    use super::{
+        encrypt, export_key_file, import_key_file, is_encrypted_key_file, JsonKeyFile,
+        KeyFileError, MAX_SCRYPT_LOG_N, MAX_SCRYPT_P,
+    };
+
+    // cheap scrypt cost for tests
+    const TEST_LOG_N: u8 = 4;
+
+    #[test]
+    fn test_plain_key_file() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let mut bytes = export_key_file(key, None);
+        assert_eq!(import_key_file(&bytes, None).unwrap().pubkey, key.pubkey);
+
+        bytes[0] ^= 1;
+        assert_eq!(
+            import_key_file(&bytes, None).unwrap_err(),
+            KeyFileError::ChecksumMismatch
+        );
+    }
+
+    #[test]
+    fn test_encrypted_key_file() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let bytes = encrypt(&export_key_file(key, None), "correct horse", TEST_LOG_N);
+        let imported = import_key_file(&bytes, Some("correct horse")).unwrap();
+        assert_eq!(imported.pubkey, key.pubkey);
+
+        assert_eq!(
+            import_key_file(&bytes, Some("battery staple")).unwrap_err(),
+            KeyFileError::WrongPassphrase
+        );
+        assert_eq!(
+            import_key_file(&bytes, None).unwrap_err(),
+            KeyFileError::PassphraseRequired
+        );
+    }
+
+    #[test]
+    fn test_encrypted_excessive_cost() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let mut bytes = encrypt(&export_key_file(key, None), "pass", TEST_LOG_N);
+        bytes[5] = MAX_SCRYPT_LOG_N + 1;
+        assert!(matches!(
+            import_key_file(&bytes, Some("pass")),
+            Err(KeyFileError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_encrypted_corrupted_checksum() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let mut plain = export_key_file(key, None);
+        plain[35] ^= 1;
+        let bytes = encrypt(&plain, "pass", TEST_LOG_N);
+        assert_eq!(
+            import_key_file(&bytes, Some("pass")).unwrap_err(),
+            KeyFileError::ChecksumMismatch
+        );
+    }
+
+    #[test]
+    fn test_json_key_file() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let bytes = JsonKeyFile::encrypt(key, "correct horse", TEST_LOG_N)
+            .to_json()
+            .into_bytes();
+        assert!(is_encrypted_key_file(&bytes));
+        let parsed = JsonKeyFile::from_json(&bytes).unwrap();
+        assert_eq!(parsed.pubkey().unwrap(), key.pubkey);
+
+        let imported = import_key_file(&bytes, Some("correct horse")).unwrap();
+        assert_eq!(imported.privkey, key.privkey);
+        assert_eq!(
+            import_key_file(&bytes, Some("battery staple")).unwrap_err(),
+            KeyFileError::WrongPassphrase
+        );
+        assert_eq!(
+            import_key_file(&bytes, None).unwrap_err(),
+            KeyFileError::PassphraseRequired
+        );
+    }
+
+    #[test]
+    fn test_json_key_file_tampering() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let json_key_file = JsonKeyFile::encrypt(key, "pass", TEST_LOG_N);
+
+        let mut other = json_key_file.clone();
+        other.pubkey = KeySet::rand(&mut rand::thread_rng()).pubkey.to_hex();
+        assert!(matches!(
+            other.decrypt("pass"),
+            Err(KeyFileError::PubkeyMismatch { .. })
+        ));
+
+        let mut truncated = json_key_file.clone();
+        truncated.crypto.ciphertext.truncate(10);
+        assert_eq!(
+            truncated.decrypt("pass").unwrap_err(),
+            KeyFileError::WrongPassphrase
+        );
+
+        // a crafted file cannot ask for an unbounded scrypt cost
+        let mut costly = json_key_file.clone();
+        costly.crypto.kdfparams.p = MAX_SCRYPT_P + 1;
+        assert!(matches!(
+            costly.decrypt("pass"),
+            Err(KeyFileError::InvalidFormat(_))
+        ));
+        let mut costly = json_key_file;
+        costly.crypto.kdfparams.n = 1 << (MAX_SCRYPT_LOG_N + 1);
+        assert!(matches!(
+            costly.decrypt("pass"),
+            Err(KeyFileError::InvalidFormat(_))
+        ));
+
+        assert!(matches!(
+            JsonKeyFile::from_json(b"{\"version\": 1}"),
+            Err(KeyFileError::InvalidFormat(_))
+        ));
+    }
+}
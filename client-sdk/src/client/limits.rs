@@ -0,0 +1,240 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use intmax2_interfaces::api::{
+    error::ServerError,
+    withdrawal_server::interface::{TokenLimit, WithdrawalLimits, WithdrawalServerClientInterface},
+};
+use intmax2_zkp::{
+    common::transfer::Transfer, constants::NUM_TRANSFERS_IN_TX, ethereum_types::u256::U256,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::utils::clock::global_clock;
+
+use super::error::ClientError;
+
+/// Seconds the limits fetched from the withdrawal server are reused
+pub const LIMITS_CACHE_TTL: u64 = 600;
+
+// withdrawal server URL -> (fetched at, limits)
+static LIMITS_CACHE: Mutex<BTreeMap<String, (u64, ProtocolLimits)>> = Mutex::new(BTreeMap::new());
+
+/// Limits enforced by the protocol and the withdrawal server. Amounts are in the
+/// smallest unit of the token, and tokens without an entry have no minimum.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtocolLimits {
+    pub min_withdrawal_per_token: BTreeMap<u32, U256>,
+    pub min_deposit_per_token: BTreeMap<u32, U256>,
+    pub max_transfers_per_tx: usize,
+    pub max_withdrawals_per_request: u32,
+}
+
+fn to_map(limits: &[TokenLimit]) -> BTreeMap<u32, U256> {
+    limits
+        .iter()
+        .map(|limit| (limit.token_index, limit.amount))
+        .collect()
+}
+
+impl ProtocolLimits {
+    /// Combine the limits of the withdrawal server with the circuit constants
+    pub fn new(withdrawal_limits: &WithdrawalLimits) -> Self {
+        Self {
+            min_withdrawal_per_token: to_map(&withdrawal_limits.min_withdrawals),
+            min_deposit_per_token: to_map(&withdrawal_limits.min_deposits),
+            max_transfers_per_tx: NUM_TRANSFERS_IN_TX,
+            max_withdrawals_per_request: withdrawal_limits.max_withdrawals_per_request,
+        }
+    }
+
+    /// The limits of a withdrawal server that does not advertise any: only the
+    /// circuit constants, and a withdrawal proof per request
+    pub fn unlimited() -> Self {
+        Self::new(&WithdrawalLimits {
+            min_withdrawals: vec![],
+            min_deposits: vec![],
+            max_withdrawals_per_request: 1,
+        })
+    }
+
+    pub fn check_withdrawal(&self, token_index: u32, amount: U256) -> Result<(), ClientError> {
+        check_min(
+            "minimum withdrawal",
+            &self.min_withdrawal_per_token,
+            token_index,
+            amount,
+        )
+    }
+
+    pub fn check_deposit(&self, token_index: u32, amount: U256) -> Result<(), ClientError> {
+        check_min(
+            "minimum deposit",
+            &self.min_deposit_per_token,
+            token_index,
+            amount,
+        )
+    }
+
+    /// Check the number of transfers of a tx, and the amount of its withdrawals
+    pub fn check_transfers(&self, transfers: &[Transfer]) -> Result<(), ClientError> {
+        if transfers.len() > self.max_transfers_per_tx {
+            return Err(ClientError::LimitViolation {
+                limit: "maximum transfers per tx".to_string(),
+                value: self.max_transfers_per_tx.to_string(),
+                actual: transfers.len().to_string(),
+            });
+        }
+        for transfer in transfers.iter().filter(|t| !t.recipient.is_pubkey) {
+            self.check_withdrawal(transfer.token_index, transfer.amount)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_min(
+    name: &str,
+    mins: &BTreeMap<u32, U256>,
+    token_index: u32,
+    amount: U256,
+) -> Result<(), ClientError> {
+    match mins.get(&token_index) {
+        Some(min) if amount < *min => Err(ClientError::LimitViolation {
+            limit: format!("{} of token {}", name, token_index),
+            value: min.to_string(),
+            actual: amount.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Fetch the limits of the withdrawal server, reusing the ones fetched from it
+/// within `LIMITS_CACHE_TTL` seconds. A server without the limits endpoint has no
+/// limits.
+pub async fn fetch_limits<W: WithdrawalServerClientInterface>(
+    withdrawal_server: &W,
+) -> Result<ProtocolLimits, ClientError> {
+    let now = global_clock().local_now();
+    let url = withdrawal_server.base_url().to_string();
+    if let Some((fetched_at, limits)) = LIMITS_CACHE.lock().unwrap().get(&url) {
+        if now < fetched_at + LIMITS_CACHE_TTL {
+            return Ok(limits.clone());
+        }
+    }
+    let limits = match withdrawal_server.limits().await {
+        Ok(limits) => ProtocolLimits::new(&limits),
+        Err(ServerError::ServerError(404, ..)) => {
+            log::warn!("{} does not advertise limits, assuming none", url);
+            ProtocolLimits::unlimited()
+        }
+        Err(e) => return Err(e.into()),
+    };
+    LIMITS_CACHE
+        .lock()
+        .unwrap()
+        .insert(url, (now, limits.clone()));
+    Ok(limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use intmax2_interfaces::api::withdrawal_server::{
+        interface::{TokenLimit, WithdrawalLimits},
+        types::GetLimitsResponse,
+    };
+    use intmax2_zkp::{
+        common::{generic_address::GenericAddress, transfer::Transfer},
+        ethereum_types::{address::Address, u256::U256, u32limb_trait::U32LimbTrait as _},
+    };
+
+    use crate::{
+        client::error::ClientError,
+        external_api::{
+            utils::mock_server::start_scripted_mock_server,
+            withdrawal_server::WithdrawalServerClient,
+        },
+    };
+
+    use super::{fetch_limits, ProtocolLimits};
+
+    fn amount(value: u32) -> U256 {
+        U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, value])
+    }
+
+    #[test]
+    fn test_below_minimum_withdrawal_is_rejected() {
+        let limits = ProtocolLimits::new(&WithdrawalLimits {
+            min_withdrawals: vec![TokenLimit {
+                token_index: 0,
+                amount: amount(1000),
+            }],
+            min_deposits: vec![],
+            max_withdrawals_per_request: 1,
+        });
+        let withdrawal = Transfer {
+            recipient: GenericAddress::from_address(Address::default()),
+            token_index: 0,
+            amount: amount(999),
+            ..Default::default()
+        };
+        let error = limits.check_transfers(&[withdrawal.clone()]).unwrap_err();
+        assert!(matches!(error, ClientError::LimitViolation { .. }));
+        assert_eq!(
+            error.to_string(),
+            "Limit violated: minimum withdrawal of token 0 is 1000, got 999"
+        );
+
+        // transfers and other tokens have no minimum
+        let transfer = Transfer {
+            recipient: GenericAddress::from_pubkey(U256::default()),
+            ..withdrawal.clone()
+        };
+        let other_token = Transfer {
+            token_index: 1,
+            ..withdrawal
+        };
+        assert!(limits.check_transfers(&[transfer, other_token]).is_ok());
+        assert!(limits.check_withdrawal(0, amount(1000)).is_ok());
+        assert!(limits.check_deposit(0, amount(1)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_limits_are_cached_per_server() {
+        let limits = WithdrawalLimits {
+            min_withdrawals: vec![TokenLimit {
+                token_index: 0,
+                amount: amount(1000),
+            }],
+            min_deposits: vec![],
+            max_withdrawals_per_request: 1,
+        };
+        let response = serde_json::to_string(&GetLimitsResponse {
+            limits: limits.clone(),
+        })
+        .unwrap();
+        let (limited_url, requests) =
+            start_scripted_mock_server(move |_, _| (200, response.clone()));
+        let (unlimited_url, _) =
+            start_scripted_mock_server(|_, _| (404, r#"{"error":"not found"}"#.to_string()));
+        let limited =
+            WithdrawalServerClient::new(&limited_url).with_timeout(Duration::from_secs(5));
+        let unlimited =
+            WithdrawalServerClient::new(&unlimited_url).with_timeout(Duration::from_secs(5));
+
+        // a server without the endpoint has no limits, and does not share the cache
+        // of another server
+        let expected = ProtocolLimits::new(&limits);
+        assert_eq!(fetch_limits(&limited).await.unwrap(), expected);
+        assert_eq!(
+            fetch_limits(&unlimited).await.unwrap(),
+            ProtocolLimits::unlimited()
+        );
+        assert_eq!(fetch_limits(&limited).await.unwrap(), expected);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert!(ProtocolLimits::unlimited()
+            .check_withdrawal(0, amount(1))
+            .is_ok());
+    }
+}
@@ -1,8 +1,34 @@
 pub mod account;
+pub mod backup_health;
+pub mod balance_history;
 pub mod balance_logic;
+pub mod balance_view;
+pub mod checkpoint;
 pub mod client;
+pub mod compatibility;
 pub mod config;
+pub mod consolidation;
+pub mod diagnostics;
 pub mod error;
+pub mod external_deposit;
+pub mod fee_dispute;
+pub mod fee_quote;
 pub mod history;
+pub mod history_index;
+pub mod key_file;
+pub mod limits;
+pub mod observer;
+pub mod provisional;
+pub mod receipt;
+pub mod replay;
+pub mod rotation;
+pub mod session;
+pub mod signer;
 pub mod strategy;
+pub mod template;
+#[cfg(feature = "test-hooks")]
+pub mod test_hooks;
+pub mod tx_status;
 pub mod utils;
+pub mod withdrawal_onchain;
+pub mod witness_bundle;
@@ -0,0 +1,661 @@
+//! Watch-only view of an account from its pubkey alone, e.g. to monitor a treasury
+//! one does not hold the key of.
+//!
+//! Only what is public can be observed: the on-chain deposits to the pubkey salt
+//! hashes the owner disclosed, the posted blocks whose sender list contains the
+//! pubkey, and the registration of the pubkey in the account tree. Balances and the
+//! contents of transfers and txs are encrypted to the key, so the methods asking
+//! for them fail with `ClientError::RequiresKey` instead of returning partial data.
+//! Disclosing a salt hash links the deposits made to it with the account, and
+//! nothing more.
+
+use std::fmt;
+
+use futures::future::try_join_all;
+
+use intmax2_interfaces::{
+    api::validity_prover::interface::ValidityProverClientInterface, data::user_data::UserData,
+};
+use intmax2_zkp::{
+    common::trees::sender_tree::SenderLeaf,
+    ethereum_types::{
+        address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::contract::{
+    interface::ContractReader, liquidity_contract::OnchainDeposit,
+};
+
+use super::{balance_view::BalanceView, error::ClientError, history::HistoryEntry};
+
+/// What cannot be observed without the key of the account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyedData {
+    Balances,
+    TransferContents,
+}
+
+impl fmt::Display for KeyedData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyedData::Balances => write!(f, "balances"),
+            KeyedData::TransferContents => write!(f, "transfer contents"),
+        }
+    }
+}
+
+pub struct ObserverConfig<V: ValidityProverClientInterface, R: ContractReader> {
+    pub validity_prover: V,
+    pub liquidity_contract: R,
+    /// First block scanned for the txs of the account. Block 0 is the genesis
+    /// block, which has no txs. If not set, only the blocks posted after the first
+    /// scan are scanned.
+    pub from_block_number: Option<u32>,
+}
+
+/// Blocks scanned by one call of `get_block_inclusions`
+pub const MAX_BLOCKS_PER_SCAN: u32 = 1000;
+
+// sender leaves requested at once while scanning
+const SCAN_BATCH_SIZE: u32 = 16;
+
+/// A deposit of the liquidity contract to a registered pubkey salt hash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObservedDeposit {
+    pub deposit_id: u64,
+    /// L1 address of the depositor
+    pub sender: Address,
+    pub pubkey_salt_hash: Bytes32,
+    pub token_index: u32,
+    pub amount: U256,
+    pub deposited_at: u64,
+}
+
+impl From<OnchainDeposit> for ObservedDeposit {
+    fn from(deposit: OnchainDeposit) -> Self {
+        Self {
+            deposit_id: deposit.deposit_id,
+            sender: Address::from_bytes_be(deposit.sender.as_bytes()),
+            pubkey_salt_hash: deposit.pubkey_salt_hash,
+            token_index: deposit.token_index,
+            amount: deposit.amount,
+            deposited_at: deposit.deposited_at,
+        }
+    }
+}
+
+/// A posted block whose sender list contains the pubkey
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockInclusion {
+    pub block_number: u32,
+    pub tx_tree_root: Bytes32,
+    /// Whether the account signed the block. A tx of an account that did not sign
+    /// is not executed.
+    pub did_return_sig: bool,
+    pub is_valid_block: bool,
+}
+
+impl BlockInclusion {
+    /// Whether the tx of the account was executed in this block
+    pub fn is_executed(&self) -> bool {
+        self.did_return_sig && self.is_valid_block
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationStatus {
+    /// Id of the pubkey in the account tree, None until its first tx is executed
+    pub account_id: Option<u64>,
+    /// Block of the account tree the id was looked up in
+    pub block_number: u32,
+}
+
+impl RegistrationStatus {
+    pub fn is_registered(&self) -> bool {
+        self.account_id.is_some()
+    }
+}
+
+/// Keyless observer of the account of `pubkey`
+pub struct Observer<V: ValidityProverClientInterface, R: ContractReader> {
+    pub config: ObserverConfig<V, R>,
+    pub pubkey: U256,
+    pubkey_salt_hashes: Vec<Bytes32>,
+    next_block_number: Option<u32>,
+    latest_block_number: u32,
+}
+
+impl<V: ValidityProverClientInterface, R: ContractReader> Observer<V, R> {
+    pub fn new(config: ObserverConfig<V, R>, pubkey: U256) -> Self {
+        // the genesis block has no sender
+        let next_block_number = config.from_block_number.map(|block| block.max(1));
+        Self {
+            config,
+            pubkey,
+            pubkey_salt_hashes: Vec::new(),
+            next_block_number,
+            latest_block_number: 0,
+        }
+    }
+
+    /// Watch the deposits to `pubkey_salt_hash`, a hash the owner generated for a
+    /// deposit and disclosed. The salt hashes cannot be derived from the pubkey.
+    pub fn register_salt_hash(&mut self, pubkey_salt_hash: Bytes32) {
+        if !self.pubkey_salt_hashes.contains(&pubkey_salt_hash) {
+            self.pubkey_salt_hashes.push(pubkey_salt_hash);
+        }
+    }
+
+    pub fn salt_hashes(&self) -> &[Bytes32] {
+        &self.pubkey_salt_hashes
+    }
+
+    /// Next block `get_block_inclusions` will scan, None before the first scan of
+    /// an observer without `from_block_number`
+    pub fn next_block_number(&self) -> Option<u32> {
+        self.next_block_number
+    }
+
+    /// Whether the last `get_block_inclusions` scanned up to the latest block
+    pub fn is_caught_up(&self) -> bool {
+        self.next_block_number
+            .is_some_and(|next_block_number| next_block_number > self.latest_block_number)
+    }
+
+    /// The deposits of the liquidity contract to the registered salt hashes, in the
+    /// order of their ids. Whether a deposit was relayed to L2 is not observable,
+    /// as its deposit hash depends on data only the owner has.
+    pub async fn get_onchain_deposits(&self) -> Result<Vec<ObservedDeposit>, ClientError> {
+        let mut deposits = Vec::new();
+        for pubkey_salt_hash in &self.pubkey_salt_hashes {
+            let onchain = self
+                .config
+                .liquidity_contract
+                .get_deposits_by_salt_hash(*pubkey_salt_hash)
+                .await?;
+            deposits.extend(onchain.into_iter().map(ObservedDeposit::from));
+        }
+        deposits.sort_by_key(|deposit| deposit.deposit_id);
+        Ok(deposits)
+    }
+
+    /// The blocks with a tx of the account, from the first block not scanned by a
+    /// previous call up to the latest block of the validity prover, or at most
+    /// `MAX_BLOCKS_PER_SCAN` blocks. Calling it again returns only the blocks
+    /// scanned since; see `is_caught_up`.
+    pub async fn get_block_inclusions(&mut self) -> Result<Vec<BlockInclusion>, ClientError> {
+        self.latest_block_number = self.config.validity_prover.get_block_number().await?;
+        let from_block_number = *self
+            .next_block_number
+            .get_or_insert(self.latest_block_number + 1);
+        let to_block_number = self
+            .latest_block_number
+            .min(from_block_number.saturating_add(MAX_BLOCKS_PER_SCAN - 1));
+        let mut inclusions = Vec::new();
+        let mut batch_start = from_block_number;
+        while batch_start <= to_block_number {
+            let batch_end = to_block_number.min(batch_start + SCAN_BATCH_SIZE - 1);
+            let validity_prover = &self.config.validity_prover;
+            let batch = try_join_all((batch_start..=batch_end).map(|block_number| async move {
+                let sender_leaves = validity_prover
+                    .get_sender_leaves(block_number)
+                    .await?
+                    .ok_or_else(|| {
+                        ClientError::UnexpectedError(format!(
+                            "Sender leaves of block {} not found",
+                            block_number
+                        ))
+                    })?;
+                Ok::<_, ClientError>((block_number, sender_leaves))
+            }))
+            .await?;
+            for (block_number, sender_leaves) in batch {
+                if let Some(inclusion) = self.inclusion(block_number, &sender_leaves).await? {
+                    inclusions.push(inclusion);
+                }
+            }
+            batch_start = batch_end + 1;
+            self.next_block_number = Some(batch_start);
+        }
+        Ok(inclusions)
+    }
+
+    async fn inclusion(
+        &self,
+        block_number: u32,
+        sender_leaves: &[SenderLeaf],
+    ) -> Result<Option<BlockInclusion>, ClientError> {
+        let Some(leaf) = sender_leaves.iter().find(|leaf| leaf.sender == self.pubkey) else {
+            return Ok(None);
+        };
+        let validity_pis = self
+            .config
+            .validity_prover
+            .get_validity_pis(block_number)
+            .await?
+            .ok_or_else(|| {
+                ClientError::UnexpectedError(format!(
+                    "Validity public inputs of block {} not found",
+                    block_number
+                ))
+            })?;
+        Ok(Some(BlockInclusion {
+            block_number,
+            tx_tree_root: validity_pis.tx_tree_root,
+            did_return_sig: leaf.did_return_sig,
+            is_valid_block: validity_pis.is_valid_block,
+        }))
+    }
+
+    pub async fn get_registration_status(&self) -> Result<RegistrationStatus, ClientError> {
+        let account_info = self
+            .config
+            .validity_prover
+            .get_account_info(self.pubkey)
+            .await?;
+        Ok(RegistrationStatus {
+            account_id: account_info.account_id,
+            block_number: account_info.block_number,
+        })
+    }
+
+    /// Always fails: the balances are in the user data, encrypted to the key
+    pub async fn get_balance_view(&self) -> Result<BalanceView, ClientError> {
+        Err(ClientError::RequiresKey(KeyedData::Balances))
+    }
+
+    /// Always fails: the user data is encrypted to the key
+    pub async fn get_user_data(&self) -> Result<UserData, ClientError> {
+        Err(ClientError::RequiresKey(KeyedData::Balances))
+    }
+
+    /// Always fails: the transfers and txs are encrypted to the key
+    pub async fn fetch_history(&self) -> Result<Vec<HistoryEntry>, ClientError> {
+        Err(ClientError::RequiresKey(KeyedData::TransferContents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use ethers::types::{Address as EthAddress, H256};
+    use intmax2_interfaces::{
+        api::{
+            error::ServerError,
+            validity_prover::interface::{AccountInfo, DepositInfo},
+        },
+        data::deposit_data::TokenType,
+    };
+    use intmax2_zkp::{
+        circuits::validity::validity_pis::ValidityPublicInputs,
+        common::{
+            deposit::get_pubkey_salt_hash,
+            salt::Salt,
+            signature::key_set::KeySet,
+            trees::{
+                account_tree::AccountMembershipProof, block_hash_tree::BlockHashMerkleProof,
+                deposit_tree::DepositMerkleProof, sender_tree::SenderLeaf,
+            },
+            witness::update_witness::UpdateWitness,
+        },
+    };
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig,
+    };
+
+    use crate::{
+        client::utils::generate_salt,
+        external_api::contract::{interface::BlockchainError, liquidity_contract::WithdrawalEvent},
+    };
+
+    use super::*;
+
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+    const D: usize = 2;
+
+    #[derive(Default)]
+    struct ChainState {
+        deposits: Vec<OnchainDeposit>,
+        // the blocks after genesis
+        blocks: Vec<(ValidityPublicInputs, Vec<SenderLeaf>)>,
+        registered: Vec<U256>,
+    }
+
+    /// Liquidity contract and validity prover over the same in-memory chain
+    #[derive(Clone, Default)]
+    struct MockChain(Arc<Mutex<ChainState>>);
+
+    impl MockChain {
+        fn deposit(&self, pubkey_salt_hash: Bytes32, token_index: u32, amount: u128) -> u64 {
+            let mut state = self.0.lock().unwrap();
+            let deposit_id = state.deposits.len() as u64 + 1;
+            state.deposits.push(OnchainDeposit {
+                deposit_id,
+                sender: EthAddress::random(),
+                pubkey_salt_hash,
+                token_index,
+                amount: U256::from(amount),
+                deposited_at: 1_700_000_000 + deposit_id,
+            });
+            deposit_id
+        }
+
+        // post a block of `senders`, registering those who signed
+        fn post_block(&self, senders: &[(U256, bool)]) -> (u32, Bytes32) {
+            let mut rng = rand::thread_rng();
+            let mut state = self.0.lock().unwrap();
+            let block_number = state.blocks.len() as u32 + 1;
+            let mut pis = ValidityPublicInputs::genesis();
+            pis.public_state.block_number = block_number;
+            pis.tx_tree_root = Bytes32::rand(&mut rng);
+            pis.is_valid_block = true;
+            let leaves = senders
+                .iter()
+                .map(|&(sender, did_return_sig)| SenderLeaf {
+                    sender,
+                    did_return_sig,
+                })
+                .collect::<Vec<_>>();
+            for leaf in &leaves {
+                if leaf.did_return_sig && !state.registered.contains(&leaf.sender) {
+                    state.registered.push(leaf.sender);
+                }
+            }
+            let tx_tree_root = pis.tx_tree_root;
+            state.blocks.push((pis, leaves));
+            (block_number, tx_tree_root)
+        }
+    }
+
+    // the calls the observer does not make fail
+    fn not_used() -> BlockchainError {
+        BlockchainError::InternalError("not used by the observer".to_string())
+    }
+
+    fn not_served() -> ServerError {
+        ServerError::InternalError("not served by the mock chain".to_string())
+    }
+
+    #[async_trait(?Send)]
+    impl ContractReader for MockChain {
+        async fn get_block_number(&self) -> Result<u64, BlockchainError> {
+            Err(not_used())
+        }
+
+        async fn get_token_index(
+            &self,
+            _token_type: TokenType,
+            _token_address: Address,
+            _token_id: U256,
+        ) -> Result<Option<u32>, BlockchainError> {
+            Err(not_used())
+        }
+
+        async fn get_token_info(
+            &self,
+            _token_index: u32,
+        ) -> Result<(TokenType, Address, U256), BlockchainError> {
+            Err(not_used())
+        }
+
+        async fn get_deposit(
+            &self,
+            _deposit_id: u64,
+        ) -> Result<Option<OnchainDeposit>, BlockchainError> {
+            Err(not_used())
+        }
+
+        async fn get_deposits_in_tx(
+            &self,
+            _tx_hash: H256,
+        ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+            Err(not_used())
+        }
+
+        async fn get_deposits_by_salt_hash(
+            &self,
+            pubkey_salt_hash: Bytes32,
+        ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+            let state = self.0.lock().unwrap();
+            Ok(state
+                .deposits
+                .iter()
+                .filter(|deposit| deposit.pubkey_salt_hash == pubkey_salt_hash)
+                .cloned()
+                .collect())
+        }
+
+        async fn get_withdrawal_events(
+            &self,
+            _withdrawal_hash: Bytes32,
+        ) -> Result<Vec<WithdrawalEvent>, BlockchainError> {
+            Err(not_used())
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl ValidityProverClientInterface for MockChain {
+        async fn get_block_number(&self) -> Result<u32, ServerError> {
+            Ok(self.0.lock().unwrap().blocks.len() as u32)
+        }
+
+        async fn get_update_witness(
+            &self,
+            _pubkey: U256,
+            _root_block_number: u32,
+            _leaf_block_number: u32,
+            _is_prev_account_tree: bool,
+        ) -> Result<UpdateWitness<F, C, D>, ServerError> {
+            Err(not_served())
+        }
+
+        async fn get_account_membership_proof(
+            &self,
+            _pubkey: U256,
+            _block_number: u32,
+        ) -> Result<AccountMembershipProof, ServerError> {
+            Err(not_served())
+        }
+
+        async fn get_deposit_info(
+            &self,
+            _deposit_hash: Bytes32,
+        ) -> Result<Option<DepositInfo>, ServerError> {
+            Err(not_served())
+        }
+
+        async fn get_block_number_by_tx_tree_root(
+            &self,
+            _tx_tree_root: Bytes32,
+        ) -> Result<Option<u32>, ServerError> {
+            Err(not_served())
+        }
+
+        async fn get_validity_pis(
+            &self,
+            block_number: u32,
+        ) -> Result<Option<ValidityPublicInputs>, ServerError> {
+            let state = self.0.lock().unwrap();
+            Ok(block_number
+                .checked_sub(1)
+                .and_then(|i| state.blocks.get(i as usize))
+                .map(|(pis, _)| pis.clone()))
+        }
+
+        async fn get_sender_leaves(
+            &self,
+            block_number: u32,
+        ) -> Result<Option<Vec<SenderLeaf>>, ServerError> {
+            let state = self.0.lock().unwrap();
+            Ok(block_number
+                .checked_sub(1)
+                .and_then(|i| state.blocks.get(i as usize))
+                .map(|(_, leaves)| leaves.clone()))
+        }
+
+        async fn get_block_merkle_proof(
+            &self,
+            _root_block_number: u32,
+            _leaf_block_number: u32,
+        ) -> Result<BlockHashMerkleProof, ServerError> {
+            Err(not_served())
+        }
+
+        async fn get_deposit_merkle_proof(
+            &self,
+            _block_number: u32,
+            _deposit_index: u32,
+        ) -> Result<DepositMerkleProof, ServerError> {
+            Err(not_served())
+        }
+
+        async fn get_account_info(&self, pubkey: U256) -> Result<AccountInfo, ServerError> {
+            let state = self.0.lock().unwrap();
+            Ok(AccountInfo {
+                // id 0 is the dummy account
+                account_id: state
+                    .registered
+                    .iter()
+                    .position(|registered| *registered == pubkey)
+                    .map(|i| i as u64 + 1),
+                block_number: state.blocks.len() as u32,
+            })
+        }
+    }
+
+    /// The owner of the account, acting on the chain with its key
+    struct KeyedAccount {
+        key: KeySet,
+        chain: MockChain,
+    }
+
+    impl KeyedAccount {
+        fn deposit(&self, salt: Salt, amount: u128) -> u64 {
+            self.chain
+                .deposit(get_pubkey_salt_hash(self.key.pubkey, salt), 0, amount)
+        }
+
+        fn send(&self, did_return_sig: bool) -> (u32, Bytes32) {
+            let mut rng = rand::thread_rng();
+            self.chain.post_block(&[
+                (U256::rand(&mut rng), true),
+                (self.key.pubkey, did_return_sig),
+            ])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observe_account_of_another_client() {
+        let mut rng = rand::thread_rng();
+        let chain = MockChain::default();
+        let owner = KeyedAccount {
+            key: KeySet::rand(&mut rng),
+            chain: chain.clone(),
+        };
+        let mut observer = Observer::new(
+            ObserverConfig {
+                validity_prover: chain.clone(),
+                liquidity_contract: chain.clone(),
+                from_block_number: Some(0),
+            },
+            owner.key.pubkey,
+        );
+        assert!(!observer
+            .get_registration_status()
+            .await
+            .unwrap()
+            .is_registered());
+
+        // the owner discloses the salt hash of one deposit, not of the other
+        let disclosed_salt = generate_salt();
+        let disclosed_id = owner.deposit(disclosed_salt, 100);
+        owner.deposit(generate_salt(), 200);
+        chain.deposit(Bytes32::rand(&mut rng), 0, 300);
+        observer.register_salt_hash(get_pubkey_salt_hash(owner.key.pubkey, disclosed_salt));
+        let deposits = observer.get_onchain_deposits().await.unwrap();
+        assert_eq!(deposits.len(), 1);
+        assert_eq!(deposits[0].deposit_id, disclosed_id);
+        assert_eq!(deposits[0].amount, U256::from(100u128));
+
+        // blocks of others, an unsigned send, then a send
+        chain.post_block(&[(U256::rand(&mut rng), true)]);
+        let (unsigned_block, _) = owner.send(false);
+        let (sent_block, sent_root) = owner.send(true);
+        let inclusions = observer.get_block_inclusions().await.unwrap();
+        assert_eq!(
+            inclusions
+                .iter()
+                .map(|inclusion| inclusion.block_number)
+                .collect::<Vec<_>>(),
+            vec![unsigned_block, sent_block]
+        );
+        assert!(!inclusions[0].is_executed());
+        assert!(inclusions[1].is_executed());
+        assert_eq!(inclusions[1].tx_tree_root, sent_root);
+        let status = observer.get_registration_status().await.unwrap();
+        assert_eq!(status.account_id, Some(2));
+        assert_eq!(status.block_number, sent_block);
+
+        // only the blocks posted since are returned
+        assert!(observer.get_block_inclusions().await.unwrap().is_empty());
+        let (next_block, _) = owner.send(true);
+        let inclusions = observer.get_block_inclusions().await.unwrap();
+        assert_eq!(inclusions.len(), 1);
+        assert_eq!(inclusions[0].block_number, next_block);
+        assert_eq!(observer.next_block_number(), Some(next_block + 1));
+        assert!(observer.is_caught_up());
+
+        // nothing that needs the key
+        assert!(matches!(
+            observer.get_balance_view().await,
+            Err(ClientError::RequiresKey(KeyedData::Balances))
+        ));
+        assert!(matches!(
+            observer.fetch_history().await,
+            Err(ClientError::RequiresKey(KeyedData::TransferContents))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_block_scan_is_bounded() {
+        let mut rng = rand::thread_rng();
+        let chain = MockChain::default();
+        let pubkey = U256::rand(&mut rng);
+        for _ in 0..MAX_BLOCKS_PER_SCAN {
+            chain.post_block(&[(U256::rand(&mut rng), true)]);
+        }
+        let (sent_block, _) = chain.post_block(&[(pubkey, true)]);
+        let config = |from_block_number| ObserverConfig {
+            validity_prover: chain.clone(),
+            liquidity_contract: chain.clone(),
+            from_block_number,
+        };
+
+        let mut observer = Observer::new(config(Some(1)), pubkey);
+        assert!(observer.get_block_inclusions().await.unwrap().is_empty());
+        assert_eq!(observer.next_block_number(), Some(MAX_BLOCKS_PER_SCAN + 1));
+        assert!(!observer.is_caught_up());
+        let inclusions = observer.get_block_inclusions().await.unwrap();
+        assert_eq!(inclusions.len(), 1);
+        assert_eq!(inclusions[0].block_number, sent_block);
+        assert!(observer.is_caught_up());
+
+        // without a starting block, only the blocks posted from then on
+        let mut observer = Observer::new(config(None), pubkey);
+        assert!(observer.get_block_inclusions().await.unwrap().is_empty());
+        assert_eq!(observer.next_block_number(), Some(sent_block + 1));
+        let (next_block, _) = chain.post_block(&[(pubkey, true)]);
+        let inclusions = observer.get_block_inclusions().await.unwrap();
+        assert_eq!(inclusions.len(), 1);
+        assert_eq!(inclusions[0].block_number, next_block);
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use intmax2_zkp::{
+    common::transfer::Transfer,
+    ethereum_types::{bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+/// Seconds a failed debit is still reported before it is forgotten
+pub const FAILED_DELTA_RETENTION: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeltaKind {
+    /// Amount of a finalized tx of the account
+    Debit,
+    /// Amount of an incoming transfer not yet received by a sync
+    Credit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeltaStatus {
+    /// The tx is not in a block yet
+    Pending,
+    /// The tx is in a block that the balance proof does not cover yet
+    Settled,
+    /// The tx was not included before the tx timeout, so the amount is not applied
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Confidence {
+    /// The tx may still fail
+    Low,
+    /// The tx is in a block, only the sync is missing
+    High,
+}
+
+/// Change of a balance that the balance proof does not reflect yet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvisionalDelta {
+    pub kind: DeltaKind,
+    pub token_index: u32,
+    pub amount: U256,
+    pub tx_tree_root: Bytes32,
+    /// Server time at which the delta was recorded, or of the transfer for credits
+    pub created_at: u64,
+    pub status: DeltaStatus,
+    pub block_number: Option<u32>,
+}
+
+impl ProvisionalDelta {
+    pub fn confidence(&self) -> Confidence {
+        match self.status {
+            DeltaStatus::Settled => Confidence::High,
+            DeltaStatus::Pending | DeltaStatus::Failed => Confidence::Low,
+        }
+    }
+
+    /// Whether the delta counts in the optimistic balance
+    pub fn is_applied(&self) -> bool {
+        self.status != DeltaStatus::Failed
+    }
+}
+
+/// Persistent storage of the provisional debits of each account, e.g. a file for
+/// the CLI. Credits are not stored since the store vault already keeps the
+/// incoming transfers.
+pub trait ProvisionalStore: std::fmt::Debug {
+    fn load(&self, pubkey: U256) -> Vec<ProvisionalDelta>;
+
+    fn save(&self, pubkey: U256, deltas: &[ProvisionalDelta]);
+}
+
+/// Store that lives as long as the process
+#[derive(Debug, Default)]
+pub struct MemoryProvisionalStore {
+    // by hex pubkey
+    deltas: Mutex<HashMap<String, Vec<ProvisionalDelta>>>,
+}
+
+impl ProvisionalStore for MemoryProvisionalStore {
+    fn load(&self, pubkey: U256) -> Vec<ProvisionalDelta> {
+        self.deltas
+            .lock()
+            .unwrap()
+            .get(&pubkey.to_hex())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn save(&self, pubkey: U256, deltas: &[ProvisionalDelta]) {
+        self.deltas
+            .lock()
+            .unwrap()
+            .insert(pubkey.to_hex(), deltas.to_vec());
+    }
+}
+
+static GLOBAL_PROVISIONAL_STORE: OnceLock<Arc<MemoryProvisionalStore>> = OnceLock::new();
+
+/// In-memory store shared by the clients of the process, for environments such as
+/// the browser where a client is built for every call
+pub fn global_provisional_store() -> Arc<MemoryProvisionalStore> {
+    GLOBAL_PROVISIONAL_STORE
+        .get_or_init(|| Arc::new(MemoryProvisionalStore::default()))
+        .clone()
+}
+
+/// One debit per token of the transfers of a finalized tx
+pub fn debits_of_tx(
+    transfers: &[Transfer],
+    tx_tree_root: Bytes32,
+    created_at: u64,
+) -> Vec<ProvisionalDelta> {
+    let mut amounts = BTreeMap::<u32, BigUint>::new();
+    for transfer in transfers {
+        *amounts.entry(transfer.token_index).or_default() += BigUint::from(transfer.amount);
+    }
+    amounts
+        .into_iter()
+        .map(|(token_index, amount)| ProvisionalDelta {
+            kind: DeltaKind::Debit,
+            token_index,
+            amount: amount.try_into().unwrap(),
+            tx_tree_root,
+            created_at,
+            status: DeltaStatus::Pending,
+            block_number: None,
+        })
+        .collect()
+}
+
+/// Update the debits with the blocks their txs were included in, given by tx tree
+/// root. Debits covered by the balance proof at `user_block_number` are removed,
+/// and those still not included after `tx_timeout` are marked as failed.
+pub fn reconcile_debits(
+    deltas: Vec<ProvisionalDelta>,
+    user_block_number: u32,
+    inclusion_blocks: &HashMap<Bytes32, u32>,
+    now: u64,
+    tx_timeout: u64,
+) -> Vec<ProvisionalDelta> {
+    deltas
+        .into_iter()
+        .filter_map(|mut delta| {
+            match inclusion_blocks.get(&delta.tx_tree_root) {
+                Some(&block_number) if block_number <= user_block_number => return None,
+                Some(&block_number) => {
+                    delta.status = DeltaStatus::Settled;
+                    delta.block_number = Some(block_number);
+                }
+                None if delta.created_at + tx_timeout < now => {
+                    if delta.created_at + FAILED_DELTA_RETENTION < now {
+                        return None;
+                    }
+                    delta.status = DeltaStatus::Failed;
+                }
+                None => {}
+            }
+            Some(delta)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptimisticBalance {
+    pub token_index: u32,
+    /// Balance of the balance proof
+    pub settled: U256,
+    /// `settled` with the applied deltas of the token. A debit larger than the
+    /// balance leaves 0.
+    pub amount: U256,
+}
+
+/// Balances including the txs not yet reflected in the balance proof
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptimisticBalances {
+    pub balances: Vec<OptimisticBalance>,
+    pub deltas: Vec<ProvisionalDelta>,
+}
+
+impl OptimisticBalances {
+    pub fn new(settled: BTreeMap<u32, U256>, deltas: Vec<ProvisionalDelta>) -> Self {
+        let mut amounts = settled
+            .iter()
+            .map(|(token_index, amount)| (*token_index, BigUint::from(*amount)))
+            .collect::<BTreeMap<_, _>>();
+        for delta in deltas.iter().filter(|delta| delta.is_applied()) {
+            let amount = amounts.entry(delta.token_index).or_default();
+            let delta_amount = BigUint::from(delta.amount);
+            match delta.kind {
+                DeltaKind::Credit => *amount += delta_amount,
+                DeltaKind::Debit if *amount >= delta_amount => *amount -= delta_amount,
+                DeltaKind::Debit => *amount = BigUint::default(),
+            }
+        }
+        let balances = amounts
+            .into_iter()
+            .map(|(token_index, amount)| OptimisticBalance {
+                token_index,
+                settled: settled.get(&token_index).copied().unwrap_or_default(),
+                amount: amount.try_into().unwrap(),
+            })
+            .collect();
+        Self { balances, deltas }
+    }
+
+    pub fn get(&self, token_index: u32) -> Option<&OptimisticBalance> {
+        self.balances
+            .iter()
+            .find(|balance| balance.token_index == token_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::common::generic_address::GenericAddress;
+
+    use super::*;
+
+    fn value(value: u32) -> U256 {
+        U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, value])
+    }
+
+    fn transfer(token_index: u32, amount: u32) -> Transfer {
+        Transfer {
+            recipient: GenericAddress::from_pubkey(U256::default()),
+            token_index,
+            amount: value(amount),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_failed_tx_restores_provisional_debit() {
+        let store = MemoryProvisionalStore::default();
+        let pubkey = value(1);
+        let tx_tree_root = Bytes32::default();
+        let settled = BTreeMap::from([(0, value(100)), (1, value(5))]);
+
+        // finalized at time 1000: 30 + 20 of token 0 leave right away
+        let debits = debits_of_tx(&[transfer(0, 30), transfer(0, 20)], tx_tree_root, 1000);
+        store.save(pubkey, &debits);
+        let balances = OptimisticBalances::new(settled.clone(), store.load(pubkey));
+        assert_eq!(balances.get(0).unwrap().amount, value(50));
+        assert_eq!(balances.get(0).unwrap().settled, value(100));
+        assert_eq!(balances.get(1).unwrap().amount, value(5));
+        assert_eq!(balances.deltas[0].confidence(), Confidence::Low);
+
+        // still within the tx timeout: unchanged
+        let deltas = reconcile_debits(store.load(pubkey), 10, &HashMap::new(), 1050, 60);
+        assert_eq!(deltas[0].status, DeltaStatus::Pending);
+
+        // never included: flagged, and the balance is back
+        let deltas = reconcile_debits(deltas, 10, &HashMap::new(), 1100, 60);
+        store.save(pubkey, &deltas);
+        assert_eq!(deltas[0].status, DeltaStatus::Failed);
+        let balances = OptimisticBalances::new(settled, store.load(pubkey));
+        assert_eq!(balances.get(0).unwrap().amount, value(100));
+        assert_eq!(balances.deltas.len(), 1);
+
+        // forgotten after the retention
+        let deltas = reconcile_debits(
+            deltas,
+            10,
+            &HashMap::new(),
+            1000 + FAILED_DELTA_RETENTION + 1,
+            60,
+        );
+        assert!(deltas.is_empty());
+    }
+
+    #[test]
+    fn test_included_debit_is_removed_once_synced() {
+        let tx_tree_root = Bytes32::default();
+        let debits = debits_of_tx(&[transfer(0, 30)], tx_tree_root, 1000);
+        let inclusion_blocks = HashMap::from([(tx_tree_root, 12)]);
+
+        let deltas = reconcile_debits(debits, 10, &inclusion_blocks, 2000, 60);
+        assert_eq!(deltas[0].status, DeltaStatus::Settled);
+        assert_eq!(deltas[0].confidence(), Confidence::High);
+        let deltas = reconcile_debits(deltas, 12, &inclusion_blocks, 2000, 60);
+        assert!(deltas.is_empty());
+    }
+}
@@ -0,0 +1,433 @@
+use intmax2_interfaces::{
+    api::{
+        balance_prover::interface::BalanceProverClientInterface,
+        block_builder::interface::BlockBuilderClientInterface,
+        error::ServerError,
+        store_vault_server::interface::{DataType, StoreVaultClientInterface},
+        validity_prover::interface::ValidityProverClientInterface,
+        withdrawal_server::interface::WithdrawalServerClientInterface,
+    },
+    data::transfer_data::TransferData,
+};
+use intmax2_zkp::{
+    common::{
+        generic_address::GenericAddress,
+        signature::key_set::KeySet,
+        transfer::Transfer,
+        trees::{
+            sender_tree::SenderLeaf, transfer_tree::TransferMerkleProof, tx_tree::TxMerkleProof,
+        },
+        tx::Tx,
+    },
+    ethereum_types::{bytes32::Bytes32, u256::U256},
+    utils::poseidon_hash_out::PoseidonHashOut,
+};
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use serde::{Deserialize, Serialize};
+
+use super::{client::Client, error::ClientError};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Proof that a transfer reached its recipient, for a third party. The transfer
+/// is linked to its tx by the transfer tree, and the tx to the tx tree root
+/// posted in `block_number`, whose sender leaves show that the sender signed it.
+/// Only the recipient can export it, as the transfer
+/// data is encrypted for them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiveReceipt {
+    pub transfer_uuid: String,
+    /// Sender as written in the transfer data, checked against the sender leaf of
+    /// the tx
+    pub sender: U256,
+    pub transfer: Transfer,
+    pub transfer_index: u32,
+    pub transfer_merkle_proof: TransferMerkleProof,
+    pub tx: Tx,
+    pub tx_index: u32,
+    pub tx_merkle_proof: TxMerkleProof,
+    pub tx_tree_root: Bytes32,
+    pub block_number: u32,
+}
+
+/// What a block posted and whether it took effect, as the verifier knows it.
+/// Fetched with `from_validity_prover`, whose tx tree root can be replaced by one
+/// from a source the verifier trusts, e.g. the rollup contract's `BlockPosted`
+/// event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RollupReference {
+    pub block_number: u32,
+    pub tx_tree_root: Bytes32,
+    /// Whether the validity proof accepted the block. The txs of an invalid
+    /// block are not executed.
+    pub is_valid_block: bool,
+    /// Sender leaves of the block, in the order of its txs. A tx whose sender
+    /// did not return the signature is not executed.
+    pub sender_leaves: Vec<SenderLeaf>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReceiptError {
+    #[error("Transfer {0} not found")]
+    TransferNotFound(String),
+
+    #[error("Transfer {0} is not in a block yet")]
+    NotIncluded(String),
+
+    #[error("Recipient is {actual:?}, expected {expected:?}")]
+    WrongRecipient {
+        expected: GenericAddress,
+        actual: GenericAddress,
+    },
+
+    #[error("Transfer is not in the tx: {0}")]
+    InvalidTransferProof(String),
+
+    #[error("Tx is not in the tx tree: {0}")]
+    InvalidTxProof(String),
+
+    #[error("Receipt is for block {receipt}, but the reference is block {reference}")]
+    BlockMismatch { receipt: u32, reference: u32 },
+
+    #[error("Tx tree root {receipt} was not posted in block {block_number}, which has {posted}")]
+    RootMismatch {
+        block_number: u32,
+        receipt: Bytes32,
+        posted: Bytes32,
+    },
+
+    #[error("Block {0} is unknown to the validity prover")]
+    UnknownBlock(u32),
+
+    #[error("Block {0} is not valid, so its txs did not take effect")]
+    InvalidBlock(u32),
+
+    #[error("Block {block_number} has no sender leaf for tx {tx_index}")]
+    MissingSenderLeaf { block_number: u32, tx_index: u32 },
+
+    #[error("Sender of tx {tx_index} in block {block_number} did not sign it")]
+    SenderDidNotSign { block_number: u32, tx_index: u32 },
+
+    #[error("Sender is {receipt}, but block {block_number} has {posted}")]
+    SenderMismatch {
+        block_number: u32,
+        receipt: U256,
+        posted: U256,
+    },
+
+    #[error("Server error: {0}")]
+    ServerError(#[from] ServerError),
+}
+
+impl RollupReference {
+    /// The tx tree root and validity of `block_number` in the validity public
+    /// inputs, and its sender leaves
+    pub async fn from_validity_prover<V: ValidityProverClientInterface>(
+        validity_prover: &V,
+        block_number: u32,
+    ) -> Result<Self, ReceiptError> {
+        let pis = validity_prover
+            .get_validity_pis(block_number)
+            .await?
+            .ok_or(ReceiptError::UnknownBlock(block_number))?;
+        let sender_leaves = validity_prover
+            .get_sender_leaves(block_number)
+            .await?
+            .ok_or(ReceiptError::UnknownBlock(block_number))?;
+        Ok(Self {
+            block_number,
+            tx_tree_root: pis.tx_tree_root,
+            is_valid_block: pis.is_valid_block,
+            sender_leaves,
+        })
+    }
+}
+
+/// Check that `receipt` pays `expected_recipient` in a tx whose tx tree root was
+/// posted in the block of `reference`, and that the tx was executed: the block is
+/// valid and the sender signed it
+pub fn verify_receive_receipt(
+    receipt: &ReceiveReceipt,
+    expected_recipient: U256,
+    reference: &RollupReference,
+) -> Result<(), ReceiptError> {
+    let expected = GenericAddress::from_pubkey(expected_recipient);
+    if receipt.transfer.recipient != expected {
+        return Err(ReceiptError::WrongRecipient {
+            expected,
+            actual: receipt.transfer.recipient,
+        });
+    }
+    receipt
+        .transfer_merkle_proof
+        .verify(
+            &receipt.transfer,
+            receipt.transfer_index as u64,
+            receipt.tx.transfer_tree_root,
+        )
+        .map_err(|e| ReceiptError::InvalidTransferProof(e.to_string()))?;
+    let tx_tree_root: PoseidonHashOut = receipt
+        .tx_tree_root
+        .try_into()
+        .map_err(|e| ReceiptError::InvalidTxProof(format!("{:?}", e)))?;
+    receipt
+        .tx_merkle_proof
+        .verify(&receipt.tx, receipt.tx_index as u64, tx_tree_root)
+        .map_err(|e| ReceiptError::InvalidTxProof(e.to_string()))?;
+    if receipt.block_number != reference.block_number {
+        return Err(ReceiptError::BlockMismatch {
+            receipt: receipt.block_number,
+            reference: reference.block_number,
+        });
+    }
+    if receipt.tx_tree_root != reference.tx_tree_root {
+        return Err(ReceiptError::RootMismatch {
+            block_number: reference.block_number,
+            receipt: receipt.tx_tree_root,
+            posted: reference.tx_tree_root,
+        });
+    }
+    if !reference.is_valid_block {
+        return Err(ReceiptError::InvalidBlock(reference.block_number));
+    }
+    let sender_leaf = reference
+        .sender_leaves
+        .get(receipt.tx_index as usize)
+        .ok_or(ReceiptError::MissingSenderLeaf {
+            block_number: reference.block_number,
+            tx_index: receipt.tx_index,
+        })?;
+    if sender_leaf.sender != receipt.sender {
+        return Err(ReceiptError::SenderMismatch {
+            block_number: reference.block_number,
+            receipt: receipt.sender,
+            posted: sender_leaf.sender,
+        });
+    }
+    if !sender_leaf.did_return_sig {
+        return Err(ReceiptError::SenderDidNotSign {
+            block_number: reference.block_number,
+            tx_index: receipt.tx_index,
+        });
+    }
+    Ok(())
+}
+
+/// Receipt of the transfer `transfer_uuid` received by `key`
+pub async fn export_receive_receipt<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    key: KeySet,
+    transfer_uuid: &str,
+) -> Result<ReceiveReceipt, ClientError> {
+    let (_, encrypted) = client
+        .store_vault_server
+        .get_data(DataType::Transfer, transfer_uuid)
+        .await?
+        .ok_or_else(|| ReceiptError::TransferNotFound(transfer_uuid.to_string()))?;
+    let transfer_data = TransferData::<F, C, D>::decrypt(&encrypted, key)
+        .map_err(|e| ClientError::DecryptionError(e.to_string()))?;
+    let tx_data = transfer_data.tx_data;
+    let block_number = client
+        .validity_prover
+        .get_block_number_by_tx_tree_root(tx_data.tx_tree_root)
+        .await?
+        .ok_or_else(|| ReceiptError::NotIncluded(transfer_uuid.to_string()))?;
+    Ok(ReceiveReceipt {
+        transfer_uuid: transfer_uuid.to_string(),
+        sender: transfer_data.sender,
+        transfer: transfer_data.transfer,
+        transfer_index: transfer_data.transfer_index,
+        transfer_merkle_proof: transfer_data.transfer_merkle_proof,
+        tx: tx_data.tx,
+        tx_index: tx_data.tx_index,
+        tx_merkle_proof: tx_data.tx_merkle_proof,
+        tx_tree_root: tx_data.tx_tree_root,
+        block_number,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{
+        common::{
+            salt::Salt,
+            trees::{transfer_tree::TransferTree, tx_tree::TxTree},
+        },
+        constants::{TRANSFER_TREE_HEIGHT, TX_TREE_HEIGHT},
+    };
+
+    use super::*;
+
+    fn transfer_to(recipient: U256, amount: u32) -> Transfer {
+        Transfer {
+            recipient: GenericAddress::from_pubkey(recipient),
+            token_index: 0,
+            amount: U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, amount]),
+            salt: Salt::rand(&mut rand::thread_rng()),
+        }
+    }
+
+    // the second transfer of the second tx of a block, paying `recipient`
+    fn receipt_for(recipient: U256) -> (ReceiveReceipt, RollupReference) {
+        let other = KeySet::rand(&mut rand::thread_rng()).pubkey;
+        let mut transfer_tree = TransferTree::new(TRANSFER_TREE_HEIGHT);
+        transfer_tree.push(transfer_to(other, 1));
+        transfer_tree.push(transfer_to(recipient, 100));
+        transfer_tree.push(transfer_to(other, 2));
+        let tx = Tx {
+            transfer_tree_root: transfer_tree.get_root(),
+            nonce: 3,
+        };
+        let mut tx_tree = TxTree::new(TX_TREE_HEIGHT);
+        tx_tree.push(Tx::default());
+        tx_tree.push(tx);
+        let tx_tree_root: Bytes32 = tx_tree.get_root().into();
+
+        let receipt = ReceiveReceipt {
+            transfer_uuid: "uuid".to_string(),
+            sender: other,
+            transfer: transfer_tree.leaves().into_iter().nth(1).unwrap(),
+            transfer_index: 1,
+            transfer_merkle_proof: transfer_tree.prove(1),
+            tx,
+            tx_index: 1,
+            tx_merkle_proof: tx_tree.prove(1),
+            tx_tree_root,
+            block_number: 42,
+        };
+        let reference = RollupReference {
+            block_number: 42,
+            tx_tree_root,
+            is_valid_block: true,
+            sender_leaves: vec![
+                SenderLeaf {
+                    sender: KeySet::rand(&mut rand::thread_rng()).pubkey,
+                    did_return_sig: true,
+                },
+                SenderLeaf {
+                    sender: other,
+                    did_return_sig: true,
+                },
+            ],
+        };
+        (receipt, reference)
+    }
+
+    #[test]
+    fn test_verify_receive_receipt() {
+        let recipient = KeySet::rand(&mut rand::thread_rng()).pubkey;
+        let (receipt, reference) = receipt_for(recipient);
+        verify_receive_receipt(&receipt, recipient, &reference).unwrap();
+
+        // a receipt survives its JSON export
+        let json = serde_json::to_string(&receipt).unwrap();
+        let parsed: ReceiveReceipt = serde_json::from_str(&json).unwrap();
+        verify_receive_receipt(&parsed, recipient, &reference).unwrap();
+
+        let other = KeySet::rand(&mut rand::thread_rng()).pubkey;
+        assert!(matches!(
+            verify_receive_receipt(&receipt, other, &reference),
+            Err(ReceiptError::WrongRecipient { .. })
+        ));
+    }
+
+    #[test]
+    fn test_tampered_receipt_fails() {
+        let recipient = KeySet::rand(&mut rand::thread_rng()).pubkey;
+        let (receipt, reference) = receipt_for(recipient);
+
+        let mut tampered = receipt.clone();
+        tampered.transfer.amount = U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, 1000]);
+        assert!(matches!(
+            verify_receive_receipt(&tampered, recipient, &reference),
+            Err(ReceiptError::InvalidTransferProof(_))
+        ));
+
+        let mut tampered = receipt.clone();
+        tampered.transfer_merkle_proof.0.siblings.swap(0, 1);
+        assert!(matches!(
+            verify_receive_receipt(&tampered, recipient, &reference),
+            Err(ReceiptError::InvalidTransferProof(_))
+        ));
+
+        let mut tampered = receipt.clone();
+        tampered.transfer_index = 0;
+        assert!(matches!(
+            verify_receive_receipt(&tampered, recipient, &reference),
+            Err(ReceiptError::InvalidTransferProof(_))
+        ));
+
+        let mut tampered = receipt.clone();
+        tampered.tx_merkle_proof.0.siblings.swap(0, 1);
+        assert!(matches!(
+            verify_receive_receipt(&tampered, recipient, &reference),
+            Err(ReceiptError::InvalidTxProof(_))
+        ));
+
+        // a root posted in another block does not count
+        let other_block = RollupReference {
+            tx_tree_root: Bytes32::default(),
+            ..reference.clone()
+        };
+        assert!(matches!(
+            verify_receive_receipt(&receipt, recipient, &other_block),
+            Err(ReceiptError::RootMismatch { .. })
+        ));
+        let mut tampered = receipt.clone();
+        tampered.block_number = 41;
+        assert!(matches!(
+            verify_receive_receipt(&tampered, recipient, &reference),
+            Err(ReceiptError::BlockMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unexecuted_tx_fails() {
+        let recipient = KeySet::rand(&mut rand::thread_rng()).pubkey;
+        let (receipt, reference) = receipt_for(recipient);
+
+        let invalid_block = RollupReference {
+            is_valid_block: false,
+            ..reference.clone()
+        };
+        assert!(matches!(
+            verify_receive_receipt(&receipt, recipient, &invalid_block),
+            Err(ReceiptError::InvalidBlock(42))
+        ));
+
+        let mut unsigned = reference.clone();
+        unsigned.sender_leaves[1].did_return_sig = false;
+        assert!(matches!(
+            verify_receive_receipt(&receipt, recipient, &unsigned),
+            Err(ReceiptError::SenderDidNotSign {
+                block_number: 42,
+                tx_index: 1
+            })
+        ));
+
+        let mut truncated = reference.clone();
+        truncated.sender_leaves.truncate(1);
+        assert!(matches!(
+            verify_receive_receipt(&receipt, recipient, &truncated),
+            Err(ReceiptError::MissingSenderLeaf { .. })
+        ));
+
+        // the sender written in the transfer data must be the one that signed
+        let mut tampered = receipt.clone();
+        tampered.sender = recipient;
+        assert!(matches!(
+            verify_receive_receipt(&tampered, recipient, &reference),
+            Err(ReceiptError::SenderMismatch { .. })
+        ));
+    }
+}
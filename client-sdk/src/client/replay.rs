@@ -0,0 +1,517 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use intmax2_interfaces::{
+    api::store_vault_server::interface::{DataType, StoreVaultClientInterface},
+    data::user_data::UserData,
+};
+use intmax2_zkp::common::signature::key_set::KeySet;
+use num_bigint::{BigInt, BigUint};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+};
+
+use crate::external_api::contract::interface::ContractReader;
+
+use super::{
+    error::ClientError,
+    history::GenericTransfer,
+    history_index::{HistoryIndex, IndexedRow, KeyDecoder, RowPayload},
+};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Balances after the actions `user_data` says it incorporated, obtained by adding
+/// up the amounts of their decrypted rows. No proof and nothing of the sync
+/// strategy is used, so that a bug in either cannot affect both sides of the check
+/// of `SyncVerifier`.
+///
+/// Fails if an incorporated action has no row, e.g. because its blob could not be
+/// decrypted, or if it is a deposit whose token index is not resolved.
+pub fn replay_balances(
+    rows: &[IndexedRow],
+    user_data: &UserData,
+) -> Result<BTreeMap<u32, BigInt>, ClientError> {
+    let mut balances = BTreeMap::<u32, BigInt>::new();
+    let mut credit = |token_index: u32, amount: BigInt| {
+        *balances.entry(token_index).or_default() += amount;
+    };
+    let processed = [
+        (&user_data.processed_deposit_uuids, "deposit"),
+        (&user_data.processed_transfer_uuids, "transfer"),
+        (&user_data.processed_tx_uuids, "tx"),
+    ];
+    for (uuids, kind) in processed {
+        for uuid in uuids.iter() {
+            let row = rows.iter().find(|row| row.uuid == *uuid).ok_or_else(|| {
+                ClientError::SyncError(format!(
+                    "cannot replay the balances: {} {} is not in the history",
+                    kind, uuid
+                ))
+            })?;
+            match &row.payload {
+                RowPayload::Deposit {
+                    token_index,
+                    amount,
+                    ..
+                } => {
+                    let token_index = token_index.ok_or_else(|| {
+                        ClientError::SyncError(format!(
+                            "cannot replay the balances: token of deposit {} is unknown",
+                            uuid
+                        ))
+                    })?;
+                    credit(token_index, BigUint::from(*amount).into());
+                }
+                RowPayload::Receive {
+                    token_index,
+                    amount,
+                    ..
+                } => credit(*token_index, BigUint::from(*amount).into()),
+                RowPayload::Send { transfers, fee, .. } => {
+                    let fee = fee.iter().map(|fee| &fee.transfer);
+                    for transfer in transfers.iter().chain(fee) {
+                        let (GenericTransfer::Transfer {
+                            token_index,
+                            amount,
+                            ..
+                        }
+                        | GenericTransfer::Withdrawal {
+                            token_index,
+                            amount,
+                            ..
+                        }) = transfer;
+                        credit(*token_index, -BigInt::from(BigUint::from(*amount)));
+                    }
+                }
+            }
+        }
+    }
+    balances.retain(|_, amount| *amount != BigInt::default());
+    Ok(balances)
+}
+
+/// Balances of the private state of `user_data`, as proved by the balance proof
+pub fn proved_balances(user_data: &UserData) -> BTreeMap<u32, BigInt> {
+    user_data
+        .balances()
+        .iter()
+        .map(|(token_index, leaf)| (*token_index as u32, BigUint::from(leaf.amount).into()))
+        .filter(|(_, amount)| *amount != BigInt::default())
+        .collect()
+}
+
+/// Fails with `BalanceMismatch` for the first token, by index, whose balances
+/// differ. All the differences are logged.
+pub fn compare_balances(
+    proved: &BTreeMap<u32, BigInt>,
+    replayed: &BTreeMap<u32, BigInt>,
+) -> Result<(), ClientError> {
+    let mismatches = proved
+        .keys()
+        .chain(replayed.keys())
+        .copied()
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|token| {
+            let proved = proved.get(&token).cloned().unwrap_or_default();
+            let replayed = replayed.get(&token).cloned().unwrap_or_default();
+            (proved != replayed).then_some((token, proved, replayed))
+        })
+        .collect::<Vec<_>>();
+    for (token, proved, replayed) in mismatches.iter() {
+        log::error!(
+            "balance mismatch of token {}: proved {}, replayed {} (difference {})",
+            token,
+            proved,
+            replayed,
+            proved - replayed
+        );
+    }
+    match mismatches.into_iter().next() {
+        Some((token, proved, replayed)) => Err(ClientError::BalanceMismatch {
+            token,
+            proved: proved.to_string(),
+            replayed: replayed.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Check of `Client::sync_verified`: before the user data of a synced action is
+/// saved, its balances are compared with those replayed from the history. The
+/// decrypted history is kept between the checks, so that each one only fetches
+/// the blobs saved since the previous one.
+pub struct SyncVerifier {
+    key: KeySet,
+    index: Mutex<HistoryIndex>,
+}
+
+impl SyncVerifier {
+    pub fn new(key: KeySet) -> Self {
+        Self {
+            key,
+            index: Mutex::new(HistoryIndex::new(key.pubkey)),
+        }
+    }
+
+    /// Fails with `BalanceMismatch` if the balances of `user_data` are not those of
+    /// the actions it incorporated
+    pub async fn verify<S: StoreVaultClientInterface>(
+        &self,
+        store_vault_server: &S,
+        liquidity_contract: &dyn ContractReader,
+        user_data: &UserData,
+    ) -> Result<(), ClientError> {
+        let decoder = KeyDecoder(self.key);
+        for data_type in [DataType::Deposit, DataType::Transfer, DataType::Tx] {
+            let fetch_after = self.index.lock().unwrap().fetch_after(data_type);
+            let blobs = store_vault_server
+                .get_data_all_after(data_type, self.key.pubkey, fetch_after)
+                .await?;
+            self.index
+                .lock()
+                .unwrap()
+                .index_blobs(data_type, blobs, &decoder);
+        }
+
+        // tokens of the deposits, registered once and fixed afterwards
+        let unresolved = self
+            .index
+            .lock()
+            .unwrap()
+            .rows
+            .iter()
+            .filter_map(|row| match row.payload {
+                RowPayload::Deposit {
+                    token_type,
+                    token_address,
+                    token_id,
+                    token_index: None,
+                    ..
+                } if user_data.processed_deposit_uuids.contains(&row.uuid) => {
+                    Some((row.uuid.clone(), token_type, token_address, token_id))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for (uuid, token_type, token_address, token_id) in unresolved {
+            let resolved = liquidity_contract
+                .get_token_index(token_type, token_address, token_id)
+                .await?;
+            let mut index = self.index.lock().unwrap();
+            if let Some(row) = index.rows.iter_mut().find(|row| row.uuid == uuid) {
+                if let RowPayload::Deposit { token_index, .. } = &mut row.payload {
+                    *token_index = resolved;
+                }
+            }
+        }
+
+        let replayed = replay_balances(&self.index.lock().unwrap().rows, user_data)?;
+        compare_balances(&proved_balances(user_data), &replayed)
+    }
+}
+
+/// Save the balance proof, if any, and the user data of a synced action. With a
+/// verifier, the user data is checked first, and nothing is saved if its balances
+/// do not match.
+pub async fn save_synced<S: StoreVaultClientInterface>(
+    store_vault_server: &S,
+    liquidity_contract: &dyn ContractReader,
+    key: KeySet,
+    balance_proof: Option<&ProofWithPublicInputs<F, C, D>>,
+    user_data: &mut UserData,
+    verifier: Option<&SyncVerifier>,
+) -> Result<(), ClientError> {
+    #[cfg(feature = "test-hooks")]
+    super::test_hooks::apply_accounting_bug(user_data);
+    if let Some(verifier) = verifier {
+        verifier
+            .verify(store_vault_server, liquidity_contract, user_data)
+            .await?;
+    }
+    if let Some(balance_proof) = balance_proof {
+        store_vault_server
+            .save_balance_proof(key.pubkey, balance_proof)
+            .await?;
+    }
+    store_vault_server
+        .save_user_data(key.pubkey, user_data.encrypt(key.pubkey))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
+
+    use super::*;
+
+    fn value(value: u32) -> U256 {
+        U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, value])
+    }
+
+    fn row(uuid: &str, payload: RowPayload) -> IndexedRow {
+        IndexedRow {
+            uuid: uuid.to_string(),
+            timestamp: 0,
+            payload,
+            block_number: None,
+        }
+    }
+
+    #[test]
+    fn test_replay_balances() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let rows = vec![
+            row(
+                "deposit",
+                RowPayload::Deposit {
+                    token_type: Default::default(),
+                    token_address: Default::default(),
+                    token_id: U256::default(),
+                    token_index: Some(0),
+                    amount: value(100),
+                    pubkey_salt_hash: Default::default(),
+                },
+            ),
+            row(
+                "receive",
+                RowPayload::Receive {
+                    amount: value(7),
+                    token_index: 1,
+                    from: U256::default(),
+                    claim_deadline_block: None,
+                    tx_tree_root: Default::default(),
+                },
+            ),
+            row(
+                "send",
+                RowPayload::Send {
+                    transfers: vec![
+                        GenericTransfer::Transfer {
+                            recipient: U256::default(),
+                            token_index: 0,
+                            amount: value(30),
+                        },
+                        GenericTransfer::Withdrawal {
+                            recipient: Default::default(),
+                            token_index: 1,
+                            amount: value(7),
+                        },
+                    ],
+                    fee: None,
+                    claim_deadline_block: None,
+                    tx_tree_root: Default::default(),
+                },
+            ),
+        ];
+        let mut user_data = UserData::new(key.pubkey);
+        user_data
+            .processed_deposit_uuids
+            .push("deposit".to_string());
+        user_data
+            .processed_transfer_uuids
+            .push("receive".to_string());
+        let replayed = replay_balances(&rows, &user_data).unwrap();
+        assert_eq!(
+            replayed,
+            BTreeMap::from([(0, BigInt::from(100)), (1, BigInt::from(7))])
+        );
+
+        // a token spent to zero has no balance, as in the asset tree
+        user_data.processed_tx_uuids.push("send".to_string());
+        let replayed = replay_balances(&rows, &user_data).unwrap();
+        assert_eq!(replayed, BTreeMap::from([(0, BigInt::from(70))]));
+        assert!(compare_balances(&replayed, &replayed).is_ok());
+
+        let proved = BTreeMap::from([(0, BigInt::from(71))]);
+        let ClientError::BalanceMismatch {
+            token,
+            proved,
+            replayed,
+        } = compare_balances(&proved, &replayed).unwrap_err()
+        else {
+            panic!("expected a balance mismatch");
+        };
+        assert_eq!((token, proved.as_str(), replayed.as_str()), (0, "71", "70"));
+
+        // an action missing from the history cannot be replayed
+        user_data.processed_tx_uuids.push("lost".to_string());
+        assert!(matches!(
+            replay_balances(&rows, &user_data),
+            Err(ClientError::SyncError(_))
+        ));
+    }
+
+    // the tests build the crate with `test-hooks`, see the dev-dependencies
+    #[cfg(feature = "test-hooks")]
+    mod accounting_bug {
+        use async_trait::async_trait;
+        use ethers::types::H256;
+        use intmax2_interfaces::data::deposit_data::{DepositData, TokenType};
+        use intmax2_zkp::{
+            common::{deposit::get_pubkey_salt_hash, salt::Salt},
+            ethereum_types::{address::Address, bytes32::Bytes32},
+        };
+
+        use crate::{
+            client::test_hooks,
+            external_api::{
+                contract::{
+                    interface::BlockchainError,
+                    liquidity_contract::{OnchainDeposit, WithdrawalEvent},
+                },
+                utils::in_memory_store_vault::InMemoryStoreVault,
+            },
+        };
+
+        use super::*;
+
+        /// Liquidity contract where every token is the native one. The check only
+        /// resolves token indices, so the other calls fail.
+        struct NativeTokenOnly;
+
+        fn not_used() -> BlockchainError {
+            BlockchainError::InternalError("not used by the sync check".to_string())
+        }
+
+        #[async_trait(?Send)]
+        impl ContractReader for NativeTokenOnly {
+            async fn get_block_number(&self) -> Result<u64, BlockchainError> {
+                Err(not_used())
+            }
+
+            async fn get_token_index(
+                &self,
+                _token_type: TokenType,
+                _token_address: Address,
+                _token_id: U256,
+            ) -> Result<Option<u32>, BlockchainError> {
+                Ok(Some(0))
+            }
+
+            async fn get_token_info(
+                &self,
+                _token_index: u32,
+            ) -> Result<(TokenType, Address, U256), BlockchainError> {
+                Err(not_used())
+            }
+
+            async fn get_deposit(
+                &self,
+                _deposit_id: u64,
+            ) -> Result<Option<OnchainDeposit>, BlockchainError> {
+                Err(not_used())
+            }
+
+            async fn get_deposits_in_tx(
+                &self,
+                _tx_hash: H256,
+            ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+                Err(not_used())
+            }
+
+            async fn get_deposits_by_salt_hash(
+                &self,
+                _pubkey_salt_hash: Bytes32,
+            ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+                Err(not_used())
+            }
+
+            async fn get_withdrawal_events(
+                &self,
+                _withdrawal_hash: Bytes32,
+            ) -> Result<Vec<WithdrawalEvent>, BlockchainError> {
+                Err(not_used())
+            }
+        }
+
+        /// Back up a deposit of `amount` and credit it to `user_data`, as its sync does
+        fn sync_deposit(
+            store_vault: &InMemoryStoreVault,
+            key: KeySet,
+            user_data: &mut UserData,
+            amount: u32,
+        ) {
+            let deposit_salt = Salt::rand(&mut rand::thread_rng());
+            let deposit_data = DepositData {
+                deposit_salt,
+                pubkey_salt_hash: get_pubkey_salt_hash(key.pubkey, deposit_salt),
+                amount: value(amount),
+                token_type: TokenType::NATIVE,
+                token_address: Address::default(),
+                token_id: U256::default(),
+                token_index: None,
+            };
+            let uuid = store_vault.insert(
+                DataType::Deposit,
+                key.pubkey,
+                deposit_data.encrypt(key.pubkey),
+            );
+            let asset_tree = &mut user_data.full_private_state.asset_tree;
+            let leaf = asset_tree.get_leaf(0);
+            asset_tree.update(0, leaf.add(value(amount)));
+            user_data.processed_deposit_uuids.push(uuid);
+        }
+
+        #[tokio::test]
+        async fn test_accounting_bug_is_not_saved() {
+            let key = KeySet::rand(&mut rand::thread_rng());
+            let store_vault = InMemoryStoreVault::new();
+            let verifier = SyncVerifier::new(key);
+            let mut user_data = UserData::new(key.pubkey);
+
+            sync_deposit(&store_vault, key, &mut user_data, 100);
+            save_synced(
+                &store_vault,
+                &NativeTokenOnly,
+                key,
+                None,
+                &mut user_data,
+                Some(&verifier),
+            )
+            .await
+            .unwrap();
+
+            sync_deposit(&store_vault, key, &mut user_data, 50);
+            test_hooks::inject_accounting_bug(1);
+            let ClientError::BalanceMismatch {
+                token,
+                proved,
+                replayed,
+            } = save_synced(
+                &store_vault,
+                &NativeTokenOnly,
+                key,
+                None,
+                &mut user_data,
+                Some(&verifier),
+            )
+            .await
+            .unwrap_err()
+            else {
+                panic!("expected a balance mismatch");
+            };
+            assert_eq!(
+                (token, proved.as_str(), replayed.as_str()),
+                (0, "151", "150")
+            );
+
+            // the user data of the first deposit is still the saved one
+            let saved = store_vault
+                .get_user_data(key.pubkey)
+                .await
+                .unwrap()
+                .unwrap();
+            let saved = UserData::decrypt(&saved, key).unwrap();
+            assert_eq!(saved.processed_deposit_uuids.len(), 1);
+            assert_eq!(
+                proved_balances(&saved),
+                BTreeMap::from([(0, BigInt::from(100))])
+            );
+        }
+    }
+}
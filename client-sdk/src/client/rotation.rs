@@ -0,0 +1,610 @@
+use std::collections::BTreeMap;
+
+use intmax2_interfaces::api::{
+    balance_prover::interface::BalanceProverClientInterface,
+    block_builder::interface::BlockBuilderClientInterface,
+    store_vault_server::interface::StoreVaultClientInterface,
+    validity_prover::interface::ValidityProverClientInterface,
+    withdrawal_server::interface::{WithdrawalServerClientInterface, WithdrawalStatus},
+};
+use intmax2_zkp::{
+    common::{generic_address::GenericAddress, signature::key_set::KeySet, transfer::Transfer},
+    constants::NUM_TRANSFERS_IN_TX,
+    ethereum_types::{bytes32::Bytes32, u256::U256},
+};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::utils::{clock::global_clock, time::sleep_for};
+
+use super::{
+    client::{Client, FeeMode, TxSendOptions},
+    error::ClientError,
+    template::TemplateBook,
+    utils::generate_salt,
+};
+
+#[derive(Debug, Clone)]
+pub struct RotationOptions {
+    /// Who pays the block builder fee of the rotation txs
+    pub fee_mode: FeeMode,
+    /// Balances at or below this amount are left behind, as they are not worth a
+    /// transfer
+    pub dust_threshold: U256,
+    /// Templates of the old account, handed back re-encrypted for the new one
+    pub templates: Option<TemplateBook>,
+    /// Seconds to wait for each proposal, block and the final sync
+    pub timeout: u64,
+    /// Seconds between polls
+    pub interval: u64,
+}
+
+impl Default for RotationOptions {
+    fn default() -> Self {
+        Self {
+            fee_mode: FeeMode::SelfPaid,
+            dust_threshold: U256::default(),
+            templates: None,
+            timeout: 120,
+            interval: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LeftBehindReason {
+    /// At or below the dust threshold
+    Dust,
+    /// The balance is marked insufficient, so it cannot be spent until topped up
+    Insufficient,
+    /// A withdrawal of the old account that has not completed yet. It completes
+    /// under the old key.
+    PendingWithdrawal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeftBehind {
+    pub reason: LeftBehindReason,
+    pub token_index: u32,
+    pub amount: U256,
+}
+
+/// What a rotation will send, computed from the synced old account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationPlan {
+    pub old_pubkey: U256,
+    pub new_pubkey: U256,
+    /// One tx per chunk, sent in order
+    pub chunks: Vec<Vec<Transfer>>,
+    pub left_behind: Vec<LeftBehind>,
+    /// Balances of the new account before the rotation, which it holds on top of
+    /// the moved amounts once the rotation is received
+    pub new_balances_before: BTreeMap<u32, U256>,
+}
+
+impl RotationPlan {
+    /// Total amount per token the chunks send
+    pub fn amounts(&self) -> BTreeMap<u32, U256> {
+        chunk_amounts(&self.chunks)
+    }
+
+    /// Balances of the new account once it received the rotation
+    pub fn expected_new_balances(&self) -> BTreeMap<u32, U256> {
+        add_amounts(&self.new_balances_before, &self.amounts())
+    }
+}
+
+/// A rotation in flight: its plan and the tx tree roots of the chunks sent so far.
+/// The caller keeps it after each chunk, so that a rotation that failed midway is
+/// resumed from the next chunk instead of being planned again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationProgress {
+    pub plan: RotationPlan,
+    pub tx_tree_roots: Vec<Bytes32>,
+}
+
+impl RotationProgress {
+    pub fn new(plan: RotationPlan) -> Self {
+        Self {
+            plan,
+            tx_tree_roots: Vec::new(),
+        }
+    }
+
+    /// The chunk to send next, None once every chunk is sent
+    pub fn next_chunk(&self) -> Option<&Vec<Transfer>> {
+        self.plan.chunks.get(self.tx_tree_roots.len())
+    }
+}
+
+/// Sum of `a` and `b` per token
+pub fn add_amounts(a: &BTreeMap<u32, U256>, b: &BTreeMap<u32, U256>) -> BTreeMap<u32, U256> {
+    let mut sums = a
+        .iter()
+        .map(|(token_index, amount)| (*token_index, BigUint::from(*amount)))
+        .collect::<BTreeMap<_, _>>();
+    for (token_index, amount) in b {
+        *sums.entry(*token_index).or_default() += BigUint::from(*amount);
+    }
+    sums.into_iter()
+        .map(|(token_index, amount)| (token_index, amount.try_into().unwrap()))
+        .collect()
+}
+
+/// Total amount per token of the transfers of `chunks`
+pub fn chunk_amounts(chunks: &[Vec<Transfer>]) -> BTreeMap<u32, U256> {
+    let mut amounts = BTreeMap::<u32, BigUint>::new();
+    for transfer in chunks.iter().flatten() {
+        *amounts.entry(transfer.token_index).or_default() += BigUint::from(transfer.amount);
+    }
+    amounts
+        .into_iter()
+        .map(|(token_index, amount)| (token_index, amount.try_into().unwrap()))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MovedToken {
+    pub token_index: u32,
+    pub amount: U256,
+    pub tx_tree_root: Bytes32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationReport {
+    pub old_pubkey: U256,
+    pub new_pubkey: U256,
+    pub moved: Vec<MovedToken>,
+    pub left_behind: Vec<LeftBehind>,
+    /// Balances of the new account after its sync
+    pub new_balances: BTreeMap<u32, U256>,
+    /// The templates of `RotationOptions`, now of the new account. The caller
+    /// stores them where it keeps templates.
+    pub templates: Option<TemplateBook>,
+}
+
+/// One transfer per token of the whole spendable balance to `new_pubkey`, in txs
+/// of up to `NUM_TRANSFERS_IN_TX` transfers. `balances` are (token index, amount,
+/// is insufficient).
+pub fn plan_transfers(
+    balances: &[(u32, U256, bool)],
+    new_pubkey: U256,
+    dust_threshold: U256,
+) -> (Vec<Vec<Transfer>>, Vec<LeftBehind>) {
+    let mut transfers = Vec::new();
+    let mut left_behind = Vec::new();
+    let mut balances = balances.to_vec();
+    balances.sort_by_key(|(token_index, _, _)| *token_index);
+    for (token_index, amount, is_insufficient) in balances {
+        if amount == U256::default() {
+            continue;
+        }
+        let reason = if is_insufficient {
+            LeftBehindReason::Insufficient
+        } else if amount <= dust_threshold {
+            LeftBehindReason::Dust
+        } else {
+            transfers.push(Transfer {
+                recipient: GenericAddress::from_pubkey(new_pubkey),
+                token_index,
+                amount,
+                salt: generate_salt(),
+            });
+            continue;
+        };
+        left_behind.push(LeftBehind {
+            reason,
+            token_index,
+            amount,
+        });
+    }
+    let chunks = transfers
+        .chunks(NUM_TRANSFERS_IN_TX)
+        .map(|chunk| chunk.to_vec())
+        .collect();
+    (chunks, left_behind)
+}
+
+/// Synced balances of `key` per token
+pub(crate) async fn synced_balances<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    key: KeySet,
+) -> Result<BTreeMap<u32, U256>, ClientError> {
+    client.sync(key).await?;
+    Ok(client
+        .get_user_data(key)
+        .await?
+        .balances()
+        .iter()
+        .map(|(token_index, leaf)| (*token_index as u32, leaf.amount))
+        .collect())
+}
+
+/// Sync both accounts and plan the txs moving the balance of `old_key` to `new_key`
+pub async fn plan_rotation<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    old_key: KeySet,
+    new_key: KeySet,
+    options: &RotationOptions,
+) -> Result<RotationPlan, ClientError> {
+    let new_pubkey = new_key.pubkey;
+    if old_key.pubkey == new_pubkey {
+        return Err(ClientError::RotationError(
+            "the new key is the old key".to_string(),
+        ));
+    }
+    client.sync(old_key).await?;
+    let balances = client
+        .get_user_data(old_key)
+        .await?
+        .balances()
+        .iter()
+        .map(|(token_index, leaf)| (*token_index as u32, leaf.amount, leaf.is_insufficient))
+        .collect::<Vec<_>>();
+    let (chunks, mut left_behind) = plan_transfers(&balances, new_pubkey, options.dust_threshold);
+
+    let withdrawals = client.get_withdrawal_info(old_key).await?;
+    left_behind.extend(
+        withdrawals
+            .iter()
+            .filter(|info| {
+                !matches!(
+                    info.status,
+                    WithdrawalStatus::Success | WithdrawalStatus::Failed
+                )
+            })
+            .map(|info| LeftBehind {
+                reason: LeftBehindReason::PendingWithdrawal,
+                token_index: info.contract_withdrawal.token_index,
+                amount: info.contract_withdrawal.amount,
+            }),
+    );
+    let new_balances_before = synced_balances(client, new_key).await?;
+    Ok(RotationPlan {
+        old_pubkey: old_key.pubkey,
+        new_pubkey,
+        chunks,
+        left_behind,
+        new_balances_before,
+    })
+}
+
+/// Send one chunk of the plan and wait until its block is synced by the validity
+/// prover, so that the next chunk can spend from the updated balance
+pub async fn send_rotation_chunk<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    block_builder_url: &str,
+    old_key: KeySet,
+    transfers: Vec<Transfer>,
+    options: &RotationOptions,
+) -> Result<Bytes32, ClientError> {
+    send_and_wait_for_inclusion(
+        client,
+        block_builder_url,
+        old_key,
+        transfers,
+        options.fee_mode.clone(),
+        options.timeout,
+        options.interval,
+    )
+    .await
+}
+
+/// Send a tx of `transfers`, finalize it and wait until its block is synced by the
+/// validity prover. Returns the tx tree root.
+pub(crate) async fn send_and_wait_for_inclusion<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    block_builder_url: &str,
+    key: KeySet,
+    transfers: Vec<Transfer>,
+    fee_mode: FeeMode,
+    timeout: u64,
+    interval: u64,
+) -> Result<Bytes32, ClientError> {
+    let tx_options = TxSendOptions {
+        fee_mode,
+        ..Default::default()
+    };
+    let memo = client
+        .send_tx_request_with_options(block_builder_url, key, transfers, tx_options)
+        .await?;
+    let proposal = client
+        .wait_for_proposal(block_builder_url, key, &memo, timeout, interval)
+        .await?;
+    let tx_tree_root = client
+        .finalize_tx(block_builder_url, key, &memo, &proposal)
+        .await?;
+    client
+        .wait_for_inclusion(tx_tree_root, timeout, interval)
+        .await?;
+    Ok(tx_tree_root)
+}
+
+/// Sync `key` until its balances are at least `expected`, and return them. Fails
+/// with `PendingError` after `timeout` seconds.
+pub(crate) async fn sync_until_received<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    key: KeySet,
+    expected: &BTreeMap<u32, U256>,
+    timeout: u64,
+    interval: u64,
+) -> Result<BTreeMap<u32, U256>, ClientError> {
+    let deadline = global_clock().local_now() + timeout;
+    loop {
+        let balances = synced_balances(client, key).await?;
+        let received = expected.iter().all(|(token_index, amount)| {
+            balances
+                .get(token_index)
+                .is_some_and(|balance| balance >= amount)
+        });
+        if received {
+            return Ok(balances);
+        }
+        if global_clock().local_now() >= deadline {
+            return Err(ClientError::PendingError(format!(
+                "{} did not receive the transfers within {} seconds",
+                key.pubkey, timeout
+            )));
+        }
+        sleep_for(interval).await;
+    }
+}
+
+/// Sync the new account until it holds what it held before the rotation plus what
+/// the plan moved, and report the rotation. `tx_tree_roots` are the roots of the
+/// sent chunks, in order.
+pub async fn complete_rotation<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    new_key: KeySet,
+    plan: &RotationPlan,
+    tx_tree_roots: &[Bytes32],
+    options: &RotationOptions,
+) -> Result<RotationReport, ClientError> {
+    if new_key.pubkey != plan.new_pubkey {
+        return Err(ClientError::RotationError(format!(
+            "the key of {} is not the one of the plan, {}",
+            new_key.pubkey, plan.new_pubkey
+        )));
+    }
+    if tx_tree_roots.len() != plan.chunks.len() {
+        return Err(ClientError::RotationError(format!(
+            "{} of {} chunks sent",
+            tx_tree_roots.len(),
+            plan.chunks.len()
+        )));
+    }
+
+    let new_balances = sync_until_received(
+        client,
+        new_key,
+        &plan.expected_new_balances(),
+        options.timeout,
+        options.interval,
+    )
+    .await?;
+
+    let moved = plan
+        .chunks
+        .iter()
+        .zip(tx_tree_roots)
+        .flat_map(|(chunk, tx_tree_root)| {
+            chunk.iter().map(|transfer| MovedToken {
+                token_index: transfer.token_index,
+                amount: transfer.amount,
+                tx_tree_root: *tx_tree_root,
+            })
+        })
+        .collect();
+    let templates = options
+        .templates
+        .as_ref()
+        .map(|book| book.rekey(new_key.pubkey));
+    Ok(RotationReport {
+        old_pubkey: plan.old_pubkey,
+        new_pubkey: plan.new_pubkey,
+        moved,
+        left_behind: plan.left_behind.clone(),
+        new_balances,
+        templates,
+    })
+}
+
+/// Plan, send every chunk, and complete the rotation from `old_key` to `new_key`
+pub async fn rotate_key<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    block_builder_url: &str,
+    old_key: KeySet,
+    new_key: KeySet,
+    options: &RotationOptions,
+) -> Result<RotationReport, ClientError> {
+    let plan = plan_rotation(client, old_key, new_key, options).await?;
+    resume_rotation(
+        client,
+        block_builder_url,
+        old_key,
+        new_key,
+        RotationProgress::new(plan),
+        options,
+        |_| {},
+    )
+    .await
+}
+
+/// Send the chunks of `progress` not sent yet and complete the rotation.
+/// `on_progress` is called after each chunk with the progress to keep.
+pub async fn resume_rotation<
+    BB: BlockBuilderClientInterface,
+    S: StoreVaultClientInterface,
+    V: ValidityProverClientInterface,
+    B: BalanceProverClientInterface,
+    W: WithdrawalServerClientInterface,
+>(
+    client: &Client<BB, S, V, B, W>,
+    block_builder_url: &str,
+    old_key: KeySet,
+    new_key: KeySet,
+    mut progress: RotationProgress,
+    options: &RotationOptions,
+    mut on_progress: impl FnMut(&RotationProgress),
+) -> Result<RotationReport, ClientError> {
+    if old_key.pubkey != progress.plan.old_pubkey {
+        return Err(ClientError::RotationError(format!(
+            "the key of {} is not the one of the plan, {}",
+            old_key.pubkey, progress.plan.old_pubkey
+        )));
+    }
+    while let Some(chunk) = progress.next_chunk() {
+        log::info!(
+            "rotation tx {}/{}",
+            progress.tx_tree_roots.len() + 1,
+            progress.plan.chunks.len()
+        );
+        let tx_tree_root =
+            send_rotation_chunk(client, block_builder_url, old_key, chunk.clone(), options).await?;
+        progress.tx_tree_roots.push(tx_tree_root);
+        on_progress(&progress);
+    }
+    complete_rotation(
+        client,
+        new_key,
+        &progress.plan,
+        &progress.tx_tree_roots,
+        options,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+
+    use super::*;
+
+    fn value(value: u32) -> U256 {
+        U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, value])
+    }
+
+    #[test]
+    fn test_plan_transfers() {
+        let new_pubkey = value(7);
+        let balances = vec![
+            (1, value(500), false),
+            (0, value(1000), false),
+            (2, value(3), false),
+            (3, value(40), true),
+            (4, U256::default(), false),
+        ];
+        let (chunks, left_behind) = plan_transfers(&balances, new_pubkey, value(5));
+
+        assert_eq!(chunks.len(), 1);
+        let sent = chunks[0]
+            .iter()
+            .map(|transfer| (transfer.token_index, transfer.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(sent, vec![(0, value(1000)), (1, value(500))]);
+        assert!(chunks[0]
+            .iter()
+            .all(|transfer| transfer.recipient == GenericAddress::from_pubkey(new_pubkey)));
+        assert_eq!(
+            left_behind,
+            vec![
+                LeftBehind {
+                    reason: LeftBehindReason::Dust,
+                    token_index: 2,
+                    amount: value(3),
+                },
+                LeftBehind {
+                    reason: LeftBehindReason::Insufficient,
+                    token_index: 3,
+                    amount: value(40),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expected_new_balances() {
+        let (chunks, _) = plan_transfers(
+            &[(0, value(1000), false), (1, value(500), false)],
+            value(7),
+            U256::default(),
+        );
+        let plan = RotationPlan {
+            old_pubkey: value(1),
+            new_pubkey: value(7),
+            chunks,
+            left_behind: vec![],
+            new_balances_before: [(0, value(2000)), (2, value(3))].into(),
+        };
+        // a new account that already held more than the moved amount has not
+        // received the rotation until it holds both
+        assert_eq!(
+            plan.expected_new_balances(),
+            [(0, value(3000)), (1, value(500)), (2, value(3))].into()
+        );
+
+        let mut progress = RotationProgress::new(plan);
+        assert_eq!(progress.next_chunk(), Some(&progress.plan.chunks[0]));
+        progress.tx_tree_roots.push(Bytes32::default());
+        assert_eq!(progress.next_chunk(), None);
+    }
+
+    #[test]
+    fn test_plan_transfers_chunks() {
+        let balances = (0..NUM_TRANSFERS_IN_TX as u32 + 1)
+            .map(|token_index| (token_index, value(1), false))
+            .collect::<Vec<_>>();
+        let (chunks, _) = plan_transfers(&balances, value(7), U256::default());
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), NUM_TRANSFERS_IN_TX);
+        assert_eq!(chunks[1].len(), 1);
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+#[cfg(target_arch = "wasm32")]
+use gloo_timers::future::sleep;
+#[cfg(target_arch = "wasm32")]
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::time::{sleep, Duration};
+
+use intmax2_interfaces::{
+    api::{error::ServerError, store_vault_server::interface::StoreVaultClientInterface},
+    data::user_data::UserData,
+};
+use intmax2_zkp::{common::signature::key_set::KeySet, ethereum_types::u256::U256};
+
+use super::error::ClientError;
+
+/// Milliseconds `shutdown` waits for the in-flight store vault writes
+pub const SHUTDOWN_TIMEOUT_MS: u64 = 2000;
+
+const POLL_INTERVAL_MS: u64 = 50;
+
+const OPEN: u8 = 0;
+const CLOSING: u8 = 1;
+const NEEDS_CHECK: u8 = 2;
+
+static ACCOUNT_SESSIONS: OnceLock<Mutex<HashMap<U256, Arc<Session>>>> = OnceLock::new();
+
+/// The session of the account `pubkey`, shared by the store vault client, which
+/// registers every write under `pubkey`, and by `Client::shutdown`. Closing the
+/// session of one account leaves the others open. Data saved for another account,
+/// e.g. the transfer data of a recipient, is registered with the session of that
+/// account.
+pub fn account_session(pubkey: U256) -> Arc<Session> {
+    ACCOUNT_SESSIONS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .entry(pubkey)
+        .or_default()
+        .clone()
+}
+
+/// Persisted flag telling whether the last session of an account was closed
+/// cleanly, e.g. a file for the CLI or local storage for the browser
+pub trait SessionMarker {
+    fn is_open(&self) -> bool;
+    fn set_open(&self, open: bool);
+}
+
+/// Cancellation state of the store vault writes. Once closed, new writes fail
+/// right away so that a teardown only has to wait for the ones already sent.
+#[derive(Debug, Default)]
+pub struct Session {
+    state: AtomicU8,
+    in_flight: AtomicUsize,
+}
+
+/// Registration of an in-flight write, released on drop
+#[derive(Debug)]
+pub struct WriteGuard<'a> {
+    session: &'a Session,
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.session.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Result of `Client::shutdown`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShutdownReport {
+    /// Writes still in flight when the timeout expired. If not zero, the session
+    /// is left marked as open so that the next start checks the local state.
+    pub abandoned_writes: usize,
+}
+
+impl ShutdownReport {
+    pub fn is_clean(&self) -> bool {
+        self.abandoned_writes == 0
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == CLOSING
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Register a write. Fails if the session is closing, or if the consistency
+    /// check after an unclean shutdown has not passed yet.
+    pub fn begin_write(&self) -> Result<WriteGuard<'_>, ServerError> {
+        let reason = match self.state.load(Ordering::SeqCst) {
+            CLOSING => "session is shutting down",
+            NEEDS_CHECK => "previous session was not closed cleanly and is not checked yet",
+            _ => "",
+        };
+        if !reason.is_empty() {
+            return Err(ServerError::InternalError(reason.to_string()));
+        }
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Ok(WriteGuard { session: self })
+    }
+
+    /// Reject new writes
+    pub fn close(&self) {
+        self.state.store(CLOSING, Ordering::SeqCst);
+    }
+
+    /// Accept writes again
+    pub fn reopen(&self) {
+        self.state.store(OPEN, Ordering::SeqCst);
+    }
+
+    fn require_check(&self) {
+        self.state.store(NEEDS_CHECK, Ordering::SeqCst);
+    }
+
+    /// Wait up to `timeout_ms` for the in-flight writes to finish. Returns the
+    /// number of writes still in flight.
+    pub async fn drain(&self, timeout_ms: u64) -> usize {
+        let mut waited = 0;
+        while self.in_flight() > 0 && waited < timeout_ms {
+            sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+            waited += POLL_INTERVAL_MS;
+        }
+        self.in_flight()
+    }
+
+    /// Close the session and wait for the in-flight writes. The marker is cleared
+    /// only if all of them finished.
+    pub async fn shutdown<M: SessionMarker>(&self, marker: &M, timeout_ms: u64) -> ShutdownReport {
+        self.close();
+        let abandoned_writes = self.drain(timeout_ms).await;
+        if abandoned_writes == 0 {
+            marker.set_open(false);
+        } else {
+            log::warn!(
+                "Shutting down with {} store vault writes in flight",
+                abandoned_writes
+            );
+        }
+        ShutdownReport { abandoned_writes }
+    }
+
+    /// Undo a `shutdown` whose teardown did not happen, e.g. a page unload that
+    /// the user cancelled, so that writes are accepted again and the session is
+    /// marked as open. A session waiting for its consistency check stays blocked.
+    pub fn cancel_shutdown<M: SessionMarker>(&self, marker: &M) {
+        if self
+            .state
+            .compare_exchange(CLOSING, OPEN, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            marker.set_open(true);
+        }
+    }
+
+    /// Start a session. If the previous one was not closed cleanly, writes are
+    /// blocked until `check_consistency` passes. Returns whether the check ran.
+    pub async fn open<S, M>(
+        &self,
+        store_vault_server: &S,
+        key: KeySet,
+        marker: &M,
+    ) -> Result<bool, ClientError>
+    where
+        S: StoreVaultClientInterface,
+        M: SessionMarker,
+    {
+        let unclean = marker.is_open();
+        if unclean {
+            log::warn!("Previous session was not closed cleanly, checking the local state");
+            self.require_check();
+            check_consistency(store_vault_server, key).await?;
+        }
+        marker.set_open(true);
+        self.reopen();
+        Ok(unclean)
+    }
+}
+
+/// Check that the user data decrypts and that the balance proof of its last
+/// checkpoint is in the store vault and parses
+pub async fn check_consistency<S: StoreVaultClientInterface>(
+    store_vault_server: &S,
+    key: KeySet,
+) -> Result<(), ClientError> {
+    let user_data = match store_vault_server.get_user_data(key.pubkey).await? {
+        Some(encrypted) => UserData::decrypt(&encrypted, key).map_err(|e| {
+            ClientError::DecryptionError(format!("failed to decrypt user data: {}", e))
+        })?,
+        None => return Ok(()),
+    };
+    if user_data.block_number == 0 {
+        // no checkpoint yet
+        return Ok(());
+    }
+    store_vault_server
+        .get_balance_proof(
+            key.pubkey,
+            user_data.block_number,
+            user_data.private_commitment(),
+        )
+        .await?
+        .ok_or_else(|| {
+            ClientError::SyncError(format!(
+                "balance proof of the checkpoint at block {} not found",
+                user_data.block_number
+            ))
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use intmax2_interfaces::{
+        api::store_vault_server::interface::StoreVaultClientInterface as _,
+        data::user_data::UserData,
+    };
+    use intmax2_zkp::common::signature::key_set::KeySet;
+
+    use crate::external_api::utils::in_memory_store_vault::InMemoryStoreVault;
+
+    use super::{account_session, Session, SessionMarker};
+
+    #[derive(Default)]
+    struct MemoryMarker(Cell<bool>);
+
+    impl SessionMarker for MemoryMarker {
+        fn is_open(&self) -> bool {
+            self.0.get()
+        }
+
+        fn set_open(&self, open: bool) {
+            self.0.set(open);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_waits_for_in_flight_writes() {
+        let session = Session::new();
+        let marker = MemoryMarker::default();
+        marker.set_open(true);
+
+        let guard = session.begin_write().unwrap();
+        let report = session.shutdown(&marker, 100).await;
+        assert_eq!(report.abandoned_writes, 1);
+        assert!(marker.is_open());
+        assert!(session.begin_write().is_err());
+
+        drop(guard);
+        let report = session.shutdown(&marker, 100).await;
+        assert!(report.is_clean());
+        assert!(!marker.is_open());
+    }
+
+    #[tokio::test]
+    async fn test_unclean_shutdown_is_checked_on_open() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let store_vault = InMemoryStoreVault::new();
+        store_vault
+            .save_user_data(key.pubkey, UserData::new(key.pubkey).encrypt(key.pubkey))
+            .await
+            .unwrap();
+        let session = Session::new();
+        let marker = MemoryMarker::default();
+
+        assert!(!session.open(&store_vault, key, &marker).await.unwrap());
+        // terminated without shutdown, while the user data was being overwritten
+        let _guard = session.begin_write().unwrap();
+        store_vault
+            .save_user_data(key.pubkey, vec![0u8; 16])
+            .await
+            .unwrap();
+
+        let session = Session::new();
+        assert!(session.open(&store_vault, key, &marker).await.is_err());
+        assert!(session.begin_write().is_err());
+
+        store_vault
+            .save_user_data(key.pubkey, UserData::new(key.pubkey).encrypt(key.pubkey))
+            .await
+            .unwrap();
+        assert!(session.open(&store_vault, key, &marker).await.unwrap());
+        assert!(session.begin_write().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_shutdown_reopens() {
+        let session = Session::new();
+        let marker = MemoryMarker::default();
+        marker.set_open(true);
+
+        assert!(session.shutdown(&marker, 0).await.is_clean());
+        assert!(session.begin_write().is_err());
+        // the page was not torn down after all
+        session.cancel_shutdown(&marker);
+        assert!(marker.is_open());
+        assert!(session.begin_write().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_per_account() {
+        let mut rng = rand::thread_rng();
+        let first = KeySet::rand(&mut rng).pubkey;
+        let second = KeySet::rand(&mut rng).pubkey;
+        let marker = MemoryMarker::default();
+
+        account_session(first).shutdown(&marker, 0).await;
+        assert!(account_session(first).begin_write().is_err());
+        assert!(account_session(second).begin_write().is_ok());
+    }
+}
@@ -0,0 +1,244 @@
+use async_trait::async_trait;
+use intmax2_interfaces::data::encryption::ENCRYPTION_VERSION;
+use intmax2_zkp::{
+    common::{
+        block_builder::{BlockProposal, UserSignature},
+        signature::{flatten::FlatG2, key_set::KeySet},
+        tx::Tx,
+    },
+    ethereum_types::u256::U256,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::utils::{
+    query::{post_request, HttpClient},
+    retry::RetryConfig,
+};
+
+use super::error::ClientError;
+
+/// Produces the signatures made with the intmax private key.
+///
+/// Only signing goes through the signer. Decrypting the data of the store vault
+/// takes the `KeySet` of the account, which is built by `remote_signer_key`
+/// without the private key while the data is not encrypted.
+#[async_trait(?Send)]
+pub trait Signer {
+    fn pubkey(&self) -> U256;
+
+    /// Sign the block proposal containing `tx`
+    async fn sign_proposal(
+        &self,
+        tx: &Tx,
+        proposal: &BlockProposal,
+    ) -> Result<UserSignature, ClientError>;
+}
+
+/// Key of an account whose private key is held by a remote signer, for the
+/// client calls that take a `KeySet` to read the account's data. It has no
+/// private key, which decrypting does not use while the data is not encrypted
+/// (`ENCRYPTION_VERSION` 0), so it is refused for any other version.
+pub fn remote_signer_key(pubkey: U256) -> Result<KeySet, ClientError> {
+    if ENCRYPTION_VERSION != 0 {
+        return Err(ClientError::SignerError(format!(
+            "the data of encryption version {} cannot be decrypted without the private key",
+            ENCRYPTION_VERSION
+        )));
+    }
+    Ok(KeySet {
+        privkey: U256::default(),
+        pubkey,
+    })
+}
+
+/// Signs with a private key held in memory
+#[derive(Debug, Clone, Copy)]
+pub struct LocalSigner(pub KeySet);
+
+#[async_trait(?Send)]
+impl Signer for LocalSigner {
+    fn pubkey(&self) -> U256 {
+        self.0.pubkey
+    }
+
+    async fn sign_proposal(
+        &self,
+        _tx: &Tx,
+        proposal: &BlockProposal,
+    ) -> Result<UserSignature, ClientError> {
+        Ok(proposal.sign(self.0))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSignProposalRequest {
+    pub pubkey: U256,
+    pub tx: Tx,
+    pub proposal: BlockProposal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSignResponse {
+    pub signature: FlatG2,
+}
+
+/// Delegates signing to a service holding the key, e.g. in front of a KMS.
+///
+/// The payload is POSTed to `{url}/sign-proposal` with the bearer token. A
+/// returned signature is checked against `pubkey` before it is used.
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    url: String,
+    bearer_token: Option<String>,
+    pubkey: U256,
+    retry_config: Option<RetryConfig>,
+    http: HttpClient,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, bearer_token: Option<String>, pubkey: U256) -> Self {
+        Self {
+            url: url.to_string(),
+            bearer_token,
+            pubkey,
+            retry_config: None,
+            http: HttpClient::default(),
+        }
+    }
+
+    /// Retry the requests of this client with `config` instead of the
+    /// process-wide `retry_config()`
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry_config = Some(config);
+        self
+    }
+}
+
+#[async_trait(?Send)]
+impl Signer for RemoteSigner {
+    fn pubkey(&self) -> U256 {
+        self.pubkey
+    }
+
+    async fn sign_proposal(
+        &self,
+        tx: &Tx,
+        proposal: &BlockProposal,
+    ) -> Result<UserSignature, ClientError> {
+        let request = RemoteSignProposalRequest {
+            pubkey: self.pubkey,
+            tx: *tx,
+            proposal: proposal.clone(),
+        };
+        let response: RemoteSignResponse = post_request(
+            &self.http,
+            &self.url,
+            "/sign-proposal",
+            &request,
+            self.bearer_token.clone(),
+            self.retry_config.as_ref(),
+        )
+        .await?;
+        let signature = UserSignature {
+            pubkey: self.pubkey,
+            signature: response.signature,
+        };
+        signature
+            .verify(proposal.tx_tree_root, proposal.pubkeys_hash)
+            .map_err(|e| {
+                ClientError::SignerError(format!(
+                    "remote signature does not verify against pubkey {}: {}",
+                    self.pubkey, e
+                ))
+            })?;
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_interfaces::data::encryption::{decrypt, encrypt};
+    use intmax2_zkp::{
+        common::{
+            block_builder::BlockProposal,
+            signature::{key_set::KeySet, utils::get_pubkey_hash},
+            trees::tx_tree::TxTree,
+            tx::Tx,
+        },
+        constants::TX_TREE_HEIGHT,
+        ethereum_types::u256::U256,
+    };
+
+    use crate::{
+        client::error::ClientError,
+        external_api::utils::mock_server::{start_mock_server, MockRequests},
+    };
+
+    use super::{
+        remote_signer_key, RemoteSignProposalRequest, RemoteSignResponse, RemoteSigner, Signer as _,
+    };
+
+    fn proposal_of(pubkey: U256, tx: Tx) -> BlockProposal {
+        let mut tx_tree = TxTree::new(TX_TREE_HEIGHT);
+        tx_tree.push(tx);
+        let pubkeys = vec![pubkey];
+        BlockProposal {
+            tx_tree_root: tx_tree.get_root().into(),
+            tx_index: 0,
+            tx_merkle_proof: tx_tree.prove(0),
+            pubkeys_hash: get_pubkey_hash(&pubkeys),
+            pubkeys,
+        }
+    }
+
+    /// A signing service holding `key`
+    fn start_signing_service(key: KeySet) -> (String, MockRequests) {
+        start_mock_server(move |_path, body| {
+            let request: RemoteSignProposalRequest = serde_json::from_str(body).unwrap();
+            let signature = request.proposal.sign(key);
+            serde_json::to_string(&RemoteSignResponse {
+                signature: signature.signature,
+            })
+            .unwrap()
+        })
+    }
+
+    #[test]
+    fn test_remote_signer_key() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let remote_key = remote_signer_key(key.pubkey).unwrap();
+        assert_eq!(remote_key.pubkey, key.pubkey);
+        // reads the data saved for the account
+        let encrypted = encrypt(key.pubkey, b"user data");
+        assert_eq!(decrypt(remote_key, &encrypted).unwrap(), b"user data");
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let tx = Tx::default();
+        let proposal = proposal_of(key.pubkey, tx);
+        let (url, requests) = start_signing_service(key);
+
+        let signer = RemoteSigner::new(&url, Some("token".to_string()), key.pubkey);
+        // verified against the pubkey before it is returned
+        let signature = signer.sign_proposal(&tx, &proposal).await.unwrap();
+        assert_eq!(signature.pubkey, key.pubkey);
+        assert_eq!(requests.lock().unwrap()[0].path, "/sign-proposal");
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer_rejects_signature_of_other_key() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let other = KeySet::rand(&mut rand::thread_rng());
+        let tx = Tx::default();
+        let proposal = proposal_of(key.pubkey, tx);
+        let (url, _) = start_signing_service(other);
+
+        let signer = RemoteSigner::new(&url, None, key.pubkey);
+        let result = signer.sign_proposal(&tx, &proposal).await;
+        assert!(matches!(result, Err(ClientError::SignerError(_))));
+    }
+}
@@ -0,0 +1,127 @@
+//! Decryption of the blobs fetched from the store vault. Sync fetches thousands
+//! of small blobs, so with the `parallel` feature they are decrypted on all cores
+//! with rayon. wasm has no threads and decrypts them one by one.
+
+/// Apply `decrypt` to the data of every blob. The results keep the order of
+/// `blobs`, and `decrypt` reports failures in its result, so a blob that fails
+/// only affects its own entry.
+pub fn decrypt_blobs<M, T, D>(blobs: Vec<(M, Vec<u8>)>, decrypt: D) -> Vec<(M, T)>
+where
+    M: Send,
+    T: Send,
+    D: Fn(&[u8]) -> T + Sync,
+{
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    {
+        decrypt_blobs_parallel(blobs, decrypt)
+    }
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    {
+        decrypt_blobs_sequential(blobs, decrypt)
+    }
+}
+
+pub fn decrypt_blobs_sequential<M, T, D>(blobs: Vec<(M, Vec<u8>)>, decrypt: D) -> Vec<(M, T)>
+where
+    D: Fn(&[u8]) -> T,
+{
+    blobs
+        .into_iter()
+        .map(|(meta, data)| {
+            let decrypted = decrypt(&data);
+            (meta, decrypted)
+        })
+        .collect()
+}
+
+/// `decrypt_blobs` on the global rayon pool, which has a thread per core. On wasm
+/// the pool only has threads if the page started them (e.g. with
+/// wasm-bindgen-rayon), otherwise the blobs are decrypted one by one.
+#[cfg(feature = "parallel")]
+pub fn decrypt_blobs_parallel<M, T, D>(blobs: Vec<(M, Vec<u8>)>, decrypt: D) -> Vec<(M, T)>
+where
+    M: Send,
+    T: Send,
+    D: Fn(&[u8]) -> T + Sync,
+{
+    use rayon::prelude::*;
+
+    // collecting an indexed parallel iterator keeps the input order
+    blobs
+        .into_par_iter()
+        .map(|(meta, data)| {
+            let decrypted = decrypt(&data);
+            (meta, decrypted)
+        })
+        .collect()
+}
+
+#[cfg(all(test, feature = "parallel", not(target_arch = "wasm32")))]
+mod tests {
+    use intmax2_interfaces::data::{
+        deposit_data::{DepositData, TokenType},
+        meta_data::MetaData,
+    };
+    use intmax2_zkp::{
+        common::{deposit::get_pubkey_salt_hash, salt::Salt, signature::key_set::KeySet},
+        ethereum_types::{address::Address, u256::U256, u32limb_trait::U32LimbTrait as _},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let mut rng = rand::thread_rng();
+        let key = KeySet::rand(&mut rng);
+        let other = KeySet::rand(&mut rng);
+        let blobs = (0..500)
+            .map(|i| {
+                // some blobs are for another key, and some are corrupted
+                let recipient = if i % 7 == 3 { other } else { key };
+                let deposit_salt = Salt::rand(&mut rng);
+                let deposit = DepositData {
+                    deposit_salt,
+                    pubkey_salt_hash: get_pubkey_salt_hash(recipient.pubkey, deposit_salt),
+                    token_type: TokenType::NATIVE,
+                    token_address: Address::default(),
+                    token_id: U256::default(),
+                    amount: U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, i]),
+                    token_index: None,
+                };
+                let data = match i % 7 {
+                    5 => vec![i as u8; 40],
+                    _ => deposit.encrypt(recipient.pubkey),
+                };
+                let meta = MetaData {
+                    uuid: format!("blob-{}", i),
+                    timestamp: i as u64,
+                    block_number: None,
+                };
+                (meta, data)
+            })
+            .collect::<Vec<_>>();
+
+        let decrypt = |data: &[u8]| DepositData::decrypt(data, key).map_err(|e| e.to_string());
+        let sequential = decrypt_blobs_sequential(blobs.clone(), decrypt);
+        let parallel = decrypt_blobs_parallel(blobs, decrypt);
+        assert_eq!(sequential.len(), 500);
+        assert_eq!(parallel.len(), 500);
+        for (i, ((seq_meta, seq), (par_meta, par))) in
+            sequential.iter().zip(parallel.iter()).enumerate()
+        {
+            assert_eq!(seq_meta.uuid, format!("blob-{}", i));
+            assert_eq!(par_meta.uuid, seq_meta.uuid);
+            let expect_ok = i % 7 != 3 && i % 7 != 5;
+            assert_eq!(seq.is_ok(), expect_ok, "blob {}: {:?}", i, seq);
+            assert_eq!(par.is_ok(), expect_ok, "blob {}: {:?}", i, par);
+            if let (Ok(seq), Ok(par)) = (seq, par) {
+                assert_eq!(
+                    seq.amount,
+                    U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, i as u32])
+                );
+                assert_eq!(par.amount, seq.amount);
+                assert_eq!(par.pubkey_salt_hash, seq.pubkey_salt_hash);
+            }
+        }
+    }
+}
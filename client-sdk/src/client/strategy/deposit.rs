@@ -8,16 +8,66 @@ use intmax2_interfaces::{
 use intmax2_zkp::common::signature::key_set::KeySet;
 
 use crate::{
-    client::error::ClientError, external_api::contract::liquidity_contract::LiquidityContract,
+    client::error::ClientError,
+    external_api::{contract::liquidity_contract::LiquidityContract, utils::clock::global_clock},
 };
 
+use super::decrypt::decrypt_blobs;
+
 #[derive(Debug, Clone)]
 pub struct DepositInfo {
     pub settled: Vec<(MetaData, DepositData)>,
-    pub pending: Vec<MetaData>,
+    /// Not in a block of the validity prover yet
+    pub pending: Vec<(MetaData, DepositData)>,
     pub rejected: Vec<MetaData>,
 }
 
+/// Deposit backed up in the store vault, but not incorporated by a sync yet
+#[derive(Debug, Clone)]
+pub struct PendingDeposit {
+    pub meta: MetaData,
+    pub deposit_data: DepositData,
+    /// Block the deposit is in, `None` while it is not in a block of the validity
+    /// prover
+    pub block_number: Option<u32>,
+}
+
+impl PendingDeposit {
+    /// Not in a block of the validity prover yet, e.g. not relayed or in a block
+    /// that is proposed but not finalized
+    pub fn is_pending(&self) -> bool {
+        self.block_number.is_none()
+    }
+}
+
+/// The deposits of `deposit_info` not among `processed_uuids`, which the lpt alone
+/// does not exclude when several share a timestamp. Rejected deposits are left out.
+pub fn unprocessed_deposits(
+    deposit_info: DepositInfo,
+    processed_uuids: &[String],
+) -> Vec<PendingDeposit> {
+    let settled = deposit_info
+        .settled
+        .into_iter()
+        .map(|(meta, deposit_data)| PendingDeposit {
+            block_number: meta.block_number,
+            meta,
+            deposit_data,
+        });
+    let pending = deposit_info
+        .pending
+        .into_iter()
+        .map(|(meta, deposit_data)| PendingDeposit {
+            meta,
+            deposit_data,
+            block_number: None,
+        });
+    settled
+        .chain(pending)
+        .filter(|deposit| !processed_uuids.contains(&deposit.meta.uuid))
+        .collect()
+}
+
 pub async fn fetch_deposit_info<S: StoreVaultClientInterface, V: ValidityProverClientInterface>(
     store_vault_server: &S,
     validity_prover: &V,
@@ -33,8 +83,9 @@ pub async fn fetch_deposit_info<S: StoreVaultClientInterface, V: ValidityProverC
     let encrypted_data = store_vault_server
         .get_data_all_after(DataType::Deposit, key.pubkey, deposit_lpt)
         .await?;
-    for (meta, encrypted_data) in encrypted_data {
-        match DepositData::decrypt(&encrypted_data, key) {
+    let decrypted = decrypt_blobs(encrypted_data, |data| DepositData::decrypt(data, key));
+    for (meta, decrypted) in decrypted {
+        match decrypted {
             Ok(deposit_data) => {
                 let token_index = liquidity_contract
                     .get_token_index(
@@ -58,14 +109,14 @@ pub async fn fetch_deposit_info<S: StoreVaultClientInterface, V: ValidityProverC
                     meta.block_number = Some(deposit_info.block_number);
                     settled.push((meta, deposit_data));
                 } else {
-                    if meta.timestamp + deposit_timeout < chrono::Utc::now().timestamp() as u64 {
+                    if global_clock().is_timed_out(meta.timestamp, deposit_timeout) {
                         // timeout
                         log::error!("Deposit {} is timeouted", meta.uuid);
                         rejected.push(meta);
                     } else {
                         // pending
                         log::info!("Deposit {} is pending", meta.uuid);
-                        pending.push(meta);
+                        pending.push((meta, deposit_data));
                     }
                 }
             }
@@ -85,3 +136,55 @@ pub async fn fetch_deposit_info<S: StoreVaultClientInterface, V: ValidityProverC
         rejected,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use intmax2_interfaces::data::deposit_data::TokenType;
+    use intmax2_zkp::ethereum_types::{
+        address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _,
+    };
+
+    use super::*;
+    use crate::client::utils::generate_salt;
+
+    fn deposit(uuid: &str, block_number: Option<u32>) -> (MetaData, DepositData) {
+        let meta = MetaData {
+            uuid: uuid.to_string(),
+            timestamp: 100,
+            block_number,
+        };
+        let deposit_data = DepositData {
+            deposit_salt: generate_salt(),
+            pubkey_salt_hash: Bytes32::default(),
+            amount: U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, 10]),
+            token_type: TokenType::NATIVE,
+            token_address: Address::default(),
+            token_id: U256::default(),
+            token_index: Some(0),
+        };
+        (meta, deposit_data)
+    }
+
+    #[test]
+    fn test_unprocessed_deposits() {
+        let empty = DepositInfo {
+            settled: vec![],
+            pending: vec![],
+            rejected: vec![],
+        };
+        assert!(unprocessed_deposits(empty, &[]).is_empty());
+
+        let info = DepositInfo {
+            settled: vec![deposit("synced", Some(3)), deposit("in-block", Some(4))],
+            pending: vec![deposit("relaying", None)],
+            rejected: vec![deposit("timed-out", None).0],
+        };
+        let deposits = unprocessed_deposits(info, &["synced".to_string()]);
+        let summary = deposits
+            .iter()
+            .map(|deposit| (deposit.meta.uuid.as_str(), deposit.is_pending()))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![("in-block", false), ("relaying", true)]);
+        assert_eq!(deposits[0].block_number, Some(4));
+    }
+}
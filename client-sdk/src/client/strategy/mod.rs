@@ -1,3 +1,4 @@
+pub mod decrypt;
 pub mod deposit;
 pub mod strategy;
 pub mod transfer;
@@ -1,3 +1,5 @@
+use std::{collections::BTreeSet, fmt};
+
 use intmax2_interfaces::{
     api::{
         store_vault_server::interface::StoreVaultClientInterface,
@@ -9,6 +11,7 @@ use intmax2_interfaces::{
     },
 };
 use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use serde::{Deserialize, Serialize};
 
 use intmax2_zkp::common::signature::key_set::KeySet;
 
@@ -30,12 +33,130 @@ pub enum Action {
     Tx(MetaData, TxData<F, C, D>),             // Send tx
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ActionKind {
+    Deposit,
+    Transfer,
+    Tx,
+}
+
+/// What `sync_token` needs to know about an action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionSummary {
+    pub kind: ActionKind,
+    pub uuid: String,
+    pub block_number: u32,
+    /// Token credited by the action. `None` for txs, which may spend any token.
+    pub token_index: Option<u32>,
+}
+
+impl fmt::Display for ActionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} at block {}",
+            self.kind, self.uuid, self.block_number
+        )?;
+        if let Some(token_index) = self.token_index {
+            write!(f, " of token {}", token_index)?;
+        }
+        Ok(())
+    }
+}
+
+impl Action {
+    pub fn summary(&self) -> ActionSummary {
+        let (kind, meta, token_index) = match self {
+            Action::Deposit(meta, data) => (ActionKind::Deposit, meta, data.token_index),
+            Action::Transfer(meta, data) => {
+                (ActionKind::Transfer, meta, Some(data.transfer.token_index))
+            }
+            Action::Tx(meta, _) => (ActionKind::Tx, meta, None),
+        };
+        ActionSummary {
+            kind,
+            uuid: meta.uuid.clone(),
+            block_number: meta.block_number.unwrap_or_default(),
+            token_index,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenSyncPlan {
+    /// Number of actions at the head of the queue to process
+    pub num_eligible: usize,
+    /// Actions of other tokens left for a full sync
+    pub skipped: Vec<ActionSummary>,
+}
+
+/// Plan a sync restricted to the deposits and incoming transfers of `token_index`.
+///
+/// The balance proof only moves forward in block number, and the store vault is
+/// read after the timestamp of the last processed action of each type. An action
+/// of another token, or a tx, queued before an eligible action would therefore be
+/// lost once the eligible action is processed, so it fails with
+/// `RequiresFullSync` instead. Actions after the last eligible one are skipped.
+pub fn plan_token_sync(
+    actions: &[ActionSummary],
+    token_index: u32,
+) -> Result<TokenSyncPlan, ClientError> {
+    let is_eligible = |action: &ActionSummary| {
+        action.kind != ActionKind::Tx && action.token_index == Some(token_index)
+    };
+    let num_eligible = actions
+        .iter()
+        .rposition(is_eligible)
+        .map_or(0, |last| last + 1);
+    if let Some(blocking) = actions[..num_eligible].iter().find(|a| !is_eligible(a)) {
+        return Err(ClientError::RequiresFullSync {
+            blocking_action: blocking.to_string(),
+        });
+    }
+    Ok(TokenSyncPlan {
+        num_eligible,
+        skipped: actions[num_eligible..].to_vec(),
+    })
+}
+
+/// Where the sync is after processing the first of `actions`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockProgress {
+    /// Whether the first action was the last of its block
+    pub completes_block: bool,
+    /// Number of blocks of the other actions, besides the block of the first one
+    pub remaining_blocks: u32,
+}
+
+pub fn block_progress(actions: &[ActionSummary]) -> BlockProgress {
+    let Some((first, rest)) = actions.split_first() else {
+        return BlockProgress {
+            completes_block: false,
+            remaining_blocks: 0,
+        };
+    };
+    let mut blocks = rest
+        .iter()
+        .map(|action| action.block_number)
+        .collect::<BTreeSet<_>>();
+    blocks.remove(&first.block_number);
+    BlockProgress {
+        completes_block: rest.first().map(|next| next.block_number) != Some(first.block_number),
+        remaining_blocks: blocks.len() as u32,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct NextAction {
     pub action: Option<Action>,
+    /// Settled actions in the order they must be processed, starting with `action`
+    pub all_actions: Vec<Action>,
     pub pending_deposits: Vec<MetaData>,
     pub pending_transfers: Vec<MetaData>,
     pub pending_txs: Vec<MetaData>,
+    pub expired_transfers: Vec<MetaData>,
 }
 
 // generate strategy of the balance proof update process
@@ -105,13 +226,123 @@ pub async fn determin_next_action<
     // Sort by block number first, then by priority
     all_actions.sort_by_key(|(block_num, priority, _)| (*block_num, *priority));
 
+    let all_actions = all_actions
+        .into_iter()
+        .map(|(_, _, action)| action)
+        .collect::<Vec<_>>();
+
     // Get the next action
-    let next_action = all_actions.first().map(|(_, _, action)| action.clone());
+    let next_action = all_actions.first().cloned();
 
     Ok(NextAction {
         action: next_action,
-        pending_deposits: deposit_info.pending,
+        all_actions,
+        pending_deposits: deposit_info
+            .pending
+            .into_iter()
+            .map(|(meta, _)| meta)
+            .collect(),
         pending_transfers: transfer_info.pending,
         pending_txs: tx_info.pending,
+        expired_transfers: transfer_info.expired,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::error::ClientError;
+
+    use super::{block_progress, plan_token_sync, ActionKind, ActionSummary, BlockProgress};
+
+    fn action(kind: ActionKind, block_number: u32, token_index: Option<u32>) -> ActionSummary {
+        ActionSummary {
+            kind,
+            uuid: format!("{:?}-{}", kind, block_number),
+            block_number,
+            token_index,
+        }
+    }
+
+    #[test]
+    fn test_token_sync_processes_only_the_token() {
+        let actions = vec![
+            action(ActionKind::Deposit, 1, Some(2)),
+            action(ActionKind::Transfer, 2, Some(2)),
+            action(ActionKind::Deposit, 3, Some(1)),
+            action(ActionKind::Tx, 4, None),
+            action(ActionKind::Transfer, 5, Some(0)),
+        ];
+        let plan = plan_token_sync(&actions, 2).unwrap();
+        assert_eq!(plan.num_eligible, 2);
+        assert_eq!(plan.skipped, actions[2..].to_vec());
+
+        // nothing to prove for a token without actions
+        let plan = plan_token_sync(&actions[2..], 2).unwrap();
+        assert_eq!(plan.num_eligible, 0);
+        assert_eq!(plan.skipped.len(), 3);
+    }
+
+    #[test]
+    fn test_earlier_action_of_another_token_requires_full_sync() {
+        let actions = vec![
+            action(ActionKind::Deposit, 1, Some(2)),
+            action(ActionKind::Deposit, 2, Some(1)),
+            action(ActionKind::Transfer, 3, Some(2)),
+        ];
+        match plan_token_sync(&actions, 2) {
+            Err(ClientError::RequiresFullSync { blocking_action }) => {
+                assert_eq!(blocking_action, "Deposit Deposit-2 at block 2 of token 1")
+            }
+            other => panic!("unexpected plan: {:?}", other),
+        }
+
+        // a tx blocks whatever it spends
+        let actions = vec![
+            action(ActionKind::Tx, 1, None),
+            action(ActionKind::Deposit, 2, Some(2)),
+        ];
+        assert!(matches!(
+            plan_token_sync(&actions, 2),
+            Err(ClientError::RequiresFullSync { .. })
+        ));
+    }
+
+    #[test]
+    fn test_block_progress() {
+        let actions = vec![
+            action(ActionKind::Deposit, 1, Some(2)),
+            action(ActionKind::Transfer, 1, Some(2)),
+            action(ActionKind::Tx, 3, None),
+            action(ActionKind::Deposit, 4, Some(0)),
+        ];
+        // the block of the first action has another action
+        assert_eq!(
+            block_progress(&actions),
+            BlockProgress {
+                completes_block: false,
+                remaining_blocks: 2,
+            }
+        );
+        assert_eq!(
+            block_progress(&actions[1..]),
+            BlockProgress {
+                completes_block: true,
+                remaining_blocks: 2,
+            }
+        );
+        assert_eq!(
+            block_progress(&actions[3..]),
+            BlockProgress {
+                completes_block: true,
+                remaining_blocks: 0,
+            }
+        );
+        assert_eq!(
+            block_progress(&[]),
+            BlockProgress {
+                completes_block: false,
+                remaining_blocks: 0,
+            }
+        );
+    }
+}
@@ -8,7 +8,9 @@ use intmax2_interfaces::{
 use intmax2_zkp::common::signature::key_set::KeySet;
 use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
 
-use crate::client::error::ClientError;
+use crate::{client::error::ClientError, external_api::utils::clock::global_clock};
+
+use super::decrypt::decrypt_blobs;
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
@@ -19,6 +21,7 @@ pub struct TransferInfo {
     pub settled: Vec<(MetaData, TransferData<F, C, D>)>,
     pub pending: Vec<MetaData>,
     pub rejected: Vec<MetaData>,
+    pub expired: Vec<MetaData>, // settled, but not received before the claim deadline
 }
 
 pub async fn fetch_transfer_info<S: StoreVaultClientInterface, V: ValidityProverClientInterface>(
@@ -31,24 +34,45 @@ pub async fn fetch_transfer_info<S: StoreVaultClientInterface, V: ValidityProver
     let mut settled = Vec::new();
     let mut pending = Vec::new();
     let mut rejected = Vec::new();
+    let mut expired = Vec::new();
+    let mut current_block_number = None;
 
     let encrypted_data = store_vault_server
         .get_data_all_after(DataType::Transfer, key.pubkey, transfer_lpt)
         .await?;
-    for (meta, encrypted_data) in encrypted_data {
-        match TransferData::decrypt(&encrypted_data, key) {
+    let decrypted = decrypt_blobs(encrypted_data, |data| {
+        TransferData::<F, C, D>::decrypt(data, key)
+    });
+    for (meta, decrypted) in decrypted {
+        match decrypted {
             Ok(transfer_data) => {
                 let tx_tree_root = transfer_data.tx_data.tx_tree_root;
                 let block_number = validity_prover
                     .get_block_number_by_tx_tree_root(tx_tree_root)
                     .await?;
                 if let Some(block_number) = block_number {
+                    if transfer_data.claim_deadline_block.is_some() {
+                        // receiving now means receiving at the latest block
+                        let current_block_number = match current_block_number {
+                            Some(current_block_number) => current_block_number,
+                            None => {
+                                let latest = validity_prover.get_block_number().await?;
+                                current_block_number = Some(latest);
+                                latest
+                            }
+                        };
+                        if transfer_data.is_expired(current_block_number) {
+                            log::warn!("Transfer {} is expired", meta.uuid);
+                            expired.push(meta);
+                            continue;
+                        }
+                    }
                     // set block number
                     let mut meta = meta;
                     meta.block_number = Some(block_number);
                     settled.push((meta, transfer_data));
                 } else {
-                    if meta.timestamp + tx_timeout < chrono::Utc::now().timestamp() as u64 {
+                    if global_clock().is_timed_out(meta.timestamp, tx_timeout) {
                         // timeout
                         log::error!("Transfer {} is timeouted", meta.uuid);
                         rejected.push(meta);
@@ -73,5 +97,6 @@ pub async fn fetch_transfer_info<S: StoreVaultClientInterface, V: ValidityProver
         settled,
         pending,
         rejected,
+        expired,
     })
 }
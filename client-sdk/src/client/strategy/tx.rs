@@ -1,4 +1,6 @@
-use crate::client::error::ClientError;
+use crate::{client::error::ClientError, external_api::utils::clock::global_clock};
+
+use super::decrypt::decrypt_blobs;
 use intmax2_interfaces::{
     api::{
         store_vault_server::interface::{DataType, StoreVaultClientInterface},
@@ -42,8 +44,9 @@ pub async fn fetch_tx_info<S: StoreVaultClientInterface, V: ValidityProverClient
     let encrypted_data = store_vault_server
         .get_data_all_after(DataType::Tx, key.pubkey, tx_lpt)
         .await?;
-    for (meta, encrypted_data) in encrypted_data {
-        match TxData::decrypt(&encrypted_data, key) {
+    let decrypted = decrypt_blobs(encrypted_data, |data| TxData::<F, C, D>::decrypt(data, key));
+    for (meta, decrypted) in decrypted {
+        match decrypted {
             Ok(tx_data) => {
                 let tx_tree_root = tx_data.common.tx_tree_root;
                 let block_number = validity_prover
@@ -55,7 +58,7 @@ pub async fn fetch_tx_info<S: StoreVaultClientInterface, V: ValidityProverClient
                     meta.block_number = Some(block_number);
                     settled.push((meta, tx_data));
                 } else {
-                    if meta.timestamp + tx_timeout < chrono::Utc::now().timestamp() as u64 {
+                    if global_clock().is_timed_out(meta.timestamp, tx_timeout) {
                         // timeout
                         log::error!("Tx {} is timeouted", meta.uuid);
                         rejected.push(meta);
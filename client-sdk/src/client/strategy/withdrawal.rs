@@ -13,7 +13,9 @@ use plonky2::{
 
 use intmax2_zkp::common::signature::key_set::KeySet;
 
-use crate::client::error::ClientError;
+use crate::{client::error::ClientError, external_api::utils::clock::global_clock};
+
+use super::decrypt::decrypt_blobs;
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
@@ -47,8 +49,11 @@ pub async fn fetch_withdrawal_info<
     let encrypted_data = store_vault_server
         .get_data_all_after(DataType::Withdrawal, key.pubkey, withdrwal_lpt)
         .await?;
-    for (meta, encrypted_data) in encrypted_data {
-        match TransferData::decrypt(&encrypted_data, key) {
+    let decrypted = decrypt_blobs(encrypted_data, |data| {
+        TransferData::<F, C, D>::decrypt(data, key)
+    });
+    for (meta, decrypted) in decrypted {
+        match decrypted {
             Ok(transfer_data) => {
                 let tx_tree_root = transfer_data.tx_data.tx_tree_root;
                 let block_number = validity_prover
@@ -60,7 +65,7 @@ pub async fn fetch_withdrawal_info<
                     meta.block_number = Some(block_number);
                     settled.push((meta, transfer_data));
                 } else {
-                    if meta.timestamp + tx_timeout < chrono::Utc::now().timestamp() as u64 {
+                    if global_clock().is_timed_out(meta.timestamp, tx_timeout) {
                         // timeout
                         log::error!("Withdrawal {} is timeouted", meta.uuid);
                         rejected.push(meta);
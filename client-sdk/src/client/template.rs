@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+
+use intmax2_interfaces::data::encryption::{decrypt, encrypt};
+use intmax2_zkp::{
+    common::{generic_address::GenericAddress, signature::key_set::KeySet, transfer::Transfer},
+    constants::NUM_TRANSFERS_IN_TX,
+    ethereum_types::{address::Address, u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{error::ClientError, utils::generate_salt};
+
+/// Version of the template book format
+pub const TEMPLATE_BOOK_VERSION: u32 = 1;
+
+/// Transfer of a template. The recipient is kept as entered and resolved when the
+/// template is applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateTransfer {
+    /// Hex pubkey (32 bytes), ethereum address (20 bytes), or `@name` of a contact
+    pub recipient: String,
+    pub token_index: u32,
+    pub amount: U256,
+}
+
+/// Named set of transfers sent together, e.g. a monthly payment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxTemplate {
+    pub name: String,
+    pub transfers: Vec<TemplateTransfer>,
+    /// Local note, not sent with the tx
+    pub memo: Option<String>,
+}
+
+/// Values replacing the ones of the template when it is applied
+#[derive(Debug, Clone, Default)]
+pub struct TemplateOverrides {
+    /// New amount, only for templates with a single transfer
+    pub amount: Option<U256>,
+}
+
+/// The templates of an account, stored by the caller, e.g. in a local file for the
+/// CLI. `encrypt` goes through the data encryption of the store vault blobs, which
+/// does not encrypt yet, so the stored bytes are plaintext.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateBook {
+    pub version: u32,
+    pub pubkey: U256,
+    pub templates: BTreeMap<String, TxTemplate>,
+}
+
+fn invalid(name: &str, reason: impl Into<String>) -> ClientError {
+    ClientError::InvalidTemplate {
+        name: name.to_string(),
+        reason: reason.into(),
+    }
+}
+
+/// Parse a hex pubkey or ethereum address
+pub fn parse_recipient(recipient: &str) -> Result<GenericAddress, String> {
+    let hex_str = recipient
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("recipient {} does not start with 0x", recipient))?;
+    match hex_str.len() {
+        40 => Address::from_hex(recipient)
+            .map(GenericAddress::from_address)
+            .map_err(|_| format!("invalid address {}", recipient)),
+        64 => U256::from_hex(recipient)
+            .map(GenericAddress::from_pubkey)
+            .map_err(|_| format!("invalid pubkey {}", recipient)),
+        _ => Err(format!(
+            "recipient {} is neither a pubkey nor an address",
+            recipient
+        )),
+    }
+}
+
+/// Name of the contact of a `@name` recipient
+pub fn contact_name(recipient: &str) -> Option<&str> {
+    recipient.strip_prefix('@')
+}
+
+// checks done both when saving and when applying, since a template may have been
+// saved by an older version with other rules. Contacts are resolved with
+// `resolve_contact`, which is given the name without the `@`.
+fn validate<R>(
+    template: &TxTemplate,
+    resolve_contact: R,
+) -> Result<Vec<GenericAddress>, ClientError>
+where
+    R: Fn(&str) -> Option<U256>,
+{
+    let name = &template.name;
+    if name.trim().is_empty() {
+        return Err(invalid(name, "name is empty"));
+    }
+    if template.transfers.is_empty() {
+        return Err(invalid(name, "no transfers"));
+    }
+    if template.transfers.len() > NUM_TRANSFERS_IN_TX {
+        return Err(invalid(
+            name,
+            format!(
+                "{} transfers, at most {} fit in a tx",
+                template.transfers.len(),
+                NUM_TRANSFERS_IN_TX
+            ),
+        ));
+    }
+    template
+        .transfers
+        .iter()
+        .map(|transfer| {
+            if transfer.amount == U256::default() {
+                return Err(invalid(
+                    name,
+                    format!("zero amount to {}", transfer.recipient),
+                ));
+            }
+            match contact_name(&transfer.recipient) {
+                Some(contact) => resolve_contact(contact)
+                    .map(GenericAddress::from_pubkey)
+                    .ok_or_else(|| invalid(name, format!("no contact named {}", contact))),
+                None => {
+                    parse_recipient(&transfer.recipient).map_err(|reason| invalid(name, reason))
+                }
+            }
+        })
+        .collect()
+}
+
+impl TemplateBook {
+    pub fn new(pubkey: U256) -> Self {
+        Self {
+            version: TEMPLATE_BOOK_VERSION,
+            pubkey,
+            templates: BTreeMap::new(),
+        }
+    }
+
+    pub fn encrypt(&self, pubkey: U256) -> Vec<u8> {
+        encrypt(pubkey, &bincode::serialize(self).unwrap())
+    }
+
+    pub fn decrypt(bytes: &[u8], key: KeySet) -> anyhow::Result<Self> {
+        let book: Self = bincode::deserialize(&decrypt(key, bytes)?)?;
+        anyhow::ensure!(
+            book.version == TEMPLATE_BOOK_VERSION,
+            "unsupported template book version {}",
+            book.version
+        );
+        anyhow::ensure!(
+            book.pubkey == key.pubkey,
+            "template book of another account"
+        );
+        Ok(book)
+    }
+
+    /// The same templates for the account of `pubkey`, e.g. after a key rotation
+    pub fn rekey(&self, pubkey: U256) -> Self {
+        Self {
+            pubkey,
+            ..self.clone()
+        }
+    }
+
+    /// Validate and save `template`, replacing the one of the same name. Contacts
+    /// are only checked to be named, as they are resolved when the template is
+    /// applied.
+    pub fn save_template(&mut self, template: TxTemplate) -> Result<(), ClientError> {
+        validate(&template, |contact| {
+            (!contact.is_empty()).then_some(U256::default())
+        })?;
+        self.templates.insert(template.name.clone(), template);
+        Ok(())
+    }
+
+    pub fn get_templates(&self) -> Vec<TxTemplate> {
+        self.templates.values().cloned().collect()
+    }
+
+    pub fn remove_template(&mut self, name: &str) -> Option<TxTemplate> {
+        self.templates.remove(name)
+    }
+
+    /// Turn the template into transfers with fresh salts. The recipients and
+    /// amounts are validated again, after the overrides are applied. Templates
+    /// with contacts fail, see `apply_template_with_contacts`.
+    pub fn apply_template(
+        &self,
+        name: &str,
+        overrides: &TemplateOverrides,
+    ) -> Result<Vec<Transfer>, ClientError> {
+        self.apply_template_with_contacts(name, overrides, |_| None)
+    }
+
+    /// `apply_template` resolving the `@name` recipients with `resolve_contact`,
+    /// e.g. from an address book, which is given the name without the `@`
+    pub fn apply_template_with_contacts<R>(
+        &self,
+        name: &str,
+        overrides: &TemplateOverrides,
+        resolve_contact: R,
+    ) -> Result<Vec<Transfer>, ClientError>
+    where
+        R: Fn(&str) -> Option<U256>,
+    {
+        let mut template = self
+            .templates
+            .get(name)
+            .cloned()
+            .ok_or_else(|| invalid(name, "not found"))?;
+        if let Some(amount) = overrides.amount {
+            if template.transfers.len() != 1 {
+                return Err(invalid(
+                    name,
+                    "the amount can only be overridden for a single transfer",
+                ));
+            }
+            template.transfers[0].amount = amount;
+        }
+        let recipients = validate(&template, resolve_contact)?;
+        Ok(template
+            .transfers
+            .iter()
+            .zip(recipients)
+            .map(|(transfer, recipient)| Transfer {
+                recipient,
+                token_index: transfer.token_index,
+                amount: transfer.amount,
+                salt: generate_salt(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{
+        common::signature::key_set::KeySet,
+        ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+    };
+
+    use crate::client::error::ClientError;
+
+    use super::{TemplateBook, TemplateOverrides, TemplateTransfer, TxTemplate};
+
+    fn amount(value: u32) -> U256 {
+        U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, value])
+    }
+
+    fn rent(recipient: &str) -> TxTemplate {
+        TxTemplate {
+            name: "rent".to_string(),
+            transfers: vec![TemplateTransfer {
+                recipient: recipient.to_string(),
+                token_index: 2,
+                amount: amount(1200),
+            }],
+            memo: Some("rent".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_save_apply_and_override() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let landlord = KeySet::rand(&mut rand::thread_rng()).pubkey;
+        let mut book = TemplateBook::new(key.pubkey);
+        book.save_template(rent(&landlord.to_hex())).unwrap();
+
+        let book = TemplateBook::decrypt(&book.encrypt(key.pubkey), key).unwrap();
+        assert_eq!(book.get_templates().len(), 1);
+
+        let transfers = book
+            .apply_template("rent", &TemplateOverrides::default())
+            .unwrap();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].recipient.to_pubkey().unwrap(), landlord);
+        assert_eq!(transfers[0].token_index, 2);
+        assert_eq!(transfers[0].amount, amount(1200));
+
+        let overrides = TemplateOverrides {
+            amount: Some(amount(1250)),
+        };
+        let transfers = book.apply_template("rent", &overrides).unwrap();
+        assert_eq!(transfers[0].amount, amount(1250));
+
+        let zero = TemplateOverrides {
+            amount: Some(U256::default()),
+        };
+        assert!(book.apply_template("rent", &zero).is_err());
+        assert!(book
+            .apply_template("groceries", &TemplateOverrides::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_recipient_is_rejected_at_use() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let mut book = TemplateBook::new(key.pubkey);
+        assert!(book.save_template(rent("0x1234")).is_err());
+
+        // e.g. saved by an older version that did not check the recipient
+        let template = rent("0x1234");
+        book.templates.insert(template.name.clone(), template);
+        let error = book
+            .apply_template("rent", &TemplateOverrides::default())
+            .unwrap_err();
+        assert!(matches!(error, ClientError::InvalidTemplate { .. }));
+    }
+
+    #[test]
+    fn test_contact_is_resolved_at_use() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let landlord = KeySet::rand(&mut rand::thread_rng()).pubkey;
+        let mut book = TemplateBook::new(key.pubkey);
+        assert!(book.save_template(rent("@")).is_err());
+        book.save_template(rent("@landlord")).unwrap();
+
+        let contacts = |name: &str| (name == "landlord").then_some(landlord);
+        let transfers = book
+            .apply_template_with_contacts("rent", &TemplateOverrides::default(), contacts)
+            .unwrap();
+        assert_eq!(transfers[0].recipient.to_pubkey().unwrap(), landlord);
+
+        // the contact was deleted since the template was saved
+        let error = book
+            .apply_template_with_contacts("rent", &TemplateOverrides::default(), |_| None)
+            .unwrap_err();
+        assert!(matches!(error, ClientError::InvalidTemplate { .. }));
+    }
+}
@@ -0,0 +1,29 @@
+//! Faults the tests can inject into the sync, to check that the safety nets around
+//! it catch them. Only compiled with the `test-hooks` feature, which the tests of
+//! this crate and of the `tests` crate enable.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use intmax2_interfaces::data::user_data::UserData;
+use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
+
+static ACCOUNTING_BUG: AtomicU32 = AtomicU32::new(0);
+
+/// Credit `amount` of token 0 to the private state of the next synced action
+/// before it is saved, as a bug of the balance accounting would.
+pub fn inject_accounting_bug(amount: u32) {
+    ACCOUNTING_BUG.store(amount, Ordering::SeqCst);
+}
+
+pub(crate) fn apply_accounting_bug(user_data: &mut UserData) {
+    let amount = ACCOUNTING_BUG.swap(0, Ordering::SeqCst);
+    if amount == 0 {
+        return;
+    }
+    let asset_tree = &mut user_data.full_private_state.asset_tree;
+    let leaf = asset_tree.get_leaf(0);
+    asset_tree.update(
+        0,
+        leaf.add(U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, amount])),
+    );
+}
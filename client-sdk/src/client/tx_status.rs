@@ -0,0 +1,198 @@
+//! Whether a finalized tx made it into a block. The validity prover knows the block
+//! of every posted tx tree root; a root it does not know is pending until the tx
+//! timeout has passed since the tx was finalized, and expired after that.
+
+use intmax2_interfaces::{
+    api::{
+        store_vault_server::interface::{DataType, StoreVaultClientInterface},
+        validity_prover::interface::ValidityProverClientInterface,
+    },
+    data::tx_data::TxData,
+};
+use intmax2_zkp::{common::signature::key_set::KeySet, ethereum_types::bytes32::Bytes32};
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::utils::{clock::global_clock, time::sleep_for};
+
+use super::{error::ClientError, provisional::ProvisionalDelta, strategy::decrypt::decrypt_blobs};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Longest wait between two polls of `wait_for_tx_status`, in seconds
+pub const MAX_POLL_INTERVAL: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum TxStatus {
+    /// Not in a block yet, and the tx timeout has not passed
+    Pending,
+    #[serde(rename_all = "camelCase")]
+    Included { block_number: u32 },
+    /// Not in a block, and the tx timeout has passed. The block builder will not
+    /// post it anymore.
+    Expired,
+}
+
+impl TxStatus {
+    /// Whether the status can no longer change
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, TxStatus::Pending)
+    }
+}
+
+/// Status of a tx from the block of its root, if any. `finalized_at` is when the tx
+/// was finalized; without it a tx not in a block is pending.
+pub fn classify_tx_status(
+    block_number: Option<u32>,
+    finalized_at: Option<u64>,
+    tx_timeout: u64,
+) -> TxStatus {
+    match (block_number, finalized_at) {
+        (Some(block_number), _) => TxStatus::Included { block_number },
+        (None, Some(finalized_at)) if global_clock().is_timed_out(finalized_at, tx_timeout) => {
+            TxStatus::Expired
+        }
+        (None, _) => TxStatus::Pending,
+    }
+}
+
+/// Current status of the tx of `tx_tree_root`
+pub async fn get_tx_status<V: ValidityProverClientInterface>(
+    validity_prover: &V,
+    tx_tree_root: Bytes32,
+    finalized_at: Option<u64>,
+    tx_timeout: u64,
+) -> Result<TxStatus, ClientError> {
+    let block_number = validity_prover
+        .get_block_number_by_tx_tree_root(tx_tree_root)
+        .await?;
+    Ok(classify_tx_status(block_number, finalized_at, tx_timeout))
+}
+
+/// When the tx of `tx_tree_root` sent by `key` was finalized, from its provisional
+/// debits kept locally, or else from the time of its backup in the store vault,
+/// which is saved just before the proposal is signed. None if the account has no
+/// record of the tx.
+pub async fn find_tx_finalized_at<S: StoreVaultClientInterface>(
+    store_vault_server: &S,
+    deltas: &[ProvisionalDelta],
+    key: KeySet,
+    tx_tree_root: Bytes32,
+) -> Result<Option<u64>, ClientError> {
+    if let Some(delta) = deltas
+        .iter()
+        .find(|delta| delta.tx_tree_root == tx_tree_root)
+    {
+        return Ok(Some(delta.created_at));
+    }
+    let blobs = store_vault_server
+        .get_data_all_after(DataType::Tx, key.pubkey, 0)
+        .await?;
+    let finalized_at = decrypt_blobs(blobs, |data| TxData::<F, C, D>::decrypt(data, key))
+        .into_iter()
+        .filter_map(|(meta, decrypted)| match decrypted {
+            Ok(tx_data) => Some((meta, tx_data)),
+            Err(e) => {
+                log::warn!("Failed to decrypt tx data {}: {}", meta.uuid, e);
+                None
+            }
+        })
+        .find(|(_, tx_data)| tx_data.common.tx_tree_root == tx_tree_root)
+        .map(|(meta, _)| meta.timestamp);
+    Ok(finalized_at)
+}
+
+/// Poll the status of the tx of `tx_tree_root` until it is included or expired.
+/// The wait between polls starts at `interval` seconds and doubles up to
+/// `MAX_POLL_INTERVAL`. Without `finalized_at`, the tx timeout counts from the
+/// first poll.
+pub async fn wait_for_tx_status<V: ValidityProverClientInterface>(
+    validity_prover: &V,
+    tx_tree_root: Bytes32,
+    finalized_at: Option<u64>,
+    tx_timeout: u64,
+    interval: u64,
+) -> Result<TxStatus, ClientError> {
+    let finalized_at = finalized_at.unwrap_or_else(|| global_clock().server_now());
+    let mut wait = interval.max(1);
+    loop {
+        let status = get_tx_status(
+            validity_prover,
+            tx_tree_root,
+            Some(finalized_at),
+            tx_timeout,
+        )
+        .await?;
+        if status.is_terminal() {
+            return Ok(status);
+        }
+        sleep_for(wait).await;
+        wait = (wait * 2).min(MAX_POLL_INTERVAL.max(interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
+
+    use crate::{
+        client::provisional::{DeltaKind, DeltaStatus},
+        external_api::utils::in_memory_store_vault::InMemoryStoreVault,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_classify_tx_status() {
+        let now = global_clock().server_now();
+        assert_eq!(
+            classify_tx_status(Some(12), Some(now - 1000), 60),
+            TxStatus::Included { block_number: 12 }
+        );
+        assert_eq!(
+            classify_tx_status(None, Some(now - 1000), 60),
+            TxStatus::Expired
+        );
+        assert_eq!(classify_tx_status(None, Some(now), 60), TxStatus::Pending);
+        assert_eq!(classify_tx_status(None, None, 60), TxStatus::Pending);
+        assert!(!TxStatus::Pending.is_terminal());
+
+        let json = serde_json::to_string(&TxStatus::Included { block_number: 3 }).unwrap();
+        assert_eq!(json, r#"{"status":"included","blockNumber":3}"#);
+    }
+
+    #[tokio::test]
+    async fn test_find_tx_finalized_at() {
+        let mut rng = rand::thread_rng();
+        let key = KeySet::rand(&mut rng);
+        let tx_tree_root = Bytes32::rand(&mut rng);
+        let store_vault = InMemoryStoreVault::new();
+        // a blob that is not a tx of the key is skipped
+        store_vault.insert(DataType::Tx, key.pubkey, vec![0u8; 8]);
+
+        let found = find_tx_finalized_at(&store_vault, &[], key, tx_tree_root)
+            .await
+            .unwrap();
+        assert_eq!(found, None);
+
+        let delta = ProvisionalDelta {
+            kind: DeltaKind::Debit,
+            token_index: 0,
+            amount: U256::from(5u128),
+            tx_tree_root,
+            created_at: 1000,
+            status: DeltaStatus::Pending,
+            block_number: None,
+        };
+        let found = find_tx_finalized_at(&store_vault, &[delta], key, tx_tree_root)
+            .await
+            .unwrap();
+        assert_eq!(found, Some(1000));
+
+        // the tx timeout counts from the recorded time, not from now
+        assert_eq!(classify_tx_status(None, found, 60), TxStatus::Expired);
+    }
+}
@@ -1,8 +1,15 @@
+use intmax2_interfaces::api::block_builder::interface::BlockBuilderDependencies;
 use intmax2_zkp::{
-    common::{salt::Salt, transfer::Transfer, trees::transfer_tree::TransferTree},
+    common::{
+        block_builder::BlockProposal, salt::Salt, transfer::Transfer,
+        trees::transfer_tree::TransferTree, tx::Tx,
+    },
     constants::{NUM_TRANSFERS_IN_TX, TRANSFER_TREE_HEIGHT},
+    ethereum_types::u256::U256,
 };
 
+use super::error::ClientError;
+
 pub fn generate_salt() -> Salt {
     let mut rng = rand::thread_rng();
     Salt::rand(&mut rng)
@@ -17,3 +24,202 @@ pub fn generate_transfer_tree(transfers: &[Transfer]) -> TransferTree {
     }
     transfer_tree
 }
+
+/// Check the dependencies reported by the block builder before sending a tx to it.
+/// A builder that has not posted a block within `max_staleness` seconds is treated
+/// as stalled. A builder that never posted a block is not, since it may have just
+/// started.
+pub fn check_builder_health(
+    dependencies: &BlockBuilderDependencies,
+    now: u64,
+    max_staleness: Option<u64>,
+) -> Result<(), ClientError> {
+    let mut problems = Vec::new();
+    if !dependencies.validity_prover_ok {
+        problems.push("validity prover is unreachable".to_string());
+    }
+    if !dependencies.l1_ok {
+        problems.push("L1 is unreachable".to_string());
+    }
+    if let (Some(max_staleness), Some(last_posted_at)) =
+        (max_staleness, dependencies.last_block_posted_at)
+    {
+        let elapsed = now.saturating_sub(last_posted_at);
+        if elapsed > max_staleness {
+            problems.push(format!(
+                "last block was posted {}s ago (threshold {}s)",
+                elapsed, max_staleness
+            ));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ClientError::BuilderUnhealthy {
+            detail: problems.join(", "),
+        })
+    }
+}
+
+/// Check that a proposal returned by the block builder is for `tx` of `pubkey`:
+/// the tx is in the tx tree at `tx_index`, and `pubkey` is the sender at that index.
+pub fn validate_proposal(
+    proposal: &BlockProposal,
+    pubkey: U256,
+    tx: Tx,
+) -> Result<(), ClientError> {
+    proposal.verify(tx).map_err(|e| {
+        ClientError::InvalidBlockProposal(format!("proposal is not for the queried tx: {}", e))
+    })?;
+    if proposal.pubkeys.get(proposal.tx_index as usize) != Some(&pubkey) {
+        return Err(ClientError::InvalidBlockProposal(format!(
+            "sender {} is not at index {} of the proposal",
+            pubkey, proposal.tx_index
+        )));
+    }
+    Ok(())
+}
+
+/// Drop a proposal that fails `validate_proposal`, so that the caller keeps polling,
+/// or return the error if `strict`
+pub fn filter_proposal(
+    proposal: Option<BlockProposal>,
+    pubkey: U256,
+    tx: Tx,
+    strict: bool,
+) -> Result<Option<BlockProposal>, ClientError> {
+    let Some(proposal) = proposal else {
+        return Ok(None);
+    };
+    match validate_proposal(&proposal, pubkey, tx) {
+        Ok(()) => Ok(Some(proposal)),
+        Err(e) if strict => Err(e),
+        Err(e) => {
+            log::warn!("Ignoring block proposal: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_interfaces::api::block_builder::{
+        interface::BlockBuilderClientInterface as _, types::QueryProposalResponse,
+    };
+    use intmax2_zkp::{
+        common::{signature::utils::get_pubkey_hash, trees::tx_tree::TxTree},
+        constants::TX_TREE_HEIGHT,
+        ethereum_types::{bytes32::Bytes32, u32limb_trait::U32LimbTrait as _},
+    };
+
+    use crate::external_api::{
+        block_builder::BlockBuilderClient, utils::mock_server::start_mock_server,
+    };
+
+    use super::*;
+
+    fn dependencies(
+        validity_prover_ok: bool,
+        l1_ok: bool,
+        last_block_posted_at: Option<u64>,
+    ) -> BlockBuilderDependencies {
+        BlockBuilderDependencies {
+            validity_prover_ok,
+            l1_ok,
+            last_block_posted_at,
+        }
+    }
+
+    #[test]
+    fn test_healthy_builder() {
+        check_builder_health(&dependencies(true, true, Some(950)), 1000, Some(60)).unwrap();
+        check_builder_health(&dependencies(true, true, None), 1000, Some(60)).unwrap();
+    }
+
+    #[test]
+    fn test_degraded_builder() {
+        let result = check_builder_health(&dependencies(false, true, Some(990)), 1000, Some(60));
+        match result {
+            Err(ClientError::BuilderUnhealthy { detail }) => {
+                assert!(detail.contains("validity prover"))
+            }
+            _ => panic!("expected BuilderUnhealthy"),
+        }
+        assert!(check_builder_health(&dependencies(true, false, None), 1000, None).is_err());
+    }
+
+    #[test]
+    fn test_stale_builder() {
+        let stale = dependencies(true, true, Some(900));
+        match check_builder_health(&stale, 1000, Some(60)) {
+            Err(ClientError::BuilderUnhealthy { detail }) => assert!(detail.contains("100s ago")),
+            _ => panic!("expected BuilderUnhealthy"),
+        }
+        // the staleness check is disabled without a threshold
+        check_builder_health(&stale, 1000, None).unwrap();
+    }
+
+    // proposals for the txs, in the same way as the block builder
+    fn propose(txs: &[(U256, Tx)]) -> Vec<BlockProposal> {
+        let pubkeys = txs.iter().map(|(pubkey, _)| *pubkey).collect::<Vec<_>>();
+        let mut tx_tree = TxTree::new(TX_TREE_HEIGHT);
+        for (_, tx) in txs {
+            tx_tree.push(tx.clone());
+        }
+        let tx_tree_root: Bytes32 = tx_tree.get_root().into();
+        (0..txs.len())
+            .map(|tx_index| BlockProposal {
+                tx_tree_root,
+                tx_index: tx_index as u32,
+                tx_merkle_proof: tx_tree.prove(tx_index as u64),
+                pubkeys: pubkeys.clone(),
+                pubkeys_hash: get_pubkey_hash(&pubkeys),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_proposal_is_ignored() {
+        let alice = U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        let bob = U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, 2]);
+        let alice_tx = Tx {
+            nonce: 1,
+            ..Default::default()
+        };
+        let bob_tx = Tx {
+            nonce: 2,
+            ..Default::default()
+        };
+        let proposals = propose(&[(alice, alice_tx), (bob, bob_tx)]);
+
+        // a buggy builder answering every query with the proposal of bob's tx
+        let response = serde_json::to_string(&QueryProposalResponse {
+            block_proposal: Some(proposals[1].clone()),
+        })
+        .unwrap();
+        let (url, _) = start_mock_server(move |_, _| response.clone());
+        let proposal = BlockBuilderClient::new()
+            .query_proposal(&url, false, alice, alice_tx)
+            .await
+            .unwrap();
+        assert!(proposal.is_some());
+
+        assert!(filter_proposal(proposal.clone(), alice, alice_tx, false)
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            filter_proposal(proposal.clone(), alice, alice_tx, true),
+            Err(ClientError::InvalidBlockProposal(_))
+        ));
+        // right tx, but another sender
+        assert!(filter_proposal(proposal.clone(), alice, bob_tx, true).is_err());
+        assert!(filter_proposal(proposal, bob, bob_tx, true)
+            .unwrap()
+            .is_some());
+        assert!(
+            filter_proposal(Some(proposals[0].clone()), alice, alice_tx, true)
+                .unwrap()
+                .is_some()
+        );
+    }
+}
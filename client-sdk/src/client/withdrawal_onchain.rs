@@ -0,0 +1,232 @@
+//! Check of the withdrawal aggregator's claims against the liquidity contract. A
+//! withdrawal the aggregator reports as done must have an event for its hash on
+//! L1, which links the status to the tx that submitted it.
+
+use ethers::types::{Address as EthAddress, H256};
+use intmax2_interfaces::api::withdrawal_server::interface::{WithdrawalInfo, WithdrawalStatus};
+use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+
+use crate::external_api::{
+    contract::liquidity_contract::{LiquidityContract, WithdrawalEvent, WithdrawalEventKind},
+    utils::clock::global_clock,
+};
+
+use super::error::ClientError;
+
+/// Default seconds after the request within which a withdrawal reported as done
+/// has to appear on chain
+pub const DEFAULT_WITHDRAWAL_ONCHAIN_GRACE: u64 = 60 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OnchainConfirmation {
+    /// The aggregator has not reported the withdrawal as submitted yet
+    NotSubmitted,
+    /// Reported as submitted, but not found on chain yet
+    Pending,
+    Confirmed {
+        tx_hash: H256,
+        block_number: u64,
+    },
+    /// Reported as submitted, but the contract disagrees or has nothing after the
+    /// grace period
+    SuspectedAggregatorFault {
+        reason: String,
+    },
+}
+
+impl OnchainConfirmation {
+    pub fn is_fault(&self) -> bool {
+        matches!(self, Self::SuspectedAggregatorFault { .. })
+    }
+}
+
+/// Compare the status of `info` with the contract `events` of its withdrawal hash.
+/// `now` and `requested_at` are in the withdrawal server's time.
+pub fn check_withdrawal_events(
+    info: &WithdrawalInfo,
+    events: &[WithdrawalEvent],
+    now: u64,
+    grace_period: u64,
+) -> OnchainConfirmation {
+    let confirmed = |event: &WithdrawalEvent| OnchainConfirmation::Confirmed {
+        tx_hash: event.tx_hash,
+        block_number: event.block_number,
+    };
+    let recipient = EthAddress::from_slice(&info.contract_withdrawal.recipient.to_bytes_be());
+    match info.status {
+        WithdrawalStatus::Success => {
+            if let Some(event) = events
+                .iter()
+                .find(|event| event.kind == WithdrawalEventKind::DirectFailed)
+            {
+                return OnchainConfirmation::SuspectedAggregatorFault {
+                    reason: format!(
+                        "reported as successful, but the direct withdrawal failed in {:?}",
+                        event.tx_hash
+                    ),
+                };
+            }
+            for event in events {
+                match &event.kind {
+                    WithdrawalEventKind::DirectSuccess { recipient: paid_to }
+                        if *paid_to != recipient =>
+                    {
+                        return OnchainConfirmation::SuspectedAggregatorFault {
+                            reason: format!(
+                                "paid to {:?} instead of {:?} in {:?}",
+                                paid_to, recipient, event.tx_hash
+                            ),
+                        };
+                    }
+                    WithdrawalEventKind::DirectSuccess { .. } => return confirmed(event),
+                    _ => {}
+                }
+            }
+        }
+        WithdrawalStatus::NeedClaim => {
+            // a failed direct withdrawal is left to be claimed
+            if let Some(event) = events.iter().find(|event| {
+                matches!(
+                    event.kind,
+                    WithdrawalEventKind::Claimable | WithdrawalEventKind::DirectFailed
+                )
+            }) {
+                return confirmed(event);
+            }
+        }
+        _ => return OnchainConfirmation::NotSubmitted,
+    }
+    match info.requested_at {
+        Some(requested_at) if requested_at + grace_period < now => {
+            OnchainConfirmation::SuspectedAggregatorFault {
+                reason: format!(
+                    "reported as {}, but not on chain {}s after the request",
+                    info.status,
+                    now - requested_at
+                ),
+            }
+        }
+        _ => OnchainConfirmation::Pending,
+    }
+}
+
+/// Look up the withdrawals the aggregator reports as submitted in the liquidity
+/// contract
+pub async fn verify_withdrawals_onchain(
+    liquidity_contract: &LiquidityContract,
+    withdrawal_info: &[WithdrawalInfo],
+    grace_period: u64,
+) -> Result<Vec<OnchainConfirmation>, ClientError> {
+    let mut confirmations = Vec::with_capacity(withdrawal_info.len());
+    for info in withdrawal_info {
+        if !matches!(
+            info.status,
+            WithdrawalStatus::Success | WithdrawalStatus::NeedClaim
+        ) {
+            confirmations.push(OnchainConfirmation::NotSubmitted);
+            continue;
+        }
+        let events = liquidity_contract
+            .get_withdrawal_events(info.contract_withdrawal.withdrawal_hash())
+            .await?;
+        let now = global_clock().server_now();
+        let confirmation = check_withdrawal_events(info, &events, now, grace_period);
+        if confirmation.is_fault() {
+            log::warn!(
+                "withdrawal {}: {:?}",
+                info.contract_withdrawal.nullifier,
+                confirmation
+            );
+        }
+        confirmations.push(confirmation);
+    }
+    Ok(confirmations)
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_interfaces::api::withdrawal_server::interface::ContractWithdrawal;
+    use intmax2_zkp::ethereum_types::{address::Address, bytes32::Bytes32, u256::U256};
+
+    use super::*;
+
+    const GRACE: u64 = 600;
+
+    fn info(status: WithdrawalStatus) -> WithdrawalInfo {
+        WithdrawalInfo {
+            status,
+            contract_withdrawal: ContractWithdrawal {
+                recipient: Address::from_bytes_be(&[7u8; 20]),
+                token_index: 0,
+                amount: U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, 10]),
+                nullifier: Bytes32::default(),
+            },
+            attribution_tag_hash: None,
+            requested_at: Some(1000),
+        }
+    }
+
+    fn event(kind: WithdrawalEventKind) -> WithdrawalEvent {
+        WithdrawalEvent {
+            kind,
+            tx_hash: H256::repeat_byte(1),
+            block_number: 42,
+        }
+    }
+
+    #[test]
+    fn test_submitted_withdrawal_is_confirmed() {
+        let paid = event(WithdrawalEventKind::DirectSuccess {
+            recipient: EthAddress::repeat_byte(7),
+        });
+        assert_eq!(
+            check_withdrawal_events(&info(WithdrawalStatus::Success), &[paid], 1100, GRACE),
+            OnchainConfirmation::Confirmed {
+                tx_hash: H256::repeat_byte(1),
+                block_number: 42
+            }
+        );
+        let claimable = event(WithdrawalEventKind::Claimable);
+        assert!(matches!(
+            check_withdrawal_events(
+                &info(WithdrawalStatus::NeedClaim),
+                &[claimable],
+                1100,
+                GRACE
+            ),
+            OnchainConfirmation::Confirmed { .. }
+        ));
+    }
+
+    #[test]
+    fn test_missing_event_is_a_fault_after_the_grace_period() {
+        let success = info(WithdrawalStatus::Success);
+        assert_eq!(
+            check_withdrawal_events(&success, &[], 1000 + GRACE, GRACE),
+            OnchainConfirmation::Pending
+        );
+        assert!(check_withdrawal_events(&success, &[], 1001 + GRACE, GRACE).is_fault());
+        // without the request time, there is nothing to measure the grace from
+        let mut unknown = success.clone();
+        unknown.requested_at = None;
+        assert_eq!(
+            check_withdrawal_events(&unknown, &[], u64::MAX, GRACE),
+            OnchainConfirmation::Pending
+        );
+        assert_eq!(
+            check_withdrawal_events(&info(WithdrawalStatus::Relayed), &[], u64::MAX, GRACE),
+            OnchainConfirmation::NotSubmitted
+        );
+    }
+
+    #[test]
+    fn test_contradicting_event_is_a_fault() {
+        let success = info(WithdrawalStatus::Success);
+        let failed = event(WithdrawalEventKind::DirectFailed);
+        assert!(check_withdrawal_events(&success, &[failed], 1100, GRACE).is_fault());
+        let paid_elsewhere = event(WithdrawalEventKind::DirectSuccess {
+            recipient: EthAddress::repeat_byte(8),
+        });
+        assert!(check_withdrawal_events(&success, &[paid_elsewhere], 1100, GRACE).is_fault());
+    }
+}
@@ -0,0 +1,950 @@
+//! Witnesses of a range of blocks gathered for an account by a service that only
+//! knows its pubkey, so that a slow client, e.g. a mobile wallet, does not make
+//! the many validity prover round trips of a sync itself. The service cannot
+//! decrypt the account's data and gathers only what is public: the validity
+//! public inputs and sender leaves of the blocks, their block merkle proofs and
+//! the update witnesses of the pubkey. The client checks every merkle proof
+//! against the validity public inputs before use, and fetches from the validity
+//! prover whatever the bundle lacks.
+
+use std::sync::Arc;
+
+use ethers::{
+    signers::{LocalWallet, Signer as _},
+    types::{Address as EthAddress, Signature, H256},
+    utils::keccak256,
+};
+use intmax2_interfaces::api::{
+    balance_prover::interface::BalanceProverClientInterface,
+    block_builder::interface::BlockBuilderClientInterface,
+    store_vault_server::interface::StoreVaultClientInterface,
+    validity_prover::interface::ValidityProverClientInterface,
+    withdrawal_server::interface::WithdrawalServerClientInterface,
+};
+use intmax2_zkp::{
+    circuits::validity::validity_pis::ValidityPublicInputs,
+    common::{
+        signature::key_set::KeySet,
+        trees::{
+            account_tree::AccountMembershipProof,
+            block_hash_tree::BlockHashMerkleProof,
+            sender_tree::{get_sender_tree_root, SenderLeaf},
+        },
+        witness::update_witness::UpdateWitness,
+    },
+    ethereum_types::{bytes32::Bytes32, u256::U256},
+};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    external_api::cached_validity_prover::{CachedValidityProver, WitnessKey},
+    utils::circuit_verifiers::verifiers,
+};
+
+use super::{
+    client::{Client, SyncReport},
+    error::ClientError,
+};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// Raised when the layout of the bundle changes
+pub const WITNESS_BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WitnessBundleError {
+    #[error("Unsupported witness bundle version {0}, expected {WITNESS_BUNDLE_VERSION}")]
+    UnsupportedVersion(u32),
+
+    #[error("Witness bundle is for pubkey {actual}, not {expected}")]
+    WrongPubkey { expected: U256, actual: U256 },
+
+    #[error("Witness bundle is signed by {actual:?}, expected {expected:?}")]
+    WrongSigner {
+        expected: EthAddress,
+        actual: EthAddress,
+    },
+
+    #[error("Invalid witness bundle signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Invalid witness bundle: {0}")]
+    Invalid(String),
+
+    #[error("Failed to decode witness bundle: {0}")]
+    DecodeError(String),
+}
+
+/// Public data of one block of the bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundledBlock {
+    pub validity_pis: ValidityPublicInputs,
+    pub sender_leaves: Vec<SenderLeaf>,
+    /// Of the block hash in the block tree of `to_block`
+    pub block_merkle_proof: BlockHashMerkleProof,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WitnessBundle {
+    pub version: u32,
+    pub pubkey: U256,
+    pub from_block: u32,
+    pub to_block: u32,
+    /// Of the blocks `from_block..=to_block`, in order
+    pub blocks: Vec<BundledBlock>,
+    /// Validity proof of `to_block`, shared by the update witnesses up to it
+    pub root_validity_proof: ProofWithPublicInputs<F, C, D>,
+    /// Of the pubkey in the account trees of `to_block` and of the block before
+    pub account_membership_proof: AccountMembershipProof,
+    pub prev_account_membership_proof: AccountMembershipProof,
+    /// Update witnesses of the sends of the pubkey, each from the previous send in
+    /// the range or from `from_block`, with the leaf block number
+    pub send_witnesses: Vec<(u32, UpdateWitness<F, C, D>)>,
+}
+
+impl WitnessBundle {
+    fn block(&self, block_number: u32) -> Option<&BundledBlock> {
+        if block_number < self.from_block {
+            return None;
+        }
+        self.blocks.get((block_number - self.from_block) as usize)
+    }
+
+    pub fn validity_pis(&self, block_number: u32) -> Option<ValidityPublicInputs> {
+        self.block(block_number)
+            .map(|block| block.validity_pis.clone())
+    }
+
+    pub fn sender_leaves(&self, block_number: u32) -> Option<Vec<SenderLeaf>> {
+        self.block(block_number)
+            .map(|block| block.sender_leaves.clone())
+    }
+
+    /// `None` if the tx tree root is not posted in the range, which does not mean
+    /// it is not posted at all
+    pub fn block_number_by_tx_tree_root(&self, tx_tree_root: Bytes32) -> Option<u32> {
+        self.blocks
+            .iter()
+            .find(|block| block.validity_pis.tx_tree_root == tx_tree_root)
+            .map(|block| block.validity_pis.public_state.block_number)
+    }
+
+    pub fn block_merkle_proof(
+        &self,
+        root_block_number: u32,
+        leaf_block_number: u32,
+    ) -> Option<BlockHashMerkleProof> {
+        if root_block_number != self.to_block {
+            return None;
+        }
+        self.block(leaf_block_number)
+            .map(|block| block.block_merkle_proof.clone())
+    }
+
+    pub fn account_membership_proof(
+        &self,
+        pubkey: U256,
+        block_number: u32,
+    ) -> Option<AccountMembershipProof> {
+        if pubkey != self.pubkey {
+            return None;
+        }
+        if block_number == self.to_block {
+            Some(self.account_membership_proof.clone())
+        } else if block_number + 1 == self.to_block {
+            Some(self.prev_account_membership_proof.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn update_witness(&self, key: &WitnessKey) -> Option<UpdateWitness<F, C, D>> {
+        if key.pubkey != self.pubkey {
+            return None;
+        }
+        if key.root_block_number == self.to_block {
+            let account_membership_proof = if key.is_prev_account_tree {
+                self.prev_account_membership_proof.clone()
+            } else {
+                self.account_membership_proof.clone()
+            };
+            return Some(UpdateWitness {
+                is_prev_account_tree: key.is_prev_account_tree,
+                validity_proof: self.root_validity_proof.clone(),
+                block_merkle_proof: self
+                    .block_merkle_proof(key.root_block_number, key.leaf_block_number)?,
+                account_membership_proof,
+            });
+        }
+        self.send_witnesses
+            .iter()
+            .find(|(leaf_block_number, witness)| {
+                *leaf_block_number == key.leaf_block_number && key.matches(witness)
+            })
+            .map(|(_, witness)| witness.clone())
+    }
+
+    /// Check that the bundle is for `pubkey`, that its validity proofs hold and that
+    /// every other proof in it leads to the validity public inputs they prove. The
+    /// public inputs of the blocks without a validity proof are bound by their
+    /// block hash in the proven block tree, and by the chain of account tree roots
+    /// up to the proven block.
+    pub fn verify(&self, pubkey: U256) -> Result<(), WitnessBundleError> {
+        let validity_vd = verifiers().get_validity_vd();
+        self.verify_with(pubkey, &|proof| validity_vd.verify(proof.clone()).is_ok())
+    }
+
+    /// `verify` with `verify_proof` checking the validity proofs
+    pub(crate) fn verify_with(
+        &self,
+        pubkey: U256,
+        verify_proof: &dyn Fn(&ProofWithPublicInputs<F, C, D>) -> bool,
+    ) -> Result<(), WitnessBundleError> {
+        let invalid = WitnessBundleError::Invalid;
+        if self.version != WITNESS_BUNDLE_VERSION {
+            return Err(WitnessBundleError::UnsupportedVersion(self.version));
+        }
+        if self.pubkey != pubkey {
+            return Err(WitnessBundleError::WrongPubkey {
+                expected: pubkey,
+                actual: self.pubkey,
+            });
+        }
+        if self.from_block == 0 || self.from_block > self.to_block {
+            return Err(invalid(format!(
+                "invalid block range {}..={}",
+                self.from_block, self.to_block
+            )));
+        }
+        if self.blocks.len() != (self.to_block - self.from_block + 1) as usize {
+            return Err(invalid(format!(
+                "{} blocks for the range {}..={}",
+                self.blocks.len(),
+                self.from_block,
+                self.to_block
+            )));
+        }
+        let mut prev_account_tree_root = None;
+        for (block_number, block) in (self.from_block..=self.to_block).zip(self.blocks.iter()) {
+            let public_state = &block.validity_pis.public_state;
+            if public_state.block_number != block_number {
+                return Err(invalid(format!(
+                    "block {} is out of order",
+                    public_state.block_number
+                )));
+            }
+            if prev_account_tree_root
+                .is_some_and(|root| root != public_state.prev_account_tree_root)
+            {
+                return Err(invalid(format!(
+                    "account tree root of block {} does not follow the previous block",
+                    block_number
+                )));
+            }
+            prev_account_tree_root = Some(public_state.account_tree_root);
+        }
+        let root_pis = self.validity_pis(self.to_block).unwrap();
+        if ValidityPublicInputs::from_pis(&self.root_validity_proof.public_inputs) != root_pis {
+            return Err(invalid(format!(
+                "validity proof is not of block {}",
+                self.to_block
+            )));
+        }
+        if !verify_proof(&self.root_validity_proof) {
+            return Err(invalid(format!(
+                "validity proof of block {} does not hold",
+                self.to_block
+            )));
+        }
+        for block in self.blocks.iter() {
+            let pis = &block.validity_pis;
+            block
+                .block_merkle_proof
+                .verify(
+                    &pis.public_state.block_hash,
+                    pis.public_state.block_number as u64,
+                    root_pis.public_state.block_tree_root,
+                )
+                .map_err(|e| {
+                    invalid(format!(
+                        "block {} is not in the block tree of block {}: {}",
+                        pis.public_state.block_number, self.to_block, e
+                    ))
+                })?;
+            if get_sender_tree_root(&block.sender_leaves) != pis.sender_tree_root {
+                return Err(invalid(format!(
+                    "sender leaves of block {} do not match its sender tree root",
+                    pis.public_state.block_number
+                )));
+            }
+        }
+        self.account_membership_proof
+            .verify(pubkey, root_pis.public_state.account_tree_root)
+            .map_err(|e| invalid(format!("account membership proof: {}", e)))?;
+        self.prev_account_membership_proof
+            .verify(pubkey, root_pis.public_state.prev_account_tree_root)
+            .map_err(|e| invalid(format!("previous account membership proof: {}", e)))?;
+        for (leaf_block_number, witness) in self.send_witnesses.iter() {
+            let witness_pis = ValidityPublicInputs::from_pis(&witness.validity_proof.public_inputs);
+            let root_block_number = witness_pis.public_state.block_number;
+            let (Some(root_pis), Some(leaf_pis)) = (
+                self.validity_pis(root_block_number),
+                self.validity_pis(*leaf_block_number),
+            ) else {
+                return Err(invalid(format!(
+                    "send witness {}..{} is out of the range",
+                    leaf_block_number, root_block_number
+                )));
+            };
+            if witness_pis != root_pis || !witness.is_prev_account_tree {
+                return Err(invalid(format!(
+                    "send witness of block {} does not match the block",
+                    root_block_number
+                )));
+            }
+            if !verify_proof(&witness.validity_proof) {
+                return Err(invalid(format!(
+                    "validity proof of block {} does not hold",
+                    root_block_number
+                )));
+            }
+            witness
+                .block_merkle_proof
+                .verify(
+                    &leaf_pis.public_state.block_hash,
+                    *leaf_block_number as u64,
+                    root_pis.public_state.block_tree_root,
+                )
+                .map_err(|e| {
+                    invalid(format!(
+                        "send witness of block {}: {}",
+                        root_block_number, e
+                    ))
+                })?;
+            witness
+                .account_membership_proof
+                .verify(pubkey, root_pis.public_state.prev_account_tree_root)
+                .map_err(|e| {
+                    invalid(format!(
+                        "send witness of block {}: {}",
+                        root_block_number, e
+                    ))
+                })?;
+        }
+        Ok(())
+    }
+
+    fn hash(&self) -> H256 {
+        H256::from(keccak256(bincode::serialize(self).unwrap()))
+    }
+}
+
+/// A bundle with the signature of the service that prepared it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedWitnessBundle {
+    pub bundle: WitnessBundle,
+    pub signer: EthAddress,
+    pub signature: Vec<u8>,
+}
+
+// the version is the first field, so that it can be read from any layout
+#[derive(Deserialize)]
+struct BundleVersion {
+    version: u32,
+}
+
+impl SignedWitnessBundle {
+    /// Sign `bundle` with the Ethereum key of the service
+    pub fn sign(bundle: WitnessBundle, signer_key: H256) -> Result<Self, WitnessBundleError> {
+        let wallet = LocalWallet::from_bytes(signer_key.as_bytes())
+            .map_err(|e| WitnessBundleError::InvalidSignature(e.to_string()))?;
+        let signature = wallet
+            .sign_hash(bundle.hash())
+            .map_err(|e| WitnessBundleError::InvalidSignature(e.to_string()))?;
+        Ok(Self {
+            bundle,
+            signer: wallet.address(),
+            signature: signature.to_vec(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, WitnessBundleError> {
+        let header: BundleVersion = bincode::deserialize(data)
+            .map_err(|e| WitnessBundleError::DecodeError(e.to_string()))?;
+        if header.version != WITNESS_BUNDLE_VERSION {
+            return Err(WitnessBundleError::UnsupportedVersion(header.version));
+        }
+        bincode::deserialize(data).map_err(|e| WitnessBundleError::DecodeError(e.to_string()))
+    }
+
+    /// Check the signature, by `trusted_signer` if given, and the contents of the
+    /// bundle for `pubkey`
+    pub fn verify(
+        &self,
+        pubkey: U256,
+        trusted_signer: Option<EthAddress>,
+    ) -> Result<(), WitnessBundleError> {
+        let validity_vd = verifiers().get_validity_vd();
+        self.verify_with(pubkey, trusted_signer, &|proof| {
+            validity_vd.verify(proof.clone()).is_ok()
+        })
+    }
+
+    pub(crate) fn verify_with(
+        &self,
+        pubkey: U256,
+        trusted_signer: Option<EthAddress>,
+        verify_proof: &dyn Fn(&ProofWithPublicInputs<F, C, D>) -> bool,
+    ) -> Result<(), WitnessBundleError> {
+        let signature = Signature::try_from(self.signature.as_slice())
+            .map_err(|e| WitnessBundleError::InvalidSignature(e.to_string()))?;
+        let signer = signature
+            .recover(self.bundle.hash())
+            .map_err(|e| WitnessBundleError::InvalidSignature(e.to_string()))?;
+        if signer != self.signer {
+            return Err(WitnessBundleError::InvalidSignature(format!(
+                "signed by {:?}, not by {:?}",
+                signer, self.signer
+            )));
+        }
+        if let Some(trusted_signer) = trusted_signer {
+            if signer != trusted_signer {
+                return Err(WitnessBundleError::WrongSigner {
+                    expected: trusted_signer,
+                    actual: signer,
+                });
+            }
+        }
+        self.bundle.verify_with(pubkey, verify_proof)
+    }
+}
+
+/// Gather the public witnesses of `pubkey` for the blocks `from_block..=to_block`,
+/// e.g. from the last block the account synced to. Needs no key of the account.
+pub async fn prepare_public_witness_bundle<V: ValidityProverClientInterface>(
+    validity_prover: &V,
+    pubkey: U256,
+    from_block: u32,
+    to_block: u32,
+) -> Result<WitnessBundle, ClientError> {
+    let latest_block_number = validity_prover.get_block_number().await?;
+    if from_block == 0 || from_block > to_block || to_block > latest_block_number {
+        return Err(WitnessBundleError::Invalid(format!(
+            "invalid block range {}..={}, the latest block is {}",
+            from_block, to_block, latest_block_number
+        ))
+        .into());
+    }
+    let mut blocks = Vec::with_capacity((to_block - from_block + 1) as usize);
+    for block_number in from_block..=to_block {
+        let validity_pis = validity_prover
+            .get_validity_pis(block_number)
+            .await?
+            .ok_or(ClientError::InternalError(format!(
+                "validity public inputs not found for block number {}",
+                block_number
+            )))?;
+        let sender_leaves = validity_prover
+            .get_sender_leaves(block_number)
+            .await?
+            .ok_or(ClientError::InternalError(format!(
+                "sender leaves not found for block number {}",
+                block_number
+            )))?;
+        let block_merkle_proof = validity_prover
+            .get_block_merkle_proof(to_block, block_number)
+            .await?;
+        blocks.push(BundledBlock {
+            validity_pis,
+            sender_leaves,
+            block_merkle_proof,
+        });
+    }
+    let root_witness = validity_prover
+        .get_update_witness(pubkey, to_block, to_block, false)
+        .await?;
+    let prev_account_membership_proof = validity_prover
+        .get_account_membership_proof(pubkey, to_block - 1)
+        .await?;
+
+    // as in the watcher, the sends are visible in the sender leaves
+    let mut send_witnesses = Vec::new();
+    let mut prev_block_number = from_block;
+    for block in blocks.iter() {
+        let block_number = block.validity_pis.public_state.block_number;
+        let did_send = block
+            .sender_leaves
+            .iter()
+            .any(|leaf| leaf.sender == pubkey && leaf.did_return_sig);
+        if !did_send || block_number <= prev_block_number {
+            continue;
+        }
+        let witness = validity_prover
+            .get_update_witness(pubkey, block_number, prev_block_number, true)
+            .await?;
+        send_witnesses.push((prev_block_number, witness));
+        prev_block_number = block_number;
+    }
+
+    Ok(WitnessBundle {
+        version: WITNESS_BUNDLE_VERSION,
+        pubkey,
+        from_block,
+        to_block,
+        blocks,
+        root_validity_proof: root_witness.validity_proof,
+        account_membership_proof: root_witness.account_membership_proof,
+        prev_account_membership_proof,
+        send_witnesses,
+    })
+}
+
+impl<
+        BB: BlockBuilderClientInterface,
+        S: StoreVaultClientInterface,
+        V: ValidityProverClientInterface,
+        B: BalanceProverClientInterface,
+        W: WithdrawalServerClientInterface,
+    > Client<BB, S, CachedValidityProver<V>, B, W>
+{
+    /// Sync up to the last block of `bundle`, taking the witnesses from it after
+    /// checking them. Those it lacks, e.g. of the deposits, are fetched from the
+    /// validity prover.
+    pub async fn sync_with_witness_bundle(
+        &self,
+        key: KeySet,
+        bundle: &SignedWitnessBundle,
+        trusted_signer: Option<EthAddress>,
+    ) -> Result<SyncReport, ClientError> {
+        bundle.verify(key.pubkey, trusted_signer)?;
+        log::info!(
+            "syncing with the witness bundle of blocks {}..={} signed by {:?}",
+            bundle.bundle.from_block,
+            bundle.bundle.to_block,
+            bundle.signer
+        );
+        self.validity_prover
+            .load_witness_bundle(Arc::new(bundle.bundle.clone()));
+        let result = self.sync_with_report(key).await;
+        self.validity_prover.unload_witness_bundle();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use async_trait::async_trait;
+    use intmax2_interfaces::api::{
+        error::ServerError,
+        validity_prover::interface::{AccountInfo, DepositInfo},
+    };
+    use intmax2_zkp::{
+        common::trees::{
+            account_tree::AccountTree, block_hash_tree::BlockHashTree,
+            deposit_tree::DepositMerkleProof,
+        },
+        constants::BLOCK_HASH_TREE_HEIGHT,
+        ethereum_types::u32limb_trait::U32LimbTrait as _,
+    };
+    use plonky2::{
+        field::types::Field,
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_builder::CircuitBuilder,
+            circuit_data::{CircuitConfig, VerifierCircuitData},
+        },
+    };
+
+    use super::*;
+
+    const LATEST_BLOCK: u32 = 4;
+
+    type ValidityProof = (ProofWithPublicInputs<F, C, D>, VerifierCircuitData<F, C, D>);
+
+    // a proof of a trivial circuit whose public inputs are `pis`, which is all the
+    // client reads of a validity proof, with the data to verify it
+    fn dummy_validity_proof(pis: &ValidityPublicInputs) -> ValidityProof {
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        for value in pis.to_u64_vec() {
+            let target = builder.constant(F::from_canonical_u64(value));
+            builder.register_public_input(target);
+        }
+        let data = builder.build::<C>();
+        let proof = data.prove(PartialWitness::new()).unwrap();
+        (proof, data.verifier_data())
+    }
+
+    /// Validity prover with consistent trees, counting the calls
+    #[derive(Debug)]
+    struct MockValidityProver {
+        pubkey: U256,
+        pis: Vec<ValidityPublicInputs>,
+        sender_leaves: Vec<Vec<SenderLeaf>>,
+        // block tree after each block
+        block_trees: Vec<BlockHashTree>,
+        account_tree: AccountTree,
+        proofs: Mutex<Vec<Option<ValidityProof>>>,
+        calls: AtomicUsize,
+    }
+
+    impl MockValidityProver {
+        // `pubkey` sends in block 2
+        fn new(pubkey: U256) -> Self {
+            let mut rng = rand::thread_rng();
+            let account_tree = AccountTree::initialize();
+            let mut block_tree = BlockHashTree::new(BLOCK_HASH_TREE_HEIGHT);
+            let mut pis = Vec::new();
+            let mut sender_leaves = Vec::new();
+            let mut block_trees = Vec::new();
+            for block_number in 0..=LATEST_BLOCK {
+                let leaves = vec![
+                    SenderLeaf {
+                        sender: U256::rand(&mut rng),
+                        did_return_sig: true,
+                    },
+                    SenderLeaf {
+                        sender: pubkey,
+                        did_return_sig: block_number == 2,
+                    },
+                ];
+                let block_hash = Bytes32::rand(&mut rng);
+                block_tree.push(block_hash);
+                let mut block_pis = ValidityPublicInputs::genesis();
+                block_pis.public_state.block_number = block_number;
+                block_pis.public_state.block_hash = block_hash;
+                block_pis.public_state.block_tree_root = block_tree.get_root();
+                block_pis.public_state.account_tree_root = account_tree.get_root();
+                block_pis.public_state.prev_account_tree_root = account_tree.get_root();
+                block_pis.tx_tree_root = Bytes32::rand(&mut rng);
+                block_pis.sender_tree_root = get_sender_tree_root(&leaves);
+                block_pis.is_valid_block = true;
+                pis.push(block_pis);
+                sender_leaves.push(leaves);
+                block_trees.push(block_tree.clone());
+            }
+            Self {
+                pubkey,
+                pis,
+                sender_leaves,
+                block_trees,
+                account_tree,
+                proofs: Mutex::new(vec![None; LATEST_BLOCK as usize + 1]),
+                calls: AtomicUsize::new(0),
+            }
+        }
+
+        fn count(&self) {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn validity_proof(&self, block_number: u32) -> ProofWithPublicInputs<F, C, D> {
+            let mut proofs = self.proofs.lock().unwrap();
+            proofs[block_number as usize]
+                .get_or_insert_with(|| dummy_validity_proof(&self.pis[block_number as usize]))
+                .0
+                .clone()
+        }
+
+        // stands in for the validity circuit: the proof has to hold for the dummy
+        // circuit of the block whose public inputs it has
+        fn verify_proof(&self, proof: &ProofWithPublicInputs<F, C, D>) -> bool {
+            let proofs = self.proofs.lock().unwrap();
+            proofs
+                .iter()
+                .flatten()
+                .find(|(known, _)| known.public_inputs == proof.public_inputs)
+                .is_some_and(|(_, vd)| vd.verify(proof.clone()).is_ok())
+        }
+    }
+
+    // the witness bundle needs no deposit merkle proof
+    fn not_served() -> ServerError {
+        ServerError::InternalError("not served by the mock validity prover".to_string())
+    }
+
+    #[async_trait(?Send)]
+    impl ValidityProverClientInterface for MockValidityProver {
+        async fn get_block_number(&self) -> Result<u32, ServerError> {
+            self.count();
+            Ok(LATEST_BLOCK)
+        }
+
+        async fn get_update_witness(
+            &self,
+            pubkey: U256,
+            root_block_number: u32,
+            leaf_block_number: u32,
+            is_prev_account_tree: bool,
+        ) -> Result<UpdateWitness<F, C, D>, ServerError> {
+            self.count();
+            Ok(UpdateWitness {
+                is_prev_account_tree,
+                validity_proof: self.validity_proof(root_block_number),
+                block_merkle_proof: self.block_trees[root_block_number as usize]
+                    .prove(leaf_block_number as u64),
+                account_membership_proof: self.account_tree.prove_membership(pubkey),
+            })
+        }
+
+        async fn get_account_membership_proof(
+            &self,
+            pubkey: U256,
+            _block_number: u32,
+        ) -> Result<AccountMembershipProof, ServerError> {
+            self.count();
+            Ok(self.account_tree.prove_membership(pubkey))
+        }
+
+        async fn get_deposit_info(
+            &self,
+            _deposit_hash: Bytes32,
+        ) -> Result<Option<DepositInfo>, ServerError> {
+            self.count();
+            Ok(None)
+        }
+
+        async fn get_block_number_by_tx_tree_root(
+            &self,
+            tx_tree_root: Bytes32,
+        ) -> Result<Option<u32>, ServerError> {
+            self.count();
+            Ok(self
+                .pis
+                .iter()
+                .find(|pis| pis.tx_tree_root == tx_tree_root)
+                .map(|pis| pis.public_state.block_number))
+        }
+
+        async fn get_validity_pis(
+            &self,
+            block_number: u32,
+        ) -> Result<Option<ValidityPublicInputs>, ServerError> {
+            self.count();
+            Ok(self.pis.get(block_number as usize).cloned())
+        }
+
+        async fn get_sender_leaves(
+            &self,
+            block_number: u32,
+        ) -> Result<Option<Vec<SenderLeaf>>, ServerError> {
+            self.count();
+            Ok(self.sender_leaves.get(block_number as usize).cloned())
+        }
+
+        async fn get_block_merkle_proof(
+            &self,
+            root_block_number: u32,
+            leaf_block_number: u32,
+        ) -> Result<BlockHashMerkleProof, ServerError> {
+            self.count();
+            Ok(self.block_trees[root_block_number as usize].prove(leaf_block_number as u64))
+        }
+
+        async fn get_deposit_merkle_proof(
+            &self,
+            _block_number: u32,
+            _deposit_index: u32,
+        ) -> Result<DepositMerkleProof, ServerError> {
+            Err(not_served())
+        }
+
+        async fn get_account_info(&self, _pubkey: U256) -> Result<AccountInfo, ServerError> {
+            self.count();
+            Ok(AccountInfo {
+                account_id: None,
+                block_number: LATEST_BLOCK,
+            })
+        }
+    }
+
+    async fn signed_bundle(mock: &MockValidityProver) -> SignedWitnessBundle {
+        let bundle = prepare_public_witness_bundle(mock, mock.pubkey, 1, LATEST_BLOCK)
+            .await
+            .unwrap();
+        SignedWitnessBundle::sign(bundle, H256::repeat_byte(7)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_valid_bundle_replaces_the_validity_prover() {
+        let mut rng = rand::thread_rng();
+        let pubkey = U256::rand(&mut rng);
+        let mock = MockValidityProver::new(pubkey);
+        let bundle = signed_bundle(&mock).await;
+        let bundle = SignedWitnessBundle::from_bytes(&bundle.to_bytes()).unwrap();
+        bundle
+            .verify_with(pubkey, Some(bundle.signer), &|proof| {
+                mock.verify_proof(proof)
+            })
+            .unwrap();
+
+        let tx_tree_root = mock.pis[3].tx_tree_root;
+        let prover = CachedValidityProver::new(mock);
+        prover.load_witness_bundle(Arc::new(bundle.bundle.clone()));
+        // the latest block is always the validity prover's, the bundle may be behind
+        assert_eq!(prover.get_block_number().await.unwrap(), LATEST_BLOCK);
+        let calls_before = prover.inner().calls.load(Ordering::SeqCst);
+        assert!(calls_before > 0);
+
+        // what a sync from block 1 asks for
+        for block_number in 1..=LATEST_BLOCK {
+            let pis = prover
+                .get_validity_pis(block_number)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(pis.public_state.block_number, block_number);
+            prover
+                .get_sender_leaves(block_number)
+                .await
+                .unwrap()
+                .unwrap();
+            prover
+                .get_block_merkle_proof(LATEST_BLOCK, block_number)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            prover
+                .get_block_number_by_tx_tree_root(tx_tree_root)
+                .await
+                .unwrap(),
+            Some(3)
+        );
+        let update = prover
+            .get_update_witness(pubkey, LATEST_BLOCK, 1, false)
+            .await
+            .unwrap();
+        assert_eq!(update.get_last_block_number(), 0);
+        // the send of block 2, from the balance proof of block 1
+        let send = prover.get_update_witness(pubkey, 2, 1, true).await.unwrap();
+        assert!(send.is_prev_account_tree);
+        assert_eq!(prover.inner().calls.load(Ordering::SeqCst), calls_before);
+
+        // a witness outside of the bundle is fetched
+        prover.get_update_witness(pubkey, 3, 2, true).await.unwrap();
+        assert_eq!(
+            prover.inner().calls.load(Ordering::SeqCst),
+            calls_before + 1
+        );
+        prover.unload_witness_bundle();
+        prover.get_validity_pis(1).await.unwrap();
+        assert_eq!(
+            prover.inner().calls.load(Ordering::SeqCst),
+            calls_before + 2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tampered_bundle_is_rejected() {
+        let mut rng = rand::thread_rng();
+        let pubkey = U256::rand(&mut rng);
+        let mock = MockValidityProver::new(pubkey);
+        let bundle = signed_bundle(&mock).await;
+        let verify_proof = |proof: &ProofWithPublicInputs<F, C, D>| mock.verify_proof(proof);
+        let verify = |bundle: &WitnessBundle| bundle.verify_with(pubkey, &verify_proof);
+        verify(&bundle.bundle).unwrap();
+
+        // the dummy proofs are not proofs of the validity circuit
+        assert!(matches!(
+            bundle.verify(pubkey, None),
+            Err(WitnessBundleError::Invalid(_))
+        ));
+
+        // a sender leaf changed by the service, signed again
+        let mut tampered = bundle.bundle.clone();
+        tampered.blocks[1].sender_leaves[0].did_return_sig = false;
+        let resigned = SignedWitnessBundle::sign(tampered.clone(), H256::repeat_byte(7)).unwrap();
+        assert!(matches!(
+            resigned.verify_with(pubkey, None, &verify_proof),
+            Err(WitnessBundleError::Invalid(_))
+        ));
+
+        // validity public inputs that are not in the block tree
+        let mut tampered = bundle.bundle.clone();
+        tampered.blocks[0].validity_pis.public_state.block_hash = Bytes32::rand(&mut rng);
+        assert!(verify(&tampered).is_err());
+
+        // a block merkle proof of another block
+        let mut tampered = bundle.bundle.clone();
+        tampered.blocks[0].block_merkle_proof = tampered.blocks[1].block_merkle_proof.clone();
+        assert!(verify(&tampered).is_err());
+
+        // forged public inputs of the last block, with its proof claiming them
+        let mut tampered = bundle.bundle.clone();
+        let last = tampered.blocks.len() - 1;
+        tampered.blocks[last]
+            .validity_pis
+            .public_state
+            .account_tree_root = Bytes32::rand(&mut rng);
+        tampered.root_validity_proof.public_inputs = tampered.blocks[last]
+            .validity_pis
+            .to_u64_vec()
+            .into_iter()
+            .map(F::from_canonical_u64)
+            .collect();
+        assert!(matches!(
+            verify(&tampered),
+            Err(WitnessBundleError::Invalid(_))
+        ));
+
+        // the proof of another block as the proof of the last block
+        let mut tampered = bundle.bundle.clone();
+        tampered.root_validity_proof = mock.validity_proof(LATEST_BLOCK - 1);
+        assert!(verify(&tampered).is_err());
+        tampered.root_validity_proof.public_inputs =
+            bundle.bundle.root_validity_proof.public_inputs.clone();
+        assert!(verify(&tampered).is_err());
+
+        // an account tree root of an intermediate block that does not chain
+        let mut tampered = bundle.bundle.clone();
+        tampered.blocks[1]
+            .validity_pis
+            .public_state
+            .account_tree_root = Bytes32::rand(&mut rng);
+        assert!(verify(&tampered).is_err());
+
+        // changed after signing
+        let mut modified = bundle.clone();
+        modified.bundle.to_block -= 1;
+        assert!(matches!(
+            modified.verify_with(pubkey, None, &verify_proof),
+            Err(WitnessBundleError::InvalidSignature(_))
+        ));
+
+        // signed by another service than the trusted one
+        assert!(matches!(
+            bundle.verify(pubkey, Some(EthAddress::repeat_byte(1))),
+            Err(WitnessBundleError::WrongSigner { .. })
+        ));
+        // for another account
+        assert!(matches!(
+            bundle.verify(U256::rand(&mut rng), None),
+            Err(WitnessBundleError::WrongPubkey { .. })
+        ));
+        // of another layout
+        let mut other_version = bundle.clone();
+        other_version.bundle.version = WITNESS_BUNDLE_VERSION + 1;
+        assert!(matches!(
+            SignedWitnessBundle::from_bytes(&other_version.to_bytes()),
+            Err(WitnessBundleError::UnsupportedVersion(_))
+        ));
+    }
+}
@@ -1,10 +1,19 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
 use async_trait::async_trait;
 use intmax2_interfaces::api::{
     balance_prover::{
         interface::BalanceProverClientInterface,
         types::{
-            ProveReceiveDepositRequest, ProveReceiveTransferRequest, ProveResponse,
-            ProveSendRequest, ProveSingleWithdrawalRequest, ProveSpentRequest, ProveUpdateRequest,
+            PrevProof, ProofEncoding, ProveReceiveDepositRequest, ProveReceiveTransferRequest,
+            ProveResponse, ProveSendRequest, ProveSingleWithdrawalRequest, ProveSpentRequest,
+            ProveUpdateRequest,
         },
     },
     error::ServerError,
@@ -23,25 +32,111 @@ use intmax2_zkp::{
 };
 use plonky2::{
     field::goldilocks_field::GoldilocksField,
-    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+    plonk::{
+        circuit_data::VerifierCircuitData, config::PoseidonGoldilocksConfig,
+        proof::ProofWithPublicInputs,
+    },
 };
 
-use super::utils::query::post_request;
+use crate::utils::circuit_verifiers::verifiers;
+
+use super::utils::{
+    query::{post_request, HttpClient},
+    retry::RetryConfig,
+};
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
 const D: usize = 2;
 
+/// Timeout of a proving request, longer than that of the other servers as proving
+/// is slow
+pub const DEFAULT_BALANCE_PROVER_TIMEOUT: Duration = Duration::from_secs(600);
+
 #[derive(Debug, Clone)]
 pub struct BalanceProverClient {
     base_url: String,
+    // set once the server has answered with a compressed proof, after which the
+    // previous proofs are sent compressed too
+    compressed_supported: Arc<AtomicBool>,
+    retry_config: Option<RetryConfig>,
+    bearer_token: Option<String>,
+    http: HttpClient,
 }
 
 impl BalanceProverClient {
     pub fn new(base_url: &str) -> Self {
+        Self::with_http(base_url, HttpClient::new(DEFAULT_BALANCE_PROVER_TIMEOUT))
+    }
+
+    /// Send the requests with `client`, whose TLS, proxy and timeout settings
+    /// apply, instead of a client built with the default settings
+    pub fn with_http_client(base_url: &str, client: reqwest::Client) -> Self {
+        Self::with_http(base_url, HttpClient::with_client(client))
+    }
+
+    fn with_http(base_url: &str, http: HttpClient) -> Self {
         BalanceProverClient {
             base_url: base_url.to_string(),
+            compressed_supported: Arc::new(AtomicBool::new(false)),
+            retry_config: None,
+            bearer_token: None,
+            http,
+        }
+    }
+
+    /// Fail each attempt of a request after `timeout` instead of
+    /// `DEFAULT_BALANCE_PROVER_TIMEOUT`, replacing a client given to
+    /// `with_http_client`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = HttpClient::new(timeout);
+        self
+    }
+
+    /// Retry the requests of this client with `config` instead of the
+    /// process-wide `retry_config()`, e.g. with more attempts, as proving is slow
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry_config = Some(config);
+        self
+    }
+
+    /// Authenticate with `token` instead of BALANCE_PROVER_BEARER_TOKEN
+    pub fn with_bearer_token(mut self, token: &str) -> Self {
+        self.bearer_token = Some(token.to_string());
+        self
+    }
+
+    fn bearer_token(&self) -> Result<String, ServerError> {
+        match &self.bearer_token {
+            Some(token) => Ok(token.clone()),
+            None => get_bearer_token(),
+        }
+    }
+
+    fn prev_proof(
+        &self,
+        prev_proof: &Option<ProofWithPublicInputs<F, C, D>>,
+    ) -> Result<PrevProof, ServerError> {
+        let encoding = if self.compressed_supported.load(Ordering::Relaxed) {
+            ProofEncoding::Compressed
+        } else {
+            ProofEncoding::Full
+        };
+        PrevProof::new(prev_proof, encoding, &verifiers().get_balance_vd())
+            .map_err(|e| ServerError::SerializeError(format!("Failed to compress proof: {}", e)))
+    }
+
+    fn decode_response(
+        &self,
+        response: ProveResponse,
+        verifier_data: &VerifierCircuitData<F, C, D>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>, ServerError> {
+        if response.encoding() == ProofEncoding::Compressed {
+            self.compressed_supported.store(true, Ordering::Relaxed);
         }
+        response
+            .decode(verifier_data)
+            .map_err(|e| ServerError::ProofDecodeError(e.to_string()))
     }
 }
 
@@ -56,13 +151,17 @@ impl BalanceProverClientInterface for BalanceProverClient {
             spent_witness: spent_witness.clone(),
         };
         let response: ProveResponse = post_request(
+            &self.http,
             &self.base_url,
             "/balance-prover/prove-spent",
             &request,
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
-        Ok(response.proof)
+        response.proof.ok_or(ServerError::DeserializationError(
+            "Response has no proof".to_string(),
+        ))
     }
 
     async fn prove_send(
@@ -79,16 +178,19 @@ impl BalanceProverClientInterface for BalanceProverClient {
             tx_witnes: tx_witnes.clone(),
             update_witness: update_witness.clone(),
             spent_proof: spent_proof.clone(),
-            prev_proof: prev_proof.clone(),
+            prev_proof: self.prev_proof(prev_proof)?,
+            proof_encoding: ProofEncoding::Compressed,
         };
         let response: ProveResponse = post_request(
+            &self.http,
             &self.base_url,
             "/balance-prover/prove-send",
             &request,
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
-        Ok(response.proof)
+        self.decode_response(response, &verifiers().get_balance_vd())
     }
 
     async fn prove_update(
@@ -101,16 +203,19 @@ impl BalanceProverClientInterface for BalanceProverClient {
         let request = ProveUpdateRequest {
             pubkey,
             update_witness: update_witness.clone(),
-            prev_proof: prev_proof.clone(),
+            prev_proof: self.prev_proof(prev_proof)?,
+            proof_encoding: ProofEncoding::Compressed,
         };
         let response: ProveResponse = post_request(
+            &self.http,
             &self.base_url,
             "/balance-prover/prove-update",
             &request,
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
-        Ok(response.proof)
+        self.decode_response(response, &verifiers().get_balance_vd())
     }
 
     async fn prove_receive_transfer(
@@ -123,16 +228,19 @@ impl BalanceProverClientInterface for BalanceProverClient {
         let request = ProveReceiveTransferRequest {
             pubkey,
             receive_transfer_witness: receive_transfer_witness.clone(),
-            prev_proof: prev_proof.clone(),
+            prev_proof: self.prev_proof(prev_proof)?,
+            proof_encoding: ProofEncoding::Compressed,
         };
         let response: ProveResponse = post_request(
+            &self.http,
             &self.base_url,
             "/balance-prover/prove-receive-transfer",
             &request,
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
-        Ok(response.proof)
+        self.decode_response(response, &verifiers().get_balance_vd())
     }
 
     async fn prove_receive_deposit(
@@ -145,16 +253,19 @@ impl BalanceProverClientInterface for BalanceProverClient {
         let request = ProveReceiveDepositRequest {
             pubkey,
             receive_deposit_witness: receive_deposit_witness.clone(),
-            prev_proof: prev_proof.clone(),
+            prev_proof: self.prev_proof(prev_proof)?,
+            proof_encoding: ProofEncoding::Compressed,
         };
         let response: ProveResponse = post_request(
+            &self.http,
             &self.base_url,
             "/balance-prover/prove-receive-deposit",
             &request,
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
-        Ok(response.proof)
+        self.decode_response(response, &verifiers().get_balance_vd())
     }
 
     async fn prove_single_withdrawal(
@@ -164,15 +275,18 @@ impl BalanceProverClientInterface for BalanceProverClient {
     ) -> Result<ProofWithPublicInputs<F, C, D>, ServerError> {
         let request = ProveSingleWithdrawalRequest {
             withdrawal_witness: withdrawal_witness.clone(),
+            proof_encoding: ProofEncoding::Compressed,
         };
         let response: ProveResponse = post_request(
+            &self.http,
             &self.base_url,
             "/balance-prover/prove-single-withdrawal",
             &request,
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
-        Ok(response.proof)
+        self.decode_response(response, &verifiers().get_single_withdrawal_vd())
     }
 }
 
@@ -1,27 +1,87 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
+use ethers::{
+    signers::{LocalWallet, Signer as _},
+    types::{Address as EthAddress, Signature, H256},
+};
 use intmax2_interfaces::api::{
     block_builder::{
-        interface::{BlockBuilderClientInterface, BlockBuilderStatus, FeeProof},
+        interface::{
+            cancel_tx_request_message, proposal_attestation_message, BlockBuilderClientInterface,
+            BlockBuilderDependencies, BlockBuilderStatus, FeeProof, ProposalAttestation,
+        },
         types::{
-            GetBlockBuilderStatusQuery, GetBlockBuilderStatusResponse, PostSignatureRequest,
-            QueryProposalRequest, QueryProposalResponse, TxRequestRequest,
+            CancelTxRequestRequest, GetBlockBuilderHealthResponse, GetBlockBuilderStatusQuery,
+            GetBlockBuilderStatusResponse, GetFeeInfoResponse, GetProposalAttestationResponse,
+            PostSignatureRequest, QueryProposalRequest, QueryProposalResponse,
+            QueryProposalsRequest, QueryProposalsResponse, TxRequestRequest,
         },
     },
     error::ServerError,
+    sponsor::interface::FeeVoucher,
+    withdrawal_server::interface::Fee,
 };
 use intmax2_zkp::{
-    common::{block_builder::BlockProposal, signature::flatten::FlatG2, tx::Tx},
-    ethereum_types::u256::U256,
+    common::{
+        block_builder::{BlockProposal, UserSignature},
+        signature::{flatten::FlatG2, key_set::KeySet, utils::get_pubkey_hash},
+        trees::tx_tree::TxTree,
+        tx::Tx,
+    },
+    constants::TX_TREE_HEIGHT,
+    ethereum_types::{
+        address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _,
+    },
 };
 
-use super::utils::query::{get_request, post_request};
+use super::utils::{
+    query::{delete_request, get_request, post_request, post_request_once, HttpClient},
+    retry::RetryConfig,
+};
 
 #[derive(Debug, Clone)]
-pub struct BlockBuilderClient;
+pub struct BlockBuilderClient {
+    retry_config: Option<RetryConfig>,
+    http: HttpClient,
+}
 
 impl BlockBuilderClient {
     pub fn new() -> Self {
-        BlockBuilderClient
+        Self::with_http(HttpClient::default())
+    }
+
+    /// Send the requests with `client`, whose TLS, proxy and timeout settings
+    /// apply, instead of a client built with the default settings
+    pub fn with_http_client(client: reqwest::Client) -> Self {
+        Self::with_http(HttpClient::with_client(client))
+    }
+
+    fn with_http(http: HttpClient) -> Self {
+        BlockBuilderClient {
+            retry_config: None,
+            http,
+        }
+    }
+
+    /// Fail each attempt of a request after `timeout` instead of `DEFAULT_TIMEOUT`,
+    /// replacing a client given to `with_http_client`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = HttpClient::new(timeout);
+        self
+    }
+
+    /// Retry the requests of this client with `config` instead of the
+    /// process-wide `retry_config()`
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry_config = Some(config);
+        self
+    }
+}
+
+impl Default for BlockBuilderClient {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -36,15 +96,46 @@ impl BlockBuilderClientInterface for BlockBuilderClient {
             is_registration_block,
         };
         let response = get_request::<GetBlockBuilderStatusQuery, GetBlockBuilderStatusResponse>(
+            &self.http,
             block_builder_url,
             "/block-builder/status",
             Some(query),
             None,
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.status)
     }
 
+    async fn get_health(
+        &self,
+        block_builder_url: &str,
+    ) -> Result<BlockBuilderDependencies, ServerError> {
+        let response = get_request::<(), GetBlockBuilderHealthResponse>(
+            &self.http,
+            block_builder_url,
+            "/block-builder/health",
+            None,
+            None,
+            self.retry_config.as_ref(),
+        )
+        .await?;
+        Ok(response.dependencies)
+    }
+
+    async fn get_fee_info(&self, block_builder_url: &str) -> Result<Vec<Fee>, ServerError> {
+        let response = get_request::<(), GetFeeInfoResponse>(
+            &self.http,
+            block_builder_url,
+            "/block-builder/fee-info",
+            None,
+            None,
+            self.retry_config.as_ref(),
+        )
+        .await?;
+        Ok(response.fees)
+    }
+
     async fn send_tx_request(
         &self,
         block_builder_url: &str,
@@ -52,18 +143,40 @@ impl BlockBuilderClientInterface for BlockBuilderClient {
         pubkey: U256,
         tx: Tx,
         fee_proof: Option<FeeProof>,
+        fee_voucher: Option<FeeVoucher>,
     ) -> Result<(), ServerError> {
         let request = TxRequestRequest {
             is_registration_block,
             pubkey,
             tx,
             fee_proof,
+            fee_voucher,
         };
-        post_request::<_, ()>(
+        post_request_once::<_, ()>(
+            &self.http,
             block_builder_url,
             "/block-builder/tx-request",
             &request,
             None,
+            self.retry_config.as_ref(),
+        )
+        .await
+    }
+
+    async fn cancel_tx_request(
+        &self,
+        block_builder_url: &str,
+        tx_hash: Bytes32,
+        pubkey: U256,
+        signature: FlatG2,
+    ) -> Result<(), ServerError> {
+        let request = CancelTxRequestRequest { pubkey, signature };
+        delete_request::<_, ()>(
+            &self.http,
+            block_builder_url,
+            &format!("/block-builder/tx-request/{}", tx_hash.to_hex()),
+            &request,
+            self.retry_config.as_ref(),
         )
         .await
     }
@@ -81,15 +194,68 @@ impl BlockBuilderClientInterface for BlockBuilderClient {
             tx,
         };
         let response: QueryProposalResponse = post_request(
+            &self.http,
             block_builder_url,
             "/block-builder/query-proposal",
             &request,
             None,
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.block_proposal)
     }
 
+    async fn query_proposals(
+        &self,
+        block_builder_url: &str,
+        requests: &[QueryProposalRequest],
+    ) -> Result<Vec<Option<BlockProposal>>, ServerError> {
+        let request = QueryProposalsRequest {
+            requests: requests.to_vec(),
+        };
+        let response: QueryProposalsResponse = post_request(
+            &self.http,
+            block_builder_url,
+            "/block-builder/query-proposals",
+            &request,
+            None,
+            self.retry_config.as_ref(),
+        )
+        .await?;
+        if response.block_proposals.len() != requests.len() {
+            return Err(ServerError::InvalidResponse(format!(
+                "expected {} proposals, got {}",
+                requests.len(),
+                response.block_proposals.len()
+            )));
+        }
+        Ok(response.block_proposals)
+    }
+
+    async fn get_proposal_attestation(
+        &self,
+        block_builder_url: &str,
+        is_registration_block: bool,
+        pubkey: U256,
+        tx: Tx,
+    ) -> Result<Option<ProposalAttestation>, ServerError> {
+        let request = QueryProposalRequest {
+            is_registration_block,
+            pubkey,
+            tx,
+        };
+        let response: GetProposalAttestationResponse = post_request(
+            &self.http,
+            block_builder_url,
+            "/block-builder/proposal-attestation",
+            &request,
+            None,
+            self.retry_config.as_ref(),
+        )
+        .await?;
+        Ok(response.attestation)
+    }
+
     async fn post_signature(
         &self,
         block_builder_url: &str,
@@ -104,12 +270,88 @@ impl BlockBuilderClientInterface for BlockBuilderClient {
             tx,
             signature,
         };
-        post_request::<_, ()>(
+        post_request_once::<_, ()>(
+            &self.http,
             block_builder_url,
             "/block-builder/post-signature",
             &request,
             None,
+            self.retry_config.as_ref(),
         )
         .await
     }
 }
+
+/// Sign the attestation of `proposal`, for the tx of `pubkey`, with the block
+/// builder's Ethereum key
+pub fn sign_proposal_attestation(
+    block_builder_private_key: H256,
+    pubkey: U256,
+    tx: Tx,
+    proposal: &BlockProposal,
+) -> anyhow::Result<ProposalAttestation> {
+    let wallet = LocalWallet::from_bytes(block_builder_private_key.as_bytes())?;
+    let tx_hash: Bytes32 = tx.hash().into();
+    let message = H256::from_slice(
+        &proposal_attestation_message(pubkey, tx_hash, proposal.tx_tree_root).to_bytes_be(),
+    );
+    let signature = wallet.sign_hash(message)?;
+    Ok(ProposalAttestation {
+        block_builder: Address::from_bytes_be(wallet.address().as_bytes()),
+        pubkey,
+        tx_hash,
+        tx_tree_root: proposal.tx_tree_root,
+        signature: signature.to_vec(),
+    })
+}
+
+/// Check that the attestation is for the tx `tx_hash` of `pubkey` in the block of
+/// `tx_tree_root`, and is signed by its block builder
+pub fn verify_proposal_attestation(
+    attestation: &ProposalAttestation,
+    pubkey: U256,
+    tx_hash: Bytes32,
+    tx_tree_root: Bytes32,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        attestation.pubkey == pubkey
+            && attestation.tx_hash == tx_hash
+            && attestation.tx_tree_root == tx_tree_root,
+        "attestation is for another tx"
+    );
+    let signature = Signature::try_from(attestation.signature.as_slice())?;
+    let message = H256::from_slice(
+        &proposal_attestation_message(pubkey, tx_hash, tx_tree_root).to_bytes_be(),
+    );
+    let signer = signature.recover(message)?;
+    anyhow::ensure!(
+        signer == EthAddress::from_slice(&attestation.block_builder.to_bytes_be()),
+        "attestation is not signed by the block builder"
+    );
+    Ok(())
+}
+
+// the cancellation is signed as a proposal whose tx tree root is the cancellation
+// message and whose only sender is `pubkey`
+fn cancel_tx_request_proposal(tx_hash: Bytes32, pubkey: U256) -> BlockProposal {
+    let pubkeys = vec![pubkey];
+    BlockProposal {
+        tx_tree_root: cancel_tx_request_message(tx_hash),
+        tx_index: 0,
+        tx_merkle_proof: TxTree::new(TX_TREE_HEIGHT).prove(0),
+        pubkeys_hash: get_pubkey_hash(&pubkeys),
+        pubkeys,
+    }
+}
+
+/// Sign the cancellation of the tx request of `tx_hash` with the sender's key
+pub fn sign_cancel_tx_request(key: KeySet, tx_hash: Bytes32) -> UserSignature {
+    cancel_tx_request_proposal(tx_hash, key.pubkey).sign(key)
+}
+
+/// Check that `signature` is the signature of its pubkey over the cancellation of
+/// the tx request of `tx_hash`
+pub fn verify_cancel_tx_request(tx_hash: Bytes32, signature: &UserSignature) -> anyhow::Result<()> {
+    let proposal = cancel_tx_request_proposal(tx_hash, signature.pubkey);
+    signature.verify(proposal.tx_tree_root, proposal.pubkeys_hash)
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use intmax2_interfaces::api::{
+    error::ServerError,
+    validity_prover::interface::{AccountInfo, DepositInfo, ValidityProverClientInterface},
+};
+use intmax2_zkp::{
+    circuits::validity::validity_pis::ValidityPublicInputs,
+    common::{
+        trees::{
+            account_tree::AccountMembershipProof, block_hash_tree::BlockHashMerkleProof,
+            deposit_tree::DepositMerkleProof, sender_tree::SenderLeaf,
+        },
+        witness::update_witness::UpdateWitness,
+    },
+    ethereum_types::{bytes32::Bytes32, u256::U256},
+};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+};
+
+use crate::client::witness_bundle::WitnessBundle;
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+pub const DEFAULT_WITNESS_CACHE_SIZE: usize = 64;
+
+// (leaf_block_number, root_block_number)
+type BlockTransition = (u32, u32);
+
+#[derive(Debug, Clone)]
+struct BlockWitness {
+    validity_proof: ProofWithPublicInputs<F, C, D>,
+    block_merkle_proof: BlockHashMerkleProof,
+}
+
+#[derive(Debug, Default)]
+struct WitnessCache {
+    entries: HashMap<BlockTransition, BlockWitness>,
+    order: VecDeque<BlockTransition>,
+}
+
+/// Arguments of `get_update_witness` identifying a whole update witness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WitnessKey {
+    pub pubkey: U256,
+    pub root_block_number: u32,
+    pub leaf_block_number: u32,
+    pub is_prev_account_tree: bool,
+}
+
+impl WitnessKey {
+    /// Whether `witness` is one for this key. A stored witness that is not is
+    /// ignored, so a corrupted or misplaced entry is fetched again.
+    pub fn matches(&self, witness: &UpdateWitness<F, C, D>) -> bool {
+        let validity_pis = ValidityPublicInputs::from_pis(&witness.validity_proof.public_inputs);
+        witness.is_prev_account_tree == self.is_prev_account_tree
+            && validity_pis.public_state.block_number == self.root_block_number
+    }
+}
+
+/// Persistent storage of whole update witnesses, e.g. filled ahead of the sync by
+/// a watcher. It is consulted before the validity prover, and every witness
+/// fetched is put into it.
+pub trait WitnessStore: std::fmt::Debug {
+    fn get(&self, key: &WitnessKey) -> Option<UpdateWitness<F, C, D>>;
+
+    fn put(&self, key: &WitnessKey, witness: &UpdateWitness<F, C, D>);
+}
+
+/// Validity prover client that shares the account-independent part of update witnesses
+/// (the validity proof and the block merkle proof) between all accounts synced through
+/// it. On a cache hit, only the account membership proof is fetched.
+///
+/// Clones share the same cache.
+#[derive(Debug, Clone)]
+pub struct CachedValidityProver<V: ValidityProverClientInterface> {
+    inner: V,
+    cache: Arc<Mutex<WitnessCache>>,
+    capacity: usize,
+    store: Option<Arc<dyn WitnessStore>>,
+    // checked witnesses of a sync, consulted before everything else
+    bundle: Arc<Mutex<Option<Arc<WitnessBundle>>>>,
+}
+
+impl<V: ValidityProverClientInterface> CachedValidityProver<V> {
+    pub fn new(inner: V) -> Self {
+        Self::with_capacity(inner, DEFAULT_WITNESS_CACHE_SIZE)
+    }
+
+    pub fn with_capacity(inner: V, capacity: usize) -> Self {
+        Self {
+            inner,
+            cache: Arc::new(Mutex::new(WitnessCache::default())),
+            capacity,
+            store: None,
+            bundle: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Consult `store` for whole update witnesses before the in-memory cache
+    pub fn with_witness_store(mut self, store: Arc<dyn WitnessStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub fn inner(&self) -> &V {
+        &self.inner
+    }
+
+    /// Answer from `bundle` what it covers, until unloaded. The bundle has to be
+    /// verified for the account being synced.
+    pub(crate) fn load_witness_bundle(&self, bundle: Arc<WitnessBundle>) {
+        *self.bundle.lock().unwrap() = Some(bundle);
+    }
+
+    pub(crate) fn unload_witness_bundle(&self) {
+        *self.bundle.lock().unwrap() = None;
+    }
+
+    fn from_bundle<T>(
+        &self,
+        what: &str,
+        get: impl FnOnce(&WitnessBundle) -> Option<T>,
+    ) -> Option<T> {
+        let bundle = self.bundle.lock().unwrap().clone()?;
+        let value = get(&bundle);
+        if value.is_none() {
+            log::debug!("{} is not in the witness bundle, fetching it", what);
+        }
+        value
+    }
+
+    /// Number of block transitions currently cached
+    pub fn cached_transitions(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+
+    fn get_cached(&self, transition: BlockTransition) -> Option<BlockWitness> {
+        self.cache.lock().unwrap().entries.get(&transition).cloned()
+    }
+
+    fn insert_cached(&self, transition: BlockTransition, witness: BlockWitness) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if cache.entries.insert(transition, witness).is_some() {
+            return;
+        }
+        cache.order.push_back(transition);
+        while cache.order.len() > self.capacity {
+            let oldest = cache.order.pop_front().unwrap();
+            cache.entries.remove(&oldest);
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<V: ValidityProverClientInterface> ValidityProverClientInterface for CachedValidityProver<V> {
+    async fn get_block_number(&self) -> Result<u32, ServerError> {
+        self.inner.get_block_number().await
+    }
+
+    async fn get_update_witness(
+        &self,
+        pubkey: U256,
+        root_block_number: u32,
+        leaf_block_number: u32,
+        is_prev_account_tree: bool,
+    ) -> Result<UpdateWitness<F, C, D>, ServerError> {
+        let key = WitnessKey {
+            pubkey,
+            root_block_number,
+            leaf_block_number,
+            is_prev_account_tree,
+        };
+        if let Some(witness) =
+            self.from_bundle("update witness", |bundle| bundle.update_witness(&key))
+        {
+            return Ok(witness);
+        }
+        if let Some(store) = &self.store {
+            if let Some(witness) = store.get(&key) {
+                if key.matches(&witness) {
+                    return Ok(witness);
+                }
+                log::warn!("ignoring stored update witness not matching {:?}", key);
+            }
+        }
+        let transition = (leaf_block_number, root_block_number);
+        if let Some(cached) = self.get_cached(transition) {
+            let account_tree_block_number = if is_prev_account_tree {
+                // the genesis block has no previous account tree but its own
+                root_block_number.saturating_sub(1)
+            } else {
+                root_block_number
+            };
+            let account_membership_proof = self
+                .inner
+                .get_account_membership_proof(pubkey, account_tree_block_number)
+                .await?;
+            let update_witness = UpdateWitness {
+                is_prev_account_tree,
+                validity_proof: cached.validity_proof,
+                block_merkle_proof: cached.block_merkle_proof,
+                account_membership_proof,
+            };
+            if let Some(store) = &self.store {
+                store.put(&key, &update_witness);
+            }
+            return Ok(update_witness);
+        }
+        let update_witness = self
+            .inner
+            .get_update_witness(
+                pubkey,
+                root_block_number,
+                leaf_block_number,
+                is_prev_account_tree,
+            )
+            .await?;
+        self.insert_cached(
+            transition,
+            BlockWitness {
+                validity_proof: update_witness.validity_proof.clone(),
+                block_merkle_proof: update_witness.block_merkle_proof.clone(),
+            },
+        );
+        if let Some(store) = &self.store {
+            store.put(&key, &update_witness);
+        }
+        Ok(update_witness)
+    }
+
+    async fn get_account_membership_proof(
+        &self,
+        pubkey: U256,
+        block_number: u32,
+    ) -> Result<AccountMembershipProof, ServerError> {
+        if let Some(proof) = self.from_bundle("account membership proof", |bundle| {
+            bundle.account_membership_proof(pubkey, block_number)
+        }) {
+            return Ok(proof);
+        }
+        self.inner
+            .get_account_membership_proof(pubkey, block_number)
+            .await
+    }
+
+    async fn get_deposit_info(
+        &self,
+        deposit_hash: Bytes32,
+    ) -> Result<Option<DepositInfo>, ServerError> {
+        self.inner.get_deposit_info(deposit_hash).await
+    }
+
+    async fn get_block_number_by_tx_tree_root(
+        &self,
+        tx_tree_root: Bytes32,
+    ) -> Result<Option<u32>, ServerError> {
+        if let Some(block_number) = self.from_bundle("tx tree root", |bundle| {
+            bundle.block_number_by_tx_tree_root(tx_tree_root)
+        }) {
+            return Ok(Some(block_number));
+        }
+        self.inner
+            .get_block_number_by_tx_tree_root(tx_tree_root)
+            .await
+    }
+
+    async fn get_validity_pis(
+        &self,
+        block_number: u32,
+    ) -> Result<Option<ValidityPublicInputs>, ServerError> {
+        if let Some(pis) =
+            self.from_bundle("validity pis", |bundle| bundle.validity_pis(block_number))
+        {
+            return Ok(Some(pis));
+        }
+        self.inner.get_validity_pis(block_number).await
+    }
+
+    async fn get_sender_leaves(
+        &self,
+        block_number: u32,
+    ) -> Result<Option<Vec<SenderLeaf>>, ServerError> {
+        if let Some(leaves) =
+            self.from_bundle("sender leaves", |bundle| bundle.sender_leaves(block_number))
+        {
+            return Ok(Some(leaves));
+        }
+        self.inner.get_sender_leaves(block_number).await
+    }
+
+    async fn get_block_merkle_proof(
+        &self,
+        root_block_number: u32,
+        leaf_block_number: u32,
+    ) -> Result<BlockHashMerkleProof, ServerError> {
+        if let Some(proof) = self.from_bundle("block merkle proof", |bundle| {
+            bundle.block_merkle_proof(root_block_number, leaf_block_number)
+        }) {
+            return Ok(proof);
+        }
+        self.inner
+            .get_block_merkle_proof(root_block_number, leaf_block_number)
+            .await
+    }
+
+    async fn get_deposit_merkle_proof(
+        &self,
+        block_number: u32,
+        deposit_index: u32,
+    ) -> Result<DepositMerkleProof, ServerError> {
+        self.inner
+            .get_deposit_merkle_proof(block_number, deposit_index)
+            .await
+    }
+
+    async fn get_account_info(&self, pubkey: U256) -> Result<AccountInfo, ServerError> {
+        self.inner.get_account_info(pubkey).await
+    }
+}
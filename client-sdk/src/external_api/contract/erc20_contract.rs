@@ -12,6 +12,7 @@ use ethers::{
 use crate::external_api::utils::retry::with_retry;
 
 use super::{
+    gas::GasConfig,
     handlers::handle_contract_call,
     interface::BlockchainError,
     utils::{get_address, get_client, get_client_with_signer},
@@ -31,6 +32,7 @@ pub struct ERC20Contract {
     pub rpc_url: String,
     pub chain_id: u64,
     pub address: Address,
+    pub gas_config: GasConfig,
 }
 
 impl ERC20Contract {
@@ -39,9 +41,15 @@ impl ERC20Contract {
             rpc_url,
             chain_id,
             address,
+            gas_config: GasConfig::default(),
         }
     }
 
+    /// Use `gas_config` for the fees of state-changing calls
+    pub fn with_gas_config(self, gas_config: GasConfig) -> Self {
+        Self { gas_config, ..self }
+    }
+
     pub async fn get_contract(&self) -> Result<ERC20<Provider<Http>>, BlockchainError> {
         let client = get_client(&self.rpc_url).await?;
         let contract = ERC20::new(self.address, client);
@@ -65,22 +73,24 @@ impl ERC20Contract {
         Ok(balance)
     }
 
+    /// Returns the hash of the approve tx
     pub async fn approve(
         &self,
         signer_private_key: H256,
         spender: Address,
         amount: U256,
-    ) -> Result<(), BlockchainError> {
+    ) -> Result<H256, BlockchainError> {
         let contract = self.get_contract_with_signer(signer_private_key).await?;
         let mut tx = contract.approve(spender, amount);
         handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "token_owner",
             "approve",
         )
-        .await?;
-        Ok(())
+        .await
     }
 
     pub async fn allowance(
@@ -0,0 +1,427 @@
+use std::{fmt::Display, str::FromStr};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{Http, Middleware as _, Provider},
+    types::{transaction::eip2718::TypedTransaction, BlockNumber, TransactionRequest, U256},
+    utils::{format_units, parse_units},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::utils::retry::with_retry;
+
+use super::interface::BlockchainError;
+
+/// Priority fee used when the sampled blocks have no tips, e.g. on an idle devnet
+const DEFAULT_PRIORITY_FEE: u64 = 1_000_000_000; // 1 gwei
+
+/// Fees of a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GasFees {
+    Legacy {
+        gas_price: U256,
+    },
+    #[serde(rename_all = "camelCase")]
+    Eip1559 {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+}
+
+impl GasFees {
+    pub fn max_fee_per_gas(&self) -> U256 {
+        match self {
+            GasFees::Legacy { gas_price } => *gas_price,
+            GasFees::Eip1559 {
+                max_fee_per_gas, ..
+            } => *max_fee_per_gas,
+        }
+    }
+
+    /// Fees raised by `percent`, and by at least 1 wei so that the node accepts
+    /// them as a replacement. The max fee is clamped to `cap`.
+    pub fn bumped(&self, percent: u64, cap: Option<U256>) -> Self {
+        let bump = |value: U256| {
+            let bumped = (value * (100 + percent) / 100).max(value + 1);
+            match cap {
+                Some(cap) => bumped.min(cap),
+                None => bumped,
+            }
+        };
+        match self {
+            GasFees::Legacy { gas_price } => GasFees::Legacy {
+                gas_price: bump(*gas_price),
+            },
+            GasFees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                let max_fee_per_gas = bump(*max_fee_per_gas);
+                GasFees::Eip1559 {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas: bump(*max_priority_fee_per_gas).min(max_fee_per_gas),
+                }
+            }
+        }
+    }
+
+    pub fn apply(&self, tx: &mut TypedTransaction) {
+        // a legacy price is sent as the gas price of a legacy tx, instead of as both
+        // the max fee and the tip of an EIP-1559 tx
+        if let (GasFees::Legacy { gas_price }, TypedTransaction::Eip1559(inner)) = (self, &*tx) {
+            *tx = TypedTransaction::Legacy(TransactionRequest {
+                from: inner.from,
+                to: inner.to.clone(),
+                gas: inner.gas,
+                gas_price: Some(*gas_price),
+                value: inner.value,
+                data: inner.data.clone(),
+                nonce: inner.nonce,
+                chain_id: inner.chain_id,
+                ..Default::default()
+            });
+            return;
+        }
+        match (self, tx) {
+            (
+                GasFees::Eip1559 {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                },
+                TypedTransaction::Eip1559(inner),
+            ) => {
+                inner.max_fee_per_gas = Some(*max_fee_per_gas);
+                inner.max_priority_fee_per_gas = Some(*max_priority_fee_per_gas);
+            }
+            (fees, tx) => tx.set_gas_price(fees.max_fee_per_gas()),
+        }
+    }
+}
+
+impl Display for GasFees {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gwei = |value: &U256| format_units(*value, "gwei").unwrap_or_default();
+        match self {
+            GasFees::Legacy { gas_price } => write!(f, "gas price {} gwei", gwei(gas_price)),
+            GasFees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => write!(
+                f,
+                "max fee {} gwei, max priority fee {} gwei",
+                gwei(max_fee_per_gas),
+                gwei(max_priority_fee_per_gas)
+            ),
+        }
+    }
+}
+
+/// Chooses the fees of state-changing contract calls
+#[async_trait(?Send)]
+pub trait GasStrategy {
+    async fn fees(&self, provider: &Provider<Http>) -> Result<GasFees, BlockchainError>;
+}
+
+/// The node's gas price times a multiplier
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Legacy(pub f64);
+
+#[async_trait(?Send)]
+impl GasStrategy for Legacy {
+    async fn fees(&self, provider: &Provider<Http>) -> Result<GasFees, BlockchainError> {
+        let gas_price = with_retry(|| async { provider.get_gas_price().await })
+            .await
+            .map_err(|e| {
+                BlockchainError::NetworkError(format!("Error getting gas price: {:?}", e))
+            })?;
+        Ok(GasFees::Legacy {
+            gas_price: multiply(gas_price, self.0),
+        })
+    }
+}
+
+/// EIP-1559 fees from the `percentile` tips of the last `blocks` blocks
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Eip1559FeeHistory {
+    pub percentile: f64,
+    pub blocks: u64,
+}
+
+#[async_trait(?Send)]
+impl GasStrategy for Eip1559FeeHistory {
+    async fn fees(&self, provider: &Provider<Http>) -> Result<GasFees, BlockchainError> {
+        let history = with_retry(|| async {
+            provider
+                .fee_history(self.blocks, BlockNumber::Latest, &[self.percentile])
+                .await
+        })
+        .await
+        .map_err(|e| {
+            BlockchainError::NetworkError(format!("Error getting fee history: {:?}", e))
+        })?;
+        Ok(eip1559_fees(&history.base_fee_per_gas, &history.reward))
+    }
+}
+
+/// Fees given by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fixed(pub GasFees);
+
+#[async_trait(?Send)]
+impl GasStrategy for Fixed {
+    async fn fees(&self, _provider: &Provider<Http>) -> Result<GasFees, BlockchainError> {
+        Ok(self.0)
+    }
+}
+
+/// Fees from `eth_feeHistory`. The max priority fee is the median of the sampled tips,
+/// and the max fee leaves room for the base fee to double.
+pub fn eip1559_fees(base_fee_per_gas: &[U256], rewards: &[Vec<U256>]) -> GasFees {
+    // the last base fee is the one of the next block
+    let next_base_fee = base_fee_per_gas.last().copied().unwrap_or_default();
+    let mut tips = rewards
+        .iter()
+        .filter_map(|reward| reward.first().copied())
+        .filter(|tip| !tip.is_zero())
+        .collect::<Vec<_>>();
+    tips.sort();
+    let max_priority_fee_per_gas = tips
+        .get(tips.len() / 2)
+        .copied()
+        .unwrap_or(DEFAULT_PRIORITY_FEE.into());
+    GasFees::Eip1559 {
+        max_fee_per_gas: next_base_fee * 2 + max_priority_fee_per_gas,
+        max_priority_fee_per_gas,
+    }
+}
+
+fn multiply(value: U256, multiplier: f64) -> U256 {
+    // multiply in thousandths to stay in integers
+    value * U256::from((multiplier * 1000.0).round() as u64) / 1000
+}
+
+/// Strategy selected in the chain config, in the form `legacy:<multiplier>`,
+/// `eip1559:<percentile>:<blocks>`, `fixed:<gas price gwei>` or
+/// `fixed:<max fee gwei>:<max priority fee gwei>`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GasStrategyConfig {
+    Legacy(Legacy),
+    Eip1559FeeHistory(Eip1559FeeHistory),
+    Fixed(Fixed),
+}
+
+impl Default for GasStrategyConfig {
+    fn default() -> Self {
+        GasStrategyConfig::Eip1559FeeHistory(Eip1559FeeHistory {
+            percentile: 50.0,
+            blocks: 10,
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl GasStrategy for GasStrategyConfig {
+    async fn fees(&self, provider: &Provider<Http>) -> Result<GasFees, BlockchainError> {
+        match self {
+            GasStrategyConfig::Legacy(strategy) => strategy.fees(provider).await,
+            GasStrategyConfig::Eip1559FeeHistory(strategy) => strategy.fees(provider).await,
+            GasStrategyConfig::Fixed(strategy) => strategy.fees(provider).await,
+        }
+    }
+}
+
+impl FromStr for GasStrategyConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').collect::<Vec<_>>();
+        let number = |s: &str| {
+            s.parse::<f64>()
+                .map_err(|_| format!("Invalid number in gas strategy: {}", s))
+        };
+        let gwei = |s: &str| -> Result<U256, String> {
+            parse_units(s, "gwei")
+                .map(Into::into)
+                .map_err(|_| format!("Invalid gwei amount in gas strategy: {}", s))
+        };
+        match parts.as_slice() {
+            ["legacy", multiplier] => Ok(GasStrategyConfig::Legacy(Legacy(number(multiplier)?))),
+            ["eip1559", percentile, blocks] => {
+                let percentile = number(percentile)?;
+                if !(0.0..=100.0).contains(&percentile) {
+                    return Err(format!("Percentile out of range: {}", percentile));
+                }
+                let blocks = blocks
+                    .parse()
+                    .map_err(|_| format!("Invalid block count in gas strategy: {}", blocks))?;
+                Ok(GasStrategyConfig::Eip1559FeeHistory(Eip1559FeeHistory {
+                    percentile,
+                    blocks,
+                }))
+            }
+            ["fixed", gas_price] => Ok(GasStrategyConfig::Fixed(Fixed(GasFees::Legacy {
+                gas_price: gwei(gas_price)?,
+            }))),
+            ["fixed", max_fee, max_priority_fee] => {
+                Ok(GasStrategyConfig::Fixed(Fixed(GasFees::Eip1559 {
+                    max_fee_per_gas: gwei(max_fee)?,
+                    max_priority_fee_per_gas: gwei(max_priority_fee)?,
+                })))
+            }
+            _ => Err(format!("Invalid gas strategy: {}", s)),
+        }
+    }
+}
+
+/// Fees of state-changing contract calls, and how stuck txs are rebroadcast
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasConfig {
+    pub strategy: GasStrategyConfig,
+    // seconds to wait for the receipt before rebroadcasting with bumped fees. None waits forever.
+    pub stuck_timeout: Option<u64>,
+    pub max_bumps: u32,
+    pub bump_percent: u64,
+    // the max fee per gas is never bumped over this
+    pub max_fee_per_gas_cap: Option<U256>,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        Self {
+            strategy: GasStrategyConfig::default(),
+            stuck_timeout: Some(180),
+            max_bumps: 3,
+            bump_percent: 20,
+            max_fee_per_gas_cap: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{core::utils::Anvil, providers::Provider, types::U256};
+
+    use super::*;
+
+    fn gwei(value: u64) -> U256 {
+        U256::from(value) * 1_000_000_000u64
+    }
+
+    #[test]
+    fn test_eip1559_fees() {
+        let base_fees = vec![gwei(10), gwei(12), gwei(20)];
+        let rewards = vec![
+            vec![gwei(1)],
+            vec![U256::zero()],
+            vec![gwei(3)],
+            vec![gwei(2)],
+        ];
+        assert_eq!(
+            eip1559_fees(&base_fees, &rewards),
+            GasFees::Eip1559 {
+                max_fee_per_gas: gwei(42),
+                max_priority_fee_per_gas: gwei(2),
+            }
+        );
+        // no tips
+        assert_eq!(
+            eip1559_fees(&base_fees, &[]).max_fee_per_gas(),
+            gwei(40) + DEFAULT_PRIORITY_FEE
+        );
+    }
+
+    #[test]
+    fn test_bump() {
+        let fees = GasFees::Eip1559 {
+            max_fee_per_gas: gwei(10),
+            max_priority_fee_per_gas: gwei(2),
+        };
+        let bumped = fees.bumped(20, None);
+        assert_eq!(
+            bumped,
+            GasFees::Eip1559 {
+                max_fee_per_gas: gwei(12),
+                max_priority_fee_per_gas: U256::from(2_400_000_000u64),
+            }
+        );
+        assert_eq!(
+            bumped.bumped(20, Some(gwei(13))).max_fee_per_gas(),
+            gwei(13)
+        );
+        let tiny = GasFees::Legacy {
+            gas_price: U256::from(1),
+        };
+        assert_eq!(tiny.bumped(10, None).max_fee_per_gas(), U256::from(2));
+    }
+
+    #[test]
+    fn test_apply_legacy_fees() {
+        let mut tx: TypedTransaction = ethers::types::Eip1559TransactionRequest::new()
+            .nonce(7)
+            .data(vec![1, 2, 3])
+            .into();
+        GasFees::Legacy {
+            gas_price: gwei(30),
+        }
+        .apply(&mut tx);
+        let TypedTransaction::Legacy(legacy) = &tx else {
+            panic!("legacy fees should make a legacy tx");
+        };
+        assert_eq!(legacy.gas_price, Some(gwei(30)));
+        assert_eq!(legacy.nonce, Some(7.into()));
+        assert_eq!(legacy.data, Some(vec![1, 2, 3].into()));
+
+        let fees = GasFees::Eip1559 {
+            max_fee_per_gas: gwei(40),
+            max_priority_fee_per_gas: gwei(2),
+        };
+        let mut tx: TypedTransaction = ethers::types::Eip1559TransactionRequest::new().into();
+        fees.apply(&mut tx);
+        let TypedTransaction::Eip1559(inner) = &tx else {
+            panic!("EIP-1559 fees should keep the EIP-1559 tx");
+        };
+        assert_eq!(inner.max_fee_per_gas, Some(gwei(40)));
+        assert_eq!(inner.max_priority_fee_per_gas, Some(gwei(2)));
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!(
+            "legacy:1.5".parse::<GasStrategyConfig>().unwrap(),
+            GasStrategyConfig::Legacy(Legacy(1.5))
+        );
+        assert_eq!(
+            "fixed:30:1.5".parse::<GasStrategyConfig>().unwrap(),
+            GasStrategyConfig::Fixed(Fixed(GasFees::Eip1559 {
+                max_fee_per_gas: gwei(30),
+                max_priority_fee_per_gas: U256::from(1_500_000_000u64),
+            }))
+        );
+        assert!("eip1559:150:10".parse::<GasStrategyConfig>().is_err());
+        assert!("auto".parse::<GasStrategyConfig>().is_err());
+        assert_eq!(multiply(gwei(10), 1.25), U256::from(12_500_000_000u64));
+    }
+
+    #[tokio::test]
+    async fn test_fee_history_follows_base_fee() -> anyhow::Result<()> {
+        let anvil = Anvil::new().spawn();
+        let provider = Provider::<Http>::try_from(anvil.endpoint())?;
+        let base_fee = gwei(100);
+        provider
+            .request::<_, ()>("anvil_setNextBlockBaseFeePerGas", [base_fee])
+            .await?;
+        provider.request::<_, String>("evm_mine", ()).await?;
+
+        let strategy = Eip1559FeeHistory {
+            percentile: 50.0,
+            blocks: 4,
+        };
+        let fees = strategy.fees(&provider).await?;
+        // an empty block lowers the base fee of the next one by 1/8
+        assert!(fees.max_fee_per_gas() >= base_fee * 7 / 8 * 2);
+        Ok(())
+    }
+}
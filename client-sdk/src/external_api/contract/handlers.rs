@@ -1,39 +1,79 @@
 use ethers::{
     abi::Detokenize,
+    contract::ContractError,
     core::k256::ecdsa::SigningKey,
     middleware::SignerMiddleware,
-    providers::{Http, Provider},
+    providers::{Http, Middleware as _, Provider},
     signers::Wallet,
-    types::{Address, H256},
+    types::{Address, BlockNumber, TransactionReceipt, H256},
 };
 
-use super::interface::BlockchainError;
+use crate::external_api::utils::{clock::global_clock, retry::with_retry, time::sleep_for};
 
+use super::{
+    gas::{GasConfig, GasStrategy as _},
+    interface::BlockchainError,
+    revert_decoder::decode_contract_error,
+    utils::get_client,
+};
+
+type SignerClient = SignerMiddleware<Provider<Http>, Wallet<SigningKey>>;
+
+const RECEIPT_POLL_INTERVAL: u64 = 2;
+
+/// Send the tx with the fees chosen by `gas_config.strategy` and wait for its receipt.
+/// If no receipt arrives within `gas_config.stuck_timeout`, the tx is rebroadcast with
+/// the same nonce and bumped fees, up to `gas_config.max_bumps` times.
 pub async fn handle_contract_call<S: ToString, O: Detokenize>(
-    tx: &mut ethers::contract::builders::ContractCall<
-        SignerMiddleware<Provider<Http>, Wallet<SigningKey>>,
-        O,
-    >,
+    tx: &mut ethers::contract::builders::ContractCall<SignerClient, O>,
+    rpc_url: &str,
+    gas_config: &GasConfig,
     from_address: Address,
     from_name: S,
     tx_name: S,
 ) -> Result<H256, BlockchainError> {
-    set_gas_price(tx)?;
-    let result = tx.send().await;
-    match result {
-        Ok(tx) => {
-            let pending_tx = tx;
-            log::info!(
-                "{} tx hash: {:?}",
-                tx_name.to_string(),
-                pending_tx.tx_hash()
-            );
-            let tx_receipt = pending_tx
-                .await
-                .map_err(|e| {
-                    BlockchainError::InternalError(format!("Error awaiting tx receipt: {:?}", e))
-                })?
-                .unwrap(); // unwrap is safe here
+    let provider = get_client(rpc_url).await?;
+    let nonce = with_retry(|| async {
+        provider
+            .get_transaction_count(from_address, Some(BlockNumber::Pending.into()))
+            .await
+    })
+    .await
+    .map_err(|e| BlockchainError::NetworkError(format!("Error getting nonce: {:?}", e)))?;
+    tx.tx.set_nonce(nonce);
+
+    let mut fees = gas_config.strategy.fees(&provider).await?;
+    let mut tx_hashes = Vec::new();
+    let mut bumps = 0;
+    loop {
+        fees.apply(&mut tx.tx);
+        log::debug!("{} fees: {}", tx_name.to_string(), fees);
+        match tx.send().await {
+            Ok(pending_tx) => {
+                log::info!(
+                    "{} tx hash: {:?}",
+                    tx_name.to_string(),
+                    pending_tx.tx_hash()
+                );
+                tx_hashes.push(pending_tx.tx_hash());
+            }
+            // a previous broadcast may have been mined in the meantime
+            Err(e) if !tx_hashes.is_empty() => {
+                log::warn!("Failed to rebroadcast {}: {:?}", tx_name.to_string(), e);
+            }
+            Err(e) => return Err(send_error(e, from_address, &from_name, &tx_name)),
+        }
+
+        let can_bump = bumps < gas_config.max_bumps
+            && gas_config
+                .max_fee_per_gas_cap
+                .map_or(true, |cap| fees.max_fee_per_gas() < cap);
+        let timeout = if can_bump {
+            gas_config.stuck_timeout
+        } else {
+            None
+        };
+        if let Some(tx_receipt) = wait_for_receipt(&provider, &tx_hashes, timeout).await? {
             if tx_receipt.status.unwrap() != 1.into() {
                 return Err(BlockchainError::TransactionFailed(format!(
                     "{} failed with tx hash: {:?}",
@@ -43,41 +83,135 @@ pub async fn handle_contract_call<S: ToString, O: Detokenize>(
             }
             return Ok(tx_receipt.transaction_hash);
         }
-        Err(e) => {
-            let error_message = e.to_string();
-            // insufficient balance
-            if error_message.contains("-32000") {
-                return Err(BlockchainError::InsufficientFunds(format!(
-                    "Insufficient funds for {} from {} {:?}",
-                    tx_name.to_string(),
-                    from_name.to_string(),
-                    from_address
-                )));
-            } else {
-                return Err(BlockchainError::InternalError(format!(
-                    "Unknown error sending transaction: {:?}",
-                    e
-                )));
+        bumps += 1;
+        fees = fees.bumped(gas_config.bump_percent, gas_config.max_fee_per_gas_cap);
+        log::warn!(
+            "{} is not mined after {} seconds, rebroadcasting with bumped fees ({}/{})",
+            tx_name.to_string(),
+            gas_config.stuck_timeout.unwrap_or_default(),
+            bumps,
+            gas_config.max_bumps
+        );
+    }
+}
+
+// Receipt of any of the broadcasts, which share the same nonce. Returns None if
+// none is mined within `timeout` seconds.
+async fn wait_for_receipt(
+    provider: &Provider<Http>,
+    tx_hashes: &[H256],
+    timeout: Option<u64>,
+) -> Result<Option<TransactionReceipt>, BlockchainError> {
+    let started_at = global_clock().local_now();
+    loop {
+        for tx_hash in tx_hashes {
+            let receipt = with_retry(|| async { provider.get_transaction_receipt(*tx_hash).await })
+                .await
+                .map_err(|e| {
+                    BlockchainError::NetworkError(format!("Error getting tx receipt: {:?}", e))
+                })?;
+            if receipt.is_some() {
+                return Ok(receipt);
             }
         }
+        if let Some(timeout) = timeout {
+            if global_clock().local_now() >= started_at + timeout {
+                return Ok(None);
+            }
+        }
+        sleep_for(RECEIPT_POLL_INTERVAL).await;
+    }
+}
+
+pub(super) fn send_error<S: ToString>(
+    e: ContractError<SignerClient>,
+    from_address: Address,
+    from_name: &S,
+    tx_name: &S,
+) -> BlockchainError {
+    if let Some(decoded) = decode_contract_error(&e) {
+        return BlockchainError::TransactionFailed(format!(
+            "{} reverted: {}",
+            tx_name.to_string(),
+            decoded
+        ));
+    }
+    let error_message = e.to_string();
+    // insufficient balance
+    if error_message.contains("-32000") {
+        BlockchainError::InsufficientFunds(format!(
+            "Insufficient funds for {} from {} {:?}",
+            tx_name.to_string(),
+            from_name.to_string(),
+            from_address
+        ))
+    } else {
+        BlockchainError::InternalError(format!("Unknown error sending transaction: {:?}", e))
     }
 }
 
-fn set_gas_price<O>(
-    _tx: &mut ethers::contract::builders::ContractCall<
-        SignerMiddleware<Provider<Http>, Wallet<SigningKey>>,
-        O,
-    >,
-) -> Result<(), BlockchainError> {
-    // todo: implement gas price setting
-    // let result = get_gas_estimation().await?;
-    // let inner_tx = tx
-    //     .tx
-    //     .as_eip1559_mut()
-    //     .ok_or(anyhow::anyhow!("EIP-1559 tx expected"))?;
-    // *inner_tx = inner_tx
-    //     .clone()
-    //     .max_priority_fee_per_gas(result.max_priority_fee_per_gas)
-    //     .max_fee_per_gas(result.max_fee_per_gas);
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        core::utils::Anvil,
+        providers::{Http, Middleware as _, Provider},
+        types::{Address, H256, U256},
+    };
+
+    use crate::external_api::{
+        contract::{
+            gas::{Fixed, GasConfig, GasFees, GasStrategyConfig},
+            rollup_contract::RollupContract,
+        },
+        utils::time::sleep_for,
+    };
+
+    #[tokio::test]
+    async fn test_stuck_tx_is_rebroadcast_with_bumped_fees() -> anyhow::Result<()> {
+        let anvil = Anvil::new().spawn();
+        let private_key: [u8; 32] = anvil.keys()[0].to_bytes().try_into().unwrap();
+        let private_key = H256::from_slice(&private_key);
+        let rpc_url = anvil.endpoint();
+        let chain_id = anvil.chain_id();
+        let provider = Provider::<Http>::try_from(rpc_url.as_str())?;
+
+        let fees = GasFees::Eip1559 {
+            max_fee_per_gas: U256::from(10_000_000_000u64),
+            max_priority_fee_per_gas: U256::from(1_000_000_000u64),
+        };
+        let gas_config = GasConfig {
+            strategy: GasStrategyConfig::Fixed(Fixed(fees)),
+            stuck_timeout: Some(1),
+            max_bumps: 2,
+            bump_percent: 50,
+            max_fee_per_gas_cap: None,
+        };
+        let rollup_contract = RollupContract::deploy(&rpc_url, chain_id, private_key)
+            .await?
+            .with_gas_config(gas_config);
+
+        // keep the tx pending until it has been rebroadcast
+        provider
+            .request::<_, ()>("evm_setAutomine", [false])
+            .await?;
+        let mine_later = async {
+            sleep_for(4).await;
+            provider.request::<_, String>("evm_mine", ()).await
+        };
+        let zero_address = Address::zero();
+        let (tx_hash, mined) = tokio::join!(
+            rollup_contract.initialize(
+                private_key,
+                zero_address,
+                zero_address,
+                zero_address,
+                zero_address
+            ),
+            mine_later
+        );
+        mined?;
+        let tx = provider.get_transaction(tx_hash?).await?.unwrap();
+        assert!(tx.max_fee_per_gas.unwrap() > fees.max_fee_per_gas());
+        Ok(())
+    }
 }
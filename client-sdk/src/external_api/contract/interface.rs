@@ -1,3 +1,12 @@
+use async_trait::async_trait;
+use ethers::types::H256;
+use intmax2_interfaces::{
+    api::withdrawal_server::interface::ContractWithdrawal, data::deposit_data::TokenType,
+};
+use intmax2_zkp::ethereum_types::{address::Address, bytes32::Bytes32, u256::U256};
+
+use super::liquidity_contract::{OnchainDeposit, WithdrawalEvent};
+
 #[derive(Debug, thiserror::Error)]
 pub enum BlockchainError {
     #[error("Insufficient funds: {0}")]
@@ -15,6 +24,107 @@ pub enum BlockchainError {
     #[error("Token not found")]
     TokenNotFound,
 
+    #[error("Transaction not found: {0}")]
+    TxNotFound(String),
+
     #[error("Internal error: {0}")]
     InternalError(String),
 }
+
+/// Read side of the liquidity contract: view calls, event queries and the block
+/// number. Needs no key, so it can be built from a URL alone.
+#[async_trait(?Send)]
+pub trait ContractReader {
+    async fn get_block_number(&self) -> Result<u64, BlockchainError>;
+
+    /// The index registered for the token, `None` if it is not registered yet
+    async fn get_token_index(
+        &self,
+        token_type: TokenType,
+        token_address: Address,
+        token_id: U256,
+    ) -> Result<Option<u32>, BlockchainError>;
+
+    /// The token type, address and id registered for `token_index`
+    async fn get_token_info(
+        &self,
+        token_index: u32,
+    ) -> Result<(TokenType, Address, U256), BlockchainError>;
+
+    /// The deposit with id `deposit_id`, found by its `Deposited` event
+    async fn get_deposit(&self, deposit_id: u64)
+        -> Result<Option<OnchainDeposit>, BlockchainError>;
+
+    /// The deposits made by the L1 tx `tx_hash`
+    async fn get_deposits_in_tx(
+        &self,
+        tx_hash: H256,
+    ) -> Result<Vec<OnchainDeposit>, BlockchainError>;
+
+    /// The deposits to `pubkey_salt_hash`, in the order of their ids
+    async fn get_deposits_by_salt_hash(
+        &self,
+        pubkey_salt_hash: Bytes32,
+    ) -> Result<Vec<OnchainDeposit>, BlockchainError>;
+
+    /// The events emitted for `withdrawal_hash`, in block order
+    async fn get_withdrawal_events(
+        &self,
+        withdrawal_hash: Bytes32,
+    ) -> Result<Vec<WithdrawalEvent>, BlockchainError>;
+}
+
+/// Write side of the liquidity contract: the transactions, signed with the given key
+#[async_trait(?Send)]
+pub trait ContractWriter: ContractReader {
+    async fn deposit_native(
+        &self,
+        signer_private_key: H256,
+        pubkey_salt_hash: Bytes32,
+        amount: U256,
+    ) -> Result<(), BlockchainError>;
+
+    /// Returns the hash of the deposit tx
+    async fn deposit_erc20(
+        &self,
+        signer_private_key: H256,
+        pubkey_salt_hash: Bytes32,
+        amount: U256,
+        token_address: Address,
+    ) -> Result<H256, BlockchainError>;
+
+    async fn deposit_erc721(
+        &self,
+        signer_private_key: H256,
+        pubkey_salt_hash: Bytes32,
+        token_address: Address,
+        token_id: U256,
+    ) -> Result<(), BlockchainError>;
+
+    async fn deposit_erc1155(
+        &self,
+        signer_private_key: H256,
+        pubkey_salt_hash: Bytes32,
+        token_address: Address,
+        token_id: U256,
+        amount: U256,
+    ) -> Result<(), BlockchainError>;
+
+    /// Fee in wei of depositing `amount` of `token_index` from the account of
+    /// `signer_private_key`: the gas estimated by the node at the max fee per gas of
+    /// the gas strategy. Nothing is sent. Fails if the deposit would revert, e.g.
+    /// for lack of balance or allowance.
+    async fn estimate_deposit_fee(
+        &self,
+        signer_private_key: H256,
+        token_index: u32,
+        amount: U256,
+    ) -> Result<U256, BlockchainError>;
+
+    /// Returns the hash of the claim tx
+    async fn claim_withdrawals(
+        &self,
+        signer_private_key: H256,
+        withdrawals: &[ContractWithdrawal],
+    ) -> Result<H256, BlockchainError>;
+}
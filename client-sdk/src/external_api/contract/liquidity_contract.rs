@@ -1,12 +1,13 @@
 use std::sync::Arc;
 
+use async_trait::async_trait;
 use ethers::{
-    contract::abigen,
+    contract::{abigen, builders::Event, EthLogDecode, LogMeta},
     core::k256::ecdsa::SigningKey,
     middleware::SignerMiddleware,
-    providers::{Http, Provider},
+    providers::{Http, Middleware, Provider},
     signers::Wallet,
-    types::{Address as EthAddress, H256},
+    types::{Address as EthAddress, BlockNumber, Bytes, H256},
 };
 use intmax2_interfaces::{
     api::withdrawal_server::interface::ContractWithdrawal, data::deposit_data::TokenType,
@@ -18,19 +19,76 @@ use intmax2_zkp::ethereum_types::{
 use crate::external_api::utils::retry::with_retry;
 
 use super::{
-    handlers::handle_contract_call,
-    interface::BlockchainError,
+    gas::{GasConfig, GasStrategy as _},
+    handlers::{handle_contract_call, send_error},
+    interface::{BlockchainError, ContractReader, ContractWriter},
+    multicall_contract::{MulticallContract, MULTICALL3_ADDRESS},
     proxy_contract::ProxyContract,
     utils::{get_address, get_client, get_client_with_signer},
 };
 
 abigen!(Liquidity, "abi/Liquidity.json",);
 
+// blocks covered by one `eth_getLogs` request, as providers reject or time out on
+// wider ranges
+const EVENT_BLOCK_RANGE: u64 = 10000;
+
+#[derive(Debug, Clone)]
+pub struct ClaimResult {
+    pub withdrawal: ContractWithdrawal,
+    pub success: bool,
+    pub tx_hash: Option<H256>,
+}
+
+/// A deposit as recorded by the `Deposited` event of the contract
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnchainDeposit {
+    pub deposit_id: u64,
+    pub sender: EthAddress,
+    pub pubkey_salt_hash: Bytes32,
+    pub token_index: u32,
+    pub amount: U256,
+    pub deposited_at: u64,
+}
+
+impl From<DepositedFilter> for OnchainDeposit {
+    fn from(event: DepositedFilter) -> Self {
+        let mut amount = [0u8; 32];
+        event.amount.to_big_endian(&mut amount);
+        Self {
+            deposit_id: event.deposit_id.as_u64(),
+            sender: event.sender,
+            pubkey_salt_hash: Bytes32::from_bytes_be(&event.recipient_salt_hash),
+            token_index: event.token_index,
+            amount: U256::from_bytes_be(&amount),
+            deposited_at: event.deposited_at.as_u64(),
+        }
+    }
+}
+
+/// What the contract recorded for a withdrawal hash
+#[derive(Debug, Clone, PartialEq)]
+pub enum WithdrawalEventKind {
+    DirectSuccess { recipient: EthAddress },
+    DirectFailed,
+    Claimable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithdrawalEvent {
+    pub kind: WithdrawalEventKind,
+    pub tx_hash: H256,
+    pub block_number: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct LiquidityContract {
     pub rpc_url: String,
     pub chain_id: u64,
     pub address: EthAddress,
+    pub gas_config: GasConfig,
+    /// First block searched for the events of the contract
+    pub deployed_block_number: u64,
 }
 
 impl LiquidityContract {
@@ -39,6 +97,22 @@ impl LiquidityContract {
             rpc_url: rpc_url.to_string(),
             chain_id,
             address,
+            gas_config: GasConfig::default(),
+            deployed_block_number: 0,
+        }
+    }
+
+    /// Use `gas_config` for the fees of state-changing calls
+    pub fn with_gas_config(self, gas_config: GasConfig) -> Self {
+        Self { gas_config, ..self }
+    }
+
+    /// Search the events of the contract from `deployed_block_number`, the block
+    /// the contract was deployed in, instead of from the genesis
+    pub fn with_deployed_block_number(self, deployed_block_number: u64) -> Self {
+        Self {
+            deployed_block_number,
+            ..self
         }
     }
 
@@ -81,6 +155,8 @@ impl LiquidityContract {
         );
         let tx_hash = handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "initialize",
             "initialize",
@@ -109,30 +185,129 @@ impl LiquidityContract {
         Ok(contract)
     }
 
+    pub async fn get_block_number(&self) -> Result<u64, BlockchainError> {
+        read_block_number(&self.get_contract().await?).await
+    }
+
     pub async fn get_token_index(
         &self,
         token_type: TokenType,
         token_address: Address,
         token_id: U256,
     ) -> Result<Option<u32>, BlockchainError> {
-        let contract = self.get_contract().await?;
-        let token_id = ethers::types::U256::from_big_endian(&token_id.to_bytes_be());
-        let token_address = EthAddress::from_slice(&token_address.to_bytes_be());
-        let (is_found, token_index) = with_retry(|| async {
-            contract
-                .get_token_index(token_type as u8, token_address, token_id)
-                .call()
-                .await
-        })
+        read_token_index(
+            &self.get_contract().await?,
+            token_type,
+            token_address,
+            token_id,
+        )
         .await
-        .map_err(|e| {
-            BlockchainError::NetworkError(format!("Error getting token index: {:?}", e))
-        })?;
-        if !is_found {
-            return Ok(None);
-        } else {
-            return Ok(Some(token_index));
+    }
+
+    /// The token type, address and id registered for `token_index`
+    pub async fn get_token_info(
+        &self,
+        token_index: u32,
+    ) -> Result<(TokenType, Address, U256), BlockchainError> {
+        read_token_info(&self.get_contract().await?, token_index).await
+    }
+
+    /// `get_token_info` of each of `token_indices`, read in one Multicall3 call if
+    /// it is deployed on the chain, and with one call per token otherwise
+    pub async fn get_token_infos(
+        &self,
+        token_indices: &[u32],
+    ) -> Result<Vec<(TokenType, Address, U256)>, BlockchainError> {
+        let contract = self.get_contract().await?;
+        let multicall = MulticallContract::new(
+            &self.rpc_url,
+            self.chain_id,
+            MULTICALL3_ADDRESS.parse().unwrap(),
+        );
+        if token_indices.len() < 2 || !multicall.is_deployed().await? {
+            return futures::future::try_join_all(
+                token_indices
+                    .iter()
+                    .map(|token_index| read_token_info(&contract, *token_index)),
+            )
+            .await;
         }
+        let calls = token_indices
+            .iter()
+            .map(|token_index| {
+                let call = contract.get_token_info(*token_index);
+                (self.address, call.calldata().unwrap_or_default())
+            })
+            .collect::<Vec<(EthAddress, Bytes)>>();
+        let results = multicall.call_aggregate3(&calls).await?;
+        token_indices
+            .iter()
+            .zip(results)
+            .map(|(token_index, (success, data))| {
+                if !success {
+                    return Err(BlockchainError::InternalError(format!(
+                        "getTokenInfo reverted for token index {}",
+                        token_index
+                    )));
+                }
+                let token_info: TokenInfo =
+                    contract.decode_output("getTokenInfo", data).map_err(|e| {
+                        BlockchainError::InternalError(format!(
+                            "Error decoding token info of token index {}: {:?}",
+                            token_index, e
+                        ))
+                    })?;
+                to_token_info(*token_index, token_info)
+            })
+            .collect()
+    }
+
+    /// The deposit with id `deposit_id`, found by its `Deposited` event
+    pub async fn get_deposit(
+        &self,
+        deposit_id: u64,
+    ) -> Result<Option<OnchainDeposit>, BlockchainError> {
+        read_deposit(
+            &self.get_contract().await?,
+            self.deployed_block_number,
+            deposit_id,
+        )
+        .await
+    }
+
+    /// The deposits made by the L1 tx `tx_hash`, read from its receipt
+    pub async fn get_deposits_in_tx(
+        &self,
+        tx_hash: H256,
+    ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+        read_deposits_in_tx(&self.get_contract().await?, tx_hash).await
+    }
+
+    /// The deposits to `pubkey_salt_hash`, in the order of their ids
+    pub async fn get_deposits_by_salt_hash(
+        &self,
+        pubkey_salt_hash: Bytes32,
+    ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+        read_deposits_by_salt_hash(
+            &self.get_contract().await?,
+            self.deployed_block_number,
+            pubkey_salt_hash,
+        )
+        .await
+    }
+
+    /// The events emitted for `withdrawal_hash` when the withdrawal aggregator
+    /// submitted it, in block order
+    pub async fn get_withdrawal_events(
+        &self,
+        withdrawal_hash: Bytes32,
+    ) -> Result<Vec<WithdrawalEvent>, BlockchainError> {
+        read_withdrawal_events(
+            &self.get_contract().await?,
+            self.deployed_block_number,
+            withdrawal_hash,
+        )
+        .await
     }
 
     pub async fn deposit_native(
@@ -149,6 +324,8 @@ impl LiquidityContract {
             .value(amount);
         handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "depositer",
             "deposit_native_token",
@@ -157,13 +334,15 @@ impl LiquidityContract {
         Ok(())
     }
 
+    /// Returns the hash of the deposit tx. The liquidity contract has to be allowed
+    /// to transfer `amount` of the token first.
     pub async fn deposit_erc20(
         &self,
         signer_private_key: H256,
         pubkey_salt_hash: Bytes32,
         amount: U256,
         token_address: Address,
-    ) -> Result<(), BlockchainError> {
+    ) -> Result<H256, BlockchainError> {
         let contract = self.get_contract_with_signer(signer_private_key).await?;
         let recipient_salt_hash: [u8; 32] = pubkey_salt_hash.to_bytes_be().try_into().unwrap();
         let amount = ethers::types::U256::from_big_endian(&amount.to_bytes_be());
@@ -171,12 +350,13 @@ impl LiquidityContract {
         let mut tx = contract.deposit_erc20(token_address, recipient_salt_hash, amount);
         handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "depositer",
             "deposit_erc20_token",
         )
-        .await?;
-        Ok(())
+        .await
     }
 
     pub async fn deposit_erc721(
@@ -193,6 +373,8 @@ impl LiquidityContract {
         let mut tx = contract.deposit_erc721(token_address, recipient_salt_hash, token_id);
         handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "depositer",
             "deposit_erc721_token",
@@ -217,6 +399,8 @@ impl LiquidityContract {
         let mut tx = contract.deposit_erc1155(token_address, recipient_salt_hash, token_id, amount);
         handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "depositer",
             "deposit_erc1155_token",
@@ -225,35 +409,675 @@ impl LiquidityContract {
         Ok(())
     }
 
+    pub async fn estimate_deposit_fee(
+        &self,
+        signer_private_key: H256,
+        token_index: u32,
+        amount: U256,
+    ) -> Result<U256, BlockchainError> {
+        let (token_type, token_address, token_id) = self.get_token_info(token_index).await?;
+        let contract = self.get_contract_with_signer(signer_private_key).await?;
+        // a random hash, so that the calldata costs as much as a real one
+        let recipient_salt_hash: [u8; 32] = rand::random();
+        let amount = ethers::types::U256::from_big_endian(&amount.to_bytes_be());
+        let token_id = ethers::types::U256::from_big_endian(&token_id.to_bytes_be());
+        let token_address = EthAddress::from_slice(&token_address.to_bytes_be());
+        let (tx, tx_name) = match token_type {
+            TokenType::NATIVE => (
+                contract
+                    .deposit_native_token(recipient_salt_hash)
+                    .value(amount),
+                "deposit_native_token",
+            ),
+            TokenType::ERC20 => (
+                contract.deposit_erc20(token_address, recipient_salt_hash, amount),
+                "deposit_erc20_token",
+            ),
+            TokenType::ERC721 => (
+                contract.deposit_erc721(token_address, recipient_salt_hash, token_id),
+                "deposit_erc721_token",
+            ),
+            TokenType::ERC1155 => (
+                contract.deposit_erc1155(token_address, recipient_salt_hash, token_id, amount),
+                "deposit_erc1155_token",
+            ),
+        };
+        let gas = tx.estimate_gas().await.map_err(|e| {
+            send_error(
+                e,
+                get_address(self.chain_id, signer_private_key),
+                &"depositer",
+                &tx_name,
+            )
+        })?;
+        let provider = get_client(&self.rpc_url).await?;
+        let fees = self.gas_config.strategy.fees(&provider).await?;
+        let fee = gas * fees.max_fee_per_gas();
+        let mut bytes = [0u8; 32];
+        fee.to_big_endian(&mut bytes);
+        Ok(U256::from_bytes_be(&bytes))
+    }
+
     pub async fn claim_withdrawals(
         &self,
         signer_private_key: H256,
         withdrawals: &[ContractWithdrawal],
-    ) -> Result<(), BlockchainError> {
-        let withdrawals = withdrawals
-            .iter()
-            .map(|w| {
-                let recipient = EthAddress::from_slice(&w.recipient.to_bytes_be());
-                let token_index = w.token_index;
-                let amount = ethers::types::U256::from_big_endian(&w.amount.to_bytes_be());
-                let nullifier: [u8; 32] = w.nullifier.to_bytes_be().try_into().unwrap();
-                Withdrawal {
-                    recipient,
-                    token_index,
-                    amount,
-                    nullifier,
-                }
-            })
-            .collect::<Vec<_>>();
+    ) -> Result<H256, BlockchainError> {
+        let withdrawals = withdrawals.iter().map(to_withdrawal).collect::<Vec<_>>();
         let contract = self.get_contract_with_signer(signer_private_key).await?;
         let mut tx = contract.claim_withdrawals(withdrawals);
-        handle_contract_call(
+        let tx_hash = handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "withdrawer",
             "claim_withdrawals",
         )
         .await?;
-        Ok(())
+        Ok(tx_hash)
+    }
+
+    /// Claim withdrawals through Multicall3, one call per withdrawal so that each
+    /// can succeed or fail independently. Batches whose gas estimate exceeds
+    /// `block_gas_fraction` of the block gas limit are split in half. A batch that
+    /// cannot be sent fails its withdrawals, without a tx hash, and the next
+    /// batches are still sent.
+    /// Falls back to sequential claims if Multicall3 is not deployed.
+    pub async fn claim_withdrawals_batched(
+        &self,
+        signer_private_key: H256,
+        withdrawals: &[ContractWithdrawal],
+        multicall: &MulticallContract,
+        block_gas_fraction: f64,
+    ) -> Result<Vec<ClaimResult>, BlockchainError> {
+        self.claim_withdrawals_batched_with_progress(
+            signer_private_key,
+            withdrawals,
+            multicall,
+            block_gas_fraction,
+            |_| {},
+        )
+        .await
+    }
+
+    /// `claim_withdrawals_batched`, calling `on_claimed` with the result of each
+    /// withdrawal once its batch is sent.
+    pub async fn claim_withdrawals_batched_with_progress(
+        &self,
+        signer_private_key: H256,
+        withdrawals: &[ContractWithdrawal],
+        multicall: &MulticallContract,
+        block_gas_fraction: f64,
+        mut on_claimed: impl FnMut(&ClaimResult),
+    ) -> Result<Vec<ClaimResult>, BlockchainError> {
+        if !multicall.is_deployed().await? {
+            log::warn!("Multicall3 is not deployed, claiming withdrawals sequentially");
+            return self
+                .claim_withdrawals_sequential_with_progress(
+                    signer_private_key,
+                    withdrawals,
+                    on_claimed,
+                )
+                .await;
+        }
+        let max_batch_gas = self.get_max_batch_gas(block_gas_fraction).await?;
+        let contract = self.get_contract().await?;
+        let calls = withdrawals
+            .iter()
+            .map(|w| {
+                let call = contract.claim_withdrawals(vec![to_withdrawal(w)]);
+                (self.address, call.calldata().unwrap_or_default())
+            })
+            .collect::<Vec<(EthAddress, Bytes)>>();
+
+        let mut results = Vec::new();
+        let mut ranges = vec![(0, withdrawals.len())];
+        while let Some((start, end)) = ranges.pop() {
+            if start == end {
+                continue;
+            }
+            let batch = &calls[start..end];
+            let sent = match multicall
+                .estimate_aggregate3(signer_private_key, batch)
+                .await
+            {
+                Ok(gas) if gas > max_batch_gas && end - start > 1 => {
+                    // process the first half first
+                    let mid = (start + end) / 2;
+                    ranges.push((mid, end));
+                    ranges.push((start, mid));
+                    continue;
+                }
+                Ok(_) => multicall.aggregate3(signer_private_key, batch).await,
+                Err(e) => Err(e),
+            };
+            // a failed batch does not lose the results of the batches already sent
+            let outcomes = match sent {
+                Ok((tx_hash, successes)) => successes
+                    .into_iter()
+                    .map(|success| (success, Some(tx_hash)))
+                    .collect::<Vec<_>>(),
+                Err(e) => {
+                    log::error!(
+                        "Failed to claim withdrawals #{} to #{}: {}",
+                        start,
+                        end - 1,
+                        e
+                    );
+                    vec![(false, None); end - start]
+                }
+            };
+            for (withdrawal, (success, tx_hash)) in withdrawals[start..end].iter().zip(outcomes) {
+                let result = ClaimResult {
+                    withdrawal: withdrawal.clone(),
+                    success,
+                    tx_hash,
+                };
+                on_claimed(&result);
+                results.push(result);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Claim withdrawals one transaction at a time, continuing after failures.
+    pub async fn claim_withdrawals_sequential(
+        &self,
+        signer_private_key: H256,
+        withdrawals: &[ContractWithdrawal],
+    ) -> Result<Vec<ClaimResult>, BlockchainError> {
+        self.claim_withdrawals_sequential_with_progress(signer_private_key, withdrawals, |_| {})
+            .await
+    }
+
+    /// `claim_withdrawals_sequential`, calling `on_claimed` with the result of each
+    /// withdrawal once it is sent.
+    pub async fn claim_withdrawals_sequential_with_progress(
+        &self,
+        signer_private_key: H256,
+        withdrawals: &[ContractWithdrawal],
+        mut on_claimed: impl FnMut(&ClaimResult),
+    ) -> Result<Vec<ClaimResult>, BlockchainError> {
+        let mut results = Vec::new();
+        for withdrawal in withdrawals {
+            let result = self
+                .claim_withdrawals(signer_private_key, &[withdrawal.clone()])
+                .await;
+            if let Err(e) = &result {
+                log::error!("Failed to claim withdrawal {:?}: {}", withdrawal, e);
+            }
+            let result = ClaimResult {
+                withdrawal: withdrawal.clone(),
+                success: result.is_ok(),
+                tx_hash: result.ok(),
+            };
+            on_claimed(&result);
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    async fn get_max_batch_gas(
+        &self,
+        block_gas_fraction: f64,
+    ) -> Result<ethers::types::U256, BlockchainError> {
+        let client = get_client(&self.rpc_url).await?;
+        let block = with_retry(|| async { client.get_block(BlockNumber::Latest).await })
+            .await
+            .map_err(|e| BlockchainError::NetworkError(format!("Error getting block: {:?}", e)))?
+            .ok_or_else(|| BlockchainError::NetworkError("Latest block not found".to_string()))?;
+        let max_batch_gas = block.gas_limit.as_u128() as f64 * block_gas_fraction;
+        Ok(ethers::types::U256::from(max_batch_gas as u128))
+    }
+}
+
+fn to_withdrawal(w: &ContractWithdrawal) -> Withdrawal {
+    let recipient = EthAddress::from_slice(&w.recipient.to_bytes_be());
+    let token_index = w.token_index;
+    let amount = ethers::types::U256::from_big_endian(&w.amount.to_bytes_be());
+    let nullifier: [u8; 32] = w.nullifier.to_bytes_be().try_into().unwrap();
+    Withdrawal {
+        recipient,
+        token_index,
+        amount,
+        nullifier,
+    }
+}
+
+/// A reader of the contract at `address` through the RPC at `url`, which needs no
+/// key. On native builds a `ws://` or `wss://` URL is read over a WebSocket.
+/// Events are searched from `deployed_block_number`.
+pub async fn connect_reader(
+    url: &str,
+    chain_id: u64,
+    address: EthAddress,
+    deployed_block_number: u64,
+) -> Result<Box<dyn ContractReader>, BlockchainError> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if super::liquidity_ws::is_ws_url(url) {
+        let reader = super::liquidity_ws::WsLiquidityReader::connect(url, address)
+            .await?
+            .with_deployed_block_number(deployed_block_number);
+        return Ok(Box::new(reader));
+    }
+    Ok(Box::new(
+        LiquidityContract::new(url, chain_id, address)
+            .with_deployed_block_number(deployed_block_number),
+    ))
+}
+
+// The reads below take the contract over any provider, so that the HTTP contract
+// and the WebSocket reader share them.
+
+pub(super) async fn read_block_number<M: Middleware>(
+    contract: &Liquidity<M>,
+) -> Result<u64, BlockchainError> {
+    let client = contract.client_ref();
+    let block_number = with_retry(|| async { client.get_block_number().await })
+        .await
+        .map_err(|_| BlockchainError::NetworkError("failed to get block number".to_string()))?;
+    Ok(block_number.as_u64())
+}
+
+// The logs of `event` from `from_block` to the latest block, queried in ranges of
+// `EVENT_BLOCK_RANGE` blocks
+pub(super) async fn query_logs<M, D, E>(
+    contract: &Liquidity<M>,
+    from_block: u64,
+    event: E,
+    error: impl Fn() -> BlockchainError,
+) -> Result<Vec<(D, LogMeta)>, BlockchainError>
+where
+    M: Middleware,
+    D: EthLogDecode,
+    E: Fn() -> Event<Arc<M>, M, D>,
+{
+    let latest_block = read_block_number(contract).await?;
+    let mut logs = Vec::new();
+    let mut start = from_block;
+    while start <= latest_block {
+        let end = latest_block.min(start + EVENT_BLOCK_RANGE - 1);
+        let range = with_retry(|| async {
+            event()
+                .address(contract.address().into())
+                .from_block(start)
+                .to_block(end)
+                .query_with_meta()
+                .await
+        })
+        .await
+        .map_err(|_| error())?;
+        logs.extend(range);
+        start = end + 1;
+    }
+    Ok(logs)
+}
+
+pub(super) async fn read_token_index<M: Middleware>(
+    contract: &Liquidity<M>,
+    token_type: TokenType,
+    token_address: Address,
+    token_id: U256,
+) -> Result<Option<u32>, BlockchainError> {
+    let token_id = ethers::types::U256::from_big_endian(&token_id.to_bytes_be());
+    let token_address = EthAddress::from_slice(&token_address.to_bytes_be());
+    let (is_found, token_index) = with_retry(|| async {
+        contract
+            .get_token_index(token_type as u8, token_address, token_id)
+            .call()
+            .await
+    })
+    .await
+    .map_err(|e| BlockchainError::NetworkError(format!("Error getting token index: {:?}", e)))?;
+    if !is_found {
+        return Ok(None);
+    } else {
+        return Ok(Some(token_index));
+    }
+}
+
+pub(super) async fn read_token_info<M: Middleware>(
+    contract: &Liquidity<M>,
+    token_index: u32,
+) -> Result<(TokenType, Address, U256), BlockchainError> {
+    let token_info = with_retry(|| async { contract.get_token_info(token_index).call().await })
+        .await
+        .map_err(|e| BlockchainError::NetworkError(format!("Error getting token info: {:?}", e)))?;
+    to_token_info(token_index, token_info)
+}
+
+fn to_token_info(
+    token_index: u32,
+    token_info: TokenInfo,
+) -> Result<(TokenType, Address, U256), BlockchainError> {
+    let token_type = TokenType::try_from(token_info.token_type).map_err(|e| {
+        BlockchainError::InternalError(format!("token index {}: {}", token_index, e))
+    })?;
+    let token_address = Address::from_bytes_be(token_info.token_address.as_bytes());
+    let mut token_id = [0u8; 32];
+    token_info.token_id.to_big_endian(&mut token_id);
+    let token_id = U256::from_bytes_be(&token_id);
+    Ok((token_type, token_address, token_id))
+}
+
+pub(super) async fn read_deposit<M: Middleware>(
+    contract: &Liquidity<M>,
+    from_block: u64,
+    deposit_id: u64,
+) -> Result<Option<OnchainDeposit>, BlockchainError> {
+    let events = query_logs(
+        contract,
+        from_block,
+        || {
+            contract
+                .deposited_filter()
+                .topic1(ethers::types::U256::from(deposit_id))
+        },
+        || BlockchainError::NetworkError(format!("failed to get deposit {}", deposit_id)),
+    )
+    .await?;
+    Ok(events
+        .into_iter()
+        .next()
+        .map(|(event, _)| OnchainDeposit::from(event)))
+}
+
+pub(super) async fn read_deposits_in_tx<M: Middleware>(
+    contract: &Liquidity<M>,
+    tx_hash: H256,
+) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+    let client = contract.client_ref();
+    let receipt = with_retry(|| async { client.get_transaction_receipt(tx_hash).await })
+        .await
+        .map_err(|_| {
+            BlockchainError::NetworkError(format!("failed to get receipt of {:?}", tx_hash))
+        })?
+        .ok_or_else(|| BlockchainError::TxNotFound(format!("{:?}", tx_hash)))?;
+    let deposits = receipt
+        .logs
+        .into_iter()
+        .filter(|log| log.address == contract.address())
+        .filter_map(|log| ethers::contract::parse_log::<DepositedFilter>(log).ok())
+        .map(OnchainDeposit::from)
+        .collect();
+    Ok(deposits)
+}
+
+pub(super) async fn read_deposits_by_salt_hash<M: Middleware>(
+    contract: &Liquidity<M>,
+    from_block: u64,
+    pubkey_salt_hash: Bytes32,
+) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+    let topic = H256::from_slice(&pubkey_salt_hash.to_bytes_be());
+    let events = query_logs(
+        contract,
+        from_block,
+        || contract.deposited_filter().topic3(topic),
+        || BlockchainError::NetworkError(format!("failed to get deposits to {}", pubkey_salt_hash)),
+    )
+    .await?;
+    let mut deposits = events
+        .into_iter()
+        .map(|(event, _)| OnchainDeposit::from(event))
+        .collect::<Vec<_>>();
+    deposits.sort_by_key(|deposit| deposit.deposit_id);
+    Ok(deposits)
+}
+
+pub(super) async fn read_withdrawal_events<M: Middleware>(
+    contract: &Liquidity<M>,
+    from_block: u64,
+    withdrawal_hash: Bytes32,
+) -> Result<Vec<WithdrawalEvent>, BlockchainError> {
+    let topic = H256::from_slice(&withdrawal_hash.to_bytes_be());
+    let error = || {
+        BlockchainError::NetworkError(format!(
+            "failed to get withdrawal events of {}",
+            withdrawal_hash
+        ))
+    };
+    let mut events = Vec::new();
+    let succeeded = query_logs(
+        contract,
+        from_block,
+        || contract.direct_withdrawal_successed_filter().topic1(topic),
+        error,
+    )
+    .await?;
+    for (event, meta) in succeeded {
+        events.push(WithdrawalEvent {
+            kind: WithdrawalEventKind::DirectSuccess {
+                recipient: event.recipient,
+            },
+            tx_hash: meta.transaction_hash,
+            block_number: meta.block_number.as_u64(),
+        });
+    }
+    let failed = query_logs(
+        contract,
+        from_block,
+        || contract.direct_withdrawal_failed_filter().topic1(topic),
+        error,
+    )
+    .await?;
+    for (_, meta) in failed {
+        events.push(WithdrawalEvent {
+            kind: WithdrawalEventKind::DirectFailed,
+            tx_hash: meta.transaction_hash,
+            block_number: meta.block_number.as_u64(),
+        });
+    }
+    let claimable = query_logs(
+        contract,
+        from_block,
+        || contract.withdrawal_claimable_filter().topic1(topic),
+        error,
+    )
+    .await?;
+    for (_, meta) in claimable {
+        events.push(WithdrawalEvent {
+            kind: WithdrawalEventKind::Claimable,
+            tx_hash: meta.transaction_hash,
+            block_number: meta.block_number.as_u64(),
+        });
+    }
+    events.sort_by_key(|event| event.block_number);
+    Ok(events)
+}
+
+#[async_trait(?Send)]
+impl ContractReader for LiquidityContract {
+    async fn get_block_number(&self) -> Result<u64, BlockchainError> {
+        LiquidityContract::get_block_number(self).await
+    }
+
+    async fn get_token_index(
+        &self,
+        token_type: TokenType,
+        token_address: Address,
+        token_id: U256,
+    ) -> Result<Option<u32>, BlockchainError> {
+        LiquidityContract::get_token_index(self, token_type, token_address, token_id).await
+    }
+
+    async fn get_token_info(
+        &self,
+        token_index: u32,
+    ) -> Result<(TokenType, Address, U256), BlockchainError> {
+        LiquidityContract::get_token_info(self, token_index).await
+    }
+
+    async fn get_deposit(
+        &self,
+        deposit_id: u64,
+    ) -> Result<Option<OnchainDeposit>, BlockchainError> {
+        LiquidityContract::get_deposit(self, deposit_id).await
+    }
+
+    async fn get_deposits_in_tx(
+        &self,
+        tx_hash: H256,
+    ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+        LiquidityContract::get_deposits_in_tx(self, tx_hash).await
+    }
+
+    async fn get_deposits_by_salt_hash(
+        &self,
+        pubkey_salt_hash: Bytes32,
+    ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+        LiquidityContract::get_deposits_by_salt_hash(self, pubkey_salt_hash).await
+    }
+
+    async fn get_withdrawal_events(
+        &self,
+        withdrawal_hash: Bytes32,
+    ) -> Result<Vec<WithdrawalEvent>, BlockchainError> {
+        LiquidityContract::get_withdrawal_events(self, withdrawal_hash).await
+    }
+}
+
+#[async_trait(?Send)]
+impl ContractWriter for LiquidityContract {
+    async fn deposit_native(
+        &self,
+        signer_private_key: H256,
+        pubkey_salt_hash: Bytes32,
+        amount: U256,
+    ) -> Result<(), BlockchainError> {
+        LiquidityContract::deposit_native(self, signer_private_key, pubkey_salt_hash, amount).await
+    }
+
+    async fn deposit_erc20(
+        &self,
+        signer_private_key: H256,
+        pubkey_salt_hash: Bytes32,
+        amount: U256,
+        token_address: Address,
+    ) -> Result<H256, BlockchainError> {
+        LiquidityContract::deposit_erc20(
+            self,
+            signer_private_key,
+            pubkey_salt_hash,
+            amount,
+            token_address,
+        )
+        .await
+    }
+
+    async fn deposit_erc721(
+        &self,
+        signer_private_key: H256,
+        pubkey_salt_hash: Bytes32,
+        token_address: Address,
+        token_id: U256,
+    ) -> Result<(), BlockchainError> {
+        LiquidityContract::deposit_erc721(
+            self,
+            signer_private_key,
+            pubkey_salt_hash,
+            token_address,
+            token_id,
+        )
+        .await
+    }
+
+    async fn deposit_erc1155(
+        &self,
+        signer_private_key: H256,
+        pubkey_salt_hash: Bytes32,
+        token_address: Address,
+        token_id: U256,
+        amount: U256,
+    ) -> Result<(), BlockchainError> {
+        LiquidityContract::deposit_erc1155(
+            self,
+            signer_private_key,
+            pubkey_salt_hash,
+            token_address,
+            token_id,
+            amount,
+        )
+        .await
+    }
+
+    async fn estimate_deposit_fee(
+        &self,
+        signer_private_key: H256,
+        token_index: u32,
+        amount: U256,
+    ) -> Result<U256, BlockchainError> {
+        LiquidityContract::estimate_deposit_fee(self, signer_private_key, token_index, amount).await
+    }
+
+    async fn claim_withdrawals(
+        &self,
+        signer_private_key: H256,
+        withdrawals: &[ContractWithdrawal],
+    ) -> Result<H256, BlockchainError> {
+        LiquidityContract::claim_withdrawals(self, signer_private_key, withdrawals).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::Address as EthAddress;
+    use intmax2_zkp::ethereum_types::{bytes32::Bytes32, u32limb_trait::U32LimbTrait as _};
+    use serde_json::{json, Value};
+
+    use crate::external_api::utils::mock_server::{start_mock_server, MockRequests};
+
+    use super::LiquidityContract;
+
+    // an RPC at block 25000 without any log, recording the ranges of `eth_getLogs`
+    fn start_rpc() -> (String, MockRequests) {
+        start_mock_server(|_, body| {
+            let request: Value = serde_json::from_str(body).unwrap();
+            let result = match request["method"].as_str().unwrap() {
+                "eth_blockNumber" => json!("0x61a8"),
+                "eth_getLogs" => json!([]),
+                method => panic!("unexpected {}", method),
+            };
+            json!({"jsonrpc": "2.0", "id": request["id"], "result": result}).to_string()
+        })
+    }
+
+    fn log_ranges(requests: &MockRequests) -> Vec<(u64, u64)> {
+        let block = |value: &Value| u64::from_str_radix(&value.as_str().unwrap()[2..], 16).unwrap();
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| serde_json::from_str::<Value>(&request.body).unwrap())
+            .filter(|request| request["method"] == "eth_getLogs")
+            .map(|request| {
+                let filter = &request["params"][0];
+                (block(&filter["fromBlock"]), block(&filter["toBlock"]))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_withdrawal_events_are_queried_in_ranges() {
+        let (url, requests) = start_rpc();
+        let contract =
+            LiquidityContract::new(&url, 1, EthAddress::random()).with_deployed_block_number(1000);
+        let events = contract
+            .get_withdrawal_events(Bytes32::rand(&mut rand::thread_rng()))
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+        // three events, each from the deployment block to the latest one
+        let ranges = [(1000, 10999), (11000, 20999), (21000, 25000)];
+        assert_eq!(log_ranges(&requests), [ranges, ranges, ranges].concat());
+    }
+
+    #[tokio::test]
+    async fn test_deposit_is_queried_from_the_deployment_block() {
+        let (url, requests) = start_rpc();
+        let contract =
+            LiquidityContract::new(&url, 1, EthAddress::random()).with_deployed_block_number(20000);
+        assert_eq!(contract.get_deposit(7).await.unwrap(), None);
+        assert_eq!(log_ranges(&requests), [(20000, 25000)]);
     }
 }
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use ethers::{
+    contract::{parse_log, EthEvent as _},
+    providers::{Middleware as _, Provider, Ws},
+    types::{Address as EthAddress, Filter, H256},
+};
+use futures::{Stream, StreamExt as _};
+use intmax2_interfaces::data::deposit_data::TokenType;
+use intmax2_zkp::ethereum_types::{address::Address, bytes32::Bytes32, u256::U256};
+
+use super::{
+    interface::{BlockchainError, ContractReader},
+    liquidity_contract::{
+        read_block_number, read_deposit, read_deposits_by_salt_hash, read_deposits_in_tx,
+        read_token_index, read_token_info, read_withdrawal_events, DepositedFilter, Liquidity,
+        OnchainDeposit, WithdrawalEvent,
+    },
+};
+
+/// Whether `url` is a WebSocket RPC URL
+pub fn is_ws_url(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// Reader of the liquidity contract over a WebSocket RPC, which can also push the
+/// `Deposited` events as they are mined instead of having them polled.
+#[derive(Debug, Clone)]
+pub struct WsLiquidityReader {
+    contract: Liquidity<Provider<Ws>>,
+    deployed_block_number: u64,
+}
+
+impl WsLiquidityReader {
+    /// Connect to the `ws://` or `wss://` RPC at `ws_url`
+    pub async fn connect(ws_url: &str, address: EthAddress) -> Result<Self, BlockchainError> {
+        if !is_ws_url(ws_url) {
+            return Err(BlockchainError::InternalError(format!(
+                "{} is not a ws:// or wss:// URL",
+                ws_url
+            )));
+        }
+        let provider = Provider::<Ws>::connect(ws_url).await.map_err(|e| {
+            BlockchainError::NetworkError(format!("failed to connect to {}: {}", ws_url, e))
+        })?;
+        let contract = Liquidity::new(address, Arc::new(provider));
+        Ok(Self {
+            contract,
+            deployed_block_number: 0,
+        })
+    }
+
+    /// Search the events of the contract from the block it was deployed in
+    pub fn with_deployed_block_number(self, deployed_block_number: u64) -> Self {
+        Self {
+            deployed_block_number,
+            ..self
+        }
+    }
+
+    pub fn address(&self) -> EthAddress {
+        self.contract.address()
+    }
+
+    /// The deposits of the contract from now on, in the order they are mined. The
+    /// stream ends when the connection is closed.
+    pub async fn subscribe_deposited_events(
+        &self,
+    ) -> Result<impl Stream<Item = Result<OnchainDeposit, BlockchainError>> + '_, BlockchainError>
+    {
+        let filter = Filter::new()
+            .address(self.address())
+            .topic0(DepositedFilter::signature());
+        let logs = self
+            .contract
+            .client_ref()
+            .subscribe_logs(&filter)
+            .await
+            .map_err(|e| {
+                BlockchainError::NetworkError(format!("failed to subscribe to deposits: {}", e))
+            })?;
+        Ok(logs.map(|log| {
+            parse_log::<DepositedFilter>(log)
+                .map(OnchainDeposit::from)
+                .map_err(|e| {
+                    BlockchainError::DecodeCallDataError(format!("invalid Deposited event: {}", e))
+                })
+        }))
+    }
+}
+
+#[async_trait(?Send)]
+impl ContractReader for WsLiquidityReader {
+    async fn get_block_number(&self) -> Result<u64, BlockchainError> {
+        read_block_number(&self.contract).await
+    }
+
+    async fn get_token_index(
+        &self,
+        token_type: TokenType,
+        token_address: Address,
+        token_id: U256,
+    ) -> Result<Option<u32>, BlockchainError> {
+        read_token_index(&self.contract, token_type, token_address, token_id).await
+    }
+
+    async fn get_token_info(
+        &self,
+        token_index: u32,
+    ) -> Result<(TokenType, Address, U256), BlockchainError> {
+        read_token_info(&self.contract, token_index).await
+    }
+
+    async fn get_deposit(
+        &self,
+        deposit_id: u64,
+    ) -> Result<Option<OnchainDeposit>, BlockchainError> {
+        read_deposit(&self.contract, self.deployed_block_number, deposit_id).await
+    }
+
+    async fn get_deposits_in_tx(
+        &self,
+        tx_hash: H256,
+    ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+        read_deposits_in_tx(&self.contract, tx_hash).await
+    }
+
+    async fn get_deposits_by_salt_hash(
+        &self,
+        pubkey_salt_hash: Bytes32,
+    ) -> Result<Vec<OnchainDeposit>, BlockchainError> {
+        read_deposits_by_salt_hash(&self.contract, self.deployed_block_number, pubkey_salt_hash)
+            .await
+    }
+
+    async fn get_withdrawal_events(
+        &self,
+        withdrawal_hash: Bytes32,
+    ) -> Result<Vec<WithdrawalEvent>, BlockchainError> {
+        read_withdrawal_events(&self.contract, self.deployed_block_number, withdrawal_hash).await
+    }
+}
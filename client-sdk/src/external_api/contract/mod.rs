@@ -1,8 +1,13 @@
 pub mod data_decoder;
 pub mod erc20_contract;
+pub mod gas;
 pub mod handlers;
 pub mod interface;
 pub mod liquidity_contract;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod liquidity_ws;
+pub mod multicall_contract;
 pub mod proxy_contract;
+pub mod revert_decoder;
 pub mod rollup_contract;
 pub mod utils;
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use ethers::{
+    contract::abigen,
+    core::k256::ecdsa::SigningKey,
+    middleware::SignerMiddleware,
+    providers::{Http, Middleware as _, Provider},
+    signers::Wallet,
+    types::{Address, Bytes, H256, U256},
+};
+
+use crate::external_api::utils::retry::with_retry;
+
+use super::{
+    gas::GasConfig,
+    handlers::handle_contract_call,
+    interface::BlockchainError,
+    revert_decoder::decode_contract_error,
+    utils::{get_address, get_client, get_client_with_signer},
+};
+
+/// Canonical Multicall3 address, which is the same on most chains.
+pub const MULTICALL3_ADDRESS: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+
+abigen!(
+    Multicall3,
+    r#"[
+        function aggregate3((address,bool,bytes)[] calls) external payable returns ((bool,bytes)[] returnData)
+    ]"#,
+);
+
+#[derive(Debug, Clone)]
+pub struct MulticallContract {
+    pub rpc_url: String,
+    pub chain_id: u64,
+    pub address: Address,
+    pub gas_config: GasConfig,
+}
+
+impl MulticallContract {
+    pub fn new(rpc_url: &str, chain_id: u64, address: Address) -> Self {
+        Self {
+            rpc_url: rpc_url.to_string(),
+            chain_id,
+            address,
+            gas_config: GasConfig::default(),
+        }
+    }
+
+    /// Use `gas_config` for the fees of state-changing calls
+    pub fn with_gas_config(self, gas_config: GasConfig) -> Self {
+        Self { gas_config, ..self }
+    }
+
+    pub async fn get_contract(&self) -> Result<Multicall3<Provider<Http>>, BlockchainError> {
+        let client = get_client(&self.rpc_url).await?;
+        let contract = Multicall3::new(self.address, client);
+        Ok(contract)
+    }
+
+    async fn get_contract_with_signer(
+        &self,
+        private_key: H256,
+    ) -> Result<Multicall3<SignerMiddleware<Provider<Http>, Wallet<SigningKey>>>, BlockchainError>
+    {
+        let client = get_client_with_signer(&self.rpc_url, self.chain_id, private_key).await?;
+        let contract = Multicall3::new(self.address, Arc::new(client));
+        Ok(contract)
+    }
+
+    /// Returns true if there is code at the multicall address.
+    pub async fn is_deployed(&self) -> Result<bool, BlockchainError> {
+        let client = get_client(&self.rpc_url).await?;
+        let code = with_retry(|| async { client.get_code(self.address, None).await })
+            .await
+            .map_err(|e| BlockchainError::NetworkError(format!("Error getting code: {:?}", e)))?;
+        Ok(!code.is_empty())
+    }
+
+    pub async fn estimate_aggregate3(
+        &self,
+        signer_private_key: H256,
+        calls: &[(Address, Bytes)],
+    ) -> Result<U256, BlockchainError> {
+        let contract = self.get_contract_with_signer(signer_private_key).await?;
+        let tx = contract.aggregate_3(to_call3(calls));
+        let gas = with_retry(|| async { tx.estimate_gas().await })
+            .await
+            .map_err(|e| BlockchainError::NetworkError(format!("Error estimating gas: {:?}", e)))?;
+        Ok(gas)
+    }
+
+    /// Runs all calls in a single `eth_call`, allowing each call to fail
+    /// independently. Returns whether each call succeeded, with its return data.
+    pub async fn call_aggregate3(
+        &self,
+        calls: &[(Address, Bytes)],
+    ) -> Result<Vec<(bool, Bytes)>, BlockchainError> {
+        let contract = self.get_contract().await?;
+        let call = contract.aggregate_3(to_call3(calls));
+        with_retry(|| async { call.call().await })
+            .await
+            .map_err(|e| BlockchainError::NetworkError(format!("Error calling multicall: {:?}", e)))
+    }
+
+    /// Sends all calls in a single transaction, allowing each call to fail independently.
+    /// Returns the tx hash and whether each call succeeded, decoded from the return data
+    /// of a simulation just before sending.
+    pub async fn aggregate3(
+        &self,
+        signer_private_key: H256,
+        calls: &[(Address, Bytes)],
+    ) -> Result<(H256, Vec<bool>), BlockchainError> {
+        let contract = self.get_contract_with_signer(signer_private_key).await?;
+        let mut tx = contract.aggregate_3(to_call3(calls));
+        let results = with_retry(|| async { tx.call().await })
+            .await
+            .map_err(|e| match decode_contract_error(&e) {
+                Some(decoded) => {
+                    BlockchainError::TransactionFailed(format!("aggregate3 reverted: {}", decoded))
+                }
+                None => {
+                    BlockchainError::NetworkError(format!("Error simulating multicall: {:?}", e))
+                }
+            })?;
+        let tx_hash = handle_contract_call(
+            &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
+            get_address(self.chain_id, signer_private_key),
+            "sender",
+            "aggregate3",
+        )
+        .await?;
+        let successes = results.into_iter().map(|(success, _)| success).collect();
+        Ok((tx_hash, successes))
+    }
+}
+
+fn to_call3(calls: &[(Address, Bytes)]) -> Vec<(Address, bool, Bytes)> {
+    calls
+        .iter()
+        .map(|(target, data)| (*target, true, data.clone()))
+        .collect()
+}
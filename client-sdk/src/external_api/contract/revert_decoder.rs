@@ -0,0 +1,175 @@
+use std::fmt;
+
+use ethers::{
+    abi::{decode, ParamType, Token},
+    contract::ContractError,
+    providers::Middleware,
+    types::U256,
+};
+
+use super::{liquidity_contract::LIQUIDITY_ABI, rollup_contract::ROLLUP_ABI};
+
+// selector of Error(string)
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+// selector of Panic(uint256)
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedRevert {
+    pub name: String,
+    pub args: Vec<String>,
+    pub remediation: Option<&'static str>,
+}
+
+impl fmt::Display for DecodedRevert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.name, self.args.join(", "))?;
+        if let Some(remediation) = self.remediation {
+            write!(f, ". {}", remediation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode revert data of the liquidity and rollup contracts, including `Error(string)`
+/// and `Panic(uint256)`. Returns None if the selector is unknown.
+pub fn decode_revert_data(data: &[u8]) -> Option<DecodedRevert> {
+    if data.len() < 4 {
+        return None;
+    }
+    let (selector, args) = data.split_at(4);
+    if selector == ERROR_STRING_SELECTOR {
+        let tokens = decode(&[ParamType::String], args).ok()?;
+        return Some(DecodedRevert {
+            name: "Error".to_string(),
+            args: tokens.into_iter().map(format_token).collect(),
+            remediation: None,
+        });
+    }
+    if selector == PANIC_SELECTOR {
+        let tokens = decode(&[ParamType::Uint(256)], args).ok()?;
+        let code = tokens[0].clone().into_uint()?;
+        return Some(DecodedRevert {
+            name: "Panic".to_string(),
+            args: vec![format!("{:#x} ({})", code, panic_code_name(code))],
+            remediation: None,
+        });
+    }
+    for abi in [&*LIQUIDITY_ABI, &*ROLLUP_ABI] {
+        for error in abi.errors() {
+            if error.signature().as_bytes()[..4] != *selector {
+                continue;
+            }
+            let tokens = error.decode(args).ok()?;
+            let args = error
+                .inputs
+                .iter()
+                .zip(tokens)
+                .map(|(param, token)| format!("{}: {}", param.name, format_token(token)))
+                .collect();
+            return Some(DecodedRevert {
+                name: error.name.clone(),
+                args,
+                remediation: remediation(&error.name),
+            });
+        }
+    }
+    None
+}
+
+/// Decode the revert data carried by a contract error, if any.
+pub fn decode_contract_error<M: Middleware>(error: &ContractError<M>) -> Option<DecodedRevert> {
+    error
+        .as_revert()
+        .and_then(|data| decode_revert_data(data.as_ref()))
+}
+
+fn format_token(token: Token) -> String {
+    match token {
+        Token::String(s) => s,
+        Token::Address(a) => format!("{:?}", a),
+        Token::FixedBytes(b) | Token::Bytes(b) => format!("0x{}", hex::encode(b)),
+        Token::Uint(u) | Token::Int(u) => u.to_string(),
+        token => token.to_string(),
+    }
+}
+
+fn panic_code_name(code: U256) -> &'static str {
+    match code.low_u64() {
+        0x00 => "generic panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to zero-initialized function",
+        _ => "unknown panic code",
+    }
+}
+
+fn remediation(error_name: &str) -> Option<&'static str> {
+    let remediation = match error_name {
+        "TriedToDepositZero" => "Deposit a non-zero amount",
+        "TokenAddressIsZero" => "Specify the token address for non-native deposits",
+        "DepositHashAlreadyExists" => "This deposit was already made. Prepare a new deposit",
+        "InvalidDepositHash" => "The deposit data does not match the deposit. Check the token and amount",
+        "OnlySenderCanCancelDeposit" => "Cancel the deposit from the address that made it",
+        "SafeERC20FailedOperation" => "Check the token balance and the allowance for the liquidity contract",
+        "WithdrawalNotFound" => "The withdrawal has not been relayed to L1 yet or was already claimed. Check the withdrawal status",
+        "InsufficientPenaltyFee" => "Send a larger penalty fee with the block",
+        "PairingCheckFailed" => "The aggregated signature of the block is invalid",
+        "AccessControlUnauthorizedAccount" | "OwnableUnauthorizedAccount" => {
+            "The sender is not allowed to call this function"
+        }
+        _ => return None,
+    };
+    Some(remediation)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::abi::{encode, Token};
+
+    use super::*;
+
+    #[test]
+    fn test_decode_error_string() {
+        let mut data = ERROR_STRING_SELECTOR.to_vec();
+        data.extend(encode(&[Token::String("not allowed".to_string())]));
+        let decoded = decode_revert_data(&data).unwrap();
+        assert_eq!(decoded.to_string(), "Error(not allowed)");
+    }
+
+    #[test]
+    fn test_decode_panic() {
+        let mut data = PANIC_SELECTOR.to_vec();
+        data.extend(encode(&[Token::Uint(U256::from(0x11))]));
+        let decoded = decode_revert_data(&data).unwrap();
+        assert_eq!(decoded.name, "Panic");
+        assert_eq!(
+            decoded.args,
+            vec!["0x11 (arithmetic overflow or underflow)"]
+        );
+    }
+
+    #[test]
+    fn test_decode_custom_error() {
+        let error = LIQUIDITY_ABI.error("TriedToDepositZero").unwrap();
+        let data = error.signature().as_bytes()[..4].to_vec();
+        let decoded = decode_revert_data(&data).unwrap();
+        assert_eq!(decoded.name, "TriedToDepositZero");
+        assert_eq!(decoded.remediation, Some("Deposit a non-zero amount"));
+
+        let error = LIQUIDITY_ABI.error("WithdrawalNotFound").unwrap();
+        let mut data = error.signature().as_bytes()[..4].to_vec();
+        data.extend(encode(&[Token::FixedBytes(vec![1u8; 32])]));
+        let decoded = decode_revert_data(&data).unwrap();
+        assert_eq!(
+            decoded.args,
+            vec![format!("withdrawalHash: 0x{}", "01".repeat(32))]
+        );
+    }
+}
@@ -23,6 +23,7 @@ use crate::external_api::{contract::utils::get_latest_block_number, utils::retry
 
 use super::{
     data_decoder::decode_post_block_calldata,
+    gas::GasConfig,
     handlers::handle_contract_call,
     interface::BlockchainError,
     proxy_contract::ProxyContract,
@@ -70,6 +71,7 @@ pub struct RollupContract {
     pub chain_id: u64,
     pub address: ethers::types::Address,
     pub deployed_block_number: u64,
+    pub gas_config: GasConfig,
 }
 
 impl RollupContract {
@@ -84,9 +86,15 @@ impl RollupContract {
             chain_id,
             address,
             deployed_block_number,
+            gas_config: GasConfig::default(),
         }
     }
 
+    /// Use `gas_config` for the fees of state-changing calls
+    pub fn with_gas_config(self, gas_config: GasConfig) -> Self {
+        Self { gas_config, ..self }
+    }
+
     pub async fn get_eth_block_number(&self) -> Result<u64, BlockchainError> {
         get_latest_block_number(&self.rpc_url).await
     }
@@ -265,6 +273,8 @@ impl RollupContract {
         );
         let tx_hash = handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "initialize",
             "initialize",
@@ -306,6 +316,8 @@ impl RollupContract {
             .value(msg_value);
         let tx_hash = handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "post_registration_block",
             "post_registration_block",
@@ -347,6 +359,8 @@ impl RollupContract {
             .value(msg_value);
         let tx_hash = handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "post_registration_block",
             "post_registration_block",
@@ -370,6 +384,8 @@ impl RollupContract {
         let mut tx = contract.process_deposits(last_processed_deposit_id.into(), deposit_hashes);
         let tx_hash = handle_contract_call(
             &mut tx,
+            &self.rpc_url,
+            &self.gas_config,
             get_address(self.chain_id, signer_private_key),
             "process_deposits",
             "process_deposits",
@@ -1,29 +1,69 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use intmax2_interfaces::api::{
     error::ServerError,
     indexer::interface::{BlockBuilderInfo, IndexerClientInterface},
 };
 
-use super::utils::query::get_request;
+use super::utils::{
+    query::{get_request, HttpClient},
+    retry::RetryConfig,
+};
 
 #[derive(Debug, Clone)]
 pub struct IndexerClient {
     base_url: String,
+    retry_config: Option<RetryConfig>,
+    http: HttpClient,
 }
 
 impl IndexerClient {
     pub fn new(base_url: &str) -> Self {
+        Self::with_http(base_url, HttpClient::default())
+    }
+
+    /// Send the requests with `client`, whose TLS, proxy and timeout settings
+    /// apply, instead of a client built with the default settings
+    pub fn with_http_client(base_url: &str, client: reqwest::Client) -> Self {
+        Self::with_http(base_url, HttpClient::with_client(client))
+    }
+
+    fn with_http(base_url: &str, http: HttpClient) -> Self {
         IndexerClient {
             base_url: base_url.to_string(),
+            retry_config: None,
+            http,
         }
     }
+
+    /// Fail each attempt of a request after `timeout` instead of `DEFAULT_TIMEOUT`,
+    /// replacing a client given to `with_http_client`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = HttpClient::new(timeout);
+        self
+    }
+
+    /// Retry the requests of this client with `config` instead of the
+    /// process-wide `retry_config()`
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry_config = Some(config);
+        self
+    }
 }
 
 #[async_trait(?Send)]
 impl IndexerClientInterface for IndexerClient {
     async fn get_block_builder_info(&self) -> Result<Vec<BlockBuilderInfo>, ServerError> {
-        let response: Vec<BlockBuilderInfo> =
-            get_request::<(), _>(&self.base_url, "/v1/indexer/builders", None, None).await?;
+        let response: Vec<BlockBuilderInfo> = get_request::<(), _>(
+            &self.http,
+            &self.base_url,
+            "/v1/indexer/builders",
+            None,
+            None,
+            self.retry_config.as_ref(),
+        )
+        .await?;
         Ok(response)
     }
 }
@@ -0,0 +1,587 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use intmax2_interfaces::{
+    api::{
+        error::ServerError,
+        store_vault_server::interface::{DataType, StoreVaultClientInterface},
+    },
+    data::meta_data::MetaData,
+};
+use intmax2_zkp::{ethereum_types::u256::U256, utils::poseidon_hash_out::PoseidonHashOut};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+};
+use serde::{Deserialize, Serialize};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+/// A write to mirror to a secondary store vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MirrorOp {
+    BalanceProof {
+        pubkey: U256,
+        proof: ProofWithPublicInputs<F, C, D>,
+    },
+    Data {
+        data_type: DataType,
+        pubkey: U256,
+        encrypted_data: Vec<u8>,
+    },
+    UserData {
+        pubkey: U256,
+        encrypted_data: Vec<u8>,
+    },
+}
+
+/// A write that failed on the secondary store vault at index `secondary`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedMirrorOp {
+    pub secondary: usize,
+    pub op: MirrorOp,
+}
+
+/// Persistent storage of the writes waiting to be mirrored, e.g. a file for the
+/// CLI, so that they survive the process
+pub trait MirrorQueueStore: std::fmt::Debug {
+    fn load(&self) -> Vec<QueuedMirrorOp>;
+
+    fn save(&self, ops: &[QueuedMirrorOp]);
+}
+
+/// Store vault client that mirrors every write to secondary store vaults, and falls back
+/// to them on reads when the primary is unreachable or missing data.
+///
+/// Writes to secondaries that fail are queued, in the `MirrorQueueStore` if one is set,
+/// and retried by `flush_mirror_queue`. Since store vaults assign their own uuids, items
+/// listed from several vaults are merged by uuid and by payload.
+pub struct MirroredStoreVaultClient<S: StoreVaultClientInterface> {
+    primary: S,
+    secondaries: Vec<S>,
+    queue: Mutex<Vec<QueuedMirrorOp>>,
+    queue_store: Option<Arc<dyn MirrorQueueStore>>,
+}
+
+impl<S: StoreVaultClientInterface> MirroredStoreVaultClient<S> {
+    pub fn new(primary: S, secondaries: Vec<S>) -> Self {
+        Self {
+            primary,
+            secondaries,
+            queue: Mutex::new(Vec::new()),
+            queue_store: None,
+        }
+    }
+
+    /// Keep the queue in `store`, starting from the writes it holds
+    pub fn with_queue_store(mut self, store: Arc<dyn MirrorQueueStore>) -> Self {
+        self.queue = Mutex::new(store.load());
+        self.queue_store = Some(store);
+        self
+    }
+
+    fn update_queue<R>(&self, f: impl FnOnce(&mut Vec<QueuedMirrorOp>) -> R) -> R {
+        let mut queue = self.queue.lock().unwrap();
+        let result = f(&mut queue);
+        if let Some(store) = &self.queue_store {
+            store.save(&queue);
+        }
+        result
+    }
+
+    pub fn primary(&self) -> &S {
+        &self.primary
+    }
+
+    /// Number of writes waiting to be mirrored
+    pub fn pending_mirror_ops(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    /// Retry the queued writes to secondaries. Returns the number of writes that
+    /// succeeded; failed writes stay in the queue.
+    ///
+    /// A user data is only written if it is still the latest: one queued again
+    /// later for the same secondary replaces it, and so does the one the primary
+    /// holds now, as every write goes to the primary first.
+    pub async fn flush_mirror_queue(&self) -> usize {
+        let ops = self.update_queue(std::mem::take);
+        let mut flushed = 0;
+        for (i, queued) in ops.iter().enumerate() {
+            if queued.secondary >= self.secondaries.len() {
+                log::warn!(
+                    "Dropping a write queued for secondary store vault #{}, which is no longer \
+                     configured",
+                    queued.secondary
+                );
+                continue;
+            }
+            let op = match &queued.op {
+                MirrorOp::UserData { pubkey, .. }
+                    if ops[i + 1..].iter().any(|later| {
+                        later.secondary == queued.secondary
+                            && matches!(&later.op, MirrorOp::UserData { pubkey: p, .. } if p == pubkey)
+                    }) =>
+                {
+                    continue;
+                }
+                MirrorOp::UserData {
+                    pubkey,
+                    encrypted_data,
+                } => match self.primary.get_user_data(*pubkey).await {
+                    Ok(Some(latest)) if latest != *encrypted_data => {
+                        log::info!(
+                            "Mirroring the latest user data of {} instead of a queued older one",
+                            pubkey
+                        );
+                        MirrorOp::UserData {
+                            pubkey: *pubkey,
+                            encrypted_data: latest,
+                        }
+                    }
+                    Ok(_) => queued.op.clone(),
+                    Err(e) => {
+                        // without the latest, the queued one could overwrite a newer one
+                        log::warn!("Primary store vault is unreachable: {}", e);
+                        self.update_queue(|queue| queue.push(queued.clone()));
+                        continue;
+                    }
+                },
+                _ => queued.op.clone(),
+            };
+            if self.mirror_op(queued.secondary, op).await {
+                flushed += 1;
+            }
+        }
+        flushed
+    }
+
+    /// Number of items of the given type present on the primary but missing on each
+    /// secondary.
+    pub async fn mirror_lag(
+        &self,
+        data_type: DataType,
+        pubkey: U256,
+    ) -> Result<Vec<usize>, ServerError> {
+        let primary_items = self
+            .primary
+            .get_data_all_after(data_type, pubkey, 0)
+            .await?;
+        let mut lags = Vec::new();
+        for secondary in self.secondaries.iter() {
+            let secondary_items = secondary.get_data_all_after(data_type, pubkey, 0).await?;
+            let lag = primary_items
+                .iter()
+                .filter(|(meta, data)| !contains_item(&secondary_items, meta, data))
+                .count();
+            lags.push(lag);
+        }
+        Ok(lags)
+    }
+
+    async fn mirror(&self, op: MirrorOp) {
+        for index in 0..self.secondaries.len() {
+            self.mirror_op(index, op.clone()).await;
+        }
+    }
+
+    // write op to the secondary, and queue it on failure
+    async fn mirror_op(&self, index: usize, op: MirrorOp) -> bool {
+        let secondary = &self.secondaries[index];
+        let result = match &op {
+            MirrorOp::BalanceProof { pubkey, proof } => {
+                secondary.save_balance_proof(*pubkey, proof).await
+            }
+            MirrorOp::Data {
+                data_type,
+                pubkey,
+                encrypted_data,
+            } => {
+                secondary
+                    .save_data(*data_type, *pubkey, encrypted_data)
+                    .await
+            }
+            MirrorOp::UserData {
+                pubkey,
+                encrypted_data,
+            } => {
+                secondary
+                    .save_user_data(*pubkey, encrypted_data.clone())
+                    .await
+            }
+        };
+        match result {
+            Ok(_) => true,
+            Err(e) => {
+                log::warn!(
+                    "Failed to mirror to secondary store vault #{}: {}",
+                    index,
+                    e
+                );
+                self.update_queue(|queue| {
+                    queue.push(QueuedMirrorOp {
+                        secondary: index,
+                        op,
+                    })
+                });
+                false
+            }
+        }
+    }
+}
+
+fn contains_item(items: &[(MetaData, Vec<u8>)], meta: &MetaData, data: &[u8]) -> bool {
+    items
+        .iter()
+        .any(|(m, d)| m.uuid == meta.uuid || d.as_slice() == data)
+}
+
+#[async_trait(?Send)]
+impl<S: StoreVaultClientInterface> StoreVaultClientInterface for MirroredStoreVaultClient<S> {
+    async fn save_balance_proof(
+        &self,
+        pubkey: U256,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<(), ServerError> {
+        self.primary.save_balance_proof(pubkey, proof).await?;
+        self.mirror(MirrorOp::BalanceProof {
+            pubkey,
+            proof: proof.clone(),
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn get_balance_proof(
+        &self,
+        pubkey: U256,
+        block_number: u32,
+        private_commitment: PoseidonHashOut,
+    ) -> Result<Option<ProofWithPublicInputs<F, C, D>>, ServerError> {
+        let primary_result = self
+            .primary
+            .get_balance_proof(pubkey, block_number, private_commitment)
+            .await;
+        if let Ok(Some(_)) = &primary_result {
+            return primary_result;
+        }
+        for secondary in self.secondaries.iter() {
+            if let Ok(Some(proof)) = secondary
+                .get_balance_proof(pubkey, block_number, private_commitment)
+                .await
+            {
+                return Ok(Some(proof));
+            }
+        }
+        primary_result
+    }
+
+    async fn save_data(
+        &self,
+        data_type: DataType,
+        pubkey: U256,
+        encrypted_data: &[u8],
+    ) -> Result<(), ServerError> {
+        self.primary
+            .save_data(data_type, pubkey, encrypted_data)
+            .await?;
+        self.mirror(MirrorOp::Data {
+            data_type,
+            pubkey,
+            encrypted_data: encrypted_data.to_vec(),
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn get_data(
+        &self,
+        data_type: DataType,
+        uuid: &str,
+    ) -> Result<Option<(MetaData, Vec<u8>)>, ServerError> {
+        let primary_result = self.primary.get_data(data_type, uuid).await;
+        if let Ok(Some(_)) = &primary_result {
+            return primary_result;
+        }
+        for secondary in self.secondaries.iter() {
+            if let Ok(Some(data)) = secondary.get_data(data_type, uuid).await {
+                return Ok(Some(data));
+            }
+        }
+        primary_result
+    }
+
+    async fn get_data_all_after(
+        &self,
+        data_type: DataType,
+        pubkey: U256,
+        timestamp: u64,
+    ) -> Result<Vec<(MetaData, Vec<u8>)>, ServerError> {
+        let primary_result = self
+            .primary
+            .get_data_all_after(data_type, pubkey, timestamp)
+            .await;
+        let mut merged = match &primary_result {
+            Ok(items) => items.clone(),
+            Err(e) => {
+                log::warn!("Primary store vault is unreachable: {}", e);
+                Vec::new()
+            }
+        };
+        let mut any_ok = primary_result.is_ok();
+        for secondary in self.secondaries.iter() {
+            match secondary
+                .get_data_all_after(data_type, pubkey, timestamp)
+                .await
+            {
+                Ok(items) => {
+                    any_ok = true;
+                    for (meta, data) in items {
+                        if !contains_item(&merged, &meta, &data) {
+                            merged.push((meta, data));
+                        }
+                    }
+                }
+                Err(e) => log::warn!("Secondary store vault is unreachable: {}", e),
+            }
+        }
+        if !any_ok {
+            return primary_result;
+        }
+        merged.sort_by_key(|(meta, _)| meta.timestamp);
+        Ok(merged)
+    }
+
+    async fn save_user_data(
+        &self,
+        pubkey: U256,
+        encrypted_data: Vec<u8>,
+    ) -> Result<(), ServerError> {
+        self.primary
+            .save_user_data(pubkey, encrypted_data.clone())
+            .await?;
+        self.mirror(MirrorOp::UserData {
+            pubkey,
+            encrypted_data,
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn get_user_data(&self, pubkey: U256) -> Result<Option<Vec<u8>>, ServerError> {
+        let primary_result = self.primary.get_user_data(pubkey).await;
+        if let Ok(Some(_)) = &primary_result {
+            return primary_result;
+        }
+        for secondary in self.secondaries.iter() {
+            if let Ok(Some(data)) = secondary.get_user_data(pubkey).await {
+                return Ok(Some(data));
+            }
+        }
+        primary_result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::external_api::utils::in_memory_store_vault::InMemoryStoreVault;
+
+    // in-memory store vault that fails every request while `down`
+    #[derive(Default)]
+    struct FlakyStoreVault {
+        vault: InMemoryStoreVault,
+        down: Cell<bool>,
+    }
+
+    impl FlakyStoreVault {
+        fn check(&self) -> Result<(), ServerError> {
+            if self.down.get() {
+                return Err(ServerError::NetworkError("down".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl StoreVaultClientInterface for FlakyStoreVault {
+        async fn save_balance_proof(
+            &self,
+            pubkey: U256,
+            proof: &ProofWithPublicInputs<F, C, D>,
+        ) -> Result<(), ServerError> {
+            self.check()?;
+            self.vault.save_balance_proof(pubkey, proof).await
+        }
+
+        async fn get_balance_proof(
+            &self,
+            pubkey: U256,
+            block_number: u32,
+            private_commitment: PoseidonHashOut,
+        ) -> Result<Option<ProofWithPublicInputs<F, C, D>>, ServerError> {
+            self.check()?;
+            self.vault
+                .get_balance_proof(pubkey, block_number, private_commitment)
+                .await
+        }
+
+        async fn save_data(
+            &self,
+            data_type: DataType,
+            pubkey: U256,
+            encrypted_data: &[u8],
+        ) -> Result<(), ServerError> {
+            self.check()?;
+            self.vault
+                .save_data(data_type, pubkey, encrypted_data)
+                .await
+        }
+
+        async fn get_data(
+            &self,
+            data_type: DataType,
+            uuid: &str,
+        ) -> Result<Option<(MetaData, Vec<u8>)>, ServerError> {
+            self.check()?;
+            self.vault.get_data(data_type, uuid).await
+        }
+
+        async fn get_data_all_after(
+            &self,
+            data_type: DataType,
+            pubkey: U256,
+            timestamp: u64,
+        ) -> Result<Vec<(MetaData, Vec<u8>)>, ServerError> {
+            self.check()?;
+            self.vault
+                .get_data_all_after(data_type, pubkey, timestamp)
+                .await
+        }
+
+        async fn save_user_data(
+            &self,
+            pubkey: U256,
+            encrypted_data: Vec<u8>,
+        ) -> Result<(), ServerError> {
+            self.check()?;
+            self.vault.save_user_data(pubkey, encrypted_data).await
+        }
+
+        async fn get_user_data(&self, pubkey: U256) -> Result<Option<Vec<u8>>, ServerError> {
+            self.check()?;
+            self.vault.get_user_data(pubkey).await
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct MemoryQueueStore(Mutex<Vec<QueuedMirrorOp>>);
+
+    impl MirrorQueueStore for MemoryQueueStore {
+        fn load(&self) -> Vec<QueuedMirrorOp> {
+            self.0.lock().unwrap().clone()
+        }
+
+        fn save(&self, ops: &[QueuedMirrorOp]) {
+            *self.0.lock().unwrap() = ops.to_vec();
+        }
+    }
+
+    fn mirrored(store: &Arc<MemoryQueueStore>) -> MirroredStoreVaultClient<FlakyStoreVault> {
+        let secondary = FlakyStoreVault::default();
+        secondary.down.set(true);
+        MirroredStoreVaultClient::new(FlakyStoreVault::default(), vec![secondary])
+            .with_queue_store(store.clone())
+    }
+
+    #[tokio::test]
+    async fn test_queue_survives_the_client() {
+        let store = Arc::new(MemoryQueueStore::default());
+        let pubkey = U256::from(7u128);
+        let client = mirrored(&store);
+        client
+            .save_data(DataType::Transfer, pubkey, b"transfer")
+            .await
+            .unwrap();
+        client.save_user_data(pubkey, b"v1".to_vec()).await.unwrap();
+        assert_eq!(client.pending_mirror_ops(), 2);
+        drop(client);
+
+        // a later process picks the queue up and mirrors it once the secondary is back
+        let client = mirrored(&store);
+        client
+            .primary()
+            .save_user_data(pubkey, b"v1".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(client.pending_mirror_ops(), 2);
+        client.secondaries[0].down.set(false);
+        assert_eq!(client.flush_mirror_queue().await, 2);
+        assert_eq!(client.pending_mirror_ops(), 0);
+        assert!(store.load().is_empty());
+        let secondary = &client.secondaries[0];
+        assert_eq!(
+            secondary.get_user_data(pubkey).await.unwrap(),
+            Some(b"v1".to_vec())
+        );
+        assert_eq!(
+            secondary
+                .get_data_all_after(DataType::Transfer, pubkey, 0)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flush_does_not_overwrite_newer_user_data() {
+        let store = Arc::new(MemoryQueueStore::default());
+        let pubkey = U256::from(7u128);
+        let client = mirrored(&store);
+        client.save_user_data(pubkey, b"v1".to_vec()).await.unwrap();
+        client.save_user_data(pubkey, b"v2".to_vec()).await.unwrap();
+        assert_eq!(client.pending_mirror_ops(), 2);
+
+        // the older queued user data is skipped for the later one
+        client.secondaries[0].down.set(false);
+        assert_eq!(client.flush_mirror_queue().await, 1);
+        assert_eq!(
+            client.secondaries[0].get_user_data(pubkey).await.unwrap(),
+            Some(b"v2".to_vec())
+        );
+
+        // a queued user data older than the primary's is replaced by it
+        client.secondaries[0].down.set(true);
+        client.save_user_data(pubkey, b"v3".to_vec()).await.unwrap();
+        client
+            .primary()
+            .save_user_data(pubkey, b"v4".to_vec())
+            .await
+            .unwrap();
+        client.secondaries[0].down.set(false);
+        assert_eq!(client.flush_mirror_queue().await, 1);
+        assert_eq!(
+            client.secondaries[0].get_user_data(pubkey).await.unwrap(),
+            Some(b"v4".to_vec())
+        );
+
+        // nothing is written while the primary cannot tell the latest
+        client.secondaries[0].down.set(true);
+        client.save_user_data(pubkey, b"v5".to_vec()).await.unwrap();
+        client.secondaries[0].down.set(false);
+        client.primary().down.set(true);
+        assert_eq!(client.flush_mirror_queue().await, 0);
+        assert_eq!(client.pending_mirror_ops(), 1);
+        assert_eq!(
+            client.secondaries[0].get_user_data(pubkey).await.unwrap(),
+            Some(b"v4".to_vec())
+        );
+    }
+}
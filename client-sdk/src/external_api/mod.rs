@@ -1,7 +1,10 @@
 pub mod balance_prover;
 pub mod block_builder;
+pub mod cached_validity_prover;
 pub mod contract;
 pub mod indexer;
+pub mod mirrored_store_vault;
+pub mod sponsor;
 pub mod store_vault_server;
 pub mod utils;
 pub mod validity_prover;
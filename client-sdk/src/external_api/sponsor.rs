@@ -0,0 +1,363 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ethers::{
+    signers::{LocalWallet, Signer as _},
+    types::{Address as EthAddress, Signature, H256},
+};
+use intmax2_interfaces::api::{
+    block_builder::interface::BlockBuilderClientInterface,
+    error::ServerError,
+    sponsor::{
+        interface::{fee_voucher_message, FeeVoucher, SponsorClientInterface},
+        types::{RequestFeeVoucherRequest, RequestFeeVoucherResponse},
+    },
+};
+use intmax2_zkp::{
+    common::tx::Tx,
+    ethereum_types::{
+        address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _,
+    },
+};
+
+use super::utils::{
+    query::{post_request_once, HttpClient},
+    retry::RetryConfig,
+};
+
+// start of the error message of a block builder rejecting a voucher
+const INVALID_FEE_VOUCHER: &str = "Invalid fee voucher";
+
+#[derive(Debug, Clone)]
+pub struct SponsorClient {
+    retry_config: Option<RetryConfig>,
+    http: HttpClient,
+}
+
+impl SponsorClient {
+    pub fn new() -> Self {
+        Self::with_http(HttpClient::default())
+    }
+
+    /// Send the requests with `client`, whose TLS, proxy and timeout settings
+    /// apply, instead of a client built with the default settings
+    pub fn with_http_client(client: reqwest::Client) -> Self {
+        Self::with_http(HttpClient::with_client(client))
+    }
+
+    fn with_http(http: HttpClient) -> Self {
+        SponsorClient {
+            retry_config: None,
+            http,
+        }
+    }
+
+    /// Fail each attempt of a request after `timeout` instead of `DEFAULT_TIMEOUT`,
+    /// replacing a client given to `with_http_client`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = HttpClient::new(timeout);
+        self
+    }
+
+    /// Retry the requests of this client with `config` instead of the
+    /// process-wide `retry_config()`
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry_config = Some(config);
+        self
+    }
+}
+
+impl Default for SponsorClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait(?Send)]
+impl SponsorClientInterface for SponsorClient {
+    async fn request_fee_voucher(
+        &self,
+        sponsor_url: &str,
+        pubkey: U256,
+        tx_hash: Bytes32,
+    ) -> Result<Option<FeeVoucher>, ServerError> {
+        let request = RequestFeeVoucherRequest { pubkey, tx_hash };
+        let response: RequestFeeVoucherResponse = post_request_once(
+            &self.http,
+            sponsor_url,
+            "/sponsor/fee-voucher",
+            &request,
+            None,
+            self.retry_config.as_ref(),
+        )
+        .await?;
+        if let Some(reason) = response.decline_reason {
+            log::info!("sponsor declined the fee of tx {}: {}", tx_hash, reason);
+        }
+        Ok(response.voucher)
+    }
+}
+
+/// Sign a fee voucher with the sponsor's Ethereum key
+pub fn sign_fee_voucher(
+    sponsor_private_key: H256,
+    pubkey: U256,
+    tx_hash: Bytes32,
+) -> anyhow::Result<FeeVoucher> {
+    let wallet = LocalWallet::from_bytes(sponsor_private_key.as_bytes())?;
+    let message = H256::from_slice(&fee_voucher_message(pubkey, tx_hash).to_bytes_be());
+    let signature = wallet.sign_hash(message)?;
+    Ok(FeeVoucher {
+        sponsor: Address::from_bytes_be(wallet.address().as_bytes()),
+        pubkey,
+        tx_hash,
+        signature: signature.to_vec(),
+    })
+}
+
+/// Check that the voucher covers the tx of `pubkey`, and is signed by `sponsor`
+pub fn verify_fee_voucher(
+    voucher: &FeeVoucher,
+    sponsor: Address,
+    pubkey: U256,
+    tx_hash: Bytes32,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(voucher.sponsor == sponsor, "voucher of an unknown sponsor");
+    anyhow::ensure!(
+        voucher.pubkey == pubkey && voucher.tx_hash == tx_hash,
+        "voucher is for another tx"
+    );
+    let signature = Signature::try_from(voucher.signature.as_slice())?;
+    let message = H256::from_slice(&fee_voucher_message(pubkey, tx_hash).to_bytes_be());
+    let signer = signature.recover(message)?;
+    anyhow::ensure!(
+        signer == EthAddress::from_slice(&sponsor.to_bytes_be()),
+        "voucher is not signed by the sponsor"
+    );
+    Ok(())
+}
+
+/// Ask the sponsor for a voucher of the tx. If the sponsor declines, fails, or
+/// returns an invalid voucher, the sender pays the fee and `None` is returned.
+pub async fn fetch_fee_voucher<SP: SponsorClientInterface>(
+    sponsor_client: &SP,
+    sponsor_url: &str,
+    pubkey: U256,
+    tx_hash: Bytes32,
+) -> Option<FeeVoucher> {
+    match sponsor_client
+        .request_fee_voucher(sponsor_url, pubkey, tx_hash)
+        .await
+    {
+        Ok(Some(voucher)) => match verify_fee_voucher(&voucher, voucher.sponsor, pubkey, tx_hash) {
+            Ok(()) => Some(voucher),
+            Err(e) => {
+                log::warn!(
+                    "invalid fee voucher from the sponsor, paying the fee: {}",
+                    e
+                );
+                None
+            }
+        },
+        Ok(None) => {
+            log::warn!("sponsor declined the fee, paying the fee");
+            None
+        }
+        Err(e) => {
+            log::warn!("failed to request a fee voucher, paying the fee: {}", e);
+            None
+        }
+    }
+}
+
+/// Send the tx request with `fee_voucher`. If the block builder rejects the
+/// voucher, the request is sent again without it, and the sender pays the fee.
+pub async fn send_tx_request_with_voucher<BB: BlockBuilderClientInterface>(
+    block_builder: &BB,
+    block_builder_url: &str,
+    is_registration_block: bool,
+    pubkey: U256,
+    tx: Tx,
+    fee_voucher: Option<FeeVoucher>,
+) -> Result<(), ServerError> {
+    let has_voucher = fee_voucher.is_some();
+    let result = block_builder
+        .send_tx_request(
+            block_builder_url,
+            is_registration_block,
+            pubkey,
+            tx,
+            None,
+            fee_voucher,
+        )
+        .await;
+    match result {
+        Err(ServerError::ServerError(_, message, ..))
+            if has_voucher && message.contains(INVALID_FEE_VOUCHER) =>
+        {
+            log::warn!(
+                "the block builder rejected the fee voucher, paying the fee: {}",
+                message
+            );
+            block_builder
+                .send_tx_request(
+                    block_builder_url,
+                    is_registration_block,
+                    pubkey,
+                    tx,
+                    None,
+                    None,
+                )
+                .await
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{common::signature::key_set::KeySet, utils::leafable::Leafable as _};
+
+    use crate::external_api::{
+        block_builder::BlockBuilderClient,
+        utils::mock_server::{start_mock_server, start_scripted_mock_server},
+    };
+
+    use super::*;
+
+    const SPONSOR_KEY: &str = "0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    fn sponsor_key() -> H256 {
+        SPONSOR_KEY.parse().unwrap()
+    }
+
+    fn accepting_sponsor(_path: &str, body: &str) -> String {
+        let request: RequestFeeVoucherRequest = serde_json::from_str(body).unwrap();
+        let voucher = sign_fee_voucher(sponsor_key(), request.pubkey, request.tx_hash).unwrap();
+        serde_json::to_string(&RequestFeeVoucherResponse {
+            voucher: Some(voucher),
+            decline_reason: None,
+        })
+        .unwrap()
+    }
+
+    fn declining_sponsor(_path: &str, _body: &str) -> String {
+        serde_json::to_string(&RequestFeeVoucherResponse {
+            voucher: None,
+            decline_reason: Some("budget exhausted".to_string()),
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sponsor_accepts() {
+        let (url, requests) = start_mock_server(accepting_sponsor);
+        let mut rng = rand::thread_rng();
+        let pubkey = KeySet::rand(&mut rng).pubkey;
+        let tx_hash = Bytes32::rand(&mut rng);
+
+        let voucher = fetch_fee_voucher(&SponsorClient::default(), &url, pubkey, tx_hash)
+            .await
+            .expect("sponsor should accept");
+        assert_eq!(requests.lock().unwrap()[0].path, "/sponsor/fee-voucher");
+
+        // the block builder accepts it for the configured sponsor only
+        let sponsor = sign_fee_voucher(sponsor_key(), pubkey, tx_hash)
+            .unwrap()
+            .sponsor;
+        verify_fee_voucher(&voucher, sponsor, pubkey, tx_hash).unwrap();
+        assert!(verify_fee_voucher(&voucher, Address::default(), pubkey, tx_hash).is_err());
+        assert!(verify_fee_voucher(&voucher, sponsor, pubkey, Bytes32::rand(&mut rng)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sponsor_declines() {
+        let (url, _) = start_mock_server(declining_sponsor);
+        let mut rng = rand::thread_rng();
+        let pubkey = KeySet::rand(&mut rng).pubkey;
+        let voucher = fetch_fee_voucher(
+            &SponsorClient::default(),
+            &url,
+            pubkey,
+            Bytes32::rand(&mut rng),
+        )
+        .await;
+        assert!(voucher.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sponsor_fails() {
+        let mut rng = rand::thread_rng();
+        let pubkey = KeySet::rand(&mut rng).pubkey;
+        let tx_hash = Bytes32::rand(&mut rng);
+
+        // an unavailable sponsor
+        let (url, _) =
+            start_scripted_mock_server(|_, _| (500, r#"{"error":"unavailable"}"#.to_string()));
+        let voucher = fetch_fee_voucher(&SponsorClient::default(), &url, pubkey, tx_hash).await;
+        assert!(voucher.is_none());
+
+        // a voucher signed for another tx
+        let (url, _) = start_mock_server(move |_, _| {
+            let voucher = sign_fee_voucher(sponsor_key(), pubkey, Bytes32::default()).unwrap();
+            serde_json::to_string(&RequestFeeVoucherResponse {
+                voucher: Some(voucher),
+                decline_reason: None,
+            })
+            .unwrap()
+        });
+        let voucher = fetch_fee_voucher(&SponsorClient::default(), &url, pubkey, tx_hash).await;
+        assert!(voucher.is_none());
+
+        // a sponsor answering something else than a voucher
+        let (url, _) = start_mock_server(|_, _| "[]".to_string());
+        let voucher = fetch_fee_voucher(&SponsorClient::default(), &url, pubkey, tx_hash).await;
+        assert!(voucher.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_builder_rejects_voucher() {
+        let mut rng = rand::thread_rng();
+        let pubkey = KeySet::rand(&mut rng).pubkey;
+        let tx = Tx {
+            nonce: 1,
+            ..Default::default()
+        };
+        let voucher = sign_fee_voucher(sponsor_key(), pubkey, tx.hash().into()).unwrap();
+
+        // a builder accepting no sponsor fails the request with the voucher only
+        let (url, requests) = start_scripted_mock_server(|_, body| {
+            if body.contains(r#""feeVoucher":null"#) {
+                (200, "null".to_string())
+            } else {
+                (
+                    500,
+                    "Invalid fee voucher: voucher of an unknown sponsor".to_string(),
+                )
+            }
+        });
+        let block_builder = BlockBuilderClient::new();
+        send_tx_request_with_voucher(&block_builder, &url, false, pubkey, tx, Some(voucher))
+            .await
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].body.contains(r#""feeVoucher":null"#));
+        drop(requests);
+
+        // other failures are not retried
+        let (url, requests) = start_scripted_mock_server(|_, _| (500, "Block is full".to_string()));
+        let voucher = sign_fee_voucher(sponsor_key(), pubkey, tx.hash().into()).unwrap();
+        assert!(send_tx_request_with_voucher(
+            &block_builder,
+            &url,
+            false,
+            pubkey,
+            tx,
+            Some(voucher)
+        )
+        .await
+        .is_err());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
@@ -1,3 +1,8 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
 use async_trait::async_trait;
 use intmax2_interfaces::{
     api::{
@@ -7,7 +12,8 @@ use intmax2_interfaces::{
             types::{
                 GetBalanceProofQuery, GetBalanceProofResponse, GetDataAllAfterQuery,
                 GetDataAllAfterResponse, GetDataQuery, GetDataResponse, GetUserDataQuery,
-                GetUserDataResponse, SaveBalanceProofRequest, SaveDataRequest,
+                GetUserDataResponse, SaveBalanceProofRequest, SaveDataRequest, StoreVaultInfo,
+                WireFormat, DEFAULT_MAX_BLOB_SIZE,
             },
         },
     },
@@ -19,21 +25,151 @@ use plonky2::{
     plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
 };
 
-use super::utils::query::{get_request, post_request};
+use crate::client::session::account_session;
+
+use super::utils::{
+    query::{
+        get_request, get_request_with_format, post_request, post_request_with_format,
+        post_request_with_format_once, HttpClient,
+    },
+    retry::RetryConfig,
+};
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
 const D: usize = 2;
 
+/// Reads go to `read_url`, which may be served by a CDN, and saves go to
+/// `write_url`, the origin. Reads of data that changes over time are sent with
+/// `Cache-Control: no-cache`, while immutable blobs may be served from a cache.
+///
+/// Blobs are exchanged in bincode if the server lists it in its info, which is
+/// asked once, and in JSON otherwise. Blobs over the maximum size of the info are
+/// refused before they are sent.
 #[derive(Debug, Clone)]
 pub struct StoreVaultServerClient {
-    base_url: String,
+    read_url: String,
+    write_url: String,
+    preferred_format: WireFormat,
+    negotiated: Arc<Mutex<Option<Negotiated>>>,
+    retry_config: Option<RetryConfig>,
+    http: HttpClient,
 }
 
 impl StoreVaultServerClient {
     pub fn new(base_url: &str) -> Self {
+        Self::with_urls(base_url, base_url)
+    }
+
+    pub fn with_urls(read_url: &str, write_url: &str) -> Self {
+        Self::with_http(read_url, write_url, HttpClient::default())
+    }
+
+    /// Send the requests with `client`, whose TLS, proxy and timeout settings
+    /// apply, instead of a client built with the default settings
+    pub fn with_http_client(base_url: &str, client: reqwest::Client) -> Self {
+        Self::with_http(base_url, base_url, HttpClient::with_client(client))
+    }
+
+    fn with_http(read_url: &str, write_url: &str, http: HttpClient) -> Self {
         StoreVaultServerClient {
-            base_url: base_url.to_string(),
+            read_url: read_url.to_string(),
+            write_url: write_url.to_string(),
+            preferred_format: WireFormat::Bincode,
+            negotiated: Arc::new(Mutex::new(None)),
+            retry_config: None,
+            http,
+        }
+    }
+
+    /// Use `format` if the server supports it
+    pub fn with_wire_format(mut self, format: WireFormat) -> Self {
+        self.preferred_format = format;
+        self
+    }
+
+    /// Fail each attempt of a request after `timeout` instead of `DEFAULT_TIMEOUT`,
+    /// replacing a client given to `with_http_client`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = HttpClient::new(timeout);
+        self
+    }
+
+    /// Retry the requests of this client with `config` instead of the
+    /// process-wide `retry_config()`
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry_config = Some(config);
+        self
+    }
+
+    pub async fn get_info(&self) -> Result<StoreVaultInfo, ServerError> {
+        get_request::<(), _>(
+            &self.http,
+            &self.write_url,
+            "/store-vault-server/info",
+            None,
+            None,
+            self.retry_config.as_ref(),
+        )
+        .await
+    }
+
+    async fn negotiated(&self) -> Negotiated {
+        if let Some(negotiated) = *self.negotiated.lock().unwrap() {
+            return negotiated;
+        }
+        let negotiated = match self.get_info().await {
+            Ok(info) => Negotiated {
+                format: if info.wire_formats.contains(&self.preferred_format) {
+                    self.preferred_format
+                } else {
+                    WireFormat::Json
+                },
+                max_blob_size: info.max_blob_size,
+            },
+            // not remembered, so that the next request asks again
+            Err(ServerError::NetworkError(_) | ServerError::CircuitOpen { .. }) => {
+                return Negotiated::default()
+            }
+            // e.g. a 404 of a server without the info endpoint
+            Err(_) => Negotiated::default(),
+        };
+        *self.negotiated.lock().unwrap() = Some(negotiated);
+        negotiated
+    }
+
+    async fn wire_format(&self) -> WireFormat {
+        if self.preferred_format == WireFormat::Json {
+            return WireFormat::Json;
+        }
+        self.negotiated().await.format
+    }
+
+    // checked before sending, so that a blob the server would refuse is not uploaded
+    async fn check_blob_size(&self, data: &[u8]) -> Result<(), ServerError> {
+        let max = self.negotiated().await.max_blob_size;
+        if data.len() > max {
+            return Err(ServerError::PayloadTooLarge {
+                size: data.len(),
+                max,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// What the info of the server allows, or the defaults of a server without it
+#[derive(Debug, Clone, Copy)]
+struct Negotiated {
+    format: WireFormat,
+    max_blob_size: usize,
+}
+
+impl Default for Negotiated {
+    fn default() -> Self {
+        Self {
+            format: WireFormat::Json,
+            max_blob_size: DEFAULT_MAX_BLOB_SIZE,
         }
     }
 }
@@ -45,15 +181,19 @@ impl StoreVaultClientInterface for StoreVaultServerClient {
         pubkey: U256,
         proof: &ProofWithPublicInputs<F, C, D>,
     ) -> Result<(), ServerError> {
+        let session = account_session(pubkey);
+        let _write = session.begin_write()?;
         let request = SaveBalanceProofRequest {
             pubkey,
             balance_proof: proof.clone(),
         };
         post_request::<_, ()>(
-            &self.base_url,
+            &self.http,
+            &self.write_url,
             "/store-vault-server/save-balance-proof",
             &request,
             None,
+            self.retry_config.as_ref(),
         )
         .await
     }
@@ -70,10 +210,12 @@ impl StoreVaultClientInterface for StoreVaultServerClient {
             private_commitment,
         };
         let response: GetBalanceProofResponse = get_request(
-            &self.base_url,
+            &self.http,
+            &self.read_url,
             "/store-vault-server/get-balance-proof",
             Some(query),
             None,
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.balance_proof)
@@ -85,15 +227,21 @@ impl StoreVaultClientInterface for StoreVaultServerClient {
         pubkey: U256,
         encrypted_data: &[u8],
     ) -> Result<(), ServerError> {
+        self.check_blob_size(encrypted_data).await?;
+        let session = account_session(pubkey);
+        let _write = session.begin_write()?;
         let request = SaveDataRequest {
             pubkey,
             data: encrypted_data.to_vec(),
         };
-        post_request::<_, ()>(
-            &self.base_url,
+        post_request_with_format_once::<_, ()>(
+            &self.http,
+            &self.write_url,
             &format!("/store-vault-server/{}/save", data_type.to_string()),
             &request,
             None,
+            self.wire_format().await,
+            self.retry_config.as_ref(),
         )
         .await
     }
@@ -106,11 +254,15 @@ impl StoreVaultClientInterface for StoreVaultServerClient {
         let query = GetDataQuery {
             uuid: uuid.to_string(),
         };
-        let response: GetDataResponse = get_request(
-            &self.base_url,
+        let response: GetDataResponse = get_request_with_format(
+            &self.http,
+            &self.read_url,
             &format!("/store-vault-server/{}/get", data_type.to_string()),
             Some(query),
             None,
+            false,
+            self.wire_format().await,
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.data)
@@ -123,14 +275,18 @@ impl StoreVaultClientInterface for StoreVaultServerClient {
         timestamp: u64,
     ) -> Result<Vec<(MetaData, Vec<u8>)>, ServerError> {
         let query = GetDataAllAfterQuery { pubkey, timestamp };
-        let response: GetDataAllAfterResponse = get_request(
-            &self.base_url,
+        let response: GetDataAllAfterResponse = get_request_with_format(
+            &self.http,
+            &self.read_url,
             &format!(
                 "/store-vault-server/{}/get-all-after",
                 data_type.to_string()
             ),
             Some(query),
             None,
+            true,
+            self.wire_format().await,
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.data)
@@ -141,28 +297,275 @@ impl StoreVaultClientInterface for StoreVaultServerClient {
         pubkey: U256,
         encrypted_data: Vec<u8>,
     ) -> Result<(), ServerError> {
+        self.check_blob_size(&encrypted_data).await?;
+        let session = account_session(pubkey);
+        let _write = session.begin_write()?;
         let request = SaveDataRequest {
             pubkey,
             data: encrypted_data,
         };
-        post_request::<_, ()>(
-            &self.base_url,
+        post_request_with_format::<_, ()>(
+            &self.http,
+            &self.write_url,
             "/store-vault-server/save-user-data",
             &request,
             None,
+            self.wire_format().await,
+            self.retry_config.as_ref(),
         )
         .await
     }
 
     async fn get_user_data(&self, pubkey: U256) -> Result<Option<Vec<u8>>, ServerError> {
         let query = GetUserDataQuery { pubkey };
-        let response: GetUserDataResponse = get_request(
-            &self.base_url,
+        let response: GetUserDataResponse = get_request_with_format(
+            &self.http,
+            &self.read_url,
             "/store-vault-server/get-user-data",
             Some(query),
             None,
+            true,
+            self.wire_format().await,
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use intmax2_zkp::common::signature::key_set::KeySet;
+
+    use crate::external_api::utils::mock_server::{
+        start_binary_mock_server, start_mock_server, start_scripted_mock_server, MockRequests,
+    };
+
+    use super::*;
+
+    // answer every request with an empty result
+    fn respond(path: &str, _body: &str) -> String {
+        if path.ends_with("/get-all-after") {
+            r#"{"data":[]}"#
+        } else if path.ends_with("/get-balance-proof") {
+            r#"{"balanceProof":null}"#
+        } else if path.contains("/save") {
+            "null"
+        } else {
+            r#"{"data":null}"#
+        }
+        .to_string()
+    }
+
+    // (path, whether the request had `Cache-Control: no-cache`)
+    fn recorded(requests: &MockRequests) -> Vec<(String, bool)> {
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| (request.path.clone(), request.no_cache))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_read_write_routing() {
+        let (read_url, read_requests) = start_mock_server(respond);
+        let (write_url, write_requests) = start_mock_server(respond);
+        let client = StoreVaultServerClient::with_urls(&read_url, &write_url)
+            .with_wire_format(WireFormat::Json);
+        let pubkey = KeySet::rand(&mut rand::thread_rng()).pubkey;
+
+        client.save_user_data(pubkey, vec![1]).await.unwrap();
+        client
+            .save_data(DataType::Deposit, pubkey, &[1])
+            .await
+            .unwrap();
+        client.get_user_data(pubkey).await.unwrap();
+        client
+            .get_data_all_after(DataType::Deposit, pubkey, 0)
+            .await
+            .unwrap();
+        client.get_data(DataType::Deposit, "uuid").await.unwrap();
+
+        assert_eq!(
+            recorded(&write_requests),
+            vec![
+                ("/store-vault-server/info".to_string(), false),
+                ("/store-vault-server/save-user-data".to_string(), false),
+                ("/store-vault-server/deposit/save".to_string(), false),
+            ]
+        );
+        // mutable data bypasses caches, immutable blobs don't
+        assert_eq!(
+            recorded(&read_requests),
+            vec![
+                ("/store-vault-server/get-user-data".to_string(), true),
+                (
+                    "/store-vault-server/deposit/get-all-after".to_string(),
+                    true
+                ),
+                ("/store-vault-server/deposit/get".to_string(), false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_blob_is_not_sent() {
+        let (url, requests) = start_mock_server(respond);
+        let client = StoreVaultServerClient::new(&url);
+        let pubkey = KeySet::rand(&mut rand::thread_rng()).pubkey;
+
+        let result = client
+            .save_user_data(pubkey, vec![0; DEFAULT_MAX_BLOB_SIZE + 1])
+            .await;
+        assert!(matches!(
+            result,
+            Err(ServerError::PayloadTooLarge { size, .. }) if size == DEFAULT_MAX_BLOB_SIZE + 1
+        ));
+        assert_eq!(
+            recorded(&requests),
+            vec![("/store-vault-server/info".to_string(), false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_advertised_max_blob_size() {
+        let (url, requests) = start_mock_server(|path, body| {
+            if path.ends_with("/info") {
+                r#"{"wireFormats":["json"],"maxBlobSize":8}"#.to_string()
+            } else {
+                respond(path, body)
+            }
+        });
+        let client = StoreVaultServerClient::new(&url);
+        let pubkey = KeySet::rand(&mut rand::thread_rng()).pubkey;
+
+        client
+            .save_data(DataType::Deposit, pubkey, &[0; 8])
+            .await
+            .unwrap();
+        let result = client.save_data(DataType::Deposit, pubkey, &[0; 9]).await;
+        assert!(matches!(
+            result,
+            Err(ServerError::PayloadTooLarge { size: 9, max: 8 })
+        ));
+        // the info is asked once, and the oversized blob is not sent
+        assert_eq!(
+            recorded(&requests),
+            vec![
+                ("/store-vault-server/info".to_string(), false),
+                ("/store-vault-server/deposit/save".to_string(), false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_structured_error_message() {
+        let (url, _) = start_scripted_mock_server(|_, _| {
+            (
+                400,
+                r#"{"error":"unknown_topic","message":"Unknown topic letter, valid topics are deposit, transfer, withdrawal, tx","field":"type","validValues":["deposit","transfer","withdrawal","tx"]}"#.to_string(),
+            )
+        });
+        let client = StoreVaultServerClient::new(&url);
+
+        match client.get_data(DataType::Deposit, "uuid").await {
+            Err(ServerError::ServerError(status, message, _, _)) => {
+                assert_eq!(status, 400);
+                assert_eq!(
+                    message,
+                    "Unknown topic letter, valid topics are deposit, transfer, withdrawal, tx"
+                );
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    // keeps the last saved user data and answers in the format asked for. Returns
+    // the number of bytes of the user data responses sent.
+    fn start_user_data_server(with_info: bool) -> (String, MockRequests, Arc<AtomicUsize>) {
+        let stored = Mutex::new(None::<Vec<u8>>);
+        let served = Arc::new(AtomicUsize::new(0));
+        let served_clone = served.clone();
+        let (url, requests) = start_binary_mock_server(move |request, body| {
+            let json = WireFormat::Json.content_type();
+            if request.path.ends_with("/info") {
+                if !with_info {
+                    return (404, json, b"not found".to_vec());
+                }
+                let info = StoreVaultInfo {
+                    wire_formats: vec![WireFormat::Json, WireFormat::Bincode],
+                    max_blob_size: DEFAULT_MAX_BLOB_SIZE,
+                };
+                return (200, json, serde_json::to_vec(&info).unwrap());
+            }
+            if request.path.ends_with("/save-user-data") {
+                let format =
+                    WireFormat::from_content_type(request.content_type.as_deref().unwrap_or(""));
+                let request: SaveDataRequest = format.decode(body).unwrap();
+                *stored.lock().unwrap() = Some(request.data);
+                return (200, json, b"null".to_vec());
+            }
+            let format = WireFormat::from_content_type(request.accept.as_deref().unwrap_or(""));
+            let response = GetUserDataResponse {
+                data: stored.lock().unwrap().clone(),
+            };
+            let response = format.encode(&response).unwrap();
+            served_clone.fetch_add(response.len(), Ordering::SeqCst);
+            (200, format.content_type(), response)
+        });
+        (url, requests, served)
+    }
+
+    #[tokio::test]
+    async fn test_binary_envelope_bytes_on_the_wire() {
+        let blob: Vec<u8> = (0..1024 * 1024).map(|i| (i * 7 % 256) as u8).collect();
+        let pubkey = KeySet::rand(&mut rand::thread_rng()).pubkey;
+
+        let mut wire_sizes = Vec::new();
+        for format in [WireFormat::Json, WireFormat::Bincode] {
+            let (url, requests, served) = start_user_data_server(true);
+            let client = StoreVaultServerClient::new(&url).with_wire_format(format);
+            client.save_user_data(pubkey, blob.clone()).await.unwrap();
+            let fetched = client.get_user_data(pubkey).await.unwrap();
+            assert_eq!(fetched.as_ref(), Some(&blob));
+
+            let sent: usize = requests
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|request| request.path.ends_with("/save-user-data"))
+                .map(|request| request.body_size)
+                .sum();
+            wire_sizes.push(sent + served.load(Ordering::SeqCst));
+        }
+
+        // the blob is saved and fetched once, bincode only adds a few bytes to
+        // it, while JSON writes each byte as up to 3 digits and a comma
+        let (json_size, binary_size) = (wire_sizes[0], wire_sizes[1]);
+        assert!(binary_size < 2 * blob.len() + 1024);
+        assert!(json_size > 3 * binary_size);
+    }
+
+    #[tokio::test]
+    async fn test_json_fallback_without_info() {
+        let (url, requests, _) = start_user_data_server(false);
+        let client = StoreVaultServerClient::new(&url);
+        let pubkey = KeySet::rand(&mut rand::thread_rng()).pubkey;
+
+        client.save_user_data(pubkey, vec![1, 2, 3]).await.unwrap();
+        let fetched = client.get_user_data(pubkey).await.unwrap();
+        assert_eq!(fetched, Some(vec![1, 2, 3]));
+
+        let requests = requests.lock().unwrap();
+        let info_requests = requests
+            .iter()
+            .filter(|request| request.path.ends_with("/info"))
+            .count();
+        assert_eq!(info_requests, 1);
+        assert!(requests.iter().all(|request| request.accept.is_none()
+            && request.content_type.as_deref() != Some(WireFormat::Bincode.content_type())));
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+/// Skew in seconds above which a warning is shown
+pub const SKEW_WARNING_THRESHOLD: u64 = 30;
+
+// weight of a new sample in the smoothed offset
+const SMOOTHING: f64 = 0.2;
+
+static GLOBAL_CLOCK: OnceLock<SkewAwareClock> = OnceLock::new();
+
+/// The clock shared by the HTTP clients, which record the `Date` header of every
+/// response, and by the expiry and timeout checks.
+pub fn global_clock() -> &'static SkewAwareClock {
+    GLOBAL_CLOCK.get_or_init(SkewAwareClock::new)
+}
+
+/// Local clock corrected by the offset of each server's clock. Timestamps given by
+/// a server are compared with `server_now`, so that a skewed local clock does not
+/// make them look expired or in the future.
+#[derive(Debug, Default)]
+pub struct SkewAwareClock {
+    // smoothed (server time - local time) in seconds, by host
+    offsets: Mutex<HashMap<String, f64>>,
+}
+
+impl SkewAwareClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Unix timestamp of the local clock. Use it for durations measured locally.
+    pub fn local_now(&self) -> u64 {
+        chrono::Utc::now().timestamp() as u64
+    }
+
+    /// Record that `server_time` was reported by the server at `url` when the local
+    /// clock was at `local_time`
+    pub fn record(&self, url: &str, server_time: u64, local_time: u64) {
+        let sample = server_time as f64 - local_time as f64;
+        let mut offsets = self.offsets.lock().unwrap();
+        let offset = offsets.entry(host_of(url)).or_insert(sample);
+        *offset += SMOOTHING * (sample - *offset);
+    }
+
+    /// Offset of the server at `url` in seconds, 0 if it was never measured
+    pub fn offset(&self, url: &str) -> i64 {
+        let offsets = self.offsets.lock().unwrap();
+        offsets
+            .get(&host_of(url))
+            .map_or(0, |offset| offset.round() as i64)
+    }
+
+    /// Median offset over all measured servers, which estimates the skew of the
+    /// local clock when the server a timestamp comes from is not known.
+    ///
+    /// A deployment has a handful of servers, so this is not a statistic but the
+    /// offset of the middle one. It is taken over the mean so that one server with
+    /// a wrong clock does not move every expiry check; each offset is itself
+    /// smoothed over the responses of its server.
+    pub fn median_offset(&self) -> i64 {
+        let offsets = self.offsets.lock().unwrap();
+        let mut values = offsets.values().copied().collect::<Vec<_>>();
+        if values.is_empty() {
+            return 0;
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        values[values.len() / 2].round() as i64
+    }
+
+    /// Current time of the server at `url`
+    pub fn server_now_for(&self, url: &str) -> u64 {
+        apply_offset(self.local_now(), self.offset(url))
+    }
+
+    /// Current time of the servers
+    pub fn server_now(&self) -> u64 {
+        apply_offset(self.local_now(), self.median_offset())
+    }
+
+    /// Whether `timeout` seconds have passed since `timestamp`, given by a server
+    pub fn is_timed_out(&self, timestamp: u64, timeout: u64) -> bool {
+        timestamp + timeout < self.server_now()
+    }
+
+    /// Warnings for the servers whose clock is more than `SKEW_WARNING_THRESHOLD`
+    /// seconds away from the local clock
+    pub fn skew_warnings(&self) -> Vec<String> {
+        let offsets = self.offsets.lock().unwrap();
+        let mut warnings = offsets
+            .iter()
+            .filter(|(_, offset)| offset.abs() > SKEW_WARNING_THRESHOLD as f64)
+            .map(|(host, offset)| {
+                format!(
+                    "local clock is {}s {} {}. Expiry and timeout checks are corrected, but please sync the system clock",
+                    offset.abs().round(),
+                    if *offset > 0.0 { "behind" } else { "ahead of" },
+                    host
+                )
+            })
+            .collect::<Vec<_>>();
+        warnings.sort();
+        warnings
+    }
+}
+
+fn apply_offset(local: u64, offset: i64) -> u64 {
+    (local as i64 + offset).max(0) as u64
+}
+
+// host and port of a url, or the input itself if it is not a url
+pub(crate) fn host_of(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) => match (parsed.host_str(), parsed.port_or_known_default()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            _ => url.to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Parse the `Date` header of an HTTP response into a unix timestamp
+pub fn parse_http_date(date: &str) -> Option<u64> {
+    chrono::DateTime::parse_from_rfc2822(date)
+        .ok()
+        .map(|date| date.timestamp() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKEW: u64 = 5 * 60;
+
+    #[test]
+    fn test_skewed_server_timestamps_are_not_timed_out() {
+        let tx_timeout = 80;
+        for server_ahead in [true, false] {
+            let clock = SkewAwareClock::new();
+            let local = clock.local_now();
+            let server = if server_ahead {
+                local + SKEW
+            } else {
+                local - SKEW
+            };
+            clock.record("http://store-vault:9000/store-vault-server", server, local);
+
+            // a tx saved 10 seconds ago by the server
+            let timestamp = server - 10;
+            assert!(!clock.is_timed_out(timestamp, tx_timeout));
+            assert!(clock.is_timed_out(timestamp - tx_timeout, tx_timeout));
+            if !server_ahead {
+                // the local clock alone would reject it
+                assert!(timestamp + tx_timeout < local);
+            }
+            assert_eq!(clock.skew_warnings().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_offset_is_smoothed_per_host() {
+        let clock = SkewAwareClock::new();
+        clock.record("http://a:9000/x", 1100, 1000);
+        clock.record("http://a:9000/y", 1000, 1000);
+        // the first sample is taken as is, the next ones are averaged in
+        assert_eq!(clock.offset("http://a:9000"), 80);
+        assert_eq!(clock.offset("http://b:9000"), 0);
+
+        clock.record("http://b:9000", 1010, 1000);
+        clock.record("http://c:9000", 990, 1000);
+        assert_eq!(clock.median_offset(), 10);
+        assert!(clock.skew_warnings()[0].contains("a:9000"));
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784111777)
+        );
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+}
@@ -0,0 +1,162 @@
+use std::cell::RefCell;
+
+use async_trait::async_trait;
+use intmax2_interfaces::{
+    api::{
+        error::ServerError,
+        store_vault_server::interface::{DataType, StoreVaultClientInterface},
+    },
+    data::meta_data::MetaData,
+};
+use intmax2_zkp::{
+    ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _},
+    utils::poseidon_hash_out::PoseidonHashOut,
+};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+};
+use sha2::{Digest as _, Sha256};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+struct Blob {
+    data_type: DataType,
+    pubkey: U256,
+    meta: MetaData,
+    data: Vec<u8>,
+}
+
+/// Store vault kept in memory, for tests that need to tamper with the stored data
+#[derive(Default)]
+pub struct InMemoryStoreVault {
+    blobs: RefCell<Vec<Blob>>,
+    user_data: RefCell<Vec<(U256, Vec<u8>)>>,
+    balance_proofs: RefCell<Vec<(U256, ProofWithPublicInputs<F, C, D>)>>,
+}
+
+impl InMemoryStoreVault {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Save a blob and return its uuid, which is derived from the data type, the
+    /// pubkey and the data. Each blob is one second after the previous one.
+    pub fn insert(&self, data_type: DataType, pubkey: U256, data: Vec<u8>) -> String {
+        let mut blobs = self.blobs.borrow_mut();
+        let hash = Sha256::new()
+            .chain_update(data_type.to_string())
+            .chain_update(pubkey.to_bytes_be())
+            .chain_update(&data)
+            .finalize();
+        let meta = MetaData {
+            uuid: format!("blob-{}", hex::encode(&hash[..16])),
+            timestamp: blobs.last().map_or(1, |blob| blob.meta.timestamp + 1),
+            block_number: None,
+        };
+        let uuid = meta.uuid.clone();
+        blobs.push(Blob {
+            data_type,
+            pubkey,
+            meta,
+            data,
+        });
+        uuid
+    }
+
+    /// Delete a blob, returning whether it existed
+    pub fn remove(&self, uuid: &str) -> bool {
+        let mut blobs = self.blobs.borrow_mut();
+        let len = blobs.len();
+        blobs.retain(|blob| blob.meta.uuid != uuid);
+        blobs.len() != len
+    }
+}
+
+#[async_trait(?Send)]
+impl StoreVaultClientInterface for InMemoryStoreVault {
+    async fn save_balance_proof(
+        &self,
+        pubkey: U256,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<(), ServerError> {
+        self.balance_proofs
+            .borrow_mut()
+            .push((pubkey, proof.clone()));
+        Ok(())
+    }
+
+    async fn get_balance_proof(
+        &self,
+        _pubkey: U256,
+        _block_number: u32,
+        _private_commitment: PoseidonHashOut,
+    ) -> Result<Option<ProofWithPublicInputs<F, C, D>>, ServerError> {
+        // the public inputs are not decoded here
+        Ok(None)
+    }
+
+    async fn save_data(
+        &self,
+        data_type: DataType,
+        pubkey: U256,
+        encrypted_data: &[u8],
+    ) -> Result<(), ServerError> {
+        self.insert(data_type, pubkey, encrypted_data.to_vec());
+        Ok(())
+    }
+
+    async fn get_data(
+        &self,
+        data_type: DataType,
+        uuid: &str,
+    ) -> Result<Option<(MetaData, Vec<u8>)>, ServerError> {
+        Ok(self
+            .blobs
+            .borrow()
+            .iter()
+            .find(|blob| blob.data_type == data_type && blob.meta.uuid == uuid)
+            .map(|blob| (blob.meta.clone(), blob.data.clone())))
+    }
+
+    async fn get_data_all_after(
+        &self,
+        data_type: DataType,
+        pubkey: U256,
+        timestamp: u64,
+    ) -> Result<Vec<(MetaData, Vec<u8>)>, ServerError> {
+        Ok(self
+            .blobs
+            .borrow()
+            .iter()
+            .filter(|blob| {
+                blob.data_type == data_type
+                    && blob.pubkey == pubkey
+                    && blob.meta.timestamp > timestamp
+            })
+            .map(|blob| (blob.meta.clone(), blob.data.clone()))
+            .collect())
+    }
+
+    async fn save_user_data(
+        &self,
+        pubkey: U256,
+        encrypted_data: Vec<u8>,
+    ) -> Result<(), ServerError> {
+        let mut user_data = self.user_data.borrow_mut();
+        user_data.retain(|(p, _)| *p != pubkey);
+        user_data.push((pubkey, encrypted_data));
+        Ok(())
+    }
+
+    async fn get_user_data(&self, pubkey: U256) -> Result<Option<Vec<u8>>, ServerError> {
+        Ok(self
+            .user_data
+            .borrow()
+            .iter()
+            .find(|(p, _)| *p == pubkey)
+            .map(|(_, data)| data.clone()))
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    io::{BufRead as _, BufReader, Read as _, Write as _},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRequest {
+    pub path: String,  // without the query string
+    pub query: String, // empty if none
+    pub no_cache: bool,
+    pub content_type: Option<String>,
+    pub accept: Option<String>,
+    pub body: String, // lossy for binary bodies
+    pub body_size: usize,
+}
+
+pub type MockRequests = Arc<Mutex<Vec<MockRequest>>>;
+
+/// Minimal HTTP server for tests. Every request is recorded and answered with
+/// status 200 and the JSON returned by `respond(path, body)`.
+pub fn start_mock_server<R>(respond: R) -> (String, MockRequests)
+where
+    R: Fn(&str, &str) -> String + Send + 'static,
+{
+    start_scripted_mock_server(move |path, body| (200, respond(path, body)))
+}
+
+/// Same as `start_mock_server`, but `respond` also returns the status
+pub fn start_scripted_mock_server<R>(respond: R) -> (String, MockRequests)
+where
+    R: Fn(&str, &str) -> (u16, String) + Send + 'static,
+{
+    start_binary_mock_server(move |request, _| {
+        let (status, body) = respond(&request.path, &request.body);
+        (status, "application/json", body.into_bytes())
+    })
+}
+
+/// Same as `start_scripted_mock_server`, but `respond` gets the raw body and
+/// returns the status, content type and body of the response
+pub fn start_binary_mock_server<R>(respond: R) -> (String, MockRequests)
+where
+    R: Fn(&MockRequest, &[u8]) -> (u16, &'static str, Vec<u8>) + Send + 'static,
+{
+    start_mock_server_with_headers(move |request, body| {
+        let (status, content_type, body) = respond(request, body);
+        (
+            status,
+            vec![("Content-Type", content_type.to_string())],
+            body,
+        )
+    })
+}
+
+/// Same as `start_binary_mock_server`, but `respond` returns the headers of the
+/// response instead of its content type, e.g. to send `Retry-After`
+pub fn start_mock_server_with_headers<R>(respond: R) -> (String, MockRequests)
+where
+    R: Fn(&MockRequest, &[u8]) -> (u16, Vec<(&'static str, String)>, Vec<u8>) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests: MockRequests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let target = request_line.split_whitespace().nth(1).unwrap();
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            let (path, query) = (path.to_string(), query.to_string());
+            let mut no_cache = false;
+            let mut content_type = None;
+            let mut accept = None;
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if line == "cache-control: no-cache" {
+                    no_cache = true;
+                }
+                if let Some(value) = line.strip_prefix("content-type: ") {
+                    content_type = Some(value.to_string());
+                }
+                if let Some(value) = line.strip_prefix("accept: ") {
+                    accept = Some(value.to_string());
+                }
+                if let Some(length) = line.strip_prefix("content-length: ") {
+                    content_length = length.parse().unwrap();
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let request = MockRequest {
+                path,
+                query,
+                no_cache,
+                content_type,
+                accept,
+                body: String::from_utf8_lossy(&body).to_string(),
+                body_size: body.len(),
+            };
+            let (status, headers, response_body) = respond(&request, &body);
+            recorded.lock().unwrap().push(request);
+            write!(stream, "HTTP/1.1 {} Status\r\n", status).unwrap();
+            for (name, value) in headers {
+                write!(stream, "{}: {}\r\n", name, value).unwrap();
+            }
+            write!(
+                stream,
+                "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                response_body.len(),
+            )
+            .unwrap();
+            stream.write_all(&response_body).unwrap();
+        }
+    });
+    (url, requests)
+}
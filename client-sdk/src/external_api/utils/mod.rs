@@ -1,3 +1,9 @@
+pub mod clock;
+#[cfg(test)]
+pub mod in_memory_store_vault;
+#[cfg(test)]
+pub mod mock_server;
 pub mod query;
 pub mod retry;
 pub mod time;
+pub mod url;
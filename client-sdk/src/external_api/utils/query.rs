@@ -1,11 +1,71 @@
-use intmax2_interfaces::api::error::ServerError;
+use std::time::Duration;
+
+use intmax2_interfaces::api::{error::ServerError, store_vault_server::types::WireFormat};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION},
-    Response,
+    header::{
+        HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, DATE,
+        RETRY_AFTER,
+    },
+    Method, RequestBuilder, Response, StatusCode,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use super::retry::with_retry;
+use super::{
+    clock::{global_clock, host_of, parse_http_date},
+    retry::{with_breaker, ResponseOutcome, RetryConfig},
+    url::join_url,
+};
+
+/// Timeout of each attempt of a request, unless the client of the server sets its
+/// own
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// HTTP client of one server. Its connections are reused across requests, and
+/// each attempt of a request fails with a `NetworkError` after `timeout`, so that
+/// an overloaded server cannot hang the caller. A client given by the caller keeps
+/// its own settings instead.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    timeout: Option<Duration>,
+}
+
+impl HttpClient {
+    pub fn new(timeout: Duration) -> Self {
+        HttpClient {
+            client: reqwest::Client::new(),
+            timeout: Some(timeout),
+        }
+    }
+
+    /// Send the requests with `client`, configured by the caller (TLS, proxies,
+    /// timeouts). No timeout is added to its own.
+    pub fn with_client(client: reqwest::Client) -> Self {
+        HttpClient {
+            client,
+            timeout: None,
+        }
+    }
+
+    /// `None` if the timeouts are those of a client given to `with_client`
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT)
+    }
+}
 
 #[derive(Debug, Deserialize)]
 struct ErrorResponse {
@@ -14,15 +74,114 @@ struct ErrorResponse {
     message: Option<String>,
 }
 
+/// A request that gives the same result however many times it is sent, e.g. a
+/// proof request, retried as a GET is. `retry` defaults to the process-wide
+/// `retry_config()`.
 pub async fn post_request<B: Serialize, R: DeserializeOwned>(
+    http: &HttpClient,
     base_url: &str,
     endpoint: &str,
     body: &B,
     bearer_token: Option<String>,
+    retry: Option<&RetryConfig>,
 ) -> Result<R, ServerError> {
-    let url = format!("{}{}", base_url, endpoint);
+    post_request_with_format(
+        http,
+        base_url,
+        endpoint,
+        body,
+        bearer_token,
+        WireFormat::Json,
+        retry,
+    )
+    .await
+}
 
-    let mut headers = HeaderMap::new();
+/// Same as `post_request`, but the body is sent in `format`, which is also asked
+/// for the response. The response is decoded by its `Content-Type`, so a server
+/// answering in JSON anyway is understood.
+pub async fn post_request_with_format<B: Serialize, R: DeserializeOwned>(
+    http: &HttpClient,
+    base_url: &str,
+    endpoint: &str,
+    body: &B,
+    bearer_token: Option<String>,
+    format: WireFormat,
+    retry: Option<&RetryConfig>,
+) -> Result<R, ServerError> {
+    send_post(
+        http,
+        base_url,
+        endpoint,
+        body,
+        bearer_token,
+        format,
+        retry,
+        true,
+    )
+    .await
+}
+
+/// A request with an effect each time it is processed, e.g. submitting a tx or
+/// saving data. It is not sent again after a network error or a 5xx response,
+/// which it may have been processed before, only after a 429.
+pub async fn post_request_once<B: Serialize, R: DeserializeOwned>(
+    http: &HttpClient,
+    base_url: &str,
+    endpoint: &str,
+    body: &B,
+    bearer_token: Option<String>,
+    retry: Option<&RetryConfig>,
+) -> Result<R, ServerError> {
+    post_request_with_format_once(
+        http,
+        base_url,
+        endpoint,
+        body,
+        bearer_token,
+        WireFormat::Json,
+        retry,
+    )
+    .await
+}
+
+/// `post_request_once` with the body in `format`, as `post_request_with_format`
+pub async fn post_request_with_format_once<B: Serialize, R: DeserializeOwned>(
+    http: &HttpClient,
+    base_url: &str,
+    endpoint: &str,
+    body: &B,
+    bearer_token: Option<String>,
+    format: WireFormat,
+    retry: Option<&RetryConfig>,
+) -> Result<R, ServerError> {
+    send_post(
+        http,
+        base_url,
+        endpoint,
+        body,
+        bearer_token,
+        format,
+        retry,
+        false,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn send_post<B: Serialize, R: DeserializeOwned>(
+    http: &HttpClient,
+    base_url: &str,
+    endpoint: &str,
+    body: &B,
+    bearer_token: Option<String>,
+    format: WireFormat,
+    retry: Option<&RetryConfig>,
+    idempotent: bool,
+) -> Result<R, ServerError> {
+    let url = join_url(base_url, endpoint)?;
+
+    let mut headers = format_headers(format);
     if let Some(token) = bearer_token {
         headers.insert(
             AUTHORIZATION,
@@ -30,33 +189,98 @@ pub async fn post_request<B: Serialize, R: DeserializeOwned>(
                 .map_err(|e| ServerError::SerializeError(format!("Failed to set header: {}", e)))?,
         );
     }
-    let client = reqwest::Client::new();
-    let response = with_retry(|| async {
-        client
-            .post(&url)
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    let encoded = format.encode(body)?;
+    let response = with_breaker(&host_of(&url), retry, idempotent, || async {
+        let response = http
+            .request(Method::POST, &url)
             .headers(headers.clone())
-            .json(body)
+            .body(encoded.clone())
             .send()
-            .await
+            .await;
+        counted(response)
     })
-    .await
-    .map_err(|e| ServerError::NetworkError(e.to_string()))?;
-    let body_str = serde_json::to_string(body)
-        .map_err(|e| ServerError::SerializeError(format!("Failed to serialize body: {}", e)))?;
+    .await?;
+    let body_str = match format {
+        WireFormat::Json => String::from_utf8_lossy(&encoded).to_string(),
+        WireFormat::Bincode => format!("<{} bytes of bincode>", encoded.len()),
+    };
     handle_response(response, &url, &Some(body_str)).await
 }
 
+/// `retry` defaults to the process-wide `retry_config()`
 pub async fn get_request<Q, R>(
+    http: &HttpClient,
     base_url: &str,
     endpoint: &str,
     query: Option<Q>,
     bearer_token: Option<String>,
+    retry: Option<&RetryConfig>,
 ) -> Result<R, ServerError>
 where
     Q: Serialize,
     R: DeserializeOwned,
 {
-    let mut url = format!("{}{}", base_url, endpoint);
+    get_request_with_format(
+        http,
+        base_url,
+        endpoint,
+        query,
+        bearer_token,
+        false,
+        WireFormat::Json,
+        retry,
+    )
+    .await
+}
+
+/// Same as `get_request`, but asks caches in between (e.g. a CDN) for a fresh
+/// response. Used for data that changes over time.
+pub async fn get_request_no_cache<Q, R>(
+    http: &HttpClient,
+    base_url: &str,
+    endpoint: &str,
+    query: Option<Q>,
+    bearer_token: Option<String>,
+    retry: Option<&RetryConfig>,
+) -> Result<R, ServerError>
+where
+    Q: Serialize,
+    R: DeserializeOwned,
+{
+    get_request_with_format(
+        http,
+        base_url,
+        endpoint,
+        query,
+        bearer_token,
+        true,
+        WireFormat::Json,
+        retry,
+    )
+    .await
+}
+
+/// `get_request`, or `get_request_no_cache` if `no_cache`, asking for the response
+/// in `format`
+pub async fn get_request_with_format<Q, R>(
+    http: &HttpClient,
+    base_url: &str,
+    endpoint: &str,
+    query: Option<Q>,
+    bearer_token: Option<String>,
+    no_cache: bool,
+    format: WireFormat,
+    retry: Option<&RetryConfig>,
+) -> Result<R, ServerError>
+where
+    Q: Serialize,
+    R: DeserializeOwned,
+{
+    let mut url = join_url(base_url, endpoint)?;
     let query_str = query
         .as_ref()
         .map(|q| {
@@ -68,8 +292,7 @@ where
     if query_str.is_some() {
         url = format!("{}?{}", url, query_str.as_ref().unwrap());
     }
-    let client = reqwest::Client::new();
-    let mut headers = HeaderMap::new();
+    let mut headers = format_headers(format);
     if let Some(token) = bearer_token {
         headers.insert(
             AUTHORIZATION,
@@ -77,18 +300,96 @@ where
                 .map_err(|e| ServerError::SerializeError(format!("Failed to set header: {}", e)))?,
         );
     }
-    let response = with_retry(|| async { client.get(&url).headers(headers.clone()).send().await })
-        .await
-        .map_err(|e| ServerError::NetworkError(e.to_string()))?;
+    if no_cache {
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
+    let response = with_breaker(&host_of(&url), retry, true, || async {
+        let request = http.request(Method::GET, &url);
+        counted(request.headers(headers.clone()).send().await)
+    })
+    .await?;
 
     handle_response(response, &url, &query_str).await
 }
 
+/// Deleting twice leaves the same state, so the request is retried. `retry`
+/// defaults to the process-wide `retry_config()`
+pub async fn delete_request<B: Serialize, R: DeserializeOwned>(
+    http: &HttpClient,
+    base_url: &str,
+    endpoint: &str,
+    body: &B,
+    retry: Option<&RetryConfig>,
+) -> Result<R, ServerError> {
+    let url = join_url(base_url, endpoint)?;
+    let encoded = WireFormat::Json.encode(body)?;
+    let response = with_breaker(&host_of(&url), retry, true, || async {
+        let response = http
+            .request(Method::DELETE, &url)
+            .header(CONTENT_TYPE, WireFormat::Json.content_type())
+            .body(encoded.clone())
+            .send()
+            .await;
+        counted(response)
+    })
+    .await?;
+    let body_str = String::from_utf8_lossy(&encoded).to_string();
+    handle_response(response, &url, &Some(body_str)).await
+}
+
+// JSON requests are sent as before, without an `Accept` header
+fn format_headers(format: WireFormat) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if format != WireFormat::Json {
+        headers.insert(ACCEPT, HeaderValue::from_static(format.content_type()));
+    }
+    headers
+}
+
+// a 5xx or 429 response is sent again, and a 5xx counts as a failure of the host
+// for its circuit breaker, unless it asks to be retried later. A 4xx is final.
+fn counted(
+    response: Result<Response, reqwest::Error>,
+) -> Result<(Response, ResponseOutcome), ServerError> {
+    let response = response.map_err(|e| ServerError::NetworkError(e.to_string()))?;
+    let outcome = ResponseOutcome::of_status(response.status().as_u16(), retry_after_ms(&response));
+    Ok((response, outcome))
+}
+
+// `Retry-After` of a 503 or 429 response, in seconds or as an HTTP date
+fn retry_after_ms(response: &Response) -> Option<u64> {
+    let status = response.status();
+    if status != StatusCode::SERVICE_UNAVAILABLE && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    let secs = match value.trim().parse::<u64>() {
+        Ok(secs) => secs,
+        Err(_) => parse_http_date(value)?
+            .saturating_sub(global_clock().server_now_for(response.url().as_str())),
+    };
+    Some(secs.max(1) * 1000)
+}
+
+// measure the offset of the server's clock from its `Date` header
+fn record_server_time(response: &Response) {
+    let server_time = response
+        .headers()
+        .get(DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(parse_http_date);
+    if let Some(server_time) = server_time {
+        let clock = global_clock();
+        clock.record(response.url().as_str(), server_time, clock.local_now());
+    }
+}
+
 async fn handle_response<R: DeserializeOwned>(
     response: Response,
     url: &str,
     request_str: &Option<String>,
 ) -> Result<R, ServerError> {
+    record_server_time(&response);
     let status = response.status();
     if !status.is_success() {
         let error_text = response
@@ -110,8 +411,281 @@ async fn handle_response<R: DeserializeOwned>(
             abr_request,
         ));
     }
-    response
-        .json::<R>()
+    let format = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(WireFormat::from_content_type)
+        .unwrap_or(WireFormat::Json);
+    let body = response
+        .bytes()
         .await
-        .map_err(|e| ServerError::DeserializationError(e.to_string()))
+        .map_err(|e| ServerError::DeserializationError(e.to_string()))?;
+    format.decode(&body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::external_api::utils::mock_server::{
+        start_mock_server_with_headers, start_scripted_mock_server, MockRequests,
+    };
+
+    // Answers 503 with `Retry-After: 1` to the first `warming_up` requests, like a
+    // prover still building its circuits
+    fn start_warming_up_server(warming_up: usize) -> (String, MockRequests) {
+        let answered = AtomicUsize::new(0);
+        start_mock_server_with_headers(move |_, _| {
+            if answered.fetch_add(1, Ordering::SeqCst) < warming_up {
+                (503, vec![("Retry-After", "1".to_string())], b"{}".to_vec())
+            } else {
+                (
+                    200,
+                    vec![("Content-Type", "application/json".to_string())],
+                    b"42".to_vec(),
+                )
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_retry_after() {
+        let (url, requests) = start_warming_up_server(2);
+        let started = Instant::now();
+        let result: u32 = post_request(&HttpClient::default(), &url, "/prove", &(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(result, 42);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        assert!(started.elapsed() >= Duration::from_secs(2));
+    }
+
+    // Answers 503 to the first `failing` requests, and records when each request
+    // arrived
+    fn start_flaky_server(failing: usize) -> (String, Arc<Mutex<Vec<Instant>>>) {
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+        let recorded = arrivals.clone();
+        let (url, _) = start_scripted_mock_server(move |_, _| {
+            let mut arrivals = recorded.lock().unwrap();
+            arrivals.push(Instant::now());
+            if arrivals.len() > failing {
+                (200, "42".to_string())
+            } else {
+                (503, r#"{"error":"unavailable"}"#.to_string())
+            }
+        });
+        (url, arrivals)
+    }
+
+    #[tokio::test]
+    async fn test_retry_config_of_request() {
+        let retry = RetryConfig {
+            max_retries: 2,
+            initial_delay_ms: 100,
+            backoff_factor: 3.0,
+            max_delay_ms: 1000,
+            ..Default::default()
+        };
+        let (url, arrivals) = start_flaky_server(2);
+        let started = Instant::now();
+        let result: u32 = get_request::<(), _>(
+            &HttpClient::default(),
+            &url,
+            "/status",
+            None,
+            None,
+            Some(&retry),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, 42);
+        let arrivals = arrivals.lock().unwrap().clone();
+        assert_eq!(arrivals.len(), 3);
+        // each retry waits at most its backoff, 100 then 300 ms, plus some slack
+        let slack = Duration::from_millis(200);
+        assert!(arrivals[0] >= started);
+        assert!(arrivals[1] - arrivals[0] <= Duration::from_millis(100) + slack);
+        assert!(arrivals[2] - arrivals[1] <= Duration::from_millis(300) + slack);
+
+        // fewer retries than failures
+        let retry = RetryConfig {
+            max_retries: 1,
+            ..retry
+        };
+        let (url, arrivals) = start_flaky_server(2);
+        let result = post_request::<_, u32>(
+            &HttpClient::default(),
+            &url,
+            "/prove",
+            &(),
+            None,
+            Some(&retry),
+        )
+        .await;
+        assert!(matches!(result, Err(ServerError::ServerError(503, ..))));
+        assert_eq!(arrivals.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_of_client() {
+        // accepts connections but never answers, like an overloaded prover
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let _streams = listener.incoming().collect::<Vec<_>>();
+        });
+        let retry = RetryConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        let http = HttpClient::new(Duration::from_millis(200));
+        let started = Instant::now();
+        let result = post_request::<_, u32>(&http, &url, "/prove", &(), None, Some(&retry)).await;
+        assert!(matches!(result, Err(ServerError::NetworkError(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_of_injected_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let _streams = listener.incoming().collect::<Vec<_>>();
+        });
+        let retry = RetryConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let http = HttpClient::with_client(client);
+        assert_eq!(http.timeout(), None);
+        let started = Instant::now();
+        let result = get_request::<(), u32>(&http, &url, "/status", None, None, Some(&retry)).await;
+        assert!(matches!(result, Err(ServerError::NetworkError(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_retries_only_transient_statuses() {
+        let retry = RetryConfig {
+            max_retries: 2,
+            initial_delay_ms: 10,
+            jitter: false,
+            ..Default::default()
+        };
+        let respond = |status: u16| {
+            start_scripted_mock_server(move |_, _| (status, r#"{"error":"no"}"#.to_string()))
+        };
+
+        // a bad request would fail again
+        let (url, requests) = respond(400);
+        let result = get_request::<(), u32>(
+            &HttpClient::default(),
+            &url,
+            "/status",
+            None,
+            None,
+            Some(&retry),
+        )
+        .await;
+        assert!(matches!(result, Err(ServerError::ServerError(400, ..))));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        for status in [503, 429] {
+            let (url, requests) = respond(status);
+            let result = post_request::<_, u32>(
+                &HttpClient::default(),
+                &url,
+                "/prove",
+                &(),
+                None,
+                Some(&retry),
+            )
+            .await;
+            assert!(matches!(result, Err(ServerError::ServerError(s, ..)) if s == status));
+            assert_eq!(requests.lock().unwrap().len(), 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_with_effect_is_sent_once() {
+        let retry = RetryConfig {
+            max_retries: 2,
+            initial_delay_ms: 10,
+            jitter: false,
+            ..Default::default()
+        };
+        let respond = |status: u16| {
+            start_scripted_mock_server(move |_, _| (status, r#"{"error":"no"}"#.to_string()))
+        };
+
+        // the tx may have been submitted before the server failed
+        let (url, requests) = respond(502);
+        let result = post_request_once::<_, u32>(
+            &HttpClient::default(),
+            &url,
+            "/block-builder/tx-request",
+            &(),
+            None,
+            Some(&retry),
+        )
+        .await;
+        assert!(matches!(result, Err(ServerError::ServerError(502, ..))));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        // the connection is closed without an answer
+        let (url, arrivals) = start_flaky_server(1);
+        let result = post_request_once::<_, u32>(
+            &HttpClient::default(),
+            &url,
+            "/withdrawal-server/request-withdrawal",
+            &(),
+            None,
+            Some(&retry),
+        )
+        .await;
+        assert!(matches!(result, Err(ServerError::NetworkError(_))));
+        assert_eq!(arrivals.lock().unwrap().len(), 1);
+
+        // a 429 turns the request down unprocessed
+        let (url, requests) = respond(429);
+        let result = post_request_with_format_once::<_, u32>(
+            &HttpClient::default(),
+            &url,
+            "/store-vault-server/transfer/save",
+            &(),
+            None,
+            WireFormat::Json,
+            Some(&retry),
+        )
+        .await;
+        assert!(matches!(result, Err(ServerError::ServerError(429, ..))));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        // a 503 asking to come back later may come from a proxy after forwarding
+        let (url, requests) = start_warming_up_server(1);
+        let result = post_request_once::<_, u32>(
+            &HttpClient::default(),
+            &url,
+            "/sponsor/fee-voucher",
+            &(),
+            None,
+            Some(&retry),
+        )
+        .await;
+        assert!(matches!(result, Err(ServerError::ServerError(503, ..))));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
 }
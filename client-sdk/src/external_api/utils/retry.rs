@@ -1,38 +1,600 @@
-use std::{future::Future, time::Duration};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, OnceLock, RwLock},
+};
 
+use intmax2_interfaces::api::error::ServerError;
 use log::warn;
+use rand::Rng as _;
+use serde::{Deserialize, Serialize};
 
-use crate::external_api::utils::time::sleep_for;
+use crate::external_api::utils::time::sleep_ms;
 
-const MAX_RETRIES: u32 = 5;
-const INITIAL_DELAY: u64 = 1000;
+/// Retry and circuit breaker settings, shared by every request of the process
+/// unless a client is given its own
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryConfig {
+    /// Retries after the first attempt, i.e. a request is sent up to
+    /// `max_retries + 1` times
+    pub max_retries: u32,
+    /// Cap of the first backoff delay, multiplied by `backoff_factor` after each
+    /// attempt
+    #[serde(alias = "baseDelayMs")]
+    pub initial_delay_ms: u64,
+    #[serde(default = "default_backoff_factor")]
+    pub backoff_factor: f64,
+    pub max_delay_ms: u64,
+    /// Wait a random time up to the backoff instead of the whole backoff. Without
+    /// it, clients failing together retry together.
+    #[serde(default = "default_jitter")]
+    pub jitter: bool,
+    /// Consecutive failures of a host after which its circuit opens
+    pub failure_threshold: u32,
+    /// Time an open circuit fails fast before letting a probe request through
+    pub cool_down_ms: u64,
+    /// Total time a request waits for a server that answers with `Retry-After`,
+    /// e.g. a prover still building its circuits
+    #[serde(default = "default_max_retry_after_ms")]
+    pub max_retry_after_ms: u64,
+}
+
+fn default_backoff_factor() -> f64 {
+    2.0
+}
+
+fn default_jitter() -> bool {
+    true
+}
+
+fn default_max_retry_after_ms() -> u64 {
+    10 * 60 * 1000
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_delay_ms: 1000,
+            backoff_factor: default_backoff_factor(),
+            max_delay_ms: 30_000,
+            jitter: default_jitter(),
+            failure_threshold: 5,
+            cool_down_ms: 30_000,
+            max_retry_after_ms: default_max_retry_after_ms(),
+        }
+    }
+}
+
+static RETRY_CONFIG: RwLock<Option<RetryConfig>> = RwLock::new(None);
+
+pub fn retry_config() -> RetryConfig {
+    RETRY_CONFIG.read().unwrap().clone().unwrap_or_default()
+}
+
+pub fn set_retry_config(config: RetryConfig) {
+    *RETRY_CONFIG.write().unwrap() = Some(config);
+}
+
+// the exponential backoff after `attempt` failed attempts. A factor below 1 would
+// shrink the delays, and is taken as 1.
+fn backoff_cap_ms(config: &RetryConfig, attempt: u32) -> u64 {
+    let cap = config.initial_delay_ms as f64
+        * config.backoff_factor.max(1.0).powi(attempt.min(64) as i32);
+    cap.min(config.max_delay_ms as f64) as u64
+}
+
+// Full jitter: a uniform delay up to the exponential backoff, so that clients
+// failing at the same time do not retry at the same time
+fn backoff_delay_ms(config: &RetryConfig, attempt: u32) -> u64 {
+    let cap = backoff_cap_ms(config, attempt);
+    if config.jitter {
+        rand::thread_rng().gen_range(0..=cap)
+    } else {
+        cap
+    }
+}
 
 pub async fn with_retry<'a, T, E, F, Fut>(f: F) -> Result<T, E>
 where
     E: std::error::Error,
     F: Fn() -> Fut,
     Fut: Future<Output = Result<T, E>> + 'a,
+{
+    retry_with(&retry_config(), f, |_| true).await
+}
+
+// errors for which `should_retry` is false are returned right away
+async fn retry_with<'a, T, E, F, Fut, P>(
+    config: &RetryConfig,
+    f: F,
+    should_retry: P,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, E>> + 'a,
+    P: Fn(&E) -> bool,
 {
     let mut retries = 0;
-    let mut delay = Duration::from_millis(INITIAL_DELAY);
 
     loop {
         match f().await {
             Ok(result) => return Ok(result),
             Err(e) => {
-                if retries >= MAX_RETRIES {
+                if retries >= config.max_retries || !should_retry(&e) {
                     return Err(e);
                 }
+                let delay = backoff_delay_ms(config, retries);
                 warn!(
-                    "Attempt {} failed: {}. Retrying in {:?}...",
+                    "Attempt {} failed: {}. Retrying in {} ms...",
                     retries + 1,
                     e.to_string(),
                     delay
                 );
-                sleep_for(delay.as_secs()).await;
+                sleep_ms(delay).await;
                 retries += 1;
-                delay *= 2; // Exponential backoff
             }
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BreakerState {
+    /// Requests go through
+    Closed,
+    /// Requests fail fast until the cool-down ends
+    Open,
+    /// A single probe request is let through, which closes the circuit if it
+    /// succeeds and opens it again otherwise
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostBreakerStatus {
+    pub host: String,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// Milliseconds until an open circuit lets a probe through
+    pub retry_after_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct HostBreaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    open_until_ms: u64,
+}
+
+impl Default for HostBreaker {
+    fn default() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            open_until_ms: 0,
+        }
+    }
+}
+
+static GLOBAL_BREAKERS: OnceLock<CircuitBreakers> = OnceLock::new();
+
+/// The circuit breakers of the HTTP requests of this process
+pub fn global_breakers() -> &'static CircuitBreakers {
+    GLOBAL_BREAKERS.get_or_init(CircuitBreakers::new)
+}
+
+fn now_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64
+}
+
+/// Per-host circuit breakers. A host that keeps failing is not sent requests for
+/// `RetryConfig::cool_down_ms`, which keeps clients from hammering a server that
+/// is recovering.
+#[derive(Debug, Default)]
+pub struct CircuitBreakers {
+    hosts: Mutex<HashMap<String, HostBreaker>>,
+}
+
+impl CircuitBreakers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check whether a request to `host` may be sent at `now_ms`. When the
+    /// cool-down of an open circuit has ended, the caller sends the probe.
+    pub fn acquire(
+        &self,
+        host: &str,
+        now_ms: u64,
+        config: &RetryConfig,
+    ) -> Result<(), ServerError> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let breaker = hosts.entry(host.to_string()).or_default();
+        match breaker.state {
+            BreakerState::Closed => Ok(()),
+            BreakerState::Open if now_ms >= breaker.open_until_ms => {
+                breaker.state = BreakerState::HalfOpen;
+                Ok(())
+            }
+            // the probe was dropped without a result, e.g. by a cancelled future
+            BreakerState::HalfOpen if now_ms >= breaker.open_until_ms + config.cool_down_ms => {
+                breaker.open_until_ms = now_ms;
+                Ok(())
+            }
+            // the probe is in flight
+            BreakerState::Open | BreakerState::HalfOpen => Err(ServerError::CircuitOpen {
+                host: host.to_string(),
+                retry_after: breaker.open_until_ms.saturating_sub(now_ms).div_ceil(1000),
+            }),
+        }
+    }
+
+    pub fn record_success(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let breaker = hosts.entry(host.to_string()).or_default();
+        if breaker.state != BreakerState::Closed {
+            log::info!("Circuit of {} closed", host);
+        }
+        *breaker = HostBreaker::default();
+    }
+
+    pub fn record_failure(&self, host: &str, now_ms: u64, config: &RetryConfig) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let breaker = hosts.entry(host.to_string()).or_default();
+        breaker.consecutive_failures += 1;
+        if breaker.state == BreakerState::HalfOpen
+            || breaker.consecutive_failures >= config.failure_threshold
+        {
+            if breaker.state != BreakerState::Open {
+                warn!(
+                    "Circuit of {} opened after {} consecutive failures",
+                    host, breaker.consecutive_failures
+                );
+            }
+            breaker.state = BreakerState::Open;
+            breaker.open_until_ms = now_ms + config.cool_down_ms;
+        }
+    }
+
+    pub fn states(&self) -> Vec<HostBreakerStatus> {
+        let now_ms = now_ms();
+        let mut states = self
+            .hosts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(host, breaker)| HostBreakerStatus {
+                host: host.clone(),
+                state: breaker.state,
+                consecutive_failures: breaker.consecutive_failures,
+                retry_after_ms: (breaker.state == BreakerState::Open)
+                    .then(|| breaker.open_until_ms.saturating_sub(now_ms)),
+            })
+            .collect::<Vec<_>>();
+        states.sort_by(|a, b| a.host.cmp(&b.host));
+        states
+    }
+}
+
+/// How a response counts for the retries and the circuit breaker of its host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseOutcome {
+    /// Includes the 4xx responses, which would fail again and are not retried
+    Success,
+    /// A 5xx or 429 response, sent again after a backoff. Only a 5xx counts as a
+    /// failure of the host.
+    Failure { status: u16 },
+    /// The server asks to come back after `delay_ms`, e.g. a 503 with
+    /// `Retry-After` while it warms up. The request is sent again after the delay,
+    /// for up to `RetryConfig::max_retry_after_ms` in total.
+    RetryAfter { status: u16, delay_ms: u64 },
+}
+
+impl ResponseOutcome {
+    /// The outcome of a response of `status`, which asks to come back after
+    /// `retry_after_ms` if given
+    pub fn of_status(status: u16, retry_after_ms: Option<u64>) -> Self {
+        match retry_after_ms {
+            Some(delay_ms) => ResponseOutcome::RetryAfter { status, delay_ms },
+            None if status >= 500 || status == 429 => ResponseOutcome::Failure { status },
+            None => ResponseOutcome::Success,
+        }
+    }
+}
+
+// why an attempt is sent again: an error, or a response worth retrying, which is
+// returned as is once no retry is left
+enum AttemptError<T> {
+    Error(ServerError),
+    Retryable { response: T, status: u16 },
+}
+
+impl<T> std::fmt::Display for AttemptError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttemptError::Error(e) => write!(f, "{}", e),
+            AttemptError::Retryable { status, .. } => write!(f, "status {}", status),
+        }
+    }
+}
+
+/// Send a request to `host` with retries, through the circuit breaker of the host.
+/// `send` returns how the response counts for the host along with it. Network
+/// errors and the 5xx and 429 responses of an `idempotent` request are retried;
+/// other responses are returned after one attempt. A request that is not
+/// idempotent, e.g. one that submits a tx, may have been processed when it fails,
+/// so it is only sent again when the server answers 429, which turns it down
+/// unprocessed. `config` defaults to the process-wide `retry_config()`.
+pub async fn with_breaker<T, F, Fut>(
+    host: &str,
+    config: Option<&RetryConfig>,
+    idempotent: bool,
+    send: F,
+) -> Result<T, ServerError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<(T, ResponseOutcome), ServerError>>,
+{
+    match config {
+        Some(config) => send_through(global_breakers(), config, host, idempotent, send).await,
+        None => send_through(global_breakers(), &retry_config(), host, idempotent, send).await,
+    }
+}
+
+async fn send_through<T, F, Fut>(
+    breakers: &CircuitBreakers,
+    config: &RetryConfig,
+    host: &str,
+    idempotent: bool,
+    send: F,
+) -> Result<T, ServerError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<(T, ResponseOutcome), ServerError>>,
+{
+    let mut waited_ms = 0;
+    loop {
+        let result = retry_with(
+            config,
+            || async {
+                breakers
+                    .acquire(host, now_ms(), config)
+                    .map_err(AttemptError::Error)?;
+                match send().await {
+                    Ok((response, ResponseOutcome::Success)) => {
+                        breakers.record_success(host);
+                        Ok((response, None))
+                    }
+                    // the server is up, and only asks for time
+                    Ok((response, ResponseOutcome::RetryAfter { status, delay_ms })) => {
+                        breakers.record_success(host);
+                        Ok((response, Some((status, delay_ms))))
+                    }
+                    Ok((response, ResponseOutcome::Failure { status })) => {
+                        if status >= 500 {
+                            breakers.record_failure(host, now_ms(), config);
+                        } else {
+                            breakers.record_success(host);
+                        }
+                        Err(AttemptError::Retryable { response, status })
+                    }
+                    Err(e) => {
+                        breakers.record_failure(host, now_ms(), config);
+                        Err(AttemptError::Error(e))
+                    }
+                }
+            },
+            |e| match e {
+                // retrying within the cool-down would fail again
+                AttemptError::Error(ServerError::CircuitOpen { .. }) => false,
+                AttemptError::Retryable { status: 429, .. } => true,
+                _ => idempotent,
+            },
+        )
+        .await;
+        let (response, retry_after) = match result {
+            Ok(result) => result,
+            Err(AttemptError::Retryable { response, .. }) => return Ok(response),
+            Err(AttemptError::Error(e)) => return Err(e),
+        };
+        match retry_after {
+            Some((status, delay_ms))
+                if (idempotent || status == 429)
+                    && waited_ms + delay_ms <= config.max_retry_after_ms =>
+            {
+                log::info!("{} is not ready, retrying in {} ms", host, delay_ms);
+                sleep_ms(delay_ms).await;
+                waited_ms += delay_ms;
+            }
+            _ => return Ok(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_interfaces::api::error::ServerError;
+
+    use crate::external_api::utils::mock_server::start_scripted_mock_server;
+
+    use super::{
+        backoff_cap_ms, backoff_delay_ms, send_through, BreakerState, CircuitBreakers,
+        ResponseOutcome, RetryConfig,
+    };
+
+    fn config() -> RetryConfig {
+        RetryConfig {
+            failure_threshold: 2,
+            cool_down_ms: 1000,
+            ..Default::default()
+        }
+    }
+
+    fn state(breakers: &CircuitBreakers, host: &str) -> BreakerState {
+        breakers
+            .states()
+            .into_iter()
+            .find(|status| status.host == host)
+            .unwrap()
+            .state
+    }
+
+    #[test]
+    fn test_breaker_transitions() {
+        let breakers = CircuitBreakers::new();
+        let config = config();
+        let host = "store-vault:443";
+
+        assert!(breakers.acquire(host, 0, &config).is_ok());
+        breakers.record_failure(host, 0, &config);
+        assert_eq!(state(&breakers, host), BreakerState::Closed);
+        breakers.record_failure(host, 10, &config);
+        assert_eq!(state(&breakers, host), BreakerState::Open);
+
+        match breakers.acquire(host, 500, &config) {
+            Err(ServerError::CircuitOpen { retry_after, .. }) => assert_eq!(retry_after, 1),
+            other => panic!("expected an open circuit, got {:?}", other),
+        }
+        // other hosts are not affected
+        assert!(breakers
+            .acquire("validity-prover:443", 500, &config)
+            .is_ok());
+
+        // a single probe after the cool-down, which fails
+        assert!(breakers.acquire(host, 1010, &config).is_ok());
+        assert_eq!(state(&breakers, host), BreakerState::HalfOpen);
+        assert!(breakers.acquire(host, 1010, &config).is_err());
+        breakers.record_failure(host, 1020, &config);
+        assert_eq!(state(&breakers, host), BreakerState::Open);
+        assert!(breakers.acquire(host, 1500, &config).is_err());
+
+        // the next probe succeeds
+        assert!(breakers.acquire(host, 2020, &config).is_ok());
+        breakers.record_success(host);
+        assert_eq!(state(&breakers, host), BreakerState::Closed);
+        assert!(breakers.acquire(host, 2020, &config).is_ok());
+    }
+
+    #[test]
+    fn test_backoff_is_jittered_and_capped() {
+        let config = RetryConfig::default();
+        for attempt in 0..40 {
+            let cap = (1000u64 << attempt.min(32)).min(config.max_delay_ms);
+            assert!(backoff_delay_ms(&config, attempt) <= cap);
+        }
+        let delays = (0..20)
+            .map(|_| backoff_delay_ms(&config, 3))
+            .collect::<std::collections::HashSet<_>>();
+        assert!(delays.len() > 1);
+    }
+
+    #[test]
+    fn test_backoff_factor() {
+        let config = RetryConfig {
+            initial_delay_ms: 100,
+            backoff_factor: 3.0,
+            max_delay_ms: 2000,
+            ..Default::default()
+        };
+        let caps = (0..5)
+            .map(|attempt| backoff_cap_ms(&config, attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(caps, vec![100, 300, 900, 2000, 2000]);
+
+        // a config saved before the factor existed keeps doubling
+        let json = r#"{"maxRetries":3,"initialDelayMs":100,"maxDelayMs":2000,"failureThreshold":5,"coolDownMs":1000}"#;
+        let config: RetryConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(backoff_cap_ms(&config, 2), 400);
+        assert!(config.jitter);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let config = RetryConfig {
+            initial_delay_ms: 100,
+            max_delay_ms: 1000,
+            jitter: false,
+            ..Default::default()
+        };
+        let delays = (0..5)
+            .map(|attempt| backoff_delay_ms(&config, attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000]);
+
+        let config = RetryConfig {
+            jitter: true,
+            ..config
+        };
+        assert!((0..100).all(|_| backoff_delay_ms(&config, 3) <= 800));
+
+        // `baseDelayMs` is read as the initial delay
+        let json = r#"{"maxRetries":2,"baseDelayMs":250,"maxDelayMs":2000,"jitter":false,"failureThreshold":5,"coolDownMs":1000}"#;
+        let config: RetryConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.initial_delay_ms, 250);
+        assert_eq!(backoff_delay_ms(&config, 1), 500);
+    }
+
+    #[tokio::test]
+    async fn test_no_requests_while_open() {
+        // two failures, then the server recovers
+        let (url, requests) = start_scripted_mock_server(|_, _| {
+            static CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+            match CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 | 1 => (500, "{}".to_string()),
+                _ => (200, "{}".to_string()),
+            }
+        });
+        let breakers = CircuitBreakers::new();
+        let config = RetryConfig {
+            max_retries: 0,
+            failure_threshold: 2,
+            cool_down_ms: 300,
+            ..Default::default()
+        };
+        let client = reqwest::Client::new();
+        let get = || async {
+            let response = client
+                .get(format!("{}/health", url))
+                .send()
+                .await
+                .map_err(|e| ServerError::NetworkError(e.to_string()))?;
+            let status = response.status().as_u16();
+            Ok((status, ResponseOutcome::of_status(status, None)))
+        };
+        let host = "mock";
+
+        assert_eq!(
+            send_through(&breakers, &config, host, true, get)
+                .await
+                .unwrap(),
+            500
+        );
+        assert_eq!(state(&breakers, host), BreakerState::Closed);
+        assert_eq!(
+            send_through(&breakers, &config, host, true, get)
+                .await
+                .unwrap(),
+            500
+        );
+        assert_eq!(state(&breakers, host), BreakerState::Open);
+
+        for _ in 0..3 {
+            let result = send_through(&breakers, &config, host, true, get).await;
+            assert!(matches!(result, Err(ServerError::CircuitOpen { .. })));
+        }
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        tokio::time::sleep(std::time::Duration::from_millis(350)).await;
+        assert_eq!(
+            send_through(&breakers, &config, host, true, get)
+                .await
+                .unwrap(),
+            200
+        );
+        assert_eq!(state(&breakers, host), BreakerState::Closed);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+}
@@ -6,10 +6,12 @@ use std::time::Duration;
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::time::{sleep, Duration};
 
+use super::clock::global_clock;
+
 /// Wait until the specified timestamp
 async fn sleep_until(target: u64) {
     loop {
-        let now = chrono::Utc::now().timestamp() as u64;
+        let now = global_clock().local_now();
         if now >= target {
             break;
         }
@@ -20,6 +22,12 @@ async fn sleep_until(target: u64) {
 
 /// Sleep function that works correctly even when PC is in sleep mode
 pub async fn sleep_for(seconds: u64) {
-    let target = chrono::Utc::now().timestamp() as u64 + seconds;
+    let target = global_clock().local_now() + seconds;
     sleep_until(target).await;
 }
+
+/// Sleep for a short duration. Unlike `sleep_for`, it is not corrected for the
+/// time the machine is suspended.
+pub async fn sleep_ms(ms: u64) {
+    sleep(Duration::from_millis(ms)).await;
+}
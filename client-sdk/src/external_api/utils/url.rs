@@ -0,0 +1,110 @@
+use intmax2_interfaces::api::error::ServerError;
+use url::Url;
+
+/// A configured base URL that cannot be used, with the name of its setting
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("invalid {field} {url:?}: {reason}")]
+pub struct InvalidUrl {
+    pub field: String,
+    pub url: String,
+    pub reason: String,
+}
+
+/// Check that `url`, the value of the setting `field`, is an http(s) URL usable as
+/// a base of the endpoints, and return it without trailing slashes. A path is
+/// kept as the prefix of every endpoint, e.g. `https://api.example.com/intmax`.
+pub fn normalize_base_url(field: &str, url: &str) -> Result<String, InvalidUrl> {
+    let invalid = |reason: String| InvalidUrl {
+        field: field.to_string(),
+        url: url.to_string(),
+        reason,
+    };
+    let trimmed = url.trim();
+    if !trimmed.contains("://") {
+        return Err(invalid("missing scheme, e.g. https://".to_string()));
+    }
+    let parsed = Url::parse(trimmed).map_err(|e| invalid(e.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(invalid(format!(
+            "scheme {} is not http or https",
+            parsed.scheme()
+        )));
+    }
+    if parsed.host().is_none() {
+        return Err(invalid("missing host".to_string()));
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err(invalid(
+            "a base URL cannot have a query or fragment".to_string(),
+        ));
+    }
+    Ok(parsed.as_str().trim_end_matches('/').to_string())
+}
+
+/// Join `endpoint` to `base_url`, keeping the path of the base as a prefix.
+/// Unlike a plain `Url::join`, a leading slash of the endpoint does not replace
+/// the base path.
+pub(crate) fn join_url(base_url: &str, endpoint: &str) -> Result<String, ServerError> {
+    let mut base = Url::parse(base_url.trim())
+        .map_err(|e| ServerError::InvalidUrl(format!("{}: {}", base_url, e)))?;
+    if !base.path().ends_with('/') {
+        let path = format!("{}/", base.path());
+        base.set_path(&path);
+    }
+    let url = base
+        .join(endpoint.trim_start_matches('/'))
+        .map_err(|e| ServerError::InvalidUrl(format!("{}{}: {}", base_url, endpoint, e)))?;
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{join_url, normalize_base_url};
+
+    #[test]
+    fn test_normalize_base_url() {
+        let normalize = |url| normalize_base_url("store_vault_server_url", url);
+        assert_eq!(
+            normalize("https://api.example.com/").unwrap(),
+            "https://api.example.com"
+        );
+        assert_eq!(
+            normalize("https://api.example.com/intmax//").unwrap(),
+            "https://api.example.com/intmax"
+        );
+        assert_eq!(
+            normalize("http://[::1]:9000/").unwrap(),
+            "http://[::1]:9000"
+        );
+
+        let error = normalize("api.example.com").unwrap_err();
+        assert_eq!(error.field, "store_vault_server_url");
+        assert!(error.to_string().contains("store_vault_server_url"));
+        assert!(normalize("ftp://api.example.com").is_err());
+        assert!(normalize("https://api.example.com?key=1").is_err());
+        assert!(normalize("https://").is_err());
+    }
+
+    #[test]
+    fn test_join_url() {
+        assert_eq!(
+            join_url("https://api.example.com", "/store-vault-server/get").unwrap(),
+            "https://api.example.com/store-vault-server/get"
+        );
+        // a trailing slash does not produce `//`
+        assert_eq!(
+            join_url("https://api.example.com/", "/store-vault-server/get").unwrap(),
+            "https://api.example.com/store-vault-server/get"
+        );
+        // the base path is kept
+        assert_eq!(
+            join_url("https://api.example.com/intmax", "/store-vault-server/get").unwrap(),
+            "https://api.example.com/intmax/store-vault-server/get"
+        );
+        assert_eq!(
+            join_url("http://[::1]:9000", "/health").unwrap(),
+            "http://[::1]:9000/health"
+        );
+        assert!(join_url("api.example.com", "/health").is_err());
+    }
+}
@@ -1,10 +1,13 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use intmax2_interfaces::api::{
     error::ServerError,
     validity_prover::{
         interface::{AccountInfo, DepositInfo, ValidityProverClientInterface},
         types::{
-            GetAccountInfoQuery, GetAccountInfoResponse, GetBlockMerkleProofQuery,
+            GetAccountInfoQuery, GetAccountInfoResponse, GetAccountMembershipProofQuery,
+            GetAccountMembershipProofResponse, GetBlockMerkleProofQuery,
             GetBlockMerkleProofResponse, GetBlockNumberByTxTreeRootQuery,
             GetBlockNumberByTxTreeRootResponse, GetBlockNumberResponse, GetDepositInfoQuery,
             GetDepositInfoResponse, GetDepositMerkleProofQuery, GetDepositMerkleProofResponse,
@@ -17,8 +20,8 @@ use intmax2_zkp::{
     circuits::validity::validity_pis::ValidityPublicInputs,
     common::{
         trees::{
-            block_hash_tree::BlockHashMerkleProof, deposit_tree::DepositMerkleProof,
-            sender_tree::SenderLeaf,
+            account_tree::AccountMembershipProof, block_hash_tree::BlockHashMerkleProof,
+            deposit_tree::DepositMerkleProof, sender_tree::SenderLeaf,
         },
         witness::update_witness::UpdateWitness,
     },
@@ -26,7 +29,10 @@ use intmax2_zkp::{
 };
 use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
 
-use super::utils::query::get_request;
+use super::utils::{
+    query::{get_request, HttpClient},
+    retry::RetryConfig,
+};
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
@@ -35,21 +41,66 @@ const D: usize = 2;
 #[derive(Debug, Clone)]
 pub struct ValidityProverClient {
     base_url: String,
+    retry_config: Option<RetryConfig>,
+    bearer_token: Option<String>,
+    http: HttpClient,
 }
 
 impl ValidityProverClient {
     pub fn new(base_url: &str) -> Self {
+        Self::with_http(base_url, HttpClient::default())
+    }
+
+    /// Send the requests with `client`, whose TLS, proxy and timeout settings
+    /// apply, instead of a client built with the default settings
+    pub fn with_http_client(base_url: &str, client: reqwest::Client) -> Self {
+        Self::with_http(base_url, HttpClient::with_client(client))
+    }
+
+    fn with_http(base_url: &str, http: HttpClient) -> Self {
         ValidityProverClient {
             base_url: base_url.to_string(),
+            retry_config: None,
+            bearer_token: None,
+            http,
+        }
+    }
+
+    /// Fail each attempt of a request after `timeout` instead of `DEFAULT_TIMEOUT`,
+    /// replacing a client given to `with_http_client`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = HttpClient::new(timeout);
+        self
+    }
+
+    /// Retry the requests of this client with `config` instead of the
+    /// process-wide `retry_config()`
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry_config = Some(config);
+        self
+    }
+
+    /// Authenticate with `token` instead of VALIDITY_PROVER_BEARER_TOKEN
+    pub fn with_bearer_token(mut self, token: &str) -> Self {
+        self.bearer_token = Some(token.to_string());
+        self
+    }
+
+    fn bearer_token(&self) -> Result<String, ServerError> {
+        match &self.bearer_token {
+            Some(token) => Ok(token.clone()),
+            None => get_bearer_token(),
         }
     }
 
     pub async fn sync(&self) -> Result<(), ServerError> {
         get_request::<(), ()>(
+            &self.http,
             &self.base_url,
             "/validity-prover/sync",
             None,
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(())
@@ -60,10 +111,12 @@ impl ValidityProverClient {
 impl ValidityProverClientInterface for ValidityProverClient {
     async fn get_block_number(&self) -> Result<u32, ServerError> {
         let response: GetBlockNumberResponse = get_request::<(), _>(
+            &self.http,
             &self.base_url,
             "/validity-prover/block-number",
             None,
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.block_number)
@@ -83,25 +136,50 @@ impl ValidityProverClientInterface for ValidityProverClient {
             is_prev_account_tree,
         };
         let response: GetUpdateWitnessResponse = get_request(
+            &self.http,
             &self.base_url,
             "/validity-prover/get-update-witness",
             Some(query),
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.update_witness)
     }
 
+    async fn get_account_membership_proof(
+        &self,
+        pubkey: U256,
+        block_number: u32,
+    ) -> Result<AccountMembershipProof, ServerError> {
+        let query = GetAccountMembershipProofQuery {
+            pubkey,
+            block_number,
+        };
+        let response: GetAccountMembershipProofResponse = get_request(
+            &self.http,
+            &self.base_url,
+            "/validity-prover/get-account-membership-proof",
+            Some(query),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
+        )
+        .await?;
+        Ok(response.account_membership_proof)
+    }
+
     async fn get_deposit_info(
         &self,
         deposit_hash: Bytes32,
     ) -> Result<Option<DepositInfo>, ServerError> {
         let query = GetDepositInfoQuery { deposit_hash };
         let response: GetDepositInfoResponse = get_request(
+            &self.http,
             &self.base_url,
             "/validity-prover/get-deposit-info",
             Some(query),
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.deposit_info)
@@ -113,10 +191,12 @@ impl ValidityProverClientInterface for ValidityProverClient {
     ) -> Result<Option<u32>, ServerError> {
         let query = GetBlockNumberByTxTreeRootQuery { tx_tree_root };
         let response: GetBlockNumberByTxTreeRootResponse = get_request(
+            &self.http,
             &self.base_url,
             "/validity-prover/get-block-number-by-tx-tree-root",
             Some(query),
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.block_number)
@@ -128,10 +208,12 @@ impl ValidityProverClientInterface for ValidityProverClient {
     ) -> Result<Option<ValidityPublicInputs>, ServerError> {
         let query = GetValidityPisQuery { block_number };
         let response: GetValidityPisResponse = get_request(
+            &self.http,
             &self.base_url,
             "/validity-prover/get-validity-pis",
             Some(query),
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.validity_pis)
@@ -143,10 +225,12 @@ impl ValidityProverClientInterface for ValidityProverClient {
     ) -> Result<Option<Vec<SenderLeaf>>, ServerError> {
         let query = GetSenderLeavesQuery { block_number };
         let response: GetSenderLeavesResponse = get_request(
+            &self.http,
             &self.base_url,
             "/validity-prover/get-sender-leaves",
             Some(query),
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.sender_leaves)
@@ -162,10 +246,12 @@ impl ValidityProverClientInterface for ValidityProverClient {
             leaf_block_number,
         };
         let response: GetBlockMerkleProofResponse = get_request(
+            &self.http,
             &self.base_url,
             "/validity-prover/get-block-merkle-proof",
             Some(query),
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.block_merkle_proof)
@@ -181,10 +267,12 @@ impl ValidityProverClientInterface for ValidityProverClient {
             deposit_index,
         };
         let response: GetDepositMerkleProofResponse = get_request(
+            &self.http,
             &self.base_url,
             "/validity-prover/get-deposit-merkle-proof",
             Some(query),
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.deposit_merkle_proof)
@@ -193,10 +281,12 @@ impl ValidityProverClientInterface for ValidityProverClient {
     async fn get_account_info(&self, pubkey: U256) -> Result<AccountInfo, ServerError> {
         let query = GetAccountInfoQuery { pubkey };
         let response: GetAccountInfoResponse = get_request(
+            &self.http,
             &self.base_url,
             "/validity-prover/get-account-info",
             Some(query),
-            Some(get_bearer_token()?),
+            Some(self.bearer_token()?),
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.account_info)
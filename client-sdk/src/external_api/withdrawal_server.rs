@@ -1,24 +1,29 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use intmax2_interfaces::api::{
     error::ServerError,
     withdrawal_server::{
-        interface::{Fee, WithdrawalInfo, WithdrawalServerClientInterface},
+        interface::{Fee, WithdrawalInfo, WithdrawalLimits, WithdrawalServerClientInterface},
         types::{
-            GetFeeResponse, GetWithdrawalInfoByRecipientRequest, GetWithdrawalInfoRequest,
-            GetWithdrawalInfoResponse, RequestWithdrawalRequest,
+            GetFeeResponse, GetLimitsResponse, GetWithdrawalInfoByRecipientRequest,
+            GetWithdrawalInfoRequest, GetWithdrawalInfoResponse, RequestWithdrawalRequest,
         },
     },
 };
 use intmax2_zkp::{
     common::signature::{flatten::FlatG2, key_set::KeySet},
-    ethereum_types::{address::Address, u256::U256},
+    ethereum_types::{address::Address, bytes32::Bytes32, u256::U256},
 };
 use plonky2::{
     field::goldilocks_field::GoldilocksField,
     plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
 };
 
-use super::utils::query::{get_request, post_request};
+use super::utils::{
+    query::{get_request, post_request_once, HttpClient},
+    retry::RetryConfig,
+};
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
@@ -27,38 +32,94 @@ const D: usize = 2;
 #[derive(Debug, Clone)]
 pub struct WithdrawalServerClient {
     base_url: String,
+    retry_config: Option<RetryConfig>,
+    http: HttpClient,
 }
 
 impl WithdrawalServerClient {
     pub fn new(base_url: &str) -> Self {
+        Self::with_http(base_url, HttpClient::default())
+    }
+
+    /// Send the requests with `client`, whose TLS, proxy and timeout settings
+    /// apply, instead of a client built with the default settings
+    pub fn with_http_client(base_url: &str, client: reqwest::Client) -> Self {
+        Self::with_http(base_url, HttpClient::with_client(client))
+    }
+
+    fn with_http(base_url: &str, http: HttpClient) -> Self {
         WithdrawalServerClient {
             base_url: base_url.to_string(),
+            retry_config: None,
+            http,
         }
     }
+
+    /// Fail each attempt of a request after `timeout` instead of `DEFAULT_TIMEOUT`,
+    /// replacing a client given to `with_http_client`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = HttpClient::new(timeout);
+        self
+    }
+
+    /// Retry the requests of this client with `config` instead of the
+    /// process-wide `retry_config()`
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry_config = Some(config);
+        self
+    }
 }
 
 #[async_trait(?Send)]
 impl WithdrawalServerClientInterface for WithdrawalServerClient {
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
     async fn fee(&self) -> Result<Vec<Fee>, ServerError> {
-        let response: GetFeeResponse =
-            get_request::<(), _>(&self.base_url, "/withdrawal-server/fee", None, None).await?;
+        let response: GetFeeResponse = get_request::<(), _>(
+            &self.http,
+            &self.base_url,
+            "/withdrawal-server/fee",
+            None,
+            None,
+            self.retry_config.as_ref(),
+        )
+        .await?;
         Ok(response.fees)
     }
 
+    async fn limits(&self) -> Result<WithdrawalLimits, ServerError> {
+        let response: GetLimitsResponse = get_request::<(), _>(
+            &self.http,
+            &self.base_url,
+            "/withdrawal-server/limits",
+            None,
+            None,
+            self.retry_config.as_ref(),
+        )
+        .await?;
+        Ok(response.limits)
+    }
+
     async fn request_withdrawal(
         &self,
         pubkey: U256,
         single_withdrawal_proof: &ProofWithPublicInputs<F, C, D>,
+        attribution_tag_hash: Option<Bytes32>,
     ) -> Result<(), ServerError> {
         let request = RequestWithdrawalRequest {
             pubkey,
             single_withdrawal_proof: single_withdrawal_proof.clone(),
+            attribution_tag_hash,
         };
-        post_request::<_, ()>(
+        post_request_once::<_, ()>(
+            &self.http,
             &self.base_url,
             "/withdrawal-server/request-withdrawal",
             &request,
             None,
+            self.retry_config.as_ref(),
         )
         .await
     }
@@ -68,10 +129,12 @@ impl WithdrawalServerClientInterface for WithdrawalServerClient {
         let signature = FlatG2::default(); // todo: get signature from key
         let query = GetWithdrawalInfoRequest { pubkey, signature };
         let response: GetWithdrawalInfoResponse = get_request(
+            &self.http,
             &self.base_url,
             "/withdrawal-server/get-withdrawal-info",
             Some(query),
             None,
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.withdrawal_info)
@@ -83,10 +146,12 @@ impl WithdrawalServerClientInterface for WithdrawalServerClient {
     ) -> Result<Vec<WithdrawalInfo>, ServerError> {
         let query = GetWithdrawalInfoByRecipientRequest { recipient };
         let response: GetWithdrawalInfoResponse = get_request(
+            &self.http,
             &self.base_url,
             "/withdrawal-server/get-withdrawal-info-by-recipient",
             Some(query),
             None,
+            self.retry_config.as_ref(),
         )
         .await?;
         Ok(response.withdrawal_info)
@@ -1,8 +1,16 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
-use intmax2_zkp::circuits::{
-    balance::balance_processor::BalanceProcessor, validity::validity_processor::ValidityProcessor,
-    withdrawal::single_withdrawal_circuit::SingleWithdrawalCircuit,
+use intmax2_interfaces::utils::encode::decode_plonky2_proof;
+use intmax2_zkp::{
+    circuits::{
+        balance::{balance_pis::BalancePublicInputs, balance_processor::BalanceProcessor},
+        validity::{validity_pis::ValidityPublicInputs, validity_processor::ValidityProcessor},
+        withdrawal::single_withdrawal_circuit::SingleWithdrawalCircuit,
+    },
+    ethereum_types::u256::U256,
 };
 use plonky2::{
     field::goldilocks_field::GoldilocksField,
@@ -21,16 +29,16 @@ fn circuit_data_path() -> PathBuf {
     PathBuf::from("circuit_data")
 }
 
-fn balance_circuit_data_path() -> PathBuf {
-    circuit_data_path().join("balance_verifier_circuit_data.bin")
+fn balance_circuit_data_path(dir: &Path) -> PathBuf {
+    dir.join("balance_verifier_circuit_data.bin")
 }
 
-fn validity_circuit_data_path() -> PathBuf {
-    circuit_data_path().join("validity_verifier_circuit_data.bin")
+fn validity_circuit_data_path(dir: &Path) -> PathBuf {
+    dir.join("validity_verifier_circuit_data.bin")
 }
 
-fn single_withdrawal_circuit_data_path() -> PathBuf {
-    circuit_data_path().join("single_withdrawal_verifier_circuit_data.bin")
+fn single_withdrawal_circuit_data_path(dir: &Path) -> PathBuf {
+    dir.join("single_withdrawal_verifier_circuit_data.bin")
 }
 
 type F = GoldilocksField;
@@ -59,15 +67,36 @@ impl CircuitVerifiers {
     }
 
     pub fn save(&self) -> anyhow::Result<()> {
-        save_verifier_circuit_data(&balance_circuit_data_path(), &self.balance_vd)?;
-        save_verifier_circuit_data(&validity_circuit_data_path(), &self.validity_vd)?;
+        self.save_to(&circuit_data_path())
+    }
+
+    /// Save the verifier data in `dir`, with the file names of `circuit_data`
+    pub fn save_to(&self, dir: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        save_verifier_circuit_data(&balance_circuit_data_path(dir), &self.balance_vd)?;
+        save_verifier_circuit_data(&validity_circuit_data_path(dir), &self.validity_vd)?;
         save_verifier_circuit_data(
-            &single_withdrawal_circuit_data_path(),
+            &single_withdrawal_circuit_data_path(dir),
             &self.single_withdrawal_vd,
         )?;
         Ok(())
     }
 
+    /// The verifier data saved in `dir` by `save_to`
+    pub fn load_from(dir: &Path) -> anyhow::Result<Self> {
+        let load = |path: PathBuf| {
+            let bytes = std::fs::read(&path)
+                .map_err(|e| anyhow::anyhow!("failed to read {:?}: {}", path, e))?;
+            deserialize_verifier_circuit_data(bytes)
+                .map_err(|e| anyhow::anyhow!("failed to deserialize {:?}: {}", path, e))
+        };
+        Ok(Self {
+            balance_vd: load(balance_circuit_data_path(dir))?,
+            validity_vd: load(validity_circuit_data_path(dir))?,
+            single_withdrawal_vd: load(single_withdrawal_circuit_data_path(dir))?,
+        })
+    }
+
     pub fn load() -> Self {
         let balance_vd = deserialize_verifier_circuit_data(BALANCE_VD_BYTES.to_vec()).unwrap();
         let validity_vd = deserialize_verifier_circuit_data(VALIDITY_VD_BYTES.to_vec()).unwrap();
@@ -94,6 +123,96 @@ impl CircuitVerifiers {
     }
 }
 
+static VERIFIERS: OnceLock<CircuitVerifiers> = OnceLock::new();
+
+/// The embedded verifiers, deserialized on first use
+pub fn verifiers() -> &'static CircuitVerifiers {
+    VERIFIERS.get_or_init(CircuitVerifiers::load)
+}
+
+/// Verify a proof in the compressed encoding of `encode_plonky2_proof` against
+/// `vd`. `Ok(false)` if the proof does not hold, an error if the bytes are not a
+/// proof of the circuit.
+pub fn verify_encoded_proof(
+    encoded_proof: &[u8],
+    vd: &VerifierCircuitData<F, C, D>,
+) -> anyhow::Result<bool> {
+    let proof = decode_plonky2_proof(encoded_proof, vd)
+        .map_err(|e| anyhow::anyhow!("failed to decode the proof: {}", e))?;
+    Ok(vd.verify(proof).is_ok())
+}
+
+/// Verify an encoded balance proof, e.g. one received with a balance claim,
+/// against the balance circuit
+pub fn verify_encoded_balance_proof(encoded_proof: &[u8]) -> anyhow::Result<bool> {
+    verify_encoded_proof(encoded_proof, &verifiers().balance_vd)
+}
+
+/// Outcome of `check_encoded_balance_proof`, the first check that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceProofStatus {
+    /// The proof holds, is of the expected account and its public state is that
+    /// of the chain
+    Valid,
+    /// The proof does not hold
+    InvalidProof,
+    /// The proof holds for another pubkey
+    WrongPubkey,
+    /// The public state of the proof is not that of the chain at its block, or
+    /// the block is not known
+    WrongPublicState,
+}
+
+/// A balance proof checked against a claim. The balances are not public inputs:
+/// they are behind `public_inputs.private_commitment`, which the claimant has to
+/// open.
+#[derive(Debug, Clone)]
+pub struct BalanceProofCheck {
+    pub status: BalanceProofStatus,
+    pub public_inputs: BalancePublicInputs,
+}
+
+impl BalanceProofCheck {
+    /// Check the public state of the proof against `validity_pis`, the public
+    /// inputs of the validity proof of its block, `None` if the block is unknown
+    pub fn with_chain_state(mut self, validity_pis: Option<&ValidityPublicInputs>) -> Self {
+        let public_state = &self.public_inputs.public_state;
+        let matches = validity_pis.is_some_and(|validity_pis| {
+            validity_pis.public_state.block_number == public_state.block_number
+                && validity_pis.public_state.block_hash == public_state.block_hash
+        });
+        if self.status == BalanceProofStatus::Valid && !matches {
+            self.status = BalanceProofStatus::WrongPublicState;
+        }
+        self
+    }
+}
+
+/// Verify an encoded balance proof against the balance circuit and check that it
+/// is of `expected_pubkey`. The public state has to be checked with
+/// `BalanceProofCheck::with_chain_state`. An error if the bytes are not a proof of
+/// the circuit.
+pub fn check_encoded_balance_proof(
+    encoded_proof: &[u8],
+    expected_pubkey: U256,
+) -> anyhow::Result<BalanceProofCheck> {
+    let vd = &verifiers().balance_vd;
+    let proof = decode_plonky2_proof(encoded_proof, vd)
+        .map_err(|e| anyhow::anyhow!("failed to decode the proof: {}", e))?;
+    let public_inputs = BalancePublicInputs::from_pis(&proof.public_inputs);
+    let status = if vd.verify(proof).is_err() {
+        BalanceProofStatus::InvalidProof
+    } else if public_inputs.pubkey != expected_pubkey {
+        BalanceProofStatus::WrongPubkey
+    } else {
+        BalanceProofStatus::Valid
+    };
+    Ok(BalanceProofCheck {
+        status,
+        public_inputs,
+    })
+}
+
 fn save_verifier_circuit_data(
     path: &Path,
     vd: &VerifierCircuitData<F, C, D>,
@@ -118,16 +237,99 @@ fn deserialize_verifier_circuit_data(
 
 #[cfg(test)]
 mod tests {
+    use intmax2_interfaces::utils::encode::encode_plonky2_proof;
+    use plonky2::{
+        field::types::Field,
+        iop::witness::PartialWitness,
+        plonk::{circuit_builder::CircuitBuilder, circuit_data::CircuitConfig},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_verify_encoded_proof() {
+        // 3 * 3, with the product public
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let x = builder.constant(F::from_canonical_u64(3));
+        let y = builder.mul(x, x);
+        builder.register_public_input(y);
+        let data = builder.build::<C>();
+        let proof = data.prove(PartialWitness::new()).unwrap();
+        let vd = data.verifier_data();
+
+        let bytes = encode_plonky2_proof(proof.clone(), &vd).unwrap();
+        assert!(verify_encoded_proof(&bytes, &vd).unwrap());
+
+        // a proof of another statement does not hold
+        let mut tampered = proof;
+        tampered.public_inputs[0] = F::from_canonical_u64(10);
+        let bytes = encode_plonky2_proof(tampered, &vd).unwrap();
+        assert!(!matches!(verify_encoded_proof(&bytes, &vd), Ok(true)));
+
+        // and bytes that are not a proof are an error
+        assert!(verify_encoded_proof(&[1, 2, 3], &vd).is_err());
+        assert!(verify_encoded_balance_proof(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_balance_proof_public_state_is_checked() {
+        let pubkey = U256::from(7u128);
+        let mut public_inputs = BalancePublicInputs::new(pubkey);
+        public_inputs.public_state.block_number = 3;
+        let check = |status| BalanceProofCheck {
+            status,
+            public_inputs: public_inputs.clone(),
+        };
+        let mut validity_pis = ValidityPublicInputs::genesis();
+        validity_pis.public_state = public_inputs.public_state.clone();
+
+        let checked = check(BalanceProofStatus::Valid).with_chain_state(Some(&validity_pis));
+        assert_eq!(checked.status, BalanceProofStatus::Valid);
+        let checked = check(BalanceProofStatus::Valid).with_chain_state(None);
+        assert_eq!(checked.status, BalanceProofStatus::WrongPublicState);
+        validity_pis.public_state.block_number = 4;
+        let checked = check(BalanceProofStatus::Valid).with_chain_state(Some(&validity_pis));
+        assert_eq!(checked.status, BalanceProofStatus::WrongPublicState);
+        // the first failed check is kept
+        let checked = check(BalanceProofStatus::WrongPubkey).with_chain_state(None);
+        assert_eq!(checked.status, BalanceProofStatus::WrongPubkey);
+    }
+
+    #[test]
+    fn test_save_and_load_from_dir() {
+        let dir = std::env::temp_dir().join(format!(
+            "intmax2-circuit-verifiers-test-{}",
+            std::process::id()
+        ));
+        let verifiers = CircuitVerifiers::load();
+        verifiers.save_to(&dir).unwrap();
+        let loaded = CircuitVerifiers::load_from(&dir).unwrap();
+        for (loaded, embedded) in [
+            (loaded.get_balance_vd(), verifiers.get_balance_vd()),
+            (loaded.get_validity_vd(), verifiers.get_validity_vd()),
+            (
+                loaded.get_single_withdrawal_vd(),
+                verifiers.get_single_withdrawal_vd(),
+            ),
+        ] {
+            assert_eq!(
+                loaded.verifier_only.circuit_digest,
+                embedded.verifier_only.circuit_digest
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(CircuitVerifiers::load_from(&dir).is_err());
+    }
 
     #[test]
     #[ignore]
     fn test_save_circuit_verifiers() {
-        let circuit_verifiers = super::CircuitVerifiers::construct();
+        let circuit_verifiers = CircuitVerifiers::construct();
         circuit_verifiers.save().unwrap();
     }
 
     #[test]
     fn test_load_circuit_verifiers() {
-        let _circuit_verifiers = super::CircuitVerifiers::load();
+        let _circuit_verifiers = CircuitVerifiers::load();
     }
 }
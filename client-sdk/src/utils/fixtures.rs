@@ -0,0 +1,286 @@
+//! Deterministic encrypted fixtures of every backup data type, for testing
+//! decryption and display code without running the whole stack.
+//!
+//! The same seed always produces the same files. `interfaces/test-assets/fixtures`
+//! holds the ones of seed 0, written by the `gen-fixtures` binary of the tests
+//! crate. The fixture tests of this crate fail if they are missing or differ from
+//! what `write_fixtures(0, ..)` writes.
+
+use std::{fs, path::Path};
+
+use intmax2_interfaces::data::{
+    common_tx_data::CommonTxData,
+    deposit_data::{DepositData, TokenType},
+    fixtures::{FixtureBlob, FixtureDataType, FixtureKey, FixtureManifest, FIXTURE_MANIFEST_FILE},
+    transfer_data::TransferData,
+    tx_data::TxData,
+    user_data::UserData,
+};
+use intmax2_zkp::{
+    circuits::balance::send::spent_circuit::SpentCircuit,
+    common::{
+        deposit::get_pubkey_salt_hash,
+        generic_address::GenericAddress,
+        private_state::FullPrivateState,
+        salt::Salt,
+        signature::key_set::KeySet,
+        transfer::Transfer,
+        trees::{transfer_tree::TransferTree, tx_tree::TxTree},
+        tx::Tx,
+        witness::spent_witness::SpentWitness,
+    },
+    constants::{NUM_TRANSFERS_IN_TX, TRANSFER_TREE_HEIGHT, TX_TREE_HEIGHT},
+    ethereum_types::{
+        address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _,
+    },
+};
+use num_bigint::BigUint;
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use rand::SeedableRng as _;
+use rand_chacha::ChaCha20Rng;
+use serde::Serialize;
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+fn amount(value: u32) -> U256 {
+    let mut limbs = [0u32; 8];
+    limbs[7] = value;
+    U256::from_u32_slice(&limbs)
+}
+
+fn fixture_key(name: &str, key: KeySet) -> FixtureKey {
+    let privkey = BigUint::from(key.privkey).to_bytes_be();
+    let mut padded = vec![0u8; 32 - privkey.len()];
+    padded.extend(privkey);
+    FixtureKey {
+        name: name.to_string(),
+        privkey: format!("0x{}", hex::encode(padded)),
+        pubkey: key.pubkey.to_hex(),
+    }
+}
+
+struct Writer<'a> {
+    out_dir: &'a Path,
+    blobs: Vec<FixtureBlob>,
+}
+
+impl Writer<'_> {
+    fn positive<T: Serialize>(
+        &mut self,
+        name: &str,
+        data_type: FixtureDataType,
+        key: &str,
+        blob: &[u8],
+        decrypted: &T,
+        description: &str,
+    ) -> anyhow::Result<()> {
+        let file = format!("{}.bin", name);
+        let json_file = format!("{}.json", name);
+        fs::write(self.out_dir.join(&file), blob)?;
+        fs::write(
+            self.out_dir.join(&json_file),
+            serde_json::to_vec_pretty(decrypted)?,
+        )?;
+        self.blobs.push(FixtureBlob {
+            file,
+            data_type,
+            key: key.to_string(),
+            decrypted: Some(json_file),
+            description: description.to_string(),
+        });
+        Ok(())
+    }
+
+    fn negative(
+        &mut self,
+        name: &str,
+        data_type: FixtureDataType,
+        key: &str,
+        blob: &[u8],
+        description: &str,
+    ) -> anyhow::Result<()> {
+        let file = format!("{}.bin", name);
+        fs::write(self.out_dir.join(&file), blob)?;
+        self.blobs.push(FixtureBlob {
+            file,
+            data_type,
+            key: key.to_string(),
+            decrypted: None,
+            description: description.to_string(),
+        });
+        Ok(())
+    }
+}
+
+/// Write the fixtures of `seed` and their manifest to `out_dir`, which must exist
+pub fn write_fixtures(seed: u64, out_dir: &Path) -> anyhow::Result<FixtureManifest> {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let sender = KeySet::rand(&mut rng);
+    let recipient = KeySet::rand(&mut rng);
+    let mut writer = Writer {
+        out_dir,
+        blobs: Vec::new(),
+    };
+
+    // deposit
+    let deposit_salt = Salt::rand(&mut rng);
+    let deposit_data = DepositData {
+        deposit_salt,
+        pubkey_salt_hash: get_pubkey_salt_hash(recipient.pubkey, deposit_salt),
+        amount: amount(100),
+        token_type: TokenType::NATIVE,
+        token_address: Address::default(),
+        token_id: U256::default(),
+        token_index: Some(0),
+    };
+    let deposit_blob = deposit_data.encrypt(recipient.pubkey);
+    writer.positive(
+        "deposit",
+        FixtureDataType::Deposit,
+        "recipient",
+        &deposit_blob,
+        &deposit_data,
+        "native token deposit to the recipient",
+    )?;
+    writer.negative(
+        "deposit_wrong_key",
+        FixtureDataType::Deposit,
+        "sender",
+        &deposit_blob,
+        "deposit of the recipient, decrypted with the sender's key",
+    )?;
+    let mut corrupted = deposit_blob.clone();
+    corrupted.truncate(corrupted.len() / 2);
+    writer.negative(
+        "deposit_corrupted",
+        FixtureDataType::Deposit,
+        "recipient",
+        &corrupted,
+        "deposit blob truncated to half its length",
+    )?;
+
+    // tx with a transfer to the recipient and a withdrawal
+    let transfers = vec![
+        Transfer {
+            recipient: GenericAddress::from_pubkey(recipient.pubkey),
+            token_index: 0,
+            amount: amount(10),
+            salt: Salt::rand(&mut rng),
+        },
+        Transfer {
+            recipient: GenericAddress::from_address(Address::rand(&mut rng)),
+            token_index: 0,
+            amount: amount(5),
+            salt: Salt::rand(&mut rng),
+        },
+    ];
+    let mut transfer_tree = TransferTree::new(TRANSFER_TREE_HEIGHT);
+    let mut padded_transfers = transfers.clone();
+    padded_transfers.resize(NUM_TRANSFERS_IN_TX, Transfer::default());
+    for transfer in &padded_transfers {
+        transfer_tree.push(transfer.clone());
+    }
+    let sender_state = FullPrivateState::new();
+    let tx = Tx {
+        nonce: sender_state.nonce,
+        transfer_tree_root: transfer_tree.get_root(),
+    };
+    let spent_witness = SpentWitness::new(
+        &sender_state.asset_tree,
+        &sender_state.to_private_state(),
+        &transfer_tree.leaves(),
+        tx,
+        Salt::rand(&mut rng),
+    )?;
+    // proving is deterministic, as the circuit is not zero-knowledge
+    let spent_circuit = SpentCircuit::<F, C, D>::new();
+    let spent_proof = spent_circuit.prove(&spent_witness.to_value()?)?;
+
+    let mut tx_tree = TxTree::new(TX_TREE_HEIGHT);
+    tx_tree.push(tx);
+    let tx_tree_root: Bytes32 = tx_tree.get_root().into();
+    let common = CommonTxData::<F, C, D> {
+        spent_proof,
+        sender_prev_block_number: 0,
+        tx,
+        tx_index: 0,
+        tx_merkle_proof: tx_tree.prove(0),
+        tx_tree_root,
+    };
+
+    let tx_data = TxData {
+        common: common.clone(),
+        spent_witness,
+        claim_deadline_block: Some(1000),
+        fee: None,
+    };
+    writer.positive(
+        "tx",
+        FixtureDataType::Tx,
+        "sender",
+        &tx_data.encrypt(sender.pubkey),
+        &tx_data,
+        "tx with a transfer and a withdrawal",
+    )?;
+
+    let transfer_data = TransferData {
+        sender: sender.pubkey,
+        prev_block_number: 0,
+        prev_private_commitment: sender_state.to_private_state().commitment(),
+        tx_data: common,
+        transfer: transfers[0].clone(),
+        transfer_index: 0,
+        transfer_merkle_proof: transfer_tree.prove(0),
+        claim_deadline_block: Some(1000),
+        attribution_tag: None,
+    };
+    let transfer_blob = transfer_data.encrypt(recipient.pubkey);
+    writer.positive(
+        "transfer",
+        FixtureDataType::Transfer,
+        "recipient",
+        &transfer_blob,
+        &transfer_data,
+        "transfer to the recipient with a claim deadline",
+    )?;
+    let corrupted = &transfer_blob[..transfer_blob.len() - 1];
+    writer.negative(
+        "transfer_corrupted",
+        FixtureDataType::Transfer,
+        "recipient",
+        corrupted,
+        "transfer blob missing its last byte",
+    )?;
+
+    // user data
+    let mut user_data = UserData::new(recipient.pubkey);
+    user_data.block_number = 2;
+    user_data.deposit_lpt = 1_700_000_000;
+    user_data
+        .processed_deposit_uuids
+        .push("00000000-0000-0000-0000-000000000001".to_string());
+    writer.positive(
+        "user_data",
+        FixtureDataType::UserData,
+        "recipient",
+        &user_data.encrypt(recipient.pubkey),
+        &user_data,
+        "user data of the recipient after one deposit",
+    )?;
+
+    let manifest = FixtureManifest {
+        seed,
+        keys: vec![
+            fixture_key("sender", sender),
+            fixture_key("recipient", recipient),
+        ],
+        blobs: writer.blobs,
+    };
+    fs::write(
+        out_dir.join(FIXTURE_MANIFEST_FILE),
+        serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    Ok(manifest)
+}
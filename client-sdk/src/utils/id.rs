@@ -0,0 +1,186 @@
+//! Display of 32-byte identifiers, e.g. tx tree roots and nullifiers, that people
+//! copy between commands: `0x`-prefixed lowercase hex followed by a checksum, as in
+//! `0x1f..9a#k3q9`. A truncated or mistyped id then fails to parse instead of
+//! silently naming something else. Plain hex is still accepted.
+
+use intmax2_zkp::ethereum_types::{bytes32::Bytes32, u32limb_trait::U32LimbTrait as _};
+use sha2::{Digest as _, Sha256};
+
+// base32 without i, l, o and u, which are easily confused with digits
+const CHECKSUM_ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+const CHECKSUM_LEN: usize = 4;
+const HEX_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum IdError {
+    #[error("{0:?} is not hex")]
+    InvalidHex(String),
+
+    #[error("{input:?} has {len} hex digits instead of 64, did you truncate this?")]
+    InvalidLength { input: String, len: usize },
+
+    #[error(
+        "Invalid checksum {0:?}, expected 4 characters of 0-9 and a-z other than i, l, o and u"
+    )]
+    InvalidChecksum(String),
+
+    #[error("Checksum of {input:?} does not match, did you truncate this? Expected #{expected}")]
+    ChecksumMismatch { input: String, expected: String },
+}
+
+/// The 4-character checksum of `id`: the first 20 bits of its SHA-256 in base32
+pub fn id_checksum(id: Bytes32) -> String {
+    let hash = Sha256::digest(id.to_bytes_be());
+    let bits = (u32::from(hash[0]) << 12) | (u32::from(hash[1]) << 4) | (u32::from(hash[2]) >> 4);
+    (0..CHECKSUM_LEN)
+        .rev()
+        .map(|i| CHECKSUM_ALPHABET[((bits >> (5 * i)) & 31) as usize] as char)
+        .collect()
+}
+
+/// `0x`-prefixed lowercase hex
+pub fn format_plain_id(id: Bytes32) -> String {
+    format!("0x{}", hex::encode(id.to_bytes_be()))
+}
+
+/// `0x`-prefixed lowercase hex with its checksum, e.g. `0x1f..9a#k3q9`
+pub fn format_id(id: Bytes32) -> String {
+    format!("{}#{}", format_plain_id(id), id_checksum(id))
+}
+
+/// Parse an id in plain hex, with or without `0x`, or with a checksum, which must
+/// then match
+pub fn parse_id(input: &str) -> Result<Bytes32, IdError> {
+    let input = input.trim();
+    let (hex_part, checksum) = match input.split_once('#') {
+        Some((hex_part, checksum)) => (hex_part, Some(checksum)),
+        None => (input, None),
+    };
+    let digits = hex_part
+        .strip_prefix("0x")
+        .or_else(|| hex_part.strip_prefix("0X"))
+        .unwrap_or(hex_part);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(IdError::InvalidHex(input.to_string()));
+    }
+    if digits.len() != HEX_LEN {
+        return Err(IdError::InvalidLength {
+            input: input.to_string(),
+            len: digits.len(),
+        });
+    }
+    let bytes = hex::decode(digits).map_err(|_| IdError::InvalidHex(input.to_string()))?;
+    let id = Bytes32::from_bytes_be(&bytes);
+    if let Some(checksum) = checksum {
+        let checksum = checksum.to_ascii_lowercase();
+        if checksum.len() != CHECKSUM_LEN
+            || !checksum.bytes().all(|b| CHECKSUM_ALPHABET.contains(&b))
+        {
+            return Err(IdError::InvalidChecksum(checksum));
+        }
+        let expected = id_checksum(id);
+        if checksum != expected {
+            return Err(IdError::ChecksumMismatch {
+                input: input.to_string(),
+                expected,
+            });
+        }
+    }
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Bytes32 {
+        Bytes32::from_bytes_be(&(0u8..32).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_format_id() {
+        // fixed, as other clients print the same checksums
+        assert_eq!(id_checksum(Bytes32::default()), "csm7");
+        assert_eq!(
+            format_id(sample()),
+            "0x000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f#cc6w"
+        );
+        assert_eq!(
+            format_plain_id(sample()),
+            "0x000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+        );
+    }
+
+    #[test]
+    fn test_parse_id() {
+        let id = sample();
+        let plain = format_plain_id(id);
+        assert_eq!(parse_id(&format_id(id)).unwrap(), id);
+        assert_eq!(parse_id(&plain).unwrap(), id);
+        assert_eq!(parse_id(&plain[2..]).unwrap(), id);
+        assert_eq!(parse_id(&format_id(id).to_uppercase()).unwrap(), id);
+        assert_eq!(parse_id(&format!("  {}\n", format_id(id))).unwrap(), id);
+    }
+
+    #[test]
+    fn test_near_misses() {
+        let id = sample();
+        let formatted = format_id(id);
+        let (plain, checksum) = formatted.split_once('#').unwrap();
+
+        // a digit lost when copying, with and without the checksum
+        let truncated = format!("{}#{}", &plain[..plain.len() - 1], checksum);
+        assert!(matches!(
+            parse_id(&truncated),
+            Err(IdError::InvalidLength { len: 63, .. })
+        ));
+        assert!(matches!(
+            parse_id(&plain[..40]),
+            Err(IdError::InvalidLength { len: 38, .. })
+        ));
+        assert!(parse_id(&truncated)
+            .unwrap_err()
+            .to_string()
+            .contains("did you truncate this?"));
+
+        // one digit changed, or two swapped
+        let mut typo = plain.to_string().into_bytes();
+        typo[10] = if typo[10] == b'a' { b'b' } else { b'a' };
+        let typo = format!("{}#{}", String::from_utf8(typo).unwrap(), checksum);
+        assert!(matches!(
+            parse_id(&typo),
+            Err(IdError::ChecksumMismatch { .. })
+        ));
+        let mut swapped = plain.to_string().into_bytes();
+        swapped.swap(4, 5);
+        let swapped = format!("{}#{}", String::from_utf8(swapped).unwrap(), checksum);
+        assert!(matches!(
+            parse_id(&swapped),
+            Err(IdError::ChecksumMismatch { .. })
+        ));
+
+        // a mistyped or cut checksum
+        let other = if checksum.starts_with('a') { "b" } else { "a" };
+        let bad_checksum = format!("{}#{}{}", plain, other, &checksum[1..]);
+        assert!(matches!(
+            parse_id(&bad_checksum),
+            Err(IdError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            parse_id(&format!("{}#{}", plain, &checksum[..3])),
+            Err(IdError::InvalidChecksum(_))
+        ));
+        assert!(matches!(
+            parse_id(&format!("{}#", plain)),
+            Err(IdError::InvalidChecksum(_))
+        ));
+        assert!(matches!(
+            parse_id(&format!("{}#abci", plain)),
+            Err(IdError::InvalidChecksum(_))
+        ));
+
+        assert!(matches!(parse_id("0xzz"), Err(IdError::InvalidHex(_))));
+        assert!(matches!(parse_id(""), Err(IdError::InvalidHex(_))));
+        assert!(matches!(parse_id("0x"), Err(IdError::InvalidHex(_))));
+    }
+}
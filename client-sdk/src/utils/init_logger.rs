@@ -4,7 +4,12 @@ use std::sync::OnceLock;
 static LOGGER: OnceLock<()> = OnceLock::new();
 
 pub fn init_logger() {
+    init_logger_with_filter("info");
+}
+
+/// Initialize the logger with `filter` as the default of RUST_LOG
+pub fn init_logger_with_filter(filter: &str) {
     LOGGER.get_or_init(|| {
-        env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+        env_logger::Builder::from_env(Env::default().default_filter_or(filter)).init();
     });
 }
@@ -1,2 +1,4 @@
 pub mod circuit_verifiers;
+pub mod fixtures;
+pub mod id;
 pub mod init_logger;
@@ -0,0 +1,58 @@
+//! Runs every example to completion so they keep up with the API.
+//!
+//! The examples need the servers and the contracts of a local environment, so
+//! this is behind the `examples-e2e` feature:
+//!
+//! ```sh
+//! ETH_PRIVATE_KEY=0x.. INTMAX_PRIVATE_KEY=0x.. RECIPIENT_PRIVATE_KEY=0x.. \
+//!   WITHDRAWAL_RECIPIENT=0x.. cargo test -p intmax2-client-sdk --features examples-e2e
+//! ```
+//!
+//! The rest of the environment is the one of the CLI, see `examples/common`.
+#![cfg(feature = "examples-e2e")]
+
+use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+
+#[path = "../examples/common/mod.rs"]
+mod common;
+#[allow(dead_code)]
+#[path = "../examples/deposit_and_sync.rs"]
+mod deposit_and_sync;
+#[allow(dead_code)]
+#[path = "../examples/exchange_sweep.rs"]
+mod exchange_sweep;
+#[allow(dead_code)]
+#[path = "../examples/offline_sign.rs"]
+mod offline_sign;
+#[allow(dead_code)]
+#[path = "../examples/send_transfer.rs"]
+mod send_transfer;
+#[allow(dead_code)]
+#[path = "../examples/withdraw.rs"]
+mod withdraw;
+
+#[tokio::test]
+async fn test_examples() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+    let sender = common::key_from_env("INTMAX_PRIVATE_KEY")?;
+    let recipient = common::key_from_env("RECIPIENT_PRIVATE_KEY")?;
+    // the recipient of the transfers sweeps them back to the sender
+    std::env::set_var("RECIPIENT_PUBKEY", recipient.pubkey.to_hex());
+    std::env::set_var(
+        "SWEEP_PRIVATE_KEYS",
+        std::env::var("RECIPIENT_PRIVATE_KEY")?,
+    );
+    std::env::set_var("HOT_WALLET_PUBKEY", sender.pubkey.to_hex());
+
+    println!("== deposit_and_sync");
+    deposit_and_sync::run().await?;
+    println!("== send_transfer");
+    send_transfer::run().await?;
+    println!("== offline_sign");
+    offline_sign::run().await?;
+    println!("== withdraw");
+    withdraw::run().await?;
+    println!("== exchange_sweep");
+    exchange_sweep::run().await?;
+    Ok(())
+}
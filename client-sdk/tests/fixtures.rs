@@ -0,0 +1,125 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use intmax2_client_sdk::utils::fixtures::write_fixtures;
+use intmax2_interfaces::data::{
+    deposit_data::DepositData,
+    fixtures::{FixtureDataType, FixtureKey, FixtureManifest, FIXTURE_MANIFEST_FILE},
+    transfer_data::TransferData,
+    tx_data::TxData,
+    user_data::UserData,
+};
+use intmax2_zkp::{
+    common::signature::key_set::KeySet, ethereum_types::u32limb_trait::U32LimbTrait as _,
+};
+use num_bigint::BigUint;
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+// the committed fixtures, which the tests fail without
+fn fixture_dir() -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../interfaces/test-assets/fixtures");
+    assert!(
+        dir.join(FIXTURE_MANIFEST_FILE).exists(),
+        "no fixtures in {:?}. Generate them with `cargo run -p tests --bin gen-fixtures -- 0 interfaces/test-assets/fixtures` and commit them",
+        dir
+    );
+    dir
+}
+
+fn to_keyset(key: &FixtureKey) -> KeySet {
+    let bytes = hex::decode(key.privkey.trim_start_matches("0x")).unwrap();
+    KeySet::new(BigUint::from_bytes_be(&bytes).into())
+}
+
+fn decrypt(
+    data_type: FixtureDataType,
+    blob: &[u8],
+    key: KeySet,
+) -> anyhow::Result<serde_json::Value> {
+    let value = match data_type {
+        FixtureDataType::Deposit => serde_json::to_value(DepositData::decrypt(blob, key)?)?,
+        FixtureDataType::Transfer => {
+            serde_json::to_value(TransferData::<F, C, D>::decrypt(blob, key)?)?
+        }
+        FixtureDataType::Tx => serde_json::to_value(TxData::<F, C, D>::decrypt(blob, key)?)?,
+        FixtureDataType::UserData => serde_json::to_value(UserData::decrypt(blob, key)?)?,
+    };
+    Ok(value)
+}
+
+// decrypt each blob of the fixtures in `dir`, and compare with its decrypted JSON
+fn check_fixtures(dir: &Path) {
+    let manifest = fs::read(dir.join(FIXTURE_MANIFEST_FILE)).unwrap();
+    let manifest: FixtureManifest = serde_json::from_slice(&manifest).unwrap();
+
+    for blob in manifest.blobs.iter() {
+        let key = manifest
+            .keys
+            .iter()
+            .find(|key| key.name == blob.key)
+            .unwrap_or_else(|| panic!("unknown key {} for {}", blob.key, blob.file));
+        let key_set = to_keyset(key);
+        assert_eq!(
+            key_set.pubkey.to_hex(),
+            key.pubkey,
+            "pubkey of {}",
+            key.name
+        );
+        let data = fs::read(dir.join(&blob.file)).unwrap();
+        let result = decrypt(blob.data_type, &data, key_set);
+        match &blob.decrypted {
+            Some(json_file) => {
+                let expected: serde_json::Value =
+                    serde_json::from_slice(&fs::read(dir.join(json_file)).unwrap()).unwrap();
+                let decrypted =
+                    result.unwrap_or_else(|e| panic!("failed to decrypt {}: {}", blob.file, e));
+                assert_eq!(decrypted, expected, "{} ({})", blob.file, blob.description);
+            }
+            None => assert!(
+                result.is_err(),
+                "{} should fail to decrypt ({})",
+                blob.file,
+                blob.description
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_decrypt_fixtures() {
+    check_fixtures(&fixture_dir());
+}
+
+#[test]
+fn test_decrypt_generated_fixtures() {
+    let dir = std::env::temp_dir().join(format!("intmax2-fixtures-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let manifest = write_fixtures(0, &dir).unwrap();
+    assert!(manifest.blobs.iter().any(|blob| blob.decrypted.is_some()));
+    assert!(manifest.blobs.iter().any(|blob| blob.decrypted.is_none()));
+    check_fixtures(&dir);
+
+    // committed fixtures are the ones of the current generator
+    let committed = fixture_dir();
+    let files = manifest
+        .blobs
+        .iter()
+        .flat_map(|blob| std::iter::once(&blob.file).chain(blob.decrypted.as_ref()))
+        .map(String::as_str)
+        .chain([FIXTURE_MANIFEST_FILE]);
+    for file in files {
+        assert_eq!(
+            fs::read(committed.join(file)).ok(),
+            Some(fs::read(dir.join(file)).unwrap()),
+            "committed {} is missing or outdated, regenerate the fixtures",
+            file
+        );
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,3 +1,4 @@
+use base64::{prelude::BASE64_STANDARD, Engine as _};
 use intmax2_zkp::{
     common::witness::{
         receive_deposit_witness::ReceiveDepositWitness,
@@ -9,18 +10,140 @@ use intmax2_zkp::{
 };
 use plonky2::{
     field::goldilocks_field::GoldilocksField,
-    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+    plonk::{
+        circuit_data::VerifierCircuitData, config::PoseidonGoldilocksConfig,
+        proof::ProofWithPublicInputs,
+    },
 };
 use serde::{Deserialize, Serialize};
 
+use crate::utils::encode::{decode_plonky2_proof, encode_plonky2_proof};
+
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
 const D: usize = 2;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Encoding of the balance and withdrawal proofs of the prove endpoints.
+///
+/// A request asks for the encoding of the response with `proofEncoding`. Servers
+/// that predate it ignore the field and answer with a full proof, so a client sends
+/// a compressed `prevProof` only once a server has answered with a compressed proof.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProofEncoding {
+    /// `ProofWithPublicInputs` as JSON
+    #[default]
+    Full,
+    /// Base64 of `encode_plonky2_proof`, decoded with the verifier data of the circuit
+    Compressed,
+}
+
+pub fn compress_proof(
+    proof: &ProofWithPublicInputs<F, C, D>,
+    verifier_data: &VerifierCircuitData<F, C, D>,
+) -> anyhow::Result<String> {
+    let bytes = encode_plonky2_proof(proof.clone(), verifier_data)?;
+    Ok(BASE64_STANDARD.encode(bytes))
+}
+
+pub fn decompress_proof(
+    compressed_proof: &str,
+    verifier_data: &VerifierCircuitData<F, C, D>,
+) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+    let bytes = BASE64_STANDARD
+        .decode(compressed_proof)
+        .map_err(|e| anyhow::anyhow!("Invalid base64 of compressed proof: {}", e))?;
+    decode_plonky2_proof(&bytes, verifier_data)
+}
+
+/// Previous balance proof of a request, in `prevProof` or `compressedPrevProof`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrevProof {
+    pub prev_proof: Option<ProofWithPublicInputs<F, C, D>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_prev_proof: Option<String>,
+}
+
+impl PrevProof {
+    pub fn new(
+        prev_proof: &Option<ProofWithPublicInputs<F, C, D>>,
+        encoding: ProofEncoding,
+        verifier_data: &VerifierCircuitData<F, C, D>,
+    ) -> anyhow::Result<Self> {
+        match (prev_proof, encoding) {
+            (Some(proof), ProofEncoding::Compressed) => Ok(Self {
+                prev_proof: None,
+                compressed_prev_proof: Some(compress_proof(proof, verifier_data)?),
+            }),
+            _ => Ok(Self {
+                prev_proof: prev_proof.clone(),
+                compressed_prev_proof: None,
+            }),
+        }
+    }
+
+    pub fn decode(
+        &self,
+        verifier_data: &VerifierCircuitData<F, C, D>,
+    ) -> anyhow::Result<Option<ProofWithPublicInputs<F, C, D>>> {
+        match &self.compressed_prev_proof {
+            Some(compressed_proof) => Ok(Some(decompress_proof(compressed_proof, verifier_data)?)),
+            None => Ok(self.prev_proof.clone()),
+        }
+    }
+}
+
+/// Proof in `proof` or `compressedProof`, depending on the requested encoding
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProveResponse {
-    pub proof: ProofWithPublicInputs<F, C, D>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<ProofWithPublicInputs<F, C, D>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_proof: Option<String>,
+}
+
+impl ProveResponse {
+    pub fn full(proof: ProofWithPublicInputs<F, C, D>) -> Self {
+        Self {
+            proof: Some(proof),
+            compressed_proof: None,
+        }
+    }
+
+    pub fn new(
+        proof: ProofWithPublicInputs<F, C, D>,
+        encoding: ProofEncoding,
+        verifier_data: &VerifierCircuitData<F, C, D>,
+    ) -> anyhow::Result<Self> {
+        match encoding {
+            ProofEncoding::Full => Ok(Self::full(proof)),
+            ProofEncoding::Compressed => Ok(Self {
+                proof: None,
+                compressed_proof: Some(compress_proof(&proof, verifier_data)?),
+            }),
+        }
+    }
+
+    pub fn encoding(&self) -> ProofEncoding {
+        if self.compressed_proof.is_some() {
+            ProofEncoding::Compressed
+        } else {
+            ProofEncoding::Full
+        }
+    }
+
+    pub fn decode(
+        self,
+        verifier_data: &VerifierCircuitData<F, C, D>,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        match (self.proof, self.compressed_proof) {
+            (_, Some(compressed_proof)) => decompress_proof(&compressed_proof, verifier_data),
+            (Some(proof), None) => Ok(proof),
+            (None, None) => anyhow::bail!("Response has no proof"),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,7 +159,10 @@ pub struct ProveSendRequest {
     pub tx_witnes: TxWitness,
     pub update_witness: UpdateWitness<F, C, D>,
     pub spent_proof: ProofWithPublicInputs<F, C, D>,
-    pub prev_proof: Option<ProofWithPublicInputs<F, C, D>>,
+    #[serde(flatten)]
+    pub prev_proof: PrevProof,
+    #[serde(default)]
+    pub proof_encoding: ProofEncoding,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,7 +170,10 @@ pub struct ProveSendRequest {
 pub struct ProveUpdateRequest {
     pub pubkey: U256,
     pub update_witness: UpdateWitness<F, C, D>,
-    pub prev_proof: Option<ProofWithPublicInputs<F, C, D>>,
+    #[serde(flatten)]
+    pub prev_proof: PrevProof,
+    #[serde(default)]
+    pub proof_encoding: ProofEncoding,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,7 +181,10 @@ pub struct ProveUpdateRequest {
 pub struct ProveReceiveTransferRequest {
     pub pubkey: U256,
     pub receive_transfer_witness: ReceiveTransferWitness<F, C, D>,
-    pub prev_proof: Option<ProofWithPublicInputs<F, C, D>>,
+    #[serde(flatten)]
+    pub prev_proof: PrevProof,
+    #[serde(default)]
+    pub proof_encoding: ProofEncoding,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,11 +192,71 @@ pub struct ProveReceiveTransferRequest {
 pub struct ProveReceiveDepositRequest {
     pub pubkey: U256,
     pub receive_deposit_witness: ReceiveDepositWitness,
-    pub prev_proof: Option<ProofWithPublicInputs<F, C, D>>,
+    #[serde(flatten)]
+    pub prev_proof: PrevProof,
+    #[serde(default)]
+    pub proof_encoding: ProofEncoding,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProveSingleWithdrawalRequest {
     pub withdrawal_witness: WithdrawalWitness<F, C, D>,
+    #[serde(default)]
+    pub proof_encoding: ProofEncoding,
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{
+        circuits::balance::send::spent_circuit::SpentCircuit,
+        common::{
+            private_state::FullPrivateState, salt::Salt, transfer::Transfer,
+            trees::transfer_tree::TransferTree, tx::Tx, witness::spent_witness::SpentWitness,
+        },
+        constants::{NUM_TRANSFERS_IN_TX, TRANSFER_TREE_HEIGHT},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_compressed_prove_response_round_trip() {
+        let mut transfer_tree = TransferTree::new(TRANSFER_TREE_HEIGHT);
+        for _ in 0..NUM_TRANSFERS_IN_TX {
+            transfer_tree.push(Transfer::default());
+        }
+        let state = FullPrivateState::new();
+        let tx = Tx {
+            nonce: state.nonce,
+            transfer_tree_root: transfer_tree.get_root(),
+        };
+        let witness = SpentWitness::new(
+            &state.asset_tree,
+            &state.to_private_state(),
+            &transfer_tree.leaves(),
+            tx,
+            Salt::default(),
+        )
+        .unwrap();
+        let spent_circuit = SpentCircuit::<F, C, D>::new();
+        let proof = spent_circuit.prove(&witness.to_value().unwrap()).unwrap();
+        let verifier_data = spent_circuit.data.verifier_data();
+
+        let full = serde_json::to_string(&ProveResponse::full(proof.clone())).unwrap();
+        let compressed = serde_json::to_string(
+            &ProveResponse::new(proof.clone(), ProofEncoding::Compressed, &verifier_data).unwrap(),
+        )
+        .unwrap();
+        assert!(compressed.len() * 2 < full.len());
+
+        let response: ProveResponse = serde_json::from_str(&compressed).unwrap();
+        assert_eq!(response.encoding(), ProofEncoding::Compressed);
+        let decoded = response.decode(&verifier_data).unwrap();
+        assert_eq!(decoded, proof);
+        verifier_data.verify(decoded).unwrap();
+
+        // a previous proof sent by a client that predates compression
+        let prev_proof: PrevProof = serde_json::from_str(r#"{"prevProof":null}"#).unwrap();
+        assert!(prev_proof.decode(&verifier_data).unwrap().is_none());
+    }
 }
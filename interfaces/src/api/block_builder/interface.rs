@@ -4,15 +4,20 @@ use intmax2_zkp::{
         block_builder::BlockProposal, signature::flatten::FlatG2, tx::Tx,
         witness::transfer_witness::TransferWitness,
     },
-    ethereum_types::u256::U256,
+    ethereum_types::{address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait},
 };
 use plonky2::{
     field::goldilocks_field::GoldilocksField,
     plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
 };
+use plonky2_keccak::utils::solidity_keccak256;
 use serde::{Deserialize, Serialize};
 
-use crate::api::error::ServerError;
+use crate::api::{
+    error::ServerError, sponsor::interface::FeeVoucher, withdrawal_server::interface::Fee,
+};
+
+use super::types::QueryProposalRequest;
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
@@ -26,6 +31,49 @@ pub struct FeeProof {
     pub transfer_witness: TransferWitness,
 }
 
+/// The block builder's statement that it proposed the tx `tx_hash` of `pubkey` in
+/// the block of `tx_tree_root`. `signature` is the 65 bytes ECDSA signature of the
+/// Ethereum key the builder posts blocks with over
+/// `proposal_attestation_message(pubkey, tx_hash, tx_tree_root)`. A sender who paid
+/// the builder a fee keeps it as evidence in case the block is never posted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProposalAttestation {
+    pub block_builder: Address,
+    pub pubkey: U256,
+    pub tx_hash: Bytes32,
+    pub tx_tree_root: Bytes32,
+    pub signature: Vec<u8>,
+}
+
+/// Message signed by the block builder: keccak256(pubkey || tx_hash || tx_tree_root)
+pub fn proposal_attestation_message(
+    pubkey: U256,
+    tx_hash: Bytes32,
+    tx_tree_root: Bytes32,
+) -> Bytes32 {
+    let mut input = Vec::new();
+    input.extend_from_slice(&pubkey.to_u32_vec());
+    input.extend_from_slice(&tx_hash.to_u32_vec());
+    input.extend_from_slice(&tx_tree_root.to_u32_vec());
+    Bytes32::from_u32_slice(solidity_keccak256(&input).as_slice())
+}
+
+// domain tag of the cancellation message, so that a signature over it can not
+// sign a tx tree root
+const CANCEL_TX_REQUEST_TAG: &[u8; 28] = b"intmax2-cancel-tx-request-v1";
+
+/// Message the sender signs with its BLS key to cancel the tx request of `tx_hash`:
+/// keccak256(CANCEL_TX_REQUEST_TAG || tx_hash)
+pub fn cancel_tx_request_message(tx_hash: Bytes32) -> Bytes32 {
+    let mut input = CANCEL_TX_REQUEST_TAG
+        .chunks(4)
+        .map(|chunk| u32::from_be_bytes(chunk.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    input.extend_from_slice(&tx_hash.to_u32_vec());
+    Bytes32::from_u32_slice(solidity_keccak256(&input).as_slice())
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum BlockBuilderStatus {
@@ -34,6 +82,15 @@ pub enum BlockBuilderStatus {
     ProposingBlock, // after constructed the block, accepting signatures for the block
 }
 
+/// The block builder's own view of the services it depends on
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockBuilderDependencies {
+    pub validity_prover_ok: bool,
+    pub l1_ok: bool,
+    pub last_block_posted_at: Option<u64>, // unix timestamp, None if no block was posted yet
+}
+
 #[async_trait(?Send)]
 pub trait BlockBuilderClientInterface {
     // Get the status of the block builder
@@ -43,6 +100,15 @@ pub trait BlockBuilderClientInterface {
         is_registration_block: bool,
     ) -> Result<BlockBuilderStatus, ServerError>;
 
+    // Get the health of the block builder's dependencies
+    async fn get_health(
+        &self,
+        block_builder_url: &str,
+    ) -> Result<BlockBuilderDependencies, ServerError>;
+
+    // Get the fees the block builder charges for including a tx, per token
+    async fn get_fee_info(&self, block_builder_url: &str) -> Result<Vec<Fee>, ServerError>;
+
     // Send tx request to the block builder
     async fn send_tx_request(
         &self,
@@ -51,6 +117,17 @@ pub trait BlockBuilderClientInterface {
         pubkey: U256,
         tx: Tx,
         fee_proof: Option<FeeProof>,
+        fee_voucher: Option<FeeVoucher>,
+    ) -> Result<(), ServerError>;
+
+    // Drop the tx request of the tx with `tx_hash` before the block is proposed.
+    // `signature` is the sender's signature over `cancel_tx_request_message(tx_hash)`
+    async fn cancel_tx_request(
+        &self,
+        block_builder_url: &str,
+        tx_hash: Bytes32,
+        pubkey: U256,
+        signature: FlatG2,
     ) -> Result<(), ServerError>;
 
     // Query tx tree root proposal from the block builder
@@ -62,6 +139,23 @@ pub trait BlockBuilderClientInterface {
         tx: Tx,
     ) -> Result<Option<BlockProposal>, ServerError>;
 
+    // Query the proposals of several txs in one request
+    async fn query_proposals(
+        &self,
+        block_builder_url: &str,
+        requests: &[QueryProposalRequest],
+    ) -> Result<Vec<Option<BlockProposal>>, ServerError>;
+
+    // Get the builder's attestation of the proposal for `tx`, None if the proposal
+    // is not ready yet
+    async fn get_proposal_attestation(
+        &self,
+        block_builder_url: &str,
+        is_registration_block: bool,
+        pubkey: U256,
+        tx: Tx,
+    ) -> Result<Option<ProposalAttestation>, ServerError>;
+
     // Send signature to the block builder
     async fn post_signature(
         &self,
@@ -4,7 +4,11 @@ use intmax2_zkp::{
 };
 use serde::{Deserialize, Serialize};
 
-use super::interface::{BlockBuilderStatus, FeeProof};
+use crate::api::{sponsor::interface::FeeVoucher, withdrawal_server::interface::Fee};
+
+use super::interface::{
+    BlockBuilderDependencies, BlockBuilderStatus, FeeProof, ProposalAttestation,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -13,9 +17,20 @@ pub struct TxRequestRequest {
     pub pubkey: U256,
     pub tx: Tx,
     pub fee_proof: Option<FeeProof>,
+    // fee paid by a sponsor instead of the sender
+    #[serde(default)]
+    pub fee_voucher: Option<FeeVoucher>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// body of `DELETE /block-builder/tx-request/{tx_hash}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelTxRequestRequest {
+    pub pubkey: U256,
+    pub signature: FlatG2,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct QueryProposalRequest {
     pub is_registration_block: bool,
@@ -29,6 +44,25 @@ pub struct QueryProposalResponse {
     pub block_proposal: Option<BlockProposal>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetProposalAttestationResponse {
+    pub attestation: Option<ProposalAttestation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryProposalsRequest {
+    pub requests: Vec<QueryProposalRequest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryProposalsResponse {
+    // in the order of the requests, None if the proposal is not ready yet
+    pub block_proposals: Vec<Option<BlockProposal>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PostSignatureRequest {
@@ -49,3 +83,28 @@ pub struct GetBlockBuilderStatusQuery {
 pub struct GetBlockBuilderStatusResponse {
     pub status: BlockBuilderStatus,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBlockBuilderHealthResponse {
+    pub dependencies: BlockBuilderDependencies,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetFeeInfoResponse {
+    pub fees: Vec<Fee>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdvanceTimeRequest {
+    pub seconds: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTimeResponse {
+    pub now: u64,
+    pub is_virtual: bool,
+}
@@ -29,4 +29,13 @@ pub enum ServerError {
 
     #[error("Internal error: {0}")]
     InternalError(String),
+
+    #[error("Payload of {size} bytes exceeds the limit of {max} bytes")]
+    PayloadTooLarge { size: usize, max: usize },
+
+    #[error("Invalid url: {0}")]
+    InvalidUrl(String),
+
+    #[error("Circuit open for {host} after repeated failures, retry after {retry_after}s")]
+    CircuitOpen { host: String, retry_after: u64 },
 }
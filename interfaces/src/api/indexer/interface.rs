@@ -9,6 +9,9 @@ pub struct BlockBuilderInfo {
     pub fee: f64,
     pub speed: u32,
     pub url: String,
+    /// Address the block builder advertises, if any
+    #[serde(default)]
+    pub address: Option<String>,
 }
 
 #[async_trait(?Send)]
@@ -2,6 +2,16 @@ pub mod balance_prover;
 pub mod block_builder;
 pub mod error;
 pub mod indexer;
+pub mod output_stream;
+pub mod sponsor;
 pub mod store_vault_server;
 pub mod validity_prover;
+pub mod version;
+pub mod webhook;
 pub mod withdrawal_server;
+
+/// Response headers the servers expose to browsers besides the CORS-safelisted
+/// ones: `Date`, which the clients measure the skew of their clock with, and
+/// `Retry-After`. They are listed because `Date` is only added after the CORS
+/// middleware ran, so it is not exposed by `Cors::permissive`.
+pub const CORS_EXPOSED_HEADERS: [&str; 2] = ["date", "retry-after"];
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+/// Line printed by a CLI command run with `--output ndjson-stream`. Each event is
+/// written as one JSON object on its own line as soon as it happens, and the last
+/// line of the command is always a `result` or an `error`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum StreamEvent {
+    /// `current` of `total` items of `stage` done. `total` is 0 when the stage has
+    /// no known end, e.g. the tx phases or the blocks followed by the watcher.
+    Progress {
+        stage: ProgressStage,
+        current: u32,
+        total: u32,
+    },
+    /// Something the user should know about that does not stop the command, e.g.
+    /// pending actions or a skewed clock
+    Warning { message: String },
+    /// The command succeeded. `value` is its output, null if it has none.
+    Result { value: serde_json::Value },
+    /// The command failed, with the fields of the errors printed with `--json`
+    #[serde(rename_all = "camelCase")]
+    Error {
+        error: String,
+        message_id: String,
+        message: String,
+    },
+}
+
+impl StreamEvent {
+    /// Whether the event ends the stream
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, StreamEvent::Result { .. } | StreamEvent::Error { .. })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressStage {
+    /// Blocks of the actions incorporated into the balance proof
+    Sync,
+    /// Deposits, transfers and txs incorporated into the balance proof
+    SyncActions,
+    /// Settled withdrawals proved by the sync of the withdrawals
+    WithdrawalSync,
+    /// Sending the tx request to the block builder
+    TxRequest,
+    /// Polling the block builder for the block proposal
+    ProposalWait,
+    /// Signing the proposal and backing up the tx
+    Finalize,
+    /// Actions processed by `recover`
+    Rebuild,
+    /// Blocks processed by `watcher`
+    Watch,
+    /// Withdrawals sent by `claim-withdrawal`
+    Claim,
+    /// Txs of `tx send-batch` included in a block
+    Batch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_event_lines() {
+        let progress = StreamEvent::Progress {
+            stage: ProgressStage::ProposalWait,
+            current: 1,
+            total: 3,
+        };
+        assert_eq!(
+            serde_json::to_string(&progress).unwrap(),
+            r#"{"type":"progress","stage":"proposal_wait","current":1,"total":3}"#
+        );
+        let error: StreamEvent = serde_json::from_str(
+            r#"{"type":"error","error":"timeout","messageId":"error.timeout","message":"Timed out"}"#,
+        )
+        .unwrap();
+        assert!(error.is_terminal());
+        assert!(!progress.is_terminal());
+        let result = StreamEvent::Result {
+            value: serde_json::json!({ "synced": true }),
+        };
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            r#"{"type":"result","value":{"synced":true}}"#
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use async_trait::async_trait;
+use intmax2_zkp::ethereum_types::{
+    address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait,
+};
+use plonky2_keccak::utils::solidity_keccak256;
+use serde::{Deserialize, Serialize};
+
+use crate::api::error::ServerError;
+
+/// A sponsor's promise to pay the block builder fee of the tx `tx_hash` sent by
+/// `pubkey`. `signature` is the 65 bytes ECDSA signature of the sponsor's Ethereum
+/// key over `fee_voucher_message(pubkey, tx_hash)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeVoucher {
+    pub sponsor: Address,
+    pub pubkey: U256,
+    pub tx_hash: Bytes32,
+    pub signature: Vec<u8>,
+}
+
+/// Message signed by the sponsor: keccak256(pubkey || tx_hash)
+pub fn fee_voucher_message(pubkey: U256, tx_hash: Bytes32) -> Bytes32 {
+    let mut input = Vec::new();
+    input.extend_from_slice(&pubkey.to_u32_vec());
+    input.extend_from_slice(&tx_hash.to_u32_vec());
+    Bytes32::from_u32_slice(solidity_keccak256(&input).as_slice())
+}
+
+/// Signing service of a fee sponsor.
+///
+/// Endpoint: `POST {sponsor_url}/sponsor/fee-voucher` with a `RequestFeeVoucherRequest`
+/// body, answered with a `RequestFeeVoucherResponse`. A sponsor that does not pay for
+/// the tx responds with `voucher: null` and a `declineReason`.
+#[async_trait(?Send)]
+pub trait SponsorClientInterface {
+    /// Ask the sponsor to pay the fee of the tx. Returns `None` if the sponsor declines.
+    async fn request_fee_voucher(
+        &self,
+        sponsor_url: &str,
+        pubkey: U256,
+        tx_hash: Bytes32,
+    ) -> Result<Option<FeeVoucher>, ServerError>;
+}
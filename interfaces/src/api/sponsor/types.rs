@@ -0,0 +1,19 @@
+use intmax2_zkp::ethereum_types::{bytes32::Bytes32, u256::U256};
+use serde::{Deserialize, Serialize};
+
+use super::interface::FeeVoucher;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestFeeVoucherRequest {
+    pub pubkey: U256,
+    pub tx_hash: Bytes32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestFeeVoucherResponse {
+    pub voucher: Option<FeeVoucher>,
+    #[serde(default)]
+    pub decline_reason: Option<String>,
+}
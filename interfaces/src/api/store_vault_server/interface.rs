@@ -23,6 +23,17 @@ pub enum DataType {
     Tx,
 }
 
+impl DataType {
+    pub fn all() -> [DataType; 4] {
+        [
+            DataType::Deposit,
+            DataType::Transfer,
+            DataType::Withdrawal,
+            DataType::Tx,
+        ]
+    }
+}
+
 impl ToString for DataType {
     fn to_string(&self) -> String {
         match self {
@@ -3,9 +3,9 @@ use plonky2::{
     field::goldilocks_field::GoldilocksField,
     plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::data::meta_data::MetaData;
+use crate::{api::error::ServerError, data::meta_data::MetaData};
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
@@ -75,3 +75,73 @@ pub struct GetDataAllAfterQuery {
 pub struct GetDataAllAfterResponse {
     pub data: Vec<(MetaData, Vec<u8>)>,
 }
+
+/// Largest encrypted blob the store vault server accepts by default
+pub const DEFAULT_MAX_BLOB_SIZE: usize = 16 * 1024 * 1024;
+
+/// Body of the 4xx responses of the store vault server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreVaultErrorResponse {
+    /// `payload_too_large`, `invalid_param`, `unknown_topic` or `invalid_body`
+    pub error: String,
+    pub message: String,
+    /// Name of the offending path or query parameter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// Accepted values of `field`, for unknown topics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_values: Option<Vec<String>>,
+}
+
+/// Envelope of the blob requests and responses (saves, gets and get-all-afters of
+/// data and user data). The encrypted blobs are the same in both, but JSON writes
+/// each byte as a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WireFormat {
+    Json,
+    Bincode,
+}
+
+impl WireFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            WireFormat::Json => "application/json",
+            WireFormat::Bincode => "application/octet-stream",
+        }
+    }
+
+    /// Bincode for `application/octet-stream`, JSON for anything else
+    pub fn from_content_type(content_type: &str) -> Self {
+        if content_type.starts_with(WireFormat::Bincode.content_type()) {
+            WireFormat::Bincode
+        } else {
+            WireFormat::Json
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, ServerError> {
+        match self {
+            WireFormat::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            WireFormat::Bincode => bincode::serialize(value).map_err(|e| e.to_string()),
+        }
+        .map_err(ServerError::SerializeError)
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, ServerError> {
+        match self {
+            WireFormat::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            WireFormat::Bincode => bincode::deserialize(bytes).map_err(|e| e.to_string()),
+        }
+        .map_err(ServerError::DeserializationError)
+    }
+}
+
+/// Response of `/store-vault-server/info`. Servers without it only speak JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreVaultInfo {
+    pub wire_formats: Vec<WireFormat>,
+    pub max_blob_size: usize,
+}
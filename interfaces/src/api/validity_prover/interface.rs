@@ -3,8 +3,8 @@ use intmax2_zkp::{
     circuits::validity::validity_pis::ValidityPublicInputs,
     common::{
         trees::{
-            block_hash_tree::BlockHashMerkleProof, deposit_tree::DepositMerkleProof,
-            sender_tree::SenderLeaf,
+            account_tree::AccountMembershipProof, block_hash_tree::BlockHashMerkleProof,
+            deposit_tree::DepositMerkleProof, sender_tree::SenderLeaf,
         },
         witness::update_witness::UpdateWitness,
     },
@@ -46,6 +46,14 @@ pub trait ValidityProverClientInterface {
         is_prev_account_tree: bool,
     ) -> Result<UpdateWitness<F, C, D>, ServerError>;
 
+    /// Returns only the account-specific part of the update witness, for callers that
+    /// already have the validity proof and the block merkle proof.
+    async fn get_account_membership_proof(
+        &self,
+        pubkey: U256,
+        block_number: u32,
+    ) -> Result<AccountMembershipProof, ServerError>;
+
     async fn get_deposit_info(
         &self,
         deposit_hash: Bytes32,
@@ -4,8 +4,8 @@ use intmax2_zkp::{
     circuits::validity::validity_pis::ValidityPublicInputs,
     common::{
         trees::{
-            block_hash_tree::BlockHashMerkleProof, deposit_tree::DepositMerkleProof,
-            sender_tree::SenderLeaf,
+            account_tree::AccountMembershipProof, block_hash_tree::BlockHashMerkleProof,
+            deposit_tree::DepositMerkleProof, sender_tree::SenderLeaf,
         },
         witness::update_witness::UpdateWitness,
     },
@@ -38,6 +38,19 @@ pub struct GetUpdateWitnessResponse {
     pub update_witness: UpdateWitness<F, C, D>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAccountMembershipProofQuery {
+    pub pubkey: U256,
+    pub block_number: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAccountMembershipProofResponse {
+    pub account_membership_proof: AccountMembershipProof,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetDepositInfoQuery {
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// Version of the API between the clients and the servers, raised on each
+/// breaking change
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version the clients of this build can talk to
+pub const MIN_SUPPORTED_PROTOCOL_VERSION: u32 = 1;
+
+/// Response of the `/version` endpoint of each server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionResponse {
+    pub service: String,
+    /// Package version of the server
+    pub version: String,
+    pub protocol_version: u32,
+    /// Oldest client package version the server accepts, if it requires one
+    pub min_client_version: Option<String>,
+}
+
+impl VersionResponse {
+    /// Of the server `service` of package `version`, requiring the clients given
+    /// by `MIN_CLIENT_VERSION`
+    pub fn new(service: &str, version: &str) -> Self {
+        Self {
+            service: service.to_string(),
+            version: version.to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            min_client_version: std::env::var("MIN_CLIENT_VERSION")
+                .ok()
+                .filter(|version| !version.is_empty()),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use hmac::{Hmac, Mac as _};
+use intmax2_zkp::ethereum_types::{address::Address, bytes32::Bytes32, u256::U256};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Header carrying `sha256=<hex of HMAC-SHA256(secret, body)>`
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Intmax-Signature";
+
+/// Notification the client posts to the webhook url of an account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookEvent {
+    /// Stays the same when the event is delivered again, for deduplication
+    pub id: String,
+    pub pubkey: U256,
+    /// Unix time at which the client detected the event
+    pub created_at: u64,
+    #[serde(flatten)]
+    pub payload: WebhookPayload,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum WebhookPayload {
+    /// Sent by `webhook test`
+    Ping,
+    #[serde(rename_all = "camelCase")]
+    IncomingTransfer {
+        from: U256,
+        token_index: u32,
+        amount: U256,
+        timestamp: Option<u64>,
+    },
+    #[serde(rename_all = "camelCase")]
+    WithdrawalSettled {
+        recipient: Address,
+        token_index: u32,
+        amount: U256,
+        nullifier: Bytes32,
+    },
+    /// A tx of the account was rejected
+    #[serde(rename_all = "camelCase")]
+    TxFailed {
+        num_transfers: u32,
+        timestamp: Option<u64>,
+    },
+}
+
+fn webhook_mac(secret: &str, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac
+}
+
+/// Value of the `X-Intmax-Signature` header for `body`
+pub fn sign_webhook_body(secret: &str, body: &[u8]) -> String {
+    let signature = webhook_mac(secret, body).finalize().into_bytes();
+    format!("sha256={}", hex::encode(signature))
+}
+
+/// Check the `X-Intmax-Signature` header of a received webhook, in constant time
+pub fn verify_webhook_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(signature) = signature
+        .strip_prefix("sha256=")
+        .and_then(|signature| hex::decode(signature).ok())
+    else {
+        return false;
+    };
+    webhook_mac(secret, body).verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_signature() {
+        let event = WebhookEvent {
+            id: "1".to_string(),
+            pubkey: U256::default(),
+            created_at: 0,
+            payload: WebhookPayload::TxFailed {
+                num_transfers: 2,
+                timestamp: None,
+            },
+        };
+        let body = serde_json::to_vec(&event).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["type"], "txFailed");
+        assert_eq!(value["numTransfers"], 2);
+
+        let signature = sign_webhook_body("secret", &body);
+        assert!(verify_webhook_signature("secret", &body, &signature));
+        assert!(!verify_webhook_signature("other", &body, &signature));
+        assert!(!verify_webhook_signature("secret", b"{}", &signature));
+    }
+}
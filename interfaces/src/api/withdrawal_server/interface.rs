@@ -27,11 +27,74 @@ pub struct Fee {
     pub coefficient: f64,
 }
 
+/// Minimum amount of a token, in its smallest unit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenLimit {
+    pub token_index: u32,
+    pub amount: U256,
+}
+
+/// Limits enforced by the withdrawal server (aggregator). Tokens without an entry
+/// have no minimum.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawalLimits {
+    pub min_withdrawals: Vec<TokenLimit>,
+    pub min_deposits: Vec<TokenLimit>,
+    pub max_withdrawals_per_request: u32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct WithdrawalInfo {
     pub status: WithdrawalStatus,
     pub contract_withdrawal: ContractWithdrawal,
+    /// Hash of the attribution tag the owner gave the withdrawal, see
+    /// `attribution_tag_hash`
+    #[serde(default)]
+    pub attribution_tag_hash: Option<Bytes32>,
+    /// Unix timestamp of the request, from which the client waits for the
+    /// withdrawal to appear on chain
+    #[serde(default)]
+    pub requested_at: Option<u64>,
+}
+
+/// Maximum length in bytes of an attribution tag
+pub const MAX_ATTRIBUTION_TAG_LEN: usize = 64;
+
+/// Check that `tag` can be used as the attribution tag of a withdrawal: a short
+/// printable ascii string, e.g. the memo code an exchange gives its users
+pub fn validate_attribution_tag(tag: &str) -> Result<(), String> {
+    if tag.is_empty() {
+        return Err("attribution tag is empty".to_string());
+    }
+    if tag.len() > MAX_ATTRIBUTION_TAG_LEN {
+        return Err(format!(
+            "attribution tag is {} bytes, at most {} are allowed",
+            tag.len(),
+            MAX_ATTRIBUTION_TAG_LEN
+        ));
+    }
+    if !tag.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err("attribution tag must be printable ascii without spaces".to_string());
+    }
+    Ok(())
+}
+
+/// Hash published with a withdrawal instead of its attribution tag. Whoever knows
+/// the tag, e.g. the exchange that issued it, can recompute the hash from the
+/// public nullifier of the withdrawal, while the nullifier keeps the same tag
+/// from hashing the same way across withdrawals.
+pub fn attribution_tag_hash(nullifier: Bytes32, tag: &str) -> Bytes32 {
+    let mut input = nullifier.to_u32_vec();
+    input.push(tag.len() as u32);
+    input.extend(tag.as_bytes().chunks(4).map(|chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        u32::from_be_bytes(word)
+    }));
+    Bytes32::from_u32_slice(solidity_keccak256(&input).as_slice())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,12 +141,18 @@ impl Display for WithdrawalStatus {
 
 #[async_trait(?Send)]
 pub trait WithdrawalServerClientInterface {
+    // URL of the withdrawal server, which identifies it in the caches
+    fn base_url(&self) -> &str;
+
     async fn fee(&self) -> Result<Vec<Fee>, ServerError>;
 
+    async fn limits(&self) -> Result<WithdrawalLimits, ServerError>;
+
     async fn request_withdrawal(
         &self,
         pubkey: U256,
         single_withdrawal_proof: &ProofWithPublicInputs<F, C, D>,
+        attribution_tag_hash: Option<Bytes32>,
     ) -> Result<(), ServerError>;
 
     async fn get_withdrawal_info(&self, key: KeySet) -> Result<Vec<WithdrawalInfo>, ServerError>;
@@ -93,3 +162,55 @@ pub trait WithdrawalServerClientInterface {
         recipient: Address,
     ) -> Result<Vec<WithdrawalInfo>, ServerError>;
 }
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::ethereum_types::{
+        address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _,
+    };
+
+    use super::{
+        attribution_tag_hash, validate_attribution_tag, ContractWithdrawal, WithdrawalInfo,
+        WithdrawalStatus,
+    };
+
+    #[test]
+    fn test_attribution_tag_hash() {
+        let nullifier = Bytes32::from_u32_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let other_nullifier = Bytes32::from_u32_slice(&[8, 7, 6, 5, 4, 3, 2, 1]);
+        let hash = attribution_tag_hash(nullifier, "EX-12345");
+        assert_eq!(hash, attribution_tag_hash(nullifier, "EX-12345"));
+        assert_ne!(hash, attribution_tag_hash(nullifier, "EX-12346"));
+        assert_ne!(hash, attribution_tag_hash(other_nullifier, "EX-12345"));
+        // the length is hashed, so zero padding does not collide
+        assert_ne!(
+            attribution_tag_hash(nullifier, "A"),
+            attribution_tag_hash(nullifier, "A\0")
+        );
+
+        assert!(validate_attribution_tag("EX-12345").is_ok());
+        assert!(validate_attribution_tag("").is_err());
+        assert!(validate_attribution_tag("two words").is_err());
+        assert!(validate_attribution_tag(&"A".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_withdrawal_info_without_tag_hash() {
+        let info = WithdrawalInfo {
+            status: WithdrawalStatus::Requested,
+            contract_withdrawal: ContractWithdrawal {
+                recipient: Address::default(),
+                token_index: 0,
+                amount: U256::default(),
+                nullifier: Bytes32::default(),
+            },
+            attribution_tag_hash: Some(Bytes32::default()),
+            requested_at: None,
+        };
+        // as returned by a server that does not know tags
+        let mut json = serde_json::to_value(&info).unwrap();
+        json.as_object_mut().unwrap().remove("attributionTagHash");
+        let info: WithdrawalInfo = serde_json::from_value(json).unwrap();
+        assert!(info.attribution_tag_hash.is_none());
+    }
+}
@@ -1,6 +1,6 @@
 use intmax2_zkp::{
     common::signature::flatten::FlatG2,
-    ethereum_types::{address::Address, u256::U256},
+    ethereum_types::{address::Address, bytes32::Bytes32, u256::U256},
 };
 use plonky2::{
     field::goldilocks_field::GoldilocksField,
@@ -8,7 +8,7 @@ use plonky2::{
 };
 use serde::{Deserialize, Serialize};
 
-use super::interface::{Fee, WithdrawalInfo};
+use super::interface::{Fee, WithdrawalInfo, WithdrawalLimits};
 
 type F = GoldilocksField;
 type C = PoseidonGoldilocksConfig;
@@ -19,6 +19,8 @@ const D: usize = 2;
 pub struct RequestWithdrawalRequest {
     pub pubkey: U256,
     pub single_withdrawal_proof: ProofWithPublicInputs<F, C, D>,
+    #[serde(default)]
+    pub attribution_tag_hash: Option<Bytes32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +29,12 @@ pub struct GetFeeResponse {
     pub fees: Vec<Fee>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetLimitsResponse {
+    pub limits: WithdrawalLimits,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetWithdrawalInfoRequest {
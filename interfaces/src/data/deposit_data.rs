@@ -9,7 +9,9 @@ use intmax2_zkp::{
         salt::Salt,
         signature::key_set::KeySet,
     },
-    ethereum_types::{address::Address, bytes32::Bytes32, u256::U256},
+    ethereum_types::{
+        address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _,
+    },
     utils::leafable::Leafable,
 };
 
@@ -25,20 +27,45 @@ pub struct DepositData {
     // token info
     pub token_type: TokenType,
     pub token_address: Address,
-    pub token_id: U256,
+    pub token_id: U256, // zero for fungible tokens
 
     pub token_index: Option<u32>, // The index of the token in the contract
 }
 
-#[derive(Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum TokenType {
+    #[default]
     NATIVE = 0,
     ERC20 = 1,
     ERC721 = 2,
     ERC1155 = 3,
 }
 
+impl TokenType {
+    /// Whether each token id of the contract is a distinct token
+    pub fn is_nft(&self) -> bool {
+        matches!(self, Self::ERC721 | Self::ERC1155)
+    }
+
+    /// Check that `amount` and `token_id` make sense for the token type: an ERC721
+    /// token is moved whole, and fungible tokens have no id.
+    pub fn check_amount(&self, amount: U256, token_id: U256) -> Result<(), String> {
+        if amount == U256::default() {
+            return Err("amount is zero".to_string());
+        }
+        match self {
+            Self::NATIVE | Self::ERC20 if token_id != U256::default() => {
+                Err(format!("{:?} token with token id {}", self, token_id))
+            }
+            Self::ERC721 if amount != U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, 1]) => {
+                Err(format!("ERC721 amount must be 1, got {}", amount))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 impl FromStr for TokenType {
     type Err = anyhow::Error;
 
@@ -1,11 +1,121 @@
 use intmax2_zkp::{common::signature::key_set::KeySet, ethereum_types::u256::U256};
 
-pub(super) fn encrypt(_pubkey: U256, data: &[u8]) -> Vec<u8> {
+/// Version of the encryption scheme. Bump it when the scheme changes, so that
+/// locally cached data (e.g. history indexes) is rebuilt. Version 0 is the
+/// placeholder below, which does not encrypt.
+pub const ENCRYPTION_VERSION: u32 = 0;
+
+pub fn encrypt(_pubkey: U256, data: &[u8]) -> Vec<u8> {
     // todo: add encryption
     data.to_vec()
 }
 
-pub(super) fn decrypt(_key: KeySet, encypted_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+pub fn decrypt(_key: KeySet, encypted_data: &[u8]) -> anyhow::Result<Vec<u8>> {
     // todo: add decryption
     Ok(encypted_data.to_vec())
 }
+
+/// Bytes of decrypted data handed out per step of `ChunkedDecryption`
+pub const DECRYPT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// `decrypt` run in steps, so that the caller can report progress or yield between
+/// them. The first step decrypts the whole blob at once, since decrypting slices of
+/// it separately only equals `decrypt` for a streaming scheme. Each step, the first
+/// included, then hands out the next `DECRYPT_CHUNK_SIZE` bytes of the decrypted
+/// data. The result is the one of `decrypt`.
+pub struct ChunkedDecryption<'a> {
+    key: KeySet,
+    encrypted_data: &'a [u8],
+    decrypted: Option<Vec<u8>>,
+    offset: usize,
+}
+
+impl<'a> ChunkedDecryption<'a> {
+    pub fn new(key: KeySet, encrypted_data: &'a [u8]) -> Self {
+        Self {
+            key,
+            encrypted_data,
+            decrypted: None,
+            offset: 0,
+        }
+    }
+
+    /// Take the next step. Returns false if everything was already handed out.
+    pub fn next_chunk(&mut self) -> anyhow::Result<bool> {
+        if self.decrypted.is_none() {
+            self.decrypted = Some(decrypt(self.key, self.encrypted_data)?);
+        }
+        let len = self.decrypted.as_ref().map_or(0, Vec::len);
+        if self.offset >= len {
+            return Ok(false);
+        }
+        self.offset = (self.offset + DECRYPT_CHUNK_SIZE).min(len);
+        Ok(true)
+    }
+
+    /// Percent of the decrypted data handed out so far
+    pub fn percent(&self) -> u32 {
+        match &self.decrypted {
+            None => 0,
+            Some(decrypted) if decrypted.is_empty() => 100,
+            Some(decrypted) => (self.offset * 100 / decrypted.len()) as u32,
+        }
+    }
+
+    /// The decrypted data, once `next_chunk` returned false
+    pub fn finish(self) -> Vec<u8> {
+        self.decrypted.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunked_decryption() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let data = (0..DECRYPT_CHUNK_SIZE * 5 / 2)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let encrypted = encrypt(key.pubkey, &data);
+
+        let mut decryption = ChunkedDecryption::new(key, &encrypted);
+        assert_eq!(decryption.percent(), 0);
+        let mut percents = Vec::new();
+        while decryption.next_chunk().unwrap() {
+            percents.push(decryption.percent());
+        }
+        assert_eq!(percents, vec![40, 80, 100]);
+        assert_eq!(decryption.finish(), decrypt(key, &encrypted).unwrap());
+
+        let mut empty = ChunkedDecryption::new(key, &[]);
+        assert!(!empty.next_chunk().unwrap());
+        assert_eq!(empty.percent(), 100);
+        assert!(empty.finish().is_empty());
+    }
+
+    #[test]
+    fn test_chunked_decryption_equals_decrypt() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let sizes = [
+            1,
+            DECRYPT_CHUNK_SIZE - 1,
+            DECRYPT_CHUNK_SIZE,
+            DECRYPT_CHUNK_SIZE + 1,
+            DECRYPT_CHUNK_SIZE * 3,
+        ];
+        for size in sizes {
+            let data = (0..size).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+            let encrypted = encrypt(key.pubkey, &data);
+            let mut decryption = ChunkedDecryption::new(key, &encrypted);
+            while decryption.next_chunk().unwrap() {}
+            assert_eq!(
+                decryption.finish(),
+                decrypt(key, &encrypted).unwrap(),
+                "chunked decryption of {} bytes differs from decrypt",
+                size
+            );
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// Name of the manifest file in a fixture directory
+pub const FIXTURE_MANIFEST_FILE: &str = "manifest.json";
+
+/// Describes the files of a fixture directory written by the `gen-fixtures` binary.
+/// The committed fixtures live in `interfaces/test-assets/fixtures`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureManifest {
+    pub seed: u64,
+    pub keys: Vec<FixtureKey>,
+    pub blobs: Vec<FixtureBlob>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureKey {
+    pub name: String,
+    pub privkey: String, // 32 bytes hex
+    pub pubkey: String,  // 32 bytes hex
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FixtureDataType {
+    Deposit,
+    Transfer,
+    Tx,
+    UserData,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureBlob {
+    /// File name of the encrypted blob
+    pub file: String,
+    pub data_type: FixtureDataType,
+    /// Name of the key in `keys` to decrypt with
+    pub key: String,
+    /// File name of the decrypted JSON. `None` for negative fixtures, which must
+    /// fail to decrypt.
+    pub decrypted: Option<String>,
+    pub description: String,
+}
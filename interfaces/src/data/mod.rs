@@ -1,7 +1,9 @@
 pub mod common_tx_data;
 pub mod deposit_data;
 pub mod encryption;
+pub mod fixtures;
 pub mod meta_data;
 pub mod transfer_data;
 pub mod tx_data;
 pub mod user_data;
+pub mod versioned;
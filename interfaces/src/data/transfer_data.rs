@@ -14,8 +14,12 @@ use intmax2_zkp::{
 use super::{
     common_tx_data::CommonTxData,
     encryption::{decrypt, encrypt},
+    versioned::{decode_versioned, encode_versioned},
 };
 
+/// Version of the encoding of `TransferData`, see `versioned`
+const TRANSFER_DATA_VERSION: u8 = 1;
+
 // backup data for receiving transfers
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -37,6 +41,14 @@ where
     pub transfer: Transfer,
     pub transfer_index: u32,
     pub transfer_merkle_proof: TransferMerkleProof,
+
+    // Block number after which the recipient refuses to receive the transfer.
+    // This is an application-level agreement, and is not enforced on chain.
+    pub claim_deadline_block: Option<u32>,
+
+    // Attribution tag of a withdrawal, only kept in the sender's backup. The
+    // withdrawal server gets its hash.
+    pub attribution_tag: Option<String>,
 }
 
 impl<F, C, const D: usize> TransferData<F, C, D>
@@ -45,12 +57,14 @@ where
     C: GenericConfig<D, F = F>,
 {
     fn to_bytes(&self) -> Vec<u8> {
-        bincode::serialize(self).unwrap()
+        encode_versioned(TRANSFER_DATA_VERSION, self)
     }
 
     fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
-        let data = bincode::deserialize(bytes)?;
-        Ok(data)
+        decode_versioned::<Self, TransferDataV0<F, C, D>>(bytes, |version, body| match version {
+            TRANSFER_DATA_VERSION => Ok(bincode::deserialize(body)?),
+            _ => anyhow::bail!("unsupported transfer data version {}", version),
+        })
     }
 
     pub fn encrypt(&self, pubkey: U256) -> Vec<u8> {
@@ -64,6 +78,11 @@ where
         Ok(data)
     }
 
+    /// Returns true if the transfer can no longer be received at `block_number`
+    pub fn is_expired(&self, block_number: u32) -> bool {
+        is_claim_expired(self.claim_deadline_block, block_number)
+    }
+
     pub fn validate(&self, _key: KeySet) -> anyhow::Result<()> {
         self.tx_data
             .validate()
@@ -78,3 +97,165 @@ where
         Ok(())
     }
 }
+
+// `TransferData` written before versioning
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct TransferDataV0<F, C, const D: usize>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    sender: U256,
+    prev_block_number: u32,
+    prev_private_commitment: PoseidonHashOut,
+    tx_data: CommonTxData<F, C, D>,
+    transfer: Transfer,
+    transfer_index: u32,
+    transfer_merkle_proof: TransferMerkleProof,
+}
+
+impl<F, C, const D: usize> From<TransferDataV0<F, C, D>> for TransferData<F, C, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    fn from(data: TransferDataV0<F, C, D>) -> Self {
+        Self {
+            sender: data.sender,
+            prev_block_number: data.prev_block_number,
+            prev_private_commitment: data.prev_private_commitment,
+            tx_data: data.tx_data,
+            transfer: data.transfer,
+            transfer_index: data.transfer_index,
+            transfer_merkle_proof: data.transfer_merkle_proof,
+            claim_deadline_block: None,
+            attribution_tag: None,
+        }
+    }
+}
+
+/// Returns true if a transfer with the given claim deadline can no longer be received
+/// at `block_number`. Transfers without a deadline never expire.
+pub fn is_claim_expired(claim_deadline_block: Option<u32>, block_number: u32) -> bool {
+    match claim_deadline_block {
+        Some(deadline) => block_number > deadline,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{
+        common::{
+            private_state::FullPrivateState,
+            trees::{transfer_tree::TransferTree, tx_tree::TxTree},
+            tx::Tx,
+        },
+        constants::{TRANSFER_TREE_HEIGHT, TX_TREE_HEIGHT},
+    };
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField,
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_builder::CircuitBuilder, circuit_data::CircuitConfig,
+            config::PoseidonGoldilocksConfig,
+        },
+    };
+    use serde::Serialize;
+
+    use super::*;
+
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+    const D: usize = 2;
+
+    // `TransferData` as it was before versioning
+    #[derive(Serialize)]
+    #[serde(bound = "")]
+    struct BaselineTransferData {
+        sender: U256,
+        prev_block_number: u32,
+        prev_private_commitment: PoseidonHashOut,
+        tx_data: CommonTxData<F, C, D>,
+        transfer: Transfer,
+        transfer_index: u32,
+        transfer_merkle_proof: TransferMerkleProof,
+    }
+
+    fn baseline_transfer_data() -> BaselineTransferData {
+        let transfer = Transfer::default();
+        let mut transfer_tree = TransferTree::new(TRANSFER_TREE_HEIGHT);
+        transfer_tree.push(transfer.clone());
+        let tx = Tx {
+            nonce: 0,
+            transfer_tree_root: transfer_tree.get_root(),
+        };
+        let mut tx_tree = TxTree::new(TX_TREE_HEIGHT);
+        tx_tree.push(tx);
+        // the spent proof is not verified on decryption, so any proof will do
+        let spent_proof = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config())
+            .build::<C>()
+            .prove(PartialWitness::new())
+            .unwrap();
+        BaselineTransferData {
+            sender: U256::from(1u128),
+            prev_block_number: 0,
+            prev_private_commitment: FullPrivateState::new().to_private_state().commitment(),
+            tx_data: CommonTxData {
+                spent_proof,
+                sender_prev_block_number: 0,
+                tx,
+                tx_index: 0,
+                tx_merkle_proof: tx_tree.prove(0),
+                tx_tree_root: tx_tree.get_root().into(),
+            },
+            transfer,
+            transfer_index: 0,
+            transfer_merkle_proof: transfer_tree.prove(0),
+        }
+    }
+
+    #[test]
+    fn test_decode_baseline_backup() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let baseline = baseline_transfer_data();
+        let bytes = encrypt(key.pubkey, &bincode::serialize(&baseline).unwrap());
+
+        let data = TransferData::<F, C, D>::decrypt(&bytes, key).unwrap();
+        assert_eq!(data.sender, baseline.sender);
+        assert_eq!(data.transfer_index, baseline.transfer_index);
+        assert_eq!(data.claim_deadline_block, None);
+
+        // the backups written now keep the claim deadline
+        let data = TransferData {
+            claim_deadline_block: Some(10),
+            ..data
+        };
+        let data = TransferData::<F, C, D>::decrypt(&data.encrypt(key.pubkey), key).unwrap();
+        assert_eq!(data.claim_deadline_block, Some(10));
+    }
+
+    #[test]
+    fn test_attribution_tag_of_baseline_backup() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let bytes = bincode::serialize(&baseline_transfer_data()).unwrap();
+        let data = TransferData::<F, C, D>::decrypt(&encrypt(key.pubkey, &bytes), key).unwrap();
+        assert_eq!(data.attribution_tag, None);
+
+        let data = TransferData {
+            attribution_tag: Some("memo-123".to_string()),
+            ..data
+        };
+        let data = TransferData::<F, C, D>::decrypt(&data.encrypt(key.pubkey), key).unwrap();
+        assert_eq!(data.attribution_tag.as_deref(), Some("memo-123"));
+    }
+
+    #[test]
+    fn test_is_claim_expired() {
+        assert!(!is_claim_expired(None, u32::MAX));
+        assert!(!is_claim_expired(Some(10), 9));
+        assert!(!is_claim_expired(Some(10), 10));
+        assert!(is_claim_expired(Some(10), 11));
+    }
+}
@@ -4,11 +4,39 @@ use plonky2::{
 use serde::{Deserialize, Serialize};
 
 use intmax2_zkp::{
-    common::{signature::key_set::KeySet, witness::spent_witness::SpentWitness},
+    common::{
+        block_builder::BlockProposal, signature::key_set::KeySet,
+        witness::spent_witness::SpentWitness,
+    },
     ethereum_types::u256::U256,
 };
 
-use super::{common_tx_data::CommonTxData, encryption::{decrypt, encrypt}};
+use crate::api::block_builder::interface::ProposalAttestation;
+
+use super::{
+    common_tx_data::CommonTxData,
+    encryption::{decrypt, encrypt, ChunkedDecryption},
+    versioned::{decode_versioned, encode_versioned},
+};
+
+/// Version of the encoding of `TxData`, see `versioned`
+const TX_DATA_VERSION: u8 = 1;
+
+/// Phase reported by `TxData::decrypt_with_progress`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecryptPhase {
+    Decrypt,
+    Parse,
+}
+
+impl DecryptPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DecryptPhase::Decrypt => "decrypt",
+            DecryptPhase::Parse => "parse",
+        }
+    }
+}
 
 // tx data for sender
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -21,6 +49,27 @@ where
 {
     pub common: CommonTxData<F, C, D>,
     pub spent_witness: SpentWitness, // to update sender's private state
+
+    // Claim deadline given to the transfers of this tx
+    pub claim_deadline_block: Option<u32>,
+
+    // Block builder fee paid by one of the transfers of this tx
+    pub fee: Option<TxFee>,
+}
+
+/// Fee transfer of a tx to its block builder. The builder collects it only if it
+/// posts the block, so the proposal and the builder's attestation of it are kept
+/// as evidence in case it does not.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxFee {
+    pub block_builder_url: String,
+    /// Index of the fee transfer in `spent_witness.transfers`
+    pub transfer_index: u32,
+    /// Proposal the sender signed
+    pub proposal: BlockProposal,
+    /// The block builder's signature of the proposal
+    pub attestation: ProposalAttestation,
 }
 
 impl<F, C, const D: usize> TxData<F, C, D>
@@ -29,12 +78,14 @@ where
     C: GenericConfig<D, F = F>,
 {
     fn to_bytes(&self) -> Vec<u8> {
-        bincode::serialize(self).unwrap()
+        encode_versioned(TX_DATA_VERSION, self)
     }
 
     fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
-        let data = bincode::deserialize(bytes)?;
-        Ok(data)
+        decode_versioned::<Self, TxDataV0<F, C, D>>(bytes, |version, body| match version {
+            TX_DATA_VERSION => Ok(bincode::deserialize(body)?),
+            _ => anyhow::bail!("unsupported tx data version {}", version),
+        })
     }
 
     pub fn encrypt(&self, pubkey: U256) -> Vec<u8> {
@@ -43,7 +94,32 @@ where
 
     pub fn decrypt(bytes: &[u8], key: KeySet) -> anyhow::Result<Self> {
         let data = decrypt(key, bytes)?;
-        let data = Self::from_bytes(&data)?;
+        Self::parse_phase(&data, key)
+    }
+
+    /// Same as `decrypt`, in the steps of `ChunkedDecryption`. `on_progress` is
+    /// called with the phase and the percent done at the start of each phase, after
+    /// each step of the decryption, and at the end of the parse.
+    pub fn decrypt_with_progress(
+        bytes: &[u8],
+        key: KeySet,
+        mut on_progress: impl FnMut(DecryptPhase, u32),
+    ) -> anyhow::Result<Self> {
+        on_progress(DecryptPhase::Decrypt, 0);
+        let mut decryption = ChunkedDecryption::new(key, bytes);
+        while decryption.next_chunk()? {
+            on_progress(DecryptPhase::Decrypt, decryption.percent());
+        }
+        on_progress(DecryptPhase::Parse, 0);
+        let data = Self::parse_phase(&decryption.finish(), key)?;
+        on_progress(DecryptPhase::Parse, 100);
+        Ok(data)
+    }
+
+    /// Second phase of `decrypt`, which deserializes and validates the decrypted
+    /// data
+    pub fn parse_phase(bytes: &[u8], key: KeySet) -> anyhow::Result<Self> {
+        let data = Self::from_bytes(bytes)?;
         data.validate(key)?;
         Ok(data)
     }
@@ -53,3 +129,127 @@ where
         Ok(())
     }
 }
+
+// `TxData` written before versioning
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct TxDataV0<F, C, const D: usize>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    common: CommonTxData<F, C, D>,
+    spent_witness: SpentWitness,
+}
+
+impl<F, C, const D: usize> From<TxDataV0<F, C, D>> for TxData<F, C, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    fn from(data: TxDataV0<F, C, D>) -> Self {
+        Self {
+            common: data.common,
+            spent_witness: data.spent_witness,
+            claim_deadline_block: None,
+            fee: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{
+        common::{
+            private_state::FullPrivateState,
+            salt::Salt,
+            transfer::Transfer,
+            trees::{transfer_tree::TransferTree, tx_tree::TxTree},
+            tx::Tx,
+        },
+        constants::{NUM_TRANSFERS_IN_TX, TRANSFER_TREE_HEIGHT, TX_TREE_HEIGHT},
+    };
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField,
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_builder::CircuitBuilder, circuit_data::CircuitConfig,
+            config::PoseidonGoldilocksConfig,
+        },
+    };
+    use serde::Serialize;
+
+    use super::*;
+
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+    const D: usize = 2;
+
+    // `TxData` as it was before versioning
+    #[derive(Serialize)]
+    #[serde(bound = "")]
+    struct BaselineTxData {
+        common: CommonTxData<F, C, D>,
+        spent_witness: SpentWitness,
+    }
+
+    fn baseline_tx_data() -> BaselineTxData {
+        let mut rng = rand::thread_rng();
+        let transfers = vec![Transfer::default(); NUM_TRANSFERS_IN_TX];
+        let mut transfer_tree = TransferTree::new(TRANSFER_TREE_HEIGHT);
+        for transfer in &transfers {
+            transfer_tree.push(transfer.clone());
+        }
+        let state = FullPrivateState::new();
+        let tx = Tx {
+            nonce: state.nonce,
+            transfer_tree_root: transfer_tree.get_root(),
+        };
+        let spent_witness = SpentWitness::new(
+            &state.asset_tree,
+            &state.to_private_state(),
+            &transfer_tree.leaves(),
+            tx,
+            Salt::rand(&mut rng),
+        )
+        .unwrap();
+        let mut tx_tree = TxTree::new(TX_TREE_HEIGHT);
+        tx_tree.push(tx);
+        // the spent proof is not verified on decryption, so any proof will do
+        let spent_proof = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config())
+            .build::<C>()
+            .prove(PartialWitness::new())
+            .unwrap();
+        BaselineTxData {
+            common: CommonTxData {
+                spent_proof,
+                sender_prev_block_number: 0,
+                tx,
+                tx_index: 0,
+                tx_merkle_proof: tx_tree.prove(0),
+                tx_tree_root: tx_tree.get_root().into(),
+            },
+            spent_witness,
+        }
+    }
+
+    #[test]
+    fn test_decode_baseline_backup() {
+        let key = KeySet::rand(&mut rand::thread_rng());
+        let baseline = baseline_tx_data();
+        let bytes = encrypt(key.pubkey, &bincode::serialize(&baseline).unwrap());
+
+        let data = TxData::<F, C, D>::decrypt(&bytes, key).unwrap();
+        assert_eq!(data.common.tx_tree_root, baseline.common.tx_tree_root);
+        assert_eq!(data.claim_deadline_block, None);
+        assert!(data.fee.is_none());
+
+        // the backups written now keep the claim deadline
+        let data = TxData {
+            claim_deadline_block: Some(10),
+            ..data
+        };
+        let data = TxData::<F, C, D>::decrypt(&data.encrypt(key.pubkey), key).unwrap();
+        assert_eq!(data.claim_deadline_block, Some(10));
+    }
+}
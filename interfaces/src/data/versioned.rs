@@ -0,0 +1,119 @@
+//! Versioned encoding of the backup data.
+//!
+//! The backup data is bincode-encoded, which is positional: a field added to a type
+//! changes its encoding, and `#[serde(default)]` has no effect on it. So the
+//! encoding of a type is prefixed with `VERSION_MAGIC` and the version of the type,
+//! and a type whose fields change keeps a legacy struct per previous version to
+//! decode the backups written before.
+//!
+//! Backups written before versioning have no prefix, and start with the bincode
+//! encoding of their first field.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Prefix of versioned data
+pub const VERSION_MAGIC: [u8; 4] = [0xff, b'I', b'M', b'X'];
+
+/// Encode `data` as version `version` of its type
+pub fn encode_versioned<T: Serialize>(version: u8, data: &T) -> Vec<u8> {
+    let mut bytes = VERSION_MAGIC.to_vec();
+    bytes.push(version);
+    bytes.extend(bincode::serialize(data).unwrap());
+    bytes
+}
+
+/// Split versioned data into its version and its bincode encoding. Returns `None`
+/// for data written before versioning.
+pub fn split_version(bytes: &[u8]) -> Option<(u8, &[u8])> {
+    let rest = bytes.strip_prefix(&VERSION_MAGIC[..])?;
+    let (version, body) = rest.split_first()?;
+    Some((*version, body))
+}
+
+/// Decode data written by `encode_versioned`, or by a version of the type before
+/// versioning. `decode_version` decodes the bincode encoding of a given version.
+///
+/// Unversioned data is decoded as the legacy type `L` if it does not parse as
+/// versioned data, which only happens if its first bytes equal `VERSION_MAGIC`.
+pub fn decode_versioned<T, L>(
+    bytes: &[u8],
+    decode_version: impl FnOnce(u8, &[u8]) -> anyhow::Result<T>,
+) -> anyhow::Result<T>
+where
+    L: DeserializeOwned + Into<T>,
+{
+    let versioned_err = match split_version(bytes) {
+        Some((version, body)) => match decode_version(version, body) {
+            Ok(data) => return Ok(data),
+            Err(e) => e,
+        },
+        None => return Ok(bincode::deserialize::<L>(bytes)?.into()),
+    };
+    // unversioned data whose first bytes happen to equal the magic
+    bincode::deserialize::<L>(bytes)
+        .map(Into::into)
+        .map_err(|_| versioned_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Legacy {
+        a: u32,
+        c: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Current {
+        a: u32,
+        c: u32,
+        b: Option<u32>,
+    }
+
+    impl From<Legacy> for Current {
+        fn from(legacy: Legacy) -> Self {
+            Self {
+                a: legacy.a,
+                c: legacy.c,
+                b: None,
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> anyhow::Result<Current> {
+        decode_versioned::<Current, Legacy>(bytes, |version, body| match version {
+            1 => Ok(bincode::deserialize(body)?),
+            _ => anyhow::bail!("unsupported version {}", version),
+        })
+    }
+
+    #[test]
+    fn test_decode_versioned() {
+        let current = Current {
+            a: 1,
+            c: 2,
+            b: Some(3),
+        };
+        assert_eq!(decode(&encode_versioned(1, &current)).unwrap(), current);
+
+        let legacy = bincode::serialize(&Legacy { a: 1, c: 2 }).unwrap();
+        let expected = Current {
+            a: 1,
+            c: 2,
+            b: None,
+        };
+        assert_eq!(decode(&legacy).unwrap(), expected);
+
+        // legacy data starting with the magic, followed by the version byte 1
+        let a = u32::from_le_bytes(VERSION_MAGIC);
+        let legacy = bincode::serialize(&Legacy { a, c: 1 }).unwrap();
+        let expected = Current { a, c: 1, b: None };
+        assert_eq!(decode(&legacy).unwrap(), expected);
+
+        assert!(decode(&encode_versioned(2, &current)).is_err());
+    }
+}
@@ -1,26 +1,50 @@
-use std::str::FromStr;
-
 use actix_web::{
     get, post,
-    web::{Data, Json, Path},
-    Error,
+    web::{Data, Json},
+    Error, HttpRequest,
 };
-use intmax2_interfaces::api::store_vault_server::{
-    interface::DataType,
-    types::{
+use intmax2_interfaces::api::{
+    store_vault_server::types::{
         GetBalanceProofQuery, GetBalanceProofResponse, GetDataAllAfterQuery,
         GetDataAllAfterResponse, GetDataQuery, GetDataResponse, GetUserDataQuery,
-        GetUserDataResponse, SaveBalanceProofRequest, SaveDataRequest,
+        GetUserDataResponse, SaveBalanceProofRequest, StoreVaultInfo, WireFormat,
     },
+    version::VersionResponse,
+};
+
+use crate::api::{
+    envelope::Envelope,
+    state::State,
+    validation::{SaveBlob, StoreVaultLimits, Topic, ValidQuery},
 };
-use serde_qs::actix::QsQuery;
 
-use crate::api::state::State;
+// The state comes last in the handlers, so that an invalid request is rejected
+// before anything else is extracted
+
+#[get("/version")]
+pub async fn version() -> Json<VersionResponse> {
+    Json(VersionResponse::new(
+        "store-vault-server",
+        env!("CARGO_PKG_VERSION"),
+    ))
+}
+
+#[get("/info")]
+pub async fn info(req: HttpRequest) -> Json<StoreVaultInfo> {
+    let limits = req
+        .app_data::<StoreVaultLimits>()
+        .copied()
+        .unwrap_or_default();
+    Json(StoreVaultInfo {
+        wire_formats: vec![WireFormat::Json, WireFormat::Bincode],
+        max_blob_size: limits.max_blob_size,
+    })
+}
 
 #[post("/save-balance-proof")]
 pub async fn save_balance_proof(
-    state: Data<State>,
     request: Json<SaveBalanceProofRequest>,
+    state: Data<State>,
 ) -> Result<Json<()>, Error> {
     let request = request.into_inner();
     state
@@ -35,8 +59,8 @@ pub async fn save_balance_proof(
 
 #[get("/get-balance-proof")]
 pub async fn get_balance_proof(
+    query: ValidQuery<GetBalanceProofQuery>,
     state: Data<State>,
-    query: QsQuery<GetBalanceProofQuery>,
 ) -> Result<Json<GetBalanceProofResponse>, Error> {
     let query = query.into_inner();
     let balance_proof = state
@@ -51,13 +75,10 @@ pub async fn get_balance_proof(
 
 #[post("/{type}/save")]
 pub async fn save_data(
+    Topic(data_type): Topic,
+    request: SaveBlob,
     state: Data<State>,
-    path: Path<String>,
-    request: Json<SaveDataRequest>,
 ) -> Result<Json<()>, Error> {
-    let data_type = path.into_inner();
-    let data_type = DataType::from_str(data_type.as_str())
-        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Invalid type: {}", e)))?;
     let request = request.into_inner();
     state
         .store_vault_server
@@ -71,13 +92,10 @@ pub async fn save_data(
 
 #[get("/{type}/get")]
 pub async fn get_data(
+    Topic(data_type): Topic,
+    query: ValidQuery<GetDataQuery>,
     state: Data<State>,
-    path: Path<String>,
-    query: QsQuery<GetDataQuery>,
-) -> Result<Json<GetDataResponse>, Error> {
-    let data_type = path.into_inner();
-    let data_type = DataType::from_str(data_type.as_str())
-        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Invalid type: {}", e)))?;
+) -> Result<Envelope<GetDataResponse>, Error> {
     let query = query.into_inner();
     let data = state
         .store_vault_server
@@ -86,18 +104,15 @@ pub async fn get_data(
         .get_data(data_type, &query.uuid)
         .await
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(Json(GetDataResponse { data }))
+    Ok(Envelope(GetDataResponse { data }))
 }
 
 #[get("/{type}/get-all-after")]
 pub async fn get_data_all_after(
+    Topic(data_type): Topic,
+    query: ValidQuery<GetDataAllAfterQuery>,
     state: Data<State>,
-    path: Path<String>,
-    query: QsQuery<GetDataAllAfterQuery>,
-) -> Result<Json<GetDataAllAfterResponse>, Error> {
-    let data_type = path.into_inner();
-    let data_type = DataType::from_str(data_type.as_str())
-        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Invalid type: {}", e)))?;
+) -> Result<Envelope<GetDataAllAfterResponse>, Error> {
     let query = query.into_inner();
     let data = state
         .store_vault_server
@@ -106,14 +121,11 @@ pub async fn get_data_all_after(
         .get_data_all_after(data_type, query.pubkey, query.timestamp)
         .await
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(Json(GetDataAllAfterResponse { data }))
+    Ok(Envelope(GetDataAllAfterResponse { data }))
 }
 
 #[post("/save-user-data")]
-pub async fn save_user_data(
-    state: Data<State>,
-    request: Json<SaveDataRequest>,
-) -> Result<Json<()>, Error> {
+pub async fn save_user_data(request: SaveBlob, state: Data<State>) -> Result<Json<()>, Error> {
     let request = request.into_inner();
     state
         .store_vault_server
@@ -127,9 +139,9 @@ pub async fn save_user_data(
 
 #[get("/get-user-data")]
 pub async fn get_user_data(
+    query: ValidQuery<GetUserDataQuery>,
     state: Data<State>,
-    query: QsQuery<GetUserDataQuery>,
-) -> Result<Json<GetUserDataResponse>, Error> {
+) -> Result<Envelope<GetUserDataResponse>, Error> {
     let query = query.into_inner();
     let data = state
         .store_vault_server
@@ -138,11 +150,16 @@ pub async fn get_user_data(
         .get_user_data(query.pubkey)
         .await
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok(Json(GetUserDataResponse { data }))
+    Ok(Envelope(GetUserDataResponse { data }))
 }
 
-pub fn store_vault_server_scope() -> actix_web::Scope {
+pub fn store_vault_server_scope(limits: StoreVaultLimits) -> actix_web::Scope {
     actix_web::web::scope("/store-vault-server")
+        .app_data(limits.json_config())
+        .app_data(limits.payload_config())
+        .app_data(limits)
+        .service(version)
+        .service(info)
         .service(save_balance_proof)
         .service(get_balance_proof)
         .service(save_data)
@@ -0,0 +1,53 @@
+use actix_web::{
+    body::BoxBody,
+    http::header::{ACCEPT, CONTENT_TYPE, VARY},
+    HttpRequest, HttpResponse, Responder,
+};
+use intmax2_interfaces::api::store_vault_server::types::WireFormat;
+use serde::Serialize;
+
+/// Format of the request body, from its `Content-Type`
+pub fn request_format(req: &HttpRequest) -> WireFormat {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(WireFormat::from_content_type)
+        .unwrap_or(WireFormat::Json)
+}
+
+/// Bincode if the `Accept` header lists `application/octet-stream`, JSON otherwise
+pub fn accepted_format(req: &HttpRequest) -> WireFormat {
+    let accepts_binary = req
+        .headers()
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| {
+            accept
+                .split(',')
+                .any(|media| WireFormat::from_content_type(media.trim()) == WireFormat::Bincode)
+        });
+    if accepts_binary {
+        WireFormat::Bincode
+    } else {
+        WireFormat::Json
+    }
+}
+
+/// Response in the format the request accepts
+pub struct Envelope<T>(pub T);
+
+impl<T: Serialize> Responder for Envelope<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse {
+        let format = accepted_format(req);
+        match format.encode(&self.0) {
+            // caches in front of the server must keep both formats apart
+            Ok(body) => HttpResponse::Ok()
+                .content_type(format.content_type())
+                .insert_header((VARY, "Accept"))
+                .body(body),
+            Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        }
+    }
+}
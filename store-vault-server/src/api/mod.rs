@@ -1,3 +1,5 @@
 pub mod api;
+pub mod envelope;
 pub mod state;
 pub mod store_vault_server;
+pub mod validation;
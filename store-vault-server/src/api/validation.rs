@@ -0,0 +1,371 @@
+use std::{future::Future, pin::Pin, str::FromStr as _};
+
+use actix_web::{
+    dev::Payload,
+    error::JsonPayloadError,
+    http::{header::CONTENT_LENGTH, StatusCode},
+    web::{Bytes, Json, JsonConfig, PayloadConfig},
+    FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use intmax2_interfaces::api::store_vault_server::{
+    interface::DataType,
+    types::{SaveDataRequest, StoreVaultErrorResponse, WireFormat, DEFAULT_MAX_BLOB_SIZE},
+};
+use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use super::envelope::request_format;
+
+#[derive(Debug, Clone, Copy)]
+pub struct StoreVaultLimits {
+    pub max_blob_size: usize,
+}
+
+impl Default for StoreVaultLimits {
+    fn default() -> Self {
+        Self {
+            max_blob_size: DEFAULT_MAX_BLOB_SIZE,
+        }
+    }
+}
+
+impl StoreVaultLimits {
+    /// `MAX_BLOB_SIZE` in bytes, or the default
+    pub fn from_env() -> Self {
+        std::env::var("MAX_BLOB_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .map(|max_blob_size| Self { max_blob_size })
+            .unwrap_or_default()
+    }
+
+    // a byte of a blob takes up to 4 characters in JSON, and a balance proof is
+    // well below the extra margin
+    fn json_limit(&self) -> usize {
+        self.max_blob_size * 4 + 8 * 1024 * 1024
+    }
+
+    // bincode only adds the pubkey and a length prefix to the blob
+    fn binary_limit(&self) -> usize {
+        self.max_blob_size + 1024
+    }
+
+    /// Limit of the bincode bodies
+    pub fn payload_config(&self) -> PayloadConfig {
+        PayloadConfig::new(self.binary_limit())
+    }
+
+    pub fn json_config(&self) -> JsonConfig {
+        let max = self.json_limit();
+        JsonConfig::default()
+            .limit(max)
+            .error_handler(move |e, _| match e {
+                JsonPayloadError::OverflowKnownLength { length, .. } => {
+                    ValidationError::PayloadTooLarge { size: length, max }.into()
+                }
+                JsonPayloadError::Overflow { .. } => {
+                    ValidationError::PayloadTooLarge { size: max + 1, max }.into()
+                }
+                e => ValidationError::InvalidBody(e.to_string()).into(),
+            })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    #[error("Payload of {size} bytes exceeds the limit of {max} bytes")]
+    PayloadTooLarge { size: usize, max: usize },
+
+    #[error("Invalid {field}: {reason}")]
+    InvalidParam { field: String, reason: String },
+
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error("Unknown topic {0}, valid topics are {valid}", valid = valid_topics().join(", "))]
+    UnknownTopic(String),
+
+    #[error("Invalid request body: {0}")]
+    InvalidBody(String),
+}
+
+fn valid_topics() -> Vec<String> {
+    DataType::all().iter().map(|t| t.to_string()).collect()
+}
+
+impl ValidationError {
+    fn to_response(&self) -> StoreVaultErrorResponse {
+        let (error, field, valid_values) = match self {
+            ValidationError::PayloadTooLarge { .. } => ("payload_too_large", None, None),
+            ValidationError::InvalidParam { field, .. } => {
+                ("invalid_param", Some(field.clone()), None)
+            }
+            ValidationError::InvalidQuery(_) => ("invalid_param", None, None),
+            ValidationError::UnknownTopic(_) => (
+                "unknown_topic",
+                Some("type".to_string()),
+                Some(valid_topics()),
+            ),
+            ValidationError::InvalidBody(_) => ("invalid_body", None, None),
+        };
+        StoreVaultErrorResponse {
+            error: error.to_string(),
+            message: self.to_string(),
+            field,
+            valid_values,
+        }
+    }
+}
+
+impl ResponseError for ValidationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ValidationError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.to_response())
+    }
+}
+
+/// The `{type}` path segment
+pub struct Topic(pub DataType);
+
+impl FromRequest for Topic {
+    type Error = ValidationError;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let topic = req.match_info().get("type").unwrap_or_default();
+        std::future::ready(
+            DataType::from_str(topic)
+                .map(Topic)
+                .map_err(|_| ValidationError::UnknownTopic(topic.to_string())),
+        )
+    }
+}
+
+// checks of the parameters that have a fixed format, so that the error names them
+fn check_param(field: &str, value: &str) -> Result<(), ValidationError> {
+    let reason = match field {
+        "pubkey" if U256::from_hex(value).is_err() => "expected a 32-byte hex string",
+        "uuid" if Uuid::parse_str(value).is_err() => "expected a UUID",
+        _ => return Ok(()),
+    };
+    Err(ValidationError::InvalidParam {
+        field: field.to_string(),
+        reason: format!("{} ({:?})", reason, value),
+    })
+}
+
+/// Query string whose `pubkey` and `uuid` parameters are checked before it is
+/// deserialized
+pub struct ValidQuery<T>(pub T);
+
+impl<T> ValidQuery<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned> FromRequest for ValidQuery<T> {
+    type Error = ValidationError;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let query = req.query_string();
+        let result = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .try_for_each(|(field, value)| check_param(field, value))
+            .and_then(|_| {
+                serde_qs::from_str::<T>(query)
+                    .map(ValidQuery)
+                    .map_err(|e| ValidationError::InvalidQuery(e.to_string()))
+            });
+        std::future::ready(result)
+    }
+}
+
+/// Body of a save request, whose blob must fit `StoreVaultLimits::max_blob_size`
+pub struct SaveBlob(pub SaveDataRequest);
+
+impl SaveBlob {
+    pub fn into_inner(self) -> SaveDataRequest {
+        self.0
+    }
+}
+
+fn check_blob(request: SaveDataRequest, max: usize) -> Result<SaveBlob, ValidationError> {
+    if request.data.len() > max {
+        return Err(ValidationError::PayloadTooLarge {
+            size: request.data.len(),
+            max,
+        });
+    }
+    Ok(SaveBlob(request))
+}
+
+impl FromRequest for SaveBlob {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let limits = req
+            .app_data::<StoreVaultLimits>()
+            .copied()
+            .unwrap_or_default();
+        let max = limits.max_blob_size;
+        match request_format(req) {
+            WireFormat::Json => {
+                let request = Json::<SaveDataRequest>::from_request(req, payload);
+                Box::pin(async move { Ok(check_blob(request.await?.into_inner(), max)?) })
+            }
+            WireFormat::Bincode => {
+                // refused before the body is read, with the same error as JSON
+                let length = req
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|length| length.to_str().ok())
+                    .and_then(|length| length.parse::<usize>().ok());
+                if let Some(length) = length.filter(|&length| length > limits.binary_limit()) {
+                    let error = ValidationError::PayloadTooLarge {
+                        size: length,
+                        max: limits.binary_limit(),
+                    };
+                    return Box::pin(async move { Err(error.into()) });
+                }
+                let body = Bytes::from_request(req, payload);
+                Box::pin(async move {
+                    let request = WireFormat::Bincode
+                        .decode::<SaveDataRequest>(&body.await?)
+                        .map_err(|e| ValidationError::InvalidBody(e.to_string()))?;
+                    Ok(check_blob(request, max)?)
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+
+    use intmax2_interfaces::api::store_vault_server::types::StoreVaultInfo;
+
+    use crate::api::api::store_vault_server_scope;
+
+    use super::*;
+
+    // the state is not registered, so the requests only reach the validation
+    async fn call(request: test::TestRequest) -> (StatusCode, StoreVaultErrorResponse) {
+        let limits = StoreVaultLimits { max_blob_size: 8 };
+        let app = test::init_service(App::new().service(store_vault_server_scope(limits))).await;
+        let response = test::call_service(&app, request.to_request()).await;
+        let status = response.status();
+        (status, test::read_body_json(response).await)
+    }
+
+    #[actix_web::test]
+    async fn test_blob_too_large() {
+        let request = test::TestRequest::post()
+            .uri("/store-vault-server/deposit/save")
+            .set_json(SaveDataRequest {
+                pubkey: U256::default(),
+                data: vec![0; 9],
+            });
+        let (status, body) = call(request).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body.error, "payload_too_large");
+        assert!(body.message.contains("9 bytes"));
+    }
+
+    #[actix_web::test]
+    async fn test_binary_blob_too_large() {
+        let body = WireFormat::Bincode
+            .encode(&SaveDataRequest {
+                pubkey: U256::default(),
+                data: vec![0; 9],
+            })
+            .unwrap();
+        let request = test::TestRequest::post()
+            .uri("/store-vault-server/save-user-data")
+            .insert_header(("content-type", WireFormat::Bincode.content_type()))
+            .set_payload(body);
+        let (status, body) = call(request).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body.error, "payload_too_large");
+
+        let request = test::TestRequest::post()
+            .uri("/store-vault-server/save-user-data")
+            .insert_header(("content-type", WireFormat::Bincode.content_type()))
+            .set_payload(vec![0; 2048]);
+        let (status, _) = call(request).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_params() {
+        let request =
+            test::TestRequest::get().uri("/store-vault-server/get-user-data?pubkey=not-hex");
+        let (status, body) = call(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.error, "invalid_param");
+        assert_eq!(body.field.as_deref(), Some("pubkey"));
+
+        let request = test::TestRequest::get().uri("/store-vault-server/tx/get?uuid=1234");
+        let (status, body) = call(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.field.as_deref(), Some("uuid"));
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_topic() {
+        let request = test::TestRequest::get().uri(&format!(
+            "/store-vault-server/letter/get?uuid={}",
+            Uuid::new_v4()
+        ));
+        let (status, body) = call(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.error, "unknown_topic");
+        assert_eq!(
+            body.valid_values.unwrap(),
+            vec!["deposit", "transfer", "withdrawal", "tx"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_body() {
+        let request = test::TestRequest::post()
+            .uri("/store-vault-server/save-user-data")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(r#"{"pubkey":"0x12","data":"#);
+        let (status, body) = call(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.error, "invalid_body");
+
+        let request = test::TestRequest::post()
+            .uri("/store-vault-server/save-user-data")
+            .insert_header(("content-type", WireFormat::Bincode.content_type()))
+            .set_payload(vec![1, 2, 3]);
+        let (status, body) = call(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.error, "invalid_body");
+    }
+
+    #[actix_web::test]
+    async fn test_info() {
+        let limits = StoreVaultLimits { max_blob_size: 8 };
+        let app = test::init_service(App::new().service(store_vault_server_scope(limits))).await;
+        let request = test::TestRequest::get().uri("/store-vault-server/info");
+        let info: StoreVaultInfo = test::call_and_read_body_json(&app, request.to_request()).await;
+        assert_eq!(
+            info.wire_formats,
+            vec![WireFormat::Json, WireFormat::Bincode]
+        );
+        assert_eq!(info.max_blob_size, 8);
+    }
+}
@@ -1,8 +1,9 @@
 use actix_cors::Cors;
 use actix_web::{middleware::Logger, web::Data, App, HttpServer};
 use intmax2_client_sdk::utils::init_logger::init_logger;
+use intmax2_interfaces::api::CORS_EXPOSED_HEADERS;
 use store_vault_server::{
-    api::{api::store_vault_server_scope, state::State},
+    api::{api::store_vault_server_scope, state::State, validation::StoreVaultLimits},
     health_check::health_check,
 };
 
@@ -15,14 +16,15 @@ async fn main() -> std::io::Result<()> {
     let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let state = Data::new(State::new(&database_url).await.unwrap());
+    let limits = StoreVaultLimits::from_env();
     HttpServer::new(move || {
-        let cors = Cors::permissive();
+        let cors = Cors::permissive().expose_headers(CORS_EXPOSED_HEADERS);
         App::new()
             .wrap(cors)
             .wrap(Logger::new("Request: %r | Status: %s | Duration: %Ts"))
             .app_data(state.clone())
             .service(health_check)
-            .service(store_vault_server_scope())
+            .service(store_vault_server_scope(limits))
     })
     .bind(format!("0.0.0.0:{}", port))?
     .run()
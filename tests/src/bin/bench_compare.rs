@@ -0,0 +1,127 @@
+//! Compares two criterion baselines and fails if a benchmark got slower than the
+//! threshold.
+//!
+//! Usage: bench-compare <baseline> <candidate> [threshold-percent] [criterion-dir]
+//!
+//! Baselines are saved with `cargo bench -- --save-baseline <name>`. The mean of
+//! every benchmark present in both is compared. The threshold defaults to 10
+//! percent and the criterion dir to `target/criterion`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+const DEFAULT_THRESHOLD_PERCENT: f64 = 10.0;
+
+#[derive(Deserialize)]
+struct Estimates {
+    mean: Estimate,
+}
+
+#[derive(Deserialize)]
+struct Estimate {
+    point_estimate: f64, // nanoseconds
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() < 3 || args.len() > 5 {
+        anyhow::bail!(
+            "usage: bench-compare <baseline> <candidate> [threshold-percent] [criterion-dir]"
+        );
+    }
+    let baseline = &args[1];
+    let candidate = &args[2];
+    let threshold = match args.get(3) {
+        Some(threshold) => threshold.parse::<f64>()?,
+        None => DEFAULT_THRESHOLD_PERCENT,
+    };
+    let dir = PathBuf::from(args.get(4).map_or("target/criterion", |dir| dir.as_str()));
+
+    let mut benches = Vec::new();
+    find_benches(&dir, baseline, candidate, &mut benches)?;
+    if benches.is_empty() {
+        anyhow::bail!(
+            "no benchmark in {} has both the {} and {} baselines",
+            dir.display(),
+            baseline,
+            candidate
+        );
+    }
+    benches.sort();
+
+    let mut regressions = 0;
+    for bench in benches.iter() {
+        let before = read_mean(&bench.join(baseline))?;
+        let after = read_mean(&bench.join(candidate))?;
+        let change = (after - before) / before * 100.0;
+        let regressed = change > threshold;
+        if regressed {
+            regressions += 1;
+        }
+        println!(
+            "{:<60} {:>12} -> {:>12} {:>+8.2}%{}",
+            bench.strip_prefix(&dir).unwrap_or(bench).display(),
+            format_ns(before),
+            format_ns(after),
+            change,
+            if regressed { "  REGRESSED" } else { "" }
+        );
+    }
+    if regressions > 0 {
+        anyhow::bail!(
+            "{} of {} benchmarks regressed by more than {}%",
+            regressions,
+            benches.len(),
+            threshold
+        );
+    }
+    println!(
+        "No regression beyond {}% in {} benchmarks",
+        threshold,
+        benches.len()
+    );
+    Ok(())
+}
+
+/// Collect the benchmark dirs under `dir` that contain both baselines
+fn find_benches(
+    dir: &Path,
+    baseline: &str,
+    candidate: &str,
+    benches: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    if dir.join(baseline).is_dir() && dir.join(candidate).is_dir() {
+        benches.push(dir.to_path_buf());
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_benches(&path, baseline, candidate, benches)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_mean(dir: &Path) -> anyhow::Result<f64> {
+    let path = dir.join("estimates.json");
+    let estimates: Estimates = serde_json::from_slice(&fs::read(&path)?)
+        .map_err(|e| anyhow::anyhow!("failed to parse {}: {}", path.display(), e))?;
+    Ok(estimates.mean.point_estimate)
+}
+
+fn format_ns(ns: f64) -> String {
+    if ns >= 1e9 {
+        format!("{:.2} s", ns / 1e9)
+    } else if ns >= 1e6 {
+        format!("{:.2} ms", ns / 1e6)
+    } else if ns >= 1e3 {
+        format!("{:.2} us", ns / 1e3)
+    } else {
+        format!("{:.2} ns", ns)
+    }
+}
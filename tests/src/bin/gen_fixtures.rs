@@ -0,0 +1,28 @@
+//! Writes deterministic encrypted fixtures of every backup data type, for testing
+//! decryption and display code without running the whole stack.
+//!
+//! Usage: gen-fixtures <seed> <out-dir>
+//!
+//! The same seed always produces the same files. The committed fixtures in
+//! `interfaces/test-assets/fixtures` are generated with seed 0.
+
+use std::{fs, path::Path};
+
+use intmax2_client_sdk::utils::fixtures::write_fixtures;
+
+fn main() -> anyhow::Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() != 3 {
+        anyhow::bail!("usage: gen-fixtures <seed> <out-dir>");
+    }
+    let seed: u64 = args[1].parse()?;
+    let out_dir = Path::new(&args[2]);
+    fs::create_dir_all(out_dir)?;
+    let manifest = write_fixtures(seed, out_dir)?;
+    println!(
+        "Wrote {} blobs to {}",
+        manifest.blobs.len(),
+        out_dir.display()
+    );
+    Ok(())
+}
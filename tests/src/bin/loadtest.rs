@@ -0,0 +1,36 @@
+//! Runs simulated users against a deployment and reports the latency and error
+//! rate of each operation.
+//!
+//! Usage: loadtest <config.json> [report-dir]
+//!
+//! The config is a `tests::loadtest::LoadTestConfig`. Every endpoint must be on a
+//! host listed in its `allowed_hosts`. The report is written to `report.json` and
+//! `report.txt` in the report dir, which defaults to the current dir, and the text
+//! is printed. Exits with an error if any operation failed.
+
+use std::{fs, path::PathBuf};
+
+use tests::loadtest::{run_load_test, LoadTestConfig};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() < 2 || args.len() > 3 {
+        anyhow::bail!("usage: loadtest <config.json> [report-dir]");
+    }
+    let config: LoadTestConfig = serde_json::from_str(&fs::read_to_string(&args[1])?)?;
+    let dir = PathBuf::from(args.get(2).map_or(".", |dir| dir.as_str()));
+
+    let report = run_load_test(config).await?;
+    fs::write(
+        dir.join("report.json"),
+        serde_json::to_string_pretty(&report)?,
+    )?;
+    let text = report.to_text();
+    fs::write(dir.join("report.txt"), &text)?;
+    print!("{}", text);
+    if report.total_errors > 0 {
+        anyhow::bail!("{} operations failed", report.total_errors);
+    }
+    Ok(())
+}
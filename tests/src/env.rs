@@ -0,0 +1,57 @@
+use intmax2_client_sdk::external_api::utils::{
+    clock::SkewAwareClock,
+    query::{get_request_no_cache, post_request, HttpClient},
+};
+use intmax2_interfaces::api::block_builder::types::{AdvanceTimeRequest, GetTimeResponse};
+use serde::Deserialize;
+
+/// The servers the integration tests run against
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestEnv {
+    pub block_builder_base_url: String,
+}
+
+impl TestEnv {
+    pub fn from_env() -> anyhow::Result<Self> {
+        dotenv::dotenv().ok();
+        Ok(envy::from_env::<Self>()?)
+    }
+
+    /// Current time of the block builder
+    pub async fn now(&self) -> anyhow::Result<GetTimeResponse> {
+        let response = get_request_no_cache::<(), GetTimeResponse>(
+            &HttpClient::default(),
+            &self.block_builder_base_url,
+            "/admin/time",
+            None,
+            None,
+            None,
+        )
+        .await?;
+        Ok(response)
+    }
+
+    /// Move the block builder's clock forward by `seconds` and return the new time.
+    /// The builder has to run with `VIRTUAL_TIME=true`.
+    pub async fn advance_time(&self, seconds: u64) -> anyhow::Result<u64> {
+        let response: GetTimeResponse = post_request(
+            &HttpClient::default(),
+            &self.block_builder_base_url,
+            "/admin/time/advance",
+            &AdvanceTimeRequest { seconds },
+            None,
+            None,
+        )
+        .await?;
+        Ok(response.now)
+    }
+
+    /// The clock of a client started now. Its offset is measured from the
+    /// builder's time, as a fresh client does from the first `Date` header it sees.
+    pub async fn client_clock(&self) -> anyhow::Result<SkewAwareClock> {
+        let clock = SkewAwareClock::new();
+        let server_time = self.now().await?.now;
+        clock.record(&self.block_builder_base_url, server_time, clock.local_now());
+        Ok(clock)
+    }
+}
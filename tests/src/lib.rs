@@ -0,0 +1,3 @@
+pub mod env;
+pub mod loadtest;
+pub mod stub_server;
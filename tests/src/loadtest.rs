@@ -0,0 +1,631 @@
+//! Simulated users running deposits, syncs, transfers and withdrawals against a
+//! deployment, through the client-sdk as a wallet would, to measure the latency and
+//! error rate of each operation under load.
+//!
+//! All users share one client, so the witnesses fetched for one account are reused
+//! by the others through the witness cache of `CachedValidityProver`.
+
+use std::{
+    collections::BTreeMap,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ethers::{
+    signers::{LocalWallet, Signer as _},
+    types::{Address as EthAddress, H256},
+};
+use intmax2_client_sdk::{
+    client::{
+        client::Client, config::ClientConfig, provisional::MemoryProvisionalStore,
+        utils::generate_salt,
+    },
+    external_api::{
+        balance_prover::BalanceProverClient,
+        block_builder::BlockBuilderClient,
+        cached_validity_prover::CachedValidityProver,
+        contract::{liquidity_contract::LiquidityContract, rollup_contract::RollupContract},
+        store_vault_server::StoreVaultServerClient,
+        validity_prover::ValidityProverClient,
+        withdrawal_server::WithdrawalServerClient,
+    },
+};
+use intmax2_interfaces::data::deposit_data::TokenType;
+use intmax2_zkp::{
+    common::{generic_address::GenericAddress, signature::key_set::KeySet, transfer::Transfer},
+    ethereum_types::{address::Address, u256::U256, u32limb_trait::U32LimbTrait as _},
+};
+use num_bigint::BigUint;
+use rand::{Rng as _, SeedableRng as _};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+pub type LoadTestClient = Client<
+    BlockBuilderClient,
+    StoreVaultServerClient,
+    CachedValidityProver<ValidityProverClient>,
+    BalanceProverClient,
+    WithdrawalServerClient,
+>;
+
+const NATIVE_TOKEN_INDEX: u64 = 0;
+// seconds between polls of the block builder and the validity prover
+const POLL_INTERVAL: u64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// Native deposit paid by a faucet key
+    Deposit,
+    Sync,
+    /// Native transfer to another simulated user
+    Send,
+    /// Native withdrawal to the faucet address, then a sync of the withdrawals
+    Withdraw,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Endpoints {
+    pub block_builder_url: String,
+    pub store_vault_server_url: String,
+    pub validity_prover_url: String,
+    pub balance_prover_url: String,
+    pub withdrawal_server_url: String,
+    pub l1_rpc_url: String,
+    pub l1_chain_id: u64,
+    pub liquidity_contract_address: EthAddress,
+    pub l2_rpc_url: String,
+    pub l2_chain_id: u64,
+    pub rollup_contract_address: EthAddress,
+    pub rollup_contract_deployed_block_number: u64,
+}
+
+impl Endpoints {
+    fn urls(&self) -> [(&'static str, &str); 7] {
+        [
+            ("block_builder_url", &self.block_builder_url),
+            ("store_vault_server_url", &self.store_vault_server_url),
+            ("validity_prover_url", &self.validity_prover_url),
+            ("balance_prover_url", &self.balance_prover_url),
+            ("withdrawal_server_url", &self.withdrawal_server_url),
+            ("l1_rpc_url", &self.l1_rpc_url),
+            ("l2_rpc_url", &self.l2_rpc_url),
+        ]
+    }
+}
+
+/// Settings of a load test, read from a JSON file
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoadTestConfig {
+    pub endpoints: Endpoints,
+    /// Hosts the load test may send requests to, as `host` or `host:port`. Every
+    /// endpoint has to be listed, so that a config copied from elsewhere cannot
+    /// load a production deployment by mistake.
+    pub allowed_hosts: Vec<String>,
+    #[serde(default = "default_users")]
+    pub users: usize,
+    /// Time over which the users are started, evenly spaced
+    #[serde(default)]
+    pub ramp_up_secs: u64,
+    /// Pause of a user after each operation
+    #[serde(default = "default_think_time_ms")]
+    pub think_time_ms: u64,
+    /// Times each user runs the script
+    #[serde(default = "default_iterations")]
+    pub iterations: usize,
+    #[serde(default = "default_script")]
+    pub script: Vec<Operation>,
+    /// Funded L1 keys paying for the deposits, assigned to the users in turn
+    #[serde(default)]
+    pub faucet_keys: Vec<H256>,
+    #[serde(default = "default_amount")]
+    pub deposit_amount: u128,
+    /// Amount of each transfer and withdrawal
+    #[serde(default = "default_amount")]
+    pub transfer_amount: u128,
+    /// Seed of the keys of the users, so that a later run reuses their balances
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_timeout")]
+    pub deposit_timeout: u64,
+    #[serde(default = "default_timeout")]
+    pub tx_timeout: u64,
+}
+
+fn default_users() -> usize {
+    10
+}
+
+fn default_think_time_ms() -> u64 {
+    1000
+}
+
+fn default_iterations() -> usize {
+    1
+}
+
+fn default_script() -> Vec<Operation> {
+    vec![
+        Operation::Deposit,
+        Operation::Sync,
+        Operation::Send,
+        Operation::Sync,
+        Operation::Withdraw,
+    ]
+}
+
+fn default_amount() -> u128 {
+    1_000_000_000
+}
+
+fn default_timeout() -> u64 {
+    120
+}
+
+fn host_of(url: &str) -> anyhow::Result<(String, Option<u16>)> {
+    let url = reqwest::Url::parse(url)?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("{} has no host", url))?;
+    Ok((host.to_string(), url.port_or_known_default()))
+}
+
+impl LoadTestConfig {
+    /// Refuse a config that would send to a host that is not in `allowed_hosts`,
+    /// or that cannot run its script
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.allowed_hosts.is_empty() {
+            anyhow::bail!("allowed_hosts is empty; list the hosts of the deployment to load");
+        }
+        for (name, url) in self.endpoints.urls() {
+            let (host, port) = host_of(url)?;
+            let allowed = self.allowed_hosts.iter().any(|allowed| {
+                *allowed == host
+                    || port.is_some_and(|port| *allowed == format!("{}:{}", host, port))
+            });
+            if !allowed {
+                anyhow::bail!("refusing to run: {} {} is not in allowed_hosts", name, url);
+            }
+        }
+        if self.users == 0 || self.iterations == 0 || self.script.is_empty() {
+            anyhow::bail!("nothing to run: users, iterations and script must not be empty");
+        }
+        let needs_faucet = self
+            .script
+            .iter()
+            .any(|op| matches!(op, Operation::Deposit | Operation::Withdraw));
+        if needs_faucet && self.faucet_keys.is_empty() {
+            anyhow::bail!("deposits and withdrawals need faucet_keys");
+        }
+        Ok(())
+    }
+
+    pub fn build_client(&self) -> LoadTestClient {
+        let endpoints = &self.endpoints;
+        Client {
+            block_builder: BlockBuilderClient::new(),
+            store_vault_server: StoreVaultServerClient::new(&endpoints.store_vault_server_url),
+            validity_prover: CachedValidityProver::new(ValidityProverClient::new(
+                &endpoints.validity_prover_url,
+            )),
+            balance_prover: BalanceProverClient::new(&endpoints.balance_prover_url),
+            withdrawal_server: WithdrawalServerClient::new(&endpoints.withdrawal_server_url),
+            liquidity_contract: LiquidityContract::new(
+                &endpoints.l1_rpc_url,
+                endpoints.l1_chain_id,
+                endpoints.liquidity_contract_address,
+            ),
+            rollup_contract: RollupContract::new(
+                &endpoints.l2_rpc_url,
+                endpoints.l2_chain_id,
+                endpoints.rollup_contract_address,
+                endpoints.rollup_contract_deployed_block_number,
+            ),
+            config: ClientConfig {
+                deposit_timeout: self.deposit_timeout,
+                tx_timeout: self.tx_timeout,
+                ..Default::default()
+            },
+            provisional_store: Arc::new(MemoryProvisionalStore::default()),
+        }
+    }
+
+    /// Keys of the simulated users, the same for the same seed
+    pub fn user_keys(&self) -> Vec<KeySet> {
+        let mut rng = ChaCha20Rng::seed_from_u64(self.seed);
+        (0..self.users).map(|_| KeySet::rand(&mut rng)).collect()
+    }
+}
+
+/// How an operation that did not fail ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    /// Not run, e.g. a transfer of a user whose deposit is not synced yet
+    Skipped,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Samples {
+    latencies_ms: Vec<u64>,
+    errors: usize,
+    skipped: usize,
+    last_error: Option<String>,
+}
+
+/// Latencies and errors of the operations of all users
+#[derive(Debug, Default)]
+pub struct Stats {
+    samples: BTreeMap<Operation, Samples>,
+}
+
+impl Stats {
+    pub fn record(&mut self, op: Operation, elapsed: Duration, result: &anyhow::Result<Outcome>) {
+        let samples = self.samples.entry(op).or_default();
+        match result {
+            Ok(Outcome::Done) => samples.latencies_ms.push(elapsed.as_millis() as u64),
+            Ok(Outcome::Skipped) => samples.skipped += 1,
+            Err(e) => {
+                samples.errors += 1;
+                samples.last_error = Some(format!("{:#}", e));
+            }
+        }
+    }
+
+    pub fn report(&self, users: usize, elapsed: Duration) -> Report {
+        let secs = elapsed.as_secs_f64();
+        let operations = self
+            .samples
+            .iter()
+            .map(|(op, samples)| {
+                let mut latencies = samples.latencies_ms.clone();
+                latencies.sort_unstable();
+                let attempts = latencies.len() + samples.errors;
+                OperationReport {
+                    operation: *op,
+                    completed: latencies.len(),
+                    errors: samples.errors,
+                    skipped: samples.skipped,
+                    error_rate: if attempts == 0 {
+                        0.0
+                    } else {
+                        samples.errors as f64 / attempts as f64
+                    },
+                    throughput_per_sec: if secs > 0.0 {
+                        latencies.len() as f64 / secs
+                    } else {
+                        0.0
+                    },
+                    p50_ms: percentile(&latencies, 50.0),
+                    p90_ms: percentile(&latencies, 90.0),
+                    p99_ms: percentile(&latencies, 99.0),
+                    max_ms: latencies.last().copied().unwrap_or_default(),
+                    last_error: samples.last_error.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+        Report {
+            users,
+            duration_secs: secs,
+            total_errors: operations.iter().map(|op| op.errors).sum(),
+            operations,
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted latencies, 0 if there are none
+pub fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationReport {
+    pub operation: Operation,
+    pub completed: usize,
+    pub errors: usize,
+    pub skipped: usize,
+    /// Errors over completed and failed operations
+    pub error_rate: f64,
+    pub throughput_per_sec: f64,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub users: usize,
+    pub duration_secs: f64,
+    pub total_errors: usize,
+    pub operations: Vec<OperationReport>,
+}
+
+impl Report {
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{} users in {:.1} s, {} errors\n{:<10} {:>9} {:>7} {:>8} {:>7} {:>8} {:>8} {:>8} {:>8} {:>8}\n",
+            self.users,
+            self.duration_secs,
+            self.total_errors,
+            "operation",
+            "completed",
+            "errors",
+            "skipped",
+            "err %",
+            "ops/s",
+            "p50 ms",
+            "p90 ms",
+            "p99 ms",
+            "max ms"
+        );
+        for op in self.operations.iter() {
+            text += &format!(
+                "{:<10} {:>9} {:>7} {:>8} {:>7.1} {:>8.2} {:>8} {:>8} {:>8} {:>8}\n",
+                format!("{:?}", op.operation).to_lowercase(),
+                op.completed,
+                op.errors,
+                op.skipped,
+                op.error_rate * 100.0,
+                op.throughput_per_sec,
+                op.p50_ms,
+                op.p90_ms,
+                op.p99_ms,
+                op.max_ms
+            );
+            if let Some(error) = &op.last_error {
+                text += &format!("  last error: {}\n", error);
+            }
+        }
+        text
+    }
+}
+
+fn to_u256(amount: u128) -> U256 {
+    BigUint::from(amount).try_into().unwrap()
+}
+
+fn native_balance(amount: Option<U256>) -> BigUint {
+    amount.map(BigUint::from).unwrap_or_default()
+}
+
+// transfer and wait until the tx is in a block
+async fn send(
+    client: &LoadTestClient,
+    config: &LoadTestConfig,
+    key: KeySet,
+    recipient: GenericAddress,
+) -> anyhow::Result<Outcome> {
+    let amount = to_u256(config.transfer_amount);
+    let balance = native_balance(
+        client
+            .get_user_data(key)
+            .await?
+            .balances()
+            .get(&NATIVE_TOKEN_INDEX)
+            .map(|leaf| leaf.amount),
+    );
+    if balance < BigUint::from(config.transfer_amount) {
+        return Ok(Outcome::Skipped);
+    }
+    let transfer = Transfer {
+        recipient,
+        token_index: NATIVE_TOKEN_INDEX as u32,
+        amount,
+        salt: generate_salt(),
+    };
+    let url = &config.endpoints.block_builder_url;
+    let memo = client.send_tx_request(url, key, vec![transfer]).await?;
+    let proposal = client
+        .wait_for_proposal(url, key, &memo, config.tx_timeout, POLL_INTERVAL)
+        .await?;
+    let tx_tree_root = client.finalize_tx(url, key, &memo, &proposal).await?;
+    client
+        .wait_for_inclusion(tx_tree_root, config.tx_timeout, POLL_INTERVAL)
+        .await?;
+    Ok(Outcome::Done)
+}
+
+fn faucet_address(faucet_key: H256) -> anyhow::Result<Address> {
+    let wallet = LocalWallet::from_bytes(faucet_key.as_bytes())?;
+    Ok(Address::from_bytes_be(wallet.address().as_bytes()))
+}
+
+async fn run_operation(
+    client: &LoadTestClient,
+    config: &LoadTestConfig,
+    op: Operation,
+    user: usize,
+    keys: &[KeySet],
+    rng: &mut ChaCha20Rng,
+) -> anyhow::Result<Outcome> {
+    let key = keys[user];
+    match op {
+        Operation::Deposit => {
+            let faucet_key = config.faucet_keys[user % config.faucet_keys.len()];
+            let amount = to_u256(config.deposit_amount);
+            let deposit_data = client
+                .prepare_deposit(
+                    key.pubkey,
+                    amount,
+                    TokenType::NATIVE,
+                    Address::default(),
+                    U256::default(),
+                )
+                .await?;
+            client
+                .liquidity_contract
+                .deposit_native(faucet_key, deposit_data.pubkey_salt_hash, amount)
+                .await?;
+        }
+        Operation::Sync => client.sync(key).await?,
+        Operation::Send => {
+            if keys.len() < 2 {
+                return Ok(Outcome::Skipped);
+            }
+            // anyone but the user
+            let other = (user + rng.gen_range(1..keys.len())) % keys.len();
+            let recipient = GenericAddress::from_pubkey(keys[other].pubkey);
+            return send(client, config, key, recipient).await;
+        }
+        Operation::Withdraw => {
+            let faucet_key = config.faucet_keys[user % config.faucet_keys.len()];
+            let recipient = GenericAddress::from_address(faucet_address(faucet_key)?);
+            if send(client, config, key, recipient).await? == Outcome::Skipped {
+                return Ok(Outcome::Skipped);
+            }
+            client.sync_withdrawals(key).await?;
+        }
+    }
+    Ok(Outcome::Done)
+}
+
+async fn run_user(
+    client: &LoadTestClient,
+    config: &LoadTestConfig,
+    user: usize,
+    keys: &[KeySet],
+    stats: &Mutex<Stats>,
+) {
+    let start_delay = config.ramp_up_secs * 1000 * user as u64 / config.users as u64;
+    tokio::time::sleep(Duration::from_millis(start_delay)).await;
+    let mut rng = ChaCha20Rng::seed_from_u64(config.seed.wrapping_add(user as u64 + 1));
+    for _ in 0..config.iterations {
+        for op in config.script.iter() {
+            let started = Instant::now();
+            let result = run_operation(client, config, *op, user, keys, &mut rng).await;
+            if let Err(e) = &result {
+                log::warn!("user {} {:?} failed: {:#}", user, op, e);
+            }
+            stats
+                .lock()
+                .unwrap()
+                .record(*op, started.elapsed(), &result);
+            tokio::time::sleep(Duration::from_millis(config.think_time_ms)).await;
+        }
+    }
+}
+
+/// Run every user of `config` to the end of its script, and report the latencies
+/// and errors. The config is checked first.
+pub async fn run_load_test(config: LoadTestConfig) -> anyhow::Result<Report> {
+    config.check()?;
+    let client = Rc::new(config.build_client());
+    let keys = Rc::new(config.user_keys());
+    let config = Rc::new(config);
+    let stats = Rc::new(Mutex::new(Stats::default()));
+    let started = Instant::now();
+    // the client's futures are not Send, so the users run concurrently on this thread
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async {
+            let handles = (0..config.users)
+                .map(|user| {
+                    let (client, config, keys, stats) =
+                        (client.clone(), config.clone(), keys.clone(), stats.clone());
+                    tokio::task::spawn_local(async move {
+                        run_user(&client, &config, user, &keys, &stats).await
+                    })
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                handle.await?;
+            }
+            anyhow::Ok(())
+        })
+        .await?;
+    let report = stats
+        .lock()
+        .unwrap()
+        .report(config.users, started.elapsed());
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(allowed_hosts: &[&str]) -> LoadTestConfig {
+        let json = serde_json::json!({
+            "endpoints": {
+                "block_builder_url": "http://localhost:9004",
+                "store_vault_server_url": "http://localhost:9000",
+                "validity_prover_url": "http://localhost:9002",
+                "balance_prover_url": "http://localhost:9001",
+                "withdrawal_server_url": "http://localhost:9003",
+                "l1_rpc_url": "http://127.0.0.1:8545",
+                "l1_chain_id": 31337,
+                "liquidity_contract_address": "0x5fbdb2315678afecb367f032d93f642f64180aa3",
+                "l2_rpc_url": "http://127.0.0.1:8546",
+                "l2_chain_id": 31338,
+                "rollup_contract_address": "0xe7f1725e7734ce288f8367e1bb143e90bb3f0512",
+                "rollup_contract_deployed_block_number": 0
+            },
+            "allowed_hosts": allowed_hosts,
+            "script": ["sync"],
+        });
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_only_allowed_hosts() {
+        assert!(config(&["localhost", "127.0.0.1"]).check().is_ok());
+        assert!(config(&[
+            "localhost:9004",
+            "localhost",
+            "127.0.0.1:8545",
+            "127.0.0.1:8546"
+        ])
+        .check()
+        .is_ok());
+        let error = config(&["localhost"]).check().unwrap_err();
+        assert!(error.to_string().contains("l1_rpc_url"));
+        assert!(config(&[]).check().is_err());
+
+        // a deposit needs a faucet
+        let mut with_deposits = config(&["localhost", "127.0.0.1"]);
+        with_deposits.script = default_script();
+        assert!(with_deposits.check().is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        assert_eq!(percentile(&[], 50.0), 0);
+        let latencies = (1..=100).collect::<Vec<u64>>();
+        assert_eq!(percentile(&latencies, 50.0), 50);
+        assert_eq!(percentile(&latencies, 99.0), 99);
+        assert_eq!(percentile(&[7], 99.0), 7);
+
+        let mut stats = Stats::default();
+        for ms in [10, 30, 20] {
+            stats.record(
+                Operation::Sync,
+                Duration::from_millis(ms),
+                &Ok(Outcome::Done),
+            );
+        }
+        stats.record(
+            Operation::Sync,
+            Duration::from_millis(5),
+            &Err(anyhow::anyhow!("timeout")),
+        );
+        stats.record(Operation::Send, Duration::ZERO, &Ok(Outcome::Skipped));
+        let report = stats.report(2, Duration::from_secs(2));
+        assert_eq!(report.total_errors, 1);
+        let sync = &report.operations[1];
+        assert_eq!(sync.operation, Operation::Sync);
+        assert_eq!((sync.completed, sync.errors), (3, 1));
+        assert_eq!(sync.error_rate, 0.25);
+        assert_eq!(sync.p50_ms, 20);
+        assert_eq!(sync.max_ms, 30);
+        assert_eq!(sync.throughput_per_sec, 1.5);
+        assert_eq!(report.operations[0].skipped, 1);
+        assert!(report.to_text().contains("last error: timeout"));
+    }
+}
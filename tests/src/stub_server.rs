@@ -0,0 +1,79 @@
+//! In-process stand-in for the servers of a deployment, for tests that only need
+//! the answers a user without any data gets: no user data and no deposits,
+//! transfers or txs. Every other endpoint answers 404.
+
+use std::{
+    net::TcpListener,
+    sync::{Arc, Mutex},
+};
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use intmax2_interfaces::api::store_vault_server::types::{
+    GetDataAllAfterResponse, GetUserDataResponse,
+};
+
+use crate::loadtest::LoadTestConfig;
+
+pub struct StubServer {
+    pub url: String,
+    paths: Arc<Mutex<Vec<String>>>,
+}
+
+impl StubServer {
+    /// Start the server on a free local port, on the current tokio runtime
+    pub fn start() -> anyhow::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let state = web::Data::new(paths.clone());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(state.clone())
+                .default_service(web::to(respond))
+        })
+        .workers(1)
+        .listen(listener)?
+        .run();
+        tokio::spawn(server);
+        Ok(Self { url, paths })
+    }
+
+    /// Paths of the requests received so far, in order
+    pub fn paths(&self) -> Vec<String> {
+        self.paths.lock().unwrap().clone()
+    }
+
+    /// Load test config with every endpoint at this server
+    pub fn load_test_config(&self) -> LoadTestConfig {
+        let json = serde_json::json!({
+            "endpoints": {
+                "block_builder_url": self.url,
+                "store_vault_server_url": self.url,
+                "validity_prover_url": self.url,
+                "balance_prover_url": self.url,
+                "withdrawal_server_url": self.url,
+                "l1_rpc_url": self.url,
+                "l1_chain_id": 31337,
+                "liquidity_contract_address": "0x5fbdb2315678afecb367f032d93f642f64180aa3",
+                "l2_rpc_url": self.url,
+                "l2_chain_id": 31338,
+                "rollup_contract_address": "0xe7f1725e7734ce288f8367e1bb143e90bb3f0512",
+                "rollup_contract_deployed_block_number": 0
+            },
+            "allowed_hosts": ["127.0.0.1"],
+        });
+        serde_json::from_value(json).unwrap()
+    }
+}
+
+async fn respond(request: HttpRequest, paths: web::Data<Arc<Mutex<Vec<String>>>>) -> HttpResponse {
+    let path = request.path();
+    paths.lock().unwrap().push(path.to_string());
+    if path == "/store-vault-server/get-user-data" {
+        HttpResponse::Ok().json(GetUserDataResponse { data: None })
+    } else if path.starts_with("/store-vault-server/") && path.ends_with("/get-all-after") {
+        HttpResponse::Ok().json(GetDataAllAfterResponse { data: Vec::new() })
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
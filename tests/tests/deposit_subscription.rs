@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use ethers::types::H256;
+use futures::StreamExt as _;
+use intmax2_client_sdk::external_api::contract::{
+    liquidity_contract::LiquidityContract, liquidity_ws::WsLiquidityReader,
+};
+use intmax2_zkp::ethereum_types::{bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait as _};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Config {
+    pub rpc_url: String,
+    /// The ws endpoint of the same node, `ws://127.0.0.1:8545` for anvil
+    pub ws_rpc_url: String,
+    pub chain_id: u64,
+    pub deployer_private_key: H256,
+}
+
+#[tokio::test]
+async fn deposit_subscription() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+    let config = envy::from_env::<Config>().unwrap();
+
+    let liquidity_contract = LiquidityContract::deploy(
+        &config.rpc_url,
+        config.chain_id,
+        config.deployer_private_key,
+    )
+    .await?;
+    let random_address = ethers::types::Address::random();
+    liquidity_contract
+        .initialize(
+            config.deployer_private_key,
+            random_address,
+            random_address,
+            random_address,
+            random_address,
+            random_address,
+            random_address,
+            vec![],
+        )
+        .await?;
+
+    let reader =
+        WsLiquidityReader::connect(&config.ws_rpc_url, liquidity_contract.address()).await?;
+    let events = reader.subscribe_deposited_events().await?;
+
+    let amounts = [100, 200].map(|amount| U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, amount]));
+    let mut rng = rand::thread_rng();
+    for amount in amounts {
+        let pubkey_salt_hash = Bytes32::rand(&mut rng);
+        liquidity_contract
+            .deposit_native(config.deployer_private_key, pubkey_salt_hash, amount)
+            .await?;
+    }
+
+    // pushed by the node, so both arrive well before any polling interval
+    let deposits = tokio::time::timeout(
+        Duration::from_secs(10),
+        events.take(amounts.len()).collect::<Vec<_>>(),
+    )
+    .await?
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        deposits.iter().map(|d| d.amount).collect::<Vec<_>>(),
+        amounts
+    );
+    assert!(deposits[0].deposit_id < deposits[1].deposit_id);
+
+    Ok(())
+}
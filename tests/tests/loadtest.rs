@@ -0,0 +1,31 @@
+//! Smoke run of the load test: five users syncing once, against an in-process stub
+//! of the servers.
+
+use tests::{
+    loadtest::{run_load_test, Operation},
+    stub_server::StubServer,
+};
+
+#[tokio::test]
+async fn five_users_sync_without_errors() -> anyhow::Result<()> {
+    let server = StubServer::start()?;
+    let mut config = server.load_test_config();
+    config.users = 5;
+    config.iterations = 1;
+    config.ramp_up_secs = 1;
+    config.think_time_ms = 0;
+    config.script = vec![Operation::Sync];
+
+    let report = run_load_test(config).await?;
+    assert_eq!(report.total_errors, 0, "{}", report.to_text());
+    assert_eq!(report.operations.len(), 1);
+    assert_eq!(report.operations[0].completed, 5);
+    // every user asked for its user data
+    let user_data_requests = server
+        .paths()
+        .iter()
+        .filter(|path| *path == "/store-vault-server/get-user-data")
+        .count();
+    assert!(user_data_requests >= 5);
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! Keyless observation of an account driven by a separate keyed client, against
+//! the deployment of the config file in `LOADTEST_CONFIG`.
+
+use std::time::{Duration, Instant};
+
+use intmax2_client_sdk::client::{
+    observer::{Observer, ObserverConfig},
+    utils::generate_salt,
+};
+use intmax2_interfaces::{
+    api::validity_prover::interface::ValidityProverClientInterface as _,
+    data::deposit_data::TokenType,
+};
+use intmax2_zkp::{
+    common::{generic_address::GenericAddress, signature::key_set::KeySet, transfer::Transfer},
+    ethereum_types::{address::Address, u256::U256},
+};
+use num_bigint::BigUint;
+use tests::loadtest::{LoadTestClient, LoadTestConfig};
+
+const NATIVE_TOKEN_INDEX: u32 = 0;
+const POLL_INTERVAL: u64 = 2;
+
+async fn native_balance(client: &LoadTestClient, key: KeySet) -> anyhow::Result<BigUint> {
+    client.sync(key).await?;
+    Ok(client
+        .get_user_data(key)
+        .await?
+        .balances()
+        .get(&(NATIVE_TOKEN_INDEX as u64))
+        .map(|leaf| BigUint::from(leaf.amount))
+        .unwrap_or_default())
+}
+
+#[tokio::test]
+async fn observe_account_of_keyed_client() -> anyhow::Result<()> {
+    let path = std::env::var("LOADTEST_CONFIG")
+        .map_err(|_| anyhow::anyhow!("LOADTEST_CONFIG is not set"))?;
+    let config: LoadTestConfig = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    anyhow::ensure!(!config.faucet_keys.is_empty(), "the test needs faucet_keys");
+    let owner = config.build_client();
+    let key = KeySet::rand(&mut rand::thread_rng());
+
+    // the observer shares nothing with the owner but the servers
+    let observed = config.build_client();
+    let start_block = observed.validity_prover.get_block_number().await? + 1;
+    let mut observer = Observer::new(
+        ObserverConfig {
+            validity_prover: observed.validity_prover,
+            liquidity_contract: observed.liquidity_contract,
+            from_block_number: Some(start_block),
+        },
+        key.pubkey,
+    );
+    assert!(!observer.get_registration_status().await?.is_registered());
+
+    // a deposit whose salt hash the owner discloses
+    let amount: U256 = BigUint::from(config.deposit_amount).try_into().unwrap();
+    let deposit_data = owner
+        .prepare_deposit(
+            key.pubkey,
+            amount,
+            TokenType::NATIVE,
+            Address::default(),
+            U256::default(),
+        )
+        .await?;
+    owner
+        .liquidity_contract
+        .deposit_native(config.faucet_keys[0], deposit_data.pubkey_salt_hash, amount)
+        .await?;
+    observer.register_salt_hash(deposit_data.pubkey_salt_hash);
+    let deposits = observer.get_onchain_deposits().await?;
+    assert_eq!(deposits.len(), 1);
+    assert_eq!(deposits[0].amount, amount);
+
+    let deadline = Instant::now() + Duration::from_secs(config.deposit_timeout);
+    while native_balance(&owner, key).await? < BigUint::from(config.transfer_amount) {
+        anyhow::ensure!(Instant::now() < deadline, "the deposit was not synced");
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL)).await;
+    }
+
+    // a transfer of the owner, which registers the account
+    let transfer = Transfer {
+        recipient: GenericAddress::from_pubkey(KeySet::rand(&mut rand::thread_rng()).pubkey),
+        token_index: NATIVE_TOKEN_INDEX,
+        amount: BigUint::from(config.transfer_amount).try_into().unwrap(),
+        salt: generate_salt(),
+    };
+    let url = &config.endpoints.block_builder_url;
+    let memo = owner.send_tx_request(url, key, vec![transfer]).await?;
+    let proposal = owner
+        .wait_for_proposal(url, key, &memo, config.tx_timeout, POLL_INTERVAL)
+        .await?;
+    let tx_tree_root = owner.finalize_tx(url, key, &memo, &proposal).await?;
+    owner
+        .wait_for_inclusion(tx_tree_root, config.tx_timeout, POLL_INTERVAL)
+        .await?;
+
+    let mut inclusions = observer.get_block_inclusions().await?;
+    while !observer.is_caught_up() {
+        inclusions.extend(observer.get_block_inclusions().await?);
+    }
+    assert_eq!(inclusions.len(), 1);
+    assert_eq!(inclusions[0].tx_tree_root, tx_tree_root);
+    assert!(inclusions[0].is_executed());
+    assert!(observer.get_registration_status().await?.is_registered());
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! Key rotation into an account that already holds a balance, resumed from its
+//! saved progress, against the deployment of the config file in `LOADTEST_CONFIG`.
+
+use std::time::{Duration, Instant};
+
+use intmax2_client_sdk::client::rotation::{RotationOptions, RotationProgress};
+use intmax2_interfaces::data::deposit_data::TokenType;
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{address::Address, u256::U256},
+};
+use num_bigint::BigUint;
+use tests::loadtest::{LoadTestClient, LoadTestConfig};
+
+const NATIVE_TOKEN_INDEX: u32 = 0;
+const POLL_INTERVAL: u64 = 2;
+
+async fn native_balance(client: &LoadTestClient, key: KeySet) -> anyhow::Result<BigUint> {
+    client.sync(key).await?;
+    Ok(client
+        .get_user_data(key)
+        .await?
+        .balances()
+        .get(&(NATIVE_TOKEN_INDEX as u64))
+        .map(|leaf| BigUint::from(leaf.amount))
+        .unwrap_or_default())
+}
+
+// deposit `amount` to `key` and sync until it is in its balance
+async fn deposit(
+    client: &LoadTestClient,
+    config: &LoadTestConfig,
+    key: KeySet,
+    amount: u128,
+) -> anyhow::Result<()> {
+    let expected = native_balance(client, key).await? + amount;
+    let amount: U256 = BigUint::from(amount).try_into().unwrap();
+    let deposit_data = client
+        .prepare_deposit(
+            key.pubkey,
+            amount,
+            TokenType::NATIVE,
+            Address::default(),
+            U256::default(),
+        )
+        .await?;
+    client
+        .liquidity_contract
+        .deposit_native(config.faucet_keys[0], deposit_data.pubkey_salt_hash, amount)
+        .await?;
+    let deadline = Instant::now() + Duration::from_secs(config.deposit_timeout);
+    while native_balance(client, key).await? < expected {
+        anyhow::ensure!(Instant::now() < deadline, "the deposit was not synced");
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL)).await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn rotation_into_funded_account_resumes() -> anyhow::Result<()> {
+    let path = std::env::var("LOADTEST_CONFIG")
+        .map_err(|_| anyhow::anyhow!("LOADTEST_CONFIG is not set"))?;
+    let config: LoadTestConfig = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    anyhow::ensure!(!config.faucet_keys.is_empty(), "the test needs faucet_keys");
+    let client = config.build_client();
+    let mut rng = rand::thread_rng();
+    let old_key = KeySet::rand(&mut rng);
+    let new_key = KeySet::rand(&mut rng);
+
+    // the new account holds more than the rotation moves, so it would look like
+    // it received the rotation before the rotation was sent
+    deposit(&client, &config, old_key, config.deposit_amount).await?;
+    deposit(&client, &config, new_key, 2 * config.deposit_amount).await?;
+
+    let options = RotationOptions {
+        timeout: config.tx_timeout,
+        interval: POLL_INTERVAL,
+        ..Default::default()
+    };
+    let plan = client.plan_rotation(old_key, new_key, &options).await?;
+    assert_eq!(plan.chunks.len(), 1);
+    let moved = BigUint::from(plan.amounts()[&NATIVE_TOKEN_INDEX]);
+    assert_eq!(moved, BigUint::from(config.deposit_amount));
+
+    // send the tx and keep the progress, as if the rotation stopped after it
+    let mut progress = RotationProgress::new(plan);
+    let chunk = progress.next_chunk().unwrap().clone();
+    let tx_tree_root = client
+        .send_rotation_chunk(
+            &config.endpoints.block_builder_url,
+            old_key,
+            chunk,
+            &options,
+        )
+        .await?;
+    progress.tx_tree_roots.push(tx_tree_root);
+    let saved = serde_json::to_string(&progress)?;
+
+    // resuming sends nothing again, and waits for the moved amount on top of the
+    // balance the new account had
+    let progress: RotationProgress = serde_json::from_str(&saved)?;
+    let mut sent = 0;
+    let report = client
+        .resume_rotation(
+            &config.endpoints.block_builder_url,
+            old_key,
+            new_key,
+            progress,
+            &options,
+            |_| sent += 1,
+        )
+        .await?;
+    assert_eq!(sent, 0);
+    assert_eq!(report.moved.len(), 1);
+    assert_eq!(report.moved[0].tx_tree_root, tx_tree_root);
+    assert_eq!(
+        BigUint::from(report.new_balances[&NATIVE_TOKEN_INDEX]),
+        BigUint::from(3 * config.deposit_amount)
+    );
+    assert_eq!(native_balance(&client, old_key).await?, BigUint::default());
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! `sync_verified` refusing to save a synced action whose balances were miscounted,
+//! against the deployment of the config file in `LOADTEST_CONFIG`. The miscount is
+//! injected with the `test-hooks` feature.
+
+use std::time::{Duration, Instant};
+
+use intmax2_client_sdk::client::{error::ClientError, test_hooks::inject_accounting_bug};
+use intmax2_interfaces::data::deposit_data::TokenType;
+use intmax2_zkp::{
+    common::signature::key_set::KeySet,
+    ethereum_types::{address::Address, u256::U256},
+};
+use num_bigint::BigUint;
+use tests::loadtest::LoadTestConfig;
+
+const NATIVE_TOKEN_INDEX: u64 = 0;
+const POLL_INTERVAL: u64 = 2;
+
+#[tokio::test]
+async fn sync_verified_refuses_accounting_bug() -> anyhow::Result<()> {
+    let path = std::env::var("LOADTEST_CONFIG")
+        .map_err(|_| anyhow::anyhow!("LOADTEST_CONFIG is not set"))?;
+    let config: LoadTestConfig = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    anyhow::ensure!(!config.faucet_keys.is_empty(), "the test needs faucet_keys");
+    let client = config.build_client();
+    let key = KeySet::rand(&mut rand::thread_rng());
+
+    let amount: U256 = BigUint::from(config.deposit_amount).try_into().unwrap();
+    let deposit_data = client
+        .prepare_deposit(
+            key.pubkey,
+            amount,
+            TokenType::NATIVE,
+            Address::default(),
+            U256::default(),
+        )
+        .await?;
+    client
+        .liquidity_contract
+        .deposit_native(config.faucet_keys[0], deposit_data.pubkey_salt_hash, amount)
+        .await?;
+
+    // the sync of the deposit credits one more than the deposit
+    inject_accounting_bug(1);
+    let deadline = Instant::now() + Duration::from_secs(config.deposit_timeout);
+    let (token, proved, replayed) = loop {
+        match client.sync_verified(key).await {
+            Err(ClientError::BalanceMismatch {
+                token,
+                proved,
+                replayed,
+            }) => break (token, proved, replayed),
+            // the deposit is not relayed or not in a block yet
+            Ok(_) | Err(ClientError::PendingError(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+        anyhow::ensure!(Instant::now() < deadline, "the deposit was not synced");
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL)).await;
+    };
+    assert_eq!(token, NATIVE_TOKEN_INDEX as u32);
+    assert_eq!(proved, (config.deposit_amount + 1).to_string());
+    assert_eq!(replayed, config.deposit_amount.to_string());
+
+    // nothing of the miscounted sync was saved
+    let user_data = client.get_user_data(key).await?;
+    assert!(user_data.processed_deposit_uuids.is_empty());
+    assert!(user_data.balances().get(&NATIVE_TOKEN_INDEX).is_none());
+
+    // the same deposit syncs once the accounting is right
+    client.sync_verified(key).await?;
+    let user_data = client.get_user_data(key).await?;
+    assert_eq!(user_data.processed_deposit_uuids.len(), 1);
+    assert_eq!(
+        BigUint::from(user_data.balances()[&NATIVE_TOKEN_INDEX].amount),
+        BigUint::from(config.deposit_amount)
+    );
+    Ok(())
+}
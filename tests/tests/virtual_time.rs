@@ -0,0 +1,67 @@
+//! Timeout paths of the client, run against a block builder started with
+//! `VIRTUAL_TIME=true` so that they do not have to wait for the timeouts.
+
+use intmax2_client_sdk::{
+    client::utils::check_builder_health, external_api::block_builder::BlockBuilderClient,
+};
+use intmax2_interfaces::api::block_builder::interface::BlockBuilderClientInterface;
+use tests::env::TestEnv;
+
+const DEPOSIT_TIMEOUT: u64 = 60 * 60;
+const MAX_STALENESS: u64 = 10 * 60;
+
+#[tokio::test]
+async fn deposit_times_out_after_advance() -> anyhow::Result<()> {
+    let env = TestEnv::from_env()?;
+    let time = env.now().await?;
+    assert!(
+        time.is_virtual,
+        "start the block builder with VIRTUAL_TIME=true"
+    );
+
+    // a deposit saved just now is pending
+    let deposited_at = time.now;
+    assert!(!env
+        .client_clock()
+        .await?
+        .is_timed_out(deposited_at, DEPOSIT_TIMEOUT));
+
+    env.advance_time(DEPOSIT_TIMEOUT + 1).await?;
+    assert!(env
+        .client_clock()
+        .await?
+        .is_timed_out(deposited_at, DEPOSIT_TIMEOUT));
+    Ok(())
+}
+
+#[tokio::test]
+async fn builder_becomes_stale_after_advance() -> anyhow::Result<()> {
+    let env = TestEnv::from_env()?;
+    let url = &env.block_builder_base_url;
+    let block_builder = BlockBuilderClient::new();
+
+    // force a block, and skip the accepting and proposing windows until it is posted
+    reqwest::Client::new()
+        .post(format!("{}/block-builder/post-empty-block", url))
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut dependencies = block_builder.get_health(url).await?;
+    for _ in 0..10 {
+        if dependencies.last_block_posted_at.is_some() {
+            break;
+        }
+        env.advance_time(60).await?;
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        dependencies = block_builder.get_health(url).await?;
+    }
+    assert!(dependencies.last_block_posted_at.is_some());
+    let now = env.client_clock().await?.server_now_for(url);
+    check_builder_health(&dependencies, now, Some(MAX_STALENESS))?;
+
+    env.advance_time(MAX_STALENESS + 1).await?;
+    let dependencies = block_builder.get_health(url).await?;
+    let now = env.client_clock().await?.server_now_for(url);
+    assert!(check_builder_health(&dependencies, now, Some(MAX_STALENESS)).is_err());
+    Ok(())
+}
@@ -4,16 +4,28 @@ use actix_web::{
     web::{Data, Json},
     Error,
 };
-use intmax2_interfaces::api::validity_prover::types::{
-    GetAccountInfoQuery, GetAccountInfoResponse, GetBlockMerkleProofQuery,
-    GetBlockMerkleProofResponse, GetBlockNumberByTxTreeRootQuery,
-    GetBlockNumberByTxTreeRootResponse, GetBlockNumberResponse, GetDepositInfoQuery,
-    GetDepositInfoResponse, GetDepositMerkleProofQuery, GetDepositMerkleProofResponse,
-    GetSenderLeavesQuery, GetSenderLeavesResponse, GetUpdateWitnessQuery, GetUpdateWitnessResponse,
-    GetValidityPisQuery, GetValidityPisResponse,
+use intmax2_interfaces::api::{
+    validity_prover::types::{
+        GetAccountInfoQuery, GetAccountInfoResponse, GetAccountMembershipProofQuery,
+        GetAccountMembershipProofResponse, GetBlockMerkleProofQuery, GetBlockMerkleProofResponse,
+        GetBlockNumberByTxTreeRootQuery, GetBlockNumberByTxTreeRootResponse,
+        GetBlockNumberResponse, GetDepositInfoQuery, GetDepositInfoResponse,
+        GetDepositMerkleProofQuery, GetDepositMerkleProofResponse, GetSenderLeavesQuery,
+        GetSenderLeavesResponse, GetUpdateWitnessQuery, GetUpdateWitnessResponse,
+        GetValidityPisQuery, GetValidityPisResponse,
+    },
+    version::VersionResponse,
 };
 use serde_qs::actix::QsQuery;
 
+#[get("/version")]
+pub async fn version() -> Json<VersionResponse> {
+    Json(VersionResponse::new(
+        "validity-prover",
+        env!("CARGO_PKG_VERSION"),
+    ))
+}
+
 #[get("/block-number")]
 pub async fn get_block_number(state: Data<State>) -> Result<Json<GetBlockNumberResponse>, Error> {
     let block_number = state
@@ -57,6 +69,22 @@ pub async fn get_update_witness(
     Ok(Json(GetUpdateWitnessResponse { update_witness }))
 }
 
+#[get("/get-account-membership-proof")]
+pub async fn get_account_membership_proof(
+    state: Data<State>,
+    query: QsQuery<GetAccountMembershipProofQuery>,
+) -> Result<Json<GetAccountMembershipProofResponse>, Error> {
+    let query = query.into_inner();
+    let account_membership_proof = state
+        .validity_prover
+        .get_account_membership_proof(query.block_number, query.pubkey)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    Ok(Json(GetAccountMembershipProofResponse {
+        account_membership_proof,
+    }))
+}
+
 #[get("/get-deposit-info")]
 pub async fn get_deposit_info(
     state: Data<State>,
@@ -145,9 +173,11 @@ pub async fn get_deposit_merkle_proof(
 
 pub fn validity_prover_scope() -> actix_web::Scope {
     actix_web::web::scope("/validity-prover")
+        .service(version)
         .service(get_block_number)
         .service(get_account_info)
         .service(get_update_witness)
+        .service(get_account_membership_proof)
         .service(get_deposit_info)
         .service(get_block_number_by_tx_tree_root)
         .service(get_validity_pis)
@@ -453,7 +453,7 @@ impl ValidityProver {
         Ok(block_tree.prove(leaf_block_number as u64))
     }
 
-    async fn get_account_membership_proof(
+    pub async fn get_account_membership_proof(
         &self,
         block_number: u32,
         pubkey: U256,
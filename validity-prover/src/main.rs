@@ -10,6 +10,7 @@ use std::{
 use actix_cors::Cors;
 use actix_web::{middleware::Logger, web::Data, App, HttpServer};
 use intmax2_client_sdk::utils::init_logger::init_logger;
+use intmax2_interfaces::api::CORS_EXPOSED_HEADERS;
 use tokio::time::interval;
 use validity_prover::{
     api::{api::validity_prover_scope, state::State, validity_prover::ValidityProver},
@@ -73,7 +74,7 @@ async fn main() -> std::io::Result<()> {
         }
     });
     HttpServer::new(move || {
-        let cors = Cors::permissive();
+        let cors = Cors::permissive().expose_headers(CORS_EXPOSED_HEADERS);
         App::new()
             .wrap(cors)
             .wrap(Logger::new("Request: %r | Status: %s | Duration: %Ts"))
@@ -35,7 +35,7 @@ interface Deposit {
 
 export async function fetchDepositHistory(storeVaultClient: StoreVaultClient, privateKey: string, userData: JsUserData): Promise<Deposit[]> {
     const allData = await storeVaultClient.getAllAfter("deposit", userData.pubkey, 0);
-    const processedUuids = userData.processed_deposit_uuids;
+    const processedUuids = userData.get_processed_uuids("deposit");
 
     const history = [];
     for (const [metaData, data] of allData) {
@@ -96,7 +96,7 @@ export interface Receive {
 
 export async function fetchReceiveHistory(storeVaultClient: StoreVaultClient, privateKey: string, userData: JsUserData): Promise<Receive[]> {
     const allData = await storeVaultClient.getAllAfter("transfer", userData.pubkey, 0);
-    const processedUuids = userData.processed_transfer_uuids
+    const processedUuids = userData.get_processed_uuids("transfer")
 
     const history = [];
     for (const [metaData, data] of allData) {
@@ -160,7 +160,7 @@ export interface Transfer {
 
 export async function fetchSendHistory(storeVaultClient: StoreVaultClient, privateKey: string, userData: JsUserData): Promise<Send[]> {
     const allData = await storeVaultClient.getAllAfter("tx", userData.pubkey, 0);
-    const processedUuids = userData.processed_tx_uuids
+    const processedUuids = userData.get_processed_uuids("tx")
 
     const history = [];
     for (const [metaData, data] of allData) {
@@ -1,5 +1,5 @@
 import { cleanEnv, num, str, url } from 'envalid';
-import { Config, finalize_tx, generate_intmax_account_from_eth_key, get_user_data, JsBlockProposal, JsGenericAddress, JsTransfer, JsTxRequestMemo, prepare_deposit, query_proposal, send_tx_request, sync, sync_withdrawals, } from '../pkg';
+import { Config, estimate_deposit_fee, finalize_tx, generate_intmax_account_from_eth_key, get_user_data, JsBlockProposal, JsGenericAddress, JsTransfer, JsTransferEvent, JsTransferOptions, JsTxRequestMemo, prepare_deposit, query_proposal, send_tx_request, sync, sync_withdrawals, transfer, } from '../pkg';
 import { postEmptyBlock, } from './state-manager';
 import { generateRandomHex } from './utils';
 import { printHistory } from './history';
@@ -76,6 +76,9 @@ async function main() {
   const balance = await getEthBalance(ethKey, env.L1_RPC_URL);
   console.log("balance: ", balance);
 
+  const depositFee = BigInt(await estimate_deposit_fee(config, ethKey, amount, 0)); // native token is index 0
+  console.log("estimated deposit fee (wei): ", depositFee);
+
   const pubkeySaltHash = await prepare_deposit(config, publicKey, amount, tokenType, tokenAddress, tokenId);
   console.log("pubkeySaltHash: ", pubkeySaltHash);
 
@@ -101,12 +104,9 @@ async function main() {
   const someonesKey = await generate_intmax_account_from_eth_key(generateRandomHex(32));
   const genericAddress = new JsGenericAddress(true, someonesKey.pubkey);
   const salt = generateRandomHex(32);
-  const transfer = new JsTransfer(genericAddress, 0, "1", salt);
-
-  await sendTx(config, env.BLOCK_BUILDER_BASE_URL, privateKey, [transfer]);
+  const transferToSomeone = new JsTransfer(genericAddress, 0, "1", salt);
 
-  // wait for the validity prover syncs
-  await sleep(30);
+  await sendTransfer(config, env.BLOCK_BUILDER_BASE_URL, privateKey, [transferToSomeone]);
 
   // get the receiver's balance
   await syncBalanceProof(config, privateKey);
@@ -163,7 +163,7 @@ async function sendTx(config: Config, block_builder_base_url: string, privateKey
   let memo: JsTxRequestMemo | undefined = undefined;
   for (let i = 0; i < env.BLOCK_BUILDER_REQUEST_LIMIT; i++) {
     try {
-      memo = await send_tx_request(config, block_builder_base_url, privateKey, transfers);
+      memo = await send_tx_request(config, block_builder_base_url, privateKey, transfers, undefined);
       break;
     } catch (error) {
       console.log("Error sending tx request: ", error, "retrying...");
@@ -200,6 +200,23 @@ async function sendTx(config: Config, block_builder_base_url: string, privateKey
   console.log("Tx finalized");
 }
 
+// send a tx with the all-in-one transfer function, checking the progress events
+async function sendTransfer(config: Config, block_builder_base_url: string, privateKey: string, transfers: JsTransfer[]) {
+  console.log("Sending transfer...");
+  const events: string[] = [];
+  const options = new JsTransferOptions();
+  options.auto_sync = false; // synced just before
+  const receipt = await transfer(config, block_builder_base_url, privateKey, transfers, options, (event: JsTransferEvent) => {
+    console.log("Transfer event: ", event.kind, event.tx_tree_root, event.block_number);
+    events.push(event.kind);
+  });
+  const expected = ["requested", "proposal_received", "backup_saved", "signed", "included"];
+  if (JSON.stringify(events) !== JSON.stringify(expected)) {
+    throw new Error(`Unexpected transfer events: ${events}`);
+  }
+  console.log(`Transfer included in block ${receipt.block_number}, tx tree root ${receipt.tx_tree_root}`);
+}
+
 async function sleep(sec: number) {
   return new Promise((resolve) => setTimeout(resolve, sec * 1000));
 }
@@ -1,23 +1,42 @@
+use std::time::Duration;
+
 use intmax2_client_sdk::{
-    client::{client::Client, config::ClientConfig},
+    client::{
+        client::Client,
+        config::{ClientConfig, WASM_CHECKPOINT_INTERVAL},
+        provisional::global_provisional_store,
+        withdrawal_onchain::DEFAULT_WITHDRAWAL_ONCHAIN_GRACE,
+    },
     external_api::{
-        balance_prover::BalanceProverClient,
+        balance_prover::{BalanceProverClient, DEFAULT_BALANCE_PROVER_TIMEOUT},
         block_builder::BlockBuilderClient,
         contract::{liquidity_contract::LiquidityContract, rollup_contract::RollupContract},
+        mirrored_store_vault::MirroredStoreVaultClient,
         store_vault_server::StoreVaultServerClient,
+        utils::{query::DEFAULT_TIMEOUT, url::normalize_base_url},
         validity_prover::ValidityProverClient,
         withdrawal_server::WithdrawalServerClient,
     },
 };
 use serde::{Deserialize, Serialize};
-use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::{prelude::wasm_bindgen, JsError};
+
+use crate::js_types::breaker::JsRetryConfig;
 
 type BB = BlockBuilderClient;
-type S = StoreVaultServerClient;
+type S = MirroredStoreVaultClient<StoreVaultServerClient>;
 type V = ValidityProverClient;
 type B = BalanceProverClient;
 type W = WithdrawalServerClient;
 
+fn default_timeout_ms() -> u64 {
+    DEFAULT_TIMEOUT.as_millis() as u64
+}
+
+fn default_balance_prover_timeout_ms() -> u64 {
+    DEFAULT_BALANCE_PROVER_TIMEOUT.as_millis() as u64
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[wasm_bindgen(getter_with_clone)]
@@ -25,6 +44,17 @@ pub struct Config {
     /// URL of the store vault server
     pub store_vault_server_url: String,
 
+    /// URL to read from the store vault, e.g. a CDN. Defaults to `store_vault_server_url`.
+    #[serde(default)]
+    pub store_vault_read_url: Option<String>,
+
+    /// URL to write to the store vault. Defaults to `store_vault_server_url`.
+    #[serde(default)]
+    pub store_vault_write_url: Option<String>,
+
+    /// URLs of store vault servers to mirror backups to
+    pub secondary_store_vault_urls: Vec<String>,
+
     /// URL of the balance prover
     pub balance_prover_url: String,
 
@@ -42,6 +72,57 @@ pub struct Config {
     /// If this time is exceeded, the tx request will be ignored
     pub tx_timeout: u64,
 
+    /// Number of chained actions between sync checkpoints. Defaults to 25.
+    /// A smaller value bounds the memory used by a long sync.
+    pub checkpoint_interval: Option<u32>,
+
+    /// Seconds without a posted block after which the block builder is considered
+    /// stalled and txs are not sent to it. Disabled if not set.
+    pub builder_max_staleness: Option<u64>,
+
+    /// Throw when the block builder returns a proposal that is not for the queried
+    /// tx. By default such a proposal is ignored, as if none was available yet.
+    #[serde(default)]
+    pub strict_proposal_validation: bool,
+
+    /// Seconds after a withdrawal request within which a withdrawal reported as
+    /// done has to appear on L1. Defaults to an hour.
+    pub withdrawal_onchain_grace: Option<u64>,
+
+    /// Retries of the balance prover requests. Proving is slow, so a prover under
+    /// load may warrant more attempts than the other servers. Defaults to the
+    /// process-wide settings.
+    #[serde(default)]
+    pub balance_prover_retry: Option<JsRetryConfig>,
+
+    /// Retries of the block builder requests. Defaults to the process-wide
+    /// settings.
+    #[serde(default)]
+    pub block_builder_retry: Option<JsRetryConfig>,
+
+    /// Milliseconds after which an attempt of a balance prover request fails.
+    /// Defaults to 10 minutes, as proving is slow.
+    #[serde(default = "default_balance_prover_timeout_ms")]
+    pub balance_prover_timeout_ms: u64,
+
+    /// Milliseconds after which an attempt of a block builder request fails.
+    /// Defaults to a minute, as do the other timeouts.
+    #[serde(default = "default_timeout_ms")]
+    pub block_builder_timeout_ms: u64,
+
+    /// Milliseconds after which an attempt of a validity prover request fails
+    #[serde(default = "default_timeout_ms")]
+    pub validity_prover_timeout_ms: u64,
+
+    /// Milliseconds after which an attempt of a store vault request fails
+    #[serde(default = "default_timeout_ms")]
+    pub store_vault_timeout_ms: u64,
+
+    /// Milliseconds after which an attempt of a withdrawal aggregator request
+    /// fails
+    #[serde(default = "default_timeout_ms")]
+    pub withdrawal_server_timeout_ms: u64,
+
     /// URL of the Ethereum RPC
     pub l1_rpc_url: String,
 
@@ -51,6 +132,11 @@ pub struct Config {
     /// Address of the liquidity contract
     pub liquidity_contract_address: String,
 
+    /// Ethereum block number when the liquidity contract was deployed, from which
+    /// its events are searched. Defaults to 0.
+    #[serde(default)]
+    pub liquidity_contract_deployed_block_number: Option<u64>,
+
     /// URL of the Scroll RPC
     pub l2_rpc_url: String,
 
@@ -82,42 +168,122 @@ impl Config {
         l2_chain_id: u64,
         rollup_contract_address: String,
         rollup_contract_deployed_block_number: u64,
-    ) -> Config {
-        Config {
-            store_vault_server_url,
-            balance_prover_url,
-            validity_prover_url,
-            withdrawal_server_url,
+    ) -> Result<Config, JsError> {
+        let normalize = |field: &str, url: &str| {
+            normalize_base_url(field, url).map_err(|e| JsError::new(&e.to_string()))
+        };
+        Ok(Config {
+            store_vault_server_url: normalize("store_vault_server_url", &store_vault_server_url)?,
+            store_vault_read_url: None,
+            store_vault_write_url: None,
+            secondary_store_vault_urls: Vec::new(),
+            balance_prover_url: normalize("balance_prover_url", &balance_prover_url)?,
+            validity_prover_url: normalize("validity_prover_url", &validity_prover_url)?,
+            withdrawal_server_url: normalize("withdrawal_server_url", &withdrawal_server_url)?,
             deposit_timeout,
             tx_timeout,
+            checkpoint_interval: Some(WASM_CHECKPOINT_INTERVAL),
+            builder_max_staleness: None,
+            strict_proposal_validation: false,
+            withdrawal_onchain_grace: None,
+            balance_prover_retry: None,
+            block_builder_retry: None,
+            balance_prover_timeout_ms: default_balance_prover_timeout_ms(),
+            block_builder_timeout_ms: default_timeout_ms(),
+            validity_prover_timeout_ms: default_timeout_ms(),
+            store_vault_timeout_ms: default_timeout_ms(),
+            withdrawal_server_timeout_ms: default_timeout_ms(),
             l1_rpc_url,
             l1_chain_id,
             liquidity_contract_address,
+            liquidity_contract_deployed_block_number: None,
             l2_rpc_url,
             l2_chain_id,
             rollup_contract_address,
             rollup_contract_deployed_block_number,
+        })
+    }
+
+    /// Check and normalize the server URLs, e.g. after setting the optional ones.
+    /// A trailing slash is removed, and a path is kept as the prefix of every
+    /// endpoint.
+    pub fn normalize_urls(&mut self) -> Result<(), JsError> {
+        let normalize = |field: &str, url: &str| {
+            normalize_base_url(field, url).map_err(|e| JsError::new(&e.to_string()))
+        };
+        self.store_vault_server_url =
+            normalize("store_vault_server_url", &self.store_vault_server_url)?;
+        if let Some(url) = &self.store_vault_read_url {
+            self.store_vault_read_url = Some(normalize("store_vault_read_url", url)?);
         }
+        if let Some(url) = &self.store_vault_write_url {
+            self.store_vault_write_url = Some(normalize("store_vault_write_url", url)?);
+        }
+        self.secondary_store_vault_urls = self
+            .secondary_store_vault_urls
+            .iter()
+            .map(|url| normalize("secondary_store_vault_urls", url))
+            .collect::<Result<_, _>>()?;
+        self.balance_prover_url = normalize("balance_prover_url", &self.balance_prover_url)?;
+        self.validity_prover_url = normalize("validity_prover_url", &self.validity_prover_url)?;
+        self.withdrawal_server_url =
+            normalize("withdrawal_server_url", &self.withdrawal_server_url)?;
+        Ok(())
     }
 }
 
 pub fn get_client(config: &Config) -> Client<BB, S, V, B, W> {
-    let block_builder = BB::new();
-    let store_vault_server = S::new(&config.store_vault_server_url);
-    let balance_prover: BalanceProverClient = B::new(&config.balance_prover_url);
-    let validity_prover = V::new(&config.validity_prover_url);
-    let withdrawal_server = W::new(&config.withdrawal_server_url);
+    let store_vault_timeout = Duration::from_millis(config.store_vault_timeout_ms);
+    let mut block_builder =
+        BB::new().with_timeout(Duration::from_millis(config.block_builder_timeout_ms));
+    if let Some(retry) = &config.block_builder_retry {
+        block_builder = block_builder.with_retry_config(retry.to_retry_config());
+    }
+    let store_vault_server = S::new(
+        StoreVaultServerClient::with_urls(
+            config
+                .store_vault_read_url
+                .as_ref()
+                .unwrap_or(&config.store_vault_server_url),
+            config
+                .store_vault_write_url
+                .as_ref()
+                .unwrap_or(&config.store_vault_server_url),
+        )
+        .with_timeout(store_vault_timeout),
+        config
+            .secondary_store_vault_urls
+            .iter()
+            .map(|url| StoreVaultServerClient::new(url).with_timeout(store_vault_timeout))
+            .collect(),
+    );
+    let mut balance_prover: BalanceProverClient = B::new(&config.balance_prover_url)
+        .with_timeout(Duration::from_millis(config.balance_prover_timeout_ms));
+    if let Some(retry) = &config.balance_prover_retry {
+        balance_prover = balance_prover.with_retry_config(retry.to_retry_config());
+    }
+    let validity_prover = V::new(&config.validity_prover_url)
+        .with_timeout(Duration::from_millis(config.validity_prover_timeout_ms));
+    let withdrawal_server = W::new(&config.withdrawal_server_url)
+        .with_timeout(Duration::from_millis(config.withdrawal_server_timeout_ms));
 
     let client_config = ClientConfig {
         deposit_timeout: config.deposit_timeout,
         tx_timeout: config.tx_timeout,
+        checkpoint_interval: config.checkpoint_interval,
+        builder_max_staleness: config.builder_max_staleness,
+        strict_proposal_validation: config.strict_proposal_validation,
+        withdrawal_onchain_grace: config
+            .withdrawal_onchain_grace
+            .unwrap_or(DEFAULT_WITHDRAWAL_ONCHAIN_GRACE),
     };
 
     let liquidity_contract = LiquidityContract::new(
         &config.l1_rpc_url,
         config.l1_chain_id,
         config.liquidity_contract_address.parse().unwrap(),
-    );
+    )
+    .with_deployed_block_number(config.liquidity_contract_deployed_block_number.unwrap_or(0));
 
     let rollup_contract = RollupContract::new(
         &config.l2_rpc_url,
@@ -135,5 +301,6 @@ pub fn get_client(config: &Config) -> Client<BB, S, V, B, W> {
         liquidity_contract,
         rollup_contract,
         config: client_config,
+        provisional_store: global_provisional_store(),
     }
 }
@@ -0,0 +1,46 @@
+use intmax2_client_sdk::client::backup_health::{BackupCoverage, BackupHealth};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsBackupCoverage {
+    pub expected: u32,
+    pub backed_up: u32,
+    /// uuids of the missing backups, or tx tree roots for txs only known locally
+    pub missing: Vec<String>,
+}
+
+impl JsBackupCoverage {
+    fn from_coverage(coverage: &BackupCoverage) -> Self {
+        Self {
+            expected: coverage.expected as u32,
+            backed_up: coverage.backed_up as u32,
+            missing: coverage.missing.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsBackupHealth {
+    pub deposits: JsBackupCoverage,
+    pub transfers: JsBackupCoverage,
+    pub txs: JsBackupCoverage,
+    pub user_data_present: bool,
+    pub last_backup_at: Option<u64>,
+    /// true if nothing is missing
+    pub is_healthy: bool,
+}
+
+impl JsBackupHealth {
+    pub fn from_backup_health(health: &BackupHealth) -> Self {
+        Self {
+            deposits: JsBackupCoverage::from_coverage(&health.deposits),
+            transfers: JsBackupCoverage::from_coverage(&health.transfers),
+            txs: JsBackupCoverage::from_coverage(&health.txs),
+            user_data_present: health.user_data_present,
+            last_backup_at: health.last_backup_at,
+            is_healthy: health.is_healthy(),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use intmax2_client_sdk::utils::circuit_verifiers::{BalanceProofCheck, BalanceProofStatus};
+use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// A balance proof checked against the pubkey of a balance claim and the chain.
+/// `status` is "valid", "invalidProof", "wrongPubkey" or "wrongPublicState". The
+/// balances are behind `private_commitment`, which the claimant has to open.
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsBalanceProofCheck {
+    pub status: String,
+    pub is_valid: bool,
+    pub pubkey: String,
+    pub block_number: u32,
+    pub block_hash: String,
+    pub private_commitment: String,
+}
+
+impl From<&BalanceProofCheck> for JsBalanceProofCheck {
+    fn from(check: &BalanceProofCheck) -> Self {
+        let status = match check.status {
+            BalanceProofStatus::Valid => "valid",
+            BalanceProofStatus::InvalidProof => "invalidProof",
+            BalanceProofStatus::WrongPubkey => "wrongPubkey",
+            BalanceProofStatus::WrongPublicState => "wrongPublicState",
+        };
+        let public_inputs = &check.public_inputs;
+        Self {
+            status: status.to_string(),
+            is_valid: check.status == BalanceProofStatus::Valid,
+            pubkey: public_inputs.pubkey.to_hex(),
+            block_number: public_inputs.public_state.block_number,
+            block_hash: public_inputs.public_state.block_hash.to_hex(),
+            private_commitment: public_inputs.private_commitment.to_string(),
+        }
+    }
+}
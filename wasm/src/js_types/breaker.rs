@@ -0,0 +1,85 @@
+use intmax2_client_sdk::external_api::utils::retry::{
+    retry_config, BreakerState, HostBreakerStatus, RetryConfig,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsBreakerStatus {
+    pub host: String,
+    pub state: String, // "closed", "open" or "halfOpen"
+    pub consecutive_failures: u32,
+    pub retry_after_ms: Option<u64>,
+}
+
+impl JsBreakerStatus {
+    pub fn from_status(status: &HostBreakerStatus) -> Self {
+        let state = match status.state {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "halfOpen",
+        };
+        Self {
+            host: status.host.clone(),
+            state: state.to_string(),
+            consecutive_failures: status.consecutive_failures,
+            retry_after_ms: status.retry_after_ms,
+        }
+    }
+}
+
+/// Retries of the requests to one server. The circuit breaker settings stay the
+/// process-wide ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsRetryConfig {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Cap of the first backoff delay
+    pub initial_delay_ms: u64,
+    /// Multiplier of the backoff cap after each attempt
+    pub backoff_factor: f64,
+    pub max_delay_ms: u64,
+    /// Wait a random time up to the backoff cap instead of the whole cap
+    #[serde(default = "default_jitter")]
+    pub jitter: bool,
+}
+
+fn default_jitter() -> bool {
+    true
+}
+
+#[wasm_bindgen]
+impl JsRetryConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        max_retries: u32,
+        initial_delay_ms: u64,
+        backoff_factor: f64,
+        max_delay_ms: u64,
+        jitter: bool,
+    ) -> Self {
+        Self {
+            max_retries,
+            initial_delay_ms,
+            backoff_factor,
+            max_delay_ms,
+            jitter,
+        }
+    }
+}
+
+impl JsRetryConfig {
+    pub fn to_retry_config(&self) -> RetryConfig {
+        RetryConfig {
+            max_retries: self.max_retries,
+            initial_delay_ms: self.initial_delay_ms,
+            backoff_factor: self.backoff_factor,
+            max_delay_ms: self.max_delay_ms,
+            jitter: self.jitter,
+            ..retry_config()
+        }
+    }
+}
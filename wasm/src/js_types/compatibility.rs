@@ -0,0 +1,61 @@
+use intmax2_client_sdk::client::compatibility::{
+    CompatibilityReport, ServiceCompatibility, ServiceStatus,
+};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsServiceCompatibility {
+    pub service: String,
+    pub base_url: String,
+    pub server_version: Option<String>,
+    pub protocol_version: Option<u32>,
+    // "compatible", "client_too_old", "server_too_old" or "unreachable"
+    pub status: String,
+    pub reason: Option<String>,
+}
+
+impl JsServiceCompatibility {
+    fn from_service(service: &ServiceCompatibility) -> Self {
+        let (status, reason) = match &service.status {
+            ServiceStatus::Compatible => ("compatible", None),
+            ServiceStatus::ClientTooOld { reason } => ("client_too_old", Some(reason.clone())),
+            ServiceStatus::ServerTooOld { reason } => ("server_too_old", Some(reason.clone())),
+            ServiceStatus::Unreachable { error } => ("unreachable", Some(error.clone())),
+        };
+        Self {
+            service: service.service.clone(),
+            base_url: service.base_url.clone(),
+            server_version: service.server_version.clone(),
+            protocol_version: service.protocol_version,
+            status: status.to_string(),
+            reason,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsCompatibilityReport {
+    pub client_version: String,
+    /// false if a server needs a newer package, or is too old for this one
+    pub compatible: bool,
+    pub services: Vec<JsServiceCompatibility>,
+    /// package version to update to, when a server requires one
+    pub min_required_frontend_version: Option<String>,
+}
+
+impl JsCompatibilityReport {
+    pub fn from_report(report: &CompatibilityReport) -> Self {
+        Self {
+            client_version: report.client_version.clone(),
+            compatible: report.compatible,
+            services: report
+                .services
+                .iter()
+                .map(JsServiceCompatibility::from_service)
+                .collect(),
+            min_required_frontend_version: report.min_required_frontend_version.clone(),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use intmax2_client_sdk::client::consolidation::ConsolidationReport;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use super::{
+    data::{token_amounts, JsTokenAmount},
+    rotation::JsMovedToken,
+};
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsConsolidationReport {
+    pub pubkey: String,
+    pub consolidated: Vec<JsMovedToken>,
+    pub absorbed_receives: usize,
+    pub remaining_receives: usize,
+    pub balances: Vec<JsTokenAmount>,
+}
+
+impl JsConsolidationReport {
+    pub fn from_consolidation_report(report: &ConsolidationReport) -> Self {
+        Self {
+            pubkey: report.pubkey.to_string(),
+            consolidated: report
+                .consolidated
+                .iter()
+                .map(|moved| JsMovedToken {
+                    token_index: moved.token_index,
+                    amount: moved.amount.to_string(),
+                    tx_tree_root: moved.tx_tree_root.to_string(),
+                })
+                .collect(),
+            absorbed_receives: report.absorbed_receives,
+            remaining_receives: report.remaining_receives,
+            balances: token_amounts(&report.balances),
+        }
+    }
+}
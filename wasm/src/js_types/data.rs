@@ -1,9 +1,26 @@
-use intmax2_interfaces::data::{
-    deposit_data::DepositData, transfer_data::TransferData, tx_data::TxData, user_data::UserData,
+use std::{collections::BTreeMap, str::FromStr as _};
+
+use intmax2_client_sdk::client::{
+    balance_history::{BalanceSeries, HistoricalBalance},
+    balance_view::BalanceView,
+    client::SyncProgress,
+    external_deposit::DepositRequest,
+    provisional::OptimisticBalances,
+    strategy::{
+        deposit::PendingDeposit,
+        strategy::{ActionKind, ActionSummary},
+    },
+};
+use intmax2_interfaces::{
+    api::store_vault_server::interface::DataType,
+    data::{
+        deposit_data::DepositData, transfer_data::TransferData, tx_data::TxData,
+        user_data::UserData,
+    },
 };
-use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
 use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
-use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::{prelude::wasm_bindgen, JsError};
 
 use super::common::{JsTransfer, JsTx};
 
@@ -20,6 +37,9 @@ pub struct JsDepositData {
     pub token_type: u8,
     pub token_address: String, // hex string
     pub token_id: String,      // 10 base string
+    pub token_index: Option<u32>,
+    /// Not in a block of the validity prover yet
+    pub pending: bool,
 }
 
 impl JsDepositData {
@@ -31,6 +51,33 @@ impl JsDepositData {
             token_type: deposit_data.token_type as u8,
             token_address: deposit_data.token_address.to_hex(),
             token_id: deposit_data.token_id.to_string(),
+            token_index: deposit_data.token_index,
+            pending: false,
+        }
+    }
+
+    pub fn from_pending_deposit(deposit: &PendingDeposit) -> Self {
+        Self {
+            pending: deposit.is_pending(),
+            ..Self::from_deposit_data(&deposit.deposit_data)
+        }
+    }
+}
+
+/// Hash to give to the depositor, and the salt to keep for
+/// `claim_external_deposit`
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsDepositRequest {
+    pub pubkey_salt_hash: String, // hex string
+    pub salt: String,             // hex string
+}
+
+impl JsDepositRequest {
+    pub fn from_deposit_request(request: &DepositRequest) -> Self {
+        Self {
+            pubkey_salt_hash: request.pubkey_salt_hash.to_hex(),
+            salt: request.salt.to_string(),
         }
     }
 }
@@ -40,6 +87,7 @@ impl JsDepositData {
 pub struct JsTransferData {
     pub sender: String,
     pub transfer: JsTransfer,
+    pub claim_deadline_block: Option<u32>,
 }
 
 impl JsTransferData {
@@ -47,6 +95,78 @@ impl JsTransferData {
         Self {
             sender: transfer_data.sender.to_hex(),
             transfer: JsTransfer::from_transfer(&transfer_data.transfer),
+            claim_deadline_block: transfer_data.claim_deadline_block,
+        }
+    }
+}
+
+/// Data of a backup blob of unknown type, as decrypted by `decrypt_any`. Only the
+/// field of `data_type` is set.
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsDecryptedData {
+    pub data_type: String, // "deposit", "transfer", "withdrawal" or "tx"
+    pub deposit: Option<JsDepositData>,
+    pub transfer: Option<JsTransferData>, // also set for "withdrawal"
+    pub tx: Option<JsTxData>,
+}
+
+impl JsDecryptedData {
+    pub fn from_deposit_data(deposit_data: &DepositData) -> Self {
+        Self {
+            data_type: DataType::Deposit.to_string(),
+            deposit: Some(JsDepositData::from_deposit_data(deposit_data)),
+            transfer: None,
+            tx: None,
+        }
+    }
+
+    pub fn from_transfer_data(transfer_data: &TransferData<F, C, D>) -> Self {
+        let data_type = if transfer_data.transfer.recipient.is_pubkey {
+            DataType::Transfer
+        } else {
+            DataType::Withdrawal
+        };
+        Self {
+            data_type: data_type.to_string(),
+            deposit: None,
+            transfer: Some(JsTransferData::from_transfer_data(transfer_data)),
+            tx: None,
+        }
+    }
+
+    pub fn from_tx_data(tx_data: &TxData<F, C, D>) -> Self {
+        Self {
+            data_type: DataType::Tx.to_string(),
+            deposit: None,
+            transfer: None,
+            tx: Some(JsTxData::from_tx_data(tx_data)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsDecryptProgress {
+    pub phase: String, // "decrypt" or "parse"
+    pub percent: u32,
+}
+
+/// Progress passed to the callback of `sync_with_staged_progress`
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsSyncProgress {
+    pub current: u32,
+    pub total: u32,
+    pub stage: String, // "actions", "blocks" or "withdrawals"
+}
+
+impl JsSyncProgress {
+    pub fn from_progress(progress: SyncProgress) -> Self {
+        Self {
+            current: progress.current,
+            total: progress.total,
+            stage: progress.stage.as_str().to_string(),
         }
     }
 }
@@ -56,6 +176,7 @@ impl JsTransferData {
 pub struct JsTxData {
     pub tx: JsTx,
     pub transfers: Vec<JsTransfer>,
+    pub claim_deadline_block: Option<u32>,
 }
 
 impl JsTxData {
@@ -67,10 +188,16 @@ impl JsTxData {
             .iter()
             .map(JsTransfer::from_transfer)
             .collect::<Vec<_>>();
-        Self { tx, transfers }
+        Self {
+            tx,
+            transfers,
+            claim_deadline_block: tx_data.claim_deadline_block,
+        }
     }
 }
 
+/// The user data. Scalar fields are materialized immediately, while large collections
+/// (nullifiers and processed uuids) are converted only when requested.
 #[derive(Debug, Clone)]
 #[wasm_bindgen(getter_with_clone)]
 pub struct JsUserData {
@@ -86,6 +213,51 @@ pub struct JsUserData {
     /// The private commitment of the user
     pub private_commitment: String,
 
+    /// The nonce of the user's private state
+    pub nonce: u32,
+
+    /// The block number of the user's balance proof, if it is in the store vault and
+    /// could be fetched
+    pub last_proof_block_number: Option<u32>,
+
+    /// The last unix timestamp of processed deposits
+    pub deposit_lpt: u64,
+
+    /// The last unix timestamp of processed transfers
+    pub transfer_lpt: u64,
+
+    /// The last unix timestamp of processed txs
+    pub tx_lpt: u64,
+
+    /// The last unix timestamp of processed withdrawals
+    pub withdrawal_lpt: u64,
+
+    user_data: UserData,
+}
+
+/// The user data with all collections materialized, as returned by `to_full_object`.
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsFullUserData {
+    /// The user public key
+    pub pubkey: String,
+
+    /// The block number of the user data
+    pub block_number: u32,
+
+    /// The token balances of the user
+    pub balances: Vec<TokenBalance>,
+
+    /// The private commitment of the user
+    pub private_commitment: String,
+
+    /// The nonce of the user's private state
+    pub nonce: u32,
+
+    /// The block number of the user's balance proof, if it is in the store vault and
+    /// could be fetched
+    pub last_proof_block_number: Option<u32>,
+
     /// The last unix timestamp of processed deposits
     pub deposit_lpt: u64,
 
@@ -125,8 +297,213 @@ pub struct TokenBalance {
     pub is_insufficient: bool,
 }
 
+/// Balance of a fungible token with the token it stands for
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsFungibleBalance {
+    pub token_index: u32,
+    pub token_type: u8,
+    pub token_address: String, // hex string
+    pub amount: String,        // 10 base string
+    pub is_insufficient: bool,
+}
+
+/// An owned NFT. The amount is always 1 for ERC721.
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsOwnedNft {
+    pub token_index: u32,
+    pub token_id: String, // 10 base string
+    pub amount: String,   // 10 base string
+}
+
+/// The NFTs owned of one contract
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsNftHolding {
+    pub token_type: u8,
+    pub token_address: String, // hex string
+    pub tokens: Vec<JsOwnedNft>,
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsBalanceView {
+    pub fungible: Vec<JsFungibleBalance>,
+    pub nfts: Vec<JsNftHolding>,
+}
+
+impl JsBalanceView {
+    pub fn from_balance_view(view: &BalanceView) -> Self {
+        let fungible = view
+            .fungible
+            .iter()
+            .map(|balance| JsFungibleBalance {
+                token_index: balance.token_index,
+                token_type: balance.token_type as u8,
+                token_address: balance.token_address.to_hex(),
+                amount: balance.amount.to_string(),
+                is_insufficient: balance.is_insufficient,
+            })
+            .collect();
+        let nfts = view
+            .nfts
+            .iter()
+            .map(|holding| JsNftHolding {
+                token_type: holding.token_type as u8,
+                token_address: holding.token_address.to_hex(),
+                tokens: holding
+                    .tokens
+                    .iter()
+                    .map(|token| JsOwnedNft {
+                        token_index: token.token_index,
+                        token_id: token.token_id.to_string(),
+                        amount: token.amount.to_string(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self { fungible, nfts }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsOptimisticBalance {
+    pub token_index: u32,
+    pub settled: String, // 10 base string
+    pub amount: String,  // 10 base string
+}
+
+/// Change of a balance not yet reflected in the balance proof
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsProvisionalDelta {
+    pub kind: String, // "debit" or "credit"
+    pub token_index: u32,
+    pub amount: String,       // 10 base string
+    pub tx_tree_root: String, // hex string
+    pub created_at: u64,
+    pub status: String,     // "pending", "settled" or "failed"
+    pub confidence: String, // "low" or "high"
+    pub block_number: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsOptimisticBalances {
+    pub balances: Vec<JsOptimisticBalance>,
+    pub deltas: Vec<JsProvisionalDelta>,
+}
+
+// the serde name of a unit variant
+fn variant_name<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+impl JsOptimisticBalances {
+    pub fn from_optimistic_balances(balances: &OptimisticBalances) -> Self {
+        Self {
+            balances: balances
+                .balances
+                .iter()
+                .map(|balance| JsOptimisticBalance {
+                    token_index: balance.token_index,
+                    settled: balance.settled.to_string(),
+                    amount: balance.amount.to_string(),
+                })
+                .collect(),
+            deltas: balances
+                .deltas
+                .iter()
+                .map(|delta| JsProvisionalDelta {
+                    kind: variant_name(&delta.kind),
+                    token_index: delta.token_index,
+                    amount: delta.amount.to_string(),
+                    tx_tree_root: delta.tx_tree_root.to_hex(),
+                    created_at: delta.created_at,
+                    status: variant_name(&delta.status),
+                    confidence: variant_name(&delta.confidence()),
+                    block_number: delta.block_number,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsTokenAmount {
+    pub token_index: u32,
+    pub amount: String, // 10 base string
+}
+
+pub(crate) fn token_amounts(balances: &BTreeMap<u32, U256>) -> Vec<JsTokenAmount> {
+    balances
+        .iter()
+        .map(|(token_index, amount)| JsTokenAmount {
+            token_index: *token_index,
+            amount: amount.to_string(),
+        })
+        .collect()
+}
+
+/// Balances as of a block, replayed from the history
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsHistoricalBalance {
+    pub block_number: u32,
+    pub balances: Vec<JsTokenAmount>,
+    pub excluded_entries: usize, // entries not yet in a block
+}
+
+impl JsHistoricalBalance {
+    pub fn from_historical_balance(balance: &HistoricalBalance) -> Self {
+        Self {
+            block_number: balance.block_number,
+            balances: token_amounts(&balance.balances),
+            excluded_entries: balance.excluded_entries,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsBalancePoint {
+    pub start: u64, // unix timestamp, inclusive
+    pub end: u64,   // unix timestamp, exclusive
+    pub balances: Vec<JsTokenAmount>,
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsBalanceSeries {
+    pub points: Vec<JsBalancePoint>,
+    pub excluded_entries: usize,
+}
+
+impl JsBalanceSeries {
+    pub fn from_balance_series(series: &BalanceSeries) -> Self {
+        Self {
+            points: series
+                .points
+                .iter()
+                .map(|point| JsBalancePoint {
+                    start: point.start,
+                    end: point.end,
+                    balances: token_amounts(&point.balances),
+                })
+                .collect(),
+            excluded_entries: series.excluded_entries,
+        }
+    }
+}
+
 impl JsUserData {
-    pub fn from_user_data(user_data: &UserData) -> Self {
+    pub fn new(user_data: UserData) -> Self {
         let balances = user_data
             .balances()
             .iter()
@@ -145,19 +522,96 @@ impl JsUserData {
             pubkey: user_data.pubkey.to_hex(),
             block_number: user_data.block_number,
             balances,
-            private_commitment: user_data
-                .full_private_state
-                .to_private_state()
-                .commitment()
-                .to_string(),
+            private_commitment: user_data.private_commitment().to_string(),
+            nonce: user_data.full_private_state.nonce,
+            last_proof_block_number: None,
             deposit_lpt: user_data.deposit_lpt,
             transfer_lpt: user_data.transfer_lpt,
             tx_lpt: user_data.tx_lpt,
             withdrawal_lpt: user_data.withdrawal_lpt,
-            processed_deposit_uuids: user_data.processed_deposit_uuids.clone(),
-            processed_transfer_uuids: user_data.processed_transfer_uuids.clone(),
-            processed_tx_uuids: user_data.processed_tx_uuids.clone(),
-            processed_withdrawal_uuids: user_data.processed_withdrawal_uuids.clone(),
+            user_data,
+        }
+    }
+
+    #[deprecated(note = "use JsUserData::new, and to_full_object for the eager conversion")]
+    pub fn from_user_data(user_data: &UserData) -> Self {
+        Self::new(user_data.clone())
+    }
+}
+
+#[wasm_bindgen]
+impl JsUserData {
+    /// The number of token balances of the user
+    pub fn balance_count(&self) -> usize {
+        self.balances.len()
+    }
+
+    /// The nullifiers of the user's private state, as hex strings
+    pub fn get_nullifiers(&self) -> Vec<String> {
+        self.user_data
+            .full_private_state
+            .nullifier_tree
+            .nullifiers()
+            .iter()
+            .map(|n| n.to_hex())
+            .collect()
+    }
+
+    /// Uuids of processed data of the given kind: "deposit", "transfer", "tx" or "withdrawal"
+    pub fn get_processed_uuids(&self, kind: &str) -> Result<Vec<String>, JsError> {
+        let data_type = DataType::from_str(kind).map_err(|e| JsError::new(&e))?;
+        let uuids = match data_type {
+            DataType::Deposit => &self.user_data.processed_deposit_uuids,
+            DataType::Transfer => &self.user_data.processed_transfer_uuids,
+            DataType::Tx => &self.user_data.processed_tx_uuids,
+            DataType::Withdrawal => &self.user_data.processed_withdrawal_uuids,
+        };
+        Ok(uuids.clone())
+    }
+
+    /// Convert into an object with all collections materialized
+    pub fn to_full_object(&self) -> JsFullUserData {
+        JsFullUserData {
+            pubkey: self.pubkey.clone(),
+            block_number: self.block_number,
+            balances: self.balances.clone(),
+            private_commitment: self.private_commitment.clone(),
+            nonce: self.nonce,
+            last_proof_block_number: self.last_proof_block_number,
+            deposit_lpt: self.deposit_lpt,
+            transfer_lpt: self.transfer_lpt,
+            tx_lpt: self.tx_lpt,
+            withdrawal_lpt: self.withdrawal_lpt,
+            processed_deposit_uuids: self.user_data.processed_deposit_uuids.clone(),
+            processed_transfer_uuids: self.user_data.processed_transfer_uuids.clone(),
+            processed_tx_uuids: self.user_data.processed_tx_uuids.clone(),
+            processed_withdrawal_uuids: self.user_data.processed_withdrawal_uuids.clone(),
+        }
+    }
+}
+
+/// Action left for a full sync by `sync_token`
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsSkippedAction {
+    pub kind: String, // "deposit", "transfer" or "tx"
+    pub uuid: String,
+    pub block_number: u32,
+    pub token_index: Option<u32>,
+}
+
+impl JsSkippedAction {
+    pub fn from_summary(summary: &ActionSummary) -> Self {
+        let kind = match summary.kind {
+            ActionKind::Deposit => "deposit",
+            ActionKind::Transfer => "transfer",
+            ActionKind::Tx => "tx",
+        };
+        Self {
+            kind: kind.to_string(),
+            uuid: summary.uuid.clone(),
+            block_number: summary.block_number,
+            token_index: summary.token_index,
         }
     }
 }
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use intmax2_client_sdk::client::limits::ProtocolLimits;
+use intmax2_zkp::ethereum_types::u256::U256;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Minimum amount of a token, as a decimal string in its smallest unit
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsTokenLimit {
+    pub token_index: u32,
+    pub amount: String,
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsProtocolLimits {
+    pub min_withdrawal_per_token: Vec<JsTokenLimit>,
+    pub min_deposit_per_token: Vec<JsTokenLimit>,
+    pub max_transfers_per_tx: u32,
+    pub max_withdrawals_per_request: u32,
+}
+
+fn to_js_limits(mins: &BTreeMap<u32, U256>) -> Vec<JsTokenLimit> {
+    mins.iter()
+        .map(|(token_index, amount)| JsTokenLimit {
+            token_index: *token_index,
+            amount: amount.to_string(),
+        })
+        .collect()
+}
+
+impl JsProtocolLimits {
+    pub fn from_limits(limits: &ProtocolLimits) -> Self {
+        Self {
+            min_withdrawal_per_token: to_js_limits(&limits.min_withdrawal_per_token),
+            min_deposit_per_token: to_js_limits(&limits.min_deposit_per_token),
+            max_transfers_per_tx: limits.max_transfers_per_tx as u32,
+            max_withdrawals_per_request: limits.max_withdrawals_per_request,
+        }
+    }
+}
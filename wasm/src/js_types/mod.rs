@@ -1,4 +1,16 @@
+pub mod backup;
+pub mod balance_proof;
+pub mod breaker;
 pub mod common;
+pub mod compatibility;
+pub mod consolidation;
 pub mod data;
+pub mod limits;
+pub mod observer;
+pub mod receipt;
+pub mod rotation;
+pub mod template;
+pub mod transfer;
 pub mod utils;
+pub mod withdrawal;
 pub mod wrapper;
@@ -0,0 +1,91 @@
+use intmax2_client_sdk::client::observer::{BlockInclusion, ObservedDeposit, RegistrationStatus};
+use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsObservedDeposit {
+    pub deposit_id: u64,
+    pub sender: String,
+    pub pubkey_salt_hash: String,
+    pub token_index: u32,
+    /// in the smallest unit of the token, as a decimal string
+    pub amount: String,
+    pub deposited_at: u64,
+}
+
+impl JsObservedDeposit {
+    fn from_deposit(deposit: &ObservedDeposit) -> Self {
+        Self {
+            deposit_id: deposit.deposit_id,
+            sender: deposit.sender.to_hex(),
+            pubkey_salt_hash: deposit.pubkey_salt_hash.to_hex(),
+            token_index: deposit.token_index,
+            amount: deposit.amount.to_string(),
+            deposited_at: deposit.deposited_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsBlockInclusion {
+    pub block_number: u32,
+    pub tx_tree_root: String,
+    pub did_return_sig: bool,
+    pub is_valid_block: bool,
+    /// true if the tx of the account was executed in the block
+    pub is_executed: bool,
+}
+
+impl JsBlockInclusion {
+    fn from_inclusion(inclusion: &BlockInclusion) -> Self {
+        Self {
+            block_number: inclusion.block_number,
+            tx_tree_root: inclusion.tx_tree_root.to_hex(),
+            did_return_sig: inclusion.did_return_sig,
+            is_valid_block: inclusion.is_valid_block,
+            is_executed: inclusion.is_executed(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsObservation {
+    /// Id in the account tree, none until the first tx of the account is executed
+    pub account_id: Option<u64>,
+    pub registration_block_number: u32,
+    pub deposits: Vec<JsObservedDeposit>,
+    pub inclusions: Vec<JsBlockInclusion>,
+    /// `from_block` of the next call to only get the blocks posted since
+    pub next_block_number: u32,
+    /// Whether the blocks were scanned up to the latest one. If not, call again
+    /// from `next_block_number` for the rest.
+    pub caught_up: bool,
+}
+
+impl JsObservation {
+    pub fn new(
+        registration: &RegistrationStatus,
+        deposits: &[ObservedDeposit],
+        inclusions: &[BlockInclusion],
+        next_block_number: u32,
+        caught_up: bool,
+    ) -> Self {
+        Self {
+            account_id: registration.account_id,
+            registration_block_number: registration.block_number,
+            deposits: deposits
+                .iter()
+                .map(JsObservedDeposit::from_deposit)
+                .collect(),
+            inclusions: inclusions
+                .iter()
+                .map(JsBlockInclusion::from_inclusion)
+                .collect(),
+            next_block_number,
+            caught_up,
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use intmax2_client_sdk::client::receipt::ReceiveReceipt;
+use wasm_bindgen::{prelude::wasm_bindgen, JsError};
+
+use super::common::JsTransfer;
+
+/// Proof that a transfer was received, to hand to a third party as JSON
+#[derive(Debug, Clone)]
+#[wasm_bindgen]
+pub struct JsReceiveReceipt {
+    data: String,
+}
+
+impl JsReceiveReceipt {
+    pub fn from_receipt(receipt: &ReceiveReceipt) -> Self {
+        Self {
+            data: serde_json::to_string(receipt).unwrap(),
+        }
+    }
+
+    pub fn to_receipt(&self) -> Result<ReceiveReceipt, JsError> {
+        serde_json::from_str(&self.data)
+            .map_err(|e| JsError::new(&format!("failed to parse receipt {}", e)))
+    }
+}
+
+#[wasm_bindgen]
+impl JsReceiveReceipt {
+    pub fn to_json(&self) -> String {
+        self.data.clone()
+    }
+
+    pub fn from_json(json: &str) -> Result<JsReceiveReceipt, JsError> {
+        let receipt = Self {
+            data: json.to_string(),
+        };
+        receipt.to_receipt()?;
+        Ok(receipt)
+    }
+
+    pub fn transfer(&self) -> Result<JsTransfer, JsError> {
+        Ok(JsTransfer::from_transfer(&self.to_receipt()?.transfer))
+    }
+
+    pub fn block_number(&self) -> Result<u32, JsError> {
+        Ok(self.to_receipt()?.block_number)
+    }
+}
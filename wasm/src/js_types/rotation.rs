@@ -0,0 +1,142 @@
+use intmax2_client_sdk::client::rotation::{
+    LeftBehind, LeftBehindReason, RotationPlan, RotationReport,
+};
+use wasm_bindgen::{prelude::wasm_bindgen, JsError};
+
+use super::{
+    common::JsTransfer,
+    data::{token_amounts, JsTokenAmount},
+};
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsLeftBehind {
+    pub reason: String, // "dust", "insufficient" or "pending_withdrawal"
+    pub token_index: u32,
+    pub amount: String, // 10 base string
+}
+
+impl JsLeftBehind {
+    pub fn from_left_behind(left: &LeftBehind) -> Self {
+        let reason = match left.reason {
+            LeftBehindReason::Dust => "dust",
+            LeftBehindReason::Insufficient => "insufficient",
+            LeftBehindReason::PendingWithdrawal => "pending_withdrawal",
+        };
+        Self {
+            reason: reason.to_string(),
+            token_index: left.token_index,
+            amount: left.amount.to_string(),
+        }
+    }
+}
+
+/// Passed back to `send_key_rotation_chunk` and `complete_key_rotation`
+#[derive(Debug, Clone)]
+#[wasm_bindgen]
+pub struct JsRotationPlan {
+    data: String,
+}
+
+impl JsRotationPlan {
+    pub fn from_rotation_plan(plan: &RotationPlan) -> Self {
+        Self {
+            data: serde_json::to_string(plan).unwrap(),
+        }
+    }
+
+    pub fn to_rotation_plan(&self) -> Result<RotationPlan, JsError> {
+        serde_json::from_str(&self.data)
+            .map_err(|e| JsError::new(&format!("failed to parse rotation plan {}", e)))
+    }
+}
+
+#[wasm_bindgen]
+impl JsRotationPlan {
+    /// The plan as JSON, to keep until the rotation completes
+    pub fn to_json(&self) -> String {
+        self.data.clone()
+    }
+
+    pub fn from_json(json: &str) -> Result<JsRotationPlan, JsError> {
+        let plan = Self {
+            data: json.to_string(),
+        };
+        plan.to_rotation_plan()?;
+        Ok(plan)
+    }
+
+    pub fn num_chunks(&self) -> Result<usize, JsError> {
+        Ok(self.to_rotation_plan()?.chunks.len())
+    }
+
+    pub fn chunk(&self, index: usize) -> Result<Vec<JsTransfer>, JsError> {
+        let plan = self.to_rotation_plan()?;
+        let chunk = plan
+            .chunks
+            .get(index)
+            .ok_or_else(|| JsError::new(&format!("no chunk {}", index)))?;
+        Ok(chunk.iter().map(JsTransfer::from_transfer).collect())
+    }
+
+    /// Total amount per token the chunks send
+    pub fn amounts(&self) -> Result<Vec<JsTokenAmount>, JsError> {
+        Ok(token_amounts(&self.to_rotation_plan()?.amounts()))
+    }
+
+    pub fn left_behind(&self) -> Result<Vec<JsLeftBehind>, JsError> {
+        let plan = self.to_rotation_plan()?;
+        Ok(plan
+            .left_behind
+            .iter()
+            .map(JsLeftBehind::from_left_behind)
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsMovedToken {
+    pub token_index: u32,
+    pub amount: String,       // 10 base string
+    pub tx_tree_root: String, // hex string
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsRotationReport {
+    pub old_pubkey: String,
+    pub new_pubkey: String,
+    pub moved: Vec<JsMovedToken>,
+    pub left_behind: Vec<JsLeftBehind>,
+    pub new_balances: Vec<JsTokenAmount>,
+    pub templates: Option<Vec<u8>>, // encrypted for the new key
+}
+
+impl JsRotationReport {
+    pub fn from_rotation_report(report: &RotationReport) -> Self {
+        Self {
+            old_pubkey: report.old_pubkey.to_string(),
+            new_pubkey: report.new_pubkey.to_string(),
+            moved: report
+                .moved
+                .iter()
+                .map(|moved| JsMovedToken {
+                    token_index: moved.token_index,
+                    amount: moved.amount.to_string(),
+                    tx_tree_root: moved.tx_tree_root.to_string(),
+                })
+                .collect(),
+            left_behind: report
+                .left_behind
+                .iter()
+                .map(JsLeftBehind::from_left_behind)
+                .collect(),
+            new_balances: token_amounts(&report.new_balances),
+            templates: report
+                .templates
+                .as_ref()
+                .map(|book| book.encrypt(report.new_pubkey)),
+        }
+    }
+}
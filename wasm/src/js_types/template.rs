@@ -0,0 +1,81 @@
+use intmax2_client_sdk::client::template::{TemplateTransfer, TxTemplate};
+use wasm_bindgen::{prelude::wasm_bindgen, JsError};
+
+use super::utils::parse_u256;
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsTemplateTransfer {
+    pub recipient: String, // hex pubkey or ethereum address
+    pub token_index: u32,
+    pub amount: String, // 10 base string
+}
+
+#[wasm_bindgen]
+impl JsTemplateTransfer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(recipient: String, token_index: u32, amount: String) -> Self {
+        Self {
+            recipient,
+            token_index,
+            amount,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsTxTemplate {
+    pub name: String,
+    pub transfers: Vec<JsTemplateTransfer>,
+    pub memo: Option<String>,
+}
+
+#[wasm_bindgen]
+impl JsTxTemplate {
+    #[wasm_bindgen(constructor)]
+    pub fn new(name: String, transfers: Vec<JsTemplateTransfer>, memo: Option<String>) -> Self {
+        Self {
+            name,
+            transfers,
+            memo,
+        }
+    }
+}
+
+impl JsTxTemplate {
+    pub fn from_template(template: &TxTemplate) -> Self {
+        Self {
+            name: template.name.clone(),
+            transfers: template
+                .transfers
+                .iter()
+                .map(|transfer| JsTemplateTransfer {
+                    recipient: transfer.recipient.clone(),
+                    token_index: transfer.token_index,
+                    amount: transfer.amount.to_string(),
+                })
+                .collect(),
+            memo: template.memo.clone(),
+        }
+    }
+
+    pub fn to_template(&self) -> Result<TxTemplate, JsError> {
+        let transfers = self
+            .transfers
+            .iter()
+            .map(|transfer| {
+                Ok(TemplateTransfer {
+                    recipient: transfer.recipient.clone(),
+                    token_index: transfer.token_index,
+                    amount: parse_u256(&transfer.amount)?,
+                })
+            })
+            .collect::<Result<Vec<_>, JsError>>()?;
+        Ok(TxTemplate {
+            name: self.name.clone(),
+            transfers,
+            memo: self.memo.clone(),
+        })
+    }
+}
@@ -0,0 +1,122 @@
+use intmax2_client_sdk::{client::error::ClientError, external_api::utils::clock::global_clock};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use super::common::JsTransfer;
+
+/// Options of `transfer`
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsTransferOptions {
+    /// Seconds to wait for the block proposal, and then for the inclusion of the block.
+    /// Defaults to the `tx_timeout` of the config.
+    pub timeout: Option<u64>,
+    /// Seconds between polls of the block builder and the validity prover
+    pub poll_interval: u64,
+    /// Sync the balance proof before sending the tx request. Without it, the tx is
+    /// built on the balance proof as it is, e.g. right after a `sync`.
+    pub auto_sync: bool,
+    /// Block number after which the recipients refuse the transfers
+    pub claim_deadline_block: Option<u32>,
+    /// Send even if the block builder looks unhealthy
+    pub skip_health_check: bool,
+    /// Sponsor service asked to pay the block builder fee. The fee is paid by the
+    /// sender if the sponsor declines.
+    pub sponsor_url: Option<String>,
+    /// Tag of the withdrawals of the tx, e.g. an exchange memo code. Only its hash
+    /// is given to the withdrawal server.
+    pub attribution_tag: Option<String>,
+    /// Transfer paying the block builder fee, appended to the transfers. The
+    /// builder's attestation of the proposal is backed up with the tx, to dispute
+    /// the fee if the block is never posted.
+    pub fee_transfer: Option<JsTransfer>,
+}
+
+#[wasm_bindgen]
+impl JsTransferOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for JsTransferOptions {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            poll_interval: 5,
+            auto_sync: true,
+            claim_deadline_block: None,
+            skip_health_check: false,
+            sponsor_url: None,
+            attribution_tag: None,
+            fee_transfer: None,
+        }
+    }
+}
+
+/// Progress event passed to the callback of `transfer`
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsTransferEvent {
+    /// "requested", "proposal_received", "backup_saved", "signed" or "included"
+    pub kind: String,
+    pub tx_tree_root: Option<String>, // set from "proposal_received" on
+    pub block_number: Option<u32>,    // set for "included"
+}
+
+/// Result of a completed `transfer`
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsTransferReceipt {
+    pub tx_tree_root: String,
+    pub block_number: u32,
+    pub is_registration_block: bool,
+}
+
+/// Error thrown by `transfer`
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsTransferError {
+    /// "sync", "request", "proposal", "backup", "sign" or "inclusion"
+    pub stage: String,
+    /// Whether the whole `transfer` can be called again right away. Once the
+    /// backup is saved, the tx has to be resolved by a sync before sending again.
+    pub retryable: bool,
+    pub message: String,
+    /// Set when the local clock is far from the servers' clocks, which can make
+    /// proposals and txs look expired
+    pub clock_skew_warning: Option<String>,
+}
+
+impl JsTransferError {
+    pub fn new(stage: &str, error: ClientError) -> Self {
+        let retryable = match stage {
+            "sync" | "proposal" => true,
+            // the request itself is rejected before anything is sent for these
+            "request" => !matches!(
+                error,
+                ClientError::InvalidNonce(_, _)
+                    | ClientError::InvalidClaimDeadline(_, _)
+                    | ClientError::InternalError(_)
+            ),
+            // the proposal is verified before anything is saved
+            "backup" => matches!(error, ClientError::InvalidBlockProposal(_)),
+            _ => false,
+        };
+        Self {
+            stage: stage.to_string(),
+            retryable,
+            message: error.to_string(),
+            clock_skew_warning: skew_warning(),
+        }
+    }
+}
+
+fn skew_warning() -> Option<String> {
+    let warnings = global_clock().skew_warnings();
+    if warnings.is_empty() {
+        None
+    } else {
+        Some(warnings.join("; "))
+    }
+}
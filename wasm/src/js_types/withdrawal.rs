@@ -0,0 +1,56 @@
+use intmax2_client_sdk::client::withdrawal_onchain::OnchainConfirmation;
+use intmax2_interfaces::api::withdrawal_server::interface::WithdrawalInfo;
+use intmax2_zkp::ethereum_types::u32limb_trait::U32LimbTrait as _;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// A withdrawal as reported by the aggregator, with its check against L1
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct JsWithdrawalInfo {
+    pub status: String,    // as reported by the aggregator
+    pub recipient: String, // hex string
+    pub token_index: u32,
+    pub amount: String,    // 10 base string
+    pub nullifier: String, // hex string
+    // "not_submitted", "pending", "confirmed" or "suspected_aggregator_fault"
+    pub onchain_status: String,
+    pub l1_tx_hash: Option<String>,
+    pub l1_block_number: Option<u64>,
+    pub fault_reason: Option<String>,
+}
+
+impl JsWithdrawalInfo {
+    pub fn from_withdrawal_info(info: &WithdrawalInfo, confirmation: &OnchainConfirmation) -> Self {
+        let withdrawal = &info.contract_withdrawal;
+        let (onchain_status, l1_tx_hash, l1_block_number, fault_reason) = match confirmation {
+            OnchainConfirmation::NotSubmitted => ("not_submitted", None, None, None),
+            OnchainConfirmation::Pending => ("pending", None, None, None),
+            OnchainConfirmation::Confirmed {
+                tx_hash,
+                block_number,
+            } => (
+                "confirmed",
+                Some(format!("{:?}", tx_hash)),
+                Some(*block_number),
+                None,
+            ),
+            OnchainConfirmation::SuspectedAggregatorFault { reason } => (
+                "suspected_aggregator_fault",
+                None,
+                None,
+                Some(reason.clone()),
+            ),
+        };
+        Self {
+            status: info.status.to_string(),
+            recipient: withdrawal.recipient.to_hex(),
+            token_index: withdrawal.token_index,
+            amount: withdrawal.amount.to_string(),
+            nullifier: withdrawal.nullifier.to_hex(),
+            onchain_status: onchain_status.to_string(),
+            l1_tx_hash,
+            l1_block_number,
+            fault_reason,
+        }
+    }
+}
@@ -1,30 +1,82 @@
 use crate::js_types::common::JsTx;
 use client::{get_client, Config};
-use intmax2_client_sdk::client::account::generate_intmax_account_from_eth_key as inner_generate_intmax_account_from_eth_key;
-use intmax2_interfaces::data::{
-    deposit_data::{DepositData, TokenType},
-    transfer_data::TransferData,
-    tx_data::TxData,
+use gloo_timers::future::TimeoutFuture;
+use intmax2_client_sdk::{
+    client::{
+        account::{
+            generate_intmax_account_from_eth_key as inner_generate_intmax_account_from_eth_key,
+            generate_intmax_account_from_mnemonic as inner_generate_intmax_account_from_mnemonic,
+        },
+        balance_history::SeriesInterval,
+        client::{FeeMode, SyncProgressCallback, SyncStage, TxSendOptions},
+        compatibility::{check_compatibility as inner_check_compatibility, ServiceUrls},
+        consolidation::ConsolidationOptions,
+        diagnostics::fetch_last_proof_block_number,
+        error::ClientError,
+        external_deposit::DepositSource,
+        observer::{Observer, ObserverConfig},
+        receipt::{verify_receive_receipt as inner_verify_receive_receipt, RollupReference},
+        rotation::RotationOptions,
+        strategy::decrypt::decrypt_blobs,
+        template::{TemplateBook, TemplateOverrides},
+    },
+    external_api::utils::{clock::global_clock, retry::global_breakers},
+    utils::{
+        circuit_verifiers::{check_encoded_balance_proof, BalanceProofCheck, BalanceProofStatus},
+        id::{format_id as inner_format_id, format_plain_id},
+    },
+};
+use intmax2_interfaces::{
+    api::validity_prover::interface::ValidityProverClientInterface as _,
+    data::{
+        deposit_data::{DepositData, TokenType},
+        encryption::ChunkedDecryption,
+        transfer_data::TransferData,
+        tx_data::{DecryptPhase, TxData},
+    },
 };
 use intmax2_zkp::{
-    common::transfer::Transfer,
+    common::{signature::key_set::KeySet, transfer::Transfer},
     constants::NUM_TRANSFERS_IN_TX,
     ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait},
 };
 use js_types::{
+    backup::JsBackupHealth,
+    balance_proof::JsBalanceProofCheck,
+    breaker::JsBreakerStatus,
     common::JsTransfer,
-    data::{JsDepositData, JsTransferData, JsTxData, JsUserData},
-    utils::{parse_address, parse_u256},
+    compatibility::JsCompatibilityReport,
+    consolidation::JsConsolidationReport,
+    data::{
+        JsBalanceSeries, JsBalanceView, JsDecryptProgress, JsDecryptedData, JsDepositData,
+        JsDepositRequest, JsHistoricalBalance, JsOptimisticBalances, JsSkippedAction,
+        JsSyncProgress, JsTransferData, JsTxData, JsUserData,
+    },
+    limits::JsProtocolLimits,
+    observer::JsObservation,
+    receipt::JsReceiveReceipt,
+    rotation::{JsRotationPlan, JsRotationReport},
+    template::JsTxTemplate,
+    transfer::{JsTransferError, JsTransferEvent, JsTransferOptions, JsTransferReceipt},
+    utils::{parse_address, parse_salt, parse_u256},
+    withdrawal::JsWithdrawalInfo,
     wrapper::{JsBlockProposal, JsTxRequestMemo},
 };
 use num_bigint::BigUint;
-use utils::{parse_h256, parse_h256_as_u256, str_privkey_to_keyset};
-use wasm_bindgen::{prelude::wasm_bindgen, JsError};
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+use session::LocalStorageMarker;
+use utils::{parse_h256, parse_h256_as_u256, parse_id_str, str_privkey_to_keyset};
+use wasm_bindgen::{prelude::wasm_bindgen, JsError, JsValue};
 
 pub mod client;
 pub mod js_types;
+pub mod session;
 pub mod utils;
 
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
 #[derive(Debug, Clone)]
 #[wasm_bindgen(getter_with_clone)]
 pub struct IntmaxAccount {
@@ -46,6 +98,23 @@ pub async fn generate_intmax_account_from_eth_key(
     })
 }
 
+/// Generate the key pair of the Ethereum account `account_index` of a BIP-39
+/// mnemonic, the same as `generate_intmax_account_from_eth_key` gives for the
+/// private key a wallet derives from the phrase. Fails on an invalid word or checksum.
+#[wasm_bindgen]
+pub async fn generate_intmax_account_from_mnemonic(
+    mnemonic: &str,
+    account_index: u32,
+) -> Result<IntmaxAccount, JsError> {
+    let key_set = inner_generate_intmax_account_from_mnemonic(mnemonic, account_index)
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    let private_key: U256 = BigUint::from(key_set.privkey).try_into().unwrap();
+    Ok(IntmaxAccount {
+        privkey: private_key.to_hex(),
+        pubkey: key_set.pubkey.to_hex(),
+    })
+}
+
 /// Function to take a backup before calling the deposit function of the liquidity contract.
 /// You can also get the pubkey_salt_hash from the return value.
 #[wasm_bindgen]
@@ -75,13 +144,163 @@ pub async fn prepare_deposit(
     Ok(deposit_data.pubkey_salt_hash.to_string())
 }
 
+/// Same as `prepare_deposit` for the token registered as `token_index` in the
+/// liquidity contract, native or not. The type, address and id of the token are
+/// read from the contract and backed up with the index. Fails if no token is
+/// registered as `token_index`, instead of backing up a native deposit.
+#[wasm_bindgen]
+pub async fn prepare_deposit_by_token_index(
+    config: &Config,
+    recipient: &str,
+    amount: &str,
+    token_index: u32,
+) -> Result<String, JsError> {
+    let recipient = parse_h256_as_u256(recipient)?;
+    let amount = parse_u256(amount)?;
+    let client = get_client(config);
+    let deposit_data = client
+        .prepare_deposit_by_token_index(recipient, amount, token_index)
+        .await
+        .map_err(|e| JsError::new(&format!("failed to prepare deposit call: {}", e)))?;
+    Ok(deposit_data.pubkey_salt_hash.to_string())
+}
+
+/// L1 fee in wei, as a decimal string for `BigInt`, of depositing `amount` of
+/// `token_index` from the Ethereum account of `private_key`: the gas the node
+/// estimates for the call of the liquidity contract, at the max fee per gas of the
+/// gas strategy. Nothing is sent or backed up. Fails if the deposit would revert,
+/// e.g. because the account lacks the balance or the allowance.
+#[wasm_bindgen]
+pub async fn estimate_deposit_fee(
+    config: &Config,
+    private_key: &str,
+    amount: &str,
+    token_index: u32,
+) -> Result<String, JsError> {
+    let eth_private_key = parse_h256(private_key)?;
+    let amount = parse_u256(amount)?;
+    let client = get_client(config);
+    let fee = client
+        .liquidity_contract
+        .estimate_deposit_fee(eth_private_key, token_index, amount)
+        .await
+        .map_err(|e| {
+            JsError::new(&format!(
+                "failed to estimate the deposit of token {}: {}",
+                token_index, e
+            ))
+        })?;
+    Ok(fee.to_string())
+}
+
+/// Hash for someone else to deposit to, e.g. from their L1 wallet. Only the hash
+/// is shared, the salt is kept to claim the deposit with `claim_external_deposit`.
+#[wasm_bindgen]
+pub fn generate_deposit_request(
+    config: &Config,
+    private_key: &str,
+) -> Result<JsDepositRequest, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let request = client.generate_deposit_request(key);
+    Ok(JsDepositRequest::from_deposit_request(&request))
+}
+
+/// Import a deposit made by someone else to the hash of `generate_deposit_request`
+/// with `salt`. `deposit` is the L1 tx hash of the deposit or its deposit id. The
+/// deposit is included by the next sync.
+#[wasm_bindgen]
+pub async fn claim_external_deposit(
+    config: &Config,
+    private_key: &str,
+    salt: &str,
+    deposit: &str,
+) -> Result<JsDepositData, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let salt = parse_salt(salt)?;
+    let source = deposit
+        .parse::<DepositSource>()
+        .map_err(|e| JsError::new(&e))?;
+    let client = get_client(config);
+    let deposit_data = client.claim_external_deposit(key, salt, source).await?;
+    Ok(JsDepositData::from_deposit_data(&deposit_data))
+}
+
+/// Get the user's deposits not incorporated by a sync yet. Those not in a block of
+/// the validity prover yet, e.g. in a block that is proposed but not finalized,
+/// have `pending` set.
+#[wasm_bindgen]
+pub async fn get_pending_deposits(
+    config: &Config,
+    private_key: &str,
+) -> Result<Vec<JsDepositData>, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let deposits = client.get_pending_deposits(key).await?;
+    Ok(deposits
+        .iter()
+        .map(JsDepositData::from_pending_deposit)
+        .collect())
+}
+
 /// Function to send a tx request to the block builder. The return value contains information to take a backup.
+/// If `claim_deadline_block` is given, the recipients refuse the transfers after that block.
+/// If `attribution_tag` is given, e.g. an exchange memo code, it is kept with the
+/// withdrawals of the tx, and the withdrawal server only gets its hash.
 #[wasm_bindgen]
 pub async fn send_tx_request(
     config: &Config,
     block_builder_url: &str,
     private_key: &str,
     transfers: Vec<JsTransfer>,
+    claim_deadline_block: Option<u32>,
+    attribution_tag: Option<String>,
+) -> Result<JsTxRequestMemo, JsError> {
+    send_tx_request_paid_by(
+        config,
+        block_builder_url,
+        private_key,
+        transfers,
+        claim_deadline_block,
+        attribution_tag,
+        FeeMode::SelfPaid,
+    )
+    .await
+}
+
+/// `send_tx_request`, asking the sponsor service at `sponsor_url` to pay the block
+/// builder fee. If the sponsor declines or fails, or the block builder does not
+/// accept its voucher, the tx is sent with the fee paid by the sender.
+#[wasm_bindgen]
+pub async fn send_sponsored_tx_request(
+    config: &Config,
+    block_builder_url: &str,
+    private_key: &str,
+    transfers: Vec<JsTransfer>,
+    sponsor_url: String,
+    claim_deadline_block: Option<u32>,
+    attribution_tag: Option<String>,
+) -> Result<JsTxRequestMemo, JsError> {
+    send_tx_request_paid_by(
+        config,
+        block_builder_url,
+        private_key,
+        transfers,
+        claim_deadline_block,
+        attribution_tag,
+        FeeMode::Sponsored { sponsor_url },
+    )
+    .await
+}
+
+async fn send_tx_request_paid_by(
+    config: &Config,
+    block_builder_url: &str,
+    private_key: &str,
+    transfers: Vec<JsTransfer>,
+    claim_deadline_block: Option<u32>,
+    attribution_tag: Option<String>,
+    fee_mode: FeeMode,
 ) -> Result<JsTxRequestMemo, JsError> {
     if transfers.len() > NUM_TRANSFERS_IN_TX {
         return Err(JsError::new(&format!(
@@ -96,17 +315,31 @@ pub async fn send_tx_request(
         .collect::<Result<Vec<_>, JsError>>()?;
 
     let client = get_client(config);
+    let options = TxSendOptions {
+        claim_deadline_block,
+        fee_mode,
+        attribution_tag,
+        ..Default::default()
+    };
     let memo = client
-        .send_tx_request(block_builder_url, key, transfers)
+        .send_tx_request_with_options(block_builder_url, key, transfers, options)
         .await
         .map_err(|e| JsError::new(&format!("failed to send tx request {}", e)))?;
 
     Ok(JsTxRequestMemo::from_tx_request_memo(&memo))
 }
 
+fn to_fee_mode(sponsor_url: Option<String>) -> FeeMode {
+    match sponsor_url {
+        Some(sponsor_url) => FeeMode::Sponsored { sponsor_url },
+        None => FeeMode::SelfPaid,
+    }
+}
+
 /// Function to query the block proposal from the block builder.
 /// The return value is the block proposal or null if the proposal is not found.
-/// If got an invalid proposal, it will return an error.
+/// A proposal that is not for `tx` of the caller is treated as not found, or throws
+/// if `strict_proposal_validation` is set in the config.
 #[wasm_bindgen]
 pub async fn query_proposal(
     config: &Config,
@@ -149,15 +382,193 @@ pub async fn finalize_tx(
     Ok(tx_tree_root.to_string())
 }
 
+/// Ask the block builder to drop the tx request of `tx_request_memo`, when the tx is
+/// not going to be finalized. The tx is only backed up to the store vault by
+/// `finalize_tx`, so nothing is left there. Does nothing if the block builder no
+/// longer has the request, and throws if the tx is already in a block proposal.
+/// The cancellation is signed with `private_key`, the key of the sender.
+#[wasm_bindgen]
+pub async fn cancel_pending_tx(
+    config: &Config,
+    private_key: &str,
+    tx_request_memo: &JsTxRequestMemo,
+) -> Result<(), JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let tx_request_memo = tx_request_memo.to_tx_request_memo()?;
+    let client = get_client(config);
+    client.cancel_tx_request(key, &tx_request_memo).await?;
+    Ok(())
+}
+
+/// Run the whole send flow: send the tx request, wait for the block proposal, save the
+/// backup, sign the proposal, and wait until the block is included.
+/// `on_event` is called with a `JsTransferEvent` after each of these steps, in the order
+/// "requested", "proposal_received", "backup_saved", "signed", "included".
+/// On failure, a `JsTransferError` naming the stage is thrown.
+#[wasm_bindgen]
+pub async fn transfer(
+    config: &Config,
+    block_builder_url: &str,
+    private_key: &str,
+    transfers: Vec<JsTransfer>,
+    options: &JsTransferOptions,
+    on_event: js_sys::Function,
+) -> Result<JsTransferReceipt, JsValue> {
+    if transfers.len() > NUM_TRANSFERS_IN_TX {
+        return Err(JsError::new(&format!(
+            "Number of transfers in a tx must be less than or equal to {}",
+            NUM_TRANSFERS_IN_TX
+        ))
+        .into());
+    }
+    let key = str_privkey_to_keyset(private_key)?;
+    let transfers: Vec<Transfer> = transfers
+        .iter()
+        .map(|transfer| transfer.to_transfer())
+        .collect::<Result<Vec<_>, JsError>>()?;
+    let timeout = options.timeout.unwrap_or(config.tx_timeout);
+    let emit = |kind: &str, tx_tree_root: Option<String>, block_number: Option<u32>| {
+        let event = JsTransferEvent {
+            kind: kind.to_string(),
+            tx_tree_root,
+            block_number,
+        };
+        on_event
+            .call1(&JsValue::NULL, &JsValue::from(event))
+            .map(|_| ())
+    };
+    let fail = |stage: &str, e: ClientError| JsValue::from(JsTransferError::new(stage, e));
+
+    let client = get_client(config);
+    // synced here rather than by the request, so that a failed sync is its own stage
+    if options.auto_sync {
+        client.sync(key).await.map_err(|e| fail("sync", e))?;
+    }
+
+    let send_options = TxSendOptions {
+        claim_deadline_block: options.claim_deadline_block,
+        skip_health_check: options.skip_health_check,
+        skip_sync: true,
+        fee_mode: to_fee_mode(options.sponsor_url.clone()),
+        attribution_tag: options.attribution_tag.clone(),
+        fee_transfer: options
+            .fee_transfer
+            .as_ref()
+            .map(|transfer| transfer.to_transfer())
+            .transpose()?,
+        ..Default::default()
+    };
+    let memo = client
+        .send_tx_request_with_options(block_builder_url, key, transfers, send_options)
+        .await
+        .map_err(|e| fail("request", e))?;
+    emit("requested", None, None)?;
+
+    let proposal = client
+        .wait_for_proposal(
+            block_builder_url,
+            key,
+            &memo,
+            timeout,
+            options.poll_interval,
+        )
+        .await
+        .map_err(|e| fail("proposal", e))?;
+    let tx_tree_root = proposal.tx_tree_root.to_string();
+    emit("proposal_received", Some(tx_tree_root.clone()), None)?;
+
+    client
+        .backup_tx(key, &memo, &proposal)
+        .await
+        .map_err(|e| fail("backup", e))?;
+    emit("backup_saved", Some(tx_tree_root.clone()), None)?;
+
+    client
+        .sign_proposal(block_builder_url, key, &memo, &proposal)
+        .await
+        .map_err(|e| fail("sign", e))?;
+    emit("signed", Some(tx_tree_root.clone()), None)?;
+
+    let block_number = client
+        .wait_for_inclusion(proposal.tx_tree_root, timeout, options.poll_interval)
+        .await
+        .map_err(|e| fail("inclusion", e))?;
+    emit("included", Some(tx_tree_root.clone()), Some(block_number))?;
+
+    Ok(JsTransferReceipt {
+        tx_tree_root,
+        block_number,
+        is_registration_block: memo.is_registration_block,
+    })
+}
+
 /// Synchronize the user's balance proof. It may take a long time to generate ZKP.
 #[wasm_bindgen]
 pub async fn sync(config: &Config, private_key: &str) -> Result<(), JsError> {
+    sync_reporting(config, private_key, Box::new(|_| {})).await
+}
+
+/// `sync`, calling `on_progress(current_block, total_blocks)` after each block is
+/// processed. It is called with `(0, 0)` if no block needed processing, so it is
+/// always called at least once. An exception thrown by `on_progress` is ignored.
+#[wasm_bindgen]
+pub async fn sync_with_progress(
+    config: &Config,
+    private_key: &str,
+    on_progress: js_sys::Function,
+) -> Result<(), JsError> {
+    sync_reporting(
+        config,
+        private_key,
+        positional_progress_callback(on_progress),
+    )
+    .await
+}
+
+/// `sync`, calling `on_progress` with a `JsSyncProgress` of the stage "actions"
+/// after each deposit, transfer and tx is processed, and of the stage "blocks"
+/// after each block. It is called with `0` of `0` blocks if no block needed
+/// processing, so it is always called at least once. An exception thrown by
+/// `on_progress` is ignored.
+#[wasm_bindgen]
+pub async fn sync_with_staged_progress(
+    config: &Config,
+    private_key: &str,
+    on_progress: &js_sys::Function,
+) -> Result<(), JsError> {
+    sync_reporting(config, private_key, staged_progress_callback(on_progress)).await
+}
+
+async fn sync_reporting(
+    config: &Config,
+    private_key: &str,
+    on_progress: SyncProgressCallback,
+) -> Result<(), JsError> {
     let key = str_privkey_to_keyset(private_key)?;
     let client = get_client(config);
-    client.sync(key).await?;
+    client.sync_with_progress(key, Some(on_progress)).await?;
     Ok(())
 }
 
+/// Synchronize only the deposits and incoming transfers of `token_index`. Returns
+/// the actions of other tokens that were skipped. Fails if one of them, or a sent
+/// tx, must be processed first, in which case `sync` is required.
+#[wasm_bindgen]
+pub async fn sync_token(
+    config: &Config,
+    private_key: &str,
+    token_index: u32,
+) -> Result<Vec<JsSkippedAction>, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let report = client.sync_token(key, token_index).await?;
+    Ok(report
+        .skipped_actions
+        .iter()
+        .map(JsSkippedAction::from_summary)
+        .collect())
+}
+
 /// Synchronize the user's withdrawal proof, and send request to the withdrawal aggregator.
 /// It may take a long time to generate ZKP.
 #[wasm_bindgen]
@@ -168,13 +579,595 @@ pub async fn sync_withdrawals(config: &Config, private_key: &str) -> Result<(),
     Ok(())
 }
 
+/// `sync_withdrawals`, calling `on_progress` as `sync_with_progress` does while the
+/// balance proof is synced, then after each withdrawal with the number of
+/// withdrawals processed and to process in total, or `(0, 0)` if there is none.
+#[wasm_bindgen]
+pub async fn sync_withdrawals_with_progress(
+    config: &Config,
+    private_key: &str,
+    on_progress: js_sys::Function,
+) -> Result<(), JsError> {
+    sync_withdrawals_reporting(
+        config,
+        private_key,
+        positional_progress_callback(on_progress),
+    )
+    .await
+}
+
+/// `sync_withdrawals`, calling `on_progress` as `sync_with_staged_progress` does
+/// while the balance proof is synced, then with the stage "withdrawals" after each
+/// withdrawal, or with `0` of `0` if there is none.
+#[wasm_bindgen]
+pub async fn sync_withdrawals_with_staged_progress(
+    config: &Config,
+    private_key: &str,
+    on_progress: &js_sys::Function,
+) -> Result<(), JsError> {
+    sync_withdrawals_reporting(config, private_key, staged_progress_callback(on_progress)).await
+}
+
+async fn sync_withdrawals_reporting(
+    config: &Config,
+    private_key: &str,
+    on_progress: SyncProgressCallback,
+) -> Result<(), JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    client
+        .sync_withdrawals_with_progress(key, Some(on_progress))
+        .await?;
+    Ok(())
+}
+
+// the `(current, total)` callback of `sync_with_progress`, which counts blocks and
+// withdrawals only. A failing callback must not abort a sync that may have run for
+// minutes, so its exceptions are ignored, as are those of the staged callback.
+fn positional_progress_callback(on_progress: js_sys::Function) -> SyncProgressCallback {
+    Box::new(move |progress| {
+        if progress.stage == SyncStage::Actions {
+            return;
+        }
+        let _ = on_progress.call2(
+            &JsValue::NULL,
+            &JsValue::from(progress.current),
+            &JsValue::from(progress.total),
+        );
+    })
+}
+
+fn staged_progress_callback(on_progress: &js_sys::Function) -> SyncProgressCallback {
+    let on_progress = on_progress.clone();
+    Box::new(move |progress| {
+        let progress = JsSyncProgress::from_progress(progress);
+        let _ = on_progress.call1(&JsValue::NULL, &JsValue::from(progress));
+    })
+}
+
 /// Get the user's data. It is recommended to sync before calling this function.
 #[wasm_bindgen]
 pub async fn get_user_data(config: &Config, private_key: &str) -> Result<JsUserData, JsError> {
     let key = str_privkey_to_keyset(private_key)?;
     let client = get_client(config);
     let user_data = client.get_user_data(key).await?;
-    Ok(JsUserData::from_user_data(&user_data))
+    // only informative, so the user data is returned without it if it cannot be fetched
+    let last_proof_block_number =
+        fetch_last_proof_block_number(&client.store_vault_server, &user_data)
+            .await
+            .ok()
+            .flatten();
+    let mut js_user_data = JsUserData::new(user_data);
+    js_user_data.last_proof_block_number = last_proof_block_number;
+    Ok(js_user_data)
+}
+
+/// Get the balance of `token_index` as a decimal string, "0" if the user has none.
+/// Does not sync, so it is recommended to sync before calling this function.
+#[wasm_bindgen]
+pub async fn get_token_balance(
+    config: &Config,
+    private_key: &str,
+    token_index: u32,
+) -> Result<String, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let user_data = client.get_user_data(key).await?;
+    let amount = user_data
+        .balances()
+        .get(&(token_index as u64))
+        .map(|leaf| leaf.amount)
+        .unwrap_or_default();
+    Ok(amount.to_string())
+}
+
+/// Get the user's balances with the token of each index, NFTs listed by token id
+/// per contract. It is recommended to sync before calling this function.
+#[wasm_bindgen]
+pub async fn get_balance_view(
+    config: &Config,
+    private_key: &str,
+) -> Result<JsBalanceView, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let view = client.get_balance_view(key).await?;
+    Ok(JsBalanceView::from_balance_view(&view))
+}
+
+/// Get the user's balances including the txs finalized since the last sync and the
+/// incoming transfers the next sync will receive, with the deltas behind them. The
+/// debits are only known to this page, and a tx that is not included before the tx
+/// timeout stops counting.
+#[wasm_bindgen]
+pub async fn get_balances_optimistic(
+    config: &Config,
+    private_key: &str,
+) -> Result<JsOptimisticBalances, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let balances = client.get_balances_optimistic(key).await?;
+    Ok(JsOptimisticBalances::from_optimistic_balances(&balances))
+}
+
+/// Balances as of `block_number`, replayed from the history. Entries not yet in a
+/// block are left out and counted in `excluded_entries`.
+#[wasm_bindgen]
+pub async fn get_balance_at_block(
+    config: &Config,
+    private_key: &str,
+    block_number: u32,
+) -> Result<JsHistoricalBalance, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let balance = client.get_balance_at_block(key, block_number).await?;
+    Ok(JsHistoricalBalance::from_historical_balance(&balance))
+}
+
+/// Balances at the end of each UTC period up to now. `interval` is "daily",
+/// "weekly" or "monthly".
+#[wasm_bindgen]
+pub async fn get_balance_series(
+    config: &Config,
+    private_key: &str,
+    interval: &str,
+) -> Result<JsBalanceSeries, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let interval = match interval {
+        "daily" => SeriesInterval::Daily,
+        "weekly" => SeriesInterval::Weekly,
+        "monthly" => SeriesInterval::Monthly,
+        _ => return Err(JsError::new(&format!("Invalid interval: {}", interval))),
+    };
+    let client = get_client(config);
+    let series = client.get_balance_series(key, interval).await?;
+    Ok(JsBalanceSeries::from_balance_series(&series))
+}
+
+/// Warnings for the servers whose clock is far from the local clock, as measured
+/// from the responses so far. Expiry and timeout checks are corrected for the skew.
+#[wasm_bindgen]
+pub fn get_clock_skew_warnings() -> Vec<String> {
+    global_clock().skew_warnings()
+}
+
+/// `id` (e.g. a tx tree root or nullifier) as shown to users: lowercase hex with a
+/// checksum, e.g. `0x1f..9a#k3q9`. Accepts plain or checksummed hex.
+#[wasm_bindgen]
+pub fn format_id(id: &str) -> Result<String, JsError> {
+    Ok(inner_format_id(parse_id_str(id)?))
+}
+
+/// Plain hex of `id`, failing if its checksum does not match, e.g. when it was
+/// truncated while copying
+#[wasm_bindgen]
+pub fn parse_id(id: &str) -> Result<String, JsError> {
+    Ok(format_plain_id(parse_id_str(id)?))
+}
+
+/// State of the circuit breaker of every server contacted so far. A server whose
+/// circuit is open is not sent requests until its cool-down ends.
+#[wasm_bindgen]
+pub fn get_circuit_breaker_states() -> Vec<JsBreakerStatus> {
+    global_breakers()
+        .states()
+        .iter()
+        .map(JsBreakerStatus::from_status)
+        .collect()
+}
+
+/// Check that everything needed to recover the account is in the store vault.
+/// `tx_tree_roots` are the roots of own txs known locally (e.g. from pending memos),
+/// which must be backed up once included in a block.
+#[wasm_bindgen]
+pub async fn check_backup_health(
+    config: &Config,
+    private_key: &str,
+    tx_tree_roots: Vec<String>,
+) -> Result<JsBackupHealth, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let tx_tree_roots = tx_tree_roots
+        .iter()
+        .map(|root| parse_id_str(root))
+        .collect::<Result<Vec<_>, _>>()?;
+    let client = get_client(config);
+    let health = client.backup_health(key, &tx_tree_roots).await?;
+    Ok(JsBackupHealth::from_backup_health(&health))
+}
+
+/// Compare the protocol versions of the servers of `config` with the ones of this
+/// package, e.g. at startup to show an "update required" screen instead of
+/// failing in the middle of a transfer
+#[wasm_bindgen]
+pub async fn check_compatibility(config: &Config) -> Result<JsCompatibilityReport, JsError> {
+    let urls = ServiceUrls {
+        store_vault_server: config.store_vault_server_url.clone(),
+        validity_prover: config.validity_prover_url.clone(),
+        balance_prover: config.balance_prover_url.clone(),
+        withdrawal_server: config.withdrawal_server_url.clone(),
+        block_builder: None,
+    };
+    let report = inner_check_compatibility(&urls, env!("CARGO_PKG_VERSION")).await;
+    Ok(JsCompatibilityReport::from_report(&report))
+}
+
+/// The withdrawals of the account as reported by the withdrawal aggregator. Those
+/// reported as submitted are checked against the liquidity contract on L1.
+#[wasm_bindgen]
+pub async fn get_withdrawal_info(
+    config: &Config,
+    private_key: &str,
+) -> Result<Vec<JsWithdrawalInfo>, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let withdrawal_info = client.get_withdrawal_info(key).await?;
+    let confirmations = client.verify_withdrawals_onchain(&withdrawal_info).await?;
+    Ok(withdrawal_info
+        .iter()
+        .zip(confirmations.iter())
+        .map(|(info, confirmation)| JsWithdrawalInfo::from_withdrawal_info(info, confirmation))
+        .collect())
+}
+
+/// Limits on withdrawals, deposits and txs. Withdrawals and deposits below the
+/// minimum of their token are rejected before anything is proven or sent.
+#[wasm_bindgen]
+pub async fn get_limits(config: &Config) -> Result<JsProtocolLimits, JsError> {
+    let client = get_client(config);
+    let limits = client.get_limits().await?;
+    Ok(JsProtocolLimits::from_limits(&limits))
+}
+
+/// What is public of the account of `pubkey`, for dashboards of accounts one does
+/// not hold the key of: its registration, the deposits to `pubkey_salt_hashes`
+/// (disclosed by the owner) and the blocks with its txs from `from_block`. Without
+/// `from_block`, no block is scanned and the returned `next_block_number` is the
+/// next block to be posted. At most 1000 blocks are scanned per call. Balances and
+/// transfers are encrypted to the key and cannot be observed. Pass the returned
+/// `next_block_number` as `from_block` to continue, until `caught_up`.
+#[wasm_bindgen]
+pub async fn observe_account(
+    config: &Config,
+    pubkey: &str,
+    pubkey_salt_hashes: Vec<String>,
+    from_block: Option<u32>,
+) -> Result<JsObservation, JsError> {
+    let pubkey = parse_h256_as_u256(pubkey)?;
+    let client = get_client(config);
+    let mut observer = Observer::new(
+        ObserverConfig {
+            validity_prover: client.validity_prover,
+            liquidity_contract: client.liquidity_contract,
+            from_block_number: from_block,
+        },
+        pubkey,
+    );
+    for pubkey_salt_hash in &pubkey_salt_hashes {
+        observer.register_salt_hash(parse_id_str(pubkey_salt_hash)?);
+    }
+    let registration = observer.get_registration_status().await?;
+    let deposits = observer.get_onchain_deposits().await?;
+    let inclusions = observer.get_block_inclusions().await?;
+    Ok(JsObservation::new(
+        &registration,
+        &deposits,
+        &inclusions,
+        observer.next_block_number().unwrap_or_default(),
+        observer.is_caught_up(),
+    ))
+}
+
+fn load_templates(key: KeySet, templates: Option<Vec<u8>>) -> Result<TemplateBook, JsError> {
+    match templates {
+        Some(bytes) => TemplateBook::decrypt(&bytes, key)
+            .map_err(|e| JsError::new(&format!("Failed to decrypt templates: {}", e))),
+        None => Ok(TemplateBook::new(key.pubkey)),
+    }
+}
+
+/// Save `template` into the serialized templates `templates` (none for the first
+/// one), replacing the template of the same name. Returns the templates to store.
+/// They go through the data encryption of the store vault blobs, which does not
+/// encrypt yet.
+#[wasm_bindgen]
+pub fn save_template(
+    private_key: &str,
+    templates: Option<Vec<u8>>,
+    template: &JsTxTemplate,
+) -> Result<Vec<u8>, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let mut book = load_templates(key, templates)?;
+    book.save_template(template.to_template()?)?;
+    Ok(book.encrypt(key.pubkey))
+}
+
+#[wasm_bindgen]
+pub fn get_templates(private_key: &str, templates: Vec<u8>) -> Result<Vec<JsTxTemplate>, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let book = load_templates(key, Some(templates))?;
+    Ok(book
+        .get_templates()
+        .iter()
+        .map(JsTxTemplate::from_template)
+        .collect())
+}
+
+/// Turn a template into transfers with fresh salts, to pass to `transfer`. The
+/// recipients and amounts are validated again. `amount_override` replaces the amount
+/// of a single-transfer template. Templates with `@name` contacts fail, as the SDK
+/// keeps no address book.
+#[wasm_bindgen]
+pub fn apply_template(
+    private_key: &str,
+    templates: Vec<u8>,
+    name: &str,
+    amount_override: Option<String>,
+) -> Result<Vec<JsTransfer>, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let book = load_templates(key, Some(templates))?;
+    let overrides = TemplateOverrides {
+        amount: amount_override.as_deref().map(parse_u256).transpose()?,
+    };
+    let transfers = book.apply_template(name, &overrides)?;
+    Ok(transfers.iter().map(JsTransfer::from_transfer).collect())
+}
+
+fn rotation_options(
+    config: &Config,
+    dust_threshold: Option<String>,
+    sponsor_url: Option<String>,
+    templates: Option<TemplateBook>,
+) -> Result<RotationOptions, JsError> {
+    Ok(RotationOptions {
+        fee_mode: to_fee_mode(sponsor_url),
+        dust_threshold: dust_threshold
+            .as_deref()
+            .map(parse_u256)
+            .transpose()?
+            .unwrap_or_default(),
+        templates,
+        timeout: config.tx_timeout,
+        ..Default::default()
+    })
+}
+
+/// First step of a key rotation: sync both accounts and plan the txs that move the
+/// whole balance of the old account to the new one. Show the plan, including what
+/// is left behind, before sending anything. Balances at or below `dust_threshold`
+/// are left behind. Keep the plan and the tx tree roots of the chunks sent so far
+/// (see `JsRotationPlan.to_json`), to resume a rotation that failed midway.
+#[wasm_bindgen]
+pub async fn plan_key_rotation(
+    config: &Config,
+    private_key: &str,
+    new_private_key: &str,
+    dust_threshold: Option<String>,
+) -> Result<JsRotationPlan, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let new_key = str_privkey_to_keyset(new_private_key)?;
+    let options = rotation_options(config, dust_threshold, None, None)?;
+    let client = get_client(config);
+    let plan = client.plan_rotation(key, new_key, &options).await?;
+    Ok(JsRotationPlan::from_rotation_plan(&plan))
+}
+
+/// Send chunk `index` of the plan and wait until its block is included. Call for
+/// each chunk in order. Returns the tx tree root.
+#[wasm_bindgen]
+pub async fn send_key_rotation_chunk(
+    config: &Config,
+    block_builder_url: &str,
+    private_key: &str,
+    plan: &JsRotationPlan,
+    index: usize,
+    sponsor_url: Option<String>,
+) -> Result<String, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let plan = plan.to_rotation_plan()?;
+    let chunk = plan
+        .chunks
+        .get(index)
+        .ok_or_else(|| JsError::new(&format!("no chunk {}", index)))?;
+    let options = rotation_options(config, None, sponsor_url, None)?;
+    let client = get_client(config);
+    let tx_tree_root = client
+        .send_rotation_chunk(block_builder_url, key, chunk.clone(), &options)
+        .await?;
+    Ok(tx_tree_root.to_string())
+}
+
+/// Last step of a key rotation: sync the new account until it holds its balance
+/// before the rotation plus what the plan moved. `tx_tree_roots` are the results of `send_key_rotation_chunk`. If the
+/// encrypted `templates` of the old account are given, the report has them
+/// encrypted for the new account.
+#[wasm_bindgen]
+pub async fn complete_key_rotation(
+    config: &Config,
+    old_private_key: &str,
+    new_private_key: &str,
+    plan: &JsRotationPlan,
+    tx_tree_roots: Vec<String>,
+    templates: Option<Vec<u8>>,
+) -> Result<JsRotationReport, JsError> {
+    let new_key = str_privkey_to_keyset(new_private_key)?;
+    let templates = match templates {
+        Some(templates) => {
+            let old_key = str_privkey_to_keyset(old_private_key)?;
+            Some(load_templates(old_key, Some(templates))?)
+        }
+        None => None,
+    };
+    let plan = plan.to_rotation_plan()?;
+    let tx_tree_roots = tx_tree_roots
+        .iter()
+        .map(|root| parse_id_str(root))
+        .collect::<Result<Vec<_>, JsError>>()?;
+    let options = rotation_options(config, None, None, templates)?;
+    let client = get_client(config);
+    let report = client
+        .complete_rotation(new_key, &plan, &tx_tree_roots, &options)
+        .await?;
+    Ok(JsRotationReport::from_rotation_report(&report))
+}
+
+/// Sync the incoming transfers and deposits of the account, then send the whole
+/// balance of `token_indices` (all tokens if empty) to the account itself and sync
+/// the receipt. `fee_reserve` of token `fee_token_index` (default 0) is kept out of
+/// the self-transfers to pay the fees with, or without it and a sponsor, the fee the
+/// block builder quotes for each tx. Fails without sending anything when
+/// fewer than `min_pending_receives` (default 8) transfers and deposits are pending.
+#[wasm_bindgen]
+pub async fn consolidate(
+    config: &Config,
+    block_builder_url: &str,
+    private_key: &str,
+    token_indices: Vec<u32>,
+    fee_reserve: Option<String>,
+    fee_token_index: Option<u32>,
+    min_pending_receives: Option<usize>,
+    sponsor_url: Option<String>,
+) -> Result<JsConsolidationReport, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let fee_reserve = match fee_reserve {
+        Some(amount) => Some((fee_token_index.unwrap_or_default(), parse_u256(&amount)?)),
+        None => None,
+    };
+    let default = ConsolidationOptions::default();
+    let options = ConsolidationOptions {
+        fee_mode: to_fee_mode(sponsor_url),
+        fee_reserve,
+        min_pending_receives: min_pending_receives.unwrap_or(default.min_pending_receives),
+        timeout: config.tx_timeout,
+        ..default
+    };
+    let client = get_client(config);
+    let report = client
+        .consolidate(block_builder_url, key, &token_indices, &options)
+        .await?;
+    Ok(JsConsolidationReport::from_consolidation_report(&report))
+}
+
+/// Receipt of the received transfer `transfer_uuid`, proving to a third party that
+/// it was included in a block
+#[wasm_bindgen]
+pub async fn export_receive_receipt(
+    config: &Config,
+    private_key: &str,
+    transfer_uuid: &str,
+) -> Result<JsReceiveReceipt, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let receipt = client.export_receive_receipt(key, transfer_uuid).await?;
+    Ok(JsReceiveReceipt::from_receipt(&receipt))
+}
+
+/// Check that `receipt` pays `expected_recipient` in a tx posted on chain and
+/// executed. The block's tx tree root is `tx_tree_root` if given (e.g. from the
+/// rollup contract), otherwise the one known to the validity prover, which also
+/// tells whether the block is valid and the sender signed.
+#[wasm_bindgen]
+pub async fn verify_receive_receipt(
+    config: &Config,
+    receipt: &JsReceiveReceipt,
+    expected_recipient: &str,
+    tx_tree_root: Option<String>,
+) -> Result<(), JsError> {
+    let receipt = receipt.to_receipt()?;
+    let expected_recipient = parse_h256_as_u256(expected_recipient)?;
+    let client = get_client(config);
+    let mut reference =
+        RollupReference::from_validity_prover(&client.validity_prover, receipt.block_number)
+            .await?;
+    if let Some(tx_tree_root) = tx_tree_root {
+        reference.tx_tree_root = parse_id_str(&tx_tree_root)?;
+    }
+    inner_verify_receive_receipt(&receipt, expected_recipient, &reference)?;
+    Ok(())
+}
+
+/// Check a balance proof, e.g. one sent with a balance claim, against the balance
+/// circuit verifier embedded in the SDK: the proof has to hold, be of the claimed
+/// `pubkey`, and its public state has to be that of the validity prover of
+/// `config` at its block. The proof is in the compressed encoding of the balance
+/// prover. A failed check is reported in the status of the result; an error
+/// means the bytes are not a proof of the circuit or the validity prover failed.
+#[wasm_bindgen]
+pub async fn verify_balance_proof(
+    config: &Config,
+    proof_bytes: &[u8],
+    pubkey: &str,
+) -> Result<JsBalanceProofCheck, JsError> {
+    let check = check_balance_proof(proof_bytes, pubkey)?;
+    if check.status != BalanceProofStatus::Valid {
+        return Ok(JsBalanceProofCheck::from(&check));
+    }
+    let client = get_client(config);
+    let validity_pis = client
+        .validity_prover
+        .get_validity_pis(check.public_inputs.public_state.block_number)
+        .await?;
+    let check = check.with_chain_state(validity_pis.as_ref());
+    Ok(JsBalanceProofCheck::from(&check))
+}
+
+fn check_balance_proof(proof_bytes: &[u8], pubkey: &str) -> Result<BalanceProofCheck, JsError> {
+    let pubkey = parse_h256_as_u256(pubkey)?;
+    check_encoded_balance_proof(proof_bytes, pubkey).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Call on page load, before any other operation on the account. If the previous
+/// page was torn down before `shutdown` completed, store vault writes are refused
+/// until the user data and the balance proof of its last checkpoint are checked.
+/// Returns whether the check ran.
+#[wasm_bindgen]
+pub async fn init(config: &Config, private_key: &str) -> Result<bool, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let marker = LocalStorageMarker::new(key.pubkey);
+    let checked = client.open_session(key, &marker).await?;
+    Ok(checked)
+}
+
+/// Call when the page is about to be torn down (e.g. on `pagehide`). Cancels new
+/// store vault writes of the account, waits up to 2 seconds for the in-flight ones
+/// and marks its session as cleanly closed. Returns false if writes were still in
+/// flight. Sessions of other accounts stay open.
+#[wasm_bindgen]
+pub async fn shutdown(config: &Config, private_key: &str) -> Result<bool, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    let marker = LocalStorageMarker::new(key.pubkey);
+    Ok(client.shutdown(key.pubkey, &marker).await.is_clean())
+}
+
+/// Call when the page was not torn down after `shutdown`, e.g. when the unload was
+/// cancelled or the page is restored from the back/forward cache (`pageshow` with
+/// `persisted`). Store vault writes of the account are accepted again.
+#[wasm_bindgen]
+pub fn cancel_shutdown(config: &Config, private_key: &str) -> Result<(), JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let client = get_client(config);
+    client.cancel_shutdown(key.pubkey, &LocalStorageMarker::new(key.pubkey));
+    Ok(())
 }
 
 /// Decrypt the deposit data.
@@ -189,6 +1182,65 @@ pub async fn decrypt_deposit_data(
     Ok(JsDepositData::from_deposit_data(&deposit_data))
 }
 
+/// Decrypt the deposit data of each blob, e.g. of the entries returned by an
+/// indexer. Blobs that fail to decrypt, such as those encrypted for another key,
+/// are skipped, so the result may be shorter than `blobs`.
+#[wasm_bindgen]
+pub async fn batch_decrypt_deposit_data(
+    private_key: &str,
+    blobs: Vec<js_sys::Uint8Array>,
+) -> Result<Vec<JsDepositData>, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let blobs = blobs.iter().map(|blob| blob.to_vec()).collect::<Vec<_>>();
+    Ok(decrypt_deposit_blobs(key, &blobs))
+}
+
+fn decrypt_deposit_blobs(key: KeySet, blobs: &[Vec<u8>]) -> Vec<JsDepositData> {
+    let decrypted = decrypt_blobs(with_unit_meta(blobs), |data| {
+        DepositData::decrypt(data, key)
+    });
+    collect_deposits(decrypted)
+}
+
+/// Same as `batch_decrypt_deposit_data`, but the blobs are decrypted concurrently
+/// on the rayon pool. A page that has not started the pool with threads (e.g.
+/// through wasm-bindgen-rayon) gets them decrypted one by one.
+#[cfg(feature = "parallel")]
+#[wasm_bindgen]
+pub async fn batch_decrypt_deposit_data_parallel(
+    private_key: &str,
+    blobs: Vec<js_sys::Uint8Array>,
+) -> Result<Vec<JsDepositData>, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    // copied out of the JS arrays, which cannot be sent to other threads
+    let blobs = blobs.iter().map(|blob| blob.to_vec()).collect::<Vec<_>>();
+    Ok(decrypt_deposit_blobs_parallel(key, &blobs))
+}
+
+#[cfg(feature = "parallel")]
+fn decrypt_deposit_blobs_parallel(key: KeySet, blobs: &[Vec<u8>]) -> Vec<JsDepositData> {
+    use intmax2_client_sdk::client::strategy::decrypt::decrypt_blobs_parallel;
+
+    let decrypted = decrypt_blobs_parallel(with_unit_meta(blobs), |data| {
+        DepositData::decrypt(data, key)
+    });
+    collect_deposits(decrypted)
+}
+
+/// The blobs passed from JS have no metadata
+fn with_unit_meta(blobs: &[Vec<u8>]) -> Vec<((), Vec<u8>)> {
+    blobs.iter().map(|data| ((), data.clone())).collect()
+}
+
+/// Keep the deposits that decrypted, in the order of the blobs
+fn collect_deposits(decrypted: Vec<((), anyhow::Result<DepositData>)>) -> Vec<JsDepositData> {
+    decrypted
+        .into_iter()
+        .filter_map(|(_, result)| result.ok())
+        .map(|deposit_data| JsDepositData::from_deposit_data(&deposit_data))
+        .collect()
+}
+
 /// Decrypt the transfer data. This is also used to decrypt the withdrawal data.
 #[wasm_bindgen]
 pub async fn decrypt_transfer_data(
@@ -209,6 +1261,71 @@ pub async fn decrypt_tx_data(private_key: &str, data: &[u8]) -> Result<JsTxData,
     Ok(JsTxData::from_tx_data(&tx_data))
 }
 
+/// Decrypt a backup blob whose type is not known, trying it as a deposit, a transfer
+/// (or withdrawal) and a tx in this order. The `data_type` of the result tells which
+/// one it is. Throws with the error of each try if it is none of them.
+#[wasm_bindgen]
+pub async fn decrypt_any(private_key: &str, data: &[u8]) -> Result<JsDecryptedData, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    // the cheap deposit first: its pubkey salt hash rules out the other types
+    let deposit_error = match DepositData::decrypt(data, key) {
+        Ok(deposit_data) => return Ok(JsDecryptedData::from_deposit_data(&deposit_data)),
+        Err(e) => e,
+    };
+    let transfer_error = match TransferData::decrypt(data, key) {
+        Ok(transfer_data) => return Ok(JsDecryptedData::from_transfer_data(&transfer_data)),
+        Err(e) => e,
+    };
+    let tx_error = match TxData::decrypt(data, key) {
+        Ok(tx_data) => return Ok(JsDecryptedData::from_tx_data(&tx_data)),
+        Err(e) => e,
+    };
+    Err(JsError::new(&format!(
+        "data is neither a deposit ({}), a transfer ({}) nor a tx ({})",
+        deposit_error, transfer_error, tx_error
+    )))
+}
+
+/// Decrypt the tx data without blocking the event loop for the whole decryption.
+/// `on_progress` is called with `{ phase: "decrypt" | "parse", percent }` after each
+/// step of `ChunkedDecryption`, and control is yielded to the event loop between the
+/// steps and before the parse.
+#[wasm_bindgen]
+pub async fn decrypt_tx_data_streaming(
+    private_key: &str,
+    data: &[u8],
+    on_progress: js_sys::Function,
+) -> Result<JsTxData, JsError> {
+    let key = str_privkey_to_keyset(private_key)?;
+    let report = |phase: DecryptPhase, percent: u32| -> Result<(), JsError> {
+        let progress = JsDecryptProgress {
+            phase: phase.as_str().to_string(),
+            percent,
+        };
+        on_progress
+            .call1(&JsValue::NULL, &JsValue::from(progress))
+            .map_err(|e| JsError::new(&format!("progress callback failed: {:?}", e)))?;
+        Ok(())
+    };
+
+    report(DecryptPhase::Decrypt, 0)?;
+    let mut decryption = ChunkedDecryption::new(key, data);
+    while decryption
+        .next_chunk()
+        .map_err(|e| JsError::new(&format!("{}", e)))?
+    {
+        report(DecryptPhase::Decrypt, decryption.percent())?;
+        TimeoutFuture::new(0).await;
+    }
+    let bytes = decryption.finish();
+
+    report(DecryptPhase::Parse, 0)?;
+    let tx_data =
+        TxData::<F, C, D>::parse_phase(&bytes, key).map_err(|e| JsError::new(&format!("{}", e)))?;
+    report(DecryptPhase::Parse, 100)?;
+    Ok(JsTxData::from_tx_data(&tx_data))
+}
+
 // Function to mimic the deposit call of the contract. For development purposes only.
 // #[wasm_bindgen]
 // pub async fn mimic_deposit(
@@ -227,3 +1344,228 @@ pub async fn decrypt_tx_data(private_key: &str, data: &[u8]) -> Result<JsTxData,
 //         .await?;
 //     Ok(())
 // }
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    #[wasm_bindgen_test]
+    fn test_progress_callbacks() {
+        use intmax2_client_sdk::client::client::SyncProgress;
+        use wasm_bindgen::{closure::Closure, JsCast as _};
+
+        let progress = |stage, current, total| SyncProgress {
+            current,
+            total,
+            stage,
+        };
+        let events = [
+            progress(SyncStage::Actions, 1, 3),
+            progress(SyncStage::Actions, 2, 3),
+            progress(SyncStage::Blocks, 1, 2),
+            progress(SyncStage::Withdrawals, 0, 0),
+        ];
+
+        // the positional callback sees blocks and withdrawals only
+        let calls = js_sys::Array::new();
+        let record = {
+            let calls = calls.clone();
+            Closure::<dyn Fn(JsValue, JsValue)>::new(move |current, total| {
+                calls.push(&js_sys::Array::of2(&current, &total));
+            })
+        };
+        let callback = positional_progress_callback(
+            record.as_ref().unchecked_ref::<js_sys::Function>().clone(),
+        );
+        events.iter().for_each(|event| callback(*event));
+        let calls = calls
+            .iter()
+            .map(|call| {
+                let call = js_sys::Array::from(&call);
+                (call.get(0).as_f64().unwrap(), call.get(1).as_f64().unwrap())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(calls, vec![(1.0, 2.0), (0.0, 0.0)]);
+
+        let staged = js_sys::Array::new();
+        let record = {
+            let staged = staged.clone();
+            Closure::<dyn Fn(JsValue)>::new(move |progress| {
+                staged.push(&progress);
+            })
+        };
+        let callback = staged_progress_callback(record.as_ref().unchecked_ref());
+        events.iter().for_each(|event| callback(*event));
+        let get = |value: &JsValue, name: &str| js_sys::Reflect::get(value, &name.into()).unwrap();
+        let staged = staged
+            .iter()
+            .map(|progress| {
+                (
+                    get(&progress, "stage").as_string().unwrap(),
+                    get(&progress, "current").as_f64().unwrap(),
+                    get(&progress, "total").as_f64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            staged,
+            vec![
+                ("actions".to_string(), 1.0, 3.0),
+                ("actions".to_string(), 2.0, 3.0),
+                ("blocks".to_string(), 1.0, 2.0),
+                ("withdrawals".to_string(), 0.0, 0.0),
+            ]
+        );
+
+        // an exception thrown by the callback is ignored
+        let throwing = js_sys::Function::new_with_args("current, total", "throw new Error('ui');");
+        positional_progress_callback(throwing)(events[2]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_check_balance_proof() {
+        use intmax2_zkp::circuits::balance::balance_pis::BalancePublicInputs;
+
+        let pubkey = U256::from(7u128);
+        assert!(check_balance_proof(&[1, 2, 3], &pubkey.to_hex()).is_err());
+        assert!(check_balance_proof(&[1, 2, 3], "not a pubkey").is_err());
+
+        let check = BalanceProofCheck {
+            status: BalanceProofStatus::Valid,
+            public_inputs: BalancePublicInputs::new(pubkey),
+        };
+        let valid = JsBalanceProofCheck::from(&check);
+        assert!(valid.is_valid);
+        assert_eq!(valid.status, "valid");
+        assert_eq!(valid.pubkey, pubkey.to_hex());
+        // a block the validity prover does not know
+        let unknown = JsBalanceProofCheck::from(&check.with_chain_state(None));
+        assert!(!unknown.is_valid);
+        assert_eq!(unknown.status, "wrongPublicState");
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_decrypt_any() {
+        use intmax2_zkp::common::{deposit::get_pubkey_salt_hash, salt::Salt};
+
+        let private_key = generate_intmax_account_from_mnemonic(TEST_MNEMONIC, 0)
+            .await
+            .unwrap()
+            .privkey;
+        let key = str_privkey_to_keyset(&private_key).unwrap();
+        let deposit_salt = Salt::rand(&mut rand::thread_rng());
+        let deposit_data = DepositData {
+            deposit_salt,
+            pubkey_salt_hash: get_pubkey_salt_hash(key.pubkey, deposit_salt),
+            amount: U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, 5]),
+            token_type: TokenType::NATIVE,
+            token_address: Default::default(),
+            token_id: U256::default(),
+            token_index: Some(0),
+        };
+        let data = deposit_data.encrypt(key.pubkey);
+
+        let decrypted = decrypt_any(&private_key, &data).await.unwrap();
+        assert_eq!(decrypted.data_type, "deposit");
+        assert_eq!(decrypted.deposit.unwrap().amount, "5");
+        assert!(decrypted.transfer.is_none() && decrypted.tx.is_none());
+
+        // the deposit of another key is none of the types
+        let other_key = generate_intmax_account_from_mnemonic(TEST_MNEMONIC, 1)
+            .await
+            .unwrap()
+            .privkey;
+        assert!(decrypt_any(&other_key, &data).await.is_err());
+        assert!(decrypt_any(&private_key, &[1, 2, 3]).await.is_err());
+    }
+
+    fn deposit_blob(key: KeySet, amount: u32) -> Vec<u8> {
+        use intmax2_zkp::common::{deposit::get_pubkey_salt_hash, salt::Salt};
+
+        let deposit_salt = Salt::rand(&mut rand::thread_rng());
+        let deposit_data = DepositData {
+            deposit_salt,
+            pubkey_salt_hash: get_pubkey_salt_hash(key.pubkey, deposit_salt),
+            amount: U256::from_u32_slice(&[0, 0, 0, 0, 0, 0, 0, amount]),
+            token_type: TokenType::NATIVE,
+            token_address: Default::default(),
+            token_id: U256::default(),
+            token_index: Some(0),
+        };
+        deposit_data.encrypt(key.pubkey)
+    }
+
+    fn amounts(deposits: &[JsDepositData]) -> Vec<String> {
+        deposits.iter().map(|d| d.amount.clone()).collect()
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_batch_decrypt_deposit_data() {
+        let private_key = generate_intmax_account_from_mnemonic(TEST_MNEMONIC, 0)
+            .await
+            .unwrap()
+            .privkey;
+        let key = str_privkey_to_keyset(&private_key).unwrap();
+        let other_private_key = generate_intmax_account_from_mnemonic(TEST_MNEMONIC, 1)
+            .await
+            .unwrap()
+            .privkey;
+        let other_key = str_privkey_to_keyset(&other_private_key).unwrap();
+
+        assert!(decrypt_deposit_blobs(key, &[]).is_empty());
+
+        let all_mine = [deposit_blob(key, 1), deposit_blob(key, 2)];
+        assert_eq!(amounts(&decrypt_deposit_blobs(key, &all_mine)), ["1", "2"]);
+
+        // the deposit of another key and a corrupted blob are skipped
+        let mixed = [
+            deposit_blob(key, 1),
+            deposit_blob(other_key, 2),
+            vec![1, 2, 3],
+            deposit_blob(key, 4),
+        ];
+        assert_eq!(amounts(&decrypt_deposit_blobs(key, &mixed)), ["1", "4"]);
+        assert!(decrypt_deposit_blobs(key, &[vec![1, 2, 3]]).is_empty());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[wasm_bindgen_test]
+    async fn test_batch_decrypt_deposit_data_parallel() {
+        let private_key = generate_intmax_account_from_mnemonic(TEST_MNEMONIC, 0)
+            .await
+            .unwrap()
+            .privkey;
+        let key = str_privkey_to_keyset(&private_key).unwrap();
+        let other_private_key = generate_intmax_account_from_mnemonic(TEST_MNEMONIC, 1)
+            .await
+            .unwrap()
+            .privkey;
+        let other_key = str_privkey_to_keyset(&other_private_key).unwrap();
+
+        assert!(decrypt_deposit_blobs_parallel(key, &[]).is_empty());
+
+        let blobs = (0..50)
+            .map(|i| match i % 5 {
+                2 => deposit_blob(other_key, i),
+                4 => vec![i as u8; 40],
+                _ => deposit_blob(key, i),
+            })
+            .collect::<Vec<_>>();
+        let parallel = decrypt_deposit_blobs_parallel(key, &blobs);
+        assert_eq!(parallel.len(), 30);
+        assert_eq!(
+            amounts(&parallel),
+            amounts(&decrypt_deposit_blobs(key, &blobs))
+        );
+
+        let all_mine = [deposit_blob(key, 1), deposit_blob(key, 2)];
+        assert_eq!(
+            amounts(&decrypt_deposit_blobs_parallel(key, &all_mine)),
+            ["1", "2"]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use intmax2_client_sdk::client::session::SessionMarker;
+use intmax2_zkp::ethereum_types::{u256::U256, u32limb_trait::U32LimbTrait as _};
+
+const SESSION_KEY_PREFIX: &str = "intmax2_session_open";
+
+/// Session marker of one account in the local storage of the page. Without a
+/// window (e.g. in a worker) nothing is persisted and every start looks clean.
+pub struct LocalStorageMarker {
+    key: String,
+}
+
+impl LocalStorageMarker {
+    pub fn new(pubkey: U256) -> Self {
+        Self {
+            key: format!("{}_{}", SESSION_KEY_PREFIX, pubkey.to_hex()),
+        }
+    }
+
+    fn storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+}
+
+impl SessionMarker for LocalStorageMarker {
+    fn is_open(&self) -> bool {
+        Self::storage()
+            .and_then(|storage| storage.get_item(&self.key).ok()?)
+            .is_some()
+    }
+
+    fn set_open(&self, open: bool) {
+        if let Some(storage) = Self::storage() {
+            let _ = if open {
+                storage.set_item(&self.key, "1")
+            } else {
+                storage.remove_item(&self.key)
+            };
+        }
+    }
+}
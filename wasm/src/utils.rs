@@ -1,4 +1,5 @@
 use ethers::types::H256;
+use intmax2_client_sdk::utils::id::parse_id;
 use intmax2_zkp::{
     common::signature::key_set::KeySet,
     ethereum_types::{bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait},
@@ -30,3 +31,8 @@ pub fn parse_h256_as_u256(s: &str) -> Result<U256, JsError> {
     let x = parse_h256(s)?;
     Ok(h256_to_bytes32(x).into())
 }
+
+/// A tx tree root or other 32-byte id, in plain hex or with a checksum
+pub fn parse_id_str(s: &str) -> Result<Bytes32, JsError> {
+    parse_id(s).map_err(|e| JsError::new(&e.to_string()))
+}
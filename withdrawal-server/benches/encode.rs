@@ -0,0 +1,97 @@
+//! Benchmarks of the proof encoding, compressed against plain bincode.
+//!
+//! The proof is a spent proof of an empty private state and empty transfers, which
+//! is deterministic since the circuit is not zero-knowledge. Building the circuit
+//! takes a while, hence the `bench-heavy` feature:
+//!
+//! ```sh
+//! cargo bench -p withdrawal-server --features bench-heavy -- --save-baseline before
+//! ```
+
+use std::time::Duration;
+
+use criterion::{black_box, Criterion};
+use intmax2_interfaces::utils::encode::{decode_plonky2_proof, encode_plonky2_proof};
+use intmax2_zkp::{
+    circuits::balance::send::spent_circuit::SpentCircuit,
+    common::{
+        private_state::FullPrivateState, salt::Salt, transfer::Transfer,
+        trees::transfer_tree::TransferTree, tx::Tx, witness::spent_witness::SpentWitness,
+    },
+    constants::{NUM_TRANSFERS_IN_TX, TRANSFER_TREE_HEIGHT},
+};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+const D: usize = 2;
+
+// (benchmark, expected order of magnitude on a recent laptop)
+const EXPECTED: &[(&str, &str)] = &[
+    ("proof_encoding/compressed_encode", "~10 ms"),
+    ("proof_encoding/compressed_decode", "~10 ms"),
+    ("proof_encoding/uncompressed_encode", "~1 ms"),
+    ("proof_encoding/uncompressed_decode", "~1 ms"),
+];
+
+fn main() {
+    println!("Expected orders of magnitude:");
+    for (name, expected) in EXPECTED {
+        println!("  {:<36} {}", name, expected);
+    }
+    println!();
+
+    let mut transfer_tree = TransferTree::new(TRANSFER_TREE_HEIGHT);
+    for _ in 0..NUM_TRANSFERS_IN_TX {
+        transfer_tree.push(Transfer::default());
+    }
+    let state = FullPrivateState::new();
+    let tx = Tx {
+        nonce: state.nonce,
+        transfer_tree_root: transfer_tree.get_root(),
+    };
+    let witness = SpentWitness::new(
+        &state.asset_tree,
+        &state.to_private_state(),
+        &transfer_tree.leaves(),
+        tx,
+        Salt::default(),
+    )
+    .unwrap();
+    let spent_circuit = SpentCircuit::<F, C, D>::new();
+    let proof = spent_circuit.prove(&witness.to_value().unwrap()).unwrap();
+    let verifier_data = spent_circuit.data.verifier_data();
+
+    let compressed = encode_plonky2_proof(proof.clone(), &verifier_data).unwrap();
+    let uncompressed = bincode::serialize(&proof).unwrap();
+    println!(
+        "Proof size: {} bytes compressed, {} bytes uncompressed\n",
+        compressed.len(),
+        uncompressed.len()
+    );
+
+    let mut c = Criterion::default()
+        .measurement_time(Duration::from_secs(10))
+        .configure_from_args();
+    let mut group = c.benchmark_group("proof_encoding");
+    group.bench_function("compressed_encode", |b| {
+        b.iter(|| encode_plonky2_proof(black_box(proof.clone()), &verifier_data).unwrap())
+    });
+    group.bench_function("compressed_decode", |b| {
+        b.iter(|| decode_plonky2_proof(black_box(&compressed), &verifier_data).unwrap())
+    });
+    group.bench_function("uncompressed_encode", |b| {
+        b.iter(|| bincode::serialize(black_box(&proof)).unwrap())
+    });
+    group.bench_function("uncompressed_decode", |b| {
+        b.iter(|| {
+            bincode::deserialize::<ProofWithPublicInputs<F, C, D>>(black_box(&uncompressed))
+                .unwrap()
+        })
+    });
+    group.finish();
+    c.final_summary();
+}
@@ -0,0 +1 @@
+ALTER TABLE withdrawals DROP COLUMN attribution_tag_hash;
@@ -0,0 +1 @@
+ALTER TABLE withdrawals ADD COLUMN attribution_tag_hash CHAR(66);
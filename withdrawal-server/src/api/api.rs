@@ -1,18 +1,29 @@
-use crate::api::state::State;
+use crate::api::{error::WithdrawalServerError, state::State};
 use actix_web::{
     get, post,
     web::{Data, Json},
     Error, Scope,
 };
-use intmax2_interfaces::api::withdrawal_server::{
-    interface::Fee,
-    types::{
-        GetFeeResponse, GetWithdrawalInfoByRecipientRequest, GetWithdrawalInfoRequest,
-        GetWithdrawalInfoResponse, RequestWithdrawalRequest,
+use intmax2_interfaces::api::{
+    version::VersionResponse,
+    withdrawal_server::{
+        interface::Fee,
+        types::{
+            GetFeeResponse, GetLimitsResponse, GetWithdrawalInfoByRecipientRequest,
+            GetWithdrawalInfoRequest, GetWithdrawalInfoResponse, RequestWithdrawalRequest,
+        },
     },
 };
 use serde_qs::actix::QsQuery;
 
+#[get("/version")]
+pub async fn version() -> Json<VersionResponse> {
+    Json(VersionResponse::new(
+        "withdrawal-server",
+        env!("CARGO_PKG_VERSION"),
+    ))
+}
+
 #[get("/fee")]
 pub async fn get_fee() -> Result<Json<GetFeeResponse>, Error> {
     let fees = vec![Fee {
@@ -23,6 +34,13 @@ pub async fn get_fee() -> Result<Json<GetFeeResponse>, Error> {
     Ok(Json(GetFeeResponse { fees }))
 }
 
+#[get("/limits")]
+pub async fn get_limits(state: Data<State>) -> Result<Json<GetLimitsResponse>, Error> {
+    Ok(Json(GetLimitsResponse {
+        limits: state.limits.clone(),
+    }))
+}
+
 #[post("/request-withdrawal")]
 pub async fn request_withdrawal(
     state: Data<State>,
@@ -30,9 +48,18 @@ pub async fn request_withdrawal(
 ) -> Result<Json<()>, Error> {
     state
         .withdrawl_server
-        .request_withdrawal(request.pubkey, &request.single_withdrawal_proof)
+        .request_withdrawal(
+            request.pubkey,
+            &request.single_withdrawal_proof,
+            request.attribution_tag_hash,
+        )
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(|e| match e {
+            WithdrawalServerError::BelowMinimumWithdrawal { .. } => {
+                actix_web::error::ErrorBadRequest(e)
+            }
+            e => actix_web::error::ErrorInternalServerError(e),
+        })?;
     Ok(Json(()))
 }
 
@@ -65,8 +92,10 @@ pub async fn get_withdrawal_info_by_recipient(
 
 pub fn withdrawal_server_scope() -> Scope {
     actix_web::web::scope("/withdrawal-server")
+        .service(version)
         .service(request_withdrawal)
         .service(get_fee)
+        .service(get_limits)
         .service(get_withdrawal_info)
         .service(get_withdrawal_info_by_recipient)
 }
@@ -1,3 +1,5 @@
+use intmax2_zkp::ethereum_types::u256::U256;
+
 #[derive(Debug, thiserror::Error)]
 pub enum WithdrawalServerError {
     #[error("Database error {0}")]
@@ -6,6 +8,13 @@ pub enum WithdrawalServerError {
     #[error("Single withdrawal proof verification error")]
     SingleWithdrawalVerificationError,
 
+    #[error("Withdrawal of {amount} of token {token_index} is below the minimum {min}")]
+    BelowMinimumWithdrawal {
+        token_index: u32,
+        min: U256,
+        amount: U256,
+    },
+
     #[error("Serialization error {0}")]
     SerializationError(String),
 }
@@ -1,5 +1,4 @@
 pub mod api;
-pub mod encode;
 pub mod error;
 pub mod state;
 pub mod status;
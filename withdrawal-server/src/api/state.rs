@@ -1,17 +1,21 @@
 use std::sync::Arc;
 
+use intmax2_interfaces::api::withdrawal_server::interface::WithdrawalLimits;
+
 use super::withdrawal_server::WithdrawalServer;
 
 #[derive(Clone)]
 pub struct State {
     pub withdrawl_server: Arc<WithdrawalServer>,
+    pub limits: WithdrawalLimits,
 }
 
 impl State {
-    pub async fn new(database_url: &str) -> anyhow::Result<Self> {
-        let withdrawal_server = WithdrawalServer::new(database_url).await?;
+    pub async fn new(database_url: &str, limits: WithdrawalLimits) -> anyhow::Result<Self> {
+        let withdrawal_server = WithdrawalServer::new(database_url, limits.clone()).await?;
         Ok(State {
             withdrawl_server: Arc::new(withdrawal_server),
+            limits,
         })
     }
 }
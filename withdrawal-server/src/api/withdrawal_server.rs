@@ -1,12 +1,15 @@
-use crate::api::{encode::encode_plonky2_proof, status::SqlWithdrawalStatus};
+use crate::api::status::SqlWithdrawalStatus;
 
 use super::error::WithdrawalServerError;
 use intmax2_client_sdk::utils::circuit_verifiers::CircuitVerifiers;
 
-use intmax2_interfaces::api::withdrawal_server::interface::{ContractWithdrawal, WithdrawalInfo};
+use intmax2_interfaces::{
+    api::withdrawal_server::interface::{ContractWithdrawal, WithdrawalInfo, WithdrawalLimits},
+    utils::encode::encode_plonky2_proof,
+};
 use intmax2_zkp::{
     common::{signature::flatten::FlatG2, withdrawal::Withdrawal},
-    ethereum_types::{address::Address, u256::U256, u32limb_trait::U32LimbTrait},
+    ethereum_types::{address::Address, bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait},
     utils::conversion::ToU64,
 };
 use plonky2::{
@@ -22,21 +25,24 @@ const D: usize = 2;
 
 pub struct WithdrawalServer {
     pub pool: PgPool,
+    // the limits advertised by `/limits`, enforced on the requests
+    pub limits: WithdrawalLimits,
 }
 
 impl WithdrawalServer {
-    pub async fn new(database_url: &str) -> anyhow::Result<Self> {
+    pub async fn new(database_url: &str, limits: WithdrawalLimits) -> anyhow::Result<Self> {
         let pool = PgPoolOptions::new()
             .max_connections(5)
             .connect(database_url)
             .await?;
-        Ok(Self { pool })
+        Ok(Self { pool, limits })
     }
 
     pub async fn request_withdrawal(
         &self,
         pubkey: U256,
         single_withdrawal_proof: &ProofWithPublicInputs<F, C, D>,
+        attribution_tag_hash: Option<Bytes32>,
     ) -> Result<(), WithdrawalServerError> {
         // Verify the single withdrawal proof
         let single_withdrawal_vd = CircuitVerifiers::load().get_single_withdrawal_vd();
@@ -50,6 +56,20 @@ impl WithdrawalServer {
                 .map_err(|e| WithdrawalServerError::SerializationError(e.to_string()))?;
         let withdrawal =
             Withdrawal::from_u64_slice(&single_withdrawal_proof.public_inputs.to_u64_vec());
+        if let Some(min) = self
+            .limits
+            .min_withdrawals
+            .iter()
+            .find(|limit| limit.token_index == withdrawal.token_index)
+        {
+            if withdrawal.amount < min.amount {
+                return Err(WithdrawalServerError::BelowMinimumWithdrawal {
+                    token_index: withdrawal.token_index,
+                    min: min.amount,
+                    amount: withdrawal.amount,
+                });
+            }
+        }
         let contract_withdrawal = ContractWithdrawal {
             recipient: withdrawal.recipient,
             token_index: withdrawal.token_index,
@@ -62,6 +82,7 @@ impl WithdrawalServer {
         let recipient = withdrawal.recipient.to_hex();
         let withdrawal_value = serde_json::to_value(contract_withdrawal)
             .map_err(|e| WithdrawalServerError::SerializationError(e.to_string()))?;
+        let attribution_tag_hash_str = attribution_tag_hash.map(|hash| hash.to_hex());
 
         sqlx::query!(
             r#"
@@ -72,9 +93,10 @@ impl WithdrawalServer {
                 withdrawal_hash,
                 single_withdrawal_proof,
                 contract_withdrawal,
-                status
+                status,
+                attribution_tag_hash
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7::withdrawal_status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7::withdrawal_status, $8)
             "#,
             uuid_str,
             pubkey_str,
@@ -82,7 +104,8 @@ impl WithdrawalServer {
             withdrawal_hash_str,
             proof_bytes,
             withdrawal_value,
-            SqlWithdrawalStatus::Requested as SqlWithdrawalStatus
+            SqlWithdrawalStatus::Requested as SqlWithdrawalStatus,
+            attribution_tag_hash_str
         )
         .execute(&self.pool)
         .await?;
@@ -100,7 +123,9 @@ impl WithdrawalServer {
             r#"
             SELECT 
                 status as "status: SqlWithdrawalStatus",
-                contract_withdrawal
+                contract_withdrawal,
+                attribution_tag_hash,
+                EXTRACT(EPOCH FROM created_at)::BIGINT as "requested_at!"
             FROM withdrawals
             WHERE pubkey = $1
             "#,
@@ -117,6 +142,8 @@ impl WithdrawalServer {
             withdrawal_infos.push(WithdrawalInfo {
                 status: record.status.into(),
                 contract_withdrawal,
+                attribution_tag_hash: parse_tag_hash(record.attribution_tag_hash)?,
+                requested_at: Some(record.requested_at as u64),
             });
         }
         Ok(withdrawal_infos)
@@ -131,7 +158,9 @@ impl WithdrawalServer {
             r#"
             SELECT 
                 status as "status: SqlWithdrawalStatus",
-                contract_withdrawal
+                contract_withdrawal,
+                attribution_tag_hash,
+                EXTRACT(EPOCH FROM created_at)::BIGINT as "requested_at!"
             FROM withdrawals
             WHERE recipient = $1
             "#,
@@ -148,8 +177,22 @@ impl WithdrawalServer {
             withdrawal_infos.push(WithdrawalInfo {
                 status: record.status.into(),
                 contract_withdrawal,
+                attribution_tag_hash: parse_tag_hash(record.attribution_tag_hash)?,
+                requested_at: Some(record.requested_at as u64),
             });
         }
         Ok(withdrawal_infos)
     }
 }
+
+fn parse_tag_hash(hash: Option<String>) -> Result<Option<Bytes32>, WithdrawalServerError> {
+    hash.map(|hash| {
+        Bytes32::from_hex(&hash).map_err(|_| {
+            WithdrawalServerError::SerializationError(format!(
+                "invalid attribution tag hash {}",
+                hash
+            ))
+        })
+    })
+    .transpose()
+}
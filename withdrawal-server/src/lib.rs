@@ -1,10 +1,56 @@
+use std::str::FromStr as _;
+
+use intmax2_interfaces::api::withdrawal_server::interface::{TokenLimit, WithdrawalLimits};
+use num_bigint::BigUint;
 use serde::Deserialize;
 
 pub mod api;
 pub mod health_check;
 
+/// A withdrawal request carries a single withdrawal proof
+pub const MAX_WITHDRAWALS_PER_REQUEST: u32 = 1;
+
 #[derive(Debug, Deserialize)]
 pub struct Env {
     pub port: u16,
     pub database_url: String,
+
+    /// Minimum withdrawal amounts, e.g. "0:1000000000000000,1:5000000"
+    pub min_withdrawal_amounts: Option<String>,
+    /// Minimum deposit amounts, in the same format
+    pub min_deposit_amounts: Option<String>,
+}
+
+impl Env {
+    pub fn limits(&self) -> anyhow::Result<WithdrawalLimits> {
+        Ok(WithdrawalLimits {
+            min_withdrawals: parse_token_limits(self.min_withdrawal_amounts.as_deref())?,
+            min_deposits: parse_token_limits(self.min_deposit_amounts.as_deref())?,
+            max_withdrawals_per_request: MAX_WITHDRAWALS_PER_REQUEST,
+        })
+    }
+}
+
+/// Parse comma separated `token_index:amount` pairs
+fn parse_token_limits(input: Option<&str>) -> anyhow::Result<Vec<TokenLimit>> {
+    let Some(input) = input else {
+        return Ok(Vec::new());
+    };
+    input
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (token_index, amount) = entry
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("expected token_index:amount, got {}", entry))?;
+            let amount = BigUint::from_str(amount)?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("amount {} does not fit in u256", amount))?;
+            Ok(TokenLimit {
+                token_index: token_index.parse()?,
+                amount,
+            })
+        })
+        .collect()
 }
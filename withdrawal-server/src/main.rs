@@ -3,6 +3,7 @@ use std::io;
 use actix_cors::Cors;
 use actix_web::{middleware::Logger, web::Data, App, HttpServer};
 use intmax2_client_sdk::utils::init_logger::init_logger;
+use intmax2_interfaces::api::CORS_EXPOSED_HEADERS;
 use withdrawal_server::{
     api::{api::withdrawal_server_scope, state::State},
     health_check::health_check,
@@ -16,12 +17,15 @@ async fn main() -> std::io::Result<()> {
 
     let env = envy::from_env::<Env>()
         .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("env error: {}", e)))?;
-    let state = State::new(&env.database_url)
+    let limits = env
+        .limits()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("limits error: {}", e)))?;
+    let state = State::new(&env.database_url, limits)
         .await
         .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("state error: {}", e)))?;
     let state = Data::new(state);
     HttpServer::new(move || {
-        let cors = Cors::permissive();
+        let cors = Cors::permissive().expose_headers(CORS_EXPOSED_HEADERS);
         App::new()
             .wrap(cors)
             .wrap(Logger::new("Request: %r | Status: %s | Duration: %Ts"))